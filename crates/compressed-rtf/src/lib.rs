@@ -10,6 +10,7 @@ mod dictionary;
 use dictionary::{DictionaryReference, TokenDictionary};
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     #[error("IO error: {0:?}")]
     IoError(#[from] io::Error),
@@ -17,6 +18,10 @@ pub enum Error {
     CompressedSizeMismatch(u32),
     #[error("COMPRESSED CRC mismatch: 0x{0:08X}")]
     CompressedCrcMismatch(u32),
+    #[error("UNCOMPRESSED CRC is not 0: 0x{0:08X}")]
+    UncompressedCrcMismatch(u32),
+    #[error("COMPRESSED RTF ended without a terminating dictionary reference")]
+    MissingEndOfStream,
     #[error("Invalid COMPTYPE: 0x{0:08X}")]
     InvalidCompressionType(u32),
     #[error("Dictionary reference error: {0:?}")]
@@ -34,9 +39,29 @@ pub type Result<T> = std::result::Result<T, Error>;
 const COMPRESSED: u32 = 0x75465A4C;
 const UNCOMPRESSED: u32 = 0x414C454D;
 
+/// A run is 1 control byte followed by up to 8 dictionary references of 2 bytes, each of which
+/// can expand to 17 bytes: `8 * 17 / (1 + 8 * 2)`.
+const MAX_EXPANSION: usize = 8;
+
 pub fn decompress_rtf(data: &[u8]) -> Result<String> {
+    Ok(string_from_ascii(&decompress_rtf_bytes(data)?))
+}
+
+/// Decompress the RTF without converting it to a [`String`], so the bytes are returned exactly
+/// as they were compressed, including any trailing null terminators.
+pub fn decompress_rtf_bytes(data: &[u8]) -> Result<Vec<u8>> {
+    decompress(data, false)
+}
+
+/// Same as [`decompress_rtf_bytes`], but COMPRESSED data must end with the terminating dictionary
+/// reference, and the CRC of UNCOMPRESSED data must be 0.
+pub fn decompress_rtf_bytes_strict(data: &[u8]) -> Result<Vec<u8>> {
+    decompress(data, true)
+}
+
+fn decompress(data: &[u8], strict: bool) -> Result<Vec<u8>> {
     let total_size = data.len();
-    let mut cursor = Cursor::new(&data[..16]);
+    let mut cursor = Cursor::new(data);
     let compressed_size = cursor.read_u32::<LittleEndian>()?;
 
     if compressed_size as usize + size_of_val(&compressed_size) != total_size {
@@ -55,14 +80,27 @@ pub fn decompress_rtf(data: &[u8]) -> Result<String> {
             }
 
             let mut dictionary = TokenDictionary::default();
-            let mut output = Vec::with_capacity(raw_size as usize);
+
+            // Each control byte is followed by at most 8 references which expand to 17 bytes
+            // each, so don't trust RAWSIZE to reserve more than the data could decompress to.
+            let max_size = (total_size - 16).saturating_mul(MAX_EXPANSION);
+            let mut output = Vec::with_capacity((raw_size as usize).min(max_size));
 
             let mut cursor = Cursor::new(&data[16..]);
-            'decompress: while let Ok(control) = cursor.read_u8() {
+            'decompress: loop {
+                let Ok(control) = cursor.read_u8() else {
+                    if strict {
+                        return Err(Error::MissingEndOfStream);
+                    }
+                    break;
+                };
                 for i in 0..8 {
                     let bit = control & (0x01 << i);
                     if bit == 0 {
                         let Ok(byte) = cursor.read_u8() else {
+                            if strict {
+                                return Err(Error::MissingEndOfStream);
+                            }
                             break 'decompress;
                         };
                         output.push(byte);
@@ -77,9 +115,14 @@ pub fn decompress_rtf(data: &[u8]) -> Result<String> {
                 }
             }
 
-            Ok(string_from_ascii(&output))
+            output.truncate(raw_size as usize);
+            Ok(output)
         }
-        UNCOMPRESSED => Ok(string_from_ascii(&data[16..raw_size as usize + 16])),
+        UNCOMPRESSED if strict && crc != 0 => Err(Error::UncompressedCrcMismatch(crc)),
+        UNCOMPRESSED => data
+            .get(16..raw_size as usize + 16)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof).into()),
         invalid => Err(Error::InvalidCompressionType(invalid)),
     }
 }
@@ -211,10 +254,36 @@ mod tests {
     /// [Example 1: Simple Compressed RTF](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxrtfcp/029bff74-8c00-402e-ac2b-0210a5f57371)
     #[test]
     fn test_decompress_simple_rtf() {
-        let rtf = decompress_rtf(&COMPRESSED_SIMPLE_RTF).unwrap();
+        let rtf = decompress_rtf(COMPRESSED_SIMPLE_RTF).unwrap();
         assert_eq!(rtf, UNCOMPRESSED_SIMPLE_RTF);
     }
 
+    #[test]
+    fn test_decompress_simple_rtf_bytes() {
+        let rtf = decompress_rtf_bytes(COMPRESSED_SIMPLE_RTF).unwrap();
+        assert_eq!(rtf, UNCOMPRESSED_SIMPLE_RTF.as_bytes());
+    }
+
+    #[test]
+    fn test_decompress_truncated_rtf() {
+        // COMPSIZE matches the 12 byte buffer, but the header is 16 bytes.
+        let mut header = COMPRESSED_SIMPLE_RTF[..12].to_vec();
+        header[..4].copy_from_slice(&8_u32.to_le_bytes());
+        assert!(matches!(
+            decompress_rtf_bytes(&header),
+            Err(Error::IoError(_))
+        ));
+
+        let mut uncompressed = encode_rtf(UNCOMPRESSED_SIMPLE_RTF).unwrap();
+        uncompressed.truncate(uncompressed.len() - 1);
+        let compressed_size = uncompressed.len() as u32 - 4;
+        uncompressed[..4].copy_from_slice(&compressed_size.to_le_bytes());
+        assert!(matches!(
+            decompress_rtf_bytes(&uncompressed),
+            Err(Error::IoError(_))
+        ));
+    }
+
     /// [Example 1: Simple RTF](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxrtfcp/ba662823-d47a-4db3-ad45-a368a82acc90)
     #[test]
     fn test_compress_simple_rtf() {
@@ -232,7 +301,7 @@ mod tests {
     /// [Example 2: Reading a Token from the Dictionary that Crosses WritePosition](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxrtfcp/421a2da5-7752-4985-8981-0f19f1e5b687)
     #[test]
     fn test_decompress_crossing_write_rtf() {
-        let rtf = decompress_rtf(&COMPRESSED_CROSSING_WRITE_RTF).unwrap();
+        let rtf = decompress_rtf(COMPRESSED_CROSSING_WRITE_RTF).unwrap();
         assert_eq!(rtf, UNCOMPRESSED_CROSSING_WRITE_RTF);
     }
 
@@ -242,4 +311,53 @@ mod tests {
         let compressed = compress_rtf(UNCOMPRESSED_CROSSING_WRITE_RTF).unwrap();
         assert_eq!(&compressed, COMPRESSED_CROSSING_WRITE_RTF);
     }
+
+    fn make_compressed(raw_size: u32, body: &[u8]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(body.len() + 16);
+        data.extend_from_slice(&(body.len() as u32 + 12).to_le_bytes());
+        data.extend_from_slice(&raw_size.to_le_bytes());
+        data.extend_from_slice(&COMPRESSED.to_le_bytes());
+        data.extend_from_slice(&crc::calculate_crc(0, body).to_le_bytes());
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn test_decompress_missing_end_of_stream() {
+        // A full run of 8 literals, and then the data ends without the final reference.
+        let data = make_compressed(8, b"\0{\\rtf1 }");
+        assert_eq!(decompress_rtf_bytes(&data).unwrap(), b"{\\rtf1 }");
+        assert!(matches!(
+            decompress_rtf_bytes_strict(&data),
+            Err(Error::MissingEndOfStream)
+        ));
+    }
+
+    #[test]
+    fn test_decompress_oversized_raw_size() {
+        let mut data = COMPRESSED_SIMPLE_RTF.to_vec();
+        data[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let rtf = decompress_rtf_bytes(&data).unwrap();
+        assert_eq!(rtf, UNCOMPRESSED_SIMPLE_RTF.as_bytes());
+        assert!(rtf.capacity() < data.len() * MAX_EXPANSION + 17);
+    }
+
+    #[test]
+    fn test_decompress_uncompressed_crc() {
+        let mut data = encode_rtf(UNCOMPRESSED_SIMPLE_RTF).unwrap();
+        assert_eq!(
+            decompress_rtf(&data).unwrap(),
+            UNCOMPRESSED_SIMPLE_RTF.to_string()
+        );
+
+        data[12] = 0x01;
+        assert_eq!(
+            decompress_rtf_bytes(&data).unwrap(),
+            UNCOMPRESSED_SIMPLE_RTF.as_bytes()
+        );
+        assert!(matches!(
+            decompress_rtf_bytes_strict(&data),
+            Err(Error::UncompressedCrcMismatch(0x00000001))
+        ));
+    }
 }
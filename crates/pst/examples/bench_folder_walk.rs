@@ -0,0 +1,68 @@
+use clap::Parser;
+use outlook_pst::{messaging::store::*, *};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+mod args;
+
+#[derive(Parser)]
+#[command(version, about, long_about)]
+struct BenchArgs {
+    #[command(flatten)]
+    pst: args::Args,
+
+    /// Number of times to walk the folder tree with each backend
+    #[arg(long, default_value_t = 100)]
+    iterations: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = BenchArgs::try_parse()?;
+    let file = &args.pst.file;
+
+    let (reader, mapped) = if let Ok(pst) = UnicodePstFile::open(file) {
+        let reader = PstFileReader::Unicode(UnicodeStore::read(Arc::new(pst))?);
+        let mapped = UnicodePstFile::open_mapped(file)?;
+        let mapped = PstFileReader::Unicode(UnicodeStore::read(Arc::new(mapped))?);
+        (
+            time_walks(&reader, args.iterations)?,
+            time_walks(&mapped, args.iterations)?,
+        )
+    } else {
+        let pst = AnsiPstFile::open(file)?;
+        let reader = PstFileReader::Ansi(AnsiStore::read(Arc::new(pst))?);
+        let mapped = AnsiPstFile::open_mapped(file)?;
+        let mapped = PstFileReader::Ansi(AnsiStore::read(Arc::new(mapped))?);
+        (
+            time_walks(&reader, args.iterations)?,
+            time_walks(&mapped, args.iterations)?,
+        )
+    };
+
+    println!("Folder tree walks: {}", args.iterations);
+    println!("Reader: {reader:?}");
+    println!("Mapped: {mapped:?}");
+
+    Ok(())
+}
+
+fn time_walks(pst: &PstFileReader, iterations: usize) -> anyhow::Result<Duration> {
+    let mut folder_count = 0;
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        for folder in pst.folders()? {
+            // Read the contents table too, so the walk touches every folder's table blocks.
+            let _ = folder
+                .contents_table()
+                .map(|table| table.rows_iter().count());
+            folder_count += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    eprintln!("Folders: {folder_count}");
+    Ok(elapsed)
+}
@@ -0,0 +1,78 @@
+use clap::Parser;
+use outlook_pst::*;
+use std::{fs, time::Instant};
+
+mod args;
+
+#[derive(Parser)]
+#[command(version, about, long_about)]
+struct BenchArgs {
+    #[command(flatten)]
+    pst: args::Args,
+
+    /// Number of reads to time with each backend
+    #[arg(long, default_value_t = 100_000)]
+    iterations: usize,
+
+    /// Size of each read in bytes
+    #[arg(long, default_value_t = 8192)]
+    size: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = BenchArgs::try_parse()?;
+    let file_size = fs::metadata(&args.pst.file)?.len();
+    let size = args.size.min(file_size as usize);
+
+    // Block offsets are aligned on 64 byte boundaries.
+    let slots = (file_size - size as u64) / 64 + 1;
+
+    let (reader, mapped) = if let Ok(pst) = UnicodePstFile::open(&args.pst.file) {
+        let mapped = UnicodePstFile::open_mapped(&args.pst.file)?;
+        (
+            time_reads(&pst, args.iterations, size, slots),
+            time_reads(&mapped, args.iterations, size, slots),
+        )
+    } else {
+        let pst = AnsiPstFile::open(&args.pst.file)?;
+        let mapped = AnsiPstFile::open_mapped(&args.pst.file)?;
+        (
+            time_reads(&pst, args.iterations, size, slots),
+            time_reads(&mapped, args.iterations, size, slots),
+        )
+    };
+
+    println!("Reads: {} x 0x{size:X} bytes", args.iterations);
+    println!("Reader: {:?}", reader?);
+    println!("Mapped: {:?}", mapped?);
+
+    Ok(())
+}
+
+fn time_reads<Pst>(
+    pst: &Pst,
+    iterations: usize,
+    size: usize,
+    slots: u64,
+) -> anyhow::Result<std::time::Duration>
+where
+    Pst: PstFile,
+{
+    // Simple LCG so that both backends read the same sequence of offsets.
+    let mut seed = 0x2545_F491_4F6C_DD1D_u64;
+    let mut checksum = 0_u64;
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        seed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let offset = (seed >> 33) % slots * 64;
+        let data = pst.read_at(offset, size)?;
+        checksum = checksum.wrapping_add(u64::from(data[0]));
+    }
+    let elapsed = start.elapsed();
+
+    eprintln!("Checksum: 0x{checksum:X}");
+    Ok(elapsed)
+}
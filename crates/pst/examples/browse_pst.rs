@@ -11,7 +11,7 @@ use ratatui::{
     widgets::{Block, Borders, List, ListState, Paragraph, StatefulWidget, Widget},
     DefaultTerminal, Frame,
 };
-use std::{cell::OnceCell, io, path::Path, rc::Rc};
+use std::{cell::OnceCell, io, path::Path, sync::Arc};
 
 use outlook_pst::{
     ltp::{
@@ -33,8 +33,8 @@ mod encoding;
 struct IpmSubTree {
     display_name: OnceCell<anyhow::Result<String>>,
     root_folders: OnceCell<anyhow::Result<Vec<Folder>>>,
-    pst_store: Rc<dyn Store>,
-    pst_folders: OnceCell<anyhow::Result<Vec<Rc<dyn PstFolder>>>>,
+    pst_store: Arc<dyn Store>,
+    pst_folders: OnceCell<anyhow::Result<Vec<Arc<dyn PstFolder>>>>,
 }
 
 impl IpmSubTree {
@@ -59,7 +59,7 @@ impl IpmSubTree {
             .to_string()
     }
 
-    fn store(&self) -> Rc<dyn Store> {
+    fn store(&self) -> Arc<dyn Store> {
         self.pst_store.clone()
     }
 
@@ -76,14 +76,15 @@ impl IpmSubTree {
                             anyhow::anyhow!("No hierarchy table found for the IPM Subtree."),
                         )?;
 
-                        hierarchy_table
-                            .rows_matrix()
+                        let folders = hierarchy_table
+                            .rows_matrix()?
                             .map(|row| {
                                 let node = NodeId::from(u32::from(row.id()));
                                 let entry_id = self.store().properties().make_entry_id(node)?;
                                 Ok(self.store().open_folder(&entry_id)?)
                             })
-                            .collect()
+                            .collect();
+                        folders
                     })
                     .as_ref()
                     .map_err(|err| anyhow::anyhow!("{err:?}"))?
@@ -105,12 +106,12 @@ struct Folder {
     name: String,
     sub_folders: OnceCell<anyhow::Result<Vec<Folder>>>,
     messages: OnceCell<anyhow::Result<Vec<Message>>>,
-    pst_folder: Rc<dyn PstFolder>,
-    pst_sub_folders: OnceCell<anyhow::Result<Vec<Rc<dyn PstFolder>>>>,
+    pst_folder: Arc<dyn PstFolder>,
+    pst_sub_folders: OnceCell<anyhow::Result<Vec<Arc<dyn PstFolder>>>>,
 }
 
 impl Folder {
-    fn new(folder: Rc<dyn PstFolder>) -> anyhow::Result<Self> {
+    fn new(folder: Arc<dyn PstFolder>) -> anyhow::Result<Self> {
         let properties = folder.properties();
         let name = properties.display_name()?.to_string();
 
@@ -134,15 +135,16 @@ impl Folder {
                                 anyhow::anyhow!("No hierarchy table found for the folder."),
                             )?;
 
-                            hierarchy_table
-                                .rows_matrix()
+                            let folders = hierarchy_table
+                                .rows_matrix()?
                                 .map(|row| {
                                     let node = NodeId::from(u32::from(row.id()));
                                     let entry_id =
                                         self.pst_folder.store().properties().make_entry_id(node)?;
                                     Ok(self.pst_folder.store().open_folder(&entry_id)?)
                                 })
-                                .collect()
+                                .collect();
+                            folders
                         })
                         .as_ref()
                         .map_err(|err| anyhow::anyhow!("{err:?}"))?
@@ -167,7 +169,7 @@ impl Folder {
                     .contents_table()
                     .ok_or(anyhow::anyhow!("No contents table found for the folder."))?;
                 contents_table
-                    .rows_matrix()
+                    .rows_matrix()?
                     .map(|row| {
                         Message::new(
                             self.pst_folder.store().clone(),
@@ -184,7 +186,7 @@ impl Folder {
 }
 
 enum MessageOrRow {
-    Message(Rc<dyn PstMessage>),
+    Message(Arc<dyn PstMessage>),
     Row {
         subject: Option<String>,
         received_time: i64,
@@ -197,15 +199,15 @@ struct Message {
     recipients: OnceCell<Vec<Recipient>>,
     body: OnceCell<anyhow::Result<Option<Body>>>,
     attachments: OnceCell<anyhow::Result<Vec<Attachment>>>,
-    pst_store: Rc<dyn Store>,
-    pst_message: OnceCell<anyhow::Result<Rc<dyn PstMessage>>>,
-    pst_full_message: OnceCell<anyhow::Result<Rc<dyn PstMessage>>>,
-    pst_attachments: OnceCell<anyhow::Result<Vec<Rc<dyn PstAttachment>>>>,
+    pst_store: Arc<dyn Store>,
+    pst_message: OnceCell<anyhow::Result<Arc<dyn PstMessage>>>,
+    pst_full_message: OnceCell<anyhow::Result<Arc<dyn PstMessage>>>,
+    pst_attachments: OnceCell<anyhow::Result<Vec<Arc<dyn PstAttachment>>>>,
 }
 
 impl Message {
     fn new(
-        store: Rc<dyn Store>,
+        store: Arc<dyn Store>,
         table: &dyn TableContext,
         row: &TableRowData,
     ) -> anyhow::Result<Self> {
@@ -283,7 +285,7 @@ impl Message {
         })
     }
 
-    fn message(&self) -> anyhow::Result<Rc<dyn PstMessage>> {
+    fn message(&self) -> anyhow::Result<Arc<dyn PstMessage>> {
         match &self.message {
             MessageOrRow::Message(message) => Ok(message.clone()),
             MessageOrRow::Row { .. } => self
@@ -299,7 +301,7 @@ impl Message {
         }
     }
 
-    fn full_message(&self) -> anyhow::Result<Rc<dyn PstMessage>> {
+    fn full_message(&self) -> anyhow::Result<Arc<dyn PstMessage>> {
         self.pst_full_message
             .get_or_init(|| Ok(self.pst_store.open_message(&self.entry_id, None)?))
             .as_ref()
@@ -341,56 +343,57 @@ impl Message {
                 let Some(recipient_table) = message.recipient_table() else {
                     return Default::default();
                 };
+                let Ok(rows) = recipient_table.rows_matrix() else {
+                    return Default::default();
+                };
                 let context = recipient_table.context();
 
-                recipient_table
-                    .rows_matrix()
-                    .filter_map(|row| {
-                        let columns: Vec<_> = context
-                            .columns()
-                            .iter()
-                            .zip(row.columns(context).ok()?)
-                            .collect();
-                        let recipient_type = match columns
-                            .iter()
-                            .find_map(|(col, value)| {
-                                if col.prop_id() == 0x0C15 {
-                                    Some((value.as_ref(), col.prop_type()))
-                                } else {
-                                    None
-                                }
-                            })
-                            .and_then(|(value, prop_type)| {
-                                recipient_table.read_column(value?, prop_type).ok()
-                            })? {
-                            PropertyValue::Integer32(value) => value,
-                            _ => return None,
-                        };
-                        let display_name = match columns
-                            .iter()
-                            .find_map(|(col, value)| {
-                                if col.prop_id() == 0x3001 {
-                                    Some((value.as_ref(), col.prop_type()))
-                                } else {
-                                    None
-                                }
-                            })
-                            .and_then(|(value, prop_type)| {
-                                recipient_table.read_column(value?, prop_type).ok()
-                            })? {
-                            PropertyValue::String8(value) => value.to_string(),
-                            PropertyValue::Unicode(value) => value.to_string(),
-                            _ => return None,
-                        };
-
-                        match recipient_type {
-                            MAPI_TO => Some(Recipient::To(display_name)),
-                            MAPI_CC => Some(Recipient::Cc(display_name)),
-                            MAPI_BCC => Some(Recipient::Bcc(display_name)),
-                            _ => None,
-                        }
-                    })
-                    .collect()
+                rows.filter_map(|row| {
+                    let columns: Vec<_> = context
+                        .columns()
+                        .iter()
+                        .zip(row.columns(context).ok()?)
+                        .collect();
+                    let recipient_type = match columns
+                        .iter()
+                        .find_map(|(col, value)| {
+                            if col.prop_id() == 0x0C15 {
+                                Some((value.as_ref(), col.prop_type()))
+                            } else {
+                                None
+                            }
+                        })
+                        .and_then(|(value, prop_type)| {
+                            recipient_table.read_column(value?, prop_type).ok()
+                        })? {
+                        PropertyValue::Integer32(value) => value,
+                        _ => return None,
+                    };
+                    let display_name = match columns
+                        .iter()
+                        .find_map(|(col, value)| {
+                            if col.prop_id() == 0x3001 {
+                                Some((value.as_ref(), col.prop_type()))
+                            } else {
+                                None
+                            }
+                        })
+                        .and_then(|(value, prop_type)| {
+                            recipient_table.read_column(value?, prop_type).ok()
+                        })? {
+                        PropertyValue::String8(value) => value.to_string(),
+                        PropertyValue::Unicode(value) => value.to_string(),
+                        _ => return None,
+                    };
+
+                    match recipient_type {
+                        MAPI_TO => Some(Recipient::To(display_name)),
+                        MAPI_CC => Some(Recipient::Cc(display_name)),
+                        MAPI_BCC => Some(Recipient::Bcc(display_name)),
+                        _ => None,
+                    }
+                })
+                .collect()
             })
             .as_slice())
     }
@@ -465,10 +468,8 @@ impl AppState {
             KeyCode::Char('h') | KeyCode::Left => self.go_back(),
             KeyCode::Char('j') | KeyCode::Down => list_state.select_next(),
             KeyCode::Char('k') | KeyCode::Up => list_state.select_previous(),
-            KeyCode::Char('l') | KeyCode::Right => {
-                if self.current_pane == Pane::Folders {
-                    self.change_folder(self.folder_state.selected());
-                }
+            KeyCode::Char('l') | KeyCode::Right if self.current_pane == Pane::Folders => {
+                self.change_folder(self.folder_state.selected());
             }
             KeyCode::Char('g') | KeyCode::Home => list_state.select_first(),
             KeyCode::Char('G') | KeyCode::End => list_state.select_last(),
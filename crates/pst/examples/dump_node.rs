@@ -0,0 +1,118 @@
+use clap::Parser;
+use outlook_pst::{
+    ndb::{
+        block::{AnsiDataTree, IntermediateTreeBlock, IntermediateTreeHeader, UnicodeDataTree},
+        block_id::{AnsiBlockId, BlockId, UnicodeBlockId},
+        header::Header,
+        node_id::NodeId,
+        page::{AnsiBlockBTree, NodeBTreeEntry, UnicodeBlockBTree},
+        root::Root,
+    },
+    *,
+};
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+mod args;
+
+#[derive(Parser)]
+#[command(version, about, long_about)]
+struct DumpNodeArgs {
+    #[command(flatten)]
+    pst: args::Args,
+
+    /// Node ID in hex, with or without a `0x` prefix
+    #[arg(long, value_parser = parse_node_id)]
+    node: NodeId,
+
+    /// Write the node data to this file instead of stdout
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+fn parse_node_id(value: &str) -> Result<NodeId, String> {
+    let value = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    u32::from_str_radix(value, 16)
+        .map(NodeId::from)
+        .map_err(|err| err.to_string())
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = DumpNodeArgs::try_parse()?;
+
+    let data = if let Ok(pst) = UnicodePstFile::open(&args.pst.file) {
+        read_node_data(&pst, args.node, unicode_data_tree_depth)?
+    } else {
+        let pst = AnsiPstFile::open(&args.pst.file)?;
+        read_node_data(&pst, args.node, ansi_data_tree_depth)?
+    };
+
+    match args.out {
+        Some(out) => File::create(out)?.write_all(&data)?,
+        None => io::stdout().lock().write_all(&data)?,
+    }
+
+    Ok(())
+}
+
+fn read_node_data<Pst>(
+    pst: &Pst,
+    node: NodeId,
+    data_tree_depth: fn(&Pst, <Pst as PstFile>::BlockId) -> io::Result<u8>,
+) -> io::Result<Vec<u8>>
+where
+    Pst: PstFile,
+{
+    let node = pst.read_node(node)?;
+    let depth = data_tree_depth(pst, node.data())?;
+    let data = pst.read_block(node.data())?;
+
+    eprintln!("Data Tree Depth: {depth}");
+    eprintln!("Total Size: 0x{:X}", data.len());
+
+    Ok(data)
+}
+
+fn unicode_data_tree_depth(pst: &UnicodePstFile, block: UnicodeBlockId) -> io::Result<u8> {
+    let header = pst.header();
+    let mut file = pst
+        .reader()
+        .lock()
+        .map_err(|_| io::Error::other("Failed to lock reader"))?;
+    let file = &mut *file;
+
+    let block_btree = UnicodeBlockBTree::read(file, *header.root().block_btree())?;
+    let mut page_cache = Default::default();
+    let block = block_btree.find_entry(file, block.search_key(), &mut page_cache)?;
+    Ok(
+        match UnicodeDataTree::read(file, header.crypt_method(), &block)? {
+            UnicodeDataTree::Intermediate(block) => block.header().level(),
+            UnicodeDataTree::Leaf(_) => 0,
+        },
+    )
+}
+
+fn ansi_data_tree_depth(pst: &AnsiPstFile, block: AnsiBlockId) -> io::Result<u8> {
+    let header = pst.header();
+    let mut file = pst
+        .reader()
+        .lock()
+        .map_err(|_| io::Error::other("Failed to lock reader"))?;
+    let file = &mut *file;
+
+    let block_btree = AnsiBlockBTree::read(file, *header.root().block_btree())?;
+    let mut page_cache = Default::default();
+    let block = block_btree.find_entry(file, block.search_key(), &mut page_cache)?;
+    Ok(
+        match AnsiDataTree::read(file, header.crypt_method(), &block)? {
+            AnsiDataTree::Intermediate(block) => block.header().level(),
+            AnsiDataTree::Leaf(_) => 0,
+        },
+    )
+}
@@ -0,0 +1,222 @@
+use clap::Parser;
+use outlook_pst::{
+    fuzzing,
+    ltp::{
+        heap::{HeapId, HeapNodePageMap, HeapNodeType},
+        prop_context::PropertyValueRecord,
+    },
+    ndb::{
+        block::{block_size, Block, UnicodeDataTree},
+        block_id::BlockId,
+        block_ref::{BlockRef, UnicodePageRef},
+        byte_index::ByteIndex,
+        header::Header,
+        page::{
+            BTreePage, BTreePageEntry, BlockBTreeEntry, NodeBTreeEntry, RootBTreePage,
+            UnicodeBTreePageEntry,
+        },
+        root::Root,
+    },
+};
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The trailer of a Unicode block, `BLOCKTRAILER`.
+const BLOCK_TRAILER_SIZE: u16 = 16;
+
+/// Size of a BTree page.
+const PAGE_SIZE: usize = 512;
+
+#[derive(Parser)]
+#[command(version, about, long_about)]
+struct ExtractArgs {
+    /// Unicode PST file to extract structures from
+    #[clap(default_value = r#"crates/pst/examples/Empty.pst"#)]
+    file: PathBuf,
+
+    /// Directory where each fuzz target gets a sub-directory of seeds
+    #[clap(default_value = r#"crates/pst/fuzz/seeds"#)]
+    output: PathBuf,
+}
+
+/// Write the structures in a Unicode PST file as seeds for the fuzz targets in `crates/pst/fuzz`,
+/// using the same input layout which each target expects.
+fn main() -> anyhow::Result<()> {
+    let args = ExtractArgs::try_parse()?;
+    let data = fs::read(&args.file)?;
+    let seeds = Seeds::new(&args.output)?;
+
+    seeds.write("ndb_header", "unicode", &data[..1024.min(data.len())])?;
+    seeds.write("messaging_store", "file", &data)?;
+
+    let header = fuzzing::unicode_header(&data)?;
+    let encoding = header.crypt_method();
+    let root = header.root();
+
+    let mut nodes = Vec::new();
+    let pages = btree_pages(&data, *root.node_btree(), |page| {
+        Ok(match fuzzing::unicode_node_btree_page(page)? {
+            RootBTreePage::Intermediate(page, ..) => child_pages(page.entries()),
+            RootBTreePage::Leaf(page) => {
+                nodes.extend_from_slice(page.entries());
+                Vec::new()
+            }
+        })
+    })?;
+    for (page, page_data) in pages {
+        let input = [&[0], page_data].concat();
+        seeds.write("ndb_btree_page", &format!("node-{page:X}"), &input)?;
+    }
+
+    let mut blocks = Vec::new();
+    let pages = btree_pages(&data, *root.block_btree(), |page| {
+        Ok(match fuzzing::unicode_block_btree_page(page)? {
+            RootBTreePage::Intermediate(page, ..) => child_pages(page.entries()),
+            RootBTreePage::Leaf(page) => {
+                blocks.extend_from_slice(page.entries());
+                Vec::new()
+            }
+        })
+    })?;
+    for (page, page_data) in pages {
+        let input = [&[2], page_data].concat();
+        seeds.write("ndb_btree_page", &format!("block-{page:X}"), &input)?;
+    }
+
+    let sub_nodes: BTreeSet<u64> = nodes
+        .iter()
+        .filter_map(|node| node.sub_node())
+        .map(|block| block.search_key())
+        .collect();
+
+    for entry in blocks {
+        let offset: u64 = entry.block().index().index();
+        let size = usize::from(block_size(entry.size() + BLOCK_TRAILER_SIZE));
+        let Some(block) = data.get(offset as usize..offset as usize + size) else {
+            continue;
+        };
+        let block_id = entry.block().block();
+        let name = format!("{:X}", block_id.search_key());
+
+        if sub_nodes.contains(&block_id.search_key()) {
+            seeds.write("ndb_sub_node_tree", &name, &[&[0], block].concat())?;
+            continue;
+        }
+
+        let input = [&[encoding as u8][..], &offset.to_le_bytes(), block].concat();
+        seeds.write("ndb_data_tree", &name, &input)?;
+
+        let Ok(UnicodeDataTree::Leaf(block)) = fuzzing::unicode_data_tree(block, encoding, offset)
+        else {
+            continue;
+        };
+        let block = block.data();
+        let Ok((Some(heap), page_map)) = fuzzing::heap_node_page(block, 0) else {
+            continue;
+        };
+        seeds.write("ltp_heap_node", &name, &[&[0], block].concat())?;
+
+        let Some(user_root) = allocation(block, &page_map, heap.user_root()) else {
+            continue;
+        };
+        match heap.client_signature() {
+            HeapNodeType::Properties => {
+                seeds.write("ltp_heap_tree_header", &name, user_root)?;
+                let Ok(tree) = fuzzing::heap_tree_header(user_root) else {
+                    continue;
+                };
+                if tree.levels() > 0 {
+                    continue;
+                }
+                let Some(records) = allocation(block, &page_map, tree.root()) else {
+                    continue;
+                };
+                for (index, record) in records.chunks_exact(8).enumerate() {
+                    let Ok(parsed) = fuzzing::property_tree_record(record) else {
+                        continue;
+                    };
+                    let value = match parsed.value() {
+                        PropertyValueRecord::Heap(heap_id) => {
+                            allocation(block, &page_map, heap_id).unwrap_or_default()
+                        }
+                        _ => &[],
+                    };
+                    seeds.write(
+                        "ltp_property_context",
+                        &format!("{name}-{index}"),
+                        &[record, value].concat(),
+                    )?;
+                }
+            }
+            HeapNodeType::Table => {
+                seeds.write("ltp_table_context_info", &name, user_root)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+struct Seeds<'a> {
+    output: &'a Path,
+}
+
+impl<'a> Seeds<'a> {
+    fn new(output: &'a Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(output)?;
+        Ok(Self { output })
+    }
+
+    fn write(&self, target: &str, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        let dir = self.output.join(target);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(name), data)?;
+        Ok(())
+    }
+}
+
+/// Walk a BTree from its root page, and return the offset and contents of every page. The
+/// `read` callback parses each page and returns the offsets of its child pages.
+fn btree_pages(
+    data: &[u8],
+    root: UnicodePageRef,
+    mut read: impl FnMut(&[u8]) -> io::Result<Vec<u64>>,
+) -> io::Result<Vec<(u64, &[u8])>> {
+    let mut pages = Vec::new();
+    let mut pending = vec![root.index().index()];
+    while let Some(offset) = pending.pop() {
+        let Some(page_data) = data.get(offset as usize..offset as usize + PAGE_SIZE) else {
+            continue;
+        };
+        pending.extend(read(page_data)?);
+        pages.push((offset, page_data));
+    }
+    Ok(pages)
+}
+
+fn child_pages(entries: &[UnicodeBTreePageEntry]) -> Vec<u64> {
+    entries
+        .iter()
+        .map(|entry| entry.block().index().index())
+        .collect()
+}
+
+/// Get the bytes of an allocation in the first block of a heap.
+fn allocation<'a>(
+    block: &'a [u8],
+    page_map: &HeapNodePageMap,
+    heap_id: HeapId,
+) -> Option<&'a [u8]> {
+    if heap_id.block_index() != 0 {
+        return None;
+    }
+    let alloc = page_map
+        .allocations()
+        .get(usize::from(heap_id.index().ok()?))?;
+    let start = usize::from(alloc.offset());
+    block.get(start..start + usize::from(alloc.size()))
+}
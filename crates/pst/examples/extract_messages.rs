@@ -0,0 +1,121 @@
+use clap::Parser;
+use outlook_pst::{
+    messaging::store::{AnsiStore, EntryId, Store, UnicodeStore},
+    ndb::node_id::{NodeId, NID_ROOT_FOLDER},
+    *,
+};
+use std::{sync::Arc, thread, time::Instant};
+
+mod args;
+
+#[derive(Parser)]
+#[command(version, about, long_about)]
+struct ExtractArgs {
+    #[command(flatten)]
+    pst: args::Args,
+
+    /// Number of threads to extract messages on
+    #[arg(long, default_value_t = 4)]
+    threads: usize,
+
+    /// Number of readers in the pool, defaults to the number of threads
+    #[arg(long)]
+    readers: Option<usize>,
+
+    /// Maximum number of messages to extract
+    #[arg(long, default_value_t = 1000)]
+    limit: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = ExtractArgs::try_parse()?;
+    let threads = args.threads.max(1);
+    let readers = args.readers.unwrap_or(threads);
+
+    let (store, readers): (Arc<dyn Store>, _) =
+        if let Ok(pst) = UnicodePstFile::open_with_readers(&args.pst.file, readers) {
+            let readers = pst.reader().len();
+            (UnicodeStore::read(Arc::new(pst))?, readers)
+        } else {
+            let pst = AnsiPstFile::open_with_readers(&args.pst.file, readers)?;
+            let readers = pst.reader().len();
+            (AnsiStore::read(Arc::new(pst))?, readers)
+        };
+
+    let mut messages = vec![];
+    let root_folder = store.properties().make_entry_id(NID_ROOT_FOLDER)?;
+    collect_messages(store.as_ref(), &root_folder, args.limit, &mut messages)?;
+
+    let chunk_size = messages.len().div_ceil(threads).max(1);
+    let start = Instant::now();
+    let extracted: usize = thread::scope(|scope| {
+        let workers: Vec<_> = messages
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let store = store.clone();
+                scope.spawn(move || -> anyhow::Result<usize> {
+                    let mut properties = 0;
+                    for entry_id in chunk {
+                        let message = store.open_message(entry_id, None)?;
+                        properties += message.properties().iter().count();
+                    }
+                    Ok(properties)
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("Worker thread panicked"))
+            .sum::<anyhow::Result<usize>>()
+    })?;
+    let elapsed = start.elapsed();
+
+    println!("Messages: {}", messages.len());
+    println!("Properties: {extracted}");
+    println!("Threads: {threads}");
+    println!("Readers: {readers}");
+    println!("Elapsed: {elapsed:?}");
+    if !elapsed.is_zero() {
+        println!(
+            "Messages/sec: {:.1}",
+            messages.len() as f64 / elapsed.as_secs_f64()
+        );
+    }
+
+    Ok(())
+}
+
+fn collect_messages(
+    store: &dyn Store,
+    entry_id: &EntryId,
+    limit: usize,
+    messages: &mut Vec<EntryId>,
+) -> anyhow::Result<()> {
+    let folder = store.open_folder(entry_id)?;
+
+    if let Some(contents_table) = folder.contents_table() {
+        for row in contents_table.rows_matrix()? {
+            if messages.len() >= limit {
+                return Ok(());
+            }
+
+            let node = NodeId::from(u32::from(row.id()));
+            messages.push(store.properties().make_entry_id(node)?);
+        }
+    }
+
+    if let Some(hierarchy_table) = folder.hierarchy_table() {
+        for row in hierarchy_table.rows_matrix()? {
+            if messages.len() >= limit {
+                return Ok(());
+            }
+
+            let node = NodeId::from(u32::from(row.id()));
+            let entry_id = store.properties().make_entry_id(node)?;
+            collect_messages(store, &entry_id, limit, messages)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,16 @@
+use clap::Parser;
+use outlook_pst::messaging::export::hierarchy_to_json_writer;
+use std::io::{self, Write};
+
+mod args;
+
+fn main() -> anyhow::Result<()> {
+    let args = args::Args::try_parse()?;
+    let store = outlook_pst::open_store(&args.file)?;
+
+    let mut stdout = io::stdout().lock();
+    hierarchy_to_json_writer(store.as_ref(), &mut stdout)?;
+    writeln!(stdout)?;
+
+    Ok(())
+}
@@ -12,7 +12,7 @@ fn main() -> anyhow::Result<()> {
     ))?;
     let context = hierarchy_table.context();
 
-    for row in hierarchy_table.rows_matrix() {
+    for row in hierarchy_table.rows_matrix()? {
         println!("Row: 0x{:X}", u32::from(row.id()));
         println!("Version: 0x{:X}", row.unique());
 
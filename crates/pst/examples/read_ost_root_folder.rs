@@ -0,0 +1,41 @@
+use clap::Parser;
+use outlook_pst::{
+    ltp::prop_context::PropertyValue,
+    messaging::store::{Store, UnicodeStore},
+    UnicodeOstFile,
+};
+use std::sync::Arc;
+
+mod args;
+
+fn main() -> anyhow::Result<()> {
+    let args = args::Args::try_parse()?;
+    let ost = UnicodeOstFile::open(&args.file)?;
+    let store = UnicodeStore::read(Arc::new(ost.into_inner()))?;
+    let hierarchy_table = store.root_hierarchy_table()?;
+    let context = hierarchy_table.context();
+
+    for row in hierarchy_table.rows_matrix()? {
+        println!("Row: 0x{:X}", u32::from(row.id()));
+
+        for (column, value) in context.columns().iter().zip(row.columns(context)?) {
+            let Some(value) = value else {
+                continue;
+            };
+
+            match hierarchy_table.read_column(&value, column.prop_type())? {
+                PropertyValue::String8(value) => {
+                    println!(" 0x{:04X}: {value}", column.prop_id());
+                }
+                PropertyValue::Unicode(value) => {
+                    println!(" 0x{:04X}: {value}", column.prop_id());
+                }
+                value => {
+                    println!(" 0x{:04X}: {value:?}", column.prop_id());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
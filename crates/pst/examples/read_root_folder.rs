@@ -8,7 +8,7 @@ fn main() -> anyhow::Result<()> {
     let hierarchy_table = store.root_hierarchy_table()?;
     let context = hierarchy_table.context();
 
-    for row in hierarchy_table.rows_matrix() {
+    for row in hierarchy_table.rows_matrix()? {
         println!("Row: 0x{:X}", u32::from(row.id()));
         println!("Version: 0x{:X}", row.unique());
 
@@ -27,7 +27,7 @@ fn main() -> anyhow::Result<()> {
             println!("  Record: {value:?}");
 
             let value = hierarchy_table.read_column(&value, column.prop_type())?;
-            println!("  Value: {value:?}");
+            println!("  Value: {value}");
         }
     }
 
@@ -0,0 +1,14 @@
+#![no_main]
+
+//! The first byte is the index of the block in the heap, which decides whether it starts with
+//! an HNHDR, HNPAGEHDR or HNBITMAPHDR. The rest is the decoded block data.
+
+use libfuzzer_sys::fuzz_target;
+use outlook_pst::fuzzing;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&block_index, block)) = data.split_first() else {
+        return;
+    };
+    let _ = fuzzing::heap_node_page(block, usize::from(block_index));
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+//! The first 8 bytes are a PC record. If it parses, the rest is read as a variable size value of
+//! the type in the record.
+
+use libfuzzer_sys::fuzz_target;
+use outlook_pst::fuzzing;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(record) = fuzzing::property_tree_record(data) else {
+        return;
+    };
+    if let Some(value) = data.get(8..) {
+        let _ = fuzzing::property_value(value, record.prop_type());
+    }
+});
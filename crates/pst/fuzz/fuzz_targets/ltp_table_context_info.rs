@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use outlook_pst::fuzzing;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = fuzzing::table_context_info(data);
+});
@@ -0,0 +1,29 @@
+#![no_main]
+
+//! Open a whole Unicode file from memory and walk its folder hierarchy.
+
+use libfuzzer_sys::fuzz_target;
+use outlook_pst::{
+    messaging::store::{Store, UnicodeStore},
+    UnicodePstFile,
+};
+use std::{io::Cursor, sync::Arc};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(pst) = UnicodePstFile::read_from(Box::new(Cursor::new(data.to_vec()))) else {
+        return;
+    };
+    let Ok(store) = UnicodeStore::read(Arc::new(pst)) else {
+        return;
+    };
+    let _ = store.properties().display_name();
+    for folder in store.folders_dfs().take(1024) {
+        let Ok(folder) = folder else {
+            continue;
+        };
+        let _ = folder.properties().display_name();
+        if let Some(table) = folder.contents_table() {
+            let _ = table.rows_iter().take(1024).count();
+        }
+    }
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+//! The first byte selects the format and the BTree: bit 0 is set for ANSI, and bit 1 for the
+//! block BTree instead of the node BTree. The rest is the page.
+
+use libfuzzer_sys::fuzz_target;
+use outlook_pst::fuzzing;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, page)) = data.split_first() else {
+        return;
+    };
+    match selector & 0x03 {
+        0 => drop(fuzzing::unicode_node_btree_page(page)),
+        1 => drop(fuzzing::ansi_node_btree_page(page)),
+        2 => drop(fuzzing::unicode_block_btree_page(page)),
+        _ => drop(fuzzing::ansi_block_btree_page(page)),
+    }
+});
@@ -0,0 +1,25 @@
+#![no_main]
+
+//! The first byte holds the `bCryptMethod` in the low bits, and bit 7 is set for ANSI. The next
+//! 8 bytes are the little-endian offset of the block in the file, and the rest is the block.
+
+use libfuzzer_sys::fuzz_target;
+use outlook_pst::{fuzzing, ndb::header::NdbCryptMethod};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, data)) = data.split_first() else {
+        return;
+    };
+    let Ok(encoding) = NdbCryptMethod::try_from(selector & 0x7F) else {
+        return;
+    };
+    let Some((offset, block)) = data.split_first_chunk::<8>() else {
+        return;
+    };
+    let offset = u64::from_le_bytes(*offset);
+    if selector & 0x80 == 0 {
+        let _ = fuzzing::unicode_data_tree(block, encoding, offset);
+    } else {
+        let _ = fuzzing::ansi_data_tree(block, encoding, offset as u32);
+    }
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use outlook_pst::fuzzing;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = fuzzing::unicode_header(data);
+    let _ = fuzzing::ansi_header(data);
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+//! The first byte is odd for ANSI, and the rest is the SLBLOCK or SIBLOCK.
+
+use libfuzzer_sys::fuzz_target;
+use outlook_pst::fuzzing;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, block)) = data.split_first() else {
+        return;
+    };
+    if selector & 0x01 == 0 {
+        let _ = fuzzing::unicode_sub_node_tree(block);
+    } else {
+        let _ = fuzzing::ansi_sub_node_tree(block);
+    }
+});
@@ -3,6 +3,7 @@
 //! This is a direct port of the CRC calculation code from the PST specification.
 
 use core::mem;
+use std::io::{self, Write};
 
 const CRC_TABLE_OFFSET32: [u32; 256] = [
     0x00000000, 0x77073096, 0xEE0E612C, 0x990951BA, 0x076DC419, 0x706AF48F, 0xE963A535, 0x9E6495A3,
@@ -333,3 +334,19 @@ pub fn compute_crc(crc: u32, data: &[u8]) -> u32 {
 
     crc
 }
+
+/// A [`Write`] sink which only keeps a running CRC of everything written to it, so large values
+/// can be checksummed while they are streamed.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CrcWriter(pub u32);
+
+impl Write for CrcWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 = compute_crc(self.0, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,417 @@
+//! ## Structural Diff
+//!
+//! Compare two PST files node by node, and report which nodes, properties, folders and table
+//! rows were added, removed or modified. This is meant for checking the output of the write
+//! support, e.g. after [`PstFile::compact`] or one of the [`messaging::edit`] functions.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::*;
+use crate::{
+    crc::{compute_crc, CrcWriter},
+    ltp::read_write::PropertyValueReadWrite,
+    messaging::read_write::StoreReadWrite,
+    upgrade::{NodeReader, NodeSource},
+};
+
+/// How an entry differs between the first and the second file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ChangeKind {
+    /// Only in the second file.
+    Added,
+    /// Only in the first file.
+    Removed,
+    /// In both files, with different contents.
+    Modified,
+}
+
+/// A node in the NBT. A node is modified if its parent, its data, or any of its sub-nodes
+/// changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct NodeChange {
+    pub node: NodeId,
+    pub kind: ChangeKind,
+}
+
+/// A property in the PC of a store, folder or message node which is modified in both files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PropertyChange {
+    pub node: NodeId,
+    pub prop_id: u16,
+    pub kind: ChangeKind,
+}
+
+/// A folder in the hierarchy. A folder is modified if it has a different parent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FolderChange {
+    pub folder: NodeId,
+    pub kind: ChangeKind,
+}
+
+/// The tables which belong to each folder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum FolderTable {
+    Hierarchy,
+    Contents,
+    Associated,
+}
+
+/// A row ID which was added to or removed from one of the tables of a folder in both files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TableRowChange {
+    pub folder: NodeId,
+    pub table: FolderTable,
+    pub row_id: u32,
+    pub kind: ChangeKind,
+}
+
+/// Every difference found by [`diff()`], sorted by node ID.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DiffReport {
+    pub nodes: Vec<NodeChange>,
+    pub properties: Vec<PropertyChange>,
+    pub folders: Vec<FolderChange>,
+    pub table_rows: Vec<TableRowChange>,
+}
+
+impl DiffReport {
+    /// True if the files have the same nodes, and the nodes have the same contents.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+            && self.properties.is_empty()
+            && self.folders.is_empty()
+            && self.table_rows.is_empty()
+    }
+}
+
+/// Compare the first file `a` with the second file `b`. The files may use different formats.
+///
+/// Nodes are compared by streaming each one through a CRC, and only the nodes which changed are
+/// opened to compare their properties, so unchanged attachments and bodies are not read into
+/// memory. Table rows are compared by row ID, a row which is in both tables with different
+/// values shows up as a modified node instead.
+pub fn diff(a: &PstFileReader, b: &PstFileReader) -> io::Result<DiffReport> {
+    let mut report = DiffReport::default();
+
+    let (nodes_a, nodes_b) = (node_hashes(a)?, node_hashes(b)?);
+    let mut modified = vec![];
+    for (node, kind) in compare(&nodes_a, &nodes_b) {
+        if kind == ChangeKind::Modified {
+            modified.push(node);
+        }
+        report.nodes.push(NodeChange {
+            node: NodeId::from(node),
+            kind,
+        });
+    }
+
+    for node in modified.into_iter().map(NodeId::from) {
+        let (Some(properties_a), Some(properties_b)) =
+            (property_hashes(a, node)?, property_hashes(b, node)?)
+        else {
+            continue;
+        };
+        report.properties.extend(
+            compare(&properties_a, &properties_b).map(|(prop_id, kind)| PropertyChange {
+                node,
+                prop_id,
+                kind,
+            }),
+        );
+    }
+
+    let (folders_a, folders_b) = (folder_summaries(a)?, folder_summaries(b)?);
+    for (folder, kind) in compare(&folders_a, &folders_b) {
+        let folder_id = NodeId::from(folder);
+        match (kind, folders_a.get(&folder), folders_b.get(&folder)) {
+            (ChangeKind::Modified, Some(summary_a), Some(summary_b)) => {
+                if summary_a.parent != summary_b.parent {
+                    report.folders.push(FolderChange {
+                        folder: folder_id,
+                        kind,
+                    });
+                }
+                for (table, (rows_a, rows_b)) in [
+                    FolderTable::Hierarchy,
+                    FolderTable::Contents,
+                    FolderTable::Associated,
+                ]
+                .into_iter()
+                .zip(summary_a.tables.iter().zip(summary_b.tables.iter()))
+                {
+                    let removed = rows_a
+                        .difference(rows_b)
+                        .map(|row| (*row, ChangeKind::Removed));
+                    let added = rows_b
+                        .difference(rows_a)
+                        .map(|row| (*row, ChangeKind::Added));
+                    let mut rows: Vec<_> = removed.chain(added).collect();
+                    rows.sort_by_key(|(row_id, _)| *row_id);
+                    report
+                        .table_rows
+                        .extend(rows.into_iter().map(|(row_id, kind)| TableRowChange {
+                            folder: folder_id,
+                            table,
+                            row_id,
+                            kind,
+                        }));
+                }
+            }
+            _ => report.folders.push(FolderChange {
+                folder: folder_id,
+                kind,
+            }),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Merge the keys of two sorted maps, and yield each key which is missing from one of them, or
+/// has a different value.
+fn compare<'a, K, V>(
+    a: &'a BTreeMap<K, V>,
+    b: &'a BTreeMap<K, V>,
+) -> impl 'a + Iterator<Item = (K, ChangeKind)>
+where
+    K: Copy + Ord,
+    V: PartialEq,
+{
+    let keys: BTreeSet<_> = a.keys().chain(b.keys()).copied().collect();
+    keys.into_iter()
+        .filter_map(move |key| match (a.get(&key), b.get(&key)) {
+            (Some(_), None) => Some((key, ChangeKind::Removed)),
+            (None, Some(_)) => Some((key, ChangeKind::Added)),
+            (Some(value_a), Some(value_b)) if value_a != value_b => {
+                Some((key, ChangeKind::Modified))
+            }
+            _ => None,
+        })
+}
+
+/// CRC of every node in the NBT, keyed by NID.
+fn node_hashes(pst: &PstFileReader) -> io::Result<BTreeMap<u32, u32>> {
+    match pst {
+        PstFileReader::Unicode(store) => {
+            let pst = store.pst();
+            let mut reader = NodeReader::new(pst.as_ref())?;
+            reader
+                .nodes()
+                .into_iter()
+                .map(|node| Ok((u32::from(node), reader.hash_node(node)?)))
+                .collect()
+        }
+        PstFileReader::Ansi(store) => {
+            let pst = store.pst();
+            let mut reader = NodeReader::new(pst.as_ref())?;
+            reader
+                .nodes()
+                .into_iter()
+                .map(|node| Ok((u32::from(node), reader.hash_node(node)?)))
+                .collect()
+        }
+    }
+}
+
+/// CRC of each property value in the PC for `node`, keyed by property ID, or `None` if the node
+/// is not a store, folder or message PC.
+fn property_hashes(pst: &PstFileReader, node: NodeId) -> io::Result<Option<BTreeMap<u16, u32>>> {
+    if node == NID_MESSAGE_STORE {
+        return hash_properties(pst.store_properties().iter()).map(Some);
+    }
+
+    match node.id_type()? {
+        NodeIdType::NormalFolder => {
+            let folder = pst.folder(node)?;
+            hash_properties(folder.properties().iter()).map(Some)
+        }
+        NodeIdType::NormalMessage | NodeIdType::AssociatedMessage => {
+            let message = pst.message(node)?;
+            hash_properties(message.properties().iter()).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+fn hash_properties<'a>(
+    properties: impl Iterator<Item = (&'a u16, &'a PropertyValue)>,
+) -> io::Result<BTreeMap<u16, u32>> {
+    properties
+        .map(|(prop_id, value)| {
+            let mut crc = CrcWriter(compute_crc(0, &u16::from(value.prop_type()).to_le_bytes()));
+            match PropertyValueRecord::from_small_value(value) {
+                Ok(record) => crc.write_all(&u32::from(record).to_le_bytes())?,
+                Err(_) => value.write(&mut crc)?,
+            }
+            Ok((*prop_id, crc.0))
+        })
+        .collect()
+}
+
+/// The parent of a folder in the hierarchy, and the row IDs in each of its tables.
+#[derive(PartialEq, Eq)]
+struct FolderSummary {
+    parent: Option<u32>,
+    tables: [BTreeSet<u32>; 3],
+}
+
+/// Walk the folder hierarchy, keyed by folder NID.
+fn folder_summaries(pst: &PstFileReader) -> io::Result<BTreeMap<u32, FolderSummary>> {
+    let row_ids = |table: Option<&Arc<dyn TableContext>>| -> io::Result<BTreeSet<u32>> {
+        let Some(table) = table else {
+            return Ok(Default::default());
+        };
+        table
+            .rows_iter()
+            .map(|row| Ok(u32::from(row?.id())))
+            .collect()
+    };
+
+    let mut parents = BTreeMap::new();
+    let mut summaries = BTreeMap::new();
+    for folder in pst.folders()? {
+        let node = u32::from(folder.properties().node_id());
+        let tables = [
+            row_ids(folder.hierarchy_table())?,
+            row_ids(folder.contents_table())?,
+            row_ids(folder.associated_table())?,
+        ];
+        for child in tables[0].iter() {
+            parents.insert(*child, node);
+        }
+        summaries.insert(
+            node,
+            FolderSummary {
+                parent: None,
+                tables,
+            },
+        );
+    }
+
+    for (node, summary) in summaries.iter_mut() {
+        summary.parent = parents.get(node).copied();
+    }
+    Ok(summaries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messaging::{
+        edit::MessageBuilder,
+        folder::UnicodeFolder,
+        message::UnicodeMessage,
+        property_ids::{PR_CONTENT_UNREAD, PR_MESSAGE_FLAGS},
+        store::UnicodeStore,
+    };
+    use crate::test_util::{TempFile, EMPTY_PST};
+    use std::{fs, sync::Arc};
+
+    fn inbox(path: &Path) -> NodeId {
+        let pst = PstFileReader::open(path).unwrap();
+        let root = pst.store_properties().ipm_sub_tree_entry_id().unwrap();
+        let root = pst.folder(NodeId::from(&root)).unwrap();
+        let row = root
+            .hierarchy_table()
+            .unwrap()
+            .rows_matrix()
+            .unwrap()
+            .next()
+            .unwrap()
+            .id();
+        NodeId::from(u32::from(row))
+    }
+
+    fn add_message(path: &Path, folder: NodeId, subject: &str) -> NodeId {
+        let store = UnicodeStore::read(Arc::new(UnicodePstFile::open(path).unwrap())).unwrap();
+        let entry_id = store.properties().make_entry_id(folder).unwrap();
+        let mut folder = Arc::into_inner(UnicodeFolder::read(store, &entry_id).unwrap()).unwrap();
+        folder
+            .add_message(&MessageBuilder::new().subject(subject))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let path_a = TempFile::new("diff_a");
+        let path_b = TempFile::new("diff_b");
+
+        // Both files have the first message, only the first file has the second one, and it is
+        // marked as read in the second file.
+        fs::copy(EMPTY_PST, &path_b).unwrap();
+        let inbox = inbox(&path_b);
+        let kept = add_message(&path_b, inbox, "Kept");
+        fs::copy(&path_b, &path_a).unwrap();
+        let removed = add_message(&path_a, inbox, "Removed");
+        let store = UnicodeStore::read(Arc::new(UnicodePstFile::open(&path_b).unwrap())).unwrap();
+        let entry_id = store.properties().make_entry_id(kept).unwrap();
+        Arc::into_inner(UnicodeMessage::read(store, &entry_id, None).unwrap())
+            .unwrap()
+            .mark_as_read()
+            .unwrap();
+
+        let a = PstFileReader::open(&path_a).unwrap();
+        let b = PstFileReader::open(&path_b).unwrap();
+        assert!(diff(&a, &a).unwrap().is_empty());
+
+        let report = diff(&a, &b).unwrap();
+        assert!(report.nodes.contains(&NodeChange {
+            node: removed,
+            kind: ChangeKind::Removed,
+        }));
+        assert!(report.nodes.contains(&NodeChange {
+            node: kept,
+            kind: ChangeKind::Modified,
+        }));
+        assert!(report
+            .nodes
+            .iter()
+            .all(|change| change.kind == ChangeKind::Modified || change.node == removed));
+
+        assert!(report.properties.contains(&PropertyChange {
+            node: kept,
+            prop_id: PR_MESSAGE_FLAGS,
+            kind: ChangeKind::Modified,
+        }));
+        assert!(report.properties.contains(&PropertyChange {
+            node: inbox,
+            prop_id: PR_CONTENT_UNREAD,
+            kind: ChangeKind::Modified,
+        }));
+        assert!(report
+            .properties
+            .iter()
+            .all(|change| change.node != removed));
+
+        assert!(report.folders.is_empty());
+        assert_eq!(
+            report.table_rows,
+            vec![TableRowChange {
+                folder: inbox,
+                table: FolderTable::Contents,
+                row_id: u32::from(removed),
+                kind: ChangeKind::Removed,
+            }]
+        );
+
+        let reversed = diff(&b, &a).unwrap();
+        assert!(reversed.nodes.contains(&NodeChange {
+            node: removed,
+            kind: ChangeKind::Added,
+        }));
+
+        #[cfg(feature = "json")]
+        assert!(serde_json::to_string(&report)
+            .unwrap()
+            .contains(r#""kind":"Removed""#));
+
+        drop((a, b));
+    }
+}
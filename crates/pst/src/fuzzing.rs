@@ -0,0 +1,205 @@
+//! Entry points for fuzz targets, which parse a single structure from a byte slice without the
+//! rest of the file around it. These are thin wrappers over the same `read` functions used when
+//! reading a file, and they are only built with the `fuzzing` feature.
+//!
+//! Functions which take the block from a slice read its trailer from the end of the slice, so
+//! the `cb` and `bid` in the trailer stand in for the [`BlockBTree`] entry which would normally
+//! refer to the block.
+
+use super::*;
+use crate::ltp::{
+    prop_type::PropertyType,
+    read_write::{
+        HeapNodePageReadWrite, PropertyTreeRecordReadWrite, PropertyValueReadWrite,
+        TableContextInfoReadWrite,
+    },
+};
+
+/// Parse a [`UnicodeHeader`] from the start of `data`.
+pub fn unicode_header(data: &[u8]) -> io::Result<UnicodeHeader> {
+    <UnicodeHeader as HeaderReadWrite<UnicodePstFile>>::read(&mut io::Cursor::new(data))
+}
+
+/// Parse an [`AnsiHeader`] from the start of `data`.
+pub fn ansi_header(data: &[u8]) -> io::Result<AnsiHeader> {
+    <AnsiHeader as HeaderReadWrite<AnsiPstFile>>::read(&mut io::Cursor::new(data))
+}
+
+fn unicode_page_ref() -> UnicodePageRef {
+    UnicodePageRef::new(UnicodePageId::from(0), UnicodeByteIndex::new(0))
+}
+
+fn ansi_page_ref() -> AnsiPageRef {
+    AnsiPageRef::new(AnsiPageId::from(0), AnsiByteIndex::new(0))
+}
+
+/// Parse a [`UnicodeNodeBTree`] page from the start of `data`.
+pub fn unicode_node_btree_page(data: &[u8]) -> io::Result<UnicodeNodeBTree> {
+    UnicodeNodeBTree::read(&mut io::Cursor::new(data), unicode_page_ref())
+}
+
+/// Parse a [`UnicodeBlockBTree`] page from the start of `data`.
+pub fn unicode_block_btree_page(data: &[u8]) -> io::Result<UnicodeBlockBTree> {
+    UnicodeBlockBTree::read(&mut io::Cursor::new(data), unicode_page_ref())
+}
+
+/// Parse an [`AnsiNodeBTree`] page from the start of `data`.
+pub fn ansi_node_btree_page(data: &[u8]) -> io::Result<AnsiNodeBTree> {
+    AnsiNodeBTree::read(&mut io::Cursor::new(data), ansi_page_ref())
+}
+
+/// Parse an [`AnsiBlockBTree`] page from the start of `data`.
+pub fn ansi_block_btree_page(data: &[u8]) -> io::Result<AnsiBlockBTree> {
+    AnsiBlockBTree::read(&mut io::Cursor::new(data), ansi_page_ref())
+}
+
+fn trailer<Trailer: BlockTrailerReadWrite>(data: &[u8]) -> io::Result<Trailer> {
+    let offset = data
+        .len()
+        .checked_sub(usize::from(Trailer::SIZE))
+        .ok_or(NdbError::InvalidBlockSize(data.len() as u16))?;
+    Trailer::read(&mut &data[offset..])
+}
+
+fn unicode_block_entry(data: &[u8], offset: u64) -> io::Result<UnicodeBlockBTreeEntry> {
+    let trailer = trailer::<UnicodeBlockTrailer>(data)?;
+    Ok(UnicodeBlockBTreeEntry::new(
+        UnicodeBlockRef::new(trailer.block_id(), UnicodeByteIndex::new(offset)),
+        trailer.size(),
+    ))
+}
+
+fn ansi_block_entry(data: &[u8], offset: u32) -> io::Result<AnsiBlockBTreeEntry> {
+    let trailer = trailer::<AnsiBlockTrailer>(data)?;
+    Ok(AnsiBlockBTreeEntry::new(
+        AnsiBlockRef::new(trailer.block_id(), AnsiByteIndex::new(offset)),
+        trailer.size(),
+    ))
+}
+
+/// Parse a data block, XBLOCK or XXBLOCK which fills `data`, as if it was found at `offset` in
+/// a Unicode file encoded with `encoding`. The `offset` only matters for the `wSig` check.
+pub fn unicode_data_tree(
+    data: &[u8],
+    encoding: NdbCryptMethod,
+    offset: u64,
+) -> io::Result<UnicodeDataTree> {
+    let entry = unicode_block_entry(data, offset)?;
+    UnicodeDataTree::read_from_slice(data, encoding, &entry)
+}
+
+/// Parse a data block, XBLOCK or XXBLOCK which fills `data`, as if it was found at `offset` in
+/// an ANSI file encoded with `encoding`. The `offset` only matters for the `wSig` check.
+pub fn ansi_data_tree(
+    data: &[u8],
+    encoding: NdbCryptMethod,
+    offset: u32,
+) -> io::Result<AnsiDataTree> {
+    let entry = ansi_block_entry(data, offset)?;
+    AnsiDataTree::read_from_slice(data, encoding, &entry)
+}
+
+/// Parse an SLBLOCK or SIBLOCK which fills `data` in a Unicode file.
+pub fn unicode_sub_node_tree(data: &[u8]) -> io::Result<UnicodeSubNodeTree> {
+    let entry = unicode_block_entry(data, 0)?;
+    UnicodeSubNodeTree::read(&mut io::Cursor::new(data), &entry)
+}
+
+/// Parse an SLBLOCK or SIBLOCK which fills `data` in an ANSI file.
+pub fn ansi_sub_node_tree(data: &[u8]) -> io::Result<AnsiSubNodeTree> {
+    let entry = ansi_block_entry(data, 0)?;
+    AnsiSubNodeTree::read(&mut io::Cursor::new(data), &entry)
+}
+
+/// Parse the HNPAGEMAP of a heap block which fills `data`, along with the [`HeapNodeHeader`]
+/// if `block_index` is 0. Other blocks start with an HNPAGEHDR or HNBITMAPHDR, depending on
+/// `block_index`.
+pub fn heap_node_page(
+    data: &[u8],
+    block_index: usize,
+) -> io::Result<(Option<HeapNodeHeader>, HeapNodePageMap)> {
+    ltp::heap::read_heap_page_map(block_index, data)
+}
+
+/// Parse a [`HeapTreeHeader`] from the start of `data`.
+pub fn heap_tree_header(data: &[u8]) -> io::Result<HeapTreeHeader> {
+    HeapTreeHeader::read(&mut io::Cursor::new(data))
+}
+
+/// Parse a [`PropertyTreeRecord`] from the start of `data`.
+pub fn property_tree_record(data: &[u8]) -> io::Result<PropertyTreeRecord> {
+    PropertyTreeRecord::read(&mut io::Cursor::new(data))
+}
+
+/// Parse a [`PropertyValue`] of type `prop_type` which fills `data`, the way a variable size
+/// value is read from the heap or a sub-node of a PC.
+pub fn property_value(data: &[u8], prop_type: PropertyType) -> io::Result<PropertyValue> {
+    PropertyValue::read(&mut io::Cursor::new(data), prop_type)
+}
+
+/// Parse a [`TableContextInfo`] (`TCINFO`) from the start of `data`.
+pub fn table_context_info(data: &[u8]) -> io::Result<TableContextInfo> {
+    TableContextInfo::read(&mut io::Cursor::new(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::EMPTY_PST;
+    use std::fs;
+
+    #[test]
+    fn test_parse_fixture_structures() {
+        let data = fs::read(EMPTY_PST).unwrap();
+        let header = unicode_header(&data).unwrap();
+        assert!(ansi_header(&data).is_err());
+
+        let root = header.root();
+        let node_btree = root.node_btree().index().index() as usize;
+        assert!(unicode_node_btree_page(&data[node_btree..]).is_ok());
+        let block_btree = root.block_btree().index().index() as usize;
+        let mut entries = Vec::new();
+        let mut pages = vec![block_btree];
+        while let Some(page) = pages.pop() {
+            match unicode_block_btree_page(&data[page..]).unwrap() {
+                RootBTreePage::Intermediate(page, ..) => pages.extend(
+                    page.entries()
+                        .iter()
+                        .map(|entry| entry.block().index().index() as usize),
+                ),
+                RootBTreePage::Leaf(page) => entries.extend_from_slice(page.entries()),
+            }
+        }
+
+        let pst = UnicodePstFile::read_from(Box::new(io::Cursor::new(data.clone()))).unwrap();
+        let node = pst.read_node(NID_MESSAGE_STORE).unwrap();
+        let entry = entries
+            .iter()
+            .find(|entry| entry.block().block() == node.data())
+            .unwrap();
+        let offset = entry.block().index().index();
+        let size = block_size(entry.size() + UnicodeBlockTrailer::SIZE) as usize;
+        let block = &data[offset as usize..offset as usize + size];
+        let UnicodeDataTree::Leaf(block) =
+            unicode_data_tree(block, header.crypt_method(), offset).unwrap()
+        else {
+            panic!("the message store PC should fit in one block");
+        };
+        let (heap, page_map) = heap_node_page(block.data(), 0).unwrap();
+        assert_eq!(
+            heap.map(|heap| heap.client_signature()),
+            Some(HeapNodeType::Properties)
+        );
+        assert!(!page_map.allocations().is_empty());
+
+        // A signature which does not match the offset of the block is rejected.
+        assert!(
+            unicode_data_tree(&data[offset as usize..][..size], header.crypt_method(), 0).is_err()
+        );
+
+        assert!(unicode_data_tree(&[], NdbCryptMethod::None, 0).is_err());
+        assert!(unicode_sub_node_tree(&[0; 8]).is_err());
+        assert!(heap_node_page(&[], 0).is_err());
+        assert!(table_context_info(&[0; 22]).is_err());
+    }
+}
@@ -1,31 +1,60 @@
 #![doc = include_str!("../README.md")]
 
 use std::{
-    cell::RefMut,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
     fmt::Debug,
     fs::{File, OpenOptions},
-    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     mem,
+    ops::{Deref, DerefMut},
     path::Path,
-    rc::Rc,
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LockResult, Mutex, MutexGuard, PoisonError, TryLockError,
+    },
 };
 use thiserror::Error;
 use tracing::{error, instrument, warn};
 
+pub mod diff;
 pub mod ltp;
 pub mod messaging;
 pub mod ndb;
 
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+
 mod block_sig;
 mod crc;
 mod encode;
+mod repair;
+#[cfg(test)]
+mod test_util;
+mod upgrade;
+mod validate;
+
+pub use repair::RepairReport;
+pub use upgrade::{upgrade_to_unicode, CompactionStats};
+pub use validate::{AmapCheckReport, ValidationReport};
+
+use upgrade::{write_data_tree, write_sub_node_tree, NodeContents, SubNodeBlockWriter};
 
 use ltp::{heap::*, prop_context::*, table_context::*, tree::*};
 use messaging::{folder::*, message::*, named_prop::*, search::*, store::*};
 use ndb::{
-    block::*, block_id::*, block_ref::*, byte_index::*, header::*, node_id::*, page::*,
-    read_write::*, root::*, *,
+    block::*,
+    block_id::*,
+    block_ref::*,
+    byte_index::*,
+    header::*,
+    node_id::*,
+    options::{Limits, ParseOptions, ParseScope},
+    page::*,
+    read_write::*,
+    root::*,
+    *,
 };
 
 #[derive(Error, Debug)]
@@ -46,8 +75,18 @@ pub enum PstError {
     NodeDatabaseError(#[from] NdbError),
     #[error("AllocationMapPage not found: {0}")]
     AllocationMapPageNotFound(usize),
+    #[error("Block or page is before the first AllocationMapPage: offset: 0x{0:X}")]
+    BlockBeforeFirstAmap(u64),
+    #[error("Allocation runs past the end of its AllocationMapPage: offset: 0x{0:X}")]
+    AllocationPastAmapPage(u64),
+    #[error("Allocations exceed the free space in AllocationMapPage: {0}")]
+    AllocationMapPageOverflow(usize),
     #[error("Invalid BTree page: offset: 0x{0:X}")]
     InvalidBTreePage(u64),
+    #[error("Node already exists: {0:?}")]
+    NodeAlreadyExists(NodeId),
+    #[error("A write transaction is already in progress")]
+    TransactionInProgress,
 }
 
 impl From<&PstError> for io::Error {
@@ -91,8 +130,60 @@ where
     fn start_write(&mut self) -> io::Result<()>;
     fn finish_write(&mut self) -> io::Result<()>;
 
-    fn block_cache(&self) -> RefMut<'_, RootBTreePageCache<<Pst as PstFile>::BlockBTree>>;
-    fn node_cache(&self) -> RefMut<'_, RootBTreePageCache<<Pst as PstFile>::NodeBTree>>;
+    fn start_transaction(&mut self) -> io::Result<()>;
+    fn commit_transaction(&mut self) -> io::Result<()>;
+    fn rollback_transaction(&mut self) -> io::Result<()>;
+
+    fn write_node(&mut self, node: &NodeContents) -> io::Result<()>;
+    fn remove_node(&mut self, node: NodeId) -> io::Result<()>;
+    fn reserve_node_ids(&mut self, next_node_ids: &[u32]) -> io::Result<()>;
+
+    fn pst(&self) -> &Pst;
+    fn pst_mut(&mut self) -> &mut Pst;
+
+    fn block_cache(&self) -> PageCacheGuard<'_, RootBTreePageCache<<Pst as PstFile>::BlockBTree>>;
+    fn node_cache(&self) -> PageCacheGuard<'_, RootBTreePageCache<<Pst as PstFile>::NodeBTree>>;
+}
+
+/// BTree page cache checked out for a single logical read operation. If another thread is
+/// already holding the shared cache, this falls back to an empty cache which is local to the
+/// operation, rather than waiting for the other thread to finish.
+enum PageCacheGuard<'a, T> {
+    Shared(MutexGuard<'a, T>),
+    Local(T),
+}
+
+impl<'a, T> PageCacheGuard<'a, T>
+where
+    T: Default,
+{
+    fn checkout(cache: &'a Mutex<T>) -> Self {
+        match cache.try_lock() {
+            Ok(cache) => Self::Shared(cache),
+            Err(TryLockError::Poisoned(err)) => Self::Shared(err.into_inner()),
+            Err(TryLockError::WouldBlock) => Self::Local(Default::default()),
+        }
+    }
+}
+
+impl<T> Deref for PageCacheGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            Self::Shared(cache) => cache,
+            Self::Local(cache) => cache,
+        }
+    }
+}
+
+impl<T> DerefMut for PageCacheGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        match self {
+            Self::Shared(cache) => cache,
+            Self::Local(cache) => cache,
+        }
+    }
 }
 
 /// This is the public interface for writing to a PST.
@@ -144,9 +235,427 @@ where
     }
 }
 
-pub trait PstReader: Read + Seek {}
+pub trait PstReader: Read + Seek + Send {}
+
+impl<T> PstReader for T where T: Read + Seek + Send {}
+
+/// Destination for writes to a PST file, which also needs to be truncated after a rollback or a
+/// rewrite of the BTrees, and cloned to append to it from a [`upgrade::NodeWriter`].
+pub(crate) trait PstWriter: Write + Seek + Send {
+    fn set_len(&mut self, size: u64) -> io::Result<()>;
+
+    fn try_clone(&self) -> io::Result<Box<dyn PstWriter>>;
+}
+
+impl PstWriter for File {
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        File::set_len(self, size)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn PstWriter>> {
+        Ok(Box::new(File::try_clone(self)?))
+    }
+}
+
+/// In-memory PST file, shared by every clone, so that the [`PstReaderPool`] and the writer of a
+/// [`PstFileInner`] see the same buffer. Each clone has its own position, like a separate handle
+/// to the same [`File`].
+#[derive(Clone, Default)]
+pub(crate) struct MemoryFile {
+    data: Arc<Mutex<Vec<u8>>>,
+    position: u64,
+}
+
+impl MemoryFile {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data: Arc::new(Mutex::new(data)),
+            position: 0,
+        }
+    }
+
+    /// Take the contents of the file, leaving every clone of it empty.
+    pub fn into_inner(self) -> io::Result<Vec<u8>> {
+        Ok(mem::take(&mut *self.data()?))
+    }
+
+    fn data(&self) -> io::Result<MutexGuard<'_, Vec<u8>>> {
+        self.data.lock().map_err(|_| PstError::LockError.into())
+    }
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.data()?;
+        let start = usize::try_from(self.position)
+            .unwrap_or(usize::MAX)
+            .min(data.len());
+        let count = buf.len().min(data.len() - start);
+        buf[..count].copy_from_slice(&data[start..start + count]);
+        drop(data);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut data = self.data()?;
+        let start = usize::try_from(self.position).map_err(|_| PstError::IntegerConversion)?;
+        let end = start
+            .checked_add(buf.len())
+            .ok_or(PstError::IntegerConversion)?;
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(buf);
+        drop(data);
+        self.position = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (self.data()?.len() as u64, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        self.position = base.checked_add_signed(offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl PstWriter for MemoryFile {
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        let size = usize::try_from(size).map_err(|_| PstError::IntegerConversion)?;
+        self.data()?.resize(size, 0);
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn PstWriter>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+/// Pool of readers for the same PST file, so that read operations on different threads do not
+/// need to wait for each other. Each reader is checked out for the duration of a single logical
+/// operation with [`PstReaderPool::lock`].
+pub struct PstReaderPool {
+    readers: Vec<Mutex<Box<dyn PstReader>>>,
+    next: AtomicUsize,
+    options: ParseOptions,
+    warnings: Mutex<Vec<NdbError>>,
+    #[cfg(feature = "mmap")]
+    mapped: Option<MappedFile>,
+}
+
+/// Shared read-only memory mapping of a PST file.
+#[cfg(feature = "mmap")]
+#[derive(Clone)]
+struct MappedFile(Arc<memmap2::Mmap>);
+
+#[cfg(feature = "mmap")]
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl PstReaderPool {
+    fn new(reader: Box<dyn PstReader>) -> Self {
+        Self {
+            readers: vec![Mutex::new(reader)],
+            next: AtomicUsize::new(0),
+            options: Default::default(),
+            warnings: Default::default(),
+            #[cfg(feature = "mmap")]
+            mapped: None,
+        }
+    }
+
+    /// Map the file at `path` into memory and create `count` readers which share the mapping.
+    #[cfg(feature = "mmap")]
+    fn map(path: impl AsRef<Path>, count: usize) -> io::Result<Self> {
+        let file = File::open(path)?;
+
+        // SAFETY: The mapping is read-only and we never hand out a mutable reference to it. The
+        // length of the mapping is fixed when the file is opened, and every read validates its
+        // offset against that length, so corrupt offsets past the end of the file are reported
+        // as errors. Another process truncating the file while it is mapped is still undefined
+        // behavior, which is why this backend is opt-in.
+        let mapped = MappedFile(Arc::new(unsafe { memmap2::Mmap::map(&file)? }));
+
+        let readers = (0..count.max(1))
+            .map(|_| {
+                let reader: Box<dyn PstReader> = Box::new(io::Cursor::new(mapped.clone()));
+                Mutex::new(reader)
+            })
+            .collect();
+
+        Ok(Self {
+            readers,
+            next: AtomicUsize::new(0),
+            options: Default::default(),
+            warnings: Default::default(),
+            mapped: Some(mapped),
+        })
+    }
+
+    /// Open `count` independent handles to the file at `path`, each with its own [`BufReader`].
+    /// If the platform does not let us open the file again, the pool keeps however many readers
+    /// it already opened, with a minimum of 1.
+    fn open(path: impl AsRef<Path>, count: usize) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut pool = Self::new(Box::new(BufReader::new(File::open(path)?)));
+
+        for _ in 1..count {
+            match File::open(path) {
+                Ok(file) => pool
+                    .readers
+                    .push(Mutex::new(Box::new(BufReader::new(file)))),
+                Err(err) => {
+                    warn!(
+                        name: "PstReaderPoolReopenFailed",
+                        ?err,
+                        readers = pool.readers.len(),
+                        "Failed to open another reader"
+                    );
+                    break;
+                }
+            }
+        }
+
+        Ok(pool)
+    }
+
+    /// Read `len` bytes starting at `offset` in the file. If the file is memory mapped, this
+    /// borrows the bytes from the mapping without copying them.
+    pub fn read_at(&self, offset: u64, len: usize) -> io::Result<Cow<'_, [u8]>> {
+        #[cfg(feature = "mmap")]
+        if let Some(mapped) = self.mapped.as_ref() {
+            let data = mapped.as_ref();
+            let start =
+                usize::try_from(offset).map_err(|_| NdbError::ReadPastEndOfFile(offset, len))?;
+            return start
+                .checked_add(len)
+                .and_then(|end| data.get(start..end))
+                .map(Cow::Borrowed)
+                .ok_or(NdbError::ReadPastEndOfFile(offset, len).into());
+        }
+
+        let mut reader = self.lock().map_err(|_| PstError::LockError)?;
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut data = vec![0; len];
+        reader
+            .read_exact(&mut data)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => NdbError::ReadPastEndOfFile(offset, len).into(),
+                _ => err,
+            })?;
+        Ok(Cow::Owned(data))
+    }
+
+    /// Check out a reader with [`PstReaderPool::lock`] and pass it to `f`. The reader goes back
+    /// to the pool when `f` returns.
+    pub fn with_reader<T>(
+        &self,
+        f: impl FnOnce(&mut dyn PstReader) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mut reader = self.lock().map_err(|_| PstError::LockError)?;
+        f(&mut **reader)
+    }
+
+    /// Number of readers in the pool.
+    pub fn len(&self) -> usize {
+        self.readers.len()
+    }
+
+    /// The pool always has at least 1 reader.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Check out a reader which is not in use by another thread. If all of the readers are
+    /// busy, this waits for one of them to become available. Reads on this thread use the
+    /// pool's [`ParseOptions`] until the reader is released.
+    pub fn lock(&self) -> LockResult<PstReaderGuard<'_>> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let count = self.readers.len();
+
+        let reader = (0..count)
+            .find_map(
+                |offset| match self.readers[(start + offset) % count].try_lock() {
+                    Ok(reader) => Some(Ok(reader)),
+                    Err(TryLockError::Poisoned(err)) => Some(Err(err)),
+                    Err(TryLockError::WouldBlock) => None,
+                },
+            )
+            .unwrap_or_else(|| self.readers[start % count].lock());
+
+        match reader {
+            Ok(reader) => Ok(PstReaderGuard::new(self, reader)),
+            Err(err) => Err(PoisonError::new(PstReaderGuard::new(
+                self,
+                err.into_inner(),
+            ))),
+        }
+    }
+
+    /// The [`ParseOptions`] which were used to open the file.
+    pub fn parse_options(&self) -> ParseOptions {
+        self.options
+    }
+
+    /// Apply the pool's [`ParseOptions`] to reads on this thread, e.g. for data returned by
+    /// [`PstReaderPool::read_at`], until the scope is dropped.
+    pub(crate) fn parse_scope(&self) -> ParseScope<'_> {
+        ParseScope::new(self.options, &self.warnings)
+    }
+
+    /// Remove and return the violations which were tolerated because of the [`ParseOptions`].
+    pub fn take_warnings(&self) -> Vec<NdbError> {
+        ndb::options::take_warnings(&self.warnings)
+    }
+}
+
+/// A reader checked out from a [`PstReaderPool`] with [`PstReaderPool::lock`].
+pub struct PstReaderGuard<'a> {
+    reader: MutexGuard<'a, Box<dyn PstReader>>,
+    _scope: ParseScope<'a>,
+}
+
+impl<'a> PstReaderGuard<'a> {
+    fn new(pool: &'a PstReaderPool, reader: MutexGuard<'a, Box<dyn PstReader>>) -> Self {
+        Self {
+            reader,
+            _scope: pool.parse_scope(),
+        }
+    }
+}
+
+impl Deref for PstReaderGuard<'_> {
+    type Target = Box<dyn PstReader>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.reader
+    }
+}
+
+impl DerefMut for PstReaderGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.reader
+    }
+}
+
+/// An explicit write transaction, see [`PstFile::begin_transaction`]. Dropping the transaction
+/// without calling [`WriteTransaction::commit`] rolls it back.
+pub struct WriteTransaction<'a, Pst>
+where
+    Pst: PstFile,
+{
+    pst: &'a mut dyn PstFileLock<Pst>,
+    finished: bool,
+}
+
+impl<'a, Pst> WriteTransaction<'a, Pst>
+where
+    Pst: PstFile,
+{
+    fn new(pst: &'a mut dyn PstFileLock<Pst>) -> io::Result<Self> {
+        pst.start_transaction()?;
+        Ok(Self {
+            pst,
+            finished: false,
+        })
+    }
+
+    /// Flush every write in the transaction, set [`AmapStatus::Valid2`], and write the header.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.finished = true;
+        self.pst.commit_transaction()
+    }
+
+    /// Undo every write in the transaction: pages which were overwritten are restored, the file is
+    /// truncated to the `ibFileEof` it had when the transaction began, and the original header is
+    /// written back.
+    pub fn rollback(mut self) -> io::Result<()> {
+        self.finished = true;
+        self.pst.rollback_transaction()
+    }
+
+    /// Write the data tree and sub-node tree of `node` to newly allocated blocks, and add it to
+    /// the [`NodeBTree`] with its parent. If there is already a node with the same [`NodeId`], it
+    /// is replaced and its old blocks are released.
+    pub(crate) fn write_node(&mut self, node: &NodeContents) -> io::Result<()> {
+        self.pst.write_node(node)
+    }
+
+    /// Remove `node` from the [`NodeBTree`], and release every block in its data tree and
+    /// sub-node tree.
+    pub(crate) fn remove_node(&mut self, node: NodeId) -> io::Result<()> {
+        self.pst.remove_node(node)
+    }
+
+    /// Raise the `rgnid` counters in the header to at least `next_node_ids`, so the node IDs
+    /// which were handed out for new nodes are not used again.
+    pub(crate) fn reserve_node_ids(&mut self, next_node_ids: &[u32]) -> io::Result<()> {
+        self.pst.reserve_node_ids(next_node_ids)
+    }
+}
+
+impl<Pst> Deref for WriteTransaction<'_, Pst>
+where
+    Pst: PstFile,
+{
+    type Target = Pst;
+
+    fn deref(&self) -> &Pst {
+        self.pst.pst()
+    }
+}
+
+impl<Pst> DerefMut for WriteTransaction<'_, Pst>
+where
+    Pst: PstFile,
+{
+    fn deref_mut(&mut self) -> &mut Pst {
+        self.pst.pst_mut()
+    }
+}
+
+impl<Pst> Drop for WriteTransaction<'_, Pst>
+where
+    Pst: PstFile,
+{
+    #[instrument(skip_all)]
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
 
-impl<T> PstReader for T where T: Read + Seek {}
+        if let Err(err) = self.pst.rollback_transaction() {
+            error!(
+                name: "PstWriteTransactionRollbackFailed",
+                ?err,
+                "Rolling back the write transaction failed"
+            );
+        }
+    }
+}
 
 /// [PST File](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/6b57253b-0853-47bb-99bb-d4b8f78105f0)
 pub trait PstFile: Sized {
@@ -198,24 +707,163 @@ pub trait PstFile: Sized {
     type SearchUpdateQueue: SearchUpdateQueue;
 
     fn header(&self) -> &Self::Header;
-    fn density_list(&self) -> Result<&dyn DensityListPage<Self>, &io::Error>;
-    fn reader(&self) -> &Mutex<Box<dyn PstReader>>;
+    /// Get the density list, or the reason it could not be read. Files which never had a density
+    /// list report [`DensityListError::NotPresent`].
+    fn density_list(&self) -> Result<&dyn DensityListPage<Self>, &DensityListError>;
+    fn reader(&self) -> &PstReaderPool;
+
+    /// Read `len` bytes starting at `offset` in the file, see [`PstReaderPool::read_at`].
+    fn read_at(&self, offset: u64, len: usize) -> io::Result<Cow<'_, [u8]>> {
+        self.reader().read_at(offset, len)
+    }
+
+    /// Run `f` with a reader from the pool, see [`PstReaderPool::with_reader`].
+    fn with_reader<T>(&self, f: impl FnOnce(&mut dyn PstReader) -> io::Result<T>) -> io::Result<T> {
+        self.reader().with_reader(f)
+    }
+
+    /// Remove and return the violations which were tolerated while reading the file, because
+    /// the [`ParseOptions`] it was opened with did not enforce them.
+    fn take_parse_warnings(&self) -> Vec<NdbError> {
+        self.reader().take_warnings()
+    }
+
     fn lock(&mut self) -> io::Result<PstFileLockGuard<'_, Self>>;
 
+    /// Begin an explicit write transaction. Every write made through the [`WriteTransaction`],
+    /// including ones which would normally finish their own transaction like
+    /// [`PstFile::insert_node`], is only completed by [`WriteTransaction::commit`], and can be
+    /// undone with [`WriteTransaction::rollback`]. Transactions cannot be nested.
+    fn begin_transaction(&mut self) -> io::Result<WriteTransaction<'_, Self>>;
+
     fn read_node(&self, node: NodeId) -> io::Result<Self::NodeBTreeEntry>;
     fn read_block(&self, block: Self::BlockId) -> io::Result<Vec<u8>>;
+
+    /// Read a single block by its BID, without following an XBLOCK or XXBLOCK to the rest of
+    /// its data tree. Data blocks are decoded with the file's [`NdbCryptMethod`], and every block
+    /// is checked against its CRC, BID and signature.
+    fn read_block_raw(&self, block: Self::BlockId) -> io::Result<DataTree<Self>>;
+
+    /// Count the free bits in every AMap and PMap page, and return the
+    /// `(amap_free_size, pmap_free_size)` values which the [`Root`] in the header should report.
+    fn recompute_free_sizes(&self) -> io::Result<(u64, u64)>;
+
+    /// Scan the `rgbAMapBits` of every AMap page which covers the file, and report the free
+    /// space and fragmentation in each one. This only reads the AMap pages, so the report is only
+    /// as accurate as the allocation map.
+    fn allocation_report(&self) -> io::Result<Vec<AmapPageStats>>;
+
+    /// Summarize [`PstFile::allocation_report`] for the whole file.
+    fn allocation_stats(&self) -> io::Result<AllocationStats> {
+        Ok(AllocationStats::new(&self.allocation_report()?))
+    }
+
+    /// Count the nodes in the [`NodeBTree`]. Only the intermediate pages are parsed, each leaf
+    /// page just contributes its `cEnt` field.
+    fn node_count(&self) -> io::Result<u64>;
+
+    /// Count the blocks in the [`BlockBTree`], the same way as [`PstFile::node_count`].
+    fn block_count(&self) -> io::Result<u64>;
+
+    /// The size of the file according to `ibFileEof` in the [`Root`] of the header.
+    fn file_size(&self) -> u64 {
+        self.header().root().file_eof_index().index().into()
+    }
+
+    /// Overwrite the free size counters in the [`Root`] of the header with the values from
+    /// [`PstFile::recompute_free_sizes`], and return them. This requires write access to the file.
+    fn fix_free_sizes(&mut self) -> io::Result<(u64, u64)>;
+
+    /// Find space which the allocation map says is allocated, but which is not part of any
+    /// BTree page or any block in the [`BlockBTree`], and return the file offset where each
+    /// contiguous run of it starts. Blocks which are still in the [`BlockBTree`] are not
+    /// orphaned, even if no node refers to them.
+    fn find_orphaned_blocks(&self) -> io::Result<Vec<u64>>;
+
+    /// Clear the allocation map bits for the space found by [`PstFile::find_orphaned_blocks`],
+    /// and return the number of bytes which were freed. This requires write access to the file.
+    fn reclaim_orphaned_blocks(&mut self) -> io::Result<u64>;
+
+    /// Check that every block referenced by a node in the [`NodeBTree`], including the blocks
+    /// in its data tree and sub-node tree, has an entry in the [`BlockBTree`].
+    fn validate(&self) -> io::Result<ValidationReport>;
+
+    /// Compare the AMap pages in the file with the allocations which the BTrees actually use,
+    /// built the same way as when an invalid allocation map is rebuilt, without changing anything
+    /// in the file.
+    fn check_allocation_map(&self) -> io::Result<AmapCheckReport>;
+
+    /// Add `node` to the [`NodeBTree`], pointing at the `data` block and optional `sub_node`
+    /// block, which should already be in the [`BlockBTree`]. Full pages are split in two, and the
+    /// BTree grows another level if the root page splits. This requires write access to the file.
+    fn insert_node(
+        &mut self,
+        node: NodeId,
+        data: Self::BlockId,
+        sub_node: Option<Self::BlockId>,
+    ) -> io::Result<()>;
+
+    /// Copy every node to a new file at `dest`, leaving out any free space in this file. This
+    /// file is not modified.
+    fn compact(&self, dest: impl AsRef<Path>) -> io::Result<CompactionStats> {
+        self.compact_with_encoding(dest, self.header().crypt_method())
+    }
+
+    /// Like [`PstFile::compact`], with the blocks in the new file encoded using `encoding`
+    /// instead of the [`NdbCryptMethod`] of this file.
+    fn compact_with_encoding(
+        &self,
+        dest: impl AsRef<Path>,
+        encoding: NdbCryptMethod,
+    ) -> io::Result<CompactionStats>;
+
+    /// Replace the [`NodeBTree`] and [`BlockBTree`] with new ones built from a linear scan of the
+    /// file, for files where the BTree pages are damaged but the blocks are intact. Every block
+    /// with a valid trailer, signature and CRC goes in the new BBT, and every node in an NBT leaf
+    /// page which passes the same checks goes in the new NBT, as long as its blocks were found.
+    /// The new pages are written after the end of the file, and the allocation map is rebuilt
+    /// from them. The header itself must be readable. This requires write access to the file.
+    fn rebuild_btrees_from_scan(&mut self) -> io::Result<RepairReport>;
+
+    /// Let `edit` change the contents of the data blocks of `node` in place, and if it returns
+    /// `true`, write them to newly allocated blocks with new block IDs. The [`NodeBTree`] entry is
+    /// updated to point at the new data tree, and the old blocks are released from the
+    /// [`BlockBTree`] and the allocation map. Unless it is part of an explicit
+    /// [`WriteTransaction`], the update is rolled back if any step fails. This requires write
+    /// access to the file.
+    fn update_node_data(
+        &mut self,
+        node: NodeId,
+        edit: impl FnOnce(&mut [Vec<u8>]) -> io::Result<bool>,
+    ) -> io::Result<bool>;
 }
 
 struct PstFileInner<Pst>
 where
     Pst: PstFile,
 {
-    reader: Mutex<Box<dyn PstReader>>,
-    writer: PstResult<Mutex<BufWriter<File>>>,
+    reader: PstReaderPool,
+    writer: PstResult<Mutex<BufWriter<Box<dyn PstWriter>>>>,
     header: Pst::Header,
-    density_list: io::Result<Pst::DensityListPage>,
+    density_list: Result<Pst::DensityListPage, DensityListError>,
     node_cache: NodeBTreePageCache<Pst>,
     block_cache: BlockBTreePageCache<Pst>,
+    transaction: Option<TransactionSnapshot<Pst>>,
+}
+
+/// The state of the file when a [`WriteTransaction`] began, so it can be restored by
+/// [`WriteTransaction::rollback`].
+struct TransactionSnapshot<Pst>
+where
+    Pst: PstFile,
+{
+    header: Pst::Header,
+    /// Allocations update the density list in memory, so it has to be restored along with the
+    /// header, or the next [`PstFileInner::finish_write`] would write the rolled back entries.
+    density_list: Option<Pst::DensityListPage>,
+    /// The original contents of every page before `ibFileEof` which was overwritten during the
+    /// transaction, by offset.
+    pages: BTreeMap<u64, Vec<u8>>,
 }
 
 pub struct UnicodePstFile {
@@ -225,15 +873,139 @@ pub struct UnicodePstFile {
 impl UnicodePstFile {
     pub fn read_from(reader: Box<dyn PstReader>) -> io::Result<Self> {
         let inner = PstFileInner::read_from(reader)?;
-        Ok(Self { inner })
+        Self::with_client(inner, NdbClient::Pst)
     }
 
     pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
-        let inner = PstFileInner::open(path)?;
+        Self::open_with_readers(path, 1)
+    }
+
+    /// Create a new, empty PST file at `path`, with its blocks encoded using `crypt_method`, and
+    /// open it. The store has the same mandatory folders and tables as a store created by
+    /// Outlook, a new `PR_RECORD_KEY`, and the file stem of `path` as its display name. Any
+    /// existing file at `path` is replaced.
+    pub fn create(path: impl AsRef<Path>, crypt_method: NdbCryptMethod) -> io::Result<Self> {
+        let path = path.as_ref();
+        messaging::edit::create_store(path, crypt_method)?;
+        Self::open(path)
+    }
+
+    /// Create an empty folder named `display_name` under `parent`, and return its [`NodeId`].
+    /// The folder's TCs are empty copies of the table templates in the store, and it gets a row
+    /// in the hierarchy table of `parent`, all in one transaction. The name follows the same rules
+    /// as [`UnicodeFolder::rename`](messaging::folder::UnicodeFolder::rename), and it must not
+    /// match any other sub-folder of `parent`. The folder holds e-mail messages.
+    pub fn create_folder(&mut self, parent: NodeId, display_name: &str) -> io::Result<NodeId> {
+        messaging::edit::create_folder(self, parent, display_name, messaging::items::IPF_NOTE)
+    }
+
+    /// Create a new, empty PST file in memory, with its blocks encoded using `crypt_method`. It
+    /// has the same contents as a file made with [`UnicodePstFile::create`], except that the
+    /// store keeps the display name of the template. Use [`UnicodePstFile::to_vec`] to get the
+    /// bytes of the file after writing to it.
+    pub fn create_in_memory(crypt_method: NdbCryptMethod) -> io::Result<Self> {
+        let mut pst = Self::open_in_memory(messaging::edit::create_store_in_memory(crypt_method)?)?;
+        pst.lock()?.flush()?;
+        Ok(pst)
+    }
+
+    /// Open a copy of the PST file in `data` for reading and writing, without touching the file
+    /// system. Use [`UnicodePstFile::to_vec`] to get the bytes of the file after writing to it.
+    pub fn open_in_memory(data: Vec<u8>) -> io::Result<Self> {
+        let inner = PstFileInner::open_in_memory(MemoryFile::new(data))?;
+        Self::with_client(inner, NdbClient::Pst)
+    }
+
+    /// Read the whole file into memory, including any writes which are still buffered.
+    pub fn to_vec(&self) -> io::Result<Vec<u8>> {
+        self.inner.to_vec()
+    }
+
+    /// Open the file with a [`PstReaderPool`] of up to `readers` independent file handles, so
+    /// that multiple threads can read from it at the same time.
+    pub fn open_with_readers(path: impl AsRef<Path>, readers: usize) -> io::Result<Self> {
+        let inner = PstFileInner::open(path, readers)?;
+        Self::with_client(inner, NdbClient::Pst)
+    }
+
+    /// Open the file with [`ParseOptions`] which relax some of the checks on the pages and blocks
+    /// read from it. Violations which are not enforced are collected for
+    /// [`PstFile::take_parse_warnings`].
+    pub fn open_with_options(path: impl AsRef<Path>, options: ParseOptions) -> io::Result<Self> {
+        let inner = PstFileInner::open_with_options(path, 1, options)?;
+        Self::with_client(inner, NdbClient::Pst)
+    }
+
+    /// Open the file read-only with a memory mapping, so that [`PstFile::read_at`] does not need
+    /// to copy data out of the file.
+    #[cfg(feature = "mmap")]
+    pub fn open_mapped(path: impl AsRef<Path>) -> io::Result<Self> {
+        let inner = PstFileInner::open_mapped(path)?;
+        Self::with_client(inner, NdbClient::Pst)
+    }
+
+    fn with_client(inner: PstFileInner<Self>, client: NdbClient) -> io::Result<Self> {
+        let found = inner.header.client();
+        if found != client {
+            return Err(NdbError::InvalidNdbHeaderMagicClientValue(found as u16).into());
+        }
         Ok(Self { inner })
     }
 }
 
+/// [OST File](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/6b57253b-0853-47bb-99bb-d4b8f78105f0)
+///
+/// Offline Storage Table files share the NDB and LTP layers with [`UnicodePstFile`], and only
+/// differ in the `wMagicClient` value of the [`Header`]. This wrapper accepts that magic value,
+/// and then everything else in the crate works on the [`UnicodePstFile`] it dereferences to.
+/// OST files written with the 4K page format (`wVer` 36) are not supported.
+#[cfg(feature = "ost")]
+pub struct UnicodeOstFile {
+    pst: UnicodePstFile,
+}
+
+#[cfg(feature = "ost")]
+impl UnicodeOstFile {
+    pub fn read_from(reader: Box<dyn PstReader>) -> io::Result<Self> {
+        let inner = PstFileInner::read_from(reader)?;
+        let pst = UnicodePstFile::with_client(inner, NdbClient::Ost)?;
+        Ok(Self { pst })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_with_readers(path, 1)
+    }
+
+    /// See [`UnicodePstFile::open_with_readers`].
+    pub fn open_with_readers(path: impl AsRef<Path>, readers: usize) -> io::Result<Self> {
+        let inner = PstFileInner::open(path, readers)?;
+        let pst = UnicodePstFile::with_client(inner, NdbClient::Ost)?;
+        Ok(Self { pst })
+    }
+
+    /// See [`UnicodePstFile::open_mapped`].
+    #[cfg(feature = "mmap")]
+    pub fn open_mapped(path: impl AsRef<Path>) -> io::Result<Self> {
+        let inner = PstFileInner::open_mapped(path)?;
+        let pst = UnicodePstFile::with_client(inner, NdbClient::Ost)?;
+        Ok(Self { pst })
+    }
+
+    /// Unwrap the [`UnicodePstFile`], e.g. to open it with [`UnicodeStore::read`].
+    pub fn into_inner(self) -> UnicodePstFile {
+        self.pst
+    }
+}
+
+#[cfg(feature = "ost")]
+impl Deref for UnicodeOstFile {
+    type Target = UnicodePstFile;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pst
+    }
+}
+
 impl PstFileLock<UnicodePstFile> for UnicodePstFile {
     fn start_write(&mut self) -> io::Result<()> {
         self.inner.start_write()
@@ -243,12 +1015,44 @@ impl PstFileLock<UnicodePstFile> for UnicodePstFile {
         self.inner.finish_write()
     }
 
-    fn block_cache(&self) -> RefMut<'_, RootBTreePageCache<<Self as PstFile>::BlockBTree>> {
-        self.inner.block_cache.borrow_mut()
+    fn start_transaction(&mut self) -> io::Result<()> {
+        self.inner.start_transaction()
+    }
+
+    fn commit_transaction(&mut self) -> io::Result<()> {
+        self.inner.commit_transaction()
+    }
+
+    fn rollback_transaction(&mut self) -> io::Result<()> {
+        self.inner.rollback_transaction()
+    }
+
+    fn write_node(&mut self, node: &NodeContents) -> io::Result<()> {
+        self.inner.write_node(node)
+    }
+
+    fn remove_node(&mut self, node: NodeId) -> io::Result<()> {
+        self.inner.remove_node(node)
+    }
+
+    fn reserve_node_ids(&mut self, next_node_ids: &[u32]) -> io::Result<()> {
+        self.inner.reserve_node_ids(next_node_ids)
+    }
+
+    fn pst(&self) -> &Self {
+        self
+    }
+
+    fn pst_mut(&mut self) -> &mut Self {
+        self
+    }
+
+    fn block_cache(&self) -> PageCacheGuard<'_, RootBTreePageCache<<Self as PstFile>::BlockBTree>> {
+        PageCacheGuard::checkout(&self.inner.block_cache)
     }
 
-    fn node_cache(&self) -> RefMut<'_, RootBTreePageCache<<Self as PstFile>::NodeBTree>> {
-        self.inner.node_cache.borrow_mut()
+    fn node_cache(&self) -> PageCacheGuard<'_, RootBTreePageCache<<Self as PstFile>::NodeBTree>> {
+        PageCacheGuard::checkout(&self.inner.node_cache)
     }
 }
 
@@ -292,11 +1096,11 @@ impl PstFile for UnicodePstFile {
         &self.inner.header
     }
 
-    fn density_list(&self) -> Result<&dyn DensityListPage<Self>, &io::Error> {
+    fn density_list(&self) -> Result<&dyn DensityListPage<Self>, &DensityListError> {
         self.inner.density_list.as_ref().map(|dl| dl as _)
     }
 
-    fn reader(&self) -> &Mutex<Box<dyn PstReader>> {
+    fn reader(&self) -> &PstReaderPool {
         &self.inner.reader
     }
 
@@ -304,13 +1108,98 @@ impl PstFile for UnicodePstFile {
         PstFileLockGuard::new(self)
     }
 
-    fn read_node(&self, node: NodeId) -> io::Result<UnicodeNodeBTreeEntry> {
+    fn begin_transaction(&mut self) -> io::Result<WriteTransaction<'_, Self>> {
+        WriteTransaction::new(self)
+    }
+
+    fn read_node(&self, node: NodeId) -> io::Result<UnicodeNodeBTreeEntry> {
         self.inner.read_node(node)
     }
 
     fn read_block(&self, block: UnicodeBlockId) -> io::Result<Vec<u8>> {
         self.inner.read_block(block)
     }
+
+    fn read_block_raw(&self, block: UnicodeBlockId) -> io::Result<UnicodeDataTree> {
+        self.inner.read_block_raw(block)
+    }
+
+    fn recompute_free_sizes(&self) -> io::Result<(u64, u64)> {
+        self.inner.recompute_free_sizes()
+    }
+
+    fn allocation_report(&self) -> io::Result<Vec<AmapPageStats>> {
+        self.inner.allocation_report()
+    }
+
+    fn node_count(&self) -> io::Result<u64> {
+        self.inner
+            .count_btree_entries::<<Self as PstFile>::NodeBTree>(*self.header().root().node_btree())
+    }
+
+    fn block_count(&self) -> io::Result<u64> {
+        self.inner
+            .count_btree_entries::<<Self as PstFile>::BlockBTree>(
+                *self.header().root().block_btree(),
+            )
+    }
+
+    fn fix_free_sizes(&mut self) -> io::Result<(u64, u64)> {
+        self.inner.fix_free_sizes()
+    }
+
+    fn find_orphaned_blocks(&self) -> io::Result<Vec<u64>> {
+        self.inner.find_orphaned_blocks()
+    }
+
+    fn reclaim_orphaned_blocks(&mut self) -> io::Result<u64> {
+        self.inner.reclaim_orphaned_blocks()
+    }
+
+    fn validate(&self) -> io::Result<ValidationReport> {
+        upgrade::NodeReader::new(self)?.validate()
+    }
+
+    fn check_allocation_map(&self) -> io::Result<AmapCheckReport> {
+        self.inner.check_allocation_map()
+    }
+
+    fn insert_node(
+        &mut self,
+        node: NodeId,
+        data: UnicodeBlockId,
+        sub_node: Option<UnicodeBlockId>,
+    ) -> io::Result<()> {
+        self.inner.insert_node(node, data, sub_node)
+    }
+
+    fn compact_with_encoding(
+        &self,
+        dest: impl AsRef<Path>,
+        encoding: NdbCryptMethod,
+    ) -> io::Result<CompactionStats> {
+        let dest = dest.as_ref();
+        let nodes = upgrade::compact(self, dest, encoding)?;
+        let mut pst = Self::open(dest)?;
+        pst.lock()?.flush()?;
+        Ok(CompactionStats {
+            nodes,
+            source_size: self.header().root().file_eof_index().index(),
+            dest_size: pst.header().root().file_eof_index().index(),
+        })
+    }
+
+    fn rebuild_btrees_from_scan(&mut self) -> io::Result<RepairReport> {
+        self.inner.rebuild_btrees_from_scan()
+    }
+
+    fn update_node_data(
+        &mut self,
+        node: NodeId,
+        edit: impl FnOnce(&mut [Vec<u8>]) -> io::Result<bool>,
+    ) -> io::Result<bool> {
+        self.inner.update_node_data(node, edit)
+    }
 }
 
 pub struct AnsiPstFile {
@@ -324,7 +1213,27 @@ impl AnsiPstFile {
     }
 
     pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
-        let inner = PstFileInner::open(path)?;
+        Self::open_with_readers(path, 1)
+    }
+
+    /// Open the file with a [`PstReaderPool`] of up to `readers` independent file handles, so
+    /// that multiple threads can read from it at the same time.
+    pub fn open_with_readers(path: impl AsRef<Path>, readers: usize) -> io::Result<Self> {
+        let inner = PstFileInner::open(path, readers)?;
+        Ok(Self { inner })
+    }
+
+    /// See [`UnicodePstFile::open_with_options`].
+    pub fn open_with_options(path: impl AsRef<Path>, options: ParseOptions) -> io::Result<Self> {
+        let inner = PstFileInner::open_with_options(path, 1, options)?;
+        Ok(Self { inner })
+    }
+
+    /// Open the file read-only with a memory mapping, so that [`PstFile::read_at`] does not need
+    /// to copy data out of the file.
+    #[cfg(feature = "mmap")]
+    pub fn open_mapped(path: impl AsRef<Path>) -> io::Result<Self> {
+        let inner = PstFileInner::open_mapped(path)?;
         Ok(Self { inner })
     }
 }
@@ -338,12 +1247,44 @@ impl PstFileLock<AnsiPstFile> for AnsiPstFile {
         self.inner.finish_write()
     }
 
-    fn block_cache(&self) -> RefMut<'_, RootBTreePageCache<<Self as PstFile>::BlockBTree>> {
-        self.inner.block_cache.borrow_mut()
+    fn start_transaction(&mut self) -> io::Result<()> {
+        self.inner.start_transaction()
+    }
+
+    fn commit_transaction(&mut self) -> io::Result<()> {
+        self.inner.commit_transaction()
+    }
+
+    fn rollback_transaction(&mut self) -> io::Result<()> {
+        self.inner.rollback_transaction()
+    }
+
+    fn write_node(&mut self, node: &NodeContents) -> io::Result<()> {
+        self.inner.write_node(node)
     }
 
-    fn node_cache(&self) -> RefMut<'_, RootBTreePageCache<<Self as PstFile>::NodeBTree>> {
-        self.inner.node_cache.borrow_mut()
+    fn remove_node(&mut self, node: NodeId) -> io::Result<()> {
+        self.inner.remove_node(node)
+    }
+
+    fn reserve_node_ids(&mut self, next_node_ids: &[u32]) -> io::Result<()> {
+        self.inner.reserve_node_ids(next_node_ids)
+    }
+
+    fn pst(&self) -> &Self {
+        self
+    }
+
+    fn pst_mut(&mut self) -> &mut Self {
+        self
+    }
+
+    fn block_cache(&self) -> PageCacheGuard<'_, RootBTreePageCache<<Self as PstFile>::BlockBTree>> {
+        PageCacheGuard::checkout(&self.inner.block_cache)
+    }
+
+    fn node_cache(&self) -> PageCacheGuard<'_, RootBTreePageCache<<Self as PstFile>::NodeBTree>> {
+        PageCacheGuard::checkout(&self.inner.node_cache)
     }
 }
 
@@ -387,11 +1328,11 @@ impl PstFile for AnsiPstFile {
         &self.inner.header
     }
 
-    fn density_list(&self) -> Result<&dyn DensityListPage<Self>, &io::Error> {
+    fn density_list(&self) -> Result<&dyn DensityListPage<Self>, &DensityListError> {
         self.inner.density_list.as_ref().map(|dl| dl as _)
     }
 
-    fn reader(&self) -> &Mutex<Box<dyn PstReader>> {
+    fn reader(&self) -> &PstReaderPool {
         &self.inner.reader
     }
 
@@ -399,6 +1340,10 @@ impl PstFile for AnsiPstFile {
         PstFileLockGuard::new(self)
     }
 
+    fn begin_transaction(&mut self) -> io::Result<WriteTransaction<'_, Self>> {
+        WriteTransaction::new(self)
+    }
+
     fn read_node(&self, node: NodeId) -> io::Result<AnsiNodeBTreeEntry> {
         self.inner.read_node(node)
     }
@@ -406,6 +1351,87 @@ impl PstFile for AnsiPstFile {
     fn read_block(&self, block: AnsiBlockId) -> io::Result<Vec<u8>> {
         self.inner.read_block(block)
     }
+
+    fn read_block_raw(&self, block: AnsiBlockId) -> io::Result<AnsiDataTree> {
+        self.inner.read_block_raw(block)
+    }
+
+    fn recompute_free_sizes(&self) -> io::Result<(u64, u64)> {
+        self.inner.recompute_free_sizes()
+    }
+
+    fn allocation_report(&self) -> io::Result<Vec<AmapPageStats>> {
+        self.inner.allocation_report()
+    }
+
+    fn node_count(&self) -> io::Result<u64> {
+        self.inner
+            .count_btree_entries::<<Self as PstFile>::NodeBTree>(*self.header().root().node_btree())
+    }
+
+    fn block_count(&self) -> io::Result<u64> {
+        self.inner
+            .count_btree_entries::<<Self as PstFile>::BlockBTree>(
+                *self.header().root().block_btree(),
+            )
+    }
+
+    fn fix_free_sizes(&mut self) -> io::Result<(u64, u64)> {
+        self.inner.fix_free_sizes()
+    }
+
+    fn find_orphaned_blocks(&self) -> io::Result<Vec<u64>> {
+        self.inner.find_orphaned_blocks()
+    }
+
+    fn reclaim_orphaned_blocks(&mut self) -> io::Result<u64> {
+        self.inner.reclaim_orphaned_blocks()
+    }
+
+    fn validate(&self) -> io::Result<ValidationReport> {
+        upgrade::NodeReader::new(self)?.validate()
+    }
+
+    fn check_allocation_map(&self) -> io::Result<AmapCheckReport> {
+        self.inner.check_allocation_map()
+    }
+
+    fn insert_node(
+        &mut self,
+        node: NodeId,
+        data: AnsiBlockId,
+        sub_node: Option<AnsiBlockId>,
+    ) -> io::Result<()> {
+        self.inner.insert_node(node, data, sub_node)
+    }
+
+    fn compact_with_encoding(
+        &self,
+        dest: impl AsRef<Path>,
+        encoding: NdbCryptMethod,
+    ) -> io::Result<CompactionStats> {
+        let dest = dest.as_ref();
+        let nodes = upgrade::compact(self, dest, encoding)?;
+        let mut pst = Self::open(dest)?;
+        pst.lock()?.flush()?;
+        Ok(CompactionStats {
+            nodes,
+            source_size: self.header().root().file_eof_index().index().into(),
+            dest_size: pst.header().root().file_eof_index().index().into(),
+        })
+    }
+
+    fn rebuild_btrees_from_scan(&mut self) -> io::Result<RepairReport> {
+        self.inner.rebuild_btrees_from_scan()
+    }
+
+    fn update_node_data(
+        &mut self,
+        node: NodeId,
+        edit: impl FnOnce(&mut [Vec<u8>]) -> io::Result<bool>,
+    ) -> io::Result<bool> {
+        self.inner.update_node_data(node, edit)
+    }
 }
 
 const AMAP_FIRST_OFFSET: u64 = 0x4400;
@@ -427,6 +1453,92 @@ const FPMAP_FIRST_OFFSET: u64 = AMAP_FIRST_OFFSET + FPMAP_FIRST_DATA_SIZE + (3 *
 const FPMAP_PAGE_COUNT: u64 = size_of::<MapBits>() as u64 * 64;
 const FPMAP_DATA_SIZE: u64 = AMAP_DATA_SIZE * FPMAP_PAGE_COUNT;
 
+/// Free space in a single AMap page, see [`PstFile::allocation_report`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AmapPageStats {
+    /// File offset of the AMap page, which is also the start of the space it covers.
+    pub offset: u64,
+    /// Total size of the free space covered by the page.
+    pub free_bytes: u64,
+    /// Size of the largest contiguous run of free space.
+    pub largest_free_run: u64,
+    /// Number of separate runs of free space.
+    pub free_runs: usize,
+}
+
+impl AmapPageStats {
+    fn new(offset: u64, map_bits: &MapBits) -> Self {
+        let mut stats = Self {
+            offset,
+            ..Default::default()
+        };
+
+        let mut run = 0;
+        let bits = map_bits
+            .iter()
+            .flat_map(|bits| (0..8).map(move |bit| bits & (0x80 >> bit) == 0));
+        for free in bits.chain(Some(false)) {
+            if free {
+                run += 64;
+                continue;
+            }
+            if run > 0 {
+                stats.free_bytes += run;
+                stats.largest_free_run = stats.largest_free_run.max(run);
+                stats.free_runs += 1;
+                run = 0;
+            }
+        }
+
+        stats
+    }
+}
+
+/// Free space in the whole file, see [`PstFile::allocation_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AllocationStats {
+    /// Size of the space covered by the AMap pages, including the pages themselves.
+    pub total_bytes: u64,
+    /// Allocated space, including the AMap pages and the other pages which share their space.
+    pub used_bytes: u64,
+    /// Free space.
+    pub free_bytes: u64,
+    /// Largest contiguous run of free space. Runs never cross from one AMap page to the next,
+    /// because the space covered by each AMap page starts with the page itself.
+    pub largest_contiguous_free: u64,
+    /// Number of AMap pages.
+    pub amap_page_count: usize,
+    /// How much of the free space is outside of the largest run, from `0.0` if all of it is in
+    /// one run (or there is none) up towards `1.0` as it is scattered in more and smaller runs.
+    pub fragmentation_ratio: f64,
+}
+
+impl AllocationStats {
+    fn new(pages: &[AmapPageStats]) -> Self {
+        let total_bytes = pages.len() as u64 * AMAP_DATA_SIZE;
+        let free_bytes = pages.iter().map(|page| page.free_bytes).sum();
+        let largest_contiguous_free = pages
+            .iter()
+            .map(|page| page.largest_free_run)
+            .max()
+            .unwrap_or_default();
+        let fragmentation_ratio = if free_bytes == 0 {
+            0.0
+        } else {
+            1.0 - largest_contiguous_free as f64 / free_bytes as f64
+        };
+
+        Self {
+            total_bytes,
+            used_bytes: total_bytes - free_bytes,
+            free_bytes,
+            largest_contiguous_free,
+            amap_page_count: pages.len(),
+            fragmentation_ratio,
+        }
+    }
+}
+
 struct AllocationMapPageInfo<Pst>
 where
     Pst: PstFile,
@@ -446,6 +1558,95 @@ where
     }
 }
 
+/// Find the index of the AMap region which covers the file `offset`. Offsets before the first AMap
+/// page come from a corrupt header or BTree, and are returned as errors rather than underflowing.
+fn amap_region(offset: u64) -> PstResult<u64> {
+    offset
+        .checked_sub(AMAP_FIRST_OFFSET)
+        .map(|offset| offset / AMAP_DATA_SIZE)
+        .ok_or(PstError::BlockBeforeFirstAmap(offset))
+}
+
+/// Count the pages reserved at the start of the AMap region at `index` for the AMap page itself,
+/// and the PMap, FMap, and FPMap pages which share the same region.
+fn amap_reserved_pages(index: u64) -> u64 {
+    let has_pmap_page = index % 8 == 0;
+    let has_fmap_page = has_pmap_page
+        && index >= FMAP_FIRST_SIZE
+        && (index - FMAP_FIRST_SIZE) % FMAP_PAGE_COUNT == 0;
+    let has_fpmap_page = has_pmap_page
+        && index >= FPMAP_FIRST_SIZE
+        && (index - FPMAP_FIRST_SIZE) % FPMAP_PAGE_COUNT == 0;
+
+    1 + u64::from(has_pmap_page) + u64::from(has_fmap_page) + u64::from(has_fpmap_page)
+}
+
+/// Count the AMap pages which cover a file ending at `file_eof`, one per region of
+/// [`AMAP_DATA_SIZE`] bytes, including a partial region at the end of the file.
+fn amap_page_count(file_eof: u64) -> u64 {
+    file_eof
+        .saturating_sub(AMAP_FIRST_OFFSET)
+        .div_ceil(AMAP_DATA_SIZE)
+}
+
+/// Count the PMap pages in a file with `num_amap_pages` AMap regions. There is one in every 8th
+/// region, starting with the first.
+fn pmap_page_count(num_amap_pages: u64) -> u64 {
+    num_amap_pages.div_ceil(PMAP_PAGE_COUNT)
+}
+
+/// Count the FMap pages in a file with `num_amap_pages` AMap regions. The first
+/// [`FMAP_FIRST_SIZE`] regions are covered by the header, so the first FMap page is in the region
+/// after that, and then there is one in every [`FMAP_PAGE_COUNT`] regions.
+fn fmap_page_count(num_amap_pages: u64) -> u64 {
+    num_amap_pages
+        .saturating_sub(FMAP_FIRST_SIZE)
+        .div_ceil(FMAP_PAGE_COUNT)
+}
+
+/// Count the FPMap pages in a file with `num_amap_pages` AMap regions. The first
+/// [`FPMAP_FIRST_SIZE`] regions are covered by the header, so the first FPMap page is in the
+/// region after that, and then there is one in every [`FPMAP_PAGE_COUNT`] regions.
+fn fpmap_page_count(num_amap_pages: u64) -> u64 {
+    num_amap_pages
+        .saturating_sub(FPMAP_FIRST_SIZE)
+        .div_ceil(FPMAP_PAGE_COUNT)
+}
+
+/// Find the first run of `count` free bits in an AMap page. Each run stays within a single 8KB
+/// chunk of the region, the largest size of a block, so a block never straddles the boundary.
+fn find_free_map_bits(map_bits: &[u8], count: usize) -> Option<usize> {
+    const CHUNK_BITS: usize = 8192 / 64;
+
+    let is_free = |bit: usize| map_bits[bit / 8] & (0x80 >> (bit % 8)) == 0;
+    let total = map_bits.len() * 8;
+    let mut start = 0;
+    while start + count <= total {
+        let chunk_end = (start / CHUNK_BITS + 1) * CHUNK_BITS;
+        if start + count > chunk_end {
+            start = chunk_end;
+            continue;
+        }
+        match (start..start + count).rev().find(|&bit| !is_free(bit)) {
+            Some(used) => start = used + 1,
+            None => return Some(start),
+        }
+    }
+    None
+}
+
+/// Set or clear `count` bits in an AMap page, starting at `start`.
+fn set_map_bits(map_bits: &mut [u8], start: usize, count: usize, allocated: bool) {
+    for bit in start..start + count {
+        let mask = 0x80 >> (bit % 8);
+        if allocated {
+            map_bits[bit / 8] |= mask;
+        } else {
+            map_bits[bit / 8] &= !mask;
+        }
+    }
+}
+
 type PstFileReadWriteBTree<Pst, BTree> = RootBTreePage<
     Pst,
     <BTree as RootBTree>::Entry,
@@ -457,6 +1658,8 @@ type PstFileReadWriteNodeBTree<Pst> = PstFileReadWriteBTree<Pst, <Pst as PstFile
 
 type PstFileReadWriteBlockBTree<Pst> = PstFileReadWriteBTree<Pst, <Pst as PstFile>::BlockBTree>;
 
+type BTreeIntermediateEntry<BTree> = <<BTree as RootBTree>::IntermediatePage as BTreePage>::Entry;
+
 impl<Pst> PstFileInner<Pst>
 where
     Pst: PstFile + PstFileLock<Pst>,
@@ -512,39 +1715,93 @@ where
     <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
         IntermediateTreeEntryReadWrite,
 {
-    fn read_from(mut reader: Box<dyn PstReader>) -> io::Result<Self> {
-        let header = <<Pst as PstFile>::Header as HeaderReadWrite<Pst>>::read(&mut reader)?;
-        let density_list =
-            <<Pst as PstFile>::DensityListPage as DensityListPageReadWrite<Pst>>::read(&mut reader);
+    fn read_from(reader: Box<dyn PstReader>) -> io::Result<Self> {
+        Self::read_from_pool(PstReaderPool::new(reader))
+    }
+
+    fn read_from_pool(reader: PstReaderPool) -> io::Result<Self> {
+        let (header, density_list) = {
+            let mut reader = reader.lock().map_err(|_| PstError::LockError)?;
+            let reader = &mut *reader;
+            let header = <<Pst as PstFile>::Header as HeaderReadWrite<Pst>>::read(reader)?;
+            let density_list =
+                <<Pst as PstFile>::DensityListPage as DensityListPageReadWrite<Pst>>::read(reader)
+                    .map_err(DensityListError::from);
+            (header, density_list)
+        };
         Ok(Self {
-            reader: Mutex::new(Box::new(reader)),
+            reader,
             writer: Err(PstError::OpenedReadOnly),
             header,
             density_list,
             node_cache: Default::default(),
             block_cache: Default::default(),
+            transaction: None,
         })
     }
 
-    fn open(path: impl AsRef<Path>) -> io::Result<Self> {
-        let reader = Box::new(File::open(&path)?);
+    fn open(path: impl AsRef<Path>, readers: usize) -> io::Result<Self> {
+        Self::open_with_options(path, readers, Default::default())
+    }
+
+    fn open_with_options(
+        path: impl AsRef<Path>,
+        readers: usize,
+        options: ParseOptions,
+    ) -> io::Result<Self> {
+        let mut reader = PstReaderPool::open(&path, readers)?;
+        reader.options = options;
         let writer = OpenOptions::new()
             .write(true)
             .open(&path)
-            .map(BufWriter::new)
+            .map(|file| BufWriter::new(Box::new(file) as Box<dyn PstWriter>))
             .map(Mutex::new)
             .map_err(|_| PstError::NoWriteAccess(path.as_ref().display().to_string()));
         Ok(Self {
             writer,
-            ..Self::read_from(reader)?
+            ..Self::read_from_pool(reader)?
+        })
+    }
+
+    fn open_in_memory(file: MemoryFile) -> io::Result<Self> {
+        let writer: Box<dyn PstWriter> = Box::new(file.clone());
+        Ok(Self {
+            writer: Ok(Mutex::new(BufWriter::new(writer))),
+            ..Self::read_from(Box::new(file))?
         })
     }
 
+    /// Flush any pending writes and read the whole file from the start.
+    fn to_vec(&self) -> io::Result<Vec<u8>> {
+        if let Ok(writer) = self.writer.as_ref() {
+            writer.lock().map_err(|_| PstError::LockError)?.flush()?;
+        }
+
+        let mut reader = self.reader.lock().map_err(|_| PstError::LockError)?;
+        let mut data = Vec::new();
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    #[cfg(feature = "mmap")]
+    fn open_mapped(path: impl AsRef<Path>) -> io::Result<Self> {
+        let readers = std::thread::available_parallelism()
+            .map(usize::from)
+            .unwrap_or(1);
+        Self::read_from_pool(PstReaderPool::map(path, readers)?)
+    }
+
     /// Begin a transaction by rebuilding the allocation map if needed and initializing the density
     /// list, then set [`AmapStatus::Invalid`] in the header till the transaction is finished.
     ///
     /// See also [Transactional Semantics](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/bc5a92df-7fc1-4dc2-9c7c-5677237dd73a).
     fn start_write(&mut self) -> io::Result<()> {
+        // An explicit transaction is already open, so this write is part of it.
+        if self.transaction.is_some() {
+            return Ok(());
+        }
+
         self.rebuild_allocation_map()?;
         self.ensure_density_list()?;
 
@@ -573,6 +1830,17 @@ where
     /// See also [Transactional Semantics](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/bc5a92df-7fc1-4dc2-9c7c-5677237dd73a).
     #[instrument(skip_all)]
     fn finish_write(&mut self) -> io::Result<()> {
+        // Leave the header alone till the explicit transaction is committed, but make sure the
+        // pages are written so they can be read back in the meantime.
+        if self.transaction.is_some() {
+            let mut writer = self
+                .writer
+                .as_ref()?
+                .lock()
+                .map_err(|_| PstError::LockError)?;
+            return writer.flush();
+        }
+
         // Reset AmapStatus::Valid2 to complete the transaction and then rewrite the updated
         // density list.
         let header = {
@@ -583,17 +1851,7 @@ where
         };
 
         self.update_density_list_page_id()?;
-        let density_list = {
-            self.density_list.as_ref().ok().and_then(|dl| {
-                <<Pst as PstFile>::DensityListPage as DensityListPageReadWrite<Pst>>::new(
-                    dl.backfill_complete(),
-                    dl.current_page(),
-                    dl.entries(),
-                    *dl.trailer(),
-                )
-                .ok()
-            })
-        };
+        let density_list = self.copy_density_list();
 
         let mut writer = self
             .writer
@@ -613,29 +1871,138 @@ where
         Ok(())
     }
 
-    /// [Crash Recovery and AMap Rebuilding](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/d9bcc1fd-c66a-41b3-b6d7-ed09d2a25ced)
-    fn rebuild_allocation_map(&mut self) -> io::Result<()> {
-        let root = self.header.root();
-        if AmapStatus::Invalid != root.amap_is_valid() {
+    /// Make a copy of the density list in memory, if there is a valid one.
+    fn copy_density_list(&self) -> Option<<Pst as PstFile>::DensityListPage> {
+        self.density_list.as_ref().ok().and_then(|dl| {
+            <<Pst as PstFile>::DensityListPage as DensityListPageReadWrite<Pst>>::new(
+                dl.backfill_complete(),
+                dl.current_page(),
+                dl.entries(),
+                *dl.trailer(),
+            )
+            .ok()
+        })
+    }
+
+    /// Begin an explicit transaction with [`Self::start_write`], and keep a snapshot of the header
+    /// and density list to restore if it is rolled back.
+    fn start_transaction(&mut self) -> io::Result<()> {
+        if self.transaction.is_some() {
+            return Err(PstError::TransactionInProgress.into());
+        }
+
+        self.start_write()?;
+        self.transaction = Some(TransactionSnapshot {
+            header: self.header.clone(),
+            density_list: self.copy_density_list(),
+            pages: Default::default(),
+        });
+        Ok(())
+    }
+
+    fn commit_transaction(&mut self) -> io::Result<()> {
+        if self.transaction.take().is_none() {
+            return Ok(());
+        }
+
+        self.finish_write()
+    }
+
+    /// Restore the pages, header and density list from the [`TransactionSnapshot`], and truncate
+    /// the file to the `ibFileEof` it had when the transaction began. The allocation map was valid
+    /// at that point, because [`Self::start_write`] rebuilds it if needed, so the header goes back
+    /// to [`AmapStatus::Valid2`].
+    #[instrument(skip_all)]
+    fn rollback_transaction(&mut self) -> io::Result<()> {
+        let Some(TransactionSnapshot {
+            header,
+            density_list,
+            pages,
+        }) = self.transaction.take()
+        else {
+            return Ok(());
+        };
+
+        if let Some(density_list) = density_list {
+            self.density_list = Ok(density_list);
+        }
+
+        let file_eof: u64 = header.root().file_eof_index().index().into();
+        let header = {
+            self.header = header;
+            self.header.update_unique();
+            let root = self.header.root_mut();
+            root.set_amap_status(AmapStatus::Valid2);
+            self.header.clone()
+        };
+
+        {
+            let mut writer = self
+                .writer
+                .as_ref()?
+                .lock()
+                .map_err(|_| PstError::LockError)?;
+            let writer = &mut *writer;
+            for (offset, data) in pages {
+                writer.seek(SeekFrom::Start(offset))?;
+                writer.write_all(&data)?;
+            }
+            writer.flush()?;
+            writer.get_mut().set_len(file_eof)?;
+            writer.seek(SeekFrom::Start(0))?;
+            header.write(writer)?;
+            writer.flush()?;
+        }
+
+        self.node_cache
+            .lock()
+            .map_err(|_| PstError::LockError)?
+            .clear();
+        self.block_cache
+            .lock()
+            .map_err(|_| PstError::LockError)?
+            .clear();
+        Ok(())
+    }
+
+    /// Save the original contents of the page at `offset` before it is overwritten, if an explicit
+    /// transaction is open and the page was already part of the file when it began.
+    fn save_page(&mut self, offset: u64) -> io::Result<()> {
+        let Some(transaction) = self.transaction.as_mut() else {
+            return Ok(());
+        };
+
+        let file_eof: u64 = transaction.header.root().file_eof_index().index().into();
+        if offset >= file_eof || transaction.pages.contains_key(&offset) {
             return Ok(());
         }
 
-        let num_amap_pages = root.file_eof_index().index().into() - AMAP_FIRST_OFFSET;
-        let num_amap_pages = num_amap_pages.div_ceil(AMAP_DATA_SIZE);
+        let data = self.reader.read_at(offset, PAGE_SIZE)?.into_owned();
+        transaction.pages.insert(offset, data);
+        Ok(())
+    }
+
+    /// Build the AMap pages which match the BTrees, with the reserved pages at the start of each
+    /// region, every BTree page, and every block in the BBT marked as allocated. If the file ends
+    /// part way through the last region, the space past the end is marked as allocated too, so
+    /// it is not counted as free.
+    ///
+    /// See also [Crash Recovery and AMap Rebuilding](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/d9bcc1fd-c66a-41b3-b6d7-ed09d2a25ced).
+    fn live_allocation_map(&self) -> io::Result<Vec<AllocationMapPageInfo<Pst>>> {
+        let root = self.header.root();
+        let file_eof: u64 = root.file_eof_index().index().into();
+        let num_amap_pages = amap_page_count(file_eof);
 
         let mut amap_pages: Vec<_> = (0..num_amap_pages)
             .map(|index| {
-                let has_pmap_page = index % 8 == 0;
-                let has_fmap_page = has_pmap_page
-                    && index >= FMAP_FIRST_SIZE
-                    && (index - FMAP_FIRST_SIZE) % FMAP_PAGE_COUNT == 0;
-                let has_fpmap_page = has_pmap_page
-                    && index >= FPMAP_FIRST_SIZE
-                    && (index - FPMAP_FIRST_SIZE) % FPMAP_PAGE_COUNT == 0;
+                let reserved = amap_reserved_pages(index);
+                let region_start = index * AMAP_DATA_SIZE + AMAP_FIRST_OFFSET;
+                let len = AMAP_DATA_SIZE.min(file_eof - region_start);
+                let present_bits = len.div_ceil(64);
 
                 let index =
                     <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(
-                        index * AMAP_DATA_SIZE + AMAP_FIRST_OFFSET,
+                        region_start,
                     )
                     .map_err(|_| PstError::IntegerConversion)?;
                 let block_id = <Pst as PstFile>::PageId::from(index);
@@ -648,21 +2015,16 @@ where
                 );
 
                 let mut map_bits = [0; mem::size_of::<MapBits>()];
-                let mut reserved = 1;
-                if has_pmap_page {
-                    reserved += 1;
-                }
-                if has_fmap_page {
-                    reserved += 1;
-                }
-                if has_fpmap_page {
-                    reserved += 1;
-                }
-
-                let free_space = AMAP_DATA_SIZE - (reserved * PAGE_SIZE) as u64;
+                let free_space = (present_bits * 64).saturating_sub(reserved * PAGE_SIZE as u64);
 
-                let reserved = &[0xFF; 4][..reserved];
+                let reserved = &[0xFF; 4][..reserved as usize];
                 map_bits[..reserved.len()].copy_from_slice(reserved);
+                set_map_bits(
+                    &mut map_bits,
+                    present_bits as usize,
+                    (AMAP_DATA_SIZE / 64 - present_bits) as usize,
+                    true,
+                );
 
                 let amap_page =
                     <<Pst as PstFile>::AllocationMapPage as AllocationMapPageReadWrite<Pst>>::new(
@@ -700,6 +2062,18 @@ where
             )?;
         }
 
+        Ok(amap_pages)
+    }
+
+    /// [Crash Recovery and AMap Rebuilding](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/d9bcc1fd-c66a-41b3-b6d7-ed09d2a25ced)
+    fn rebuild_allocation_map(&mut self) -> io::Result<()> {
+        if AmapStatus::Invalid != self.header.root().amap_is_valid() {
+            return Ok(());
+        }
+
+        let amap_pages = self.live_allocation_map()?;
+        let num_amap_pages = amap_pages.len() as u64;
+
         let free_bytes =
             <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(
                 amap_pages.iter().map(|page| page.free_space).sum(),
@@ -715,7 +2089,7 @@ where
             *entry = free_space;
         }
 
-        let pmap_pages: Vec<_> = (0..=(num_amap_pages / 8))
+        let pmap_pages: Vec<_> = (0..pmap_page_count(num_amap_pages))
             .map(|index| {
                 let index =
                     <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(
@@ -741,8 +2115,7 @@ where
             })
             .collect::<PstResult<Vec<_>>>()?;
 
-        let fmap_pages: Vec<_> = (0..(num_amap_pages.max(FMAP_FIRST_SIZE) - FMAP_FIRST_SIZE)
-            .div_ceil(FMAP_PAGE_COUNT))
+        let fmap_pages: Vec<_> = (0..fmap_page_count(num_amap_pages))
             .map(|index| {
                 let amap_index =
                     FMAP_FIRST_SIZE as usize + (index as usize * mem::size_of::<MapBits>());
@@ -777,8 +2150,7 @@ where
             })
             .collect::<PstResult<Vec<_>>>()?;
 
-        let fpmap_pages: Vec<_> = (0..(num_amap_pages.max(FPMAP_FIRST_SIZE) - FPMAP_FIRST_SIZE)
-            .div_ceil(FPMAP_PAGE_COUNT))
+        let fpmap_pages: Vec<_> = (0..fpmap_page_count(num_amap_pages))
             .map(|index| {
                 let index =
                     <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(
@@ -859,24 +2231,275 @@ where
         writer.flush()
     }
 
-    /// Recursively mark all of the pages in the [`Node BTree`](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/7d759bcb-7864-480c-8746-f6af913ab085).
-    /// as allocated. This does not include any blocks referenced in the nodes or the sub-trees in
-    /// those blocks, blocks will be marked by [`Self::mark_block_btree_allocations`].
-    ///
-    /// See also [Crash Recovery and AMap Rebuilding](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/d9bcc1fd-c66a-41b3-b6d7-ed09d2a25ced).
-    #[instrument(skip_all)]
-    fn mark_node_btree_allocations<R: PstReader>(
-        reader: &mut R,
-        page_index: Pst::ByteIndex,
-        node_btree: &PstFileReadWriteNodeBTree<Pst>,
-        amap_pages: &mut Vec<AllocationMapPageInfo<Pst>>,
-    ) -> io::Result<()> {
-        Self::mark_page_allocation(page_index.index().into(), amap_pages)?;
+    /// Count the free bits in every AMap and PMap page which covers the file, and return the
+    /// `(amap_free_size, pmap_free_size)` values that the [`Root`] should report.
+    fn recompute_free_sizes(&self) -> io::Result<(u64, u64)> {
+        let num_amap_pages = amap_page_count(self.header.root().file_eof_index().index().into());
 
-        if let RootBTreePage::Intermediate(page, ..) = node_btree {
-            let level = page.level();
-            for entry in page.entries() {
-                let block = entry.block();
+        let count_free_bits = |map_bits: &MapBits| -> u64 {
+            map_bits
+                .iter()
+                .map(|bits| u64::from(bits.count_zeros()))
+                .sum()
+        };
+
+        let mut amap_free_size = 0;
+        let mut pmap_free_size = 0;
+
+        for index in 0..num_amap_pages {
+            let offset = index * AMAP_DATA_SIZE + AMAP_FIRST_OFFSET;
+            let data = self.reader.read_at(offset, PAGE_SIZE)?;
+            let amap_page = <<Pst as PstFile>::AllocationMapPage as AllocationMapPageReadWrite<
+                Pst,
+            >>::read(&mut data.as_ref())?;
+            amap_free_size += count_free_bits(amap_page.map_bits()) * 64;
+
+            if index % PMAP_PAGE_COUNT == 0 {
+                let data = self.reader.read_at(offset + PAGE_SIZE as u64, PAGE_SIZE)?;
+                let pmap_page =
+                    <<Pst as PstFile>::AllocationPageMapPage as AllocationPageMapPageReadWrite<
+                        Pst,
+                    >>::read(&mut data.as_ref())?;
+                pmap_free_size += count_free_bits(pmap_page.map_bits()) * PAGE_SIZE as u64;
+            }
+        }
+
+        Ok((amap_free_size, pmap_free_size))
+    }
+
+    /// See [`PstFile::allocation_report`].
+    fn allocation_report(&self) -> io::Result<Vec<AmapPageStats>> {
+        let num_amap_pages = amap_page_count(self.header.root().file_eof_index().index().into());
+
+        (0..num_amap_pages)
+            .map(|index| {
+                let offset = index * AMAP_DATA_SIZE + AMAP_FIRST_OFFSET;
+                let data = self.reader.read_at(offset, PAGE_SIZE)?;
+                let amap_page =
+                    <<Pst as PstFile>::AllocationMapPage as AllocationMapPageReadWrite<Pst>>::read(
+                        &mut data.as_ref(),
+                    )?;
+                Ok(AmapPageStats::new(offset, amap_page.map_bits()))
+            })
+            .collect()
+    }
+
+    /// Count the entries in the leaf pages of the BTree starting at `root_ref`. The leaf pages
+    /// are not parsed, only the `cEnt` and `cLevel` fields are read from each one.
+    fn count_btree_entries<BTree>(&self, root_ref: <Pst as PstFile>::PageRef) -> io::Result<u64>
+    where
+        BTree: RootBTree<Pst = Pst> + RootBTreeReadWrite,
+        <BTree as RootBTree>::Entry: BTreeEntryReadWrite,
+        <BTree as RootBTree>::IntermediatePage: RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <BTree as RootBTree>::Entry,
+            <BTree as RootBTree>::LeafPage,
+        >,
+        BTreeIntermediateEntry<BTree>: BTreePageEntryReadWrite,
+        <BTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    {
+        let mut count = 0;
+        let mut leaf_pages = vec![];
+        {
+            let mut reader = self.reader.lock().map_err(|_| PstError::LockError)?;
+            let reader = &mut *reader;
+
+            // Each child of an intermediate page must be exactly one level lower, which also
+            // keeps a cycle in a corrupt file from looping forever.
+            let mut pages = vec![(root_ref, None)];
+            while let Some((page_ref, expected_level)) = pages.pop() {
+                let page = <BTree as RootBTreeReadWrite>::read(reader, page_ref)?;
+                let level = match &page {
+                    RootBTreePage::Intermediate(page, ..) => page.level(),
+                    RootBTreePage::Leaf(_) => 0,
+                };
+                if expected_level.is_some_and(|expected| expected != level) {
+                    return Err(PstError::InvalidBTreePage(page_ref.index().index().into()).into());
+                }
+
+                match page {
+                    RootBTreePage::Intermediate(page, ..) if level == 1 => {
+                        leaf_pages.extend(page.entries().iter().map(|entry| entry.block()));
+                    }
+                    RootBTreePage::Intermediate(page, ..) => {
+                        pages.extend(
+                            page.entries()
+                                .iter()
+                                .map(|entry| (entry.block(), Some(level - 1))),
+                        );
+                    }
+                    RootBTreePage::Leaf(page) => count += page.entries().len() as u64,
+                }
+            }
+        }
+
+        let entries_size =
+            <<BTree as RootBTree>::LeafPage as RootBTreeLeafPageReadWrite<Pst>>::BTREE_ENTRIES_SIZE;
+        for page_ref in leaf_pages {
+            let offset = page_ref.index().index().into();
+            let data = self.reader.read_at(offset, PAGE_SIZE)?;
+
+            // cEnt, cEntMax, cbEnt, and cLevel follow the entries.
+            let (entry_count, max_entries, level) = (
+                data[entries_size],
+                data[entries_size + 1],
+                data[entries_size + 3],
+            );
+            if level != 0 || entry_count > max_entries {
+                return Err(PstError::InvalidBTreePage(offset).into());
+            }
+            count += u64::from(entry_count);
+        }
+
+        Ok(count)
+    }
+
+    /// Write the values from [`Self::recompute_free_sizes`] into the [`Root`] of the header.
+    fn fix_free_sizes(&mut self) -> io::Result<(u64, u64)> {
+        let (amap_free_size, pmap_free_size) = self.recompute_free_sizes()?;
+        let to_byte_index = |value: u64| -> PstResult<<Pst as PstFile>::ByteIndex> {
+            let value =
+                <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(
+                    value,
+                )
+                .map_err(|_| PstError::IntegerConversion)?;
+            Ok(<<Pst as PstFile>::ByteIndex as ByteIndexReadWrite>::new(
+                value,
+            ))
+        };
+        let amap_free_bytes = to_byte_index(amap_free_size)?;
+        let pmap_free_bytes = to_byte_index(pmap_free_size)?;
+
+        let header = {
+            self.header.update_unique();
+
+            let root = self.header.root_mut();
+            root.set_free_sizes(amap_free_bytes, pmap_free_bytes);
+            self.header.clone()
+        };
+
+        let mut writer = self
+            .writer
+            .as_ref()?
+            .lock()
+            .map_err(|_| PstError::LockError)?;
+        let writer = &mut *writer;
+        writer.seek(SeekFrom::Start(0))?;
+        header.write(writer)?;
+        writer.flush()?;
+
+        Ok((amap_free_size, pmap_free_size))
+    }
+
+    /// See [`PstFile::check_allocation_map`].
+    fn check_allocation_map(&self) -> io::Result<AmapCheckReport> {
+        let add_run = |runs: &mut Vec<(u64, u64)>, offset: u64| match runs.last_mut() {
+            Some((start, size)) if *start + *size == offset => *size += 64,
+            _ => runs.push((offset, 64)),
+        };
+
+        let root = self.header.root();
+        let file_eof: u64 = root.file_eof_index().index().into();
+        let mut report = AmapCheckReport {
+            amap_free_size: root.amap_free_size().index().into(),
+            ..Default::default()
+        };
+        for (index, live) in self.live_allocation_map()?.into_iter().enumerate() {
+            let amap_offset = index as u64 * AMAP_DATA_SIZE + AMAP_FIRST_OFFSET;
+            let data = self.reader.read_at(amap_offset, PAGE_SIZE)?;
+            let amap_page = <<Pst as PstFile>::AllocationMapPage as AllocationMapPageReadWrite<
+                Pst,
+            >>::read(&mut data.as_ref())?;
+
+            let bits = amap_page
+                .map_bits()
+                .iter()
+                .zip(live.amap_page.map_bits().iter())
+                .flat_map(|(file, live)| {
+                    (0..8).map(move |bit| {
+                        let mask = 0x80 >> bit;
+                        (file & mask != 0, live & mask != 0)
+                    })
+                });
+            for (bit, (file, live)) in bits.enumerate() {
+                let offset = amap_offset + bit as u64 * 64;
+                if !live {
+                    report.expected_free_size += 64;
+                }
+                if offset >= file_eof {
+                    continue;
+                }
+                match (file, live) {
+                    (true, false) => add_run(&mut report.leaked, offset),
+                    (false, true) => add_run(&mut report.dangling, offset),
+                    _ => {}
+                }
+            }
+            report.amap_pages_checked += 1;
+        }
+
+        Ok(report)
+    }
+
+    /// Return the offset and size of each run of allocated space which is not part of a BTree
+    /// page or a block in the BBT, see [`Self::check_allocation_map`]. If the allocation map is
+    /// already marked invalid, there is nothing to compare it with, and it will be rebuilt by the
+    /// next write anyway.
+    fn orphaned_allocations(&self) -> io::Result<Vec<(u64, u64)>> {
+        if AmapStatus::Invalid == self.header.root().amap_is_valid() {
+            return Ok(Default::default());
+        }
+
+        Ok(self.check_allocation_map()?.leaked)
+    }
+
+    /// See [`PstFile::find_orphaned_blocks`].
+    fn find_orphaned_blocks(&self) -> io::Result<Vec<u64>> {
+        Ok(self
+            .orphaned_allocations()?
+            .into_iter()
+            .map(|(offset, _)| offset)
+            .collect())
+    }
+
+    /// See [`PstFile::reclaim_orphaned_blocks`].
+    fn reclaim_orphaned_blocks(&mut self) -> io::Result<u64> {
+        let reclaimed = self
+            .orphaned_allocations()?
+            .into_iter()
+            .map(|(_, size)| size)
+            .sum();
+        if reclaimed == 0 {
+            return Ok(0);
+        }
+        if self.transaction.is_some() {
+            return Err(PstError::TransactionInProgress.into());
+        }
+
+        // Rebuilding the allocation map from the BTrees clears every orphaned bit at once.
+        self.header.root_mut().set_amap_status(AmapStatus::Invalid);
+        self.rebuild_allocation_map()?;
+        Ok(reclaimed)
+    }
+
+    /// Recursively mark all of the pages in the [`Node BTree`](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/7d759bcb-7864-480c-8746-f6af913ab085).
+    /// as allocated. This does not include any blocks referenced in the nodes or the sub-trees in
+    /// those blocks, blocks will be marked by [`Self::mark_block_btree_allocations`].
+    ///
+    /// See also [Crash Recovery and AMap Rebuilding](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/d9bcc1fd-c66a-41b3-b6d7-ed09d2a25ced).
+    #[instrument(skip_all)]
+    fn mark_node_btree_allocations<R: PstReader>(
+        reader: &mut R,
+        page_index: Pst::ByteIndex,
+        node_btree: &PstFileReadWriteNodeBTree<Pst>,
+        amap_pages: &mut Vec<AllocationMapPageInfo<Pst>>,
+    ) -> io::Result<()> {
+        Self::mark_page_allocation(page_index.index().into(), amap_pages)?;
+
+        if let RootBTreePage::Intermediate(page, ..) = node_btree {
+            let level = page.level();
+            for entry in page.entries() {
+                let block = entry.block();
                 let node_btree = <Pst::NodeBTree as RootBTreeReadWrite>::read(reader, block)?;
                 match &node_btree {
                     RootBTreePage::Intermediate(page, ..) if page.level() + 1 != level => {
@@ -978,23 +2601,43 @@ where
         Ok(())
     }
 
-    /// Mark a page at the given file offset as allocated.
-    fn mark_page_allocation(
+    /// Find the AMap page which covers an allocation of `size` bytes at the given file offset,
+    /// and subtract `size` from its free space. Returns the page and the offset of the
+    /// allocation within the data which the page covers. Corrupt offsets and sizes in the
+    /// BTrees are returned as errors, rather than overflowing.
+    fn allocate_in_amap_page(
         index: u64,
+        size: u64,
         amap_pages: &mut [AllocationMapPageInfo<Pst>],
-    ) -> io::Result<()> {
-        let index = index - AMAP_FIRST_OFFSET;
+    ) -> PstResult<(&mut AllocationMapPageInfo<Pst>, u64)> {
+        let offset = index
+            .checked_sub(AMAP_FIRST_OFFSET)
+            .ok_or(PstError::BlockBeforeFirstAmap(index))?;
+        if offset % AMAP_DATA_SIZE + size > AMAP_DATA_SIZE {
+            return Err(PstError::AllocationPastAmapPage(index));
+        }
         let amap_index =
-            usize::try_from(index / AMAP_DATA_SIZE).map_err(|_| PstError::IntegerConversion)?;
+            usize::try_from(offset / AMAP_DATA_SIZE).map_err(|_| PstError::IntegerConversion)?;
         let entry = amap_pages
             .get_mut(amap_index)
             .ok_or(PstError::AllocationMapPageNotFound(amap_index))?;
-        entry.free_space -= PAGE_SIZE as u64;
+        entry.free_space = entry
+            .free_space
+            .checked_sub(size)
+            .ok_or(PstError::AllocationMapPageOverflow(amap_index))?;
+        Ok((entry, offset % AMAP_DATA_SIZE))
+    }
+
+    /// Mark a page at the given file offset as allocated.
+    fn mark_page_allocation(
+        index: u64,
+        amap_pages: &mut [AllocationMapPageInfo<Pst>],
+    ) -> io::Result<()> {
+        let (entry, offset) = Self::allocate_in_amap_page(index, PAGE_SIZE as u64, amap_pages)?;
 
         let bytes = entry.amap_page.map_bits_mut();
 
-        let bit_index = usize::try_from((index % AMAP_DATA_SIZE) / 64)
-            .map_err(|_| PstError::IntegerConversion)?;
+        let bit_index = usize::try_from(offset / 64).map_err(|_| PstError::IntegerConversion)?;
         let byte_index = bit_index / 8;
         let bit_index = bit_index % 8;
 
@@ -1016,21 +2659,16 @@ where
         size: u16,
         amap_pages: &mut [AllocationMapPageInfo<Pst>],
     ) -> io::Result<()> {
-        let index = index - AMAP_FIRST_OFFSET;
-        let amap_index =
-            usize::try_from(index / AMAP_DATA_SIZE).map_err(|_| PstError::IntegerConversion)?;
-        let entry = amap_pages
-            .get_mut(amap_index)
-            .ok_or(PstError::AllocationMapPageNotFound(amap_index))?;
-        let size = u64::from(block_size(
-            size + <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE,
-        ));
-        entry.free_space -= size;
+        let size = size
+            .checked_add(<<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE)
+            .filter(|size| *size <= MAX_BLOCK_SIZE)
+            .ok_or(NdbError::InvalidBlockSize(size))?;
+        let size = u64::from(block_size(size));
+        let (entry, offset) = Self::allocate_in_amap_page(index, size, amap_pages)?;
 
         let bytes = entry.amap_page.map_bits_mut();
 
-        let bit_start = usize::try_from((index % AMAP_DATA_SIZE) / 64)
-            .map_err(|_| PstError::IntegerConversion)?;
+        let bit_start = usize::try_from(offset / 64).map_err(|_| PstError::IntegerConversion)?;
         let bit_end =
             bit_start + usize::try_from(size / 64).map_err(|_| PstError::IntegerConversion)?;
         let byte_start = bit_start / 8;
@@ -1084,10 +2722,9 @@ where
             }
         }
 
-        let current_page = u32::try_from(
-            (self.header.root().amap_last_index().index().into() - AMAP_FIRST_OFFSET)
-                / AMAP_DATA_SIZE,
-        )
+        let current_page = u32::try_from(amap_region(
+            self.header.root().amap_last_index().index().into(),
+        )?)
         .map_err(|_| PstError::IntegerConversion)?;
         let block_id = self.header.next_page();
         let signature = PageType::DensityList
@@ -1143,48 +2780,2413 @@ where
         Ok(())
     }
 
-    fn read_node(&self, node: NodeId) -> io::Result<<Pst as PstFile>::NodeBTreeEntry> {
-        let node_btree = *self.header.root().node_btree();
-        let mut reader = self.reader.lock().map_err(|_| PstError::LockError)?;
-        let reader = &mut *reader;
-        let node_btree =
-            <<Pst as PstFile>::NodeBTree as RootBTreeReadWrite>::read(reader, node_btree)?;
-        let mut page_cache = self.node_cache.borrow_mut();
-        let node_id: <Pst as PstFile>::BTreeKey = u32::from(node).into();
-        let node = node_btree.find_entry(reader, node_id, &mut page_cache)?;
-        Ok(node)
+    /// Refresh the density list entry for the AMap page at `amap_offset` after allocating or
+    /// freeing space in it. The free slots are the free 64-byte units in the page. This only
+    /// updates the copy in memory; [`Self::finish_write`] writes it back to the file.
+    fn update_density_list_entry(
+        density_list: &mut Result<<Pst as PstFile>::DensityListPage, DensityListError>,
+        amap_offset: u64,
+        amap_page: &<Pst as PstFile>::AllocationMapPage,
+    ) -> PstResult<()> {
+        let Ok(density_list) = density_list.as_mut() else {
+            return Ok(());
+        };
+
+        let page =
+            u32::try_from(amap_region(amap_offset)?).map_err(|_| PstError::IntegerConversion)?;
+        let free_slots = amap_page
+            .map_bits()
+            .iter()
+            .map(|bits| bits.count_zeros() as u16)
+            .sum();
+        Ok(density_list.update_entry(page, free_slots)?)
     }
 
-    fn read_block(&self, block: <Pst as PstFile>::BlockId) -> io::Result<Vec<u8>> {
-        let encoding = self.header.crypt_method();
-        let block_btree = *self.header.root().block_btree();
-        let mut reader = self.reader.lock().map_err(|_| PstError::LockError)?;
-        let reader = &mut *reader;
-        let block_btree =
-            <<Pst as PstFile>::BlockBTree as RootBTreeReadWrite>::read(reader, block_btree)?;
-        let mut page_cache = self.block_cache.borrow_mut();
-        let block = block_btree.find_entry(reader, block.search_key(), &mut page_cache)?;
-        let block = DataTree::<Pst>::read(reader, encoding, &block)?;
-        let mut block_cache = Default::default();
-        let mut data = vec![];
-        let _ = block
-            .reader(
-                reader,
-                encoding,
-                &block_btree,
-                &mut page_cache,
-                &mut block_cache,
-            )?
-            .read_to_end(&mut data)?;
-        Ok(data)
+    fn byte_index(offset: u64) -> PstResult<<Pst as PstFile>::ByteIndex> {
+        let index =
+            <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(offset)
+                .map_err(|_| PstError::IntegerConversion)?;
+        Ok(<<Pst as PstFile>::ByteIndex as ByteIndexReadWrite>::new(
+            index,
+        ))
     }
-}
 
-pub fn open_store(path: impl AsRef<Path>) -> io::Result<Rc<dyn Store>> {
-    Ok(if let Ok(pst_file) = UnicodePstFile::open(path.as_ref()) {
-        UnicodeStore::read(Rc::new(pst_file))?
-    } else {
-        let pst_file = AnsiPstFile::open(path.as_ref())?;
-        AnsiStore::read(Rc::new(pst_file))?
-    })
+    /// Write the PMap, FMap, and FPMap pages which belong in the new AMap region at `index`, and
+    /// return an [`AllocationMapPage`] for the region with only the reserved pages allocated. The
+    /// caller allocates something in the AMap page and writes it.
+    ///
+    /// The file is extended to the end of the region, like a file written by
+    /// [`upgrade::NodeWriter`], and `root` is updated with the new EOF and last AMap page. That
+    /// way the whole region is inside the file, and [`Self::rebuild_allocation_map`] agrees with
+    /// the free space we add for it.
+    fn new_allocation_map_region(
+        writer: &mut BufWriter<Box<dyn PstWriter>>,
+        root: &mut <Pst as PstFile>::Root,
+        index: u64,
+    ) -> io::Result<<Pst as PstFile>::AllocationMapPage> {
+        let amap_offset = index * AMAP_DATA_SIZE + AMAP_FIRST_OFFSET;
+        let region_end = amap_offset + AMAP_DATA_SIZE;
+        let file_eof: u64 = root.file_eof_index().index().into();
+        if region_end > file_eof {
+            writer.flush()?;
+            writer.get_mut().set_len(region_end)?;
+            root.set_file_eof_index(Self::byte_index(region_end)?);
+        }
+        root.set_amap_last_index(Self::byte_index(amap_offset)?);
+
+        let reserved = amap_reserved_pages(index);
+        let page_trailer = |page_type: PageType, offset: u64| -> PstResult<_> {
+            let index =
+                <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(
+                    offset,
+                )
+                .map_err(|_| PstError::IntegerConversion)?;
+            Ok(
+                <<Pst as PstFile>::PageTrailer as PageTrailerReadWrite>::new(
+                    page_type,
+                    0,
+                    <Pst as PstFile>::PageId::from(index),
+                    0,
+                ),
+            )
+        };
+
+        // The PMap, FMap, and FPMap pages follow the AMap page in the same order as
+        // Self::rebuild_allocation_map writes them.
+        let mut map_offset = amap_offset + PAGE_SIZE as u64;
+        if index % PMAP_PAGE_COUNT == 0 {
+            let trailer = page_trailer(PageType::AllocationPageMap, map_offset)?;
+            let page =
+                <<Pst as PstFile>::AllocationPageMapPage as AllocationPageMapPageReadWrite<
+                    Pst,
+                >>::new([0xFF; mem::size_of::<MapBits>()], trailer)?;
+            writer.seek(SeekFrom::Start(map_offset))?;
+            <Pst::AllocationPageMapPage as AllocationPageMapPageReadWrite<Pst>>::write(
+                &page, writer,
+            )?;
+            map_offset += PAGE_SIZE as u64;
+        }
+        if index >= FMAP_FIRST_SIZE && (index - FMAP_FIRST_SIZE) % FMAP_PAGE_COUNT == 0 {
+            let trailer = page_trailer(PageType::FreeMap, map_offset)?;
+            let page = <<Pst as PstFile>::FreeMapPage as FreeMapPageReadWrite<Pst>>::new(
+                [0; mem::size_of::<MapBits>()],
+                trailer,
+            )?;
+            writer.seek(SeekFrom::Start(map_offset))?;
+            <Pst::FreeMapPage as FreeMapPageReadWrite<Pst>>::write(&page, writer)?;
+            map_offset += PAGE_SIZE as u64;
+        }
+        if index >= FPMAP_FIRST_SIZE && (index - FPMAP_FIRST_SIZE) % FPMAP_PAGE_COUNT == 0 {
+            let trailer = page_trailer(PageType::FreePageMap, map_offset)?;
+            let page = <<Pst as PstFile>::FreePageMapPage as FreePageMapPageReadWrite<Pst>>::new(
+                [0xFF; mem::size_of::<MapBits>()],
+                trailer,
+            )?;
+            writer.seek(SeekFrom::Start(map_offset))?;
+            <Pst::FreePageMapPage as FreePageMapPageReadWrite<Pst>>::write(&page, writer)?;
+        }
+
+        // Mark the reserved pages as allocated.
+        let mut map_bits = [0; mem::size_of::<MapBits>()];
+        map_bits[..reserved as usize].fill(0xFF);
+        Ok(
+            <<Pst as PstFile>::AllocationMapPage as AllocationMapPageReadWrite<Pst>>::new(
+                map_bits,
+                page_trailer(PageType::AllocationMap, amap_offset)?,
+            )?,
+        )
+    }
+
+    /// Allocate a page from the first free page in the allocation map, or add another AMap region
+    /// at the end of the file if all of the existing regions are full. The header is updated with
+    /// the new page ID, free space, and EOF, but it is not written until [`Self::finish_write`].
+    fn allocate_page(
+        &mut self,
+        page_type: PageType,
+    ) -> io::Result<(<Pst as PstFile>::PageRef, <Pst as PstFile>::PageTrailer)> {
+        let root = self.header.root();
+        let amap_last_index: u64 = root.amap_last_index().index().into();
+        let num_amap_pages = amap_region(amap_last_index)? + 1;
+
+        // Each byte in the AMap covers 8 * 64 bytes, so a byte with no bits set is a free page.
+        let mut free_page = None;
+        for index in 0..num_amap_pages {
+            let amap_offset = index * AMAP_DATA_SIZE + AMAP_FIRST_OFFSET;
+            let data = self.reader.read_at(amap_offset, PAGE_SIZE)?;
+            let mut amap_page =
+                <<Pst as PstFile>::AllocationMapPage as AllocationMapPageReadWrite<Pst>>::read(
+                    &mut data.as_ref(),
+                )?;
+            if let Some(byte_index) = amap_page.map_bits().iter().position(|bits| *bits == 0) {
+                amap_page.map_bits_mut()[byte_index] = 0xFF;
+                let offset = amap_offset + (byte_index * PAGE_SIZE) as u64;
+                free_page = Some((amap_offset, amap_page, offset, 0));
+                break;
+            }
+        }
+
+        if let Some((amap_offset, ..)) = &free_page {
+            self.save_page(*amap_offset)?;
+        }
+
+        let mut writer = self
+            .writer
+            .as_ref()?
+            .lock()
+            .map_err(|_| PstError::LockError)?;
+        let writer = &mut *writer;
+
+        let (amap_offset, amap_page, offset, added_free_space) = match free_page {
+            Some(free_page) => free_page,
+            None => {
+                let index = num_amap_pages;
+                let amap_offset = index * AMAP_DATA_SIZE + AMAP_FIRST_OFFSET;
+                let reserved = amap_reserved_pages(index);
+
+                // Mark the new page as allocated after the reserved pages.
+                let mut amap_page =
+                    Self::new_allocation_map_region(writer, self.header.root_mut(), index)?;
+                amap_page.map_bits_mut()[reserved as usize] = 0xFF;
+
+                let offset = amap_offset + reserved * PAGE_SIZE as u64;
+                let added_free_space = AMAP_DATA_SIZE - reserved * PAGE_SIZE as u64;
+                (amap_offset, amap_page, offset, added_free_space)
+            }
+        };
+
+        writer.seek(SeekFrom::Start(amap_offset))?;
+        <Pst::AllocationMapPage as AllocationMapPageReadWrite<Pst>>::write(&amap_page, writer)?;
+        writer.flush()?;
+        Self::update_density_list_entry(&mut self.density_list, amap_offset, &amap_page)?;
+
+        let page_id = self.header.next_page();
+        let next_page =
+            <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(
+                page_id.into_u64() + 1,
+            )
+            .map_err(|_| PstError::IntegerConversion)?;
+        self.header
+            .set_next_page(<Pst as PstFile>::PageId::from(next_page));
+
+        let root = self.header.root_mut();
+        let amap_free_size: u64 = root.amap_free_size().index().into();
+        let amap_free_size = (amap_free_size + added_free_space).saturating_sub(PAGE_SIZE as u64);
+        let pmap_free_size = *root.pmap_free_size();
+        root.set_free_sizes(Self::byte_index(amap_free_size)?, pmap_free_size);
+
+        let file_eof_index: u64 = root.file_eof_index().index().into();
+        let page_end = offset + PAGE_SIZE as u64;
+        if page_end > file_eof_index {
+            root.set_file_eof_index(Self::byte_index(page_end)?);
+        }
+
+        let signature = page_type.signature(offset, page_id.into_u64());
+        let trailer = <<Pst as PstFile>::PageTrailer as PageTrailerReadWrite>::new(
+            page_type, signature, page_id, 0,
+        );
+        let page_ref = <<Pst as PstFile>::PageRef as BlockRefReadWrite>::new(
+            page_id,
+            Self::byte_index(offset)?,
+        );
+        Ok((page_ref, trailer))
+    }
+
+    /// Take the next BID from the header for a new block.
+    fn new_block_id(&mut self, is_internal: bool) -> PstResult<<Pst as PstFile>::BlockId> {
+        let index: u64 = self.header.next_block().index().into();
+        let block_id = |value: u64| -> PstResult<<Pst as PstFile>::BlockId> {
+            let value =
+                <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(
+                    value,
+                )
+                .map_err(|_| PstError::IntegerConversion)?;
+            Ok(<Pst as PstFile>::BlockId::from(value))
+        };
+        let next_block = block_id((index + 1) << 2)?;
+        let block_id = block_id((index << 2) | if is_internal { 0x2 } else { 0x0 })?;
+        self.header.set_next_block(next_block);
+        Ok(block_id)
+    }
+
+    /// Find room for a block with `size` bytes of data in the allocation map and mark it as
+    /// allocated, or add another AMap region at the end of the file if none of the existing
+    /// regions have enough contiguous free space. The header is updated with the free space and
+    /// EOF, but it is not written until [`Self::finish_write`].
+    fn allocate_block_space(&mut self, size: u16) -> io::Result<u64> {
+        let size = u64::from(block_size(
+            size + <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE,
+        ));
+        let bit_count = (size / 64) as usize;
+
+        let root = self.header.root();
+        let amap_last_index: u64 = root.amap_last_index().index().into();
+        let num_amap_pages = amap_region(amap_last_index)? + 1;
+
+        let mut free_bits = None;
+        for index in 0..num_amap_pages {
+            let amap_offset = index * AMAP_DATA_SIZE + AMAP_FIRST_OFFSET;
+            let data = self.reader.read_at(amap_offset, PAGE_SIZE)?;
+            let amap_page = <<Pst as PstFile>::AllocationMapPage as AllocationMapPageReadWrite<
+                Pst,
+            >>::read(&mut data.as_ref())?;
+            if let Some(bit_index) = find_free_map_bits(amap_page.map_bits(), bit_count) {
+                free_bits = Some((amap_offset, amap_page, bit_index, 0));
+                break;
+            }
+        }
+
+        if let Some((amap_offset, ..)) = &free_bits {
+            self.save_page(*amap_offset)?;
+        }
+
+        let mut writer = self
+            .writer
+            .as_ref()?
+            .lock()
+            .map_err(|_| PstError::LockError)?;
+        let writer = &mut *writer;
+
+        let (amap_offset, mut amap_page, bit_index, added_free_space) = match free_bits {
+            Some(free_bits) => free_bits,
+            None => {
+                let index = num_amap_pages;
+                let amap_offset = index * AMAP_DATA_SIZE + AMAP_FIRST_OFFSET;
+                let reserved = amap_reserved_pages(index);
+                let amap_page =
+                    Self::new_allocation_map_region(writer, self.header.root_mut(), index)?;
+
+                let bit_index = reserved as usize * PAGE_SIZE / 64;
+                let added_free_space = AMAP_DATA_SIZE - reserved * PAGE_SIZE as u64;
+                (amap_offset, amap_page, bit_index, added_free_space)
+            }
+        };
+
+        set_map_bits(amap_page.map_bits_mut(), bit_index, bit_count, true);
+        writer.seek(SeekFrom::Start(amap_offset))?;
+        <Pst::AllocationMapPage as AllocationMapPageReadWrite<Pst>>::write(&amap_page, writer)?;
+        writer.flush()?;
+        Self::update_density_list_entry(&mut self.density_list, amap_offset, &amap_page)?;
+
+        let offset = amap_offset + bit_index as u64 * 64;
+        let root = self.header.root_mut();
+        let amap_free_size: u64 = root.amap_free_size().index().into();
+        let amap_free_size = (amap_free_size + added_free_space).saturating_sub(size);
+        let pmap_free_size = *root.pmap_free_size();
+        root.set_free_sizes(Self::byte_index(amap_free_size)?, pmap_free_size);
+
+        let file_eof_index: u64 = root.file_eof_index().index().into();
+        if offset + size > file_eof_index {
+            root.set_file_eof_index(Self::byte_index(offset + size)?);
+        }
+
+        Ok(offset)
+    }
+
+    /// Clear the bits for a block at `offset` with `size` bytes of data in the allocation map, and
+    /// add the space back to the free size in the header.
+    fn free_block_space(&mut self, offset: u64, size: u16) -> io::Result<()> {
+        let size = u64::from(block_size(
+            size + <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE,
+        ));
+        let amap_offset = amap_region(offset)? * AMAP_DATA_SIZE + AMAP_FIRST_OFFSET;
+        let bit_index = ((offset - amap_offset) / 64) as usize;
+
+        let mut amap_page = {
+            let data = self.reader.read_at(amap_offset, PAGE_SIZE)?;
+            <<Pst as PstFile>::AllocationMapPage as AllocationMapPageReadWrite<Pst>>::read(
+                &mut data.as_ref(),
+            )?
+        };
+        set_map_bits(
+            amap_page.map_bits_mut(),
+            bit_index,
+            (size / 64) as usize,
+            false,
+        );
+
+        self.save_page(amap_offset)?;
+        {
+            let mut writer = self
+                .writer
+                .as_ref()?
+                .lock()
+                .map_err(|_| PstError::LockError)?;
+            let writer = &mut *writer;
+            writer.seek(SeekFrom::Start(amap_offset))?;
+            <Pst::AllocationMapPage as AllocationMapPageReadWrite<Pst>>::write(&amap_page, writer)?;
+            writer.flush()?;
+        }
+        Self::update_density_list_entry(&mut self.density_list, amap_offset, &amap_page)?;
+
+        let root = self.header.root_mut();
+        let amap_free_size: u64 = root.amap_free_size().index().into();
+        let pmap_free_size = *root.pmap_free_size();
+        root.set_free_sizes(Self::byte_index(amap_free_size + size)?, pmap_free_size);
+        Ok(())
+    }
+
+    /// Write a new block at the location in its [`BlockBTree`] `entry`, saving any pages it
+    /// overwrites in the current transaction.
+    fn write_block(
+        &mut self,
+        block: &DataTree<Pst>,
+        entry: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<()> {
+        self.save_block_pages(entry)?;
+
+        let mut writer = self
+            .writer
+            .as_ref()?
+            .lock()
+            .map_err(|_| PstError::LockError)?;
+        let writer = &mut *writer;
+        block.write(writer, entry)?;
+        writer.flush()
+    }
+
+    /// Write an SLBLOCK or SIBLOCK at the location in `entry`, like [`Self::write_block`].
+    fn write_sub_node_block(
+        &mut self,
+        block: &SubNodeTree<Pst>,
+        entry: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<()> {
+        self.save_block_pages(entry)?;
+
+        let mut writer = self
+            .writer
+            .as_ref()?
+            .lock()
+            .map_err(|_| PstError::LockError)?;
+        let writer = &mut *writer;
+        block.write(writer, entry)?;
+        writer.flush()
+    }
+
+    /// Save every page which the block in `entry` overlaps, see [`Self::save_page`].
+    fn save_block_pages(&mut self, entry: &<Pst as PstFile>::BlockBTreeEntry) -> io::Result<()> {
+        let offset: u64 = entry.block().index().index().into();
+        let size = u64::from(block_size(
+            entry.size() + <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE,
+        ));
+        let first_page = offset - offset % PAGE_SIZE as u64;
+        for page in (first_page..offset + size).step_by(PAGE_SIZE) {
+            self.save_page(page)?;
+        }
+        Ok(())
+    }
+
+    /// Add a new entry to the [`BlockBTree`].
+    fn insert_block(&mut self, block: <Pst as PstFile>::BlockBTreeEntry) -> io::Result<()> {
+        let key = block.key();
+        let search_key: u64 = key.into();
+        let root_ref = *self.header.root().block_btree();
+        let new_root_ref = self.update_btree::<<Pst as PstFile>::BlockBTree>(
+            root_ref,
+            PageType::BlockBTree,
+            key,
+            |entries| {
+                let position = entries.partition_point(|entry| entry.key().into() < search_key);
+                entries.insert(position, block);
+                Ok(())
+            },
+        )?;
+        self.header.root_mut().set_block_btree(new_root_ref);
+        Ok(())
+    }
+
+    /// Drop a reference to a block in the [`BlockBTree`]. Once nothing refers to the block, its
+    /// entry is removed and the space is freed in the allocation map.
+    fn release_block(&mut self, block: <Pst as PstFile>::BlockBTreeEntry) -> io::Result<()> {
+        let key = block.key();
+        let search_key: u64 = key.into();
+        let root_ref = *self.header.root().block_btree();
+        let mut freed = None;
+        let new_root_ref = self.update_btree::<<Pst as PstFile>::BlockBTree>(
+            root_ref,
+            PageType::BlockBTree,
+            key,
+            |entries| {
+                let position = entries
+                    .iter()
+                    .position(|entry| entry.key().into() == search_key)
+                    .ok_or(NdbError::BTreePageNotFound(search_key))?;
+                let entry = entries[position];
+                if entry.ref_count() > 1 {
+                    entries[position] = entry.with_ref_count(entry.ref_count() - 1);
+                } else {
+                    entries.remove(position);
+                    freed = Some(entry);
+                }
+                Ok(())
+            },
+        )?;
+        self.header.root_mut().set_block_btree(new_root_ref);
+
+        if let Some(entry) = freed {
+            self.free_block_space(entry.block().index().index().into(), entry.size())?;
+        }
+        Ok(())
+    }
+
+    /// Write the `entries` of a BTree page back to the page at `page_ref`. If there are more
+    /// entries than fit in the page, the second half is moved to a newly allocated page, and the
+    /// first key and [`PageRef`] of that page are returned so they can be added to the parent.
+    #[allow(clippy::too_many_arguments)]
+    fn write_btree_page<Page>(
+        &mut self,
+        page_ref: <Pst as PstFile>::PageRef,
+        page_type: PageType,
+        level: u8,
+        max_entries: u8,
+        entry_size: u8,
+        trailer: <Pst as PstFile>::PageTrailer,
+        mut entries: Vec<<Page as BTreePage>::Entry>,
+        write: impl Fn(&Page, &mut BufWriter<Box<dyn PstWriter>>) -> io::Result<()>,
+    ) -> io::Result<Option<(<Pst as PstFile>::BTreeKey, <Pst as PstFile>::PageRef)>>
+    where
+        Page: BTreePageReadWrite<
+            Entry: BTreeEntry<Key = <Pst as PstFile>::BTreeKey>,
+            Trailer = <Pst as PstFile>::PageTrailer,
+        >,
+    {
+        let split = if entries.len() > usize::from(max_entries) {
+            let right = entries.split_off(entries.len() / 2);
+            let (right_ref, right_trailer) = self.allocate_page(page_type)?;
+            self.save_page(right_ref.index().index().into())?;
+            Some((right, right_ref, right_trailer))
+        } else {
+            None
+        };
+        self.save_page(page_ref.index().index().into())?;
+
+        let mut writer = self
+            .writer
+            .as_ref()?
+            .lock()
+            .map_err(|_| PstError::LockError)?;
+        let writer = &mut *writer;
+
+        let page = Page::new(level, max_entries, entry_size, &entries, trailer)?;
+        writer.seek(SeekFrom::Start(page_ref.index().index().into()))?;
+        write(&page, writer)?;
+
+        let split = match split {
+            Some((right, right_ref, right_trailer)) => {
+                let key = right[0].key();
+                let page = Page::new(level, max_entries, entry_size, &right, right_trailer)?;
+                writer.seek(SeekFrom::Start(right_ref.index().index().into()))?;
+                write(&page, writer)?;
+                Some((key, right_ref))
+            }
+            None => None,
+        };
+
+        writer.flush()?;
+        Ok(split)
+    }
+
+    /// Find the leaf page of a BTree which should hold `key`, let `edit` change its entries, and
+    /// write it back along with any intermediate pages whose keys changed. Full pages are split in
+    /// two, and the BTree grows another level if the root page splits. Returns the [`PageRef`] of
+    /// the root page, which is only different from `root_ref` if the root page was split.
+    fn update_btree<BTree>(
+        &mut self,
+        root_ref: <Pst as PstFile>::PageRef,
+        page_type: PageType,
+        key: <Pst as PstFile>::BTreeKey,
+        edit: impl FnOnce(&mut Vec<<BTree as RootBTree>::Entry>) -> io::Result<()>,
+    ) -> io::Result<<Pst as PstFile>::PageRef>
+    where
+        BTree: RootBTree<Pst = Pst> + RootBTreeReadWrite,
+        <BTree as RootBTree>::Entry: BTreeEntryReadWrite,
+        <BTree as RootBTree>::IntermediatePage: RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <BTree as RootBTree>::Entry,
+            <BTree as RootBTree>::LeafPage,
+        >,
+        BTreeIntermediateEntry<BTree>: BTreePageEntryReadWrite,
+        <BTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    {
+        let search_key: u64 = key.into();
+
+        // Find the leaf page for the key, keeping track of the intermediate pages along the way
+        // and the index of the entry we followed in each of them. A page which is already on the
+        // path means the BTree has a cycle.
+        let mut path: Vec<(<Pst as PstFile>::PageRef, _, _)> = vec![];
+        let (leaf_ref, leaf) = {
+            let mut reader = self.reader.lock().map_err(|_| PstError::LockError)?;
+            let reader = &mut *reader;
+            let mut page_ref = root_ref;
+            loop {
+                if path
+                    .iter()
+                    .any(|(parent, ..)| parent.block() == page_ref.block())
+                {
+                    return Err(NdbError::CyclicReference(page_ref.block().into_u64()).into());
+                }
+                match <BTree as RootBTreeReadWrite>::read(reader, page_ref)? {
+                    RootBTreePage::Intermediate(page, ..) => {
+                        let index = page
+                            .entries()
+                            .partition_point(|entry| entry.key().into() <= search_key)
+                            .saturating_sub(1);
+                        let child = page
+                            .entries()
+                            .get(index)
+                            .ok_or(PstError::InvalidBTreePage(page_ref.index().index().into()))?
+                            .block();
+                        path.push((page_ref, page, index));
+                        page_ref = child;
+                    }
+                    RootBTreePage::Leaf(page) => break (page_ref, page),
+                }
+            }
+        };
+
+        let mut entries = leaf.entries().to_vec();
+        edit(&mut entries)?;
+
+        self.start_write()?;
+
+        let mut first_key = entries.first().map(BTreeEntry::key);
+        let mut split = self.write_btree_page::<<BTree as RootBTree>::LeafPage>(
+            leaf_ref,
+            page_type,
+            0,
+            leaf.max_entries(),
+            leaf.entry_size(),
+            *leaf.trailer(),
+            entries,
+            |page, f| {
+                <<BTree as RootBTree>::LeafPage as RootBTreeLeafPageReadWrite<Pst>>::write(page, f)
+            },
+        )?;
+
+        // Walk back up the tree, adding the new pages from any splits and updating the keys for
+        // pages which now start with a different entry. An empty leaf keeps its old key.
+        let mut root_level = 0;
+        for (page_ref, page, index) in path.into_iter().rev() {
+            let mut entries = page.entries().to_vec();
+            let child = entries[index];
+            let child_key = first_key.unwrap_or(child.key());
+            if split.is_none() && child.key().into() == child_key.into() {
+                break;
+            }
+
+            entries[index] = <BTreeIntermediateEntry<BTree> as BTreePageEntryReadWrite>::new(
+                child_key,
+                child.block(),
+            );
+            if let Some((key, split_ref)) = split {
+                entries.insert(
+                    index + 1,
+                    <BTreeIntermediateEntry<BTree> as BTreePageEntryReadWrite>::new(key, split_ref),
+                );
+            }
+
+            first_key = Some(entries[0].key());
+            root_level = page.level();
+            split = self.write_btree_page::<<BTree as RootBTree>::IntermediatePage>(
+                page_ref,
+                page_type,
+                page.level(),
+                page.max_entries(),
+                page.entry_size(),
+                *page.trailer(),
+                entries,
+                |page, f| {
+                    <<BTree as RootBTree>::IntermediatePage as RootBTreeIntermediatePageReadWrite<
+                        Pst,
+                        <BTree as RootBTree>::Entry,
+                        <BTree as RootBTree>::LeafPage,
+                    >>::write(page, f)
+                },
+            )?;
+        }
+
+        // If the root page was split, add a new root page one level higher.
+        let Some((key, split_ref)) = split else {
+            return Ok(root_ref);
+        };
+        let first_key =
+            first_key.ok_or(PstError::InvalidBTreePage(root_ref.index().index().into()))?;
+        let entry_size = <BTreeIntermediateEntry<BTree> as BTreePageEntryReadWrite>::ENTRY_SIZE;
+        let max_entries =
+            <<BTree as RootBTree>::LeafPage as RootBTreeLeafPageReadWrite<Pst>>::BTREE_ENTRIES_SIZE
+                / entry_size;
+        let entries = vec![
+            <BTreeIntermediateEntry<BTree> as BTreePageEntryReadWrite>::new(first_key, root_ref),
+            <BTreeIntermediateEntry<BTree> as BTreePageEntryReadWrite>::new(key, split_ref),
+        ];
+        let (new_root_ref, trailer) = self.allocate_page(page_type)?;
+        self.write_btree_page::<<BTree as RootBTree>::IntermediatePage>(
+            new_root_ref,
+            page_type,
+            root_level + 1,
+            max_entries as u8,
+            entry_size as u8,
+            trailer,
+            entries,
+            |page, f| {
+                <<BTree as RootBTree>::IntermediatePage as RootBTreeIntermediatePageReadWrite<
+                    Pst,
+                    <BTree as RootBTree>::Entry,
+                    <BTree as RootBTree>::LeafPage,
+                >>::write(page, f)
+            },
+        )?;
+        Ok(new_root_ref)
+    }
+
+    /// Insert a new entry in the [`NodeBTree`], see [`PstFile::insert_node`].
+    fn insert_node(
+        &mut self,
+        node: NodeId,
+        data: <Pst as PstFile>::BlockId,
+        sub_node: Option<<Pst as PstFile>::BlockId>,
+    ) -> io::Result<()> {
+        let key: <Pst as PstFile>::BTreeKey = u32::from(node).into();
+        let search_key: u64 = key.into();
+        let root_ref = *self.header.root().node_btree();
+        let new_root_ref = self.update_btree::<<Pst as PstFile>::NodeBTree>(
+            root_ref,
+            PageType::NodeBTree,
+            key,
+            |entries| {
+                let position = entries.partition_point(|entry| entry.key().into() < search_key);
+                if entries
+                    .get(position)
+                    .is_some_and(|entry| entry.key().into() == search_key)
+                {
+                    return Err(PstError::NodeAlreadyExists(node).into());
+                }
+                entries.insert(
+                    position,
+                    <<Pst as PstFile>::NodeBTreeEntry as NodeBTreeEntryReadWrite>::new(
+                        node, data, sub_node, None,
+                    ),
+                );
+                Ok(())
+            },
+        )?;
+        self.header.root_mut().set_node_btree(new_root_ref);
+
+        // Pages were rewritten in place, so anything in the cache may be stale.
+        self.node_cache
+            .lock()
+            .map_err(|_| PstError::LockError)?
+            .clear();
+
+        self.finish_write()
+    }
+
+    fn read_node(&self, node: NodeId) -> io::Result<<Pst as PstFile>::NodeBTreeEntry> {
+        let node_btree = *self.header.root().node_btree();
+        let mut reader = self.reader.lock().map_err(|_| PstError::LockError)?;
+        let reader = &mut *reader;
+        let node_btree =
+            <<Pst as PstFile>::NodeBTree as RootBTreeReadWrite>::read(reader, node_btree)?;
+        let mut page_cache = PageCacheGuard::checkout(&*self.node_cache);
+        let node_id: <Pst as PstFile>::BTreeKey = u32::from(node).into();
+        let node = node_btree.find_entry(reader, node_id, &mut page_cache)?;
+        Ok(node)
+    }
+
+    fn read_block_raw(&self, block: <Pst as PstFile>::BlockId) -> io::Result<DataTree<Pst>> {
+        let encoding = self.header.crypt_method();
+        let block_btree = *self.header.root().block_btree();
+        let mut reader = self.reader.lock().map_err(|_| PstError::LockError)?;
+        let reader = &mut *reader;
+        let block_btree =
+            <<Pst as PstFile>::BlockBTree as RootBTreeReadWrite>::read(reader, block_btree)?;
+        let mut page_cache = PageCacheGuard::checkout(&*self.block_cache);
+        DataTree::<Pst>::read_block(reader, encoding, &block_btree, &mut page_cache, block)
+    }
+
+    fn read_block(&self, block: <Pst as PstFile>::BlockId) -> io::Result<Vec<u8>> {
+        let encoding = self.header.crypt_method();
+        let block_btree = *self.header.root().block_btree();
+        let mut reader = self.reader.lock().map_err(|_| PstError::LockError)?;
+        let reader = &mut *reader;
+        let block_btree =
+            <<Pst as PstFile>::BlockBTree as RootBTreeReadWrite>::read(reader, block_btree)?;
+        let mut page_cache = PageCacheGuard::checkout(&*self.block_cache);
+        let block =
+            DataTree::<Pst>::read_block(reader, encoding, &block_btree, &mut page_cache, block)?;
+        let mut block_cache = Default::default();
+        let mut data = vec![];
+        let _ = block
+            .reader(
+                reader,
+                encoding,
+                &block_btree,
+                &mut page_cache,
+                &mut block_cache,
+            )?
+            .read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Read every block in the data tree rooted at `block`. Returns the [`BlockBTree`] entries for
+    /// any XBLOCKs and XXBLOCKs, and the entries and contents of the data blocks in order.
+    #[allow(clippy::type_complexity)]
+    fn read_data_tree_blocks(
+        &self,
+        block: <Pst as PstFile>::BlockId,
+    ) -> io::Result<(
+        Vec<<Pst as PstFile>::BlockBTreeEntry>,
+        Vec<(
+            <Pst as PstFile>::BlockBTreeEntry,
+            Box<<Pst as PstFile>::DataBlock>,
+        )>,
+    )> {
+        let encoding = self.header.crypt_method();
+        let block_btree = *self.header.root().block_btree();
+        let mut reader = self.reader.lock().map_err(|_| PstError::LockError)?;
+        let reader = &mut *reader;
+        let block_btree =
+            <<Pst as PstFile>::BlockBTree as RootBTreeReadWrite>::read(reader, block_btree)?;
+        let mut page_cache = PageCacheGuard::checkout(&*self.block_cache);
+
+        let limits = Limits::current();
+        let mut visited = BTreeSet::new();
+        let mut intermediate = vec![];
+        let mut leaves = vec![];
+        let mut pending = vec![(block, 1)];
+        while let Some((block, depth)) = pending.pop() {
+            let entry = block_btree.find_entry(reader, block.search_key(), &mut page_cache)?;
+            match DataTree::<Pst>::read(reader, encoding, &entry)? {
+                DataTree::Intermediate(block) => {
+                    limits.check_data_tree_depth(depth)?;
+                    if !visited.insert(entry.block().block()) {
+                        return Err(
+                            NdbError::CyclicReference(entry.block().block().into_u64()).into()
+                        );
+                    }
+                    intermediate.push(entry);
+                    pending.extend(
+                        block
+                            .entries()
+                            .iter()
+                            .rev()
+                            .map(|entry| (entry.block(), depth + 1)),
+                    )
+                }
+                DataTree::Leaf(block) => {
+                    leaves.push((entry, block));
+                    limits.check_block_count(leaves.len())?;
+                }
+            }
+        }
+        Ok((intermediate, leaves))
+    }
+
+    /// Rewrite the data blocks of `node` after `edit` changes them, see
+    /// [`PstFile::update_node_data`].
+    fn update_node_data(
+        &mut self,
+        node: NodeId,
+        edit: impl FnOnce(&mut [Vec<u8>]) -> io::Result<bool>,
+    ) -> io::Result<bool> {
+        let entry = self.read_node(node)?;
+        let (intermediate, leaves) = self.read_data_tree_blocks(entry.data())?;
+        let mut blocks: Vec<_> = leaves
+            .iter()
+            .map(|(_, block)| block.data().to_vec())
+            .collect();
+        if !edit(&mut blocks)? {
+            return Ok(false);
+        }
+
+        // Join an explicit transaction if there is one, otherwise roll back everything written so
+        // far if any step fails.
+        if self.transaction.is_some() {
+            self.replace_node_data(entry, intermediate, leaves, &blocks)?;
+            return Ok(true);
+        }
+
+        self.start_transaction()?;
+        match self.replace_node_data(entry, intermediate, leaves, &blocks) {
+            Ok(()) => {
+                self.commit_transaction()?;
+                Ok(true)
+            }
+            Err(err) => {
+                if let Err(err) = self.rollback_transaction() {
+                    error!(
+                        name: "PstUpdateNodeDataRollbackFailed",
+                        ?err,
+                        "Rolling back the node data update failed"
+                    );
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Write `blocks` as a new data tree for the node in `entry`, point the node at it, and
+    /// release the `intermediate` and `leaves` blocks of the old data tree.
+    #[allow(clippy::type_complexity)]
+    fn replace_node_data(
+        &mut self,
+        entry: <Pst as PstFile>::NodeBTreeEntry,
+        intermediate: Vec<<Pst as PstFile>::BlockBTreeEntry>,
+        leaves: Vec<(
+            <Pst as PstFile>::BlockBTreeEntry,
+            Box<<Pst as PstFile>::DataBlock>,
+        )>,
+        blocks: &[Vec<u8>],
+    ) -> io::Result<()> {
+        self.start_write()?;
+
+        let mut allocator = FileBlockAllocator {
+            pst: self,
+            blocks: vec![],
+        };
+        let data_tree =
+            DataTree::<Pst>::build_from_blocks(blocks.iter().map(Vec::as_slice), &mut allocator)?;
+        for block in allocator.blocks {
+            self.insert_block(block)?;
+        }
+
+        let key = entry.key();
+        let root_ref = *self.header.root().node_btree();
+        let new_root_ref = self.update_btree::<<Pst as PstFile>::NodeBTree>(
+            root_ref,
+            PageType::NodeBTree,
+            key,
+            |entries| {
+                let position = entries
+                    .iter()
+                    .position(|node| node.key().into() == key.into())
+                    .ok_or(NdbError::BTreePageNotFound(key.into()))?;
+                entries[position] =
+                    <<Pst as PstFile>::NodeBTreeEntry as NodeBTreeEntryReadWrite>::new(
+                        entry.node(),
+                        data_tree.block_id(),
+                        entry.sub_node(),
+                        entry.parent(),
+                    );
+                Ok(())
+            },
+        )?;
+        self.header.root_mut().set_node_btree(new_root_ref);
+
+        for block in intermediate
+            .into_iter()
+            .chain(leaves.into_iter().map(|(entry, _)| entry))
+        {
+            self.release_block(block)?;
+        }
+
+        // Pages were rewritten in place, so anything in the cache may be stale.
+        self.node_cache
+            .lock()
+            .map_err(|_| PstError::LockError)?
+            .clear();
+        self.block_cache
+            .lock()
+            .map_err(|_| PstError::LockError)?
+            .clear();
+
+        self.finish_write()
+    }
+
+    /// Write `node` in place, see [`WriteTransaction::write_node`].
+    fn write_node(&mut self, node: &NodeContents) -> io::Result<()> {
+        self.start_write()?;
+
+        let mut allocator = FileBlockAllocator {
+            pst: self,
+            blocks: vec![],
+        };
+        let data = write_data_tree(&mut allocator, &node.data)?;
+        let sub_node = write_sub_node_tree(&mut allocator, &node.sub_nodes)?;
+        for block in allocator.blocks {
+            self.insert_block(block)?;
+        }
+
+        let entry = <<Pst as PstFile>::NodeBTreeEntry as NodeBTreeEntryReadWrite>::new(
+            node.node,
+            data,
+            sub_node,
+            node.parent,
+        );
+        let key = entry.key();
+        let search_key: u64 = key.into();
+        let root_ref = *self.header.root().node_btree();
+        let mut replaced = None;
+        let new_root_ref = self.update_btree::<<Pst as PstFile>::NodeBTree>(
+            root_ref,
+            PageType::NodeBTree,
+            key,
+            |entries| {
+                let position = entries.partition_point(|entry| entry.key().into() < search_key);
+                match entries.get_mut(position) {
+                    Some(existing) if existing.key().into() == search_key => {
+                        replaced = Some(std::mem::replace(existing, entry));
+                    }
+                    _ => entries.insert(position, entry),
+                }
+                Ok(())
+            },
+        )?;
+        self.header.root_mut().set_node_btree(new_root_ref);
+        self.clear_page_caches()?;
+
+        if let Some(replaced) = replaced {
+            self.release_node_blocks(replaced)?;
+            self.clear_page_caches()?;
+        }
+
+        self.finish_write()
+    }
+
+    /// Remove `node` from the [`NodeBTree`], see [`WriteTransaction::remove_node`].
+    fn remove_node(&mut self, node: NodeId) -> io::Result<()> {
+        self.start_write()?;
+
+        let key: <Pst as PstFile>::BTreeKey = u32::from(node).into();
+        let search_key: u64 = key.into();
+        let root_ref = *self.header.root().node_btree();
+        let mut removed = None;
+        let new_root_ref = self.update_btree::<<Pst as PstFile>::NodeBTree>(
+            root_ref,
+            PageType::NodeBTree,
+            key,
+            |entries| {
+                let position = entries
+                    .iter()
+                    .position(|entry| entry.key().into() == search_key)
+                    .ok_or(NdbError::BTreePageNotFound(search_key))?;
+                removed = Some(entries.remove(position));
+                Ok(())
+            },
+        )?;
+        self.header.root_mut().set_node_btree(new_root_ref);
+        self.clear_page_caches()?;
+
+        if let Some(removed) = removed {
+            self.release_node_blocks(removed)?;
+            self.clear_page_caches()?;
+        }
+
+        self.finish_write()
+    }
+
+    /// Raise the `rgnid` counters in the header, see [`WriteTransaction::reserve_node_ids`].
+    fn reserve_node_ids(&mut self, next_node_ids: &[u32]) -> io::Result<()> {
+        self.start_write()?;
+        for (next, reserved) in self.header.next_node_ids().iter_mut().zip(next_node_ids) {
+            *next = (*next).max(*reserved);
+        }
+        self.finish_write()
+    }
+
+    /// Release every block in the data tree and sub-node tree of the node in `entry`, which is
+    /// no longer in the [`NodeBTree`].
+    fn release_node_blocks(&mut self, entry: <Pst as PstFile>::NodeBTreeEntry) -> io::Result<()> {
+        let mut blocks = vec![];
+        let mut data_trees = vec![entry.data()];
+        let mut sub_node_trees: Vec<_> = entry.sub_node().into_iter().collect();
+        {
+            let mut reader = self.reader.lock().map_err(|_| PstError::LockError)?;
+            let reader = &mut *reader;
+            let block_btree = <<Pst as PstFile>::BlockBTree as RootBTreeReadWrite>::read(
+                reader,
+                *self.header.root().block_btree(),
+            )?;
+            let mut page_cache = PageCacheGuard::checkout(&*self.block_cache);
+            let mut visited = BTreeSet::new();
+            while let Some(block) = sub_node_trees.pop() {
+                let entry = block_btree.find_entry(reader, block.search_key(), &mut page_cache)?;
+                if !visited.insert(block.into_u64()) {
+                    return Err(NdbError::CyclicReference(block.into_u64()).into());
+                }
+                match SubNodeTree::<Pst>::read(reader, &entry)? {
+                    SubNodeTree::Intermediate(block) => {
+                        sub_node_trees.extend(block.entries().iter().map(|entry| entry.block()));
+                    }
+                    SubNodeTree::Leaf(block) => {
+                        for entry in block.entries() {
+                            data_trees.push(entry.block());
+                            sub_node_trees.extend(entry.sub_node());
+                        }
+                    }
+                }
+                blocks.push(entry);
+            }
+        }
+
+        // Nodes without any data have a BID of 0.
+        for block in data_trees {
+            if block.search_key().into() == 0 {
+                continue;
+            }
+            let (intermediate, leaves) = self.read_data_tree_blocks(block)?;
+            blocks.extend(intermediate);
+            blocks.extend(leaves.into_iter().map(|(entry, _)| entry));
+        }
+
+        for block in blocks {
+            self.release_block(block)?;
+        }
+        Ok(())
+    }
+
+    /// Pages were rewritten in place, so anything in the caches may be stale.
+    fn clear_page_caches(&self) -> io::Result<()> {
+        self.node_cache
+            .lock()
+            .map_err(|_| PstError::LockError)?
+            .clear();
+        self.block_cache
+            .lock()
+            .map_err(|_| PstError::LockError)?
+            .clear();
+        Ok(())
+    }
+}
+
+/// Allocates new blocks in the free space of an existing file for [`DataTree::build_from_blocks`],
+/// and keeps the [`BlockBTree`] entries so they can be added once the data tree is written.
+struct FileBlockAllocator<'a, Pst>
+where
+    Pst: PstFile,
+{
+    pst: &'a mut PstFileInner<Pst>,
+    blocks: Vec<<Pst as PstFile>::BlockBTreeEntry>,
+}
+
+impl<Pst> BlockAllocator<Pst> for FileBlockAllocator<'_, Pst>
+where
+    Pst: PstFile + PstFileLock<Pst>,
+    <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey>
+        + From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index>
+        + Debug,
+    <Pst as PstFile>::PageId: From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index> + Debug,
+    <Pst as PstFile>::ByteIndex: ByteIndex<Index: TryFrom<u64>> + Debug,
+    <Pst as PstFile>::BlockRef: Debug,
+    <Pst as PstFile>::PageRef: Debug,
+    <Pst as PstFile>::Root: RootReadWrite<Pst>,
+    <Pst as PstFile>::Header: HeaderReadWrite<Pst>,
+    <Pst as PstFile>::DensityListPage: DensityListPageReadWrite<Pst>,
+    <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+    <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+    <Pst as PstFile>::NodeBTreeEntry: NodeBTreeEntryReadWrite,
+    <Pst as PstFile>::NodeBTree: NodeBTreeReadWrite<Pst, <Pst as PstFile>::NodeBTreeEntry>,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::NodeBTreeEntry,
+            <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockBTree: BlockBTreeReadWrite<Pst, <Pst as PstFile>::BlockBTreeEntry>,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::BlockBTreeEntry,
+            <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::AllocationMapPage: AllocationMapPageReadWrite<Pst>,
+    <Pst as PstFile>::AllocationPageMapPage: AllocationPageMapPageReadWrite<Pst>,
+    <Pst as PstFile>::FreeMapPage: FreeMapPageReadWrite<Pst>,
+    <Pst as PstFile>::FreePageMapPage: FreePageMapPageReadWrite<Pst>,
+    <Pst as PstFile>::DensityListPage: DensityListPageReadWrite<Pst>,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry:
+        IntermediateTreeEntryReadWrite + From<<Pst as PstFile>::BlockId>,
+    <Pst as PstFile>::DataBlock: BlockReadWrite + Clone,
+    <Pst as PstFile>::SubNodeTreeBlockHeader: SubNodeTreeBlockHeaderReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::SubNodeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+{
+    fn encoding(&self) -> NdbCryptMethod {
+        self.pst.header.crypt_method()
+    }
+
+    fn allocate_block(
+        &mut self,
+        is_internal: bool,
+        size: u16,
+    ) -> io::Result<<Pst as PstFile>::BlockBTreeEntry> {
+        let block_id = self.pst.new_block_id(is_internal)?;
+        let offset = self.pst.allocate_block_space(size)?;
+        let block = <<Pst as PstFile>::BlockRef as BlockRefReadWrite>::new(
+            block_id,
+            PstFileInner::<Pst>::byte_index(offset)?,
+        );
+        let entry =
+            <<Pst as PstFile>::BlockBTreeEntry as BlockBTreeEntryReadWrite>::new(block, size);
+        self.blocks.push(entry);
+        Ok(entry)
+    }
+
+    fn write_block(
+        &mut self,
+        block: &DataTree<Pst>,
+        entry: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<()> {
+        self.pst.write_block(block, entry)
+    }
+}
+
+impl<Pst> SubNodeBlockWriter<Pst> for FileBlockAllocator<'_, Pst>
+where
+    Pst: PstFile + PstFileLock<Pst>,
+    <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey>
+        + From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index>
+        + Debug,
+    <Pst as PstFile>::PageId: From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index> + Debug,
+    <Pst as PstFile>::ByteIndex: ByteIndex<Index: TryFrom<u64>> + Debug,
+    <Pst as PstFile>::BlockRef: Debug,
+    <Pst as PstFile>::PageRef: Debug,
+    <Pst as PstFile>::Root: RootReadWrite<Pst>,
+    <Pst as PstFile>::Header: HeaderReadWrite<Pst>,
+    <Pst as PstFile>::DensityListPage: DensityListPageReadWrite<Pst>,
+    <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+    <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+    <Pst as PstFile>::NodeBTreeEntry: NodeBTreeEntryReadWrite,
+    <Pst as PstFile>::NodeBTree: NodeBTreeReadWrite<Pst, <Pst as PstFile>::NodeBTreeEntry>,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::NodeBTreeEntry,
+            <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockBTree: BlockBTreeReadWrite<Pst, <Pst as PstFile>::BlockBTreeEntry>,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::BlockBTreeEntry,
+            <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::AllocationMapPage: AllocationMapPageReadWrite<Pst>,
+    <Pst as PstFile>::AllocationPageMapPage: AllocationPageMapPageReadWrite<Pst>,
+    <Pst as PstFile>::FreeMapPage: FreeMapPageReadWrite<Pst>,
+    <Pst as PstFile>::FreePageMapPage: FreePageMapPageReadWrite<Pst>,
+    <Pst as PstFile>::DensityListPage: DensityListPageReadWrite<Pst>,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry:
+        IntermediateTreeEntryReadWrite + From<<Pst as PstFile>::BlockId>,
+    <Pst as PstFile>::DataBlock: BlockReadWrite + Clone,
+    <Pst as PstFile>::SubNodeTreeBlockHeader: SubNodeTreeBlockHeaderReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::SubNodeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+{
+    fn write_sub_node_block(
+        &mut self,
+        block: &SubNodeTree<Pst>,
+        entry: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<()> {
+        self.pst.write_sub_node_block(block, entry)
+    }
+}
+
+pub fn open_store(path: impl AsRef<Path>) -> io::Result<Arc<dyn Store>> {
+    Ok(if let Ok(pst_file) = UnicodePstFile::open(path.as_ref()) {
+        UnicodeStore::read(Arc::new(pst_file))?
+    } else if let Some(ost_file) = open_ost(path.as_ref()) {
+        UnicodeStore::read(Arc::new(ost_file))?
+    } else {
+        let pst_file = AnsiPstFile::open(path.as_ref())?;
+        AnsiStore::read(Arc::new(pst_file))?
+    })
+}
+
+#[cfg(feature = "ost")]
+fn open_ost(path: &Path) -> Option<UnicodePstFile> {
+    UnicodeOstFile::open(path)
+        .ok()
+        .map(UnicodeOstFile::into_inner)
+}
+
+#[cfg(not(feature = "ost"))]
+fn open_ost(_path: &Path) -> Option<UnicodePstFile> {
+    None
+}
+
+/// A read-only PST store of either format, chosen from the file header when it is opened. This
+/// hides the [`PstFile`] generics for callers who only need to read folders and messages; match
+/// on the variants to get back to [`UnicodeStore`] or [`AnsiStore`].
+///
+/// This is named `PstFileReader` because [`PstReader`] is already the reader trait bound used
+/// throughout the crate.
+pub enum PstFileReader {
+    Unicode(Arc<UnicodeStore>),
+    Ansi(Arc<AnsiStore>),
+}
+
+impl PstFileReader {
+    /// Open a PST file, using `wVer` in the header to pick the Unicode or ANSI format.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let version = NdbVersion::read(&mut File::open(path)?)?;
+
+        Ok(match version {
+            NdbVersion::Unicode => {
+                let pst_file = match UnicodePstFile::open(path) {
+                    Ok(pst_file) => pst_file,
+                    Err(err) => open_ost(path).ok_or(err)?,
+                };
+                Self::Unicode(UnicodeStore::read(Arc::new(pst_file))?)
+            }
+            NdbVersion::Ansi => Self::Ansi(AnsiStore::read(Arc::new(AnsiPstFile::open(path)?))?),
+        })
+    }
+
+    pub fn version(&self) -> NdbVersion {
+        match self {
+            Self::Unicode(_) => NdbVersion::Unicode,
+            Self::Ansi(_) => NdbVersion::Ansi,
+        }
+    }
+
+    pub fn store(&self) -> Arc<dyn Store> {
+        match self {
+            Self::Unicode(store) => store.clone(),
+            Self::Ansi(store) => store.clone(),
+        }
+    }
+
+    pub fn store_properties(&self) -> &StoreProperties {
+        match self {
+            Self::Unicode(store) => store.properties(),
+            Self::Ansi(store) => store.properties(),
+        }
+    }
+
+    pub fn folder(&self, node_id: NodeId) -> io::Result<Arc<dyn Folder>> {
+        let store = self.store();
+        let entry_id = store.properties().make_entry_id(node_id)?;
+        store.open_folder(&entry_id)
+    }
+
+    /// Open every folder in the store, starting with the root folder and walking each
+    /// hierarchy table depth first.
+    pub fn folders(&self) -> io::Result<Vec<Arc<dyn Folder>>> {
+        self.store().folders_dfs().collect()
+    }
+
+    pub fn message(&self, node_id: NodeId) -> io::Result<Arc<dyn Message>> {
+        let store = self.store();
+        let entry_id = store.properties().make_entry_id(node_id)?;
+        store.open_message(&entry_id, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{TempFile, EMPTY_PST};
+
+    #[test]
+    fn test_debug_clone_eq() {
+        use ltp::{heap::*, prop_context::*, table_context::*, tree::*};
+        use ndb::{block::*, block_id::*, block_ref::*, page::*, root::*};
+
+        fn assert_traits<T: Debug + Clone + PartialEq>() {}
+
+        fn format<T: Debug + Clone + PartialEq + Default>() -> String {
+            let value = T::default();
+            assert_eq!(value.clone(), value);
+            format!("{value:?}")
+        }
+
+        assert_traits::<UnicodeDataTreeBlock>();
+        assert_traits::<AnsiDataTreeBlock>();
+        assert_traits::<UnicodeIntermediateSubNodeTreeBlock>();
+        assert_traits::<AnsiLeafSubNodeTreeBlock>();
+        assert_traits::<UnicodeMapPage<{ PageType::AllocationMap as u8 }>>();
+        assert_traits::<AnsiMapPage<{ PageType::FreeMap as u8 }>>();
+        assert_traits::<UnicodeDensityListPage>();
+        assert_traits::<AnsiDensityListPage>();
+        assert_traits::<UnicodeBlockBTreePage>();
+        assert_traits::<AnsiNodeBTreePage>();
+        assert_traits::<UnicodeRoot>();
+        assert_traits::<HeapNodeHeader>();
+        assert_traits::<HeapTreeHeader>();
+        assert_traits::<UnicodeTableRowIdRecord>();
+        assert_traits::<PropertyTreeRecord>();
+        assert_traits::<TableRowData>();
+        assert_traits::<TableRowColumnValue>();
+
+        let formatted = [
+            format::<UnicodeDataBlock>(),
+            format::<AnsiDataBlock>(),
+            format::<UnicodeBlockTrailer>(),
+            format::<DataTreeBlockHeader>(),
+            format::<UnicodeDataTreeEntry>(),
+            format::<UnicodeSubNodeTreeBlockHeader>(),
+            format::<UnicodeLeafSubNodeTreeEntry>(),
+            format::<AnsiIntermediateSubNodeTreeEntry>(),
+            format::<UnicodeBlockId>(),
+            format::<AnsiBlockRef>(),
+            format::<UnicodePageTrailer>(),
+            format::<UnicodeBlockBTreeEntry>(),
+            format::<AnsiNodeBTreeEntry>(),
+            format::<HeapId>(),
+            format::<HeapNodePageAllocOffsets>(),
+            format::<HeapNodePageMap>(),
+            format::<String8Value>(),
+            format::<UnicodeValue>(),
+            format::<GuidValue>(),
+            format::<BinaryValue>(),
+            format::<ObjectValue>(),
+            format::<PropertyValue>(),
+            format::<TableContextInfo>(),
+            format::<TableColumnDescriptor>(),
+            format::<TableRowId>(),
+            format::<AnsiTableRowIndex>(),
+        ];
+        assert!(formatted.iter().all(|value| !value.is_empty()));
+
+        let block = UnicodeDataBlock::new(
+            NdbCryptMethod::None,
+            vec![0; 0x20],
+            UnicodeBlockTrailer::default(),
+        )
+        .unwrap();
+        assert!(format!("{block:?}").contains("size: 0x20"));
+    }
+
+    #[test]
+    fn test_reader_pool() {
+        let pst = UnicodePstFile::open_with_readers(EMPTY_PST, 2).unwrap();
+        assert_eq!(pst.reader().len(), 2);
+
+        // Both readers can be checked out at the same time without blocking.
+        let first = pst.reader().lock().unwrap();
+        let second = pst.reader().lock().unwrap();
+        assert!(!std::ptr::eq(&*first, &*second));
+    }
+
+    #[test]
+    fn test_with_reader() {
+        let pst = UnicodePstFile::open_with_readers(EMPTY_PST, 4).unwrap();
+        let magic = |pst: &UnicodePstFile| {
+            pst.with_reader(|reader| {
+                let mut magic = [0; 4];
+                reader.seek(SeekFrom::Start(0))?;
+                reader.read_exact(&mut magic)?;
+                Ok(magic)
+            })
+        };
+
+        // Each thread seeks its own reader, so they do not move each other's file position.
+        std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8).map(|_| scope.spawn(|| magic(&pst))).collect();
+            for thread in threads {
+                assert_eq!(&thread.join().unwrap().unwrap(), b"!BDN");
+            }
+        });
+
+        // All of the readers went back to the pool. Use try_lock, so a leaked reader fails the
+        // test instead of blocking it.
+        let readers: Vec<_> = pst
+            .reader()
+            .readers
+            .iter()
+            .filter_map(|reader| reader.try_lock().ok())
+            .collect();
+        assert_eq!(readers.len(), 4);
+    }
+
+    #[test]
+    fn test_single_reader() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        assert_eq!(pst.reader().len(), 1);
+    }
+
+    #[test]
+    fn test_read_at() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let file_size = std::fs::metadata(EMPTY_PST).unwrap().len();
+
+        // The HEADER starts with dwMagic: `!BDN`.
+        let data = pst.read_at(0, 4).unwrap();
+        assert_eq!(data.as_ref(), b"!BDN");
+        assert!(matches!(data, Cow::Owned(_)));
+
+        let err = pst.read_at(file_size - 2, 4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_read_at_mapped() {
+        let pst = UnicodePstFile::open_mapped(EMPTY_PST).unwrap();
+        let file_size = std::fs::metadata(EMPTY_PST).unwrap().len();
+
+        let data = pst.read_at(0, 4).unwrap();
+        assert_eq!(data.as_ref(), b"!BDN");
+        assert!(matches!(data, Cow::Borrowed(_)));
+
+        let err = pst.read_at(file_size - 2, 4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = pst.read_at(u64::MAX, 4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_pst_client() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        assert_eq!(pst.header().client(), NdbClient::Pst);
+    }
+
+    /// Copy `Empty.pst` with `wMagicClient` changed to `SO`, updating both of the header CRCs.
+    #[cfg(feature = "ost")]
+    fn empty_ost() -> Vec<u8> {
+        let mut data = std::fs::read(EMPTY_PST).unwrap();
+        data[8..10].copy_from_slice(&(NdbClient::Ost as u16).to_le_bytes());
+        let crc_partial = crc::compute_crc(0, &data[8..8 + 471]);
+        data[4..8].copy_from_slice(&crc_partial.to_le_bytes());
+        let crc_full = crc::compute_crc(0, &data[8..8 + 516]);
+        data[524..528].copy_from_slice(&crc_full.to_le_bytes());
+        data
+    }
+
+    #[cfg(feature = "ost")]
+    #[test]
+    fn test_read_ost() {
+        let ost = UnicodeOstFile::read_from(Box::new(io::Cursor::new(empty_ost()))).unwrap();
+        assert_eq!(ost.header().client(), NdbClient::Ost);
+
+        let store = UnicodeStore::read(Arc::new(ost.into_inner())).unwrap();
+        let hierarchy_table = store.root_hierarchy_table().unwrap();
+        assert!(hierarchy_table.rows_matrix().unwrap().next().is_some());
+    }
+
+    #[cfg(feature = "ost")]
+    #[test]
+    fn test_ost_client_mismatch() {
+        let err = UnicodePstFile::read_from(Box::new(io::Cursor::new(empty_ost())))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            NdbError::InvalidNdbHeaderMagicClientValue(NdbClient::Ost as u16).to_string()
+        );
+
+        let err = UnicodeOstFile::read_from(Box::new(File::open(EMPTY_PST).unwrap()))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            NdbError::InvalidNdbHeaderMagicClientValue(NdbClient::Pst as u16).to_string()
+        );
+    }
+
+    #[test]
+    fn test_pst_file_reader() {
+        let reader = PstFileReader::open(EMPTY_PST).unwrap();
+        assert!(matches!(reader, PstFileReader::Unicode(_)));
+        assert_eq!(reader.version(), NdbVersion::Unicode);
+        assert!(!reader.store_properties().display_name().unwrap().is_empty());
+
+        let folders = reader.folders().unwrap();
+        assert!(folders.len() > 1);
+
+        let ipm_sub_tree = reader.store_properties().ipm_sub_tree_entry_id().unwrap();
+        let ipm_sub_tree = reader.folder(NodeId::from(&ipm_sub_tree)).unwrap();
+        let ipm_sub_tree = ipm_sub_tree.properties().display_name().unwrap();
+        assert!(folders
+            .iter()
+            .any(|folder| folder.properties().display_name().ok().as_deref()
+                == Some(ipm_sub_tree.as_str())));
+    }
+
+    #[test]
+    fn test_read_ndb_version() {
+        let mut file = File::open(EMPTY_PST).unwrap();
+        assert_eq!(NdbVersion::read(&mut file).unwrap(), NdbVersion::Unicode);
+
+        let err = NdbVersion::read(&mut io::Cursor::new([0_u8; 12])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    fn read_store_block<Pst>(pst: &Pst) -> (DataTree<Pst>, DataTree<Pst>)
+    where
+        Pst: PstFile,
+        <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+        <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+        <<Pst as PstFile>::DataTreeBlock as IntermediateTreeBlock>::Entry:
+            IntermediateTreeEntryReadWrite,
+        <Pst as PstFile>::DataBlock: BlockReadWrite,
+        <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey> + BlockIdReadWrite,
+        <Pst as PstFile>::ByteIndex: ByteIndexReadWrite,
+        <Pst as PstFile>::BlockRef: BlockRefReadWrite,
+        <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+        <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+        <Pst as PstFile>::BlockBTree: RootBTreeReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::Entry: BTreeEntryReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+            RootBTreeIntermediatePageReadWrite<
+                Pst,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::Entry,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+            >,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage:
+            RootBTreeLeafPageReadWrite<Pst> + BTreePageReadWrite,
+    {
+        let header = pst.header();
+        let encoding = header.crypt_method();
+        let node = pst.read_node(NID_MESSAGE_STORE).unwrap();
+        let mut guard = pst.reader().lock().unwrap();
+        let reader = &mut *guard;
+        let block_btree = <<Pst as PstFile>::BlockBTree as RootBTreeReadWrite>::read(
+            reader,
+            *header.root().block_btree(),
+        )
+        .unwrap();
+        let block = block_btree
+            .find_entry(reader, node.data().search_key(), &mut Default::default())
+            .unwrap();
+
+        let read = DataTree::<Pst>::read(reader, encoding, &block).unwrap();
+
+        // Without a mapping, read_at needs to check out a reader from the pool too.
+        drop(guard);
+        let read_at = DataTree::<Pst>::read_at(pst, encoding, &block).unwrap();
+        (read, read_at)
+    }
+
+    #[test]
+    fn test_data_tree_read_at() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let (DataTree::Leaf(read), DataTree::Leaf(read_at)) = read_store_block(&pst) else {
+            panic!("Expected a single data block");
+        };
+        assert!(!read.data().is_empty());
+        assert_eq!(read.data(), read_at.data());
+        assert_eq!(read.trailer().crc(), read_at.trailer().crc());
+    }
+
+    #[test]
+    fn test_read_block_raw() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let block_id = pst.read_node(NID_MESSAGE_STORE).unwrap().data();
+        let DataTree::Leaf(block) = pst.read_block_raw(block_id).unwrap() else {
+            panic!("Expected a single data block");
+        };
+        assert_eq!(block.trailer().block_id(), block_id);
+        assert_eq!(block.data(), pst.read_block(block_id).unwrap());
+
+        // A BBT entry which points at the right offset with the wrong BID is rejected.
+        let header = pst.header();
+        let mut reader = pst.reader().lock().unwrap();
+        let block_btree =
+            UnicodeBlockBTree::read(&mut *reader, *header.root().block_btree()).unwrap();
+        let entry = block_btree
+            .find_entry(&mut *reader, block_id.search_key(), &mut Default::default())
+            .unwrap();
+        let wrong_id = UnicodeBlockId::new(false, block_id.index() + 1).unwrap();
+        let wrong_entry = UnicodeBlockBTreeEntry::new(
+            UnicodeBlockRef::new(wrong_id, entry.block().index()),
+            entry.size(),
+        );
+        let err = UnicodeDataTree::read(&mut *reader, header.crypt_method(), &wrong_entry)
+            .err()
+            .unwrap();
+        let err = err.into_inner().unwrap().downcast::<NdbError>().unwrap();
+        assert!(matches!(
+            err.without_offset(),
+            NdbError::MismatchBlockTrailerId(_)
+        ));
+        assert_eq!(err.offset(), Some(entry.block().index().index()));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_data_tree_read_at_mapped() {
+        let pst = UnicodePstFile::open_mapped(EMPTY_PST).unwrap();
+        let (DataTree::Leaf(read), DataTree::Leaf(read_at)) = read_store_block(&pst) else {
+            panic!("Expected a single data block");
+        };
+        assert_eq!(read.data(), read_at.data());
+    }
+
+    #[test]
+    fn test_mark_allocation_out_of_range() {
+        type Inner = PstFileInner<UnicodePstFile>;
+
+        fn pst_error(err: io::Error) -> PstError {
+            *err.into_inner().unwrap().downcast::<PstError>().unwrap()
+        }
+
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let mut amap_pages = pst.inner.live_allocation_map().unwrap();
+
+        let err = Inner::mark_block_allocation(0x1000, 64, &mut amap_pages).unwrap_err();
+        assert!(matches!(
+            pst_error(err),
+            PstError::BlockBeforeFirstAmap(0x1000)
+        ));
+        let err = Inner::mark_page_allocation(0, &mut amap_pages).unwrap_err();
+        assert!(matches!(pst_error(err), PstError::BlockBeforeFirstAmap(0)));
+
+        let offset = AMAP_FIRST_OFFSET + AMAP_DATA_SIZE - 64;
+        let err = Inner::mark_block_allocation(offset, 1000, &mut amap_pages).unwrap_err();
+        assert!(
+            matches!(pst_error(err), PstError::AllocationPastAmapPage(value) if value == offset)
+        );
+
+        let count = amap_pages.len();
+        let offset = AMAP_FIRST_OFFSET + count as u64 * AMAP_DATA_SIZE;
+        let err = Inner::mark_page_allocation(offset, &mut amap_pages).unwrap_err();
+        assert!(
+            matches!(pst_error(err), PstError::AllocationMapPageNotFound(value) if value == count)
+        );
+
+        // The allocator reads the last AMap page and the block offset from the header and BBT.
+        let path = TempFile::copy_of(EMPTY_PST, "amap_region").unwrap();
+        {
+            let mut pst = UnicodePstFile::open(&path).unwrap();
+            let err = pst.inner.free_block_space(0x1000, 64).unwrap_err();
+            assert!(matches!(
+                pst_error(err),
+                PstError::BlockBeforeFirstAmap(0x1000)
+            ));
+
+            pst.inner
+                .header
+                .root_mut()
+                .set_amap_last_index(UnicodeByteIndex::new(0x1000));
+            let err = pst.inner.allocate_block_space(64).unwrap_err();
+            assert!(matches!(
+                pst_error(err),
+                PstError::BlockBeforeFirstAmap(0x1000)
+            ));
+            let err = pst.inner.allocate_page(PageType::NodeBTree).unwrap_err();
+            assert!(matches!(
+                pst_error(err),
+                PstError::BlockBeforeFirstAmap(0x1000)
+            ));
+        }
+
+        let err =
+            Inner::mark_block_allocation(AMAP_FIRST_OFFSET, u16::MAX, &mut amap_pages).unwrap_err();
+        let err = *err.into_inner().unwrap().downcast::<NdbError>().unwrap();
+        assert!(matches!(err, NdbError::InvalidBlockSize(u16::MAX)));
+
+        // Marking the same block over and over eventually uses up all of the free space.
+        let offset = AMAP_FIRST_OFFSET + AMAP_DATA_SIZE / 2;
+        let err = (0..AMAP_DATA_SIZE / 8192 + 1)
+            .find_map(|_| Inner::mark_block_allocation(offset, 8000, &mut amap_pages).err())
+            .unwrap();
+        assert!(matches!(
+            pst_error(err),
+            PstError::AllocationMapPageOverflow(0)
+        ));
+    }
+
+    /// Count the regions with each kind of map page by walking every AMap region, the same way
+    /// [`amap_reserved_pages`] decides which pages are reserved at the start of a region.
+    fn count_map_pages(num_amap_pages: u64) -> (u64, u64, u64) {
+        let mut counts = (0, 0, 0);
+        for index in 0..num_amap_pages {
+            match amap_reserved_pages(index) {
+                1 => {}
+                2 => counts.0 += 1,
+                3 if index >= FPMAP_FIRST_SIZE
+                    && (index - FPMAP_FIRST_SIZE) % FPMAP_PAGE_COUNT == 0 =>
+                {
+                    counts.0 += 1;
+                    counts.2 += 1;
+                }
+                3 => {
+                    counts.0 += 1;
+                    counts.1 += 1;
+                }
+                _ => {
+                    counts.0 += 1;
+                    counts.1 += 1;
+                    counts.2 += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn test_allocation_map_page_counts() {
+        let check = |file_eof: u64, expected: (u64, u64, u64, u64)| {
+            let num_amap_pages = amap_page_count(file_eof);
+            let counts = (
+                num_amap_pages,
+                pmap_page_count(num_amap_pages),
+                fmap_page_count(num_amap_pages),
+                fpmap_page_count(num_amap_pages),
+            );
+            assert_eq!(counts, expected, "file_eof: 0x{file_eof:X}");
+
+            let (pmap, fmap, fpmap) = count_map_pages(num_amap_pages);
+            assert_eq!((num_amap_pages, pmap, fmap, fpmap), expected);
+        };
+
+        check(AMAP_FIRST_OFFSET + PAGE_SIZE as u64, (1, 1, 0, 0));
+        check(AMAP_FIRST_OFFSET + 8 * AMAP_DATA_SIZE, (8, 1, 0, 0));
+        check(AMAP_FIRST_OFFSET + 8 * AMAP_DATA_SIZE + 1, (9, 2, 0, 0));
+
+        // Exactly at the end of the regions covered by the FMap in the header, and then one
+        // AMap region beyond that, which holds the first FMap page.
+        check(AMAP_FIRST_OFFSET + FMAP_FIRST_DATA_SIZE, (128, 16, 0, 0));
+        check(
+            AMAP_FIRST_OFFSET + FMAP_FIRST_DATA_SIZE + 1,
+            (129, 17, 1, 0),
+        );
+        check(
+            AMAP_FIRST_OFFSET + FMAP_FIRST_DATA_SIZE + AMAP_DATA_SIZE,
+            (129, 17, 1, 0),
+        );
+        check(
+            AMAP_FIRST_OFFSET + FMAP_FIRST_DATA_SIZE + FMAP_DATA_SIZE,
+            (624, 78, 1, 0),
+        );
+        check(
+            AMAP_FIRST_OFFSET + FMAP_FIRST_DATA_SIZE + FMAP_DATA_SIZE + 1,
+            (625, 79, 2, 0),
+        );
+
+        // The same boundaries for the FPMap.
+        check(
+            AMAP_FIRST_OFFSET + FPMAP_FIRST_DATA_SIZE,
+            (8192, 1024, 17, 0),
+        );
+        check(
+            AMAP_FIRST_OFFSET + FPMAP_FIRST_DATA_SIZE + AMAP_DATA_SIZE,
+            (8193, 1025, 17, 1),
+        );
+    }
+
+    #[test]
+    fn test_recompute_free_sizes() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let (amap_free_size, pmap_free_size) = pst.recompute_free_sizes().unwrap();
+        let root = pst.header().root();
+        assert_eq!(amap_free_size, root.amap_free_size().index());
+        assert_eq!(pmap_free_size, root.pmap_free_size().index());
+    }
+
+    #[test]
+    fn test_fix_free_sizes() {
+        let path = TempFile::copy_of(EMPTY_PST, "fix_free_sizes").unwrap();
+
+        let expected = {
+            let mut pst = UnicodePstFile::open(&path).unwrap();
+            pst.fix_free_sizes().unwrap()
+        };
+
+        let pst = UnicodePstFile::open(&path).unwrap();
+        let root = pst.header().root();
+        let actual = (root.amap_free_size().index(), root.pmap_free_size().index());
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_amap_page_stats() {
+        let mut map_bits = [0xFF; size_of::<MapBits>()];
+        map_bits[1] = 0b1100_0011;
+        map_bits[2] = 0b0000_0000;
+        map_bits[3] = 0b0011_1111;
+        map_bits[10] = 0b1110_1111;
+        let stats = AmapPageStats::new(AMAP_FIRST_OFFSET, &map_bits);
+        assert_eq!(
+            stats,
+            AmapPageStats {
+                offset: AMAP_FIRST_OFFSET,
+                free_bytes: 15 * 64,
+                largest_free_run: 10 * 64,
+                free_runs: 3,
+            }
+        );
+
+        let stats = AmapPageStats::new(AMAP_FIRST_OFFSET, &[0; size_of::<MapBits>()]);
+        assert_eq!(stats.free_bytes, AMAP_DATA_SIZE);
+        assert_eq!(stats.largest_free_run, AMAP_DATA_SIZE);
+        assert_eq!(stats.free_runs, 1);
+    }
+
+    #[test]
+    fn test_allocation_report() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let report = pst.allocation_report().unwrap();
+        let file_eof = pst.header().root().file_eof_index().index();
+        assert_eq!(report.len() as u64, amap_page_count(file_eof));
+        assert_eq!(report[0].offset, AMAP_FIRST_OFFSET);
+        assert_eq!(
+            report.iter().map(|page| page.free_bytes).sum::<u64>(),
+            pst.recompute_free_sizes().unwrap().0
+        );
+        for page in report {
+            assert!(page.largest_free_run <= page.free_bytes);
+            assert_eq!(page.free_runs == 0, page.free_bytes == 0);
+        }
+    }
+
+    #[test]
+    fn test_btree_counts() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        assert_eq!(pst.node_count().unwrap(), 47);
+        assert_eq!(pst.block_count().unwrap(), 31);
+        assert_eq!(
+            pst.node_count().unwrap(),
+            pst.validate().unwrap().nodes_checked as u64
+        );
+
+        // Empty.pst is a single AMap region.
+        assert_eq!(pst.file_size(), AMAP_FIRST_OFFSET + AMAP_DATA_SIZE);
+        assert_eq!(
+            pst.allocation_stats().unwrap().total_bytes,
+            pst.file_size() - AMAP_FIRST_OFFSET
+        );
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let path = TempFile::new("parse_options");
+        let mut data = std::fs::read(EMPTY_PST).unwrap();
+        let page = {
+            let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+            pst.header().root().node_btree().index().index() as usize
+        };
+
+        // Set dwPadding in the NBT root page, and update dwCRC to match.
+        data[page + 492] = 1;
+        let crc = crate::crc::compute_crc(0, &data[page..page + 496]);
+        data[page + 500..page + 504].copy_from_slice(&crc.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+
+        let pst = UnicodePstFile::open(&path).unwrap();
+        let err = pst.read_node(NID_MESSAGE_STORE).unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<NdbError>().unwrap();
+        assert!(matches!(
+            err.without_offset(),
+            NdbError::InvalidBTreePagePadding(1)
+        ));
+        assert_eq!(err.offset(), Some(page as u64));
+        assert!(pst.take_parse_warnings().is_empty());
+        drop(pst);
+
+        let pst = UnicodePstFile::open_with_options(&path, ParseOptions::lenient()).unwrap();
+        assert_eq!(pst.reader().parse_options(), ParseOptions::lenient());
+        pst.read_node(NID_MESSAGE_STORE).unwrap();
+        let warnings = pst.take_parse_warnings();
+        assert!(!warnings.is_empty());
+        assert!(warnings
+            .iter()
+            .all(|warning| matches!(warning, NdbError::InvalidBTreePagePadding(1))));
+        assert!(pst.take_parse_warnings().is_empty());
+        drop(pst);
+
+        // Without the matching dwCRC, lenient mode still fails unless CRCs are not verified.
+        data[page + 500] ^= 0xFF;
+        std::fs::write(&path, &data).unwrap();
+        let pst = UnicodePstFile::open_with_options(&path, ParseOptions::lenient()).unwrap();
+        let err = pst.read_node(NID_MESSAGE_STORE).unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<NdbError>().unwrap();
+        assert!(matches!(err.without_offset(), NdbError::InvalidPageCrc(_)));
+        assert_eq!(err.offset(), Some(page as u64));
+        drop(pst);
+
+        let options = ParseOptions {
+            verify_crc: false,
+            ..ParseOptions::lenient()
+        };
+        let pst = UnicodePstFile::open_with_options(&path, options).unwrap();
+        pst.read_node(NID_MESSAGE_STORE).unwrap();
+        assert!(pst
+            .take_parse_warnings()
+            .iter()
+            .any(|warning| matches!(warning, NdbError::InvalidPageCrc(_))));
+        drop(pst);
+    }
+
+    #[test]
+    fn test_orphaned_blocks() {
+        let path = TempFile::copy_of(EMPTY_PST, "orphaned").unwrap();
+
+        let mut pst = UnicodePstFile::open(&path).unwrap();
+        pst.lock().unwrap().flush().unwrap();
+        assert_eq!(pst.find_orphaned_blocks().unwrap(), Vec::<u64>::new());
+        assert_eq!(pst.reclaim_orphaned_blocks().unwrap(), 0);
+
+        // Allocate space for a block without ever adding it to the BBT.
+        pst.inner.start_write().unwrap();
+        let offset = pst.inner.allocate_block_space(200).unwrap();
+        pst.inner.finish_write().unwrap();
+        let free_size = pst.header().root().amap_free_size().index();
+        drop(pst);
+
+        let mut pst = UnicodePstFile::open(&path).unwrap();
+        assert_eq!(pst.find_orphaned_blocks().unwrap(), vec![offset]);
+        assert_eq!(pst.reclaim_orphaned_blocks().unwrap(), 256);
+        assert!(pst.find_orphaned_blocks().unwrap().is_empty());
+        assert_eq!(
+            pst.header().root().amap_free_size().index(),
+            free_size + 256
+        );
+        assert_eq!(
+            pst.recompute_free_sizes().unwrap().0,
+            pst.header().root().amap_free_size().index()
+        );
+        drop(pst);
+    }
+
+    #[test]
+    fn test_density_list_allocation() {
+        let path = TempFile::copy_of(EMPTY_PST, "density").unwrap();
+
+        let mut pst = UnicodePstFile::open(&path).unwrap();
+        pst.lock().unwrap().flush().unwrap();
+        let initial_free = pst.allocation_report().unwrap()[0].free_bytes;
+
+        // Fill half of the first AMap page with blocks of about 8KB each.
+        pst.inner.start_write().unwrap();
+        let mut allocated = 0;
+        while allocated < AMAP_DATA_SIZE / 2 {
+            let offset = pst.inner.allocate_block_space(8000).unwrap();
+            assert!(offset < AMAP_FIRST_OFFSET + AMAP_DATA_SIZE);
+            allocated += u64::from(block_size(8000 + UnicodeBlockTrailer::SIZE));
+        }
+        pst.inner.finish_write().unwrap();
+        drop(pst);
+
+        let pst = UnicodePstFile::open(&path).unwrap();
+        let free_bytes = pst.allocation_report().unwrap()[0].free_bytes;
+        assert_eq!(free_bytes, initial_free - allocated);
+        let entry = *pst
+            .density_list()
+            .unwrap()
+            .entries()
+            .iter()
+            .find(|entry| entry.page() == 0)
+            .unwrap();
+        assert_eq!(u64::from(entry.free_slots()) * 64, free_bytes);
+        assert!(u64::from(entry.free_slots()) * 64 < initial_free);
+        drop(pst);
+    }
+
+    #[test]
+    fn test_density_list_rollback() {
+        let path = TempFile::copy_of(EMPTY_PST, "density_rollback").unwrap();
+
+        // Commit an empty transaction first, so the file has a density list to compare with.
+        let mut pst = UnicodePstFile::open(&path).unwrap();
+        pst.begin_transaction().unwrap().commit().unwrap();
+        let entries = pst.density_list().unwrap().entries().to_vec();
+        let file_eof: u64 = pst.header().root().file_eof_index().index();
+        let last_region = amap_region(file_eof - 1).unwrap();
+
+        // Allocate blocks till they spill over into a new AMap region, and then roll it back.
+        pst.inner.start_transaction().unwrap();
+        loop {
+            let offset = pst.inner.allocate_block_space(8000).unwrap();
+            if amap_region(offset).unwrap() > last_region {
+                break;
+            }
+        }
+        assert_ne!(pst.density_list().unwrap().entries(), entries.as_slice());
+        pst.inner.rollback_transaction().unwrap();
+        assert_eq!(pst.density_list().unwrap().entries(), entries.as_slice());
+
+        // An empty transaction should write the same density list back to the file.
+        pst.begin_transaction().unwrap().commit().unwrap();
+        drop(pst);
+
+        let pst = UnicodePstFile::open(&path).unwrap();
+        assert_eq!(pst.header().root().file_eof_index().index(), file_eof);
+        let density_list = pst.density_list().unwrap();
+        assert_eq!(density_list.entries(), entries.as_slice());
+        assert!(density_list
+            .entries()
+            .iter()
+            .all(|entry| u64::from(entry.page()) <= last_region));
+        drop(pst);
+    }
+
+    #[test]
+    fn test_create() {
+        let path = TempFile::new("create");
+        let pst = UnicodePstFile::create(&path, NdbCryptMethod::Permute).unwrap();
+        assert_eq!(pst.header().crypt_method(), NdbCryptMethod::Permute);
+        assert_eq!(pst.header().root().amap_is_valid(), AmapStatus::Valid2);
+        let (amap_free_size, pmap_free_size) = pst.recompute_free_sizes().unwrap();
+        let root = pst.header().root();
+        assert_eq!(amap_free_size, root.amap_free_size().index());
+        assert_eq!(pmap_free_size, root.pmap_free_size().index());
+        drop(pst);
+
+        let reader = PstFileReader::open(&path).unwrap();
+        let properties = reader.store_properties();
+        assert_eq!(
+            properties.display_name().unwrap(),
+            path.file_stem().unwrap().to_str().unwrap()
+        );
+        let record_key = properties.record_key().unwrap();
+        let template = PstFileReader::open(EMPTY_PST).unwrap();
+        assert_ne!(
+            record_key.record_key(),
+            template
+                .store_properties()
+                .record_key()
+                .unwrap()
+                .record_key()
+        );
+        let ipm_sub_tree = properties.ipm_sub_tree_entry_id().unwrap();
+        assert_eq!(ipm_sub_tree.record_key(), record_key.record_key());
+        let folders = reader.folders().unwrap();
+        assert_eq!(
+            folders
+                .iter()
+                .map(|folder| folder.properties().display_name().unwrap_or_default())
+                .collect::<Vec<_>>(),
+            template
+                .folders()
+                .unwrap()
+                .iter()
+                .map(|folder| folder.properties().display_name().unwrap_or_default())
+                .collect::<Vec<_>>()
+        );
+        assert!(reader.folder(ipm_sub_tree.node_id()).is_ok());
+        drop(reader);
+
+        // Rebuilding the allocation map from the BTrees gives the same AMap and PMap pages.
+        let before = std::fs::read(&path).unwrap();
+        {
+            let mut pst = UnicodePstFile::open(&path).unwrap();
+            pst.inner
+                .header
+                .root_mut()
+                .set_amap_status(AmapStatus::Invalid);
+            pst.lock().unwrap().flush().unwrap();
+        }
+        let after = std::fs::read(&path).unwrap();
+        assert_eq!(after.len(), before.len());
+        let header_size = AMAP_FIRST_OFFSET as usize;
+        assert!(after[header_size..] == before[header_size..]);
+    }
+
+    #[test]
+    fn test_rebuild_partial_amap_region() {
+        let path = TempFile::new("partial_amap");
+        let region_end = AMAP_FIRST_OFFSET + AMAP_DATA_SIZE;
+        let first_free = {
+            let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+            assert_eq!(pst.file_size(), region_end);
+            pst.recompute_free_sizes().unwrap().0
+        };
+
+        // Pretend the file ends exactly on, 1 byte past, and half way through the second region,
+        // then rebuild the AMap. The reserved AMap page in the second region is 512 bytes, so
+        // there is no free space in it till the file covers more than that.
+        for (file_eof, second_free) in [
+            (region_end, None),
+            (region_end + 1, Some(0)),
+            (
+                region_end + AMAP_DATA_SIZE / 2,
+                Some(AMAP_DATA_SIZE / 2 - PAGE_SIZE as u64),
+            ),
+        ] {
+            std::fs::copy(EMPTY_PST, &path).unwrap();
+            {
+                let mut pst = UnicodePstFile::open(&path).unwrap();
+                let root = pst.inner.header.root_mut();
+                root.set_file_eof_index(UnicodeByteIndex::new(file_eof));
+                root.set_amap_status(AmapStatus::Invalid);
+                pst.lock().unwrap().flush().unwrap();
+            }
+
+            let pst = UnicodePstFile::open(&path).unwrap();
+            let report = pst.allocation_report().unwrap();
+            assert_eq!(report.len(), 1 + usize::from(second_free.is_some()));
+            assert_eq!(report[0].free_bytes, first_free);
+            assert_eq!(
+                report.get(1).map(|stats| stats.free_bytes),
+                second_free,
+                "file_eof: {file_eof}"
+            );
+            let root = pst.header().root();
+            assert_eq!(
+                root.amap_free_size().index(),
+                first_free + second_free.unwrap_or_default()
+            );
+            assert_eq!(
+                pst.recompute_free_sizes().unwrap().0,
+                root.amap_free_size().index()
+            );
+            let check = pst.check_allocation_map().unwrap();
+            assert!(check.is_consistent(), "{check:?}");
+        }
+
+        // Running out of space in the first region adds a second one, and the file grows to
+        // the end of it.
+        std::fs::copy(EMPTY_PST, &path).unwrap();
+        let mut pst = UnicodePstFile::open(&path).unwrap();
+        pst.inner.start_write().unwrap();
+        while pst.inner.allocate_block_space(8000).unwrap() < region_end {}
+        pst.inner.finish_write().unwrap();
+        drop(pst);
+
+        let pst = UnicodePstFile::open(&path).unwrap();
+        assert_eq!(pst.file_size(), region_end + AMAP_DATA_SIZE);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            region_end + AMAP_DATA_SIZE
+        );
+        assert_eq!(
+            pst.recompute_free_sizes().unwrap().0,
+            pst.header().root().amap_free_size().index()
+        );
+        drop(pst);
+    }
+
+    #[test]
+    fn test_create_in_memory() {
+        let pst = UnicodePstFile::create_in_memory(NdbCryptMethod::None).unwrap();
+        assert_eq!(pst.header().crypt_method(), NdbCryptMethod::None);
+        assert_eq!(pst.header().root().amap_is_valid(), AmapStatus::Valid2);
+        let (amap_free_size, pmap_free_size) = pst.recompute_free_sizes().unwrap();
+        let root = pst.header().root();
+        assert_eq!(amap_free_size, root.amap_free_size().index());
+        assert_eq!(pmap_free_size, root.pmap_free_size().index());
+
+        let data = pst.to_vec().unwrap();
+        let file_eof: u64 = root.file_eof_index().index();
+        assert_eq!(data.len() as u64, file_eof);
+
+        let store = UnicodeStore::read(Arc::new(pst)).unwrap();
+        let template = PstFileReader::open(EMPTY_PST).unwrap();
+        assert_eq!(
+            store.properties().display_name().unwrap(),
+            template.store_properties().display_name().unwrap()
+        );
+        assert_ne!(
+            store.properties().record_key().unwrap().record_key(),
+            template
+                .store_properties()
+                .record_key()
+                .unwrap()
+                .record_key()
+        );
+        assert_eq!(
+            store.folders_dfs().count(),
+            template.folders().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_open_in_memory() {
+        let message_id =
+            |index: u32| NodeId::new(NodeIdType::NormalMessage, 0x10000 + index).unwrap();
+        let mut pst = UnicodePstFile::create_in_memory(NdbCryptMethod::Permute).unwrap();
+        let data = pst.read_node(NID_MESSAGE_STORE).unwrap().data();
+        let before = pst.to_vec().unwrap();
+
+        // Rolling back truncates the buffer to where it was.
+        let mut transaction = pst.begin_transaction().unwrap();
+        for index in 0..100 {
+            transaction
+                .insert_node(message_id(index), data, None)
+                .unwrap();
+        }
+        transaction.rollback().unwrap();
+        let after = pst.to_vec().unwrap();
+        assert_eq!(after.len(), before.len());
+        let header_size = AMAP_FIRST_OFFSET as usize;
+        assert!(after[header_size..] == before[header_size..]);
+
+        for index in 0..100 {
+            pst.insert_node(message_id(index), data, None).unwrap();
+        }
+        let written = pst.to_vec().unwrap();
+        drop(pst);
+
+        // The copy is independent of the buffer it was opened from.
+        let mut pst = UnicodePstFile::open_in_memory(written.clone()).unwrap();
+        assert_eq!(pst.header().root().amap_is_valid(), AmapStatus::Valid2);
+        for index in 0..100 {
+            assert_eq!(pst.read_node(message_id(index)).unwrap().data(), data);
+        }
+        pst.insert_node(message_id(100), data, None).unwrap();
+        assert_ne!(pst.to_vec().unwrap(), written);
+
+        let pst = UnicodePstFile::read_from(Box::new(io::Cursor::new(written))).unwrap();
+        assert!(pst.read_node(message_id(99)).is_ok());
+        assert!(pst.read_node(message_id(100)).is_err());
+        let (amap_free_size, pmap_free_size) = pst.recompute_free_sizes().unwrap();
+        let root = pst.header().root();
+        assert_eq!(amap_free_size, root.amap_free_size().index());
+        assert_eq!(pmap_free_size, root.pmap_free_size().index());
+    }
+
+    fn count_node_btree_leaves(pst: &UnicodePstFile) -> usize {
+        let mut reader = pst.reader().lock().unwrap();
+        let reader = &mut *reader;
+        let mut pages = vec![*pst.header().root().node_btree()];
+        let mut leaves = 0;
+        while let Some(page_ref) = pages.pop() {
+            match UnicodeNodeBTree::read(reader, page_ref).unwrap() {
+                RootBTreePage::Intermediate(page, ..) => {
+                    pages.extend(page.entries().iter().map(|entry| entry.block()))
+                }
+                RootBTreePage::Leaf(_) => leaves += 1,
+            }
+        }
+        leaves
+    }
+
+    #[test]
+    fn test_insert_node() {
+        let path = TempFile::copy_of(EMPTY_PST, "insert_node").unwrap();
+
+        let message_id =
+            |index: u32| NodeId::new(NodeIdType::NormalMessage, 0x10000 + index).unwrap();
+        let first_node = NodeId::new(NodeIdType::HeapNode, 1).unwrap();
+        let (data, leaves) = {
+            let mut pst = UnicodePstFile::open(&path).unwrap();
+            pst.fix_free_sizes().unwrap();
+            let data = pst.read_node(NID_MESSAGE_STORE).unwrap().data();
+            let leaves = count_node_btree_leaves(&pst);
+
+            // Each leaf page holds 20 entries, so this needs to split pages more than once.
+            for index in 0..100 {
+                pst.insert_node(message_id(index), data, None).unwrap();
+            }
+            pst.insert_node(first_node, data, Some(data)).unwrap();
+
+            let err = pst.insert_node(message_id(0), data, None).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                PstError::NodeAlreadyExists(message_id(0)).to_string()
+            );
+            (data, leaves)
+        };
+
+        let pst = UnicodePstFile::open(&path).unwrap();
+        assert_eq!(pst.header().root().amap_is_valid(), AmapStatus::Valid2);
+        assert!(count_node_btree_leaves(&pst) > leaves);
+
+        for index in 0..100 {
+            let node = pst.read_node(message_id(index)).unwrap();
+            assert_eq!(u32::from(node.node()), u32::from(message_id(index)));
+            assert_eq!(node.data(), data);
+            assert_eq!(node.sub_node(), None);
+        }
+        let node = pst.read_node(first_node).unwrap();
+        assert_eq!(node.sub_node(), Some(data));
+        assert_eq!(pst.read_node(NID_MESSAGE_STORE).unwrap().data(), data);
+
+        let (amap_free_size, pmap_free_size) = pst.recompute_free_sizes().unwrap();
+        let root = pst.header().root();
+        assert_eq!(amap_free_size, root.amap_free_size().index());
+        assert_eq!(pmap_free_size, root.pmap_free_size().index());
+
+        let store = UnicodeStore::read(Arc::new(pst)).unwrap();
+        assert!(!store.properties().display_name().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_transaction() {
+        let path = TempFile::copy_of(EMPTY_PST, "transaction").unwrap();
+
+        let message_id =
+            |index: u32| NodeId::new(NodeIdType::NormalMessage, 0x10000 + index).unwrap();
+        let mut pst = UnicodePstFile::open(&path).unwrap();
+        pst.fix_free_sizes().unwrap();
+        let data = pst.read_node(NID_MESSAGE_STORE).unwrap().data();
+        let leaves = count_node_btree_leaves(&pst);
+        let file_eof: u64 = pst.header().root().file_eof_index().index();
+        let before = std::fs::read(&path).unwrap();
+        let amap_first_offset = AMAP_FIRST_OFFSET as usize;
+
+        // Enough nodes to split pages and allocate new ones, both explicitly rolled back and
+        // dropped without committing.
+        for explicit in [true, false] {
+            let mut transaction = pst.begin_transaction().unwrap();
+            for index in 0..100 {
+                transaction
+                    .insert_node(message_id(index), data, None)
+                    .unwrap();
+            }
+            assert!(transaction.read_node(message_id(99)).is_ok());
+
+            // The nested writes should not have finished the transaction.
+            let other = UnicodePstFile::open(&path).unwrap();
+            assert_eq!(other.header().root().amap_is_valid(), AmapStatus::Invalid);
+            drop(other);
+
+            let Err(err) = transaction.begin_transaction() else {
+                panic!("transactions should not nest");
+            };
+            assert_eq!(err.to_string(), PstError::TransactionInProgress.to_string());
+
+            if explicit {
+                transaction.rollback().unwrap();
+            } else {
+                drop(transaction);
+            }
+
+            // Only the header and density list are allowed to change.
+            let after = std::fs::read(&path).unwrap();
+            assert_eq!(after.len() as u64, file_eof);
+            assert!(after[amap_first_offset..] == before[amap_first_offset..]);
+            assert!(pst.read_node(message_id(0)).is_err());
+
+            let reopened = UnicodePstFile::open(&path).unwrap();
+            assert_eq!(reopened.header().root().amap_is_valid(), AmapStatus::Valid2);
+            assert_eq!(count_node_btree_leaves(&reopened), leaves);
+            assert!(reopened.read_node(message_id(0)).is_err());
+            let (amap_free_size, pmap_free_size) = reopened.recompute_free_sizes().unwrap();
+            let root = reopened.header().root();
+            assert_eq!(amap_free_size, root.amap_free_size().index());
+            assert_eq!(pmap_free_size, root.pmap_free_size().index());
+        }
+
+        let mut transaction = pst.begin_transaction().unwrap();
+        for index in 0..100 {
+            transaction
+                .insert_node(message_id(index), data, None)
+                .unwrap();
+        }
+        transaction.commit().unwrap();
+        drop(pst);
+
+        let pst = UnicodePstFile::open(&path).unwrap();
+        assert_eq!(pst.header().root().amap_is_valid(), AmapStatus::Valid2);
+        assert!(count_node_btree_leaves(&pst) > leaves);
+        for index in 0..100 {
+            assert_eq!(pst.read_node(message_id(index)).unwrap().data(), data);
+        }
+        let (amap_free_size, pmap_free_size) = pst.recompute_free_sizes().unwrap();
+        let root = pst.header().root();
+        assert_eq!(amap_free_size, root.amap_free_size().index());
+        assert_eq!(pmap_free_size, root.pmap_free_size().index());
+
+        drop(pst);
+    }
 }
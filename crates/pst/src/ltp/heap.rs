@@ -1,7 +1,10 @@
 //! ## [HN (Heap-on-Node)](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/77ce49a3-3772-4d8d-bb2c-2f7520a238a6)
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::{
+    io::{self, Cursor, Read, Write},
+    ops::Range,
+};
 
 use super::{read_write::*, *};
 use crate::{
@@ -19,7 +22,7 @@ use crate::{
 pub const HEAP_INDEX_MASK: u32 = (1_u16.rotate_right(5) - 1) as u32;
 
 /// [HID](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/85b9e985-ea53-447f-b70c-eb82bfbdcbc9)
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
 pub struct HeapId(NodeId);
 
 impl HeapId {
@@ -210,15 +213,37 @@ impl HeapFillLevel {
         ]
     }
 
+    fn from_free_space(free: usize) -> Self {
+        match free {
+            3584.. => Self::Empty,
+            2560.. => Self::Level1,
+            2048.. => Self::Level2,
+            1792.. => Self::Level3,
+            1536.. => Self::Level4,
+            1280.. => Self::Level5,
+            1024.. => Self::Level6,
+            768.. => Self::Level7,
+            512.. => Self::Level8,
+            256.. => Self::Level9,
+            128.. => Self::Level10,
+            64.. => Self::Level11,
+            32.. => Self::Level12,
+            16.. => Self::Level13,
+            8.. => Self::Level14,
+            _ => Self::Level15,
+        }
+    }
+
     fn pack_fill_levels(fill_levels: &[HeapFillLevel; 8]) -> u32 {
         fill_levels
             .iter()
+            .rev()
             .fold(0, |acc, &x| (acc << 4) | (x as u32))
     }
 }
 
 /// [HNHDR](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/8e4ae05c-3c24-4103-b7e5-ffef6f244834)
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct HeapNodeHeader {
     page_map_offset: u16,
     client_signature: HeapNodeType,
@@ -291,7 +316,7 @@ impl HeapNodePageReadWrite for HeapNodeHeader {
 }
 
 /// [HNPAGEHDR](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/9c34ecf8-36bc-45a1-a2df-ee35c6dc840a)
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct HeapNodePageHeader(u16);
 
 impl HeapNodePageHeader {
@@ -316,7 +341,7 @@ impl HeapNodePageReadWrite for HeapNodePageHeader {
 }
 
 /// [HNBITMAPHDR](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/822e2327-b29d-4ec4-91be-45637a438d40)
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct HeapNodeBitmapHeader {
     page_map_offset: u16,
     fill_levels: [HeapFillLevel; 128],
@@ -363,6 +388,7 @@ impl HeapNodePageReadWrite for HeapNodeBitmapHeader {
     }
 }
 
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
 pub struct HeapNodePageAllocOffsets(Vec<u16>);
 
 impl HeapNodePageAllocOffsets {
@@ -371,7 +397,7 @@ impl HeapNodePageAllocOffsets {
     }
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
 pub struct HeapNodePageAlloc {
     offset: u16,
     size: u16,
@@ -388,7 +414,7 @@ impl HeapNodePageAlloc {
 }
 
 /// [HNPAGEMAP](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/291653c0-b347-4c5b-ba41-85ad780b4ba4)
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
 pub struct HeapNodePageMap {
     allocations: Vec<HeapNodePageAlloc>,
     next_offset: u16,
@@ -414,6 +440,43 @@ impl HeapNodePageMap {
         Ok(page_map)
     }
 
+    /// Read the HNPAGEMAP at `page_map_offset` in a heap `page`. `cAlloc`, `cFree`, and every
+    /// `rgibAlloc` entry are checked against the size of the page before they are used, so the
+    /// allocations can be sliced out of `page` safely.
+    pub fn read_from_page(page: &[u8], page_map_offset: u16) -> io::Result<Self> {
+        let page_map = page
+            .get(usize::from(page_map_offset)..)
+            .filter(|page_map| page_map.len() >= 4)
+            .ok_or(LtpError::InvalidHeapPageMapOffset(page_map_offset))?;
+        let mut cursor = Cursor::new(page_map);
+        let alloc_count = cursor.read_u16::<LittleEndian>()?;
+        let free_count = cursor.read_u16::<LittleEndian>()?;
+
+        // rgibAlloc has cAlloc + 1 entries, which must all fit after cAlloc and cFree.
+        if (usize::from(alloc_count) + 1) * 2 > page_map.len() - 4 {
+            return Err(LtpError::InvalidHeapPageAllocCount(alloc_count).into());
+        }
+        if free_count > alloc_count {
+            return Err(LtpError::InvalidHeapPageFreeCount(free_count).into());
+        }
+
+        let mut offsets = Vec::with_capacity(usize::from(alloc_count) + 1);
+        for _ in 0..=alloc_count {
+            // Allocations are stored between the header and the HNPAGEMAP.
+            let offset = cursor.read_u16::<LittleEndian>()?;
+            if offset > page_map_offset {
+                return Err(LtpError::InvalidHeapPageAllocOffset(offset).into());
+            }
+            offsets.push(offset);
+        }
+
+        Ok(Self::new(
+            alloc_count,
+            free_count,
+            HeapNodePageAllocOffsets::new(offsets),
+        )?)
+    }
+
     pub fn allocations(&self) -> &[HeapNodePageAlloc] {
         &self.allocations
     }
@@ -494,18 +557,330 @@ impl HeapNodePageReadWrite for HeapNodePageMap {
             u16::try_from(self.allocations.len()).map_err(|_| LtpError::HeapPageOutOfSpace)?;
         f.write_u16::<LittleEndian>(alloc_count)?;
         f.write_u16::<LittleEndian>(self.free_count)?;
-        let mut last_offset = 0;
-        for HeapNodePageAlloc { offset, size } in &self.allocations {
+        for HeapNodePageAlloc { offset, .. } in &self.allocations {
             f.write_u16::<LittleEndian>(*offset)?;
-            last_offset = *offset + *size;
         }
-        f.write_u16::<LittleEndian>(last_offset)
+        f.write_u16::<LittleEndian>(self.next_offset)
+    }
+}
+
+/// Read the HNPAGEMAP from one of the data blocks of a heap, along with the [`HeapNodeHeader`] if
+/// it is the first block.
+pub(crate) fn read_heap_page_map(
+    block_index: usize,
+    block: &[u8],
+) -> io::Result<(Option<HeapNodeHeader>, HeapNodePageMap)> {
+    let mut cursor = Cursor::new(block);
+    let (header, page_map_offset) = match block_index {
+        0 => {
+            let header = HeapNodeHeader::read(&mut cursor)?;
+            let page_map_offset = header.page_map_offset();
+            (Some(header), page_map_offset)
+        }
+        bitmap if bitmap % 128 == 8 => (
+            None,
+            HeapNodeBitmapHeader::read(&mut cursor)?.page_map_offset(),
+        ),
+        _ => (
+            None,
+            HeapNodePageHeader::read(&mut cursor)?.page_map_offset(),
+        ),
+    };
+
+    let page_map = HeapNodePageMap::read_from_page(block, page_map_offset)?;
+    Ok((header, page_map))
+}
+
+/// Find the index of the data block and the byte range within that block which hold `heap_id`,
+/// so the allocation can be modified in place without moving anything else in the heap.
+pub(crate) fn heap_allocation_range(
+    blocks: &[Vec<u8>],
+    heap_id: HeapId,
+) -> io::Result<(usize, Range<usize>)> {
+    let block_index = heap_id.block_index();
+    let index = heap_id.index()?;
+    let block = blocks
+        .get(usize::from(block_index))
+        .ok_or(LtpError::HeapBlockIndexNotFound(block_index))?;
+    let (_, page_map) = read_heap_page_map(usize::from(block_index), block)?;
+    let alloc = page_map
+        .allocations()
+        .get(usize::from(index))
+        .ok_or(LtpError::HeapAllocIndexNotFound(index))?;
+    let start = usize::from(alloc.offset());
+    Ok((
+        usize::from(block_index),
+        start..start + usize::from(alloc.size()),
+    ))
+}
+
+/// The largest allocation which can be stored in a heap, anything larger must be stored in a
+/// sub-node.
+pub const MAX_HEAP_ALLOCATION_SIZE: usize = 3580;
+
+/// `hidIndex` is an 11-bit value, and 0 is reserved.
+const MAX_HEAP_PAGE_ALLOCATIONS: usize = 0x7FF;
+
+/// Size of the largest new allocation which fits in a heap page of up to `max_page_size` bytes,
+/// when `used` bytes are taken by the page header and the `alloc_count` existing allocations. A
+/// new allocation needs another `rgibAlloc` entry in the HNPAGEMAP unless it can reuse a
+/// `free_slot`.
+fn page_available_space(
+    used: usize,
+    alloc_count: usize,
+    free_slot: bool,
+    max_page_size: usize,
+) -> usize {
+    let (used, alloc_count) = if free_slot {
+        (used, alloc_count)
+    } else if alloc_count < MAX_HEAP_PAGE_ALLOCATIONS {
+        (used + 2, alloc_count)
+    } else {
+        return 0;
+    };
+
+    // The allocations are padded to a 2-byte boundary before the HNPAGEMAP, which holds cAlloc,
+    // cFree, and cAlloc + 1 offsets in rgibAlloc.
+    let page_map_offset = max_page_size.saturating_sub(4 + 2 * (alloc_count + 1)) & !1;
+    page_map_offset
+        .saturating_sub(used)
+        .min(MAX_HEAP_ALLOCATION_SIZE)
+}
+
+/// An editable copy of every allocation in a heap, which can be serialized back into the data
+/// blocks of a node. Allocations never move, so the [`HeapId`] of an existing allocation does not
+/// change. A freed allocation keeps its slot in the HNPAGEMAP with a size of 0, and the slot may be
+/// reused by a later allocation in the same page.
+pub(crate) struct HeapNodeEditor {
+    client_signature: HeapNodeType,
+    user_root: HeapId,
+    max_page_size: usize,
+    pages: Vec<Vec<Vec<u8>>>,
+}
+
+impl HeapNodeEditor {
+    /// Start a new heap with no allocations. Pages written by [`HeapNodeEditor::write`] will be no
+    /// larger than `max_page_size`.
+    pub fn new(client_signature: HeapNodeType, max_page_size: usize) -> Self {
+        Self {
+            client_signature,
+            user_root: Default::default(),
+            max_page_size,
+            pages: vec![Vec::new()],
+        }
+    }
+
+    /// Read all of the allocations in the data `blocks` of a heap, see [`HeapNodeEditor::new`].
+    pub fn read(blocks: &[Vec<u8>], max_page_size: usize) -> io::Result<Self> {
+        let mut client_signature = None;
+        let mut user_root = Default::default();
+        let mut pages = Vec::with_capacity(blocks.len());
+
+        for (block_index, block) in blocks.iter().enumerate() {
+            let (header, page_map) = read_heap_page_map(block_index, block)?;
+            if let Some(header) = header {
+                client_signature = Some(header.client_signature());
+                user_root = header.user_root();
+            }
+
+            let allocations = page_map
+                .allocations()
+                .iter()
+                .map(|alloc| {
+                    let start = usize::from(alloc.offset());
+                    let end = start + usize::from(alloc.size());
+                    block
+                        .get(start..end)
+                        .map(<[u8]>::to_vec)
+                        .ok_or(LtpError::InvalidHeapPageAllocOffset(alloc.offset()))
+                })
+                .collect::<LtpResult<Vec<_>>>()?;
+            pages.push(allocations);
+        }
+
+        let client_signature = client_signature.ok_or(io::ErrorKind::UnexpectedEof)?;
+
+        Ok(Self {
+            client_signature,
+            user_root,
+            max_page_size,
+            pages,
+        })
+    }
+
+    pub fn user_root(&self) -> HeapId {
+        self.user_root
+    }
+
+    pub fn set_user_root(&mut self, user_root: HeapId) {
+        self.user_root = user_root;
+    }
+
+    pub fn get(&self, heap_id: HeapId) -> LtpResult<&[u8]> {
+        let block_index = heap_id.block_index();
+        let index = heap_id.index()?;
+        self.pages
+            .get(usize::from(block_index))
+            .ok_or(LtpError::HeapBlockIndexNotFound(block_index))?
+            .get(usize::from(index))
+            .map(Vec::as_slice)
+            .ok_or(LtpError::HeapAllocIndexNotFound(index))
+    }
+
+    /// Add a new allocation to the first page with enough room for it, appending a new page if
+    /// none of the existing pages have enough space. Anything larger than
+    /// [`MAX_HEAP_ALLOCATION_SIZE`] must be stored in a sub-node instead.
+    pub fn allocate(&mut self, data: Vec<u8>) -> LtpResult<HeapId> {
+        if data.len() > MAX_HEAP_ALLOCATION_SIZE {
+            return Err(LtpError::HeapAllocationTooLarge(data.len()));
+        }
+
+        let block_index = (0..self.pages.len())
+            .find(|&block_index| self.available_space(block_index) >= data.len())
+            .unwrap_or_else(|| {
+                self.pages.push(Vec::new());
+                self.pages.len() - 1
+            });
+        let block_index_u16 =
+            u16::try_from(block_index).map_err(|_| LtpError::HeapBlockIndexNotFound(u16::MAX))?;
+
+        let page = &mut self.pages[block_index];
+        let index = match page.iter().position(Vec::is_empty) {
+            Some(index) => {
+                page[index] = data;
+                index
+            }
+            None => {
+                page.push(data);
+                page.len() - 1
+            }
+        };
+        HeapId::new(index as u16 + 1, block_index_u16)
+    }
+
+    /// Free the allocation for `heap_id`. The space it used is reclaimed the next time the heap
+    /// is written, and the [`HeapId`] is no longer valid.
+    #[allow(dead_code)]
+    pub fn free(&mut self, heap_id: HeapId) -> LtpResult<()> {
+        let block_index = heap_id.block_index();
+        let index = heap_id.index()?;
+        let alloc = self
+            .pages
+            .get_mut(usize::from(block_index))
+            .ok_or(LtpError::HeapBlockIndexNotFound(block_index))?
+            .get_mut(usize::from(index))
+            .filter(|alloc| !alloc.is_empty())
+            .ok_or(LtpError::HeapAllocIndexNotFound(index))?;
+        *alloc = Vec::new();
+        Ok(())
+    }
+
+    /// Size of the largest allocation which [`HeapNodeEditor::allocate`] could add to the page at
+    /// `block_index` without appending a new page.
+    pub fn available_space(&self, block_index: usize) -> usize {
+        let Some(page) = self.pages.get(block_index) else {
+            return 0;
+        };
+        let used = Self::header_size(block_index) + page.iter().map(Vec::len).sum::<usize>();
+        page_available_space(
+            used,
+            page.len(),
+            page.iter().any(Vec::is_empty),
+            self.max_page_size,
+        )
+    }
+
+    fn header_size(block_index: usize) -> usize {
+        match block_index {
+            0 => 12,
+            bitmap if bitmap % 128 == 8 => 66,
+            _ => 2,
+        }
+    }
+
+    /// Size of a page with `extra` additional bytes, including the header and the HNPAGEMAP.
+    fn page_size(&self, block_index: usize, extra: usize) -> usize {
+        let page = &self.pages[block_index];
+        let data =
+            Self::header_size(block_index) + page.iter().map(Vec::len).sum::<usize>() + extra;
+        data + data % 2 + 4 + 2 * (page.len() + 1)
+    }
+
+    /// Serialize the heap into the contents of each data block.
+    pub fn write(&self) -> io::Result<Vec<Vec<u8>>> {
+        let fill_levels: Vec<_> = (0..self.pages.len())
+            .map(|block_index| {
+                HeapFillLevel::from_free_space(
+                    self.max_page_size
+                        .saturating_sub(self.page_size(block_index, 0)),
+                )
+            })
+            .collect();
+        let fill_level = |block_index: usize| {
+            fill_levels
+                .get(block_index)
+                .copied()
+                .unwrap_or(HeapFillLevel::Empty)
+        };
+
+        let mut blocks = Vec::with_capacity(self.pages.len());
+        for (block_index, page) in self.pages.iter().enumerate() {
+            let header_size = Self::header_size(block_index);
+            let mut data = vec![0_u8; header_size];
+            let mut offsets = Vec::with_capacity(page.len() + 1);
+            for alloc in page.iter() {
+                offsets.push(u16::try_from(data.len()).map_err(|_| LtpError::HeapPageOutOfSpace)?);
+                data.extend_from_slice(alloc);
+            }
+            offsets.push(u16::try_from(data.len()).map_err(|_| LtpError::HeapPageOutOfSpace)?);
+            if data.len() % 2 != 0 {
+                data.push(0);
+            }
+
+            let page_map_offset =
+                u16::try_from(data.len()).map_err(|_| LtpError::HeapPageOutOfSpace)?;
+            let alloc_count =
+                u16::try_from(page.len()).map_err(|_| LtpError::HeapPageOutOfSpace)?;
+            let free_count = u16::try_from(page.iter().filter(|alloc| alloc.is_empty()).count())
+                .map_err(|_| LtpError::HeapPageOutOfSpace)?;
+            let page_map = HeapNodePageMap::new(
+                alloc_count,
+                free_count,
+                HeapNodePageAllocOffsets::new(offsets),
+            )?;
+            page_map.write(&mut data)?;
+
+            let mut cursor = Cursor::new(&mut data[..header_size]);
+            match block_index {
+                0 => HeapNodeHeader::new(
+                    page_map_offset,
+                    self.client_signature,
+                    self.user_root,
+                    core::array::from_fn(fill_level),
+                )
+                .write(&mut cursor)?,
+                bitmap if bitmap % 128 == 8 => HeapNodeBitmapHeader::new(
+                    page_map_offset,
+                    core::array::from_fn(|i| fill_level(block_index + i)),
+                )
+                .write(&mut cursor)?,
+                _ => HeapNodePageHeader::new(page_map_offset).write(&mut cursor)?,
+            }
+
+            blocks.push(data);
+        }
+
+        Ok(blocks)
     }
 }
 
 pub trait HeapNode {
     fn header(&self) -> io::Result<HeapNodeHeader>;
     fn find_entry(&self, heap_id: HeapId) -> io::Result<&[u8]>;
+
+    /// Size of the largest allocation which fits in the last page of the heap, without adding
+    /// another data block. This is never more than [`MAX_HEAP_ALLOCATION_SIZE`].
+    fn available_space(&self) -> io::Result<u16>;
 }
 
 struct HeapNodeInner<Pst>
@@ -596,19 +971,34 @@ where
             }
         };
 
-        cursor.seek(SeekFrom::Start(u64::from(page_map_offset)))?;
-        let page_map = HeapNodePageMap::read(&mut cursor)?;
+        let page_map = HeapNodePageMap::read_from_page(block, page_map_offset)?;
         let allocations = page_map.allocations();
 
         let index = heap_id.index()?;
-        if index as usize >= allocations.len() {
-            return Err(LtpError::HeapAllocIndexNotFound(index).into());
-        }
-
-        let alloc = &allocations[index as usize];
+        let alloc = allocations
+            .get(index as usize)
+            .ok_or(LtpError::HeapAllocIndexNotFound(index))?;
         let start = alloc.offset() as usize;
         let end = start + alloc.size() as usize;
-        Ok(&block[start..end])
+        Ok(block
+            .get(start..end)
+            .ok_or(LtpError::InvalidHeapPageAllocOffset(alloc.offset()))?)
+    }
+
+    fn available_space(&self) -> io::Result<u16> {
+        let block_index = self
+            .data
+            .len()
+            .checked_sub(1)
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+        let (_, page_map) = read_heap_page_map(block_index, self.data[block_index].data())?;
+        let available = page_available_space(
+            usize::from(page_map.next_offset()),
+            page_map.allocations().len(),
+            page_map.free_count > 0,
+            DataTree::<Pst>::max_data_size(),
+        );
+        Ok(available as u16)
     }
 }
 
@@ -624,6 +1014,10 @@ impl HeapNode for UnicodeHeapNode {
     fn find_entry(&self, heap_id: HeapId) -> io::Result<&[u8]> {
         self.inner.find_entry(heap_id)
     }
+
+    fn available_space(&self) -> io::Result<u16> {
+        self.inner.available_space()
+    }
 }
 
 impl HeapNodeReadWrite<UnicodePstFile> for UnicodeHeapNode {
@@ -651,6 +1045,10 @@ impl HeapNode for AnsiHeapNode {
     fn find_entry(&self, heap_id: HeapId) -> io::Result<&[u8]> {
         self.inner.find_entry(heap_id)
     }
+
+    fn available_space(&self) -> io::Result<u16> {
+        self.inner.available_space()
+    }
 }
 
 impl HeapNodeReadWrite<AnsiPstFile> for AnsiHeapNode {
@@ -665,3 +1063,249 @@ impl HeapNodeReadWrite<AnsiPstFile> for AnsiHeapNode {
         Ok(Self { inner })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 8192;
+
+    /// Build an 8KB heap page with a HNPAGEMAP at `page_map_offset`.
+    fn heap_page(
+        page_map_offset: u16,
+        alloc_count: u16,
+        free_count: u16,
+        offsets: &[u16],
+    ) -> Vec<u8> {
+        let mut page = vec![0; PAGE_SIZE];
+        let mut cursor = Cursor::new(&mut page[usize::from(page_map_offset)..]);
+        cursor.write_u16::<LittleEndian>(alloc_count).unwrap();
+        cursor.write_u16::<LittleEndian>(free_count).unwrap();
+        for offset in offsets {
+            cursor.write_u16::<LittleEndian>(*offset).unwrap();
+        }
+        page
+    }
+
+    fn ltp_error(err: io::Error) -> LtpError {
+        *err.into_inner().unwrap().downcast::<LtpError>().unwrap()
+    }
+
+    #[test]
+    fn test_read_page_map() {
+        let page = heap_page(0x100, 2, 0, &[0x0C, 0x20, 0x40]);
+        let page_map = HeapNodePageMap::read_from_page(&page, 0x100).unwrap();
+        let allocations = page_map.allocations();
+        assert_eq!(allocations.len(), 2);
+        assert_eq!(
+            (allocations[1].offset(), allocations[1].size()),
+            (0x20, 0x20)
+        );
+        assert_eq!(page_map.next_offset(), 0x40);
+    }
+
+    #[test]
+    fn test_page_map_alloc_count_too_large() {
+        let page_map_offset = 0x1E00;
+        let page = heap_page(page_map_offset, 0x0FFF, 0, &[0x0C, 0x20]);
+        let err = HeapNodePageMap::read_from_page(&page, page_map_offset).unwrap_err();
+        assert!(matches!(
+            ltp_error(err),
+            LtpError::InvalidHeapPageAllocCount(0x0FFF)
+        ));
+    }
+
+    #[test]
+    fn test_page_map_free_count_too_large() {
+        let page = heap_page(0x100, 1, 2, &[0x0C, 0x20]);
+        let err = HeapNodePageMap::read_from_page(&page, 0x100).unwrap_err();
+        assert!(matches!(
+            ltp_error(err),
+            LtpError::InvalidHeapPageFreeCount(2)
+        ));
+    }
+
+    #[test]
+    fn test_page_map_offset_out_of_bounds() {
+        let page = heap_page(0x100, 1, 0, &[0x0C, 0x1000]);
+        let err = HeapNodePageMap::read_from_page(&page, 0x100).unwrap_err();
+        assert!(matches!(
+            ltp_error(err),
+            LtpError::InvalidHeapPageAllocOffset(0x1000)
+        ));
+
+        let err = HeapNodePageMap::read_from_page(&page, PAGE_SIZE as u16 - 2).unwrap_err();
+        assert!(matches!(
+            ltp_error(err),
+            LtpError::InvalidHeapPageMapOffset(0x1FFE)
+        ));
+    }
+
+    const MAX_PAGE_SIZE: usize = 8176;
+
+    /// Check every block of a heap with the strict HNPAGEMAP reader, and return the `cFree` of
+    /// each page.
+    fn free_counts(blocks: &[Vec<u8>]) -> Vec<u16> {
+        blocks
+            .iter()
+            .enumerate()
+            .map(|(block_index, block)| {
+                assert!(block.len() <= MAX_PAGE_SIZE);
+                let (header, page_map) = read_heap_page_map(block_index, block).unwrap();
+                assert_eq!(header.is_some(), block_index == 0);
+                page_map.free_count
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_heap_editor_free() {
+        let mut heap = HeapNodeEditor::new(HeapNodeType::Properties, MAX_PAGE_SIZE);
+        let first = heap.allocate(vec![1; 16]).unwrap();
+        let second = heap.allocate(vec![2; 32]).unwrap();
+        let third = heap.allocate(vec![3; 8]).unwrap();
+        heap.free(second).unwrap();
+        assert!(matches!(
+            heap.free(second),
+            Err(LtpError::HeapAllocIndexNotFound(1))
+        ));
+
+        let blocks = heap.write().unwrap();
+        assert_eq!(free_counts(&blocks), vec![1]);
+
+        let mut heap = HeapNodeEditor::read(&blocks, MAX_PAGE_SIZE).unwrap();
+        assert_eq!(heap.get(first).unwrap(), &[1; 16]);
+        assert!(heap.get(second).unwrap().is_empty());
+        assert_eq!(heap.get(third).unwrap(), &[3; 8]);
+
+        // The freed slot is reused by the next allocation, without moving anything else.
+        let reused = heap.allocate(vec![4; 24]).unwrap();
+        assert_eq!(u32::from(reused), u32::from(second));
+
+        let blocks = heap.write().unwrap();
+        assert_eq!(free_counts(&blocks), vec![0]);
+        let heap = HeapNodeEditor::read(&blocks, MAX_PAGE_SIZE).unwrap();
+        assert_eq!(heap.get(first).unwrap(), &[1; 16]);
+        assert_eq!(heap.get(reused).unwrap(), &[4; 24]);
+        assert_eq!(heap.get(third).unwrap(), &[3; 8]);
+    }
+
+    #[test]
+    fn test_heap_editor_spill() {
+        let mut heap = HeapNodeEditor::new(HeapNodeType::Table, MAX_PAGE_SIZE);
+        assert!(matches!(
+            heap.allocate(vec![0; MAX_HEAP_ALLOCATION_SIZE + 1]),
+            Err(LtpError::HeapAllocationTooLarge(3581))
+        ));
+
+        // Only 2 of the largest allocations fit in each page, so these spill into 10 pages,
+        // including the HNBITMAPHDR page at block index 8.
+        let heap_ids: Vec<_> = (0..20_u8)
+            .map(|value| {
+                heap.allocate(vec![value; MAX_HEAP_ALLOCATION_SIZE])
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(heap_ids[19].block_index(), 9);
+
+        let blocks = heap.write().unwrap();
+        assert_eq!(free_counts(&blocks), vec![0; 10]);
+        let header = HeapNodeHeader::read(&mut blocks[0].as_slice()).unwrap();
+        assert_eq!(header.client_signature(), HeapNodeType::Table);
+        assert!(header
+            .fill_levels()
+            .iter()
+            .all(|level| *level == HeapFillLevel::Level7));
+
+        // Freeing both allocations in one page makes room there for an allocation which does not
+        // fit in any of the other pages.
+        let mut heap = HeapNodeEditor::read(&blocks, MAX_PAGE_SIZE).unwrap();
+        heap.free(heap_ids[6]).unwrap();
+        heap.free(heap_ids[7]).unwrap();
+        let blocks = heap.write().unwrap();
+        assert_eq!(free_counts(&blocks)[3], 2);
+        let header = HeapNodeHeader::read(&mut blocks[0].as_slice()).unwrap();
+        assert_eq!(header.fill_levels()[3], HeapFillLevel::Empty);
+
+        let mut heap = HeapNodeEditor::read(&blocks, MAX_PAGE_SIZE).unwrap();
+        let reused = heap.allocate(vec![0xFF; 3000]).unwrap();
+        assert_eq!(u32::from(reused), u32::from(heap_ids[6]));
+
+        let blocks = heap.write().unwrap();
+        let heap = HeapNodeEditor::read(&blocks, MAX_PAGE_SIZE).unwrap();
+        for (value, heap_id) in heap_ids.into_iter().enumerate() {
+            match value {
+                6 => assert_eq!(heap.get(heap_id).unwrap(), &[0xFF; 3000]),
+                7 => assert!(heap.get(heap_id).unwrap().is_empty()),
+                _ => assert_eq!(
+                    heap.get(heap_id).unwrap(),
+                    &[value as u8; MAX_HEAP_ALLOCATION_SIZE]
+                ),
+            }
+        }
+    }
+
+    fn heap_node(blocks: &[Vec<u8>]) -> UnicodeHeapNode {
+        let data = blocks
+            .iter()
+            .map(|block| {
+                UnicodeDataBlock::new(NdbCryptMethod::None, block.clone(), Default::default())
+                    .unwrap()
+            })
+            .collect();
+        UnicodeHeapNode {
+            inner: HeapNodeInner { data },
+        }
+    }
+
+    #[test]
+    fn test_available_space() {
+        let mut heap = HeapNodeEditor::new(HeapNodeType::Properties, MAX_PAGE_SIZE);
+        heap.allocate(vec![1; 100]).unwrap();
+        heap.allocate(vec![2; 1001]).unwrap();
+        let blocks = heap.write().unwrap();
+
+        // There is still room for the largest allocation.
+        assert_eq!(
+            heap_node(&blocks).available_space().unwrap(),
+            MAX_HEAP_ALLOCATION_SIZE as u16
+        );
+        assert_eq!(heap.available_space(0), MAX_HEAP_ALLOCATION_SIZE);
+
+        // Fill the rest of the page until it has less room than the largest allocation.
+        let filler = heap.allocate(vec![3; MAX_HEAP_ALLOCATION_SIZE]).unwrap();
+        assert_eq!(filler.block_index(), 0);
+        let blocks = heap.write().unwrap();
+        let available = heap_node(&blocks).available_space().unwrap();
+
+        // The header and 3 allocations, followed by a HNPAGEMAP with 5 offsets once there is a
+        // fourth allocation.
+        let used = 12 + 100 + 1001 + MAX_HEAP_ALLOCATION_SIZE;
+        assert_eq!(usize::from(available), MAX_PAGE_SIZE - 4 - 2 * 5 - used);
+        assert_eq!(heap.available_space(0), usize::from(available));
+
+        // An allocation of exactly that size still fits in the page, and then it's full.
+        let mut heap = HeapNodeEditor::read(&blocks, MAX_PAGE_SIZE).unwrap();
+        let exact = heap.allocate(vec![4; usize::from(available)]).unwrap();
+        assert_eq!(exact.block_index(), 0);
+        let blocks = heap.write().unwrap();
+        assert_eq!(blocks[0].len(), MAX_PAGE_SIZE);
+        assert_eq!(heap_node(&blocks).available_space().unwrap(), 0);
+
+        // Freeing it leaves an empty slot which can be reused without another rgibAlloc entry,
+        // but one more byte than that spills into a new page.
+        let mut heap = HeapNodeEditor::read(&blocks, MAX_PAGE_SIZE).unwrap();
+        heap.free(exact).unwrap();
+        let blocks = heap.write().unwrap();
+        assert_eq!(heap_node(&blocks).available_space().unwrap(), available);
+        let mut heap = HeapNodeEditor::read(&blocks, MAX_PAGE_SIZE).unwrap();
+        let spilled = heap.allocate(vec![5; usize::from(available) + 1]).unwrap();
+        assert_eq!(spilled.block_index(), 1);
+        let blocks = heap.write().unwrap();
+        let heap_node = heap_node(&blocks);
+        assert_eq!(
+            usize::from(heap_node.available_space().unwrap()),
+            heap.available_space(1)
+        );
+    }
+}
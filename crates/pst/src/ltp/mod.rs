@@ -11,7 +11,12 @@ pub mod tree;
 
 pub(crate) mod read_write;
 
+/// Shorter name for [`prop_context::PropertyValue`], the value type returned by every property
+/// and table read in this crate.
+pub type Value = prop_context::PropertyValue;
+
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum LtpError {
     #[error("Node Database error: {0}")]
     NodeDatabaseError(#[from] crate::ndb::NdbError),
@@ -27,6 +32,8 @@ pub enum LtpError {
     InvalidHeapFillLevel(u8),
     #[error("HNPAGEMAP is out of space")]
     HeapPageOutOfSpace,
+    #[error("Heap allocation is too large: 0x{0:X}, it must be stored in a sub-node")]
+    HeapAllocationTooLarge(usize),
     #[error("Empty HNPAGEMAP rgibAlloc")]
     EmptyHeapPageAlloc,
     #[error("Invalid HNPAGEMAP rgibAlloc entry: 0x{0:04X}")]
@@ -35,6 +42,8 @@ pub enum LtpError {
     InvalidHeapPageAllocCount(u16),
     #[error("Invalid HNPAGEMAP cFree: 0x{0:04X}")]
     InvalidHeapPageFreeCount(u16),
+    #[error("Invalid HNHDR ibHnpm: 0x{0:04X}")]
+    InvalidHeapPageMapOffset(u16),
     #[error("Invalid BTHHEADER bType: {0:?}")]
     InvalidHeapTreeNodeType(heap::HeapNodeType),
     #[error("Invalid BTHHEADER cbKey: 0x{0:02X}")]
@@ -53,10 +62,14 @@ pub enum LtpError {
     InvalidMultiValuePropertyOffset(usize),
     #[error("Invalid multi-value property count: 0x{0:X}")]
     InvalidMultiValuePropertyCount(usize),
+    #[error("Missing PC property: 0x{0:04X}")]
+    PropertyNotFound(u16),
     #[error("Missing PC sub-node value: 0x{0:08X}")]
     PropertySubNodeValueNotFound(u32),
     #[error("Invalid small PC value property type: {0:?}")]
     InvalidSmallPropertyType(prop_type::PropertyType),
+    #[error("Invalid PtypBoolean value: 0x{0:02X}")]
+    InvalidPropertyBooleanValue(u8),
     #[error("Invalid PC property tree key size: 0x{0:X}")]
     InvalidPropertyTreeKeySize(u8),
     #[error("Invalid PC property tree entry size: 0x{0:X}")]
@@ -95,11 +108,49 @@ pub enum LtpError {
     InvalidTableColumnBooleanValue(u8),
     #[error("Missing TCROWID: 0x{0:08X}")]
     TableRowIdNotFound(u32),
+    #[error("Missing TC Row Matrix block: {0}")]
+    TableRowMatrixBlockNotFound(usize),
+    #[error("Failed to read TC Row Matrix: {0}")]
+    TableRowMatrixReadFailed(String),
+    #[error("Property type mismatch: expected {expected:?}, found {actual:?}")]
+    PropertyTypeMismatch {
+        expected: prop_type::PropertyType,
+        actual: prop_type::PropertyType,
+    },
+    #[error("Invalid PtypTime value: {0}")]
+    InvalidTimeValue(i64),
+    #[error("Unsupported code page: {0}")]
+    UnsupportedCodePage(u32),
+    #[error("PtypString8 value outside of ASCII needs a code page")]
+    String8CodePageRequired,
+    #[error("Embedded message nesting exceeds the limit of {0} levels")]
+    EmbeddedMessageDepthExceeded(usize),
+    #[error("I/O error: {0:?}")]
+    Io(io::Error),
 }
 
 impl From<LtpError> for io::Error {
     fn from(err: LtpError) -> io::Error {
-        io::Error::new(io::ErrorKind::InvalidData, err)
+        match err {
+            LtpError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// Unwrap an [`LtpError`] which was converted to an [`io::Error`], or wrap any other error in
+/// [`LtpError::Io`].
+impl From<io::Error> for LtpError {
+    fn from(err: io::Error) -> Self {
+        if !err.get_ref().is_some_and(|err| err.is::<LtpError>()) {
+            return Self::Io(err);
+        }
+        let kind = err.kind();
+        match err.into_inner().map(|err| err.downcast::<LtpError>()) {
+            Some(Ok(err)) => *err,
+            Some(Err(err)) => Self::Io(io::Error::new(kind, err)),
+            None => Self::Io(io::Error::from(kind)),
+        }
     }
 }
 
@@ -3,14 +3,16 @@
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use core::mem;
 use std::{
-    cell::RefCell,
-    collections::BTreeMap,
+    collections::{btree_map, BTreeMap},
     fmt::{Debug, Display},
     io::{self, Cursor, Read, Write},
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
 };
 
 use super::{heap::*, prop_type::*, read_write::*, tree::*, *};
 use crate::{
+    messaging::time::{filetime_to_system_time, UtcDateTime},
     ndb::{
         block::{DataBlockCache, DataTree, IntermediateTreeBlock, SubNodeTree},
         block_id::BlockId,
@@ -26,7 +28,7 @@ use crate::{
     AnsiPstFile, PstFile, PstFileReadWriteBlockBTree, PstReader, UnicodePstFile,
 };
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub enum PropertyValueRecord {
     Small(u32),
     Heap(HeapId),
@@ -34,28 +36,65 @@ pub enum PropertyValueRecord {
 }
 
 impl PropertyValueRecord {
-    pub fn small_value(&self, prop_type: PropertyType) -> Option<PropertyValue> {
+    /// Decode a value which is stored inline in the record. Values of any other type, or a
+    /// `PtypBoolean` which is not 0 or 1, are an error.
+    pub fn small_value(&self, prop_type: PropertyType) -> LtpResult<PropertyValue> {
         match (self, prop_type) {
+            (PropertyValueRecord::Small(_), PropertyType::Null) => Ok(PropertyValue::Null),
             (PropertyValueRecord::Small(value), PropertyType::Integer16) => {
-                Some(PropertyValue::Integer16((*value & 0xFFFF) as i16))
+                Ok(PropertyValue::Integer16((*value & 0xFFFF) as i16))
             }
             (PropertyValueRecord::Small(value), PropertyType::Integer32) => {
-                Some(PropertyValue::Integer32(*value as i32))
+                Ok(PropertyValue::Integer32(*value as i32))
             }
             (PropertyValueRecord::Small(value), PropertyType::Floating32) => {
-                Some(PropertyValue::Floating32(f32::from_bits(*value)))
+                Ok(PropertyValue::Floating32(f32::from_bits(*value)))
             }
             (PropertyValueRecord::Small(value), PropertyType::ErrorCode) => {
-                Some(PropertyValue::ErrorCode(*value as i32))
+                Ok(PropertyValue::ErrorCode(*value as i32))
             }
             (PropertyValueRecord::Small(value), PropertyType::Boolean) => {
-                Some(PropertyValue::Boolean(*value & 0xFF != 0))
+                let value = (*value & 0xFF) as u8;
+                decode_boolean(value)
+                    .map(PropertyValue::Boolean)
+                    .ok_or(LtpError::InvalidPropertyBooleanValue(value))
             }
-            _ => None,
+            (PropertyValueRecord::Small(value), PropertyType::Unknown(prop_type)) => Ok(
+                PropertyValue::Unknown(prop_type, BinaryValue::new(value.to_le_bytes().to_vec())),
+            ),
+            _ => Err(LtpError::InvalidSmallPropertyType(prop_type)),
+        }
+    }
+}
+
+impl PropertyValueRecord {
+    /// Encode a value which fits inline in the record, the reverse of [`Self::small_value`].
+    /// Values of any other type are an error.
+    pub fn from_small_value(value: &PropertyValue) -> LtpResult<Self> {
+        match value {
+            PropertyValue::Null => Ok(PropertyValueRecord::Small(0)),
+            PropertyValue::Integer16(value) => {
+                Ok(PropertyValueRecord::Small(u32::from(*value as u16)))
+            }
+            PropertyValue::Integer32(value) | PropertyValue::ErrorCode(value) => {
+                Ok(PropertyValueRecord::Small(*value as u32))
+            }
+            PropertyValue::Floating32(value) => Ok(PropertyValueRecord::Small(value.to_bits())),
+            PropertyValue::Boolean(value) => Ok(PropertyValueRecord::Small(u32::from(*value))),
+            _ => Err(LtpError::InvalidSmallPropertyType(value.prop_type())),
         }
     }
 }
 
+/// Decode a `PtypBoolean` byte, which must be exactly 0 or 1 in both PCs and TCs.
+pub(crate) fn decode_boolean(value: u8) -> Option<bool> {
+    match value {
+        0x00 => Some(false),
+        0x01 => Some(true),
+        _ => None,
+    }
+}
+
 impl Debug for PropertyValueRecord {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -92,7 +131,7 @@ impl HeapNodePageReadWrite for PropertyTreeRecordKey {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct PropertyTreeRecordValue {
     prop_type: PropertyType,
     value: PropertyValueRecord,
@@ -120,7 +159,7 @@ impl HeapNodePageReadWrite for PropertyTreeRecordValue {
     fn read(f: &mut dyn Read) -> io::Result<Self> {
         // wPropType
         let prop_type = f.read_u16::<LittleEndian>()?;
-        let prop_type = PropertyType::try_from(prop_type)?;
+        let prop_type = PropertyType::from_raw(prop_type);
 
         // dwValueHnid
         let value = f.read_u32::<LittleEndian>()?;
@@ -135,14 +174,10 @@ impl HeapNodePageReadWrite for PropertyTreeRecordValue {
 
             PropertyType::Boolean => PropertyValueRecord::Small(value & 0xFF),
 
-            PropertyType::Floating64
-            | PropertyType::Currency
-            | PropertyType::FloatingTime
-            | PropertyType::Integer64
-            | PropertyType::Time
-            | PropertyType::Guid
-            | PropertyType::Object => PropertyValueRecord::Heap(HeapId::from(value)),
+            PropertyType::Unknown(_) => PropertyValueRecord::Small(value),
 
+            // Fixed-size values wider than 4 bytes, e.g. `PtypInteger64`, are normally stored in
+            // the heap, but the HNID may also name a sub-node, just like a variable-size value.
             _ => match NodeId::from(value).id_type() {
                 Ok(NodeIdType::HeapNode) => PropertyValueRecord::Heap(HeapId::from(value)),
                 _ => PropertyValueRecord::Node(NodeId::from(value)),
@@ -159,7 +194,7 @@ impl HeapNodePageReadWrite for PropertyTreeRecordValue {
 }
 
 /// [PC BTH Record](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/7daab6f5-ce65-437e-80d5-1b1be4088bd3)
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct PropertyTreeRecord {
     key: PropertyTreeRecordKey,
     data: PropertyTreeRecordValue,
@@ -200,22 +235,65 @@ impl PropertyTreeRecordReadWrite for PropertyTreeRecord {
     }
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, PartialEq, Eq)]
 pub struct String8Value {
     buffer: Vec<u8>,
 }
 
 impl String8Value {
+    pub fn new(buffer: Vec<u8>) -> Self {
+        Self { buffer }
+    }
+
     pub fn buffer(&self) -> &[u8] {
         &self.buffer
     }
+
+    /// Decode the value with the Windows code page `codepage`, see [`decode_string8_lossy`].
+    pub fn decode(&self, codepage: u32) -> (String, bool) {
+        decode_string8_lossy(&self.buffer, codepage)
+    }
 }
 
+/// Without a code page, only ASCII can be displayed as it is. Any other bytes are escaped like
+/// `\x93`, decode the value with [`String8Value::decode`] or [`decode_string8`] to read them.
 impl Display for String8Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let buffer: Vec<_> = self.buffer.iter().map(|&b| u16::from(b)).collect();
-        let value = String::from_utf16_lossy(&buffer);
-        write!(f, "{value}")
+        for &byte in &self.buffer {
+            if byte.is_ascii() {
+                write!(f, "{}", char::from(byte))?;
+            } else {
+                write!(f, "\\x{byte:02X}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The bytes are serialized as Latin-1 so that they round trip through [`serde::Deserialize`]
+/// unchanged. This is not a decoding of the value, which needs its code page.
+#[cfg(feature = "serde")]
+impl serde::Serialize for String8Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&decode_latin1(&self.buffer))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for String8Value {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        let buffer = value
+            .chars()
+            .map(|ch| u8::try_from(ch).unwrap_or(b'?'))
+            .collect();
+        Ok(Self { buffer })
     }
 }
 
@@ -226,12 +304,16 @@ impl Debug for String8Value {
     }
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, PartialEq, Eq)]
 pub struct UnicodeValue {
     buffer: Vec<u16>,
 }
 
 impl UnicodeValue {
+    pub fn new(buffer: Vec<u16>) -> Self {
+        Self { buffer }
+    }
+
     pub fn buffer(&self) -> &[u16] {
         &self.buffer
     }
@@ -244,6 +326,28 @@ impl Display for UnicodeValue {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for UnicodeValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for UnicodeValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        let buffer = value.encode_utf16().collect();
+        Ok(Self { buffer })
+    }
+}
+
 impl Debug for UnicodeValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let value = self.to_string();
@@ -251,7 +355,7 @@ impl Debug for UnicodeValue {
     }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
 pub struct GuidValue {
     data1: u32,
     data2: u16,
@@ -286,11 +390,11 @@ impl GuidValue {
     }
 }
 
-impl Debug for GuidValue {
+impl Display for GuidValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "GuidValue {{ {:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X} }}",
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
             self.data1,
             self.data2,
             self.data3,
@@ -306,7 +410,23 @@ impl Debug for GuidValue {
     }
 }
 
-#[derive(Clone, Default)]
+#[cfg(feature = "serde")]
+impl serde::Serialize for GuidValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl Debug for GuidValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GuidValue {{ {self} }}")
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq)]
 pub struct BinaryValue {
     buffer: Vec<u8>,
 }
@@ -321,6 +441,41 @@ impl BinaryValue {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for BinaryValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&encode_base64(&self.buffer))
+    }
+}
+
+/// Encode binary property values with the standard base64 alphabet and padding.
+#[cfg(feature = "serde")]
+fn encode_base64(buffer: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut value = String::with_capacity(buffer.len().div_ceil(3) * 4);
+    for chunk in buffer.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or_default(),
+            chunk.get(2).copied().unwrap_or_default(),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let sextet = (bits >> (18 - 6 * index)) & 0x3F;
+                value.push(char::from(ALPHABET[sextet as usize]));
+            } else {
+                value.push('=');
+            }
+        }
+    }
+    value
+}
+
 impl Debug for BinaryValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let value = self
@@ -333,7 +488,8 @@ impl Debug for BinaryValue {
     }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ObjectValue {
     node_id: NodeId,
     size: u32,
@@ -359,7 +515,19 @@ impl Debug for ObjectValue {
     }
 }
 
-#[derive(Clone, Default, Debug)]
+/// Every [`PropertyType`] maps to the variant with the same name, so [`PropertyValue::prop_type`]
+/// gives back the type the value was read with. There are two exceptions:
+/// - A property whose value is stored in the heap with a `HID` of 0 reads as [`PropertyValue::Null`].
+/// - `PtypUnspecified` (0x0000), and any other type code which MS-PST does not list, is not a
+///   valid [`PropertyType`]. Property contexts read these as [`PropertyType::Unknown`] with the raw
+///   bytes in [`PropertyValue::Unknown`], while table contexts reject them.
+///
+/// With the `serde` feature enabled, [`PropertyValue`] is serialized as an externally tagged enum.
+/// [`PropertyValue::Time`] values are written as RFC 3339 strings in UTC, and binary values as
+/// base64 strings. Only the scalar and string variants can be deserialized again, the rest are
+/// skipped when deserializing.
+#[derive(Clone, Default, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PropertyValue {
     /// `PtypNull`: None: This property is a placeholder.
     #[default]
@@ -378,6 +546,7 @@ pub enum PropertyValue {
     /// `PtypFloatingTime`: 8 bytes; a 64-bit floating point number in which the whole number part
     /// represents the number of days since December 30, 1899, and the fractional part represents
     /// the fraction of a day since midnight
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     FloatingTime(f64),
     /// `PtypErrorCode`: 4 bytes; a 32-bit integer encoding error information as specified in
     /// section [2.4.1](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxcdata/c9dc2fb0-73ca-4cc2-bdee-cc6ffb9b70eb).
@@ -394,51 +563,239 @@ pub enum PropertyValue {
     Unicode(UnicodeValue),
     /// `PtypTime`: 8 bytes; a 64-bit integer representing the number of 100-nanosecond intervals
     /// since January 1, 1601
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "serialize_time", skip_deserializing)
+    )]
     Time(i64),
     /// `PtypGuid`: 16 bytes; a GUID with Data1, Data2, and Data3 fields in little-endian format
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     Guid(GuidValue),
     /// `PtypBinary`: Variable size; a COUNT field followed by that many bytes.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     Binary(BinaryValue),
     /// `PtypObject`: The property value is a Component Object Model (COM) object, as specified in
     /// section [2.11.1.5](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxcdata/5a024c95-2264-4832-9840-d6260c9c2cdb).
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     Object(ObjectValue),
 
     /// `PtypMultipleInteger16`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::Integer16] values.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     MultipleInteger16(Vec<i16>),
     /// `PtypMultipleInteger32`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::Integer32] values.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     MultipleInteger32(Vec<i32>),
     /// `PtypMultipleFloating32`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::Floating32] values.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     MultipleFloating32(Vec<f32>),
     /// `PtypFloating64`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::Floating64] values.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     MultipleFloating64(Vec<f64>),
     /// `PtypMultipleCurrency`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::Currency] values.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     MultipleCurrency(Vec<i64>),
     /// `PtypMultipleFloatingTime`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::FloatingTime] values.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     MultipleFloatingTime(Vec<f64>),
     /// `PtypMultipleInteger64`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::Integer64] values.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     MultipleInteger64(Vec<i64>),
     /// `PtypMultipleString8`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::String8] values.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     MultipleString8(Vec<String8Value>),
     /// `PtypMultipleString`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::Unicode] values.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     MultipleUnicode(Vec<UnicodeValue>),
     /// `PtypMultipleTime`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::Time] values.
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "serialize_multiple_time", skip_deserializing)
+    )]
     MultipleTime(Vec<i64>),
     /// `PtypMultipleGuid`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::Guid] values.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     MultipleGuid(Vec<GuidValue>),
     /// `PtypMultipleBinary`: Variable size; a COUNT field followed by that many
     /// [PropertyValue::Binary] values.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     MultipleBinary(Vec<BinaryValue>),
+
+    /// A property with a [`PropertyType::Unknown`] type code. The buffer holds the raw
+    /// `dwValueHnid` from the property tree, or the whole value if it was read from a heap or
+    /// sub-node.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
+    Unknown(u16, BinaryValue),
+}
+
+/// Format a `PtypTime` value, the number of 100-nanosecond intervals since January 1, 1601 (UTC),
+/// as an RFC 3339 string.
+pub(crate) fn format_time(value: i64) -> String {
+    let UtcDateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        ticks,
+    } = UtcDateTime::from_filetime(value);
+    if ticks == 0 {
+        format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+    } else {
+        format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{ticks:07}Z")
+    }
+}
+
+#[cfg(feature = "serde")]
+fn serialize_time<S>(value: &i64, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&format_time(*value))
+}
+
+#[cfg(feature = "serde")]
+fn serialize_multiple_time<S>(values: &[i64], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_seq(values.iter().map(|value| format_time(*value)))
+}
+
+/// Format a `PtypFloatingTime` value, the number of days since December 30, 1899, as an RFC 3339
+/// string.
+fn format_floating_time(value: f64) -> String {
+    const DAYS_FROM_1601_TO_1899: f64 = 109_205.0;
+    const TICKS_PER_DAY: f64 = 864_000_000_000.0;
+
+    format_time(((value + DAYS_FROM_1601_TO_1899) * TICKS_PER_DAY).round() as i64)
+}
+
+/// Format a `PtypCurrency` value, which is scaled by 10,000, with 4 decimal places.
+fn format_currency(value: i64) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    let value = value.unsigned_abs();
+    format!("{sign}{}.{:04}", value / 10_000, value % 10_000)
+}
+
+/// Format a `PtypGuid` value in registry format, with braces.
+fn format_guid(value: &GuidValue) -> String {
+    format!("{{{value}}}")
+}
+
+/// The number of bytes in a `PtypBinary` value which are shown by the [`Display`] implementation
+/// of [`PropertyValue`].
+pub const MAX_DISPLAY_BINARY: usize = 32;
+
+/// Format a `PtypBinary` value as its length and a hex dump, truncated after
+/// [`MAX_DISPLAY_BINARY`] bytes.
+fn format_binary(value: &BinaryValue) -> String {
+    let buffer = value.buffer();
+    let mut result = format!("[{} bytes]", buffer.len());
+    for byte in buffer.iter().take(MAX_DISPLAY_BINARY) {
+        result.push_str(&format!(" {byte:02X}"));
+    }
+    if buffer.len() > MAX_DISPLAY_BINARY {
+        result.push_str(" ...");
+    }
+    result
+}
+
+/// Format each of the values in a multi-valued property, separated by commas.
+fn format_multiple<T>(values: &[T], format: impl Fn(&T) -> String) -> String {
+    values.iter().map(format).collect::<Vec<_>>().join(", ")
+}
+
+/// A human readable rendering of the value for debugging, e.g. in the examples. Times are shown
+/// in RFC 3339 (ISO 8601) format in UTC, GUIDs in registry format, binary values as their length
+/// and a truncated hex dump, and multi-valued properties as a comma-separated list. Use the
+/// [`Debug`] implementation to see the raw values.
+impl Display for PropertyValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PropertyValue::Null => write!(f, "null"),
+            PropertyValue::Integer16(value) => write!(f, "{value}"),
+            PropertyValue::Integer32(value) => write!(f, "{value}"),
+            PropertyValue::Floating32(value) => write!(f, "{value}"),
+            PropertyValue::Floating64(value) => write!(f, "{value}"),
+            PropertyValue::Currency(value) => write!(f, "{}", format_currency(*value)),
+            PropertyValue::FloatingTime(value) => write!(f, "{}", format_floating_time(*value)),
+            PropertyValue::ErrorCode(value) => write!(f, "0x{value:08X}"),
+            PropertyValue::Boolean(value) => write!(f, "{value}"),
+            PropertyValue::Integer64(value) => write!(f, "{value}"),
+            PropertyValue::String8(value) => write!(f, "{value}"),
+            PropertyValue::Unicode(value) => write!(f, "{value}"),
+            PropertyValue::Time(value) => write!(f, "{}", format_time(*value)),
+            PropertyValue::Guid(value) => write!(f, "{}", format_guid(value)),
+            PropertyValue::Binary(value) => write!(f, "{}", format_binary(value)),
+            PropertyValue::Object(value) => write!(
+                f,
+                "object 0x{:X} [{} bytes]",
+                u32::from(value.node()),
+                value.size()
+            ),
+            PropertyValue::MultipleInteger16(values) => {
+                write!(f, "{}", format_multiple(values, ToString::to_string))
+            }
+            PropertyValue::MultipleInteger32(values) => {
+                write!(f, "{}", format_multiple(values, ToString::to_string))
+            }
+            PropertyValue::MultipleFloating32(values) => {
+                write!(f, "{}", format_multiple(values, ToString::to_string))
+            }
+            PropertyValue::MultipleFloating64(values) => {
+                write!(f, "{}", format_multiple(values, ToString::to_string))
+            }
+            PropertyValue::MultipleCurrency(values) => {
+                write!(
+                    f,
+                    "{}",
+                    format_multiple(values, |value| format_currency(*value))
+                )
+            }
+            PropertyValue::MultipleFloatingTime(values) => write!(
+                f,
+                "{}",
+                format_multiple(values, |value| format_floating_time(*value))
+            ),
+            PropertyValue::MultipleInteger64(values) => {
+                write!(f, "{}", format_multiple(values, ToString::to_string))
+            }
+            PropertyValue::MultipleString8(values) => {
+                write!(f, "{}", format_multiple(values, ToString::to_string))
+            }
+            PropertyValue::MultipleUnicode(values) => {
+                write!(f, "{}", format_multiple(values, ToString::to_string))
+            }
+            PropertyValue::MultipleTime(values) => {
+                write!(
+                    f,
+                    "{}",
+                    format_multiple(values, |value| format_time(*value))
+                )
+            }
+            PropertyValue::MultipleGuid(values) => {
+                write!(f, "{}", format_multiple(values, format_guid))
+            }
+            PropertyValue::MultipleBinary(values) => {
+                write!(f, "{}", format_multiple(values, format_binary))
+            }
+            PropertyValue::Unknown(prop_type, value) => {
+                write!(f, "unknown 0x{prop_type:04X} {}", format_binary(value))
+            }
+        }
+    }
 }
 
 impl From<&PropertyValue> for PropertyType {
@@ -472,10 +829,256 @@ impl From<&PropertyValue> for PropertyType {
             PropertyValue::MultipleTime(_) => PropertyType::MultipleTime,
             PropertyValue::MultipleGuid(_) => PropertyType::MultipleGuid,
             PropertyValue::MultipleBinary(_) => PropertyType::MultipleBinary,
+            PropertyValue::Unknown(prop_type, _) => PropertyType::Unknown(*prop_type),
+        }
+    }
+}
+
+impl PropertyValue {
+    pub fn prop_type(&self) -> PropertyType {
+        PropertyType::from(self)
+    }
+
+    /// The value of a `PtypBoolean` property, or `None` for any other type.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            PropertyValue::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// Convert a [`PropertyValue`] to a native Rust type. Conversions are strict, so a value with a
+/// different [`PropertyType`] fails with [`LtpError::PropertyTypeMismatch`].
+pub trait FromProperty: Sized {
+    fn from_value(value: PropertyValue) -> LtpResult<Self>;
+
+    /// Convert a value from an object which encodes `PtypString8` values with the Windows code
+    /// page `codepage`. Only [`String`] needs it, every other type ignores it and uses
+    /// [`FromProperty::from_value`].
+    fn from_value_in_codepage(value: PropertyValue, _codepage: u32) -> LtpResult<Self> {
+        Self::from_value(value)
+    }
+}
+
+/// Read typed values from a map of properties, such as the properties of a folder or message.
+pub trait TypedProperties {
+    /// Get the raw value of a property, or `None` if the property is not set.
+    fn property_value(&self, id: u16) -> Option<&PropertyValue>;
+
+    /// Get a property converted to `T`, or `None` if the property is not set. `PtypString8`
+    /// values are decoded with [`Self::codepage`].
+    fn get_typed<T: FromProperty>(&self, id: u16) -> LtpResult<Option<T>> {
+        self.property_value(id)
+            .cloned()
+            .map(|value| T::from_value_in_codepage(value, self.codepage()))
+            .transpose()
+    }
+
+    /// Get a property converted to `T`, or `default` if the property is not set.
+    fn get_typed_or<T: FromProperty>(&self, id: u16, default: T) -> LtpResult<T> {
+        Ok(self.get_typed(id)?.unwrap_or(default))
+    }
+
+    /// The Windows code page which `PtypString8` values in these properties are encoded with.
+    /// Objects which don't know any better use [`DEFAULT_CODEPAGE`].
+    fn codepage(&self) -> u32 {
+        DEFAULT_CODEPAGE
+    }
+
+    /// Get a string property, decoding `PtypString8` values with [`Self::codepage`], or `None` if
+    /// the property is not set. An unsupported code page fails with
+    /// [`LtpError::UnsupportedCodePage`], see [`decode_string8`].
+    fn get_string8(&self, id: u16) -> LtpResult<Option<String>> {
+        self.get_typed::<String>(id)
+    }
+}
+
+impl FromProperty for PropertyValue {
+    fn from_value(value: PropertyValue) -> LtpResult<Self> {
+        Ok(value)
+    }
+}
+
+/// Both `PtypString8` and `PtypString` values convert to a [`String`]. Without a code page, a
+/// `PtypString8` value can only be converted if it is ASCII, otherwise it fails with
+/// [`LtpError::String8CodePageRequired`]. With one, it is decoded with [`decode_string8`].
+impl FromProperty for String {
+    fn from_value(value: PropertyValue) -> LtpResult<Self> {
+        match value {
+            PropertyValue::String8(value) if value.buffer().is_ascii() => {
+                Ok(decode_latin1(value.buffer()))
+            }
+            PropertyValue::String8(_) => Err(LtpError::String8CodePageRequired),
+            PropertyValue::Unicode(value) => Ok(value.to_string()),
+            invalid => Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Unicode,
+                actual: PropertyType::from(&invalid),
+            }),
+        }
+    }
+
+    fn from_value_in_codepage(value: PropertyValue, codepage: u32) -> LtpResult<Self> {
+        match value {
+            PropertyValue::String8(value) => decode_string8(value.buffer(), codepage),
+            value => Self::from_value(value),
+        }
+    }
+}
+
+impl FromProperty for i16 {
+    fn from_value(value: PropertyValue) -> LtpResult<Self> {
+        match value {
+            PropertyValue::Integer16(value) => Ok(value),
+            invalid => Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Integer16,
+                actual: PropertyType::from(&invalid),
+            }),
+        }
+    }
+}
+
+impl FromProperty for i32 {
+    fn from_value(value: PropertyValue) -> LtpResult<Self> {
+        match value {
+            PropertyValue::Integer32(value) => Ok(value),
+            invalid => Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Integer32,
+                actual: PropertyType::from(&invalid),
+            }),
+        }
+    }
+}
+
+impl FromProperty for i64 {
+    fn from_value(value: PropertyValue) -> LtpResult<Self> {
+        match value {
+            PropertyValue::Integer64(value) => Ok(value),
+            invalid => Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Integer64,
+                actual: PropertyType::from(&invalid),
+            }),
+        }
+    }
+}
+
+impl FromProperty for f64 {
+    fn from_value(value: PropertyValue) -> LtpResult<Self> {
+        match value {
+            PropertyValue::Floating64(value) => Ok(value),
+            invalid => Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Floating64,
+                actual: PropertyType::from(&invalid),
+            }),
         }
     }
 }
 
+impl FromProperty for bool {
+    fn from_value(value: PropertyValue) -> LtpResult<Self> {
+        match value {
+            PropertyValue::Boolean(value) => Ok(value),
+            invalid => Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Boolean,
+                actual: PropertyType::from(&invalid),
+            }),
+        }
+    }
+}
+
+impl FromProperty for Vec<u8> {
+    fn from_value(value: PropertyValue) -> LtpResult<Self> {
+        match value {
+            PropertyValue::Binary(value) => Ok(value.buffer().to_vec()),
+            invalid => Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Binary,
+                actual: PropertyType::from(&invalid),
+            }),
+        }
+    }
+}
+
+/// `PtypTime` values are stored as a `FILETIME`. A value of `0`, or one which does not fit in a
+/// [`SystemTime`] on this platform, fails with [`LtpError::InvalidTimeValue`].
+impl FromProperty for SystemTime {
+    fn from_value(value: PropertyValue) -> LtpResult<Self> {
+        match value {
+            PropertyValue::Time(value) => u64::try_from(value)
+                .ok()
+                .and_then(filetime_to_system_time)
+                .ok_or(LtpError::InvalidTimeValue(value)),
+            invalid => Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Time,
+                actual: PropertyType::from(&invalid),
+            }),
+        }
+    }
+}
+
+impl TryFrom<PropertyValue> for String {
+    type Error = LtpError;
+
+    fn try_from(value: PropertyValue) -> Result<Self, Self::Error> {
+        Self::from_value(value)
+    }
+}
+
+impl TryFrom<PropertyValue> for i16 {
+    type Error = LtpError;
+
+    fn try_from(value: PropertyValue) -> Result<Self, Self::Error> {
+        Self::from_value(value)
+    }
+}
+
+impl TryFrom<PropertyValue> for i32 {
+    type Error = LtpError;
+
+    fn try_from(value: PropertyValue) -> Result<Self, Self::Error> {
+        Self::from_value(value)
+    }
+}
+
+impl TryFrom<PropertyValue> for i64 {
+    type Error = LtpError;
+
+    fn try_from(value: PropertyValue) -> Result<Self, Self::Error> {
+        Self::from_value(value)
+    }
+}
+
+impl TryFrom<PropertyValue> for f64 {
+    type Error = LtpError;
+
+    fn try_from(value: PropertyValue) -> Result<Self, Self::Error> {
+        Self::from_value(value)
+    }
+}
+
+impl TryFrom<PropertyValue> for bool {
+    type Error = LtpError;
+
+    fn try_from(value: PropertyValue) -> Result<Self, Self::Error> {
+        Self::from_value(value)
+    }
+}
+
+impl TryFrom<PropertyValue> for Vec<u8> {
+    type Error = LtpError;
+
+    fn try_from(value: PropertyValue) -> Result<Self, Self::Error> {
+        Self::from_value(value)
+    }
+}
+
+impl TryFrom<PropertyValue> for SystemTime {
+    type Error = LtpError;
+
+    fn try_from(value: PropertyValue) -> Result<Self, Self::Error> {
+        Self::from_value(value)
+    }
+}
+
 impl PropertyValueReadWrite for PropertyValue {
     fn read(f: &mut dyn Read, prop_type: PropertyType) -> io::Result<Self> {
         match prop_type {
@@ -758,7 +1361,12 @@ impl PropertyValueReadWrite for PropertyValue {
                         if next > start {
                             let mut buffer = vec![0; next - start];
                             start = next;
-                            f.read_exact(&mut buffer)?;
+                            f.read_exact(&mut buffer).map_err(|err| match err.kind() {
+                                io::ErrorKind::UnexpectedEof => {
+                                    LtpError::InvalidMultiValuePropertyOffset(next).into()
+                                }
+                                _ => err,
+                            })?;
                             buffer
                         } else {
                             Default::default()
@@ -775,6 +1383,12 @@ impl PropertyValueReadWrite for PropertyValue {
                 Ok(Self::MultipleBinary(values))
             }
 
+            PropertyType::Unknown(prop_type) => {
+                let mut buffer = Vec::new();
+                f.read_to_end(&mut buffer)?;
+                Ok(Self::Unknown(prop_type, BinaryValue { buffer }))
+            }
+
             _ => Err(LtpError::InvalidVariableLengthPropertyType(prop_type).into()),
         }
     }
@@ -809,6 +1423,8 @@ impl PropertyValueReadWrite for PropertyValue {
 
             Self::Binary(value) => f.write_all(value.buffer()),
 
+            Self::Unknown(_, value) => f.write_all(value.buffer()),
+
             Self::Object(value) => {
                 value.node_id.write(f)?;
                 f.write_u32::<LittleEndian>(value.size)
@@ -958,9 +1574,98 @@ impl PropertyValueReadWrite for PropertyValue {
 
 pub type PropertyTree = dyn HeapTree<Key = PropertyTreeRecordKey, Value = PropertyTreeRecordValue>;
 
-pub trait PropertyContext {
+pub trait PropertyContext: Send + Sync {
     fn tree(&self) -> &PropertyTree;
     fn properties(&self) -> io::Result<BTreeMap<PropertyTreeRecordKey, PropertyTreeRecordValue>>;
+
+    /// Iterate over the ID of every property in ascending order, without reading any values.
+    fn property_ids(
+        &self,
+    ) -> io::Result<btree_map::IntoKeys<PropertyTreeRecordKey, PropertyTreeRecordValue>> {
+        Ok(self.properties()?.into_keys())
+    }
+
+    /// Count the properties without reading the records, see [`HeapTree::entry_count`].
+    fn property_count(&self) -> io::Result<usize> {
+        self.tree().entry_count()
+    }
+}
+
+/// Iterator over every property in a [`PropertyContext`], in property ID order. Each value is
+/// only read from the heap or sub-node when the iterator reaches it, and [`Iterator::nth`] (and
+/// therefore [`Iterator::skip`]) passes over properties without reading their values at all.
+pub struct PropertyContextIter<'a, Pst, Context, R>
+where
+    Pst: PstFile,
+    Context: PropertyContextReadWrite<Pst>,
+    R: PstReader,
+{
+    context: &'a Context,
+    f: &'a mut R,
+    encoding: NdbCryptMethod,
+    block_btree: &'a PstFileReadWriteBlockBTree<Pst>,
+    page_cache: &'a mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+    records: btree_map::IntoIter<PropertyTreeRecordKey, PropertyTreeRecordValue>,
+}
+
+impl<'a, Pst, Context, R> PropertyContextIter<'a, Pst, Context, R>
+where
+    Pst: PstFile,
+    Context: PropertyContextReadWrite<Pst>,
+    R: PstReader,
+{
+    pub(crate) fn new(
+        context: &'a Context,
+        f: &'a mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &'a PstFileReadWriteBlockBTree<Pst>,
+        page_cache: &'a mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+    ) -> io::Result<Self> {
+        let records = context.properties()?.into_iter();
+        Ok(Self {
+            context,
+            f,
+            encoding,
+            block_btree,
+            page_cache,
+            records,
+        })
+    }
+}
+
+impl<Pst, Context, R> Iterator for PropertyContextIter<'_, Pst, Context, R>
+where
+    Pst: PstFile,
+    Context: PropertyContextReadWrite<Pst>,
+    R: PstReader,
+{
+    type Item = io::Result<(u16, PropertyType, PropertyValue)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (prop_id, record) = self.records.next()?;
+        Some(
+            self.context
+                .read_property(
+                    self.f,
+                    self.encoding,
+                    self.block_btree,
+                    self.page_cache,
+                    record,
+                )
+                .map(|value| (prop_id, record.prop_type(), value)),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.records.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n > 0 {
+            self.records.nth(n - 1)?;
+        }
+        self.next()
+    }
 }
 
 struct PropertyContextInner<Pst>
@@ -969,7 +1674,7 @@ where
 {
     node: <Pst as PstFile>::NodeBTreeEntry,
     tree: <Pst as PstFile>::PropertyTree,
-    block_cache: RefCell<DataBlockCache<Pst>>,
+    block_cache: Mutex<DataBlockCache<Pst>>,
 }
 
 impl<Pst> PropertyContextInner<Pst>
@@ -1054,7 +1759,10 @@ where
                 let sub_node_tree = SubNodeTree::<Pst>::read(f, &block)?;
                 let block = sub_node_tree.find_entry(f, block_btree, sub_node_id, page_cache)?;
                 let block = block_btree.find_entry(f, block.search_key(), page_cache)?;
-                let mut block_cache = self.block_cache.borrow_mut();
+                let mut block_cache = self
+                    .block_cache
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
                 let data_tree = match block_cache.remove(&block.block().block()) {
                     Some(data_tree) => data_tree,
                     None => DataTree::read(f, encoding, &block)?,
@@ -1068,11 +1776,41 @@ where
                 let mut cursor = Cursor::new(data);
                 PropertyValueReadWrite::read(&mut cursor, value.prop_type())
             }
-            small => small
-                .small_value(value.prop_type())
-                .ok_or(LtpError::InvalidSmallPropertyType(value.prop_type()).into()),
+            small => Ok(small.small_value(value.prop_type())?),
         }
     }
+
+    fn set_property(&self, pst: &mut Pst, prop_id: u16, value: &PropertyValue) -> io::Result<()> {
+        let prop_type = value.prop_type();
+        let record = PropertyValueRecord::from_small_value(value)?;
+        pst.update_node_data(self.node.node(), |blocks| {
+            let (block_index, range) = heap_tree_record_range(blocks, prop_id)?
+                .ok_or(LtpError::PropertyNotFound(prop_id))?;
+            let data = &mut blocks[block_index][range];
+
+            // Replacing a value in the heap or a sub-node would leave the old allocation behind.
+            let existing = PropertyTreeRecordValue::read(&mut &data[..])?;
+            if !matches!(existing.value(), PropertyValueRecord::Small(_)) {
+                return Err(LtpError::InvalidSmallPropertyType(existing.prop_type()).into());
+            }
+            if existing.prop_type() != prop_type {
+                return Err(LtpError::PropertyTypeMismatch {
+                    expected: existing.prop_type(),
+                    actual: prop_type,
+                }
+                .into());
+            }
+
+            let mut updated = Vec::with_capacity(data.len());
+            PropertyTreeRecordValue::new(prop_type, record).write(&mut updated)?;
+            if updated == data {
+                return Ok(false);
+            }
+            data.copy_from_slice(&updated);
+            Ok(true)
+        })?;
+        Ok(())
+    }
 }
 
 pub struct UnicodePropertyContext {
@@ -1104,6 +1842,144 @@ impl UnicodePropertyContext {
             value,
         )
     }
+
+    /// Read a `PtypString` or `PtypString8` property, or `None` if it is not set. Values stored
+    /// inline in the property tree, in the heap, or in a sub-node are all read the same way, and
+    /// any other property type fails with [`LtpError::PropertyTypeMismatch`]. Errors reading the
+    /// file are returned as [`LtpError::Io`]. A `PtypString8` value outside of ASCII needs a code
+    /// page, see [`String::from_value`](FromProperty::from_value).
+    pub fn get_string<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &UnicodeBlockBTree,
+        page_cache: &mut RootBTreePageCache<UnicodeBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<String>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Read a `PtypInteger16` property, see [`Self::get_string`]. The value is stored inline in the
+    /// low 2 bytes of the property tree record.
+    pub fn get_i16<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &UnicodeBlockBTree,
+        page_cache: &mut RootBTreePageCache<UnicodeBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<i16>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+    /// Read a `PtypInteger32` property, see [`Self::get_string`].
+    pub fn get_i32<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &UnicodeBlockBTree,
+        page_cache: &mut RootBTreePageCache<UnicodeBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<i32>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Read a `PtypInteger64` property, see [`Self::get_string`]. The value is stored in the heap, or
+    /// in a sub-node if the HNID is a sub-node NID.
+    pub fn get_i64<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &UnicodeBlockBTree,
+        page_cache: &mut RootBTreePageCache<UnicodeBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<i64>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Read a `PtypBoolean` property, see [`Self::get_string`].
+    pub fn get_bool<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &UnicodeBlockBTree,
+        page_cache: &mut RootBTreePageCache<UnicodeBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<bool>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Read a `PtypBinary` property, see [`Self::get_string`].
+    pub fn get_binary<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &UnicodeBlockBTree,
+        page_cache: &mut RootBTreePageCache<UnicodeBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<Vec<u8>>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Read a `PtypTime` property, see [`Self::get_string`].
+    pub fn get_time<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &UnicodeBlockBTree,
+        page_cache: &mut RootBTreePageCache<UnicodeBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<SystemTime>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Iterate over every property in ID order, reading each value as the iterator reaches it.
+    /// Properties with an unknown type code are returned as [`PropertyValue::Unknown`].
+    pub fn iter<'a, R: PstReader>(
+        &'a self,
+        f: &'a mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &'a UnicodeBlockBTree,
+        page_cache: &'a mut RootBTreePageCache<UnicodeBlockBTree>,
+    ) -> io::Result<PropertyContextIter<'a, UnicodePstFile, Self, R>> {
+        <Self as PropertyContextReadWrite<UnicodePstFile>>::iter(
+            self,
+            f,
+            encoding,
+            block_btree,
+            page_cache,
+        )
+    }
+
+    /// Read every property into a map keyed by property ID, see [`Self::iter`].
+    pub fn to_map<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &UnicodeBlockBTree,
+        page_cache: &mut RootBTreePageCache<UnicodeBlockBTree>,
+    ) -> io::Result<BTreeMap<u16, PropertyValue>> {
+        <Self as PropertyContextReadWrite<UnicodePstFile>>::to_map(
+            self,
+            f,
+            encoding,
+            block_btree,
+            page_cache,
+        )
+    }
+
+    /// Replace the value of an existing property which is stored inline in the property tree,
+    /// i.e. a `PtypNull`, `PtypInteger16`, `PtypInteger32`, `PtypFloating32`, `PtypErrorCode`,
+    /// or `PtypBoolean` value, with another value of the same type. A different type fails with
+    /// [`LtpError::PropertyTypeMismatch`]. The heap block holding the record is written to a new
+    /// block, so read this context again to see the new value.
+    pub fn set_property(
+        &self,
+        pst: &mut UnicodePstFile,
+        prop_id: u16,
+        value: &PropertyValue,
+    ) -> io::Result<()> {
+        <Self as PropertyContextReadWrite<UnicodePstFile>>::set_property(self, pst, prop_id, value)
+    }
 }
 
 impl PropertyContext for UnicodePropertyContext {
@@ -1133,6 +2009,15 @@ impl PropertyContextReadWrite<UnicodePstFile> for UnicodePropertyContext {
         self.inner
             .read_property(f, encoding, block_btree, page_cache, value)
     }
+
+    fn set_property(
+        &self,
+        pst: &mut UnicodePstFile,
+        prop_id: u16,
+        value: &PropertyValue,
+    ) -> io::Result<()> {
+        self.inner.set_property(pst, prop_id, value)
+    }
 }
 
 pub struct AnsiPropertyContext {
@@ -1161,6 +2046,144 @@ impl AnsiPropertyContext {
             value,
         )
     }
+
+    /// Read a `PtypString` or `PtypString8` property, or `None` if it is not set. Values stored
+    /// inline in the property tree, in the heap, or in a sub-node are all read the same way, and
+    /// any other property type fails with [`LtpError::PropertyTypeMismatch`]. Errors reading the
+    /// file are returned as [`LtpError::Io`]. A `PtypString8` value outside of ASCII needs a code
+    /// page, see [`String::from_value`](FromProperty::from_value).
+    pub fn get_string<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &AnsiBlockBTree,
+        page_cache: &mut RootBTreePageCache<AnsiBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<String>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Read a `PtypInteger16` property, see [`Self::get_string`]. The value is stored inline in the
+    /// low 2 bytes of the property tree record.
+    pub fn get_i16<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &AnsiBlockBTree,
+        page_cache: &mut RootBTreePageCache<AnsiBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<i16>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+    /// Read a `PtypInteger32` property, see [`Self::get_string`].
+    pub fn get_i32<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &AnsiBlockBTree,
+        page_cache: &mut RootBTreePageCache<AnsiBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<i32>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Read a `PtypInteger64` property, see [`Self::get_string`]. The value is stored in the heap, or
+    /// in a sub-node if the HNID is a sub-node NID.
+    pub fn get_i64<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &AnsiBlockBTree,
+        page_cache: &mut RootBTreePageCache<AnsiBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<i64>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Read a `PtypBoolean` property, see [`Self::get_string`].
+    pub fn get_bool<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &AnsiBlockBTree,
+        page_cache: &mut RootBTreePageCache<AnsiBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<bool>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Read a `PtypBinary` property, see [`Self::get_string`].
+    pub fn get_binary<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &AnsiBlockBTree,
+        page_cache: &mut RootBTreePageCache<AnsiBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<Vec<u8>>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Read a `PtypTime` property, see [`Self::get_string`].
+    pub fn get_time<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &AnsiBlockBTree,
+        page_cache: &mut RootBTreePageCache<AnsiBlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<SystemTime>> {
+        self.read_typed_property(f, encoding, block_btree, page_cache, prop_id)
+    }
+
+    /// Iterate over every property in ID order, reading each value as the iterator reaches it.
+    /// Properties with an unknown type code are returned as [`PropertyValue::Unknown`].
+    pub fn iter<'a, R: PstReader>(
+        &'a self,
+        f: &'a mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &'a AnsiBlockBTree,
+        page_cache: &'a mut RootBTreePageCache<AnsiBlockBTree>,
+    ) -> io::Result<PropertyContextIter<'a, AnsiPstFile, Self, R>> {
+        <Self as PropertyContextReadWrite<AnsiPstFile>>::iter(
+            self,
+            f,
+            encoding,
+            block_btree,
+            page_cache,
+        )
+    }
+
+    /// Read every property into a map keyed by property ID, see [`Self::iter`].
+    pub fn to_map<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &AnsiBlockBTree,
+        page_cache: &mut RootBTreePageCache<AnsiBlockBTree>,
+    ) -> io::Result<BTreeMap<u16, PropertyValue>> {
+        <Self as PropertyContextReadWrite<AnsiPstFile>>::to_map(
+            self,
+            f,
+            encoding,
+            block_btree,
+            page_cache,
+        )
+    }
+
+    /// Replace the value of an existing property which is stored inline in the property tree,
+    /// i.e. a `PtypNull`, `PtypInteger16`, `PtypInteger32`, `PtypFloating32`, `PtypErrorCode`,
+    /// or `PtypBoolean` value, with another value of the same type. A different type fails with
+    /// [`LtpError::PropertyTypeMismatch`]. The heap block holding the record is written to a new
+    /// block, so read this context again to see the new value.
+    pub fn set_property(
+        &self,
+        pst: &mut AnsiPstFile,
+        prop_id: u16,
+        value: &PropertyValue,
+    ) -> io::Result<()> {
+        <Self as PropertyContextReadWrite<AnsiPstFile>>::set_property(self, pst, prop_id, value)
+    }
 }
 
 impl PropertyContext for AnsiPropertyContext {
@@ -1190,4 +2213,880 @@ impl PropertyContextReadWrite<AnsiPstFile> for AnsiPropertyContext {
         self.inner
             .read_property(f, encoding, block_btree, page_cache, value)
     }
+
+    fn set_property(
+        &self,
+        pst: &mut AnsiPstFile,
+        prop_id: u16,
+        value: &PropertyValue,
+    ) -> io::Result<()> {
+        self.inner.set_property(pst, prop_id, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        messaging::property_ids::*,
+        ndb::{
+            byte_index::ByteIndex,
+            header::Header,
+            node_id::{NID_MESSAGE_STORE, NID_ROOT_FOLDER},
+            page::UnicodeNodeBTree,
+            root::{AmapStatus, Root},
+        },
+        test_util::{message_node, TempFile, EMPTY_PST, FIRST_MESSAGE_INDEX},
+        PstFileLock,
+    };
+
+    /// `PtypMultipleBinary` value with 2 entries: `[0x01, 0x02, 0x03]` and `[0x04, 0x05]`.
+    const MULTIPLE_BINARY: [u8; 17] = [
+        0x02, 0x00, 0x00, 0x00, // ulCount
+        0x0C, 0x00, 0x00, 0x00, // rgulDataOffsets[0]
+        0x0F, 0x00, 0x00, 0x00, // rgulDataOffsets[1]
+        0x01, 0x02, 0x03, // rgDataItems[0]
+        0x04, 0x05, // rgDataItems[1]
+    ];
+
+    #[test]
+    fn test_from_property_string() {
+        let value = PropertyValue::Unicode(UnicodeValue {
+            buffer: "Inbox".encode_utf16().collect(),
+        });
+        assert_eq!(String::from_value(value).unwrap(), "Inbox");
+
+        let value = PropertyValue::String8(String8Value {
+            buffer: b"Outbox".to_vec(),
+        });
+        assert_eq!(String::from_value(value).unwrap(), "Outbox");
+
+        // Anything outside of ASCII needs the code page.
+        let value = PropertyValue::String8(String8Value {
+            buffer: b"\xC4\xE5\xEB\xE0".to_vec(),
+        });
+        assert!(matches!(
+            String::from_value(value.clone()),
+            Err(LtpError::String8CodePageRequired)
+        ));
+        assert!(matches!(
+            String::from_value_in_codepage(value.clone(), 12345),
+            Err(LtpError::UnsupportedCodePage(12345))
+        ));
+        #[cfg(feature = "codepage")]
+        assert_eq!(
+            String::from_value_in_codepage(value.clone(), 1251).unwrap(),
+            "Дела"
+        );
+        assert_eq!(value.to_string(), "\\xC4\\xE5\\xEB\\xE0");
+    }
+
+    #[test]
+    fn test_from_property_mismatch() {
+        let Err(LtpError::PropertyTypeMismatch { expected, actual }) =
+            i32::from_value(PropertyValue::Boolean(true))
+        else {
+            panic!("Expected LtpError::PropertyTypeMismatch");
+        };
+        assert_eq!(expected, PropertyType::Integer32);
+        assert_eq!(actual, PropertyType::Boolean);
+
+        assert!(bool::from_value(PropertyValue::Integer32(1)).is_err());
+        assert!(i64::from_value(PropertyValue::Integer32(1)).is_err());
+    }
+
+    #[test]
+    fn test_from_property_values() {
+        assert_eq!(i32::from_value(PropertyValue::Integer32(-7)).unwrap(), -7);
+        assert_eq!(
+            i64::from_value(PropertyValue::Integer64(1 << 40)).unwrap(),
+            1 << 40
+        );
+        assert_eq!(
+            f64::from_value(PropertyValue::Floating64(0.5)).unwrap(),
+            0.5
+        );
+        assert!(bool::from_value(PropertyValue::Boolean(true)).unwrap());
+
+        let value = PropertyValue::Binary(BinaryValue::new(vec![0x01, 0x02]));
+        assert_eq!(Vec::<u8>::from_value(value).unwrap(), [0x01, 0x02]);
+    }
+
+    #[test]
+    fn test_from_property_time() {
+        // 2024-01-01T00:00:00Z
+        let value = PropertyValue::Time(133_485_408_000_000_000);
+        let time = SystemTime::from_value(value).unwrap();
+        let since_epoch = time.duration_since(std::time::UNIX_EPOCH).unwrap();
+        assert_eq!(since_epoch.as_secs(), 1_704_067_200);
+
+        assert!(matches!(
+            SystemTime::from_value(PropertyValue::Time(0)),
+            Err(LtpError::InvalidTimeValue(0))
+        ));
+    }
+
+    #[test]
+    fn test_read_multiple_binary() {
+        let mut cursor = Cursor::new(MULTIPLE_BINARY);
+        let value = <PropertyValue as PropertyValueReadWrite>::read(
+            &mut cursor,
+            PropertyType::MultipleBinary,
+        )
+        .unwrap();
+        let PropertyValue::MultipleBinary(values) = value else {
+            panic!("Expected PropertyValue::MultipleBinary: {value:?}");
+        };
+        let values: Vec<_> = values.iter().map(BinaryValue::buffer).collect();
+        assert_eq!(values, [&[0x01, 0x02, 0x03][..], &[0x04, 0x05][..]]);
+    }
+
+    #[test]
+    fn test_write_multiple_binary() {
+        let value = PropertyValue::MultipleBinary(vec![
+            BinaryValue::new(vec![0x01, 0x02, 0x03]),
+            BinaryValue::new(vec![0x04, 0x05]),
+        ]);
+        let mut buffer = Vec::new();
+        value.write(&mut buffer).unwrap();
+        assert_eq!(buffer, MULTIPLE_BINARY);
+    }
+
+    #[test]
+    fn test_multiple_binary_unsorted_offsets() {
+        let mut data = MULTIPLE_BINARY;
+        data[8] = 0x0B;
+        let mut cursor = Cursor::new(data);
+        let err = <PropertyValue as PropertyValueReadWrite>::read(
+            &mut cursor,
+            PropertyType::MultipleBinary,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_multiple_binary_offset_out_of_bounds() {
+        let mut data = MULTIPLE_BINARY;
+        data[8] = 0x20;
+        let mut cursor = Cursor::new(data);
+        let err = <PropertyValue as PropertyValueReadWrite>::read(
+            &mut cursor,
+            PropertyType::MultipleBinary,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1601-01-01T00:00:00Z");
+        assert_eq!(format_time(116_444_736_000_000_000), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_time(133_485_408_001_234_567),
+            "2024-01-01T00:00:00.1234567Z"
+        );
+    }
+
+    #[test]
+    fn test_display_property_value() {
+        assert_eq!(
+            PropertyValue::Time(116_444_736_000_000_000).to_string(),
+            "1970-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            PropertyValue::FloatingTime(25_569.5).to_string(),
+            "1970-01-01T12:00:00Z"
+        );
+        assert_eq!(PropertyValue::Currency(-12_345).to_string(), "-1.2345");
+        assert_eq!(
+            PropertyValue::ErrorCode(0x8004010F_u32 as i32).to_string(),
+            "0x8004010F"
+        );
+        assert_eq!(
+            PropertyValue::Guid(GuidValue::new(
+                0x00062002,
+                0x0000,
+                0x0000,
+                [0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+            ))
+            .to_string(),
+            "{00062002-0000-0000-C000-000000000046}"
+        );
+        assert_eq!(
+            PropertyValue::Binary(BinaryValue::new(vec![0x01, 0xAB])).to_string(),
+            "[2 bytes] 01 AB"
+        );
+        assert_eq!(
+            PropertyValue::Binary(BinaryValue::default()).to_string(),
+            "[0 bytes]"
+        );
+
+        let long = PropertyValue::Binary(BinaryValue::new(vec![0xFF; MAX_DISPLAY_BINARY + 1]));
+        let long = long.to_string();
+        assert!(long.starts_with("[33 bytes] FF FF"));
+        assert!(long.ends_with("FF ..."));
+
+        assert_eq!(
+            PropertyValue::MultipleInteger32(vec![1, 2, 3]).to_string(),
+            "1, 2, 3"
+        );
+        assert_eq!(
+            PropertyValue::MultipleUnicode(vec![
+                UnicodeValue::new("a".encode_utf16().collect()),
+                UnicodeValue::new("b".encode_utf16().collect()),
+            ])
+            .to_string(),
+            "a, b"
+        );
+
+        // Debug keeps the raw value.
+        assert_eq!(
+            format!("{:?}", PropertyValue::Time(116_444_736_000_000_000)),
+            "Time(116444736000000000)"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_time() {
+        let value = PropertyValue::Time(116_444_736_000_000_000);
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, r#"{"Time":"1970-01-01T00:00:00Z"}"#);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_binary() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"f"), "Zg==");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(encode_base64(b"foo"), "Zm9v");
+        assert_eq!(encode_base64(b"foobar"), "Zm9vYmFy");
+
+        let value = PropertyValue::Binary(BinaryValue::new(vec![0x00, 0xFF, 0x10, 0x80]));
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, r#"{"Binary":"AP8QgA=="}"#);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_scalar_round_trip() {
+        let values = [
+            PropertyValue::Null,
+            PropertyValue::Integer16(-2),
+            PropertyValue::Integer32(0x0E08),
+            PropertyValue::Floating64(1.5),
+            PropertyValue::Currency(12_345),
+            PropertyValue::ErrorCode(-2_147_221_233),
+            PropertyValue::Boolean(true),
+            PropertyValue::Integer64(i64::MAX),
+            PropertyValue::String8(String8Value {
+                buffer: b"Inbox".to_vec(),
+            }),
+            PropertyValue::Unicode(UnicodeValue {
+                buffer: "Sent Items".encode_utf16().collect(),
+            }),
+        ];
+
+        for value in values {
+            let json = serde_json::to_string(&value).unwrap();
+            let round_trip: PropertyValue = serde_json::from_str(&json).unwrap();
+            assert_eq!(format!("{value:?}"), format!("{round_trip:?}"));
+        }
+    }
+
+    #[test]
+    fn test_property_context_getters() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let header = pst.header();
+        let encoding = header.crypt_method();
+        let mut file = pst.reader().lock().unwrap();
+        let file = &mut *file;
+        let node_btree = UnicodeNodeBTree::read(file, *header.root().node_btree()).unwrap();
+        let block_btree = UnicodeBlockBTree::read(file, *header.root().block_btree()).unwrap();
+        let mut page_cache = pst.block_cache();
+
+        let mut read_context = |node: NodeId| {
+            let node = node_btree
+                .find_entry(file, u64::from(u32::from(node)), &mut pst.node_cache())
+                .unwrap();
+            let heap = UnicodeHeapNode::read(
+                file,
+                &block_btree,
+                &mut page_cache,
+                encoding,
+                node.data().search_key(),
+            )
+            .unwrap();
+            let user_root = heap.header().unwrap().user_root();
+            let tree = <UnicodePstFile as PstFile>::PropertyTree::new(heap, user_root);
+            UnicodePropertyContext::new(node, tree)
+        };
+        let store = read_context(NID_MESSAGE_STORE);
+        let root_folder = read_context(NID_ROOT_FOLDER);
+        let mut page_cache = pst.block_cache();
+
+        // PidTagDisplayName and PidTagRecordKey are stored in the heap.
+        let display_name = store
+            .get_string(
+                file,
+                encoding,
+                &block_btree,
+                &mut page_cache,
+                PR_DISPLAY_NAME,
+            )
+            .unwrap()
+            .unwrap();
+        assert!(!display_name.is_empty());
+        let record_key = store
+            .get_binary(file, encoding, &block_btree, &mut page_cache, PR_RECORD_KEY)
+            .unwrap()
+            .unwrap();
+        assert_eq!(record_key.len(), 16);
+
+        // PidTagContentCount and PidTagSubfolders are stored inline in the property tree.
+        let content_count = root_folder
+            .get_i32(
+                file,
+                encoding,
+                &block_btree,
+                &mut page_cache,
+                PR_CONTENT_COUNT,
+            )
+            .unwrap();
+        assert_eq!(content_count, Some(0));
+        let subfolders = root_folder
+            .get_bool(file, encoding, &block_btree, &mut page_cache, PR_SUBFOLDERS)
+            .unwrap();
+        assert_eq!(subfolders, Some(true));
+
+        assert!(root_folder
+            .get_time(file, encoding, &block_btree, &mut page_cache, 0x7FFF)
+            .unwrap()
+            .is_none());
+
+        let err = store
+            .get_i32(
+                file,
+                encoding,
+                &block_btree,
+                &mut page_cache,
+                PR_DISPLAY_NAME,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Integer32,
+                actual: PropertyType::Unicode,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_boolean_small_value() {
+        let boolean = |value: u32| {
+            let mut record = vec![];
+            record.extend_from_slice(&u16::from(PropertyType::Boolean).to_le_bytes());
+            record.extend_from_slice(&value.to_le_bytes());
+            let record = PropertyTreeRecordValue::read(&mut Cursor::new(record)).unwrap();
+            record.value().small_value(record.prop_type())
+        };
+
+        assert_eq!(boolean(0).unwrap().as_bool(), Some(false));
+        assert_eq!(boolean(1).unwrap().as_bool(), Some(true));
+        // Only the low byte of dwValueHnid holds the value.
+        assert_eq!(boolean(0xABCD_EF01).unwrap().as_bool(), Some(true));
+        assert!(matches!(
+            boolean(2),
+            Err(LtpError::InvalidPropertyBooleanValue(0x02))
+        ));
+        assert!(matches!(
+            boolean(0xFF),
+            Err(LtpError::InvalidPropertyBooleanValue(0xFF))
+        ));
+
+        assert_eq!(PropertyValue::Integer32(1).as_bool(), None);
+    }
+
+    #[test]
+    fn test_integer_records() {
+        let record = |prop_type: PropertyType, value: u32| {
+            let mut record = vec![];
+            record.extend_from_slice(&u16::from(prop_type).to_le_bytes());
+            record.extend_from_slice(&value.to_le_bytes());
+            PropertyTreeRecordValue::read(&mut Cursor::new(record)).unwrap()
+        };
+
+        // PtypInteger16 is stored in the low 2 bytes of dwValueHnid.
+        for value in [0x0000_FFFE, 0xABCD_FFFE] {
+            let small = record(PropertyType::Integer16, value);
+            assert!(matches!(small.value(), PropertyValueRecord::Small(0xFFFE)));
+            assert!(matches!(
+                small.value().small_value(small.prop_type()),
+                Ok(PropertyValue::Integer16(-2))
+            ));
+        }
+
+        // PtypInteger64 is an HNID, either in the heap or in a sub-node.
+        let heap = record(PropertyType::Integer64, 0x0000_0020);
+        assert!(
+            matches!(heap.value(), PropertyValueRecord::Heap(heap_id) if u32::from(heap_id) == 0x20)
+        );
+        let sub_node_id = NodeId::new(NodeIdType::ListsTablesProperties, 1).unwrap();
+        let sub_node = record(PropertyType::Integer64, u32::from(sub_node_id));
+        assert!(matches!(sub_node.value(), PropertyValueRecord::Node(node) if node == sub_node_id));
+        let null = record(PropertyType::Integer64, 0);
+        assert!(
+            matches!(null.value(), PropertyValueRecord::Heap(heap_id) if u32::from(heap_id) == 0)
+        );
+
+        // Only values which are not stored inline are rejected by small_value.
+        assert!(matches!(
+            PropertyValueRecord::Small(0x2A5C).small_value(PropertyType::Integer64),
+            Err(LtpError::InvalidSmallPropertyType(PropertyType::Integer64))
+        ));
+        assert!(matches!(
+            PropertyValueRecord::Small(0x2A5C).small_value(PropertyType::Integer32),
+            Ok(PropertyValue::Integer32(0x2A5C))
+        ));
+    }
+
+    #[test]
+    fn test_read_integer_properties() {
+        use crate::{
+            ltp::{
+                heap::{HeapNodeEditor, HeapNodeType},
+                tree::allocate_heap_tree,
+            },
+            ndb::block::UnicodeDataTree,
+            upgrade::*,
+        };
+
+        const MESSAGE_SIZE: i32 = 0x2A5C;
+        const MESSAGE_SIZE_EXTENDED: i64 = 0x1_0000_2A5C;
+
+        let path = TempFile::new("integer_properties");
+        let message = NodeId::new(NodeIdType::NormalMessage, FIRST_MESSAGE_INDEX).unwrap();
+        let sub_node = NodeId::new(NodeIdType::ListsTablesProperties, 1).unwrap();
+        {
+            let max_data_size = UnicodeDataTree::max_data_size();
+            let mut heap = HeapNodeEditor::new(HeapNodeType::Properties, max_data_size);
+            let heap_value = heap
+                .allocate(MESSAGE_SIZE_EXTENDED.to_le_bytes().to_vec())
+                .unwrap();
+            let properties = [
+                (
+                    PR_MESSAGE_SIZE,
+                    PropertyType::Integer32,
+                    PropertyValueRecord::Small(MESSAGE_SIZE as u32),
+                ),
+                (
+                    0x8000,
+                    PropertyType::Integer64,
+                    PropertyValueRecord::Heap(heap_value),
+                ),
+                (
+                    0x8001,
+                    PropertyType::Integer64,
+                    PropertyValueRecord::Node(sub_node),
+                ),
+                (
+                    0x8002,
+                    PropertyType::Integer16,
+                    PropertyValueRecord::Small(u32::from(-2_i16 as u16)),
+                ),
+            ];
+            let records: Vec<_> = properties
+                .into_iter()
+                .map(|(prop_id, prop_type, value)| {
+                    let mut record = Vec::with_capacity(8);
+                    prop_id.write(&mut record).unwrap();
+                    PropertyTreeRecordValue::new(prop_type, value)
+                        .write(&mut record)
+                        .unwrap();
+                    record
+                })
+                .collect();
+            let user_root = allocate_heap_tree(
+                &mut heap,
+                <PropertyTreeRecordKey as HeapTreeEntryKey>::SIZE,
+                <PropertyTreeRecordValue as HeapTreeEntryValue>::SIZE,
+                &records,
+            )
+            .unwrap();
+            heap.set_user_root(user_root);
+
+            let empty = UnicodePstFile::open(EMPTY_PST).unwrap();
+            let mut reader = NodeReader::new(&empty).unwrap();
+            let mut writer =
+                NodeWriter::<UnicodePstFile>::create(&path, empty.header().crypt_method()).unwrap();
+            for node in reader.nodes() {
+                writer.write_node(&reader.read_node(node).unwrap()).unwrap();
+            }
+            writer
+                .write_node(&NodeContents {
+                    node: message,
+                    parent: None,
+                    data: heap.write().unwrap(),
+                    sub_nodes: vec![NodeContents {
+                        node: sub_node,
+                        parent: None,
+                        data: split_data(&(-MESSAGE_SIZE_EXTENDED).to_le_bytes(), max_data_size),
+                        sub_nodes: Default::default(),
+                    }],
+                })
+                .unwrap();
+            let mut next_node_ids = reader.next_node_ids();
+            next_node_ids[NodeIdType::NormalMessage as usize] = FIRST_MESSAGE_INDEX + 1;
+            writer.finish(&next_node_ids).unwrap();
+        }
+
+        {
+            let pst = UnicodePstFile::open(&path).unwrap();
+            let (context, message_size) = read_i32_property(&pst, message, PR_MESSAGE_SIZE);
+            assert_eq!(message_size, Some(MESSAGE_SIZE));
+
+            let header = pst.header();
+            let encoding = header.crypt_method();
+            let mut file = pst.reader().lock().unwrap();
+            let file = &mut *file;
+            let block_btree = UnicodeBlockBTree::read(file, *header.root().block_btree()).unwrap();
+            let mut page_cache = pst.block_cache();
+            assert_eq!(
+                context
+                    .get_i64(file, encoding, &block_btree, &mut page_cache, 0x8000)
+                    .unwrap(),
+                Some(MESSAGE_SIZE_EXTENDED)
+            );
+            assert_eq!(
+                context
+                    .get_i64(file, encoding, &block_btree, &mut page_cache, 0x8001)
+                    .unwrap(),
+                Some(-MESSAGE_SIZE_EXTENDED)
+            );
+            assert_eq!(
+                context
+                    .get_i16(file, encoding, &block_btree, &mut page_cache, 0x8002)
+                    .unwrap(),
+                Some(-2)
+            );
+            assert!(context
+                .get_i16(
+                    file,
+                    encoding,
+                    &block_btree,
+                    &mut page_cache,
+                    PR_MESSAGE_SIZE
+                )
+                .is_err());
+        }
+    }
+
+    /// Read the PC for `node` and the current value of a `PtypInteger32` property.
+    fn read_i32_property(
+        pst: &UnicodePstFile,
+        node: NodeId,
+        prop_id: u16,
+    ) -> (UnicodePropertyContext, Option<i32>) {
+        let header = pst.header();
+        let encoding = header.crypt_method();
+        let mut file = pst.reader().lock().unwrap();
+        let file = &mut *file;
+        let node_btree = UnicodeNodeBTree::read(file, *header.root().node_btree()).unwrap();
+        let block_btree = UnicodeBlockBTree::read(file, *header.root().block_btree()).unwrap();
+        let mut page_cache = pst.block_cache();
+
+        let node = node_btree
+            .find_entry(file, u64::from(u32::from(node)), &mut pst.node_cache())
+            .unwrap();
+        let heap = UnicodeHeapNode::read(
+            file,
+            &block_btree,
+            &mut page_cache,
+            encoding,
+            node.data().search_key(),
+        )
+        .unwrap();
+        let user_root = heap.header().unwrap().user_root();
+        let tree = <UnicodePstFile as PstFile>::PropertyTree::new(heap, user_root);
+        let context = UnicodePropertyContext::new(node, tree);
+        let value = context
+            .get_i32(file, encoding, &block_btree, &mut page_cache, prop_id)
+            .unwrap();
+        (context, value)
+    }
+
+    #[test]
+    fn test_set_property() {
+        use crate::upgrade::*;
+
+        let path = TempFile::new("set_property");
+        let message = NodeId::new(NodeIdType::NormalMessage, FIRST_MESSAGE_INDEX).unwrap();
+        {
+            let empty = UnicodePstFile::open(EMPTY_PST).unwrap();
+            let mut reader = NodeReader::new(&empty).unwrap();
+            let mut writer =
+                NodeWriter::<UnicodePstFile>::create(&path, empty.header().crypt_method()).unwrap();
+            for node in reader.nodes() {
+                writer.write_node(&reader.read_node(node).unwrap()).unwrap();
+            }
+            writer
+                .write_node(&message_node(message, "Subject", "Body").unwrap())
+                .unwrap();
+            let mut next_node_ids = reader.next_node_ids();
+            next_node_ids[NodeIdType::NormalMessage as usize] = FIRST_MESSAGE_INDEX + 1;
+            writer.finish(&next_node_ids).unwrap();
+        }
+
+        let old_block = {
+            let mut pst = UnicodePstFile::open(&path).unwrap();
+            let (context, flags) = read_i32_property(&pst, message, PR_MESSAGE_FLAGS);
+            assert_eq!(flags, Some(0));
+
+            // Only values which fit in the record can be set.
+            let err = context
+                .set_property(
+                    &mut pst,
+                    PR_MESSAGE_FLAGS,
+                    &PropertyValue::Integer64(i64::from(MSGFLAG_READ)),
+                )
+                .unwrap_err();
+            let err = err.into_inner().unwrap().downcast::<LtpError>().unwrap();
+            assert!(matches!(
+                *err,
+                LtpError::InvalidSmallPropertyType(PropertyType::Integer64)
+            ));
+            let err = context
+                .set_property(&mut pst, PR_MESSAGE_FLAGS, &PropertyValue::Boolean(true))
+                .unwrap_err();
+            let err = err.into_inner().unwrap().downcast::<LtpError>().unwrap();
+            assert!(matches!(
+                *err,
+                LtpError::PropertyTypeMismatch {
+                    expected: PropertyType::Integer32,
+                    actual: PropertyType::Boolean,
+                }
+            ));
+            let err = context
+                .set_property(
+                    &mut pst,
+                    PR_SUBJECT,
+                    &PropertyValue::Integer32(MSGFLAG_READ),
+                )
+                .unwrap_err();
+            let err = err.into_inner().unwrap().downcast::<LtpError>().unwrap();
+            assert!(matches!(
+                *err,
+                LtpError::InvalidSmallPropertyType(PropertyType::Unicode)
+            ));
+
+            let old_block = pst.read_node(message).unwrap().data();
+            context
+                .set_property(
+                    &mut pst,
+                    PR_MESSAGE_FLAGS,
+                    &PropertyValue::Integer32(MSGFLAG_READ),
+                )
+                .unwrap();
+            old_block
+        };
+
+        let pst = UnicodePstFile::open(&path).unwrap();
+        let (_, flags) = read_i32_property(&pst, message, PR_MESSAGE_FLAGS);
+        assert_eq!(flags, Some(MSGFLAG_READ));
+
+        // The node points at a new block, the old one is gone, and the AMap still adds up.
+        let new_block = pst.read_node(message).unwrap().data();
+        assert_ne!(new_block, old_block);
+        assert!(pst.read_block(old_block).is_err());
+        let root = pst.header().root();
+        assert_eq!(root.amap_is_valid(), AmapStatus::Valid2);
+        assert_eq!(
+            pst.recompute_free_sizes().unwrap(),
+            (root.amap_free_size().index(), root.pmap_free_size().index())
+        );
+
+        drop(pst);
+    }
+
+    #[test]
+    fn test_unknown_property_type() {
+        let mut cursor = Cursor::new([0x34, 0x12, 0x78, 0x56, 0x34, 0x12]);
+        let record = PropertyTreeRecordValue::read(&mut cursor).unwrap();
+        assert_eq!(record.prop_type(), PropertyType::Unknown(0x1234));
+        assert_eq!(u16::from(record.prop_type()), 0x1234);
+
+        let value = record.value().small_value(record.prop_type()).unwrap();
+        let PropertyValue::Unknown(prop_type, buffer) = &value else {
+            panic!("expected an unknown property value");
+        };
+        assert_eq!(*prop_type, 0x1234);
+        assert_eq!(buffer.buffer(), &[0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(PropertyType::from(&value), PropertyType::Unknown(0x1234));
+    }
+
+    #[test]
+    fn test_property_context_iter() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let header = pst.header();
+        let encoding = header.crypt_method();
+        let mut file = pst.reader().lock().unwrap();
+        let file = &mut *file;
+        let node_btree = UnicodeNodeBTree::read(file, *header.root().node_btree()).unwrap();
+        let block_btree = UnicodeBlockBTree::read(file, *header.root().block_btree()).unwrap();
+        let mut page_cache = pst.block_cache();
+
+        let node = node_btree
+            .find_entry(
+                file,
+                u64::from(u32::from(NID_MESSAGE_STORE)),
+                &mut pst.node_cache(),
+            )
+            .unwrap();
+        let heap = UnicodeHeapNode::read(
+            file,
+            &block_btree,
+            &mut page_cache,
+            encoding,
+            node.data().search_key(),
+        )
+        .unwrap();
+        let user_root = heap.header().unwrap().user_root();
+        let tree = <UnicodePstFile as PstFile>::PropertyTree::new(heap, user_root);
+        let store = UnicodePropertyContext::new(node, tree);
+
+        let entries = store
+            .iter(file, encoding, &block_btree, &mut page_cache)
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert!(entries.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(entries.iter().all(|(_, prop_type, value)| {
+            *prop_type == PropertyType::from(value) || matches!(value, PropertyValue::Null)
+        }));
+
+        let map = store
+            .to_map(file, encoding, &block_btree, &mut page_cache)
+            .unwrap();
+        assert_eq!(map.len(), entries.len());
+        for prop_id in [PR_DISPLAY_NAME, PR_RECORD_KEY] {
+            assert!(map.contains_key(&prop_id), "missing 0x{prop_id:04X}");
+        }
+        assert!(matches!(
+            map.get(&PR_DISPLAY_NAME),
+            Some(PropertyValue::Unicode(_))
+        ));
+
+        let mut iter = store
+            .iter(file, encoding, &block_btree, &mut page_cache)
+            .unwrap();
+        let (prop_id, _, _) = iter.nth(1).unwrap().unwrap();
+        assert_eq!(prop_id, entries[1].0);
+        assert_eq!(
+            iter.size_hint(),
+            (entries.len() - 2, Some(entries.len() - 2))
+        );
+    }
+
+    #[test]
+    fn test_property_ids() {
+        use crate::messaging::edit::{edit_store, PropertyData};
+
+        const PROP_ID: u16 = 0x6700;
+
+        let path = TempFile::copy_of(EMPTY_PST, "property_ids").unwrap();
+        let read_ids = |path: &std::path::Path| {
+            let pst = UnicodePstFile::open(path).unwrap();
+            let (context, _) = read_i32_property(&pst, NID_ROOT_FOLDER, PR_CONTENT_COUNT);
+            let ids: Vec<_> = context.property_ids().unwrap().collect();
+            assert_eq!(context.property_count().unwrap(), ids.len());
+            assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+            ids
+        };
+
+        let before = read_ids(&path);
+        assert!(before.contains(&PR_CONTENT_COUNT));
+        assert!(!before.contains(&PROP_ID));
+
+        edit_store(&mut UnicodePstFile::open(&path).unwrap(), |editor| {
+            editor.folder(NID_ROOT_FOLDER)?.set(
+                PROP_ID,
+                PropertyType::Integer32,
+                PropertyData::Small(1),
+            );
+            Ok(())
+        })
+        .unwrap();
+        let added = read_ids(&path);
+        assert_eq!(added.len(), before.len() + 1);
+        assert!(added.contains(&PROP_ID));
+
+        edit_store(&mut UnicodePstFile::open(&path).unwrap(), |editor| {
+            editor.folder(NID_ROOT_FOLDER)?.properties.remove(&PROP_ID);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(read_ids(&path), before);
+    }
+
+    #[test]
+    fn test_property_type_mapping() {
+        let codes = [
+            0x0001, 0x0002, 0x0003, 0x0004, 0x0005, 0x0006, 0x0007, 0x000A, 0x000B, 0x000D, 0x0014,
+            0x001E, 0x001F, 0x0040, 0x0048, 0x0102, 0x1002, 0x1003, 0x1004, 0x1005, 0x1006, 0x1007,
+            0x1014, 0x101E, 0x101F, 0x1040, 0x1048, 0x1102,
+        ];
+
+        for code in codes {
+            let prop_type = PropertyType::try_from(code).unwrap();
+            assert_eq!(u16::from(prop_type), code);
+
+            let mut record = Cursor::new([0_u8; 6]);
+            record.get_mut()[..2].copy_from_slice(&code.to_le_bytes());
+            let record = PropertyTreeRecordValue::read(&mut record).unwrap();
+            let value = match record.value() {
+                PropertyValueRecord::Small(_) => record.value().small_value(prop_type).unwrap(),
+                _ => PropertyValue::read(&mut Cursor::new([0_u8; 16]), prop_type).unwrap(),
+            };
+            assert_eq!(value.prop_type(), prop_type, "0x{code:04X}");
+        }
+
+        // PtypUnspecified is not a valid PropertyType, so it is kept as an unknown type code.
+        assert!(matches!(
+            PropertyType::try_from(0x0000),
+            Err(LtpError::InvalidPropertyType(0x0000))
+        ));
+        assert_eq!(
+            PropertyType::from_raw(0x0000),
+            PropertyType::Unknown(0x0000)
+        );
+        let value =
+            PropertyValue::read(&mut Cursor::new([1, 2]), PropertyType::Unknown(0x0000)).unwrap();
+        assert_eq!(value.prop_type(), PropertyType::Unknown(0x0000));
+    }
+
+    #[test]
+    fn test_try_from_property_value() {
+        assert_eq!(i32::try_from(PropertyValue::Integer32(7)).unwrap(), 7);
+        assert_eq!(
+            Vec::<u8>::try_from(PropertyValue::Binary(BinaryValue::new(vec![1, 2]))).unwrap(),
+            vec![1, 2]
+        );
+        assert!(matches!(
+            String::try_from(PropertyValue::Integer32(7)),
+            Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Unicode,
+                actual: PropertyType::Integer32,
+            })
+        ));
+        assert_eq!(
+            SystemTime::try_from(PropertyValue::Time(116_444_736_000_000_000)).unwrap(),
+            SystemTime::UNIX_EPOCH
+        );
+
+        let value: crate::ltp::Value = PropertyValue::Boolean(true);
+        assert!(matches!(value, crate::ltp::Value::Boolean(true)));
+    }
 }
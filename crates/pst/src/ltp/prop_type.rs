@@ -1,6 +1,7 @@
 //! ## [Data Types](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/1d61ee78-4466-4141-8276-f45153484619)
 
 use std::fmt::Debug;
+use tracing::warn;
 
 use super::*;
 
@@ -86,6 +87,18 @@ pub enum PropertyType {
     /// `PtypMultipleBinary`: Variable size; a COUNT field followed by that many
     /// [PropertyType::Binary] values.
     MultipleBinary = 0x1102,
+
+    /// A property type code which is not listed in MS-PST. Property contexts keep these records
+    /// instead of failing to read the rest of the node, see [`PropertyType::from_raw`].
+    Unknown(u16),
+}
+
+impl PropertyType {
+    /// Map a raw property type code to a [`PropertyType`], using [`PropertyType::Unknown`] for
+    /// any code which [`PropertyType::try_from`] rejects.
+    pub fn from_raw(value: u16) -> Self {
+        Self::try_from(value).unwrap_or(Self::Unknown(value))
+    }
 }
 
 impl TryFrom<u16> for PropertyType {
@@ -108,6 +121,7 @@ impl TryFrom<u16> for PropertyType {
             0x0040 => Ok(Self::Time),
             0x0048 => Ok(Self::Guid),
             0x0102 => Ok(Self::Binary),
+            0x000D => Ok(Self::Object),
 
             0x1002 => Ok(Self::MultipleInteger16),
             0x1003 => Ok(Self::MultipleInteger32),
@@ -129,6 +143,281 @@ impl TryFrom<u16> for PropertyType {
 
 impl From<PropertyType> for u16 {
     fn from(value: PropertyType) -> Self {
-        value as u16
+        match value {
+            PropertyType::Null => 0x0001,
+            PropertyType::Integer16 => 0x0002,
+            PropertyType::Integer32 => 0x0003,
+            PropertyType::Floating32 => 0x0004,
+            PropertyType::Floating64 => 0x0005,
+            PropertyType::Currency => 0x0006,
+            PropertyType::FloatingTime => 0x0007,
+            PropertyType::ErrorCode => 0x000A,
+            PropertyType::Boolean => 0x000B,
+            PropertyType::Integer64 => 0x0014,
+            PropertyType::String8 => 0x001E,
+            PropertyType::Unicode => 0x001F,
+            PropertyType::Time => 0x0040,
+            PropertyType::Guid => 0x0048,
+            PropertyType::Binary => 0x0102,
+            PropertyType::Object => 0x000D,
+            PropertyType::MultipleInteger16 => 0x1002,
+            PropertyType::MultipleInteger32 => 0x1003,
+            PropertyType::MultipleFloating32 => 0x1004,
+            PropertyType::MultipleFloating64 => 0x1005,
+            PropertyType::MultipleCurrency => 0x1006,
+            PropertyType::MultipleFloatingTime => 0x1007,
+            PropertyType::MultipleInteger64 => 0x1014,
+            PropertyType::MultipleString8 => 0x101E,
+            PropertyType::MultipleUnicode => 0x101F,
+            PropertyType::MultipleTime => 0x1040,
+            PropertyType::MultipleGuid => 0x1048,
+            PropertyType::MultipleBinary => 0x1102,
+            PropertyType::Unknown(value) => value,
+        }
+    }
+}
+
+/// Map a Windows code page identifier to the matching [`encoding_rs::Encoding`].
+#[cfg(feature = "codepage")]
+fn code_page_encoding(codepage: u32) -> Option<&'static encoding_rs::Encoding> {
+    use encoding_rs::*;
+
+    Some(match codepage {
+        // encoding_rs has no OEM code pages besides 866, so 437 falls through to None rather
+        // than being decoded as Windows-1252, which has different characters above 0x7F.
+        1252 | 20127 | 28591 => WINDOWS_1252,
+        866 => IBM866,
+        874 => WINDOWS_874,
+        932 => SHIFT_JIS,
+        936 => GBK,
+        949 => EUC_KR,
+        950 => BIG5,
+        1200 => UTF_16LE,
+        1201 => UTF_16BE,
+        1250 => WINDOWS_1250,
+        1251 => WINDOWS_1251,
+        1253 => WINDOWS_1253,
+        1254 => WINDOWS_1254,
+        1255 => WINDOWS_1255,
+        1256 => WINDOWS_1256,
+        1257 => WINDOWS_1257,
+        1258 => WINDOWS_1258,
+        10000 => MACINTOSH,
+        20866 => KOI8_R,
+        21866 => KOI8_U,
+        28592 => ISO_8859_2,
+        28593 => ISO_8859_3,
+        28594 => ISO_8859_4,
+        28595 => ISO_8859_5,
+        28596 => ISO_8859_6,
+        28597 => ISO_8859_7,
+        28598 => ISO_8859_8,
+        28603 => ISO_8859_13,
+        28605 => ISO_8859_15,
+        50220..=50222 => ISO_2022_JP,
+        51932 => EUC_JP,
+        54936 => GB18030,
+        65001 => UTF_8,
+        _ => return None,
+    })
+}
+
+#[cfg(feature = "codepage")]
+fn decode_code_page(bytes: &[u8], codepage: u32) -> Option<String> {
+    let (value, _) = code_page_encoding(codepage)?.decode_without_bom_handling(bytes);
+    Some(value.into_owned())
+}
+
+/// Without `encoding_rs`, only US-ASCII, Latin-1 and UTF-8 can be decoded correctly. The other
+/// Windows code pages which extend ASCII can still decode text which is entirely ASCII.
+#[cfg(not(feature = "codepage"))]
+fn decode_code_page(bytes: &[u8], codepage: u32) -> Option<String> {
+    match codepage {
+        866
+        | 874
+        | 932
+        | 936
+        | 949
+        | 950
+        | 1250..=1258
+        | 10000
+        | 20866
+        | 21866
+        | 28592..=28598
+        | 28603
+        | 28605
+        | 51932
+        | 54936
+            if bytes.is_ascii() =>
+        {
+            Some(decode_latin1(bytes))
+        }
+        20127 => Some(
+            bytes
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii() {
+                        char::from(b)
+                    } else {
+                        '\u{FFFD}'
+                    }
+                })
+                .collect(),
+        ),
+        28591 => Some(decode_latin1(bytes)),
+        65001 => Some(String::from_utf8_lossy(bytes).into_owned()),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "codepage")]
+fn encode_code_page(value: &str, codepage: u32) -> Option<Vec<u8>> {
+    let (bytes, _, _) = code_page_encoding(codepage)?.encode(value);
+    Some(bytes.into_owned())
+}
+
+#[cfg(not(feature = "codepage"))]
+fn encode_code_page(value: &str, codepage: u32) -> Option<Vec<u8>> {
+    match codepage {
+        20127 => Some(encode_latin1(value, '\u{7F}')),
+        28591 => Some(encode_latin1(value, '\u{FF}')),
+        65001 => Some(value.as_bytes().to_vec()),
+        _ => None,
+    }
+}
+
+/// Windows-1252, the code page for `PtypString8` values when neither the object nor its store
+/// names one.
+pub const DEFAULT_CODEPAGE: u32 = 1252;
+
+/// Decode a `PtypString8` value which was encoded with the Windows code page `codepage`. Any
+/// bytes which are not valid in that code page are replaced with `U+FFFD`. Most code pages need
+/// the `codepage` feature, without it only US-ASCII, Latin-1 and UTF-8 are supported.
+pub fn decode_string8(bytes: &[u8], codepage: u32) -> LtpResult<String> {
+    decode_code_page(bytes, codepage).ok_or(LtpError::UnsupportedCodePage(codepage))
+}
+
+/// Decode a `PtypString8` value like [`decode_string8`], but fall back to Latin-1 if `codepage`
+/// is not supported instead of failing. The second value is `true` if it used the fallback, in
+/// which case any characters outside of ASCII may be wrong.
+pub fn decode_string8_lossy(bytes: &[u8], codepage: u32) -> (String, bool) {
+    match decode_code_page(bytes, codepage) {
+        Some(value) => (value, false),
+        None => (decode_latin1(bytes), true),
+    }
+}
+
+/// Decode a `PtypString8` value with [`decode_string8_lossy`], and log a warning if it had to
+/// fall back to Latin-1.
+pub(crate) fn decode_string8_or_latin1(bytes: &[u8], codepage: u32) -> String {
+    let (value, lossy) = decode_string8_lossy(bytes, codepage);
+    if lossy {
+        warn!(
+            name: "PstString8CodePageFallback",
+            codepage,
+            "Unsupported code page, decoded the string as Latin-1"
+        );
+    }
+    value
+}
+
+/// Map each byte to the Unicode code point with the same value, which is the ISO-8859-1 decoding.
+pub(crate) fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().copied().map(char::from).collect()
+}
+
+/// Map each character up to `max` to the byte with the same value, and replace the rest with
+/// HTML numeric character references like [`encode_string8`].
+pub(crate) fn encode_latin1(value: &str, max: char) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(value.len());
+    for ch in value.chars() {
+        if ch <= max {
+            bytes.push(ch as u8);
+        } else {
+            bytes.extend_from_slice(format!("&#{};", u32::from(ch)).as_bytes());
+        }
+    }
+    bytes
+}
+
+/// Encode a `PtypString8` value with the Windows code page `codepage`. Any characters which
+/// cannot be represented in that code page are replaced with HTML numeric character references.
+/// The same code pages are supported as for [`decode_string8`].
+pub fn encode_string8(value: &str, codepage: u32) -> LtpResult<Vec<u8>> {
+    encode_code_page(value, codepage).ok_or(LtpError::UnsupportedCodePage(codepage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "codepage")]
+    #[test]
+    fn test_decode_string8_french() {
+        // "Élève à Noël" in Windows-1252
+        let bytes = b"\xC9l\xE8ve \xE0 No\xEBl";
+        assert_eq!(decode_string8(bytes, 1252).unwrap(), "Élève à Noël");
+    }
+
+    #[cfg(feature = "codepage")]
+    #[test]
+    fn test_decode_string8_japanese() {
+        // "日本語" in Shift_JIS
+        let bytes = [0x93, 0xFA, 0x96, 0x7B, 0x8C, 0xEA];
+        assert_eq!(decode_string8(&bytes, 932).unwrap(), "日本語");
+    }
+
+    #[cfg(feature = "codepage")]
+    #[test]
+    fn test_encode_string8_round_trip() {
+        let bytes = encode_string8("Élève à Noël", 1252).unwrap();
+        assert_eq!(bytes, b"\xC9l\xE8ve \xE0 No\xEBl");
+        assert_eq!(decode_string8(&bytes, 1252).unwrap(), "Élève à Noël");
+
+        let bytes = encode_string8("日本語", 932).unwrap();
+        assert_eq!(bytes, [0x93, 0xFA, 0x96, 0x7B, 0x8C, 0xEA]);
+    }
+
+    #[test]
+    fn test_decode_string8_unsupported() {
+        assert!(matches!(
+            decode_string8(b"test", 12345),
+            Err(LtpError::UnsupportedCodePage(12345))
+        ));
+    }
+
+    #[test]
+    fn test_decode_string8_cp437() {
+        // "Ç" is 0x80 in CP437, but "€" in Windows-1252.
+        assert!(matches!(
+            decode_string8(b"\x80a", 437),
+            Err(LtpError::UnsupportedCodePage(437))
+        ));
+        assert_eq!(
+            decode_string8_lossy(b"\x80a", 437),
+            ("\u{80}a".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn test_decode_string8_lossy() {
+        let bytes = b"\xC9l\xE8ve \xE0 No\xEBl";
+        #[cfg(feature = "codepage")]
+        assert_eq!(
+            decode_string8_lossy(bytes, 1252),
+            ("Élève à Noël".to_string(), false)
+        );
+        #[cfg(not(feature = "codepage"))]
+        assert_eq!(
+            decode_string8_lossy(bytes, 1252),
+            ("Élève à Noël".to_string(), true)
+        );
+
+        // "Αγάπη" in Windows-1253 is garbled by the Latin-1 fallback, but ASCII survives.
+        let bytes = b"<p>\xC1\xE3\xDC\xF0\xE7</p>";
+        assert_eq!(
+            decode_string8_lossy(bytes, 12345),
+            ("<p>ÁãÜðç</p>".to_string(), true)
+        );
     }
 }
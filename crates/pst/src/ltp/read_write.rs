@@ -1,6 +1,9 @@
 #![allow(dead_code)]
 
-use std::io::{self, Read, Write};
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+};
 
 use super::{prop_type::*, table_context::*, *};
 use crate::*;
@@ -61,6 +64,56 @@ where
         page_cache: &mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
         value: PropertyTreeRecordValue,
     ) -> io::Result<PropertyValue>;
+
+    /// Replace the value of an existing property which is stored inline in the property tree,
+    /// i.e. a `PtypNull`, `PtypInteger16`, `PtypInteger32`, `PtypFloating32`, `PtypErrorCode`,
+    /// or `PtypBoolean` value, with another value of the same type. A different type fails with
+    /// [`LtpError::PropertyTypeMismatch`]. The heap block holding the record is written to a new
+    /// block with [`PstFile::update_node_data`]. This context still holds the old heap, read it
+    /// again to see the new value.
+    fn set_property(&self, pst: &mut Pst, prop_id: u16, value: &PropertyValue) -> io::Result<()>;
+
+    /// Read a single property and convert it with [`FromProperty`], wherever it is stored.
+    fn read_typed_property<T: FromProperty, R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &PstFileReadWriteBlockBTree<Pst>,
+        page_cache: &mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+        prop_id: u16,
+    ) -> LtpResult<Option<T>> {
+        let Some(record) = self.properties()?.remove(&prop_id) else {
+            return Ok(None);
+        };
+        let value = self.read_property(f, encoding, block_btree, page_cache, record)?;
+        Ok(Some(T::from_value(value)?))
+    }
+
+    /// Iterate over every property in ID order, reading each value as the iterator reaches it.
+    /// Properties with a type code which MS-PST does not list are returned as
+    /// [`PropertyValue::Unknown`] rather than ending the iteration.
+    fn iter<'a, R: PstReader>(
+        &'a self,
+        f: &'a mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &'a PstFileReadWriteBlockBTree<Pst>,
+        page_cache: &'a mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+    ) -> io::Result<PropertyContextIter<'a, Pst, Self, R>> {
+        PropertyContextIter::new(self, f, encoding, block_btree, page_cache)
+    }
+
+    /// Read every property into a map keyed by property ID, see [`Self::iter`].
+    fn to_map<R: PstReader>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &PstFileReadWriteBlockBTree<Pst>,
+        page_cache: &mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+    ) -> io::Result<BTreeMap<u16, PropertyValue>> {
+        self.iter(f, encoding, block_btree, page_cache)?
+            .map(|entry| entry.map(|(prop_id, _, value)| (prop_id, value)))
+            .collect()
+    }
 }
 
 pub trait TableContextInfoReadWrite: Sized {
@@ -83,7 +136,7 @@ where
     Pst: PstFile,
 {
     fn read(
-        store: Rc<Pst::Store>,
+        store: Arc<Pst::Store>,
         node: <Pst as PstFile>::NodeBTreeEntry,
-    ) -> io::Result<Rc<dyn TableContext>>;
+    ) -> io::Result<Arc<dyn TableContext>>;
 }
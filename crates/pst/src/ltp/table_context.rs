@@ -2,13 +2,14 @@
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::{
-    cell::RefCell,
     collections::BTreeMap,
     fmt::Debug,
     io::{self, Cursor, Read, Write},
     marker::PhantomData,
-    rc::Rc,
+    ops::Range,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
 };
+use tracing::error;
 
 use super::{heap::*, prop_context::*, prop_type::*, read_write::*, tree::*, *};
 use crate::{
@@ -17,7 +18,7 @@ use crate::{
         store::{AnsiStore, UnicodeStore},
     },
     ndb::{
-        block::{Block, DataBlockCache, DataTree, IntermediateTreeBlock, SubNodeTree},
+        block::{DataBlockCache, DataTree, IntermediateTreeBlock, SubNodeTree, MAX_BLOCK_SIZE},
         block_id::BlockId,
         block_ref::BlockRef,
         header::Header,
@@ -46,7 +47,7 @@ pub const fn check_existence_bitmap(column: usize, existence_bitmap: &[u8]) -> L
 }
 
 /// [TCINFO](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/45b3a0c5-d6d6-4e02-aebf-13766ff693f0)
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
 pub struct TableContextInfo {
     end_4byte_values: u16,
     end_2byte_values: u16,
@@ -270,6 +271,14 @@ impl TableContextInfo {
         self.end_existence_bitmap
     }
 
+    pub fn row_index(&self) -> HeapId {
+        self.row_index
+    }
+
+    pub fn rows(&self) -> Option<NodeId> {
+        self.rows
+    }
+
     pub fn columns(&self) -> &[TableColumnDescriptor] {
         &self.columns
     }
@@ -362,7 +371,7 @@ impl TableContextInfoReadWrite for TableContextInfo {
 }
 
 /// [TCOLDESC](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/3a2f63cf-bb40-4559-910c-e55ec43d9cbb)
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
 pub struct TableColumnDescriptor {
     prop_type: PropertyType,
     prop_id: u16,
@@ -427,7 +436,7 @@ impl TableColumnDescriptorReadWrite for TableColumnDescriptor {
     }
 
     fn write(&self, f: &mut dyn Write) -> io::Result<()> {
-        f.write_u16::<LittleEndian>(self.prop_type as u16)?;
+        f.write_u16::<LittleEndian>(u16::from(self.prop_type))?;
         f.write_u16::<LittleEndian>(self.prop_id)?;
         f.write_u16::<LittleEndian>(self.offset)?;
         f.write_u8(self.size)?;
@@ -438,6 +447,7 @@ impl TableColumnDescriptorReadWrite for TableColumnDescriptor {
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(transparent))]
 pub struct TableRowId {
     id: u32,
 }
@@ -477,7 +487,7 @@ where
     type Index: Copy;
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
 pub struct UnicodeTableRowIndex {
     index: u32,
 }
@@ -507,7 +517,7 @@ impl HeapNodePageReadWrite for UnicodeTableRowIndex {
     }
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
 pub struct AnsiTableRowIndex {
     index: u16,
 }
@@ -551,7 +561,7 @@ pub type UnicodeTableRowIdRecord = HeapTreeLeafEntry<TableRowId, UnicodeTableRow
 /// [TCROWID](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/e20b5cf4-ea56-48b8-a8fa-e086c9b862ca)
 pub type AnsiTableRowIdRecord = HeapTreeLeafEntry<TableRowId, AnsiTableRowIndex>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum TableRowColumnValue {
     Small(PropertyValue),
     Heap(HeapId),
@@ -559,6 +569,7 @@ pub enum TableRowColumnValue {
 }
 
 /// [Row Data Format](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/c48fa6b4-bfd4-49d7-80f8-8718bc4bcddc)
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct TableRowData {
     id: TableRowId,
     unique: u32,
@@ -602,130 +613,131 @@ impl TableRowData {
         context
             .columns()
             .iter()
-            .map(|column| {
-                let existence_bit = column.existence_bitmap_index() as usize;
-                if !check_existence_bitmap(existence_bit, &self.existence_bitmap)? {
-                    return Ok(None);
-                }
+            .map(|column| self.column(context, column))
+            .collect()
+    }
 
-                match (column.prop_type(), column.offset(), column.size()) {
-                    (PropertyType::Null, _, 0) => Ok(None),
-                    (PropertyType::Integer16, offset, 2) => {
-                        let mut cursor = self.read_2byte_offset(context, offset)?;
-                        let value = cursor.read_i16::<LittleEndian>()?;
-                        Ok(Some(TableRowColumnValue::Small(PropertyValue::Integer16(
-                            value,
-                        ))))
-                    }
-                    (PropertyType::Integer32, 0, 4) => Ok(Some(TableRowColumnValue::Small(
-                        PropertyValue::Integer32(u32::from(self.id) as i32),
-                    ))),
-                    (PropertyType::Integer32, 4, 4) => Ok(Some(TableRowColumnValue::Small(
-                        PropertyValue::Integer32(self.unique as i32),
-                    ))),
-                    (PropertyType::Integer32, offset, 4) => {
-                        let mut cursor = self.read_4byte_offset(offset)?;
-                        let value = cursor.read_i32::<LittleEndian>()?;
-                        Ok(Some(TableRowColumnValue::Small(PropertyValue::Integer32(
-                            value,
-                        ))))
-                    }
-                    (PropertyType::Floating32, offset, 4) => {
-                        let mut cursor = self.read_4byte_offset(offset)?;
-                        let value = cursor.read_f32::<LittleEndian>()?;
-                        Ok(Some(TableRowColumnValue::Small(PropertyValue::Floating32(
-                            value,
-                        ))))
-                    }
-                    (PropertyType::Floating64, offset, 8) => {
-                        let mut cursor = self.read_8byte_offset(offset)?;
-                        let value = cursor.read_f64::<LittleEndian>()?;
-                        Ok(Some(TableRowColumnValue::Small(PropertyValue::Floating64(
-                            value,
-                        ))))
-                    }
-                    (PropertyType::Currency, offset, 8) => {
-                        let mut cursor = self.read_8byte_offset(offset)?;
-                        let value = cursor.read_i64::<LittleEndian>()?;
-                        Ok(Some(TableRowColumnValue::Small(PropertyValue::Currency(
-                            value,
-                        ))))
-                    }
-                    (PropertyType::FloatingTime, offset, 8) => {
-                        let mut cursor = self.read_8byte_offset(offset)?;
-                        let value = cursor.read_f64::<LittleEndian>()?;
-                        Ok(Some(TableRowColumnValue::Small(
-                            PropertyValue::FloatingTime(value),
-                        )))
-                    }
-                    (PropertyType::ErrorCode, offset, 4) => {
-                        let mut cursor = self.read_4byte_offset(offset)?;
-                        let value = cursor.read_i32::<LittleEndian>()?;
-                        Ok(Some(TableRowColumnValue::Small(PropertyValue::ErrorCode(
-                            value,
-                        ))))
-                    }
-                    (PropertyType::Boolean, offset, 1) => {
-                        let value = self.read_1byte_offset(context, offset)?;
-                        Ok(Some(TableRowColumnValue::Small(PropertyValue::Boolean(
-                            match value {
-                                0x00 => false,
-                                0x01 => true,
-                                _ => {
-                                    return Err(
-                                        LtpError::InvalidTableColumnBooleanValue(value).into()
-                                    )
-                                }
-                            },
-                        ))))
-                    }
-                    (PropertyType::Integer64, offset, 8) => {
-                        let mut cursor = self.read_8byte_offset(offset)?;
-                        let value = cursor.read_i64::<LittleEndian>()?;
-                        Ok(Some(TableRowColumnValue::Small(PropertyValue::Integer64(
-                            value,
-                        ))))
-                    }
-                    (PropertyType::Time, offset, 8) => {
-                        let mut cursor = self.read_8byte_offset(offset)?;
-                        let value = cursor.read_i64::<LittleEndian>()?;
-                        Ok(Some(TableRowColumnValue::Small(PropertyValue::Time(value))))
-                    }
-                    (
-                        PropertyType::String8
-                        | PropertyType::Unicode
-                        | PropertyType::Guid
-                        | PropertyType::Binary
-                        | PropertyType::Object
-                        | PropertyType::MultipleInteger16
-                        | PropertyType::MultipleInteger32
-                        | PropertyType::MultipleFloating32
-                        | PropertyType::MultipleFloating64
-                        | PropertyType::MultipleCurrency
-                        | PropertyType::MultipleFloatingTime
-                        | PropertyType::MultipleInteger64
-                        | PropertyType::MultipleString8
-                        | PropertyType::MultipleUnicode
-                        | PropertyType::MultipleTime
-                        | PropertyType::MultipleGuid
-                        | PropertyType::MultipleBinary,
-                        offset,
-                        4,
-                    ) => {
-                        let mut cursor = self.read_4byte_offset(offset)?;
-                        let node_id = NodeId::from(cursor.read_u32::<LittleEndian>()?);
-                        let value = match node_id.id_type() {
-                            Ok(NodeIdType::HeapNode) => {
-                                TableRowColumnValue::Heap(HeapId::from(u32::from(node_id)))
-                            }
-                            _ => TableRowColumnValue::Node(node_id),
-                        };
-                        Ok(Some(value))
+    /// Read a single column from this row, or `None` if its bit in the existence bitmap is clear.
+    pub fn column(
+        &self,
+        context: &TableContextInfo,
+        column: &TableColumnDescriptor,
+    ) -> io::Result<Option<TableRowColumnValue>> {
+        let existence_bit = column.existence_bitmap_index() as usize;
+        if !check_existence_bitmap(existence_bit, &self.existence_bitmap)? {
+            return Ok(None);
+        }
+
+        match (column.prop_type(), column.offset(), column.size()) {
+            (PropertyType::Null, _, 0) => Ok(None),
+            (PropertyType::Integer16, offset, 2) => {
+                let mut cursor = self.read_2byte_offset(context, offset)?;
+                let value = cursor.read_i16::<LittleEndian>()?;
+                Ok(Some(TableRowColumnValue::Small(PropertyValue::Integer16(
+                    value,
+                ))))
+            }
+            (PropertyType::Integer32, 0, 4) => Ok(Some(TableRowColumnValue::Small(
+                PropertyValue::Integer32(u32::from(self.id) as i32),
+            ))),
+            (PropertyType::Integer32, 4, 4) => Ok(Some(TableRowColumnValue::Small(
+                PropertyValue::Integer32(self.unique as i32),
+            ))),
+            (PropertyType::Integer32, offset, 4) => {
+                let mut cursor = self.read_4byte_offset(offset)?;
+                let value = cursor.read_i32::<LittleEndian>()?;
+                Ok(Some(TableRowColumnValue::Small(PropertyValue::Integer32(
+                    value,
+                ))))
+            }
+            (PropertyType::Floating32, offset, 4) => {
+                let mut cursor = self.read_4byte_offset(offset)?;
+                let value = cursor.read_f32::<LittleEndian>()?;
+                Ok(Some(TableRowColumnValue::Small(PropertyValue::Floating32(
+                    value,
+                ))))
+            }
+            (PropertyType::Floating64, offset, 8) => {
+                let mut cursor = self.read_8byte_offset(offset)?;
+                let value = cursor.read_f64::<LittleEndian>()?;
+                Ok(Some(TableRowColumnValue::Small(PropertyValue::Floating64(
+                    value,
+                ))))
+            }
+            (PropertyType::Currency, offset, 8) => {
+                let mut cursor = self.read_8byte_offset(offset)?;
+                let value = cursor.read_i64::<LittleEndian>()?;
+                Ok(Some(TableRowColumnValue::Small(PropertyValue::Currency(
+                    value,
+                ))))
+            }
+            (PropertyType::FloatingTime, offset, 8) => {
+                let mut cursor = self.read_8byte_offset(offset)?;
+                let value = cursor.read_f64::<LittleEndian>()?;
+                Ok(Some(TableRowColumnValue::Small(
+                    PropertyValue::FloatingTime(value),
+                )))
+            }
+            (PropertyType::ErrorCode, offset, 4) => {
+                let mut cursor = self.read_4byte_offset(offset)?;
+                let value = cursor.read_i32::<LittleEndian>()?;
+                Ok(Some(TableRowColumnValue::Small(PropertyValue::ErrorCode(
+                    value,
+                ))))
+            }
+            (PropertyType::Boolean, offset, 1) => {
+                let value = self.read_1byte_offset(context, offset)?;
+                let value =
+                    decode_boolean(value).ok_or(LtpError::InvalidTableColumnBooleanValue(value))?;
+                Ok(Some(TableRowColumnValue::Small(PropertyValue::Boolean(
+                    value,
+                ))))
+            }
+            (PropertyType::Integer64, offset, 8) => {
+                let mut cursor = self.read_8byte_offset(offset)?;
+                let value = cursor.read_i64::<LittleEndian>()?;
+                Ok(Some(TableRowColumnValue::Small(PropertyValue::Integer64(
+                    value,
+                ))))
+            }
+            (PropertyType::Time, offset, 8) => {
+                let mut cursor = self.read_8byte_offset(offset)?;
+                let value = cursor.read_i64::<LittleEndian>()?;
+                Ok(Some(TableRowColumnValue::Small(PropertyValue::Time(value))))
+            }
+            (
+                PropertyType::String8
+                | PropertyType::Unicode
+                | PropertyType::Guid
+                | PropertyType::Binary
+                | PropertyType::Object
+                | PropertyType::MultipleInteger16
+                | PropertyType::MultipleInteger32
+                | PropertyType::MultipleFloating32
+                | PropertyType::MultipleFloating64
+                | PropertyType::MultipleCurrency
+                | PropertyType::MultipleFloatingTime
+                | PropertyType::MultipleInteger64
+                | PropertyType::MultipleString8
+                | PropertyType::MultipleUnicode
+                | PropertyType::MultipleTime
+                | PropertyType::MultipleGuid
+                | PropertyType::MultipleBinary,
+                offset,
+                4,
+            ) => {
+                let mut cursor = self.read_4byte_offset(offset)?;
+                let node_id = NodeId::from(cursor.read_u32::<LittleEndian>()?);
+                let value = match node_id.id_type() {
+                    Ok(NodeIdType::HeapNode) => {
+                        TableRowColumnValue::Heap(HeapId::from(u32::from(node_id)))
                     }
-                    (_, _, size) => Err(LtpError::InvalidTableColumnSize(size).into()),
-                }
-            })
-            .collect()
+                    _ => TableRowColumnValue::Node(node_id),
+                };
+                Ok(Some(value))
+            }
+            (_, _, size) => Err(LtpError::InvalidTableColumnSize(size).into()),
+        }
     }
 
     fn read_1byte_offset(&self, context: &TableContextInfo, offset: u16) -> LtpResult<u8> {
@@ -819,15 +831,58 @@ impl TableRowReadWrite for TableRowData {
     }
 }
 
-pub trait TableContext {
+/// Get the range of Row Matrix blocks which contain the rows in `rows`, if every block except the
+/// last one holds `rows_per_block` rows.
+pub const fn row_matrix_blocks(rows: Range<usize>, rows_per_block: usize) -> Range<usize> {
+    if rows.start >= rows.end {
+        return 0..0;
+    }
+    (rows.start / rows_per_block)..((rows.end - 1) / rows_per_block + 1)
+}
+
+pub trait TableContext: Send + Sync {
     fn context(&self) -> &TableContextInfo;
-    fn rows_matrix<'a>(&'a self) -> Box<dyn 'a + Iterator<Item = &'a TableRowData>>;
+    /// The number of rows in the table, from the `TCROWID` BTH which is read along with the
+    /// `TCINFO`. This does not read the Row Matrix.
+    fn row_count(&self) -> usize;
+    /// Iterate over every row in the Row Matrix. The rows are all read the first time this or
+    /// [`TableContext::find_row`] is called, use [`TableContext::rows_iter`] or
+    /// [`TableContext::rows_range`] to read them on demand instead. If the Row Matrix cannot be
+    /// read, this and every later call return the error.
+    fn rows_matrix<'a>(&'a self) -> io::Result<Box<dyn 'a + Iterator<Item = &'a TableRowData>>>;
+    /// Lazily read the rows in the Row Matrix, one data block at a time.
+    fn rows_iter<'a>(&'a self) -> Box<dyn 'a + Iterator<Item = io::Result<TableRowData>>>;
+    /// Read up to `count` rows starting at row `start`, only reading the data blocks which
+    /// contain those rows.
+    fn rows_range(&self, start: usize, count: usize) -> io::Result<Vec<TableRowData>>;
     fn find_row(&self, id: TableRowId) -> LtpResult<&TableRowData>;
+    /// Look up a row by its ID in the `TCROWID` BTH and read only that row, without loading the
+    /// rest of the Row Matrix. Returns `None` if the table has no such row.
+    fn find_row_by_id(&self, id: TableRowId) -> io::Result<Option<TableRowData>>;
+    /// Read a single cell, reading only the row which contains it if the Row Matrix has not
+    /// been loaded yet. Returns `None` if the table has no such column or the cell is not set
+    /// in the row's existence bitmap, and [`LtpError::TableRowIdNotFound`] for unknown rows.
+    fn cell(&self, id: TableRowId, prop_id: u16) -> io::Result<Option<PropertyValue>>;
     fn read_column(
         &self,
         value: &TableRowColumnValue,
         prop_type: PropertyType,
     ) -> io::Result<PropertyValue>;
+
+    /// Read every column which is set in `row`, as `(prop_id, value)` pairs in column order.
+    fn row_values(&self, row: &TableRowData) -> io::Result<Vec<(u16, PropertyValue)>> {
+        let context = self.context();
+        let mut values = Vec::with_capacity(context.columns().len());
+        for column in context.columns() {
+            if let Some(value) = row.column(context, column)? {
+                values.push((
+                    column.prop_id(),
+                    self.read_column(&value, column.prop_type())?,
+                ));
+            }
+        }
+        Ok(values)
+    }
 }
 
 struct TableContextInner<Pst, RowIndex, RowIndexTree>
@@ -837,13 +892,14 @@ where
     RowIndexTree: TableRowIndexTree<Pst, RowIndex = RowIndex>,
     u32: From<RowIndex>,
 {
-    store: Rc<<Pst as PstFile>::Store>,
+    store: Arc<<Pst as PstFile>::Store>,
     node: <Pst as PstFile>::NodeBTreeEntry,
     context: TableContextInfo,
     heap: <Pst as PstFile>::HeapNode,
     row_index: BTreeMap<TableRowId, RowIndex>,
-    rows: Vec<TableRowData>,
-    block_cache: RefCell<DataBlockCache<Pst>>,
+    /// The whole Row Matrix, or the reason it could not be read, once it has been loaded.
+    rows: OnceLock<Result<Vec<TableRowData>, String>>,
+    block_cache: Mutex<DataBlockCache<Pst>>,
     _phantom: PhantomData<RowIndexTree>,
 }
 
@@ -882,19 +938,19 @@ where
     u32: From<RowIndex>,
 {
     fn read(
-        store: Rc<<Pst as PstFile>::Store>,
+        store: Arc<<Pst as PstFile>::Store>,
         node: <Pst as PstFile>::NodeBTreeEntry,
     ) -> io::Result<Self> {
-        let mut file = store
-            .pst()
+        let pst = store.pst();
+        let mut file = pst
             .reader()
             .lock()
             .map_err(|_| LtpError::FailedToLockFile)?;
         let file = &mut *file;
 
-        let header = store.pst().header();
+        let header = pst.header();
         let encoding = header.crypt_method();
-        let mut page_cache = store.pst().block_cache();
+        let mut page_cache = pst.block_cache();
         let block_btree = <<Pst as PstFile>::BlockBTree as RootBTreeReadWrite>::read(
             file,
             *header.root().block_btree(),
@@ -909,68 +965,9 @@ where
             data.search_key(),
         )?;
         let header = HeapNode::header(&heap)?;
-        let mut block_cache: DataBlockCache<Pst> = Default::default();
-
         let mut cursor = Cursor::new(heap.find_entry(header.user_root())?);
         let context = TableContextInfo::read(&mut cursor)?;
 
-        let rows = if let Some(rows) = context.rows {
-            match rows.id_type() {
-                Ok(NodeIdType::HeapNode) => {
-                    let rows: u32 = rows.into();
-                    vec![heap.find_entry(HeapId::from(rows))?.to_vec()]
-                }
-                _ => {
-                    let sub_node = node
-                        .sub_node()
-                        .ok_or(LtpError::PropertySubNodeValueNotFound(rows.into()))?;
-                    let block =
-                        block_btree.find_entry(file, sub_node.search_key(), &mut page_cache)?;
-                    let sub_node_tree = SubNodeTree::<Pst>::read(file, &block)?;
-                    let block =
-                        sub_node_tree.find_entry(file, &block_btree, rows, &mut page_cache)?;
-                    let block =
-                        block_btree.find_entry(file, block.search_key(), &mut page_cache)?;
-                    let data_tree = match block_cache.remove(&block.block().block()) {
-                        Some(data_tree) => data_tree,
-                        None => DataTree::read(file, encoding, &block)?,
-                    };
-                    let result = data_tree
-                        .blocks(
-                            file,
-                            encoding,
-                            &block_btree,
-                            &mut page_cache,
-                            &mut block_cache,
-                        )
-                        .map(|blocks| {
-                            blocks
-                                .map(|block| block.data().to_vec())
-                                .collect::<Vec<_>>()
-                        });
-                    block_cache.insert(block.block().block(), data_tree);
-                    result?
-                }
-            }
-            .into_iter()
-            .map(|data| {
-                let row_count = data.len() / context.end_existence_bitmap() as usize;
-                let mut cursor = Cursor::new(data);
-                let mut rows = Vec::with_capacity(row_count);
-                for _ in 0..row_count {
-                    let row = TableRowData::read(&mut cursor, &context)?;
-                    rows.push(row);
-                }
-                Ok(rows)
-            })
-            .collect::<io::Result<Vec<_>>>()?
-            .into_iter()
-            .flatten()
-            .collect()
-        } else {
-            Default::default()
-        };
-
         let row_index_tree = RowIndexTree::new(heap, context.row_index);
         let row_index = row_index_tree
             .entries()?
@@ -985,12 +982,193 @@ where
             context,
             heap,
             row_index,
-            rows,
-            block_cache: RefCell::new(block_cache),
+            rows: Default::default(),
+            block_cache: Default::default(),
             _phantom: PhantomData,
         })
     }
 
+    fn row_count(&self) -> usize {
+        self.row_index.len()
+    }
+
+    /// Rows never span data blocks, so every block except the last one holds as many rows as
+    /// will fit. If the Row Matrix is stored in the heap, all of the rows are in a single block.
+    fn rows_per_block(&self) -> usize {
+        let row_size = usize::from(self.context.end_existence_bitmap()).max(1);
+        match self.context.rows.map(|rows| rows.id_type()) {
+            Some(Ok(NodeIdType::HeapNode)) => usize::MAX,
+            _ => {
+                let block_size = MAX_BLOCK_SIZE
+                    - <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE;
+                (usize::from(block_size) / row_size).max(1)
+            }
+        }
+    }
+
+    fn read_row_block(&self, index: usize) -> io::Result<Vec<TableRowData>> {
+        let Some(rows) = self.context.rows else {
+            return Ok(Default::default());
+        };
+
+        let data = match rows.id_type() {
+            Ok(NodeIdType::HeapNode) => {
+                if index != 0 {
+                    return Err(LtpError::TableRowMatrixBlockNotFound(index).into());
+                }
+                let rows: u32 = rows.into();
+                self.heap.find_entry(HeapId::from(rows))?.to_vec()
+            }
+            _ => {
+                let pst = self.store.pst();
+                let mut file = pst
+                    .reader()
+                    .lock()
+                    .map_err(|_| LtpError::FailedToLockFile)?;
+                let file = &mut *file;
+
+                let encoding = pst.header().crypt_method();
+                let block_btree = self.store.block_btree();
+                let block_btree = block_btree.as_ref();
+                let mut page_cache = pst.block_cache();
+
+                let sub_node = self
+                    .node
+                    .sub_node()
+                    .ok_or(LtpError::PropertySubNodeValueNotFound(rows.into()))?;
+                let block = block_btree.find_entry(file, sub_node.search_key(), &mut page_cache)?;
+                let sub_node_tree = SubNodeTree::<Pst>::read(file, &block)?;
+                let block = sub_node_tree.find_entry(file, block_btree, rows, &mut page_cache)?;
+                let block = block_btree.find_entry(file, block.search_key(), &mut page_cache)?;
+                let mut block_cache = self
+                    .block_cache
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let data_tree = match block_cache.remove(&block.block().block()) {
+                    Some(data_tree) => data_tree,
+                    None => DataTree::read(file, encoding, &block)?,
+                };
+                let result = data_tree.nth(
+                    index,
+                    file,
+                    encoding,
+                    block_btree,
+                    &mut page_cache,
+                    &mut block_cache,
+                );
+                block_cache.insert(block.block().block(), data_tree);
+                result?.ok_or(LtpError::TableRowMatrixBlockNotFound(index))?
+            }
+        };
+
+        let row_size = usize::from(self.context.end_existence_bitmap()).max(1);
+        let row_count = data.len() / row_size;
+        let mut cursor = Cursor::new(data);
+        let mut rows = Vec::with_capacity(row_count);
+        for _ in 0..row_count {
+            rows.push(TableRowData::read(&mut cursor, &self.context)?);
+        }
+        Ok(rows)
+    }
+
+    fn rows_iter<'a>(&'a self) -> Box<dyn 'a + Iterator<Item = io::Result<TableRowData>>> {
+        let blocks = row_matrix_blocks(0..self.row_count(), self.rows_per_block());
+        Box::new(
+            blocks
+                .flat_map(move |index| match self.read_row_block(index) {
+                    Ok(rows) => rows.into_iter().map(Ok).collect::<Vec<_>>(),
+                    Err(err) => vec![Err(err)],
+                })
+                .take(self.row_count()),
+        )
+    }
+
+    fn rows_range(&self, start: usize, count: usize) -> io::Result<Vec<TableRowData>> {
+        let end = start.saturating_add(count).min(self.row_count());
+        let rows_per_block = self.rows_per_block();
+        let mut rows = Vec::with_capacity(end.saturating_sub(start));
+        for index in row_matrix_blocks(start..end, rows_per_block) {
+            let first_row = index.saturating_mul(rows_per_block);
+            rows.extend(
+                self.read_row_block(index)?
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(offset, _)| (start..end).contains(&(first_row + offset)))
+                    .map(|(_, row)| row),
+            );
+        }
+        Ok(rows)
+    }
+
+    /// Read the whole Row Matrix the first time it is needed. A failure is kept as well, so the
+    /// Row Matrix is only read once.
+    fn rows(&self) -> LtpResult<&[TableRowData]> {
+        let rows = self.rows.get_or_init(|| {
+            self.rows_iter()
+                .collect::<io::Result<Vec<_>>>()
+                .map_err(|err| {
+                    error!(
+                        name: "PstTableRowMatrixReadFailed",
+                        ?err,
+                        "Failed to read TC Row Matrix"
+                    );
+                    err.to_string()
+                })
+        });
+        rows.as_deref()
+            .map_err(|err| LtpError::TableRowMatrixReadFailed(err.clone()))
+    }
+
+    fn rows_matrix<'a>(&'a self) -> io::Result<Box<dyn 'a + Iterator<Item = &'a TableRowData>>> {
+        Ok(Box::new(self.rows()?.iter()))
+    }
+
+    fn find_row(&self, id: TableRowId) -> LtpResult<&TableRowData> {
+        let row_id = Into::<u32>::into(id);
+        let index = self
+            .row_index
+            .get(&id)
+            .ok_or(LtpError::TableRowIdNotFound(row_id))?;
+        let rows = self.rows()?;
+        rows.get(u32::from(*index) as usize)
+            .ok_or(LtpError::TableRowIdNotFound(row_id))
+    }
+
+    fn find_row_by_id(&self, id: TableRowId) -> io::Result<Option<TableRowData>> {
+        let Some(index) = self.row_index.get(&id) else {
+            return Ok(None);
+        };
+        let index = u32::from(*index) as usize;
+        let row = match self.rows.get() {
+            Some(rows) => rows
+                .as_ref()
+                .map_err(|err| LtpError::TableRowMatrixReadFailed(err.clone()))?
+                .get(index)
+                .cloned(),
+            None => self.rows_range(index, 1)?.into_iter().next(),
+        };
+        let row = row.ok_or(LtpError::TableRowIdNotFound(Into::<u32>::into(id)))?;
+        Ok(Some(row))
+    }
+
+    fn cell(&self, id: TableRowId, prop_id: u16) -> io::Result<Option<PropertyValue>> {
+        let Some(column) = self
+            .context
+            .columns()
+            .iter()
+            .find(|column| column.prop_id() == prop_id)
+        else {
+            return Ok(None);
+        };
+
+        let row = self
+            .find_row_by_id(id)?
+            .ok_or(LtpError::TableRowIdNotFound(Into::<u32>::into(id)))?;
+        row.column(&self.context, column)?
+            .map(|value| self.read_column(&value, column.prop_type()))
+            .transpose()
+    }
+
     fn read_column(
         &self,
         value: &TableRowColumnValue,
@@ -999,22 +1177,26 @@ where
         match value {
             TableRowColumnValue::Small(small) => Ok(small.clone()),
             TableRowColumnValue::Heap(heap_id) => {
+                if <u32 as From<HeapId>>::from(*heap_id) == 0 {
+                    return Ok(PropertyValue::Null);
+                }
+
                 let data = self.heap.find_entry(*heap_id)?;
                 let mut cursor = Cursor::new(data);
                 PropertyValueReadWrite::read(&mut cursor, prop_type)
             }
             TableRowColumnValue::Node(sub_node_id) => {
-                let mut file = self
-                    .store
-                    .pst()
+                let pst = self.store.pst();
+                let mut file = pst
                     .reader()
                     .lock()
                     .map_err(|_| LtpError::FailedToLockFile)?;
                 let file = &mut *file;
 
-                let encoding = self.store.pst().header().crypt_method();
+                let encoding = pst.header().crypt_method();
                 let block_btree = self.store.block_btree();
-                let mut page_cache = self.store.pst().block_cache();
+                let block_btree = block_btree.as_ref();
+                let mut page_cache = pst.block_cache();
 
                 let sub_node =
                     self.node
@@ -1027,7 +1209,10 @@ where
                 let block =
                     sub_node_tree.find_entry(file, block_btree, *sub_node_id, &mut page_cache)?;
                 let block = block_btree.find_entry(file, block.search_key(), &mut page_cache)?;
-                let mut block_cache = self.block_cache.borrow_mut();
+                let mut block_cache = self
+                    .block_cache
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
                 let data_tree = match block_cache.remove(&block.block().block()) {
                     Some(data_tree) => data_tree,
                     None => DataTree::read(file, encoding, &block)?,
@@ -1060,9 +1245,9 @@ pub struct UnicodeTableContext {
 
 impl UnicodeTableContext {
     pub fn read(
-        store: Rc<UnicodeStore>,
+        store: Arc<UnicodeStore>,
         node: UnicodeNodeBTreeEntry,
-    ) -> io::Result<Rc<dyn TableContext>> {
+    ) -> io::Result<Arc<dyn TableContext>> {
         <Self as TableContextReadWrite<UnicodePstFile>>::read(store, node)
     }
 }
@@ -1072,17 +1257,32 @@ impl TableContext for UnicodeTableContext {
         &self.inner.context
     }
 
-    fn rows_matrix<'a>(&'a self) -> Box<dyn 'a + Iterator<Item = &'a TableRowData>> {
-        Box::new(self.inner.rows.iter())
+    fn row_count(&self) -> usize {
+        self.inner.row_count()
+    }
+
+    fn rows_matrix<'a>(&'a self) -> io::Result<Box<dyn 'a + Iterator<Item = &'a TableRowData>>> {
+        self.inner.rows_matrix()
+    }
+
+    fn rows_iter<'a>(&'a self) -> Box<dyn 'a + Iterator<Item = io::Result<TableRowData>>> {
+        self.inner.rows_iter()
+    }
+
+    fn rows_range(&self, start: usize, count: usize) -> io::Result<Vec<TableRowData>> {
+        self.inner.rows_range(start, count)
     }
 
     fn find_row(&self, id: TableRowId) -> LtpResult<&TableRowData> {
-        let index = self
-            .inner
-            .row_index
-            .get(&id)
-            .ok_or(LtpError::TableRowIdNotFound(u32::from(id)))?;
-        Ok(&self.inner.rows[u32::from(*index) as usize])
+        self.inner.find_row(id)
+    }
+
+    fn find_row_by_id(&self, id: TableRowId) -> io::Result<Option<TableRowData>> {
+        self.inner.find_row_by_id(id)
+    }
+
+    fn cell(&self, id: TableRowId, prop_id: u16) -> io::Result<Option<PropertyValue>> {
+        self.inner.cell(id, prop_id)
     }
 
     fn read_column(
@@ -1096,11 +1296,11 @@ impl TableContext for UnicodeTableContext {
 
 impl TableContextReadWrite<UnicodePstFile> for UnicodeTableContext {
     fn read(
-        store: Rc<UnicodeStore>,
+        store: Arc<UnicodeStore>,
         node: UnicodeNodeBTreeEntry,
-    ) -> io::Result<Rc<dyn TableContext>> {
+    ) -> io::Result<Arc<dyn TableContext>> {
         let inner = TableContextInner::read(store, node)?;
-        Ok(Rc::new(Self { inner }))
+        Ok(Arc::new(Self { inner }))
     }
 }
 
@@ -1116,9 +1316,9 @@ pub struct AnsiTableContext {
 
 impl AnsiTableContext {
     pub fn read(
-        store: Rc<AnsiStore>,
+        store: Arc<AnsiStore>,
         node: AnsiNodeBTreeEntry,
-    ) -> io::Result<Rc<dyn TableContext>> {
+    ) -> io::Result<Arc<dyn TableContext>> {
         <Self as TableContextReadWrite<AnsiPstFile>>::read(store, node)
     }
 }
@@ -1128,17 +1328,32 @@ impl TableContext for AnsiTableContext {
         &self.inner.context
     }
 
-    fn rows_matrix<'a>(&'a self) -> Box<dyn 'a + Iterator<Item = &'a TableRowData>> {
-        Box::new(self.inner.rows.iter())
+    fn row_count(&self) -> usize {
+        self.inner.row_count()
+    }
+
+    fn rows_matrix<'a>(&'a self) -> io::Result<Box<dyn 'a + Iterator<Item = &'a TableRowData>>> {
+        self.inner.rows_matrix()
+    }
+
+    fn rows_iter<'a>(&'a self) -> Box<dyn 'a + Iterator<Item = io::Result<TableRowData>>> {
+        self.inner.rows_iter()
+    }
+
+    fn rows_range(&self, start: usize, count: usize) -> io::Result<Vec<TableRowData>> {
+        self.inner.rows_range(start, count)
     }
 
     fn find_row(&self, id: TableRowId) -> LtpResult<&TableRowData> {
-        let index = self
-            .inner
-            .row_index
-            .get(&id)
-            .ok_or(LtpError::TableRowIdNotFound(u32::from(id)))?;
-        Ok(&self.inner.rows[u32::from(*index) as usize])
+        self.inner.find_row(id)
+    }
+
+    fn find_row_by_id(&self, id: TableRowId) -> io::Result<Option<TableRowData>> {
+        self.inner.find_row_by_id(id)
+    }
+
+    fn cell(&self, id: TableRowId, prop_id: u16) -> io::Result<Option<PropertyValue>> {
+        self.inner.cell(id, prop_id)
     }
 
     fn read_column(
@@ -1151,8 +1366,280 @@ impl TableContext for AnsiTableContext {
 }
 
 impl TableContextReadWrite<AnsiPstFile> for AnsiTableContext {
-    fn read(store: Rc<AnsiStore>, node: AnsiNodeBTreeEntry) -> io::Result<Rc<dyn TableContext>> {
+    fn read(store: Arc<AnsiStore>, node: AnsiNodeBTreeEntry) -> io::Result<Arc<dyn TableContext>> {
         let inner = TableContextInner::read(store, node)?;
-        Ok(Rc::new(Self { inner }))
+        Ok(Arc::new(Self { inner }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        messaging::{property_ids::PR_DISPLAY_NAME, store::Store},
+        ndb::node_id::NID_ROOT_FOLDER,
+        test_util::EMPTY_PST,
+    };
+    use std::{
+        fs::File,
+        io::{Seek, SeekFrom},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    /// Count the number of reads made through the wrapped file.
+    struct CountingReader {
+        file: File,
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.file.read(buf)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.file.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_row_matrix_blocks() {
+        // 0x6C byte rows fit 75 to a block in a Unicode PST.
+        let rows_per_block = (8176 / 0x6C) as usize;
+        assert_eq!(rows_per_block, 75);
+
+        assert_eq!(row_matrix_blocks(1000..1050, rows_per_block), 13..14);
+        assert_eq!(row_matrix_blocks(1000..1100, rows_per_block), 13..15);
+        assert_eq!(row_matrix_blocks(0..75, rows_per_block), 0..1);
+        assert_eq!(row_matrix_blocks(75..76, rows_per_block), 1..2);
+        assert_eq!(row_matrix_blocks(10..10, rows_per_block), 0..0);
+    }
+
+    #[test]
+    fn test_rows_range() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let reader = CountingReader {
+            file: File::open(EMPTY_PST).unwrap(),
+            reads: reads.clone(),
+        };
+        let pst = Arc::new(UnicodePstFile::read_from(Box::new(reader)).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let entry_id = store.properties().make_entry_id(NID_ROOT_FOLDER).unwrap();
+        let folder = store.open_folder(&entry_id).unwrap();
+        let hierarchy_table = folder.hierarchy_table().unwrap();
+
+        // The Row Matrix for the root folder fits in the TC heap, which was already read when the
+        // table was opened, so reading a range of rows does not touch the file.
+        reads.store(0, Ordering::SeqCst);
+        let rows = hierarchy_table.rows_range(1, 2).unwrap();
+        assert_eq!(reads.load(Ordering::SeqCst), 0);
+
+        let all_rows: Vec<_> = hierarchy_table
+            .rows_matrix()
+            .unwrap()
+            .map(|row| row.id())
+            .collect();
+        assert!(all_rows.len() > 2);
+        assert_eq!(
+            rows.iter().map(TableRowData::id).collect::<Vec<_>>(),
+            all_rows[1..3]
+        );
+
+        let iter_rows = hierarchy_table
+            .rows_iter()
+            .map(|row| row.map(|row| row.id()))
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(iter_rows, all_rows);
+
+        assert!(hierarchy_table
+            .rows_range(all_rows.len(), 10)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rows_matrix_read_failed() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst.clone()).unwrap();
+        let node_id = NodeId::new(NodeIdType::HierarchyTable, NID_ROOT_FOLDER.index()).unwrap();
+        let node = {
+            let mut file = pst.reader().lock().unwrap();
+            let mut page_cache = pst.node_cache();
+            store
+                .node_btree()
+                .find_entry(&mut *file, u32::from(node_id).into(), &mut page_cache)
+                .unwrap()
+        };
+        let mut inner =
+            TableContextInner::<UnicodePstFile, UnicodeTableRowIndex, UnicodeRowIndexTree>::read(
+                store, node,
+            )
+            .unwrap();
+
+        // Point the Row Matrix at a heap allocation which does not exist.
+        let missing = HeapId::new(0x7FF, 0).unwrap();
+        inner.context.rows = Some(NodeId::from(u32::from(missing)));
+        let table = UnicodeTableContext { inner };
+        let row = *table.inner.row_index.keys().next().unwrap();
+
+        assert!(table.rows_matrix().is_err());
+        assert!(matches!(table.inner.rows.get(), Some(Err(_))));
+        assert!(table.rows_matrix().is_err());
+        assert!(matches!(
+            table.find_row(row),
+            Err(LtpError::TableRowMatrixReadFailed(_))
+        ));
+    }
+
+    /// Row layout with one column of each size: `dwRowID` and `dwRowVersion`, then an 8-byte
+    /// `Integer64`, a `Unicode` HNID in the heap, a `Binary` NID in a sub-node, an `Integer32`
+    /// which is not set, a 2-byte `Integer16` and a 1-byte `Boolean`.
+    fn sample_row() -> (TableContextInfo, TableRowData) {
+        let columns = vec![
+            TableColumnDescriptor::new(PropertyType::Integer32, LTP_ROW_ID_PROP_ID, 0, 4, 0),
+            TableColumnDescriptor::new(PropertyType::Integer32, LTP_ROW_VERSION_PROP_ID, 4, 4, 1),
+            TableColumnDescriptor::new(PropertyType::Integer64, 0x0001, 8, 8, 2),
+            TableColumnDescriptor::new(PropertyType::Unicode, 0x0002, 16, 4, 3),
+            TableColumnDescriptor::new(PropertyType::Binary, 0x0003, 20, 4, 4),
+            TableColumnDescriptor::new(PropertyType::Integer32, 0x0004, 24, 4, 5),
+            TableColumnDescriptor::new(PropertyType::Integer16, 0x0005, 28, 2, 6),
+            TableColumnDescriptor::new(PropertyType::Boolean, 0x0006, 30, 1, 7),
+        ];
+        let context =
+            TableContextInfo::new(28, 30, 31, 32, HeapId::default(), None, columns).unwrap();
+
+        let mut align_4byte = vec![];
+        align_4byte.extend_from_slice(&0x0123_4567_89AB_CDEF_i64.to_le_bytes());
+        align_4byte.extend_from_slice(&0x0000_0040_u32.to_le_bytes());
+        align_4byte.extend_from_slice(&0x0000_8025_u32.to_le_bytes());
+        align_4byte.extend_from_slice(&[0xFF; 4]);
+        let row = TableRowData::new(
+            TableRowId::new(0x8022),
+            7,
+            align_4byte,
+            (-2_i16).to_le_bytes().to_vec(),
+            vec![0x01],
+            vec![0b1111_1011],
+        );
+
+        (context, row)
+    }
+
+    #[test]
+    fn test_row_column_sizes() {
+        let (context, row) = sample_row();
+        let column = |index: usize| row.column(&context, &context.columns()[index]).unwrap();
+
+        assert!(matches!(
+            column(0),
+            Some(TableRowColumnValue::Small(PropertyValue::Integer32(0x8022)))
+        ));
+        assert!(matches!(
+            column(1),
+            Some(TableRowColumnValue::Small(PropertyValue::Integer32(7)))
+        ));
+        assert!(matches!(
+            column(2),
+            Some(TableRowColumnValue::Small(PropertyValue::Integer64(
+                0x0123_4567_89AB_CDEF
+            )))
+        ));
+        let Some(TableRowColumnValue::Heap(heap_id)) = column(3) else {
+            panic!("Expected TableRowColumnValue::Heap");
+        };
+        assert_eq!(u32::from(heap_id), 0x40);
+        let Some(TableRowColumnValue::Node(node_id)) = column(4) else {
+            panic!("Expected TableRowColumnValue::Node");
+        };
+        assert_eq!(u32::from(node_id), 0x8025);
+        assert!(column(5).is_none());
+        assert!(matches!(
+            column(6),
+            Some(TableRowColumnValue::Small(PropertyValue::Integer16(-2)))
+        ));
+        assert!(matches!(
+            column(7),
+            Some(TableRowColumnValue::Small(PropertyValue::Boolean(true)))
+        ));
+    }
+
+    #[test]
+    fn test_row_boolean_out_of_range() {
+        let (context, row) = sample_row();
+        let row = TableRowData::new(
+            row.id(),
+            row.unique(),
+            row.align_4byte.clone(),
+            row.align_2byte.clone(),
+            vec![0x02],
+            row.existence_bitmap.clone(),
+        );
+        let err = row.column(&context, &context.columns()[7]).unwrap_err();
+        assert!(matches!(
+            err.into_inner()
+                .and_then(|err| err.downcast::<LtpError>().ok())
+                .as_deref(),
+            Some(LtpError::InvalidTableColumnBooleanValue(0x02))
+        ));
+    }
+
+    #[test]
+    fn test_cell() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let entry_id = store.properties().make_entry_id(NID_ROOT_FOLDER).unwrap();
+        let folder = store.open_folder(&entry_id).unwrap();
+        let hierarchy_table = folder.hierarchy_table().unwrap();
+
+        // Read the cells before the Row Matrix is loaded, then compare them with the full rows.
+        let row_ids: Vec<_> = hierarchy_table
+            .rows_iter()
+            .map(|row| row.map(|row| row.id()))
+            .collect::<io::Result<_>>()
+            .unwrap();
+        let cells: Vec<_> = row_ids
+            .iter()
+            .map(|id| hierarchy_table.cell(*id, PR_DISPLAY_NAME).unwrap())
+            .collect();
+
+        let column_index = hierarchy_table
+            .context()
+            .columns()
+            .iter()
+            .position(|column| column.prop_id() == PR_DISPLAY_NAME)
+            .unwrap();
+        let prop_type = hierarchy_table.context().columns()[column_index].prop_type();
+        for (row, cell) in hierarchy_table.rows_matrix().unwrap().zip(cells) {
+            let columns = row.columns(hierarchy_table.context()).unwrap();
+            let expected = columns[column_index]
+                .as_ref()
+                .map(|value| hierarchy_table.read_column(value, prop_type).unwrap());
+            let Some(PropertyValue::Unicode(cell)) = cell else {
+                panic!("Expected PropertyValue::Unicode: {cell:?}");
+            };
+            assert_eq!(
+                format!("{expected:?}"),
+                format!("{:?}", Some(PropertyValue::Unicode(cell)))
+            );
+        }
+
+        let row_id = row_ids[0];
+        assert!(hierarchy_table.cell(row_id, 0x7FFF).unwrap().is_none());
+        assert!(matches!(
+            hierarchy_table.cell(row_id, LTP_ROW_ID_PROP_ID).unwrap(),
+            Some(PropertyValue::Integer32(id)) if id as u32 == u32::from(row_id)
+        ));
+
+        let err = hierarchy_table
+            .cell(TableRowId::new(0xFFFF_FFE1), PR_DISPLAY_NAME)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            LtpError::TableRowIdNotFound(0xFFFF_FFE1).to_string()
+        );
     }
 }
@@ -5,13 +5,14 @@ use core::mem;
 use std::{
     io::{self, Cursor, Read, Write},
     marker::PhantomData,
+    ops::Range,
 };
 
 use super::{heap::*, read_write::*, *};
 use crate::{AnsiPstFile, PstFile, UnicodePstFile};
 
 /// [BTHHEADER](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/8e4ae05c-3c24-4103-b7e5-ffef6f244834)
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct HeapTreeHeader {
     key_size: u8,
     entry_size: u8,
@@ -96,7 +97,7 @@ impl HeapNodePageReadWrite for HeapTreeHeader {
 }
 
 /// [Intermediate BTH (Index) Records](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/2c992ac1-1b21-4167-b111-f76cf609005f)
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct HeapTreeIntermediateEntry<K>
 where
     K: HeapTreeEntryKey,
@@ -140,7 +141,7 @@ where
 }
 
 /// [Leaf BTH (Data) Records](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/660db569-c8f7-4516-82ad-44709b1c667f)
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct HeapTreeLeafEntry<K, V>
 where
     K: HeapTreeEntryKey,
@@ -194,6 +195,10 @@ pub trait HeapTree {
     fn user_root(&self) -> HeapId;
     fn header(&self) -> io::Result<HeapTreeHeader>;
     fn entries(&self) -> io::Result<Vec<HeapTreeLeafEntry<Self::Key, Self::Value>>>;
+
+    /// Count the leaf records without reading them. A BTH with only one level does not need to
+    /// allocate anything for this.
+    fn entry_count(&self) -> io::Result<usize>;
 }
 
 struct HeapTreeInner<Pst, K, V>
@@ -226,7 +231,8 @@ where
         HeapTreeHeader::read(&mut cursor)
     }
 
-    fn entries(&self) -> io::Result<Vec<HeapTreeLeafEntry<K, V>>> {
+    /// Read the [`HeapTreeHeader`], and check that the key and value sizes match `K` and `V`.
+    fn checked_header(&self) -> io::Result<HeapTreeHeader> {
         let header = self.header()?;
         if header.key_size() != K::SIZE {
             return Err(LtpError::InvalidHeapTreeKeySize(header.key_size()).into());
@@ -234,11 +240,11 @@ where
         if header.entry_size() != V::SIZE {
             return Err(LtpError::InvalidHeapTreeDataSize(header.entry_size()).into());
         }
+        Ok(header)
+    }
 
-        if u32::from(header.root()) == 0 {
-            return Ok(Default::default());
-        }
-
+    /// Walk the intermediate levels of the BTH, and return the [`HeapId`] of every leaf.
+    fn leaves(&self, header: &HeapTreeHeader) -> io::Result<Vec<HeapId>> {
         let mut level = header.levels();
         let mut next_level = vec![header.root()];
 
@@ -253,8 +259,17 @@ where
             level -= 1;
         }
 
+        Ok(next_level)
+    }
+
+    fn entries(&self) -> io::Result<Vec<HeapTreeLeafEntry<K, V>>> {
+        let header = self.checked_header()?;
+        if u32::from(header.root()) == 0 {
+            return Ok(Default::default());
+        }
+
         let mut results = Vec::new();
-        for heap_id in mem::take(&mut next_level).into_iter() {
+        for heap_id in self.leaves(&header)? {
             let mut cursor = Cursor::new(self.heap.find_entry(heap_id)?);
             while let Ok(row) = HeapTreeLeafEntry::<K, V>::read(&mut cursor) {
                 results.push(row);
@@ -263,6 +278,153 @@ where
 
         Ok(results)
     }
+
+    fn entry_count(&self) -> io::Result<usize> {
+        let header = self.checked_header()?;
+        if u32::from(header.root()) == 0 {
+            return Ok(0);
+        }
+
+        let record_size = usize::from(K::SIZE) + usize::from(V::SIZE);
+        if header.levels() == 0 {
+            return Ok(self.heap.find_entry(header.root())?.len() / record_size);
+        }
+
+        self.leaves(&header)?
+            .into_iter()
+            .map(|heap_id| Ok(self.heap.find_entry(heap_id)?.len() / record_size))
+            .sum()
+    }
+}
+
+/// Read the [`HeapTreeHeader`] and the raw key and data of every leaf record in a BTH stored in a
+/// [`HeapNodeEditor`], in key order.
+pub(crate) fn heap_tree_records(
+    heap: &HeapNodeEditor,
+    header: HeapId,
+) -> io::Result<(HeapTreeHeader, Vec<Vec<u8>>)> {
+    let mut cursor = Cursor::new(heap.get(header)?);
+    let header = HeapTreeHeader::read(&mut cursor)?;
+
+    if u32::from(header.root()) == 0 {
+        return Ok((header, Default::default()));
+    }
+
+    let key_size = usize::from(header.key_size());
+    let mut level = vec![header.root()];
+    for _ in 0..header.levels() {
+        let mut next_level = Vec::new();
+        for heap_id in level {
+            for entry in heap.get(heap_id)?.chunks_exact(key_size + 4) {
+                let mut cursor = Cursor::new(&entry[key_size..]);
+                next_level.push(HeapId::read(&mut cursor)?);
+            }
+        }
+        level = next_level;
+    }
+
+    let record_size = key_size + usize::from(header.entry_size());
+    let mut records = Vec::new();
+    for heap_id in level {
+        records.extend(
+            heap.get(heap_id)?
+                .chunks_exact(record_size)
+                .map(<[u8]>::to_vec),
+        );
+    }
+
+    Ok((header, records))
+}
+
+/// Find the leaf record for `key` in the BTH at the user root of a heap with 2-byte keys, e.g. a PC,
+/// directly in the data `blocks` of the heap. Returns the index of the block and the byte range of
+/// the record's data within that block, which can be modified in place.
+pub(crate) fn heap_tree_record_range(
+    blocks: &[Vec<u8>],
+    key: u16,
+) -> io::Result<Option<(usize, Range<usize>)>> {
+    let first = blocks.first().ok_or(io::ErrorKind::UnexpectedEof)?;
+    let user_root = HeapNodeHeader::read(&mut Cursor::new(first.as_slice()))?.user_root();
+    let (block_index, range) = heap_allocation_range(blocks, user_root)?;
+    let header = HeapTreeHeader::read(&mut Cursor::new(&blocks[block_index][range]))?;
+    if header.key_size() != 2 {
+        return Err(LtpError::InvalidHeapTreeKeySize(header.key_size()).into());
+    }
+    if u32::from(header.root()) == 0 {
+        return Ok(None);
+    }
+
+    // Follow the last intermediate entry with a key less than or equal to `key` down to a leaf.
+    let mut heap_id = header.root();
+    for _ in 0..header.levels() {
+        let (block_index, range) = heap_allocation_range(blocks, heap_id)?;
+        let mut next = None;
+        for entry in blocks[block_index][range].chunks_exact(6) {
+            if u16::from_le_bytes([entry[0], entry[1]]) > key {
+                break;
+            }
+            next = Some(HeapId::read(&mut Cursor::new(&entry[2..]))?);
+        }
+        let Some(next) = next else {
+            return Ok(None);
+        };
+        heap_id = next;
+    }
+
+    let entry_size = usize::from(header.entry_size());
+    let record_size = 2 + entry_size;
+    let (block_index, range) = heap_allocation_range(blocks, heap_id)?;
+    Ok(blocks[block_index][range.clone()]
+        .chunks_exact(record_size)
+        .position(|record| u16::from_le_bytes([record[0], record[1]]) == key)
+        .map(|index| {
+            let start = range.start + index * record_size + 2;
+            (block_index, start..start + entry_size)
+        }))
+}
+
+/// Build a new BTH in a [`HeapNodeEditor`] from `records`, which must already be sorted by key.
+/// Returns the [`HeapId`] of the new [`HeapTreeHeader`].
+pub(crate) fn allocate_heap_tree(
+    heap: &mut HeapNodeEditor,
+    key_size: u8,
+    entry_size: u8,
+    records: &[Vec<u8>],
+) -> io::Result<HeapId> {
+    let mut levels = 0;
+    let mut root = HeapId::default();
+
+    if !records.is_empty() {
+        let record_size = usize::from(key_size) + usize::from(entry_size);
+        let mut level: Vec<(Vec<u8>, HeapId)> = Vec::new();
+        for chunk in records.chunks(MAX_HEAP_ALLOCATION_SIZE / record_size) {
+            let key = chunk[0][..usize::from(key_size)].to_vec();
+            let heap_id = heap.allocate(chunk.concat())?;
+            level.push((key, heap_id));
+        }
+
+        let index_size = usize::from(key_size) + 4;
+        while level.len() > 1 {
+            let mut next_level = Vec::new();
+            for chunk in level.chunks(MAX_HEAP_ALLOCATION_SIZE / index_size) {
+                let mut data = Vec::with_capacity(chunk.len() * index_size);
+                for (key, heap_id) in chunk {
+                    data.extend_from_slice(key);
+                    heap_id.write(&mut data)?;
+                }
+                let heap_id = heap.allocate(data)?;
+                next_level.push((chunk[0].0.clone(), heap_id));
+            }
+            level = next_level;
+            levels += 1;
+        }
+
+        root = level[0].1;
+    }
+
+    let mut data = Vec::with_capacity(8);
+    HeapTreeHeader::new(key_size, entry_size, levels, root)?.write(&mut data)?;
+    Ok(heap.allocate(data)?)
 }
 
 pub struct UnicodeHeapTree<K, V>
@@ -296,6 +458,10 @@ where
     fn entries(&self) -> io::Result<Vec<HeapTreeLeafEntry<K, V>>> {
         self.inner.entries()
     }
+
+    fn entry_count(&self) -> io::Result<usize> {
+        self.inner.entry_count()
+    }
 }
 
 impl<K, V> HeapTreeReadWrite<UnicodePstFile> for UnicodeHeapTree<K, V>
@@ -350,6 +516,10 @@ where
     fn entries(&self) -> io::Result<Vec<HeapTreeLeafEntry<K, V>>> {
         self.inner.entries()
     }
+
+    fn entry_count(&self) -> io::Result<usize> {
+        self.inner.entry_count()
+    }
 }
 
 impl<K, V> HeapTreeReadWrite<AnsiPstFile> for AnsiHeapTree<K, V>
@@ -1,14 +1,15 @@
 //! ## [Attachment Objects](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/46eb4828-c6a5-420d-a137-9ee36df317c1)
 
-use std::{collections::BTreeMap, io, rc::Rc};
+use std::{collections::BTreeMap, io, sync::Arc};
 
-use super::{message::*, read_write::*, *};
+use super::{message::*, property_ids::*, read_write::*, *};
 use crate::{
     ltp::{
         heap::HeapNode,
-        prop_context::{BinaryValue, PropertyContext, PropertyValue},
+        prop_context::{BinaryValue, PropertyContext, PropertyValue, TypedProperties},
         prop_type::PropertyType,
         read_write::*,
+        LtpError, LtpResult,
     },
     ndb::{
         block::{DataTree, IntermediateTreeBlock},
@@ -27,6 +28,30 @@ pub struct AttachmentProperties {
     properties: BTreeMap<u16, PropertyValue>,
 }
 
+/// Well known properties are serialized as named fields, like `filename`, `size` and `mime_type`.
+/// Every property is also included in the `properties` map, keyed by its hex property ID.
+#[cfg(feature = "serde")]
+impl serde::Serialize for AttachmentProperties {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        const FIELDS: &[(&str, &[u16])] = &[
+            ("filename", &[PR_ATTACH_LONG_FILENAME, PR_ATTACH_FILENAME]),
+            ("size", &[PR_ATTACH_SIZE]),
+            ("mime_type", &[PR_ATTACH_MIME_TAG]),
+            ("content_id", &[PR_ATTACH_CONTENT_ID]),
+            ("method", &[PR_ATTACH_METHOD]),
+        ];
+
+        let mut state = serializer.serialize_struct("AttachmentProperties", FIELDS.len() + 1)?;
+        super::serialize_named_properties(&mut state, FIELDS, &self.properties, self.codepage())?;
+        state.end()
+    }
+}
+
 impl AttachmentProperties {
     pub fn get(&self, id: u16) -> Option<&PropertyValue> {
         self.properties.get(&id)
@@ -39,7 +64,7 @@ impl AttachmentProperties {
     pub fn attachment_size(&self) -> io::Result<i32> {
         let attachment_size = self
             .properties
-            .get(&0x0E20)
+            .get(&PR_ATTACH_SIZE)
             .ok_or(MessagingError::AttachmentSizeNotFound)?;
 
         match attachment_size {
@@ -53,7 +78,7 @@ impl AttachmentProperties {
     pub fn attachment_method(&self) -> io::Result<i32> {
         let attachment_method = self
             .properties
-            .get(&0x3705)
+            .get(&PR_ATTACH_METHOD)
             .ok_or(MessagingError::AttachmentMethodNotFound)?;
 
         match attachment_method {
@@ -67,7 +92,7 @@ impl AttachmentProperties {
     pub fn rendering_position(&self) -> io::Result<i32> {
         let rendering_position = self
             .properties
-            .get(&0x370B)
+            .get(&PR_RENDERING_POSITION)
             .ok_or(MessagingError::AttachmentRenderingPositionNotFound)?;
 
         match rendering_position {
@@ -78,6 +103,86 @@ impl AttachmentProperties {
             ),
         }
     }
+
+    /// `PidTagAttachMimeTag`
+    pub fn mime_tag(&self) -> io::Result<Option<String>> {
+        Ok(self.get_typed(PR_ATTACH_MIME_TAG)?)
+    }
+
+    /// `PidTagAttachContentId`, which matches a `cid:` reference in the HTML body of the message
+    /// for an inline attachment.
+    pub fn content_id(&self) -> io::Result<Option<String>> {
+        Ok(self.get_typed(PR_ATTACH_CONTENT_ID)?)
+    }
+
+    /// A file name for saving the attachment: `PidTagAttachLongFilename`, then
+    /// `PidTagAttachFilename`, or else `attachment_<index>` with an extension from
+    /// `PidTagAttachExtension` or `PidTagAttachMimeTag`. The `index` is the position of the
+    /// attachment in the message, since the attachment does not know it.
+    pub fn suggested_filename(&self, index: usize) -> io::Result<String> {
+        for prop_id in [PR_ATTACH_LONG_FILENAME, PR_ATTACH_FILENAME] {
+            if let Some(file_name) = self
+                .get_typed::<String>(prop_id)?
+                .filter(|file_name| !file_name.is_empty())
+            {
+                return Ok(file_name);
+            }
+        }
+
+        let extension = match self
+            .get_typed::<String>(PR_ATTACH_EXTENSION)?
+            .map(|extension| extension.trim_start_matches('.').to_string())
+            .filter(|extension| !extension.is_empty())
+        {
+            Some(extension) => Some(extension),
+            None => self
+                .mime_tag()?
+                .as_deref()
+                .and_then(mime_type_extension)
+                .map(String::from),
+        };
+
+        Ok(match extension {
+            Some(extension) => format!("attachment_{index}.{extension}"),
+            None => format!("attachment_{index}"),
+        })
+    }
+}
+
+impl TypedProperties for AttachmentProperties {
+    fn property_value(&self, id: u16) -> Option<&PropertyValue> {
+        self.get(id)
+    }
+}
+
+/// Map some common MIME types to a file extension, ignoring any parameters after the type.
+fn mime_type_extension(mime_type: &str) -> Option<&'static str> {
+    let mime_type = mime_type.split(';').next()?.trim().to_ascii_lowercase();
+    Some(match mime_type.as_str() {
+        "application/msword" => "doc",
+        "application/pdf" => "pdf",
+        "application/rtf" | "text/rtf" => "rtf",
+        "application/vnd.ms-excel" => "xls",
+        "application/vnd.ms-powerpoint" => "ppt",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation" => "pptx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => "xlsx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => "docx",
+        "application/zip" => "zip",
+        "image/bmp" => "bmp",
+        "image/gif" => "gif",
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/png" => "png",
+        "image/svg+xml" => "svg",
+        "image/tiff" => "tif",
+        "message/rfc822" => "eml",
+        "text/calendar" => "ics",
+        "text/csv" => "csv",
+        "text/html" => "html",
+        "text/plain" => "txt",
+        "text/vcard" | "text/x-vcard" => "vcf",
+        "text/xml" | "application/xml" => "xml",
+        _ => return None,
+    })
 }
 
 /// [PidTagAttachMethod](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxcmsg/252923d6-dd41-468b-9c57-d3f68051a516)
@@ -126,22 +231,45 @@ impl TryFrom<i32> for AttachmentMethod {
     }
 }
 
+/// Default limit for [`Message::attachments`] on how deeply embedded messages may be nested, so
+/// that a malicious file cannot make a reader recurse without bound.
+pub const DEFAULT_MAX_EMBEDDED_DEPTH: usize = 16;
+
 pub enum AttachmentData {
     Binary(BinaryValue),
-    Message(Rc<dyn Message>),
+    Message(Arc<dyn Message>),
 }
 
-pub trait Attachment {
-    fn message(&self) -> Rc<dyn Message>;
+pub trait Attachment: Send + Sync {
+    fn message(&self) -> Arc<dyn Message>;
     fn properties(&self) -> &AttachmentProperties;
     fn data(&self) -> Option<&AttachmentData>;
+
+    /// Get the message stored in `PidTagAttachDataObject` for an [`AttachmentMethod::EmbeddedMessage`]
+    /// attachment. Its own attachments can be read with [`Message::attachments`].
+    fn embedded_message(&self) -> Option<Arc<dyn Message>> {
+        match self.data()? {
+            AttachmentData::Message(message) => Some(message.clone()),
+            AttachmentData::Binary(_) => None,
+        }
+    }
+}
+
+/// Check that an embedded message in a message at `depth` is within `max_depth`, and return the
+/// depth of the embedded message.
+fn embedded_depth(depth: usize, max_depth: usize) -> LtpResult<usize> {
+    let depth = depth + 1;
+    if depth > max_depth {
+        return Err(LtpError::EmbeddedMessageDepthExceeded(max_depth));
+    }
+    Ok(depth)
 }
 
 struct AttachmentInner<Pst>
 where
     Pst: PstFile,
 {
-    message: Rc<Pst::Message>,
+    message: Arc<Pst::Message>,
     properties: AttachmentProperties,
     data: Option<AttachmentData>,
 }
@@ -184,9 +312,10 @@ where
     <Pst as PstFile>::Message: MessageReadWrite<Pst> + 'static,
 {
     fn read(
-        message: Rc<<Pst as PstFile>::Message>,
+        message: Arc<<Pst as PstFile>::Message>,
         sub_node: NodeId,
         prop_ids: Option<&[u16]>,
+        max_depth: usize,
     ) -> io::Result<Self> {
         let node_id_type = sub_node.id_type()?;
         match node_id_type {
@@ -255,7 +384,7 @@ where
             let data = match attachment_method {
                 AttachmentMethod::ByValue => {
                     let binary_data = match properties
-                        .get(0x3701)
+                        .get(PR_ATTACH_DATA_BIN)
                         .ok_or(MessagingError::AttachmentMessageObjectDataNotFound)?
                     {
                         PropertyValue::Binary(value) => value,
@@ -270,7 +399,7 @@ where
                 }
                 AttachmentMethod::EmbeddedMessage => {
                     let object_data = match properties
-                        .get(0x3701)
+                        .get(PR_ATTACH_DATA_BIN)
                         .ok_or(MessagingError::AttachmentMessageObjectDataNotFound)?
                     {
                         PropertyValue::Object(value) => value,
@@ -282,6 +411,7 @@ where
                         }
                     };
 
+                    let depth = embedded_depth(message.embedded_depth(), max_depth)?;
                     let sub_node = object_data.node();
                     let node = message
                         .sub_nodes()
@@ -298,12 +428,13 @@ where
                             store.clone(),
                             node,
                             prop_ids,
+                            depth,
                         )?;
                     Some(AttachmentData::Message(message))
                 }
                 AttachmentMethod::Storage => {
                     let object_data = match properties
-                        .get(0x3701)
+                        .get(PR_ATTACH_DATA_BIN)
                         .ok_or(MessagingError::AttachmentMessageObjectDataNotFound)?
                     {
                         PropertyValue::Object(value) => value,
@@ -319,9 +450,13 @@ where
                         .sub_nodes()
                         .get(&sub_node)
                         .ok_or(MessagingError::AttachmentSubNodeNotFound(sub_node))?;
-                    let block =
-                        block_btree.find_entry(file, node.block().search_key(), &mut page_cache)?;
-                    let block = DataTree::read(file, encoding, &block)?;
+                    let block = DataTree::read_block(
+                        file,
+                        encoding,
+                        &block_btree,
+                        &mut page_cache,
+                        node.block(),
+                    )?;
                     let mut data = vec![];
                     let _ = block
                         .reader(
@@ -354,16 +489,21 @@ pub struct UnicodeAttachment {
 
 impl UnicodeAttachment {
     pub fn read(
-        message: Rc<UnicodeMessage>,
+        message: Arc<UnicodeMessage>,
         sub_node: NodeId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>> {
-        <Self as AttachmentReadWrite<UnicodePstFile>>::read(message, sub_node, prop_ids)
+    ) -> io::Result<Arc<Self>> {
+        <Self as AttachmentReadWrite<UnicodePstFile>>::read(
+            message,
+            sub_node,
+            prop_ids,
+            DEFAULT_MAX_EMBEDDED_DEPTH,
+        )
     }
 }
 
 impl Attachment for UnicodeAttachment {
-    fn message(&self) -> Rc<dyn Message> {
+    fn message(&self) -> Arc<dyn Message> {
         self.inner.message.clone()
     }
 
@@ -378,12 +518,13 @@ impl Attachment for UnicodeAttachment {
 
 impl AttachmentReadWrite<UnicodePstFile> for UnicodeAttachment {
     fn read(
-        message: Rc<UnicodeMessage>,
+        message: Arc<UnicodeMessage>,
         sub_node: NodeId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>> {
-        let inner = AttachmentInner::read(message, sub_node, prop_ids)?;
-        Ok(Rc::new(Self { inner }))
+        max_depth: usize,
+    ) -> io::Result<Arc<Self>> {
+        let inner = AttachmentInner::read(message, sub_node, prop_ids, max_depth)?;
+        Ok(Arc::new(Self { inner }))
     }
 }
 
@@ -393,16 +534,21 @@ pub struct AnsiAttachment {
 
 impl AnsiAttachment {
     pub fn read(
-        message: Rc<AnsiMessage>,
+        message: Arc<AnsiMessage>,
         sub_node: NodeId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>> {
-        <Self as AttachmentReadWrite<AnsiPstFile>>::read(message, sub_node, prop_ids)
+    ) -> io::Result<Arc<Self>> {
+        <Self as AttachmentReadWrite<AnsiPstFile>>::read(
+            message,
+            sub_node,
+            prop_ids,
+            DEFAULT_MAX_EMBEDDED_DEPTH,
+        )
     }
 }
 
 impl Attachment for AnsiAttachment {
-    fn message(&self) -> Rc<dyn Message> {
+    fn message(&self) -> Arc<dyn Message> {
         self.inner.message.clone()
     }
 
@@ -417,11 +563,87 @@ impl Attachment for AnsiAttachment {
 
 impl AttachmentReadWrite<AnsiPstFile> for AnsiAttachment {
     fn read(
-        message: Rc<AnsiMessage>,
+        message: Arc<AnsiMessage>,
         sub_node: NodeId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>> {
-        let inner = AttachmentInner::read(message, sub_node, prop_ids)?;
-        Ok(Rc::new(Self { inner }))
+        max_depth: usize,
+    ) -> io::Result<Arc<Self>> {
+        let inner = AttachmentInner::read(message, sub_node, prop_ids, max_depth)?;
+        Ok(Arc::new(Self { inner }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ltp::prop_context::UnicodeValue;
+
+    #[test]
+    fn test_embedded_depth() {
+        assert_eq!(embedded_depth(0, DEFAULT_MAX_EMBEDDED_DEPTH).unwrap(), 1);
+        assert_eq!(embedded_depth(1, 2).unwrap(), 2);
+        assert!(matches!(
+            embedded_depth(2, 2),
+            Err(LtpError::EmbeddedMessageDepthExceeded(2))
+        ));
+        assert!(matches!(
+            embedded_depth(0, 0),
+            Err(LtpError::EmbeddedMessageDepthExceeded(0))
+        ));
+    }
+
+    fn attachment_properties(values: &[(u16, &str)]) -> AttachmentProperties {
+        let properties = values
+            .iter()
+            .map(|(prop_id, value)| {
+                let value = UnicodeValue::new(value.encode_utf16().collect());
+                (*prop_id, PropertyValue::Unicode(value))
+            })
+            .collect();
+        AttachmentProperties { properties }
+    }
+
+    #[test]
+    fn test_suggested_filename() {
+        let properties = attachment_properties(&[
+            (PR_ATTACH_LONG_FILENAME, "Quarterly Report.docx"),
+            (PR_ATTACH_FILENAME, "QUARTE~1.DOC"),
+        ]);
+        assert_eq!(
+            properties.suggested_filename(0).unwrap(),
+            "Quarterly Report.docx"
+        );
+
+        let properties = attachment_properties(&[
+            (PR_ATTACH_LONG_FILENAME, ""),
+            (PR_ATTACH_FILENAME, "QUARTE~1.DOC"),
+        ]);
+        assert_eq!(properties.suggested_filename(0).unwrap(), "QUARTE~1.DOC");
+
+        let properties = attachment_properties(&[
+            (PR_ATTACH_EXTENSION, ".txt"),
+            (PR_ATTACH_MIME_TAG, "image/png"),
+        ]);
+        assert_eq!(
+            properties.suggested_filename(1).unwrap(),
+            "attachment_1.txt"
+        );
+
+        let properties = attachment_properties(&[
+            (PR_ATTACH_MIME_TAG, "image/JPEG; name=photo"),
+            (PR_ATTACH_CONTENT_ID, "image001.jpg@01D00000.00000000"),
+        ]);
+        assert_eq!(
+            properties.suggested_filename(2).unwrap(),
+            "attachment_2.jpg"
+        );
+        assert_eq!(
+            properties.content_id().unwrap().as_deref(),
+            Some("image001.jpg@01D00000.00000000")
+        );
+
+        let properties = attachment_properties(&[(PR_ATTACH_MIME_TAG, "application/x-unknown")]);
+        assert_eq!(properties.suggested_filename(3).unwrap(), "attachment_3");
+        assert_eq!(properties.content_id().unwrap(), None);
     }
 }
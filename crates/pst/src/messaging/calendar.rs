@@ -0,0 +1,468 @@
+//! ## Appointment and Meeting Objects
+//!
+//! Calendar items are described in [MS-OXOCAL](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxocal).
+
+use std::{
+    io::{self, Write},
+    sync::Arc,
+};
+
+use super::{
+    content_line::*, items::*, message::*, named_prop::*, property_ids::*, recurrence::*, store::*,
+    *,
+};
+use crate::{
+    ltp::{
+        prop_context::{GuidValue, PropertyValue, TypedProperties},
+        prop_type::PropertyType,
+    },
+    ndb::node_id::NodeId,
+};
+
+/// `PSETID_Appointment`
+pub const PSETID_APPOINTMENT: GuidValue = GuidValue::new(
+    0x00062002,
+    0x0000,
+    0x0000,
+    [0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+);
+
+/// `PidLidLocation`
+pub const PID_LID_LOCATION: u32 = 0x8208;
+/// `PidLidAppointmentStartWhole`
+pub const PID_LID_APPOINTMENT_START_WHOLE: u32 = 0x820D;
+/// `PidLidAppointmentEndWhole`
+pub const PID_LID_APPOINTMENT_END_WHOLE: u32 = 0x820E;
+/// `PidLidAppointmentSubType`
+pub const PID_LID_APPOINTMENT_SUB_TYPE: u32 = 0x8215;
+/// `PidLidAppointmentRecur`
+pub const PID_LID_APPOINTMENT_RECUR: u32 = 0x8216;
+/// `PidLidTimeZoneDescription`
+pub const PID_LID_TIME_ZONE_DESCRIPTION: u32 = 0x8234;
+
+/// The `PSETID_Appointment` named properties, resolved to the property IDs used in one store.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct AppointmentPropertyIds {
+    location: Option<u16>,
+    start_whole: Option<u16>,
+    end_whole: Option<u16>,
+    sub_type: Option<u16>,
+    recur: Option<u16>,
+    time_zone_description: Option<u16>,
+}
+
+impl AppointmentPropertyIds {
+    pub fn resolve(named_props: &dyn NamedPropertyMap) -> io::Result<Self> {
+        let find = |lid| named_props.find_numeric_id(&PSETID_APPOINTMENT, lid);
+        Ok(Self {
+            location: find(PID_LID_LOCATION)?,
+            start_whole: find(PID_LID_APPOINTMENT_START_WHOLE)?,
+            end_whole: find(PID_LID_APPOINTMENT_END_WHOLE)?,
+            sub_type: find(PID_LID_APPOINTMENT_SUB_TYPE)?,
+            recur: find(PID_LID_APPOINTMENT_RECUR)?,
+            time_zone_description: find(PID_LID_TIME_ZONE_DESCRIPTION)?,
+        })
+    }
+
+    pub fn location(&self) -> Option<u16> {
+        self.location
+    }
+
+    pub fn start_whole(&self) -> Option<u16> {
+        self.start_whole
+    }
+
+    pub fn end_whole(&self) -> Option<u16> {
+        self.end_whole
+    }
+
+    pub fn sub_type(&self) -> Option<u16> {
+        self.sub_type
+    }
+
+    pub fn recur(&self) -> Option<u16> {
+        self.recur
+    }
+
+    pub fn time_zone_description(&self) -> Option<u16> {
+        self.time_zone_description
+    }
+}
+
+/// A calendar item, with the `PSETID_Appointment` named properties looked up through the
+/// store's [`NamedPropertyMap`].
+pub struct Appointment {
+    message: Arc<dyn Message>,
+    title: Option<String>,
+    location: Option<String>,
+    start: Option<PropertyValue>,
+    end: Option<PropertyValue>,
+    time_zone: Option<String>,
+    all_day: bool,
+    recurrence: Option<Vec<u8>>,
+}
+
+impl Appointment {
+    pub fn open(store: &dyn Store, node_id: NodeId) -> io::Result<Self> {
+        let prop_ids = AppointmentPropertyIds::resolve(&*store.named_property_map()?)?;
+        let entry_id = store.properties().make_entry_id(node_id)?;
+        let message = store.open_message(&entry_id, None)?;
+        Self::read(message, &prop_ids)
+    }
+
+    /// Read the appointment properties from a message which is already open, using `prop_ids`
+    /// resolved from the same store. Fails with [`MessagingError::UnexpectedMessageClass`] if
+    /// the message is not an [`IPM_APPOINTMENT`].
+    pub fn read(message: Arc<dyn Message>, prop_ids: &AppointmentPropertyIds) -> io::Result<Self> {
+        let properties = message.properties();
+        check_message_class(properties, IPM_APPOINTMENT)?;
+
+        // PidTagSubject may start with 0x01 and the length of the prefix, which is not part of
+        // the title.
+        let title = properties.get_string8(PR_SUBJECT)?.map(|subject| {
+            if subject.starts_with('\u{1}') {
+                subject.chars().skip(2).collect()
+            } else {
+                subject
+            }
+        });
+
+        let location = prop_ids
+            .location
+            .map(|prop_id| properties.get_string8(prop_id))
+            .transpose()?
+            .flatten();
+
+        let time_zone = prop_ids
+            .time_zone_description
+            .map(|prop_id| properties.get_string8(prop_id))
+            .transpose()?
+            .flatten();
+
+        let get_time = |prop_id: Option<u16>, lid| -> MessagingResult<Option<PropertyValue>> {
+            match prop_id.and_then(|prop_id| properties.get(prop_id)) {
+                None => Ok(None),
+                Some(value @ PropertyValue::Time(_)) => Ok(Some(value.clone())),
+                Some(invalid) => Err(MessagingError::InvalidAppointmentProperty(
+                    lid,
+                    PropertyType::from(invalid),
+                )),
+            }
+        };
+        let start = get_time(prop_ids.start_whole, PID_LID_APPOINTMENT_START_WHOLE)?;
+        let end = get_time(prop_ids.end_whole, PID_LID_APPOINTMENT_END_WHOLE)?;
+
+        let all_day = match prop_ids
+            .sub_type
+            .and_then(|prop_id| properties.get(prop_id))
+        {
+            None => false,
+            Some(PropertyValue::Boolean(value)) => *value,
+            Some(invalid) => {
+                return Err(MessagingError::InvalidAppointmentProperty(
+                    PID_LID_APPOINTMENT_SUB_TYPE,
+                    PropertyType::from(invalid),
+                )
+                .into())
+            }
+        };
+
+        let recurrence = prop_ids
+            .recur
+            .map(|prop_id| properties.get_typed::<Vec<u8>>(prop_id))
+            .transpose()?
+            .flatten();
+
+        Ok(Self {
+            message,
+            title,
+            location,
+            start,
+            end,
+            time_zone,
+            all_day,
+            recurrence,
+        })
+    }
+
+    pub fn message(&self) -> &Arc<dyn Message> {
+        &self.message
+    }
+
+    /// `PidTagSubject`, without any prefix marker.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// `PidLidLocation`
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    /// `PidLidAppointmentStartWhole`, as a [`PropertyValue::Time`].
+    pub fn start(&self) -> Option<&PropertyValue> {
+        self.start.as_ref()
+    }
+
+    /// `PidLidAppointmentEndWhole`, as a [`PropertyValue::Time`].
+    pub fn end(&self) -> Option<&PropertyValue> {
+        self.end.as_ref()
+    }
+
+    /// `PidLidTimeZoneDescription`, the display name of the time zone the appointment was
+    /// created in. [`Self::start`] and [`Self::end`] are always in UTC.
+    pub fn time_zone(&self) -> Option<&str> {
+        self.time_zone.as_deref()
+    }
+
+    /// `PidLidAppointmentSubType`, which is `true` for all-day events.
+    pub fn is_all_day(&self) -> bool {
+        self.all_day
+    }
+
+    /// The raw `PidLidAppointmentRecur` blob, if this is a recurring appointment.
+    pub fn recurrence(&self) -> Option<&[u8]> {
+        self.recurrence.as_deref()
+    }
+
+    /// Parse [`Self::recurrence`], or return `None` if this is not a recurring appointment.
+    pub fn recurrence_pattern(&self) -> io::Result<Option<RecurrencePattern>> {
+        self.recurrence
+            .as_deref()
+            .map(RecurrencePattern::parse)
+            .transpose()
+            .map_err(|err| MessagingError::InvalidRecurrencePattern(err).into())
+    }
+
+    /// Write the appointment as an [iCalendar](https://www.rfc-editor.org/rfc/rfc5545) object
+    /// with a single `VEVENT`. `DTSTART` and `DTEND` are written in UTC.
+    ///
+    /// A recurring appointment also gets an `RRULE` from [`RecurrencePattern::to_rrule`]. If the
+    /// recurrence blob cannot be parsed, only the first instance is written and
+    /// [`IcsExport::recurrence_dropped`] is set on the result. Deleted and modified instances
+    /// are not exported.
+    pub fn to_ics(&self, writer: &mut impl Write) -> io::Result<IcsExport> {
+        let properties = self.message.properties();
+
+        write_line(writer, "BEGIN", "VCALENDAR")?;
+        write_line(writer, "VERSION", "2.0")?;
+        write_text(writer, "PRODID", ICS_PRODUCT_ID)?;
+        write_line(writer, "BEGIN", "VEVENT")?;
+
+        if let Some(search_key) = properties.get_typed::<Vec<u8>>(PR_SEARCH_KEY)? {
+            let uid: String = search_key
+                .iter()
+                .map(|byte| format!("{byte:02X}"))
+                .collect();
+            write_line(writer, "UID", &uid)?;
+        }
+
+        let timestamp = [PR_LAST_MODIFICATION_TIME, PR_CREATION_TIME]
+            .into_iter()
+            .find_map(|prop_id| match properties.get(prop_id) {
+                Some(PropertyValue::Time(value)) => Some(*value),
+                _ => None,
+            });
+        if let Some(timestamp) = timestamp {
+            write_line(writer, "DTSTAMP", &format_date_time(timestamp))?;
+        }
+
+        for (name, value) in [("DTSTART", &self.start), ("DTEND", &self.end)] {
+            if let Some(PropertyValue::Time(value)) = value {
+                write_line(writer, name, &format_date_time(*value))?;
+            }
+        }
+
+        let recurrence_dropped = match self.recurrence_pattern() {
+            Ok(Some(pattern)) => {
+                write_line(writer, "RRULE", &pattern.to_rrule())?;
+                false
+            }
+            Ok(None) => false,
+            Err(_) => true,
+        };
+
+        if let Some(title) = self.title.as_deref() {
+            write_text(writer, "SUMMARY", title)?;
+        }
+        if let Some(location) = self.location.as_deref() {
+            write_text(writer, "LOCATION", location)?;
+        }
+        if let Some(body) = properties.get_string8(PR_BODY)? {
+            write_text(writer, "DESCRIPTION", &body)?;
+        }
+
+        write_line(writer, "END", "VEVENT")?;
+        write_line(writer, "END", "VCALENDAR")?;
+
+        Ok(IcsExport { recurrence_dropped })
+    }
+}
+
+/// `PRODID` written by [`Appointment::to_ics`] and [`Task::to_ics`](super::items::Task::to_ics).
+pub(crate) const ICS_PRODUCT_ID: &str = "-//outlook-pst-rs//outlook-pst//EN";
+
+/// The result of [`Appointment::to_ics`].
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct IcsExport {
+    recurrence_dropped: bool,
+}
+
+impl IcsExport {
+    /// `true` if the appointment has a recurrence pattern which could not be parsed, so only
+    /// the first instance was exported.
+    pub fn recurrence_dropped(&self) -> bool {
+        self.recurrence_dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ltp::prop_context::BinaryValue,
+        messaging::items::tests::{message, time, unicode},
+        ndb::node_id::NodeIdType,
+        test_util::EMPTY_PST,
+        UnicodePstFile,
+    };
+
+    #[test]
+    fn test_open_appointment() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+
+        let named_props = store.named_property_map().unwrap();
+        let prop_ids = AppointmentPropertyIds::resolve(&*named_props).unwrap();
+        for prop_id in [
+            prop_ids.location(),
+            prop_ids.start_whole(),
+            prop_ids.end_whole(),
+            prop_ids.sub_type(),
+            prop_ids.recur(),
+            prop_ids.time_zone_description(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            assert!(prop_id >= 0x8000);
+        }
+
+        let missing = NodeId::new(NodeIdType::NormalMessage, 0x7FFF).unwrap();
+        assert!(Appointment::open(store.as_ref(), missing).is_err());
+    }
+
+    #[test]
+    fn test_appointment_to_ics() {
+        let prop_ids = AppointmentPropertyIds::default();
+        let appointment = message(
+            "IPM.Appointment",
+            [
+                (PR_SUBJECT, unicode("Review, part 1")),
+                (PR_BODY, unicode("Agenda:\r\n1; 2")),
+                (
+                    PR_SEARCH_KEY,
+                    PropertyValue::Binary(BinaryValue::new(vec![0x01, 0xAB])),
+                ),
+                (PR_LAST_MODIFICATION_TIME, time(1_704_067_200)),
+            ],
+        );
+        let appointment = Appointment::read(appointment, &prop_ids).unwrap();
+
+        let mut ics = Vec::new();
+        let export = appointment.to_ics(&mut ics).unwrap();
+        assert!(!export.recurrence_dropped());
+        assert_eq!(
+            String::from_utf8(ics).unwrap(),
+            concat!(
+                "BEGIN:VCALENDAR\r\n",
+                "VERSION:2.0\r\n",
+                "PRODID:-//outlook-pst-rs//outlook-pst//EN\r\n",
+                "BEGIN:VEVENT\r\n",
+                "UID:01AB\r\n",
+                "DTSTAMP:20240101T000000Z\r\n",
+                "SUMMARY:Review\\, part 1\r\n",
+                "DESCRIPTION:Agenda:\\n1\\; 2\r\n",
+                "END:VEVENT\r\n",
+                "END:VCALENDAR\r\n",
+            )
+        );
+    }
+
+    /// A daily `AppointmentRecurrencePattern` which ends after 3 instances, from 9:00 to 9:30
+    /// starting on January 1, 2024.
+    fn daily_recurrence() -> Vec<u8> {
+        let start_date = 222_475_680_u32;
+        let mut data = Vec::new();
+        for value in [0x3004_u16, 0x3004, 0x200A, 0x0000, 0x0000] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [
+            0,
+            1_440,
+            0,
+            0x2022,
+            3,
+            0,
+            0,
+            0,
+            start_date,
+            start_date + 2 * 1_440,
+            0x3006,
+            0x3009,
+            540,
+            570,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&0_u16.to_le_bytes());
+        data.extend_from_slice(&[0; 8]);
+        data
+    }
+
+    #[test]
+    fn test_recurring_appointment_to_ics() {
+        let prop_ids = AppointmentPropertyIds {
+            location: Some(0x8001),
+            start_whole: Some(0x8002),
+            end_whole: Some(0x8003),
+            recur: Some(0x8004),
+            ..Default::default()
+        };
+        let appointment = |recurrence: Vec<u8>| {
+            let appointment = message(
+                "IPM.Appointment",
+                [
+                    (PR_SUBJECT, unicode("Standup")),
+                    (0x8001, unicode("Room 1")),
+                    (0x8002, time(1_704_099_600)),
+                    (0x8003, time(1_704_101_400)),
+                    (0x8004, PropertyValue::Binary(BinaryValue::new(recurrence))),
+                ],
+            );
+            Appointment::read(appointment, &prop_ids).unwrap()
+        };
+
+        let recurring = appointment(daily_recurrence());
+        let pattern = recurring.recurrence_pattern().unwrap().unwrap();
+        assert_eq!(pattern.end(), RecurrenceEnd::Count(3));
+        let mut ics = Vec::new();
+        let export = recurring.to_ics(&mut ics).unwrap();
+        assert!(!export.recurrence_dropped());
+        let ics = String::from_utf8(ics).unwrap();
+        assert!(
+            ics.contains("\r\nDTEND:20240101T093000Z\r\nRRULE:FREQ=DAILY;INTERVAL=1;COUNT=3\r\n")
+        );
+
+        let invalid = appointment(vec![0xFF; 8]);
+        assert!(invalid.recurrence_pattern().is_err());
+        let mut ics = Vec::new();
+        let export = invalid.to_ics(&mut ics).unwrap();
+        assert!(export.recurrence_dropped());
+        let ics = String::from_utf8(ics).unwrap();
+        assert!(ics.contains("\r\nDTSTART:20240101T090000Z\r\n"));
+        assert!(ics.contains("\r\nDTEND:20240101T093000Z\r\n"));
+        assert!(ics.contains("\r\nLOCATION:Room 1\r\n"));
+        assert!(!ics.contains("RRULE"));
+    }
+}
@@ -0,0 +1,149 @@
+//! ## vCard and iCalendar Content Lines
+//!
+//! Both [RFC 2426](https://www.rfc-editor.org/rfc/rfc2426) (vCard 3.0) and
+//! [RFC 5545](https://www.rfc-editor.org/rfc/rfc5545) (iCalendar) serialize objects as a
+//! sequence of `NAME;PARAM=VALUE:value` lines, terminated with CRLF and folded at 75 octets.
+
+use std::io::{self, Write};
+
+use super::time::UtcDateTime;
+
+/// Maximum length of a content line in octets, not counting the CRLF.
+const MAX_LINE_LENGTH: usize = 75;
+
+/// Escape a `TEXT` value: backslashes, semicolons and commas are prefixed with a backslash,
+/// and line breaks become `\n`.
+pub(crate) fn escape_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                escaped.push_str("\\n");
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Format a `FILETIME` as an iCalendar `DATE-TIME` in UTC, e.g. `20240101T090000Z`.
+pub(crate) fn format_date_time(filetime: i64) -> String {
+    let UtcDateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        ..
+    } = UtcDateTime::from_filetime(filetime);
+    format!("{year:04}{month:02}{day:02}T{hour:02}{minute:02}{second:02}Z")
+}
+
+/// Format the UTC date of a `FILETIME` as an iCalendar `DATE`, e.g. `20240101`.
+pub(crate) fn format_date(filetime: i64) -> String {
+    let UtcDateTime {
+        year, month, day, ..
+    } = UtcDateTime::from_filetime(filetime);
+    format!("{year:04}{month:02}{day:02}")
+}
+
+/// Write a single content line, folding it with CRLF followed by a space so that no physical
+/// line is longer than 75 octets. Multi-byte UTF-8 sequences are never split. `value` must
+/// already be escaped.
+pub(crate) fn write_line(writer: &mut dyn Write, name: &str, value: &str) -> io::Result<()> {
+    let line = format!("{name}:{value}");
+    let mut remaining = line.as_str();
+    let mut limit = MAX_LINE_LENGTH;
+    loop {
+        if remaining.len() <= limit {
+            writer.write_all(remaining.as_bytes())?;
+            return writer.write_all(b"\r\n");
+        }
+
+        let mut split = limit;
+        while !remaining.is_char_boundary(split) {
+            split -= 1;
+        }
+        let (head, tail) = remaining.split_at(split);
+        writer.write_all(head.as_bytes())?;
+        writer.write_all(b"\r\n ")?;
+        remaining = tail;
+
+        // Continuation lines start with the space.
+        limit = MAX_LINE_LENGTH - 1;
+    }
+}
+
+/// Write a content line with a `TEXT` value, escaping it first.
+pub(crate) fn write_text(writer: &mut dyn Write, name: &str, value: &str) -> io::Result<()> {
+    write_line(writer, name, &escape_text(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(name: &str, value: &str) -> String {
+        let mut buffer = Vec::new();
+        write_line(&mut buffer, name, value).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_escape_text() {
+        assert_eq!(escape_text("plain"), "plain");
+        assert_eq!(escape_text("Smith, John; Jr."), "Smith\\, John\\; Jr.");
+        assert_eq!(escape_text("C:\\temp"), "C:\\\\temp");
+        assert_eq!(
+            escape_text("one\r\ntwo\nthree\rfour"),
+            "one\\ntwo\\nthree\\nfour"
+        );
+    }
+
+    #[test]
+    fn test_write_short_line() {
+        assert_eq!(lines("SUMMARY", "Standup"), "SUMMARY:Standup\r\n");
+    }
+
+    #[test]
+    fn test_fold_long_line() {
+        let value = "x".repeat(200);
+        let folded = lines("DESCRIPTION", &value);
+        let physical: Vec<_> = folded.split("\r\n").collect();
+        assert_eq!(physical.last(), Some(&""));
+        for line in &physical[1..physical.len() - 1] {
+            assert!(line.starts_with(' '));
+        }
+        assert!(physical.iter().all(|line| line.len() <= MAX_LINE_LENGTH));
+
+        let unfolded = folded.trim_end_matches("\r\n").replace("\r\n ", "");
+        assert_eq!(unfolded, format!("DESCRIPTION:{value}"));
+    }
+
+    #[test]
+    fn test_fold_multi_byte() {
+        // Each of these is 3 octets in UTF-8, so 75 octets would fall inside a character.
+        let value = "\u{65e5}".repeat(40);
+        let folded = lines("SUMMARY", &value);
+        for line in folded.split("\r\n") {
+            assert!(line.len() <= MAX_LINE_LENGTH);
+        }
+        let unfolded = folded.trim_end_matches("\r\n").replace("\r\n ", "");
+        assert_eq!(unfolded, format!("SUMMARY:{value}"));
+    }
+
+    #[test]
+    fn test_format_date_time() {
+        // 2024-01-01T09:30:15Z
+        let filetime = 133_485_750_150_000_000;
+        assert_eq!(format_date_time(filetime), "20240101T093015Z");
+    }
+}
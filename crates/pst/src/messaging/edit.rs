@@ -0,0 +1,2573 @@
+//! ## Edit Stores
+//!
+//! Make changes to the folders and messages in a Unicode PST file.
+//!
+//! Every edit is applied in place, in a single [`WriteTransaction`](crate::WriteTransaction). The
+//! property and table contexts which change are written to new blocks, and then replace the old
+//! nodes in the node BTree. Every other node is left alone. If anything fails, the transaction is
+//! rolled back and the file is unchanged.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::Path,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use super::{
+    attachment::AttachmentMethod,
+    folder::{Folder, UnicodeFolder},
+    items::{IPF_NOTE, IPM_NOTE},
+    message::{Message, UnicodeMessage},
+    property_ids::*,
+    read_write::{FolderReadWrite, MessageReadWrite, StoreKeyReadWrite},
+    recipient::RecipientType,
+    search::MessageRef,
+    store::{EntryId, StoreRecordKey, UnicodeStore},
+    time::system_time_to_filetime,
+    MessagingError,
+};
+use crate::{
+    ltp::{
+        heap::*,
+        prop_context::*,
+        prop_type::PropertyType,
+        read_write::{HeapNodePageReadWrite, TableContextInfoReadWrite},
+        table_context::*,
+        tree::*,
+        LtpError, LtpResult,
+    },
+    ndb::{block::UnicodeDataTree, header::*, node_id::*},
+    upgrade::{
+        is_hnid_column, split_data, NodeContents, NodeReader, NodeSink, NodeSource, NodeWriter,
+    },
+    MemoryFile, PstFile, UnicodePstFile, AMAP_DATA_SIZE, AMAP_FIRST_OFFSET,
+};
+
+/// Create an empty folder named `display_name` under `parent` in `pst`, with `PR_CONTAINER_CLASS`
+/// set to `container_class`, and return its [`NodeId`], see [`UnicodePstFile::create_folder`].
+pub(crate) fn create_folder(
+    pst: &mut UnicodePstFile,
+    parent: NodeId,
+    display_name: &str,
+    container_class: &str,
+) -> io::Result<NodeId> {
+    check_new_folder(parent, display_name)?;
+    edit_store(pst, |editor| {
+        add_folder(editor, parent, display_name, container_class)
+    })
+}
+
+fn check_new_folder(parent: NodeId, display_name: &str) -> io::Result<()> {
+    if display_name.is_empty() || display_name.contains(['/', '\\']) {
+        return Err(MessagingError::InvalidFolderName(display_name.to_string()).into());
+    }
+    check_folder_id(parent)
+}
+
+/// Add the PC and TCs of a new folder under `parent` to `editor`, for [`create_folder`] and
+/// [`UnicodeFolder::create_sub_folder_with_class`].
+fn add_folder(
+    editor: &mut StoreEditor<'_>,
+    parent: NodeId,
+    display_name: &str,
+    container_class: &str,
+) -> io::Result<NodeId> {
+    if editor.find_sub_folder(parent, display_name)?.is_some() {
+        return Err(MessagingError::DuplicateFolderName(display_name.to_string()).into());
+    }
+
+    let max_data_size = editor.max_data_size();
+    let folder = editor.new_node_id(NodeIdType::NormalFolder)?;
+    let mut properties = PropertyNode {
+        node: folder,
+        parent: Some(parent),
+        properties: Default::default(),
+        sub_nodes: Default::default(),
+    };
+    properties.set(
+        PR_DISPLAY_NAME,
+        PropertyType::Unicode,
+        PropertyData::Bytes(unicode_bytes(display_name)),
+    );
+    properties.set(
+        PR_CONTAINER_CLASS,
+        PropertyType::Unicode,
+        PropertyData::Bytes(unicode_bytes(container_class)),
+    );
+    for prop_id in [PR_CONTENT_COUNT, PR_CONTENT_UNREAD] {
+        properties.set(prop_id, PropertyType::Integer32, PropertyData::Small(0));
+    }
+    properties.set(PR_SUBFOLDERS, PropertyType::Boolean, PropertyData::Small(0));
+    editor.set_folder(properties);
+
+    for (id_type, template) in [
+        (NodeIdType::HierarchyTable, NID_HIERARCHY_TABLE_TEMPLATE),
+        (NodeIdType::ContentsTable, NID_CONTENTS_TABLE_TEMPLATE),
+        (
+            NodeIdType::AssociatedContentsTable,
+            NID_ASSOC_CONTENTS_TABLE_TEMPLATE,
+        ),
+    ] {
+        let mut table = TableNode::read(&editor.read_node(template)?, max_data_size)?;
+        table.node = table_id(id_type, folder)?;
+        table.rows.clear();
+        editor.set_table(table);
+    }
+    editor.update_hierarchy_row(folder)?;
+
+    editor
+        .folder(parent)?
+        .set(PR_SUBFOLDERS, PropertyType::Boolean, PropertyData::Small(1));
+    editor.update_hierarchy_row(parent)?;
+    Ok(folder)
+}
+
+/// The properties, recipients and attachments of a new message for
+/// [`UnicodeFolder::add_message`].
+#[derive(Clone, Debug)]
+pub struct MessageBuilder {
+    message_class: String,
+    subject: Option<String>,
+    body: Option<String>,
+    html_body: Option<String>,
+    read: bool,
+    recipients: Vec<(RecipientType, String, String)>,
+    attachments: Vec<(String, Vec<u8>)>,
+}
+
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self {
+            message_class: IPM_NOTE.to_string(),
+            subject: None,
+            body: None,
+            html_body: None,
+            read: false,
+            recipients: Default::default(),
+            attachments: Default::default(),
+        }
+    }
+}
+
+impl MessageBuilder {
+    /// Start an unread `IPM.Note` message with no other properties.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set `PR_MESSAGE_CLASS`, e.g. `IPM.Note`.
+    pub fn message_class(mut self, message_class: &str) -> Self {
+        self.message_class = message_class.to_string();
+        self
+    }
+
+    /// Set `PR_SUBJECT`.
+    pub fn subject(mut self, subject: &str) -> Self {
+        self.subject = Some(subject.to_string());
+        self
+    }
+
+    /// Set the plain text `PR_BODY`.
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = Some(body.to_string());
+        self
+    }
+
+    /// Set `PR_HTML`, which is stored as UTF-8 with `PR_INTERNET_CPID` set to match.
+    pub fn html_body(mut self, html_body: &str) -> Self {
+        self.html_body = Some(html_body.to_string());
+        self
+    }
+
+    /// Set `MSGFLAG_READ` in `PR_MESSAGE_FLAGS`.
+    pub fn read(mut self, read: bool) -> Self {
+        self.read = read;
+        self
+    }
+
+    /// Add an SMTP recipient to the recipient table.
+    pub fn recipient(
+        mut self,
+        recipient_type: RecipientType,
+        display_name: &str,
+        email_address: &str,
+    ) -> Self {
+        self.recipients.push((
+            recipient_type,
+            display_name.to_string(),
+            email_address.to_string(),
+        ));
+        self
+    }
+
+    /// Add a file attachment, which is stored by value in `PR_ATTACH_DATA_BIN`.
+    pub fn attachment(mut self, file_name: &str, data: Vec<u8>) -> Self {
+        self.attachments.push((file_name.to_string(), data));
+        self
+    }
+
+    /// Build the PC of the message, with its recipient table, attachment table and attachments
+    /// as sub-nodes. The tables are copies of the templates in `editor` without any rows.
+    fn build(
+        &self,
+        editor: &mut StoreEditor<'_>,
+        node: NodeId,
+        folder: NodeId,
+    ) -> io::Result<PropertyNode> {
+        let max_data_size = editor.max_data_size();
+        let now = system_time_to_filetime(SystemTime::now())
+            .to_le_bytes()
+            .to_vec();
+        let unicode = |value: &str| {
+            (
+                PropertyType::Unicode,
+                PropertyData::Bytes(unicode_bytes(value)),
+            )
+        };
+        let integer = |value: i32| (PropertyType::Integer32, PropertyData::Small(value as u32));
+        let boolean = |value: bool| (PropertyType::Boolean, PropertyData::Small(u32::from(value)));
+        let new_node = |node| PropertyNode {
+            node,
+            parent: None,
+            properties: Default::default(),
+            sub_nodes: Default::default(),
+        };
+
+        let mut recipient_table =
+            TableNode::read(&editor.read_node(NID_RECIPIENT_TABLE)?, max_data_size)?;
+        recipient_table.rows.clear();
+        for (index, (recipient_type, display_name, email_address)) in
+            self.recipients.iter().enumerate()
+        {
+            let mut recipient = new_node(NodeId::from(index as u32));
+            recipient.properties.extend([
+                (PR_RECIPIENT_TYPE, integer(*recipient_type as i32)),
+                (PR_DISPLAY_NAME, unicode(display_name)),
+                (PR_ADDRTYPE, unicode("SMTP")),
+                (PR_EMAIL_ADDRESS, unicode(email_address)),
+                (PR_OBJECT_TYPE, integer(MAPI_MAILUSER)),
+                (PR_DISPLAY_TYPE, integer(DT_MAILUSER)),
+                (PR_RESPONSIBILITY, boolean(false)),
+            ]);
+            let row = recipient_table.row_from_properties(recipient.node, &recipient)?;
+            recipient_table.rows.push(row);
+        }
+
+        let mut message = new_node(node);
+        message.parent = Some(folder);
+        message
+            .sub_nodes
+            .push(recipient_table.write(max_data_size, &mut 1)?);
+
+        if !self.attachments.is_empty() {
+            let mut attachment_table =
+                TableNode::read(&editor.read_node(NID_ATTACHMENT_TABLE)?, max_data_size)?;
+            attachment_table.rows.clear();
+            for (index, (file_name, data)) in self.attachments.iter().enumerate() {
+                let mut attachment =
+                    new_node(NodeId::new(NodeIdType::Attachment, index as u32 + 1)?);
+                attachment.properties.extend([
+                    (PR_ATTACH_METHOD, integer(AttachmentMethod::ByValue as i32)),
+                    (PR_ATTACH_FILENAME, unicode(file_name)),
+                    (PR_ATTACH_LONG_FILENAME, unicode(file_name)),
+                    (PR_ATTACH_SIZE, integer(data.len() as i32)),
+                    (PR_ATTACH_NUM, integer(index as i32)),
+                    (PR_RENDERING_POSITION, integer(-1)),
+                    (PR_OBJECT_TYPE, integer(MAPI_ATTACH)),
+                    (
+                        PR_ATTACH_DATA_BIN,
+                        (PropertyType::Binary, PropertyData::Bytes(data.clone())),
+                    ),
+                ]);
+                let row = attachment_table.row_from_properties(attachment.node, &attachment)?;
+                attachment_table.rows.push(row);
+                message
+                    .sub_nodes
+                    .push(attachment.write(max_data_size, &mut 1)?);
+            }
+            message
+                .sub_nodes
+                .push(attachment_table.write(max_data_size, &mut 1)?);
+        }
+
+        let mut flags = 0;
+        if self.read {
+            flags |= MSGFLAG_READ;
+        }
+        if !self.attachments.is_empty() {
+            flags |= MSGFLAG_HASATTACH;
+        }
+        let display = |recipient_type| {
+            self.recipients
+                .iter()
+                .filter(|(existing, ..)| *existing == recipient_type)
+                .map(|(_, display_name, _)| display_name.as_str())
+                .collect::<Vec<_>>()
+                .join("; ")
+        };
+        message.properties.extend([
+            (PR_MESSAGE_CLASS, unicode(&self.message_class)),
+            (PR_MESSAGE_FLAGS, integer(flags)),
+            (PR_HASATTACH, boolean(!self.attachments.is_empty())),
+            (PR_DISPLAY_TO, unicode(&display(RecipientType::To))),
+            (PR_DISPLAY_CC, unicode(&display(RecipientType::Cc))),
+            (PR_DISPLAY_BCC, unicode(&display(RecipientType::Bcc))),
+        ]);
+        for prop_id in [PR_CREATION_TIME, PR_LAST_MODIFICATION_TIME] {
+            message.set(
+                prop_id,
+                PropertyType::Time,
+                PropertyData::Bytes(now.clone()),
+            );
+        }
+        if let Some(subject) = self.subject.as_deref() {
+            message.properties.insert(PR_SUBJECT, unicode(subject));
+        }
+        if let Some(body) = self.body.as_deref() {
+            message.properties.insert(PR_BODY, unicode(body));
+        }
+        if let Some(html_body) = self.html_body.as_deref() {
+            message.properties.extend([
+                (
+                    PR_HTML_BODY,
+                    (
+                        PropertyType::Binary,
+                        PropertyData::Bytes(html_body.as_bytes().to_vec()),
+                    ),
+                ),
+                (PR_INTERNET_CPID, integer(65001)),
+            ]);
+        }
+
+        let size = message
+            .properties
+            .values()
+            .map(|(_, value)| match value {
+                PropertyData::Small(_) => 4,
+                PropertyData::Bytes(data) => data.len(),
+            })
+            .chain(self.attachments.iter().map(|(_, data)| data.len()))
+            .sum::<usize>();
+        message.set(
+            PR_MESSAGE_SIZE,
+            PropertyType::Integer32,
+            PropertyData::Small(u32::try_from(size).unwrap_or(u32::MAX)),
+        );
+
+        Ok(message)
+    }
+}
+
+/// The edits go through the file handle of the store this folder was read from, and the folders
+/// passed to them must come from the same store, or they fail with
+/// [`MessagingError::EditWrongFile`]. The folders which are passed by `&mut` are
+/// read again after the edit. Any other folders and messages which were already read from the
+/// store do not see the changes; read them again to see them.
+impl UnicodeFolder {
+    /// Change the display name of this folder, in both the folder's PC and its row in the
+    /// hierarchy table of its parent folder, in one transaction. The new name must not be empty,
+    /// or contain a `/` or `\`, since those separate the folders in a path.
+    pub fn rename(&mut self, new_name: &str) -> io::Result<()> {
+        if new_name.is_empty() || new_name.contains(['/', '\\']) {
+            return Err(MessagingError::InvalidFolderName(new_name.to_string()).into());
+        }
+        let folder = self.properties().node_id();
+        check_folder_id(folder)?;
+
+        edit_in_place(self.pst_store(), |editor| {
+            editor.folder(folder)?.set(
+                PR_DISPLAY_NAME,
+                PropertyType::Unicode,
+                PropertyData::Bytes(unicode_bytes(new_name)),
+            );
+            editor.update_hierarchy_row(folder)
+        })?;
+        self.reload()
+    }
+
+    /// Move this folder so it becomes a sub-folder of `new_parent`. The folder's row moves from
+    /// the hierarchy table of its old parent to the hierarchy table of `new_parent`, and
+    /// `PR_SUBFOLDERS` is updated on both parents, all in one transaction. Moving a folder into
+    /// itself or any of its own sub-folders fails before anything is changed.
+    pub fn move_to(&mut self, new_parent: &mut UnicodeFolder) -> io::Result<()> {
+        check_same_store(self.pst_store(), new_parent.pst_store())?;
+        let folder = self.properties().node_id();
+        let parent_folder = new_parent;
+        let new_parent = parent_folder.properties().node_id();
+        check_folder_id(folder)?;
+        check_folder_id(new_parent)?;
+
+        edit_in_place(self.pst_store(), |editor| {
+            // Walk up from the destination to the root folder, which is its own parent. If we pass
+            // through the folder we are moving, it would become its own ancestor.
+            let mut ancestors = BTreeSet::new();
+            let mut ancestor = new_parent;
+            while ancestors.insert(u32::from(ancestor)) {
+                if ancestor == folder {
+                    return Err(MessagingError::FolderMoveCycle(u32::from(folder)).into());
+                }
+                match editor.folder(ancestor)?.parent {
+                    Some(parent) => ancestor = parent,
+                    None => break,
+                }
+            }
+
+            let old_parent = match editor.folder(folder)?.parent {
+                Some(parent) if parent != folder => parent,
+                _ => return Err(MessagingError::FolderMoveCycle(u32::from(folder)).into()),
+            };
+            if old_parent == new_parent {
+                return Ok(());
+            }
+
+            let old_hierarchy = table_id(NodeIdType::HierarchyTable, old_parent)?;
+            editor.table(old_hierarchy)?.remove_row(folder);
+
+            editor.folder(folder)?.set_parent(new_parent)?;
+            editor.update_hierarchy_row(folder)?;
+
+            for parent in [old_parent, new_parent] {
+                let hierarchy = table_id(NodeIdType::HierarchyTable, parent)?;
+                let has_sub_folders = !editor.table(hierarchy)?.rows.is_empty();
+                editor.folder(parent)?.set(
+                    PR_SUBFOLDERS,
+                    PropertyType::Boolean,
+                    PropertyData::Small(u32::from(has_sub_folders)),
+                );
+                editor.update_hierarchy_row(parent)?;
+            }
+            Ok(())
+        })?;
+        self.reload()?;
+        parent_folder.reload()
+    }
+
+    /// Create an empty sub-folder of this folder named `display_name`, and return its
+    /// [`NodeId`]. The new folder holds e-mail messages, see [`UnicodePstFile::create_folder`].
+    pub fn create_sub_folder(&mut self, display_name: &str) -> io::Result<NodeId> {
+        self.create_sub_folder_with_class(display_name, IPF_NOTE)
+    }
+
+    /// Like [`Self::create_sub_folder`], with `PR_CONTAINER_CLASS` set to `container_class`, e.g.
+    /// `IPF.Appointment` for a calendar folder.
+    pub fn create_sub_folder_with_class(
+        &mut self,
+        display_name: &str,
+        container_class: &str,
+    ) -> io::Result<NodeId> {
+        let parent = self.properties().node_id();
+        check_new_folder(parent, display_name)?;
+
+        let folder = edit_in_place(self.pst_store(), |editor| {
+            add_folder(editor, parent, display_name, container_class)
+        })?;
+        self.reload()?;
+        Ok(folder)
+    }
+
+    /// Delete this folder, along with its TCs and its row in the hierarchy table of its parent,
+    /// and clear `PR_SUBFOLDERS` on the parent if it was the last one, all in one transaction.
+    /// Only empty folders can be deleted, a folder with any messages, FAI messages or
+    /// sub-folders fails with [`MessagingError::FolderNotEmpty`].
+    pub fn delete(self) -> io::Result<()> {
+        let folder = self.properties().node_id();
+        check_folder_id(folder)?;
+
+        edit_in_place(self.pst_store(), |editor| {
+            let parent = match editor.folder(folder)?.parent {
+                Some(parent) if parent != folder => parent,
+                _ => return Err(MessagingError::DeleteRootFolder.into()),
+            };
+
+            for id_type in [
+                NodeIdType::HierarchyTable,
+                NodeIdType::ContentsTable,
+                NodeIdType::AssociatedContentsTable,
+            ] {
+                if !editor.table(table_id(id_type, folder)?)?.rows.is_empty() {
+                    return Err(MessagingError::FolderNotEmpty(u32::from(folder)).into());
+                }
+            }
+
+            editor.remove_folder(parent, folder)?;
+            let hierarchy = table_id(NodeIdType::HierarchyTable, parent)?;
+            let has_sub_folders = !editor.table(hierarchy)?.rows.is_empty();
+            editor.folder(parent)?.set(
+                PR_SUBFOLDERS,
+                PropertyType::Boolean,
+                PropertyData::Small(u32::from(has_sub_folders)),
+            );
+            editor.update_hierarchy_row(parent)
+        })
+    }
+
+    /// Add a new message built from `message` to this folder, and return its [`NodeId`]. The
+    /// message gets a row in the contents table of this folder, and `PR_CONTENT_COUNT` and
+    /// `PR_CONTENT_UNREAD` are updated on this folder, all in one transaction.
+    pub fn add_message(&mut self, message: &MessageBuilder) -> io::Result<NodeId> {
+        let folder = self.properties().node_id();
+        check_folder_id(folder)?;
+
+        let node = edit_in_place(self.pst_store(), |editor| {
+            let node = editor.new_node_id(NodeIdType::NormalMessage)?;
+            let properties = message.build(editor, node, folder)?;
+            let unread = i32::from(properties.is_unread());
+            editor.set_folder(properties);
+            editor.update_contents_row(node)?;
+            editor.add_content_counts(folder, 1, unread)?;
+            Ok(node)
+        })?;
+        self.reload()?;
+        Ok(node)
+    }
+
+    /// Move each of `messages` into this folder, like [`UnicodeMessage::move_to`], sharing one
+    /// edit and one transaction for all of them. Messages which are already in this
+    /// folder are left alone.
+    pub fn move_messages(&mut self, messages: &[&UnicodeMessage]) -> io::Result<()> {
+        let (folder, messages) = self.edited_messages(messages)?;
+
+        edit_in_place(self.pst_store(), |editor| {
+            for message in messages {
+                let properties = editor.folder(message)?;
+                let source = properties
+                    .parent
+                    .ok_or(MessagingError::MessageParentFolderNotFound)?;
+                if source == folder {
+                    continue;
+                }
+                properties.set_parent(folder)?;
+                let unread = i32::from(properties.is_unread());
+
+                let source_contents = table_id(NodeIdType::ContentsTable, source)?;
+                editor.table(source_contents)?.remove_row(message);
+                editor.update_contents_row(message)?;
+
+                editor.add_content_counts(source, -1, -unread)?;
+                editor.add_content_counts(folder, 1, unread)?;
+            }
+            Ok(())
+        })?;
+        self.reload()
+    }
+
+    /// Copy each of `messages` into this folder, like [`UnicodeMessage::copy_to`], sharing one
+    /// edit and one transaction for all of them, and return a [`MessageRef`] to each
+    /// copy, in the same order.
+    pub fn copy_messages(&mut self, messages: &[&UnicodeMessage]) -> io::Result<Vec<MessageRef>> {
+        let (folder, messages) = self.edited_messages(messages)?;
+
+        let copies = edit_in_place(self.pst_store(), |editor| {
+            let max_data_size = editor.max_data_size();
+            let mut unread = 0;
+            let copies = messages
+                .into_iter()
+                .map(|message| {
+                    let mut properties =
+                        PropertyNode::read(&editor.read_node(message)?, max_data_size)?;
+                    let copy = editor.new_node_id(NodeIdType::NormalMessage)?;
+                    properties.node = copy;
+                    properties.set_parent(folder)?;
+                    unread += i32::from(properties.is_unread());
+                    editor.set_folder(properties);
+                    editor.update_contents_row(copy)?;
+                    Ok(MessageRef {
+                        node_id: copy,
+                        folder_id: folder,
+                    })
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            let count = i32::try_from(copies.len()).unwrap_or(i32::MAX);
+            editor.add_content_counts(folder, count, unread)?;
+            Ok(copies)
+        })?;
+        self.reload()?;
+        Ok(copies)
+    }
+
+    /// Check that `messages` can be moved or copied into this folder, and get the [`NodeId`] of
+    /// the folder and of each message.
+    fn edited_messages(&self, messages: &[&UnicodeMessage]) -> io::Result<(NodeId, Vec<NodeId>)> {
+        let folder = self.properties().node_id();
+        check_folder_id(folder)?;
+        let messages = messages
+            .iter()
+            .map(|message| {
+                check_same_store(self.pst_store(), message.pst_store())?;
+                message.folder_message_id()
+            })
+            .collect::<io::Result<_>>()?;
+        Ok((folder, messages))
+    }
+}
+
+/// Like the edits on [`UnicodeFolder`], these go through the file handle of the store this
+/// message was read from, and fail with [`MessagingError::EditWrongFile`] if the folder passed to
+/// them comes from a different store. To mark a message as read or unread, take it out of the
+/// [`Arc`] it was read into with [`Arc::into_inner`]. That handle keeps its properties in step
+/// with the edits, but it can no longer open its attachments; read the message again for those.
+impl UnicodeMessage {
+    /// Move this message into `folder`. The message node keeps the same [`NodeId`], but its row
+    /// moves from the contents table of the folder it was in to the contents table of `folder`,
+    /// and `PR_CONTENT_COUNT` and `PR_CONTENT_UNREAD` are updated on both folders, all in one
+    /// transaction. Use [`UnicodeFolder::move_messages`] to move many messages at once.
+    pub fn move_to(&self, folder: &mut UnicodeFolder) -> io::Result<()> {
+        folder.move_messages(&[self])
+    }
+
+    /// Copy this message into `folder`, and return a [`MessageRef`] to the copy. The copy has all
+    /// of the same properties, recipients and attachments as this message, and
+    /// `PR_CONTENT_COUNT` and `PR_CONTENT_UNREAD` are updated on `folder`, all in one
+    /// transaction. Use [`UnicodeFolder::copy_messages`] to copy many messages at once.
+    pub fn copy_to(&self, folder: &mut UnicodeFolder) -> io::Result<MessageRef> {
+        let mut copies = folder.copy_messages(&[self])?;
+        Ok(copies.remove(0))
+    }
+
+    /// Set `MSGFLAG_READ` in `PR_MESSAGE_FLAGS` on this message, and on its row in the contents
+    /// table of its folder, and decrement `PR_CONTENT_UNREAD` on the folder, all in one
+    /// transaction. A message which is already read is left alone.
+    pub fn mark_as_read(&mut self) -> io::Result<()> {
+        self.set_read_flag(true)
+    }
+
+    /// Clear `MSGFLAG_READ` in `PR_MESSAGE_FLAGS` on this message, like [`Self::mark_as_read`],
+    /// and increment `PR_CONTENT_UNREAD` on its folder. A message which is already unread is left
+    /// alone.
+    pub fn mark_as_unread(&mut self) -> io::Result<()> {
+        self.set_read_flag(false)
+    }
+
+    fn set_read_flag(&mut self, read: bool) -> io::Result<()> {
+        let message = self.folder_message_id()?;
+
+        let message_flags = edit_in_place(self.pst_store(), |editor| {
+            let properties = editor.folder(message)?;
+            let flags = properties.integer(PR_MESSAGE_FLAGS).unwrap_or_default();
+            if properties.is_unread() != read {
+                return Ok(flags);
+            }
+            let folder = properties
+                .parent
+                .ok_or(MessagingError::MessageParentFolderNotFound)?;
+            let flags = if read {
+                flags | MSGFLAG_READ
+            } else {
+                flags & !MSGFLAG_READ
+            };
+            properties.set(
+                PR_MESSAGE_FLAGS,
+                PropertyType::Integer32,
+                PropertyData::Small(flags as u32),
+            );
+            editor.update_contents_row(message)?;
+            editor.add_content_counts(folder, 0, if read { -1 } else { 1 })?;
+            Ok(flags)
+        })?;
+        self.set_message_flags(message_flags);
+        Ok(())
+    }
+
+    /// The [`NodeId`] of this message, which must be in a folder rather than embedded in an
+    /// attachment.
+    fn folder_message_id(&self) -> io::Result<NodeId> {
+        if self.embedded_depth() > 0 {
+            return Err(MessagingError::EditEmbeddedMessage.into());
+        }
+        let message = self.node_id();
+        check_message_id(message)?;
+        Ok(message)
+    }
+}
+
+fn check_message_id(message: NodeId) -> io::Result<()> {
+    match message.id_type()? {
+        NodeIdType::NormalMessage => Ok(()),
+        id_type => Err(MessagingError::InvalidMessageEntryIdType(id_type).into()),
+    }
+}
+
+fn check_same_store(store: &Arc<UnicodeStore>, other: &Arc<UnicodeStore>) -> io::Result<()> {
+    if Arc::ptr_eq(store, other) {
+        Ok(())
+    } else {
+        Err(MessagingError::EditWrongFile.into())
+    }
+}
+
+fn check_folder_id(folder: NodeId) -> io::Result<()> {
+    match folder.id_type()? {
+        NodeIdType::NormalFolder | NodeIdType::SearchFolder => Ok(()),
+        id_type => Err(MessagingError::InvalidFolderEntryIdType(id_type).into()),
+    }
+}
+
+/// Edit the file `store` was read from through its own file handle, with [`edit_store`].
+fn edit_in_place<T>(
+    store: &UnicodeStore,
+    edit: impl FnOnce(&mut StoreEditor<'_>) -> io::Result<T>,
+) -> io::Result<T> {
+    store.edit_file(|pst| edit_store(pst, edit))
+}
+
+/// Open `pst` in a [`StoreEditor`], and if `edit` succeeds, write the nodes which it added,
+/// changed or removed back to `pst` in a single [`WriteTransaction`](crate::WriteTransaction).
+/// If anything fails, the transaction is rolled back and the file is left alone.
+pub(crate) fn edit_store<T>(
+    pst: &mut UnicodePstFile,
+    edit: impl FnOnce(&mut StoreEditor<'_>) -> io::Result<T>,
+) -> io::Result<T> {
+    let (result, changes) = {
+        let mut editor = StoreEditor::new(pst)?;
+        let result = edit(&mut editor)?;
+        (result, editor.into_changes()?)
+    };
+
+    let mut transaction = pst.begin_transaction()?;
+    for node in changes.nodes.values() {
+        transaction.write_node(node)?;
+    }
+    for node in changes.removed {
+        transaction.remove_node(node)?;
+    }
+    transaction.reserve_node_ids(&changes.next_node_ids)?;
+    transaction.commit()?;
+    Ok(result)
+}
+
+/// Nodes for an empty message store, as Outlook creates them: the store PC, the name-to-id map,
+/// the root folder and IPM subtree with their tables, the search root, Deleted Items, and the
+/// template TCs which new folders and messages copy their column schemas from.
+///
+/// `empty_store.pst` is a Unicode PST file with no encryption and a single AMap region, which is
+/// the smallest valid file. The nodes are copied from `examples/Empty.pst`, a store which Outlook
+/// created, with [`StoreEditor::write_to`], so it has no unused blocks. Almost all of the AMap
+/// region is free space, so the trailing zeros are trimmed and [`empty_store_template`] adds them
+/// back. `test_empty_store_template` regenerates it and checks that it matches.
+const EMPTY_STORE_TEMPLATE: &[u8] = include_bytes!("empty_store.pst");
+
+/// [`EMPTY_STORE_TEMPLATE`] padded with zeros to the end of the first AMap region.
+fn empty_store_template() -> Vec<u8> {
+    let mut template = EMPTY_STORE_TEMPLATE.to_vec();
+    template.resize((AMAP_FIRST_OFFSET + AMAP_DATA_SIZE) as usize, 0);
+    template
+}
+
+/// Write a new, empty Unicode PST file at `path`, encoded with `encoding`.
+///
+/// See [`write_new_store`] for the contents, the display name is the file stem of `path`.
+pub(crate) fn create_store(path: &Path, encoding: NdbCryptMethod) -> io::Result<()> {
+    let display_name = path.file_stem().and_then(|name| name.to_str());
+    let result = NodeWriter::<UnicodePstFile>::create(path, encoding)
+        .and_then(|writer| write_new_store(writer, display_name));
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result?;
+
+    UnicodePstFile::open(path)?.lock()?.flush()
+}
+
+/// Write a new, empty Unicode PST file in memory, encoded with `encoding`, and return its bytes.
+///
+/// See [`write_new_store`] for the contents, the display name is copied from the template.
+pub(crate) fn create_store_in_memory(encoding: NdbCryptMethod) -> io::Result<Vec<u8>> {
+    let file = MemoryFile::default();
+    write_new_store(
+        NodeWriter::<UnicodePstFile>::new(Box::new(file.clone()), encoding),
+        None,
+    )?;
+    file.into_inner()
+}
+
+/// Write a new, empty store to `writer`.
+///
+/// The NDB layer is written from scratch by [`NodeWriter`], and the mandatory nodes are copied
+/// from [`EMPTY_STORE_TEMPLATE`]. The store gets a new `PR_RECORD_KEY`, which is also updated in
+/// every entry ID the store PC refers to, and `display_name` replaces its display name.
+fn write_new_store(
+    writer: NodeWriter<UnicodePstFile>,
+    display_name: Option<&str>,
+) -> io::Result<()> {
+    let template = UnicodePstFile::read_from(Box::new(io::Cursor::new(empty_store_template())))?;
+    let mut editor = StoreEditor::new(&template)?;
+    let store = editor.folder(NID_MESSAGE_STORE)?;
+    let old_key = match store.properties.get(&PR_RECORD_KEY) {
+        Some((_, PropertyData::Bytes(record_key))) => {
+            <[u8; 16]>::try_from(record_key.as_slice())
+                .map_err(|_| MessagingError::StoreRecordKeyNotFound)?
+        }
+        _ => return Err(MessagingError::StoreRecordKeyNotFound.into()),
+    };
+    let new_key = new_record_key();
+
+    for (_, value) in store.properties.values_mut() {
+        let PropertyData::Bytes(data) = value else {
+            continue;
+        };
+        if data.as_slice() == old_key {
+            data.copy_from_slice(&new_key);
+        } else if data.len() == 24 && data[4..20] == old_key {
+            data[4..20].copy_from_slice(&new_key);
+        }
+    }
+
+    if let Some(name) = display_name {
+        store.set(
+            PR_DISPLAY_NAME,
+            PropertyType::Unicode,
+            PropertyData::Bytes(unicode_bytes(name)),
+        );
+    }
+
+    editor.write_to(writer)
+}
+
+/// Make a random version 4 GUID for `PR_RECORD_KEY`, without depending on a GUID crate. The
+/// randomness comes from the per-process random keys of [`RandomState`], mixed with the time.
+fn new_record_key() -> [u8; 16] {
+    use std::{
+        hash::{BuildHasher, Hasher},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+
+    let mut record_key = [0; 16];
+    for (index, chunk) in record_key.chunks_exact_mut(8).enumerate() {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        hasher.write_usize(index);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+
+    record_key[7] = (record_key[7] & 0x0F) | 0x40;
+    record_key[8] = (record_key[8] & 0x3F) | 0x80;
+    record_key
+}
+
+/// A property value read from a PC, with any value which is not stored inline in the record
+/// resolved to its raw bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum PropertyData {
+    Small(u32),
+    Bytes(Vec<u8>),
+}
+
+pub(crate) fn unicode_bytes(value: &str) -> Vec<u8> {
+    value.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+/// Store a variable-size value in `heap`, or in a new LTP sub-node if it is too big for the heap,
+/// and get the HNID which refers to it.
+pub(crate) fn store_value(
+    heap: &mut HeapNodeEditor,
+    sub_nodes: &mut Vec<NodeContents>,
+    data: Vec<u8>,
+    max_data_size: usize,
+    next_ltp_index: &mut u32,
+) -> io::Result<PropertyValueRecord> {
+    if data.is_empty() {
+        return Ok(PropertyValueRecord::Heap(Default::default()));
+    }
+
+    if data.len() <= MAX_HEAP_ALLOCATION_SIZE {
+        return Ok(PropertyValueRecord::Heap(heap.allocate(data)?));
+    }
+
+    let sub_node = NodeId::new(NodeIdType::ListsTablesProperties, *next_ltp_index)?;
+    *next_ltp_index += 1;
+    sub_nodes.push(NodeContents {
+        node: sub_node,
+        parent: None,
+        data: split_data(&data, max_data_size),
+        sub_nodes: Default::default(),
+    });
+    Ok(PropertyValueRecord::Node(sub_node))
+}
+
+/// Read a value which was moved out of the heap into a sub-node.
+pub(crate) fn sub_node_data(node: &NodeContents, sub_node: NodeId) -> LtpResult<Vec<u8>> {
+    node.sub_nodes
+        .iter()
+        .find(|existing| existing.node == sub_node)
+        .map(|existing| existing.data.concat())
+        .ok_or(LtpError::PropertySubNodeValueNotFound(u32::from(sub_node)))
+}
+
+/// A decoded PC which can be modified and written back out.
+pub(crate) struct PropertyNode {
+    pub node: NodeId,
+    pub parent: Option<NodeId>,
+    pub properties: BTreeMap<u16, (PropertyType, PropertyData)>,
+    /// Sub-nodes which do not hold property values, e.g. the recipient table of a message.
+    pub sub_nodes: Vec<NodeContents>,
+}
+
+impl PropertyNode {
+    pub fn read(node: &NodeContents, max_data_size: usize) -> io::Result<Self> {
+        let heap = HeapNodeEditor::read(&node.data, max_data_size)?;
+        let (_, records) = heap_tree_records(&heap, heap.user_root())?;
+
+        let mut properties = BTreeMap::new();
+        let mut value_nodes = BTreeSet::new();
+        for record in records {
+            let mut cursor = record.as_slice();
+            let prop_id = PropertyTreeRecordKey::read(&mut cursor)?;
+            let value = PropertyTreeRecordValue::read(&mut cursor)?;
+            let data = match value.value() {
+                PropertyValueRecord::Small(value) => PropertyData::Small(value),
+                PropertyValueRecord::Heap(heap_id) if u32::from(heap_id) == 0 => {
+                    PropertyData::Bytes(Default::default())
+                }
+                PropertyValueRecord::Heap(heap_id) => {
+                    PropertyData::Bytes(heap.get(heap_id)?.to_vec())
+                }
+                PropertyValueRecord::Node(sub_node) => {
+                    value_nodes.insert(sub_node);
+                    PropertyData::Bytes(sub_node_data(node, sub_node)?)
+                }
+            };
+            properties.insert(prop_id, (value.prop_type(), data));
+        }
+
+        Ok(Self {
+            node: node.node,
+            parent: node.parent,
+            properties,
+            sub_nodes: node
+                .sub_nodes
+                .iter()
+                .filter(|sub_node| !value_nodes.contains(&sub_node.node))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    pub fn write(self, max_data_size: usize, next_ltp_index: &mut u32) -> io::Result<NodeContents> {
+        let mut heap = HeapNodeEditor::new(HeapNodeType::Properties, max_data_size);
+        let mut sub_nodes = self.sub_nodes;
+        let mut records = Vec::with_capacity(self.properties.len());
+        for (prop_id, (prop_type, data)) in self.properties {
+            let value = match data {
+                PropertyData::Small(value) => PropertyValueRecord::Small(value),
+                PropertyData::Bytes(data) => store_value(
+                    &mut heap,
+                    &mut sub_nodes,
+                    data,
+                    max_data_size,
+                    next_ltp_index,
+                )?,
+            };
+
+            let mut record = Vec::with_capacity(8);
+            prop_id.write(&mut record)?;
+            PropertyTreeRecordValue::new(prop_type, value).write(&mut record)?;
+            records.push(record);
+        }
+
+        let user_root = allocate_heap_tree(
+            &mut heap,
+            <PropertyTreeRecordKey as HeapTreeEntryKey>::SIZE,
+            <PropertyTreeRecordValue as HeapTreeEntryValue>::SIZE,
+            &records,
+        )?;
+        heap.set_user_root(user_root);
+        sub_nodes.sort_by_key(|sub_node| u32::from(sub_node.node));
+
+        Ok(NodeContents {
+            node: self.node,
+            parent: self.parent,
+            data: heap.write()?,
+            sub_nodes,
+        })
+    }
+
+    pub fn set(&mut self, prop_id: u16, prop_type: PropertyType, data: PropertyData) {
+        self.properties.insert(prop_id, (prop_type, data));
+    }
+
+    /// Change the parent of this node in the NBT, and `PR_PARENT_ENTRYID` if it has one.
+    pub fn set_parent(&mut self, parent: NodeId) -> io::Result<()> {
+        self.parent = Some(parent);
+        if let Some((prop_type, PropertyData::Bytes(entry_id))) =
+            self.properties.get(&PR_PARENT_ENTRYID)
+        {
+            let prop_type = *prop_type;
+            let entry_id = EntryId::try_from(entry_id.as_slice())?;
+            let entry_id = EntryId::new(StoreRecordKey::new(*entry_id.record_key()), parent);
+            let mut data = Vec::new();
+            entry_id.write(&mut data)?;
+            self.set(PR_PARENT_ENTRYID, prop_type, PropertyData::Bytes(data));
+        }
+        Ok(())
+    }
+
+    /// A message is unread unless `PR_MESSAGE_FLAGS` has `MSGFLAG_READ`.
+    pub fn is_unread(&self) -> bool {
+        self.integer(PR_MESSAGE_FLAGS)
+            .is_none_or(|flags| flags & MSGFLAG_READ == 0)
+    }
+
+    pub fn integer(&self, prop_id: u16) -> Option<i32> {
+        match self.properties.get(&prop_id) {
+            Some((PropertyType::Integer32, PropertyData::Small(value))) => Some(*value as i32),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, prop_id: u16) -> Option<String> {
+        match self.properties.get(&prop_id)? {
+            (PropertyType::Unicode, PropertyData::Bytes(data)) => {
+                let data: Vec<_> = data
+                    .chunks_exact(2)
+                    .map(|ch| u16::from_le_bytes([ch[0], ch[1]]))
+                    .collect();
+                Some(String::from_utf16_lossy(&data))
+            }
+            (PropertyType::String8, PropertyData::Bytes(data)) => {
+                Some(String::from_utf8_lossy(data).into_owned())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A single row in a [`TableNode`].
+pub(crate) struct TableRow {
+    /// The fixed-size part of the row, including the existence bitmap.
+    pub data: Vec<u8>,
+    /// The values of the HNID columns which are present in this row, by column index.
+    pub values: BTreeMap<usize, Vec<u8>>,
+}
+
+impl TableRow {
+    pub fn id(&self) -> u32 {
+        u32::from_le_bytes([self.data[0], self.data[1], self.data[2], self.data[3]])
+    }
+}
+
+/// A decoded TC which can be modified and written back out.
+pub(crate) struct TableNode {
+    pub node: NodeId,
+    pub parent: Option<NodeId>,
+    pub info: TableContextInfo,
+    pub rows: Vec<TableRow>,
+    /// Sub-nodes which do not hold the row matrix or any of the column values.
+    pub sub_nodes: Vec<NodeContents>,
+}
+
+impl TableNode {
+    pub fn read(node: &NodeContents, max_data_size: usize) -> io::Result<Self> {
+        let heap = HeapNodeEditor::read(&node.data, max_data_size)?;
+        let info = TableContextInfo::read(&mut heap.get(heap.user_root())?)?;
+        let row_size = usize::from(info.end_existence_bitmap()).max(1);
+
+        let mut value_nodes = BTreeSet::new();
+        let data = match info.rows() {
+            None => Default::default(),
+            Some(rows) if matches!(rows.id_type(), Ok(NodeIdType::HeapNode)) => {
+                heap.get(HeapId::from(u32::from(rows)))?.to_vec()
+            }
+            Some(rows) => {
+                value_nodes.insert(rows);
+                let sub_node = node
+                    .sub_nodes
+                    .iter()
+                    .find(|sub_node| sub_node.node == rows)
+                    .ok_or(LtpError::PropertySubNodeValueNotFound(u32::from(rows)))?;
+                // Rows never span blocks, so skip any padding at the end of each block.
+                sub_node
+                    .data
+                    .iter()
+                    .flat_map(|block| &block[..block.len() - block.len() % row_size])
+                    .copied()
+                    .collect()
+            }
+        };
+
+        let existence_bitmap = usize::from(info.end_1byte_values())..row_size;
+        let mut rows = Vec::new();
+        for row in data.chunks_exact(row_size) {
+            let mut values = BTreeMap::new();
+            for (index, column) in info.columns().iter().enumerate() {
+                if !is_hnid_column(column.prop_type())
+                    || !check_existence_bitmap(
+                        usize::from(column.existence_bitmap_index()),
+                        &row[existence_bitmap.clone()],
+                    )?
+                {
+                    continue;
+                }
+
+                let offset = usize::from(column.offset());
+                let cell = row
+                    .get(offset..offset + 4)
+                    .ok_or(LtpError::InvalidTableColumnOffset(column.offset()))?;
+                let cell = u32::from_le_bytes([cell[0], cell[1], cell[2], cell[3]]);
+                let value = match NodeId::from(cell).id_type() {
+                    _ if cell == 0 => Default::default(),
+                    Ok(NodeIdType::HeapNode) => heap.get(HeapId::from(cell))?.to_vec(),
+                    _ => {
+                        value_nodes.insert(NodeId::from(cell));
+                        sub_node_data(node, NodeId::from(cell))?
+                    }
+                };
+                values.insert(index, value);
+            }
+
+            rows.push(TableRow {
+                data: row.to_vec(),
+                values,
+            });
+        }
+
+        Ok(Self {
+            node: node.node,
+            parent: node.parent,
+            info,
+            rows,
+            sub_nodes: node
+                .sub_nodes
+                .iter()
+                .filter(|sub_node| !value_nodes.contains(&sub_node.node))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    pub fn write(self, max_data_size: usize, next_ltp_index: &mut u32) -> io::Result<NodeContents> {
+        let mut heap = HeapNodeEditor::new(HeapNodeType::Table, max_data_size);
+        let mut sub_nodes = self.sub_nodes;
+        let row_size = usize::from(self.info.end_existence_bitmap()).max(1);
+
+        let mut data = Vec::with_capacity(self.rows.len() * row_size);
+        let mut row_index = Vec::with_capacity(self.rows.len());
+        for (index, row) in self.rows.into_iter().enumerate() {
+            let row_id = row.id();
+            let mut row_data = row.data;
+            for (column, value) in row.values {
+                let offset = usize::from(self.info.columns()[column].offset());
+                let value = store_value(
+                    &mut heap,
+                    &mut sub_nodes,
+                    value,
+                    max_data_size,
+                    next_ltp_index,
+                )?;
+                row_data[offset..offset + 4].copy_from_slice(&u32::from(value).to_le_bytes());
+            }
+            data.extend_from_slice(&row_data);
+            row_index.push((row_id, index as u32));
+        }
+
+        row_index.sort_by_key(|(row_id, _)| *row_id);
+        let row_index: Vec<_> = row_index
+            .into_iter()
+            .map(|(row_id, index)| [row_id.to_le_bytes(), index.to_le_bytes()].concat())
+            .collect();
+        let row_index = allocate_heap_tree(
+            &mut heap,
+            <TableRowId as HeapTreeEntryKey>::SIZE,
+            <UnicodeTableRowIndex as HeapTreeEntryValue>::SIZE,
+            &row_index,
+        )?;
+
+        let rows = if data.is_empty() {
+            None
+        } else if data.len() <= MAX_HEAP_ALLOCATION_SIZE {
+            Some(NodeId::from(u32::from(heap.allocate(data)?)))
+        } else {
+            let sub_node = NodeId::new(NodeIdType::ListsTablesProperties, *next_ltp_index)?;
+            *next_ltp_index += 1;
+            let rows_per_block = (max_data_size / row_size).max(1);
+            sub_nodes.push(NodeContents {
+                node: sub_node,
+                parent: None,
+                data: split_data(&data, rows_per_block * row_size),
+                sub_nodes: Default::default(),
+            });
+            Some(sub_node)
+        };
+
+        let info = TableContextInfo::new(
+            self.info.end_4byte_values(),
+            self.info.end_2byte_values(),
+            self.info.end_1byte_values(),
+            self.info.end_existence_bitmap(),
+            row_index,
+            rows,
+            self.info.columns().to_vec(),
+        )?;
+        let mut info_data = Vec::new();
+        info.write(&mut info_data)?;
+        let user_root = heap.allocate(info_data)?;
+        heap.set_user_root(user_root);
+        sub_nodes.sort_by_key(|sub_node| u32::from(sub_node.node));
+
+        Ok(NodeContents {
+            node: self.node,
+            parent: self.parent,
+            data: heap.write()?,
+            sub_nodes,
+        })
+    }
+
+    /// Build a row for `row_id` from the properties of the folder or message it describes.
+    /// Columns without a matching property, with the same ID and type, are left out.
+    pub fn row_from_properties(
+        &self,
+        row_id: NodeId,
+        properties: &PropertyNode,
+    ) -> LtpResult<TableRow> {
+        let row_size = usize::from(self.info.end_existence_bitmap());
+        let existence_bitmap = usize::from(self.info.end_1byte_values());
+        let mut data = vec![0; row_size];
+        let mut values = BTreeMap::new();
+
+        for (index, column) in self.info.columns().iter().enumerate() {
+            let value = match column.prop_id() {
+                PR_LTP_ROW_ID => PropertyData::Small(u32::from(row_id)),
+                prop_id => match properties.properties.get(&prop_id) {
+                    Some((prop_type, value)) if *prop_type == column.prop_type() => value.clone(),
+                    _ => continue,
+                },
+            };
+
+            let offset = usize::from(column.offset());
+            let size = usize::from(column.size());
+            let cell = data
+                .get_mut(offset..offset + size)
+                .ok_or(LtpError::InvalidTableColumnOffset(column.offset()))?;
+            match value {
+                PropertyData::Small(value) if size <= 4 => {
+                    cell.copy_from_slice(&value.to_le_bytes()[..size]);
+                }
+                PropertyData::Bytes(value) if is_hnid_column(column.prop_type()) => {
+                    values.insert(index, value);
+                }
+                PropertyData::Bytes(value) if value.len() == size => {
+                    cell.copy_from_slice(&value);
+                }
+                _ => continue,
+            }
+
+            let bit = usize::from(column.existence_bitmap_index());
+            let byte = data
+                .get_mut(existence_bitmap + bit / 8)
+                .ok_or(LtpError::InvalidTableContextColumnCount(bit))?;
+            *byte |= 1 << (7 - (bit % 8));
+        }
+
+        Ok(TableRow { data, values })
+    }
+
+    /// Replace the row with the same row ID, or add it to the end of the table.
+    pub fn set_row(&mut self, row: TableRow) {
+        match self
+            .rows
+            .iter_mut()
+            .find(|existing| existing.id() == row.id())
+        {
+            Some(existing) => *existing = row,
+            None => self.rows.push(row),
+        }
+    }
+
+    pub fn remove_row(&mut self, row_id: NodeId) {
+        self.rows
+            .retain(|existing| existing.id() != u32::from(row_id));
+    }
+
+    pub fn row_ids(&self) -> Vec<NodeId> {
+        self.rows.iter().map(|row| NodeId::from(row.id())).collect()
+    }
+}
+
+pub(crate) fn table_id(id_type: NodeIdType, folder: NodeId) -> io::Result<NodeId> {
+    Ok(NodeId::new(id_type, folder.index())?)
+}
+
+/// Get the [`NodeId`] of the IPM subtree from the store PC.
+pub(crate) fn ipm_subtree(source: &mut dyn NodeSource, max_data_size: usize) -> io::Result<NodeId> {
+    let store = PropertyNode::read(&source.read_node(NID_MESSAGE_STORE)?, max_data_size)?;
+    match store.properties.get(&PR_IPM_SUBTREE_ENTRYID) {
+        Some((PropertyType::Binary, PropertyData::Bytes(entry_id))) => {
+            Ok(NodeId::from(&EntryId::try_from(entry_id.as_slice())?))
+        }
+        Some((prop_type, _)) => {
+            Err(MessagingError::InvalidStoreIpmSubTreeEntryId(*prop_type).into())
+        }
+        None => Err(MessagingError::StoreIpmSubTreeEntryIdNotFound.into()),
+    }
+}
+
+/// The working set of an edit. Nothing is written until [`edit_store`] applies the changes.
+pub(crate) struct StoreEditor<'a> {
+    reader: NodeReader<'a, UnicodePstFile>,
+    max_data_size: usize,
+    next_node_ids: Vec<u32>,
+    /// Every [`NodeId`] which is in use in the store, including new nodes.
+    used: BTreeSet<u32>,
+    /// Nodes which were removed from the store.
+    removed: BTreeSet<u32>,
+    /// New nodes, e.g. messages, which are written as-is.
+    nodes: BTreeMap<u32, NodeContents>,
+    folders: BTreeMap<u32, PropertyNode>,
+    tables: BTreeMap<u32, TableNode>,
+}
+
+impl<'a> StoreEditor<'a> {
+    pub fn new(pst: &'a UnicodePstFile) -> io::Result<Self> {
+        let reader = NodeReader::new(pst)?;
+        let next_node_ids = reader.next_node_ids();
+        let used = reader.nodes().into_iter().map(u32::from).collect();
+
+        Ok(Self {
+            reader,
+            max_data_size: UnicodeDataTree::max_data_size(),
+            next_node_ids,
+            used,
+            removed: Default::default(),
+            nodes: Default::default(),
+            folders: Default::default(),
+            tables: Default::default(),
+        })
+    }
+
+    pub fn max_data_size(&self) -> usize {
+        self.max_data_size
+    }
+
+    /// Every [`NodeId`] in the original file, in key order.
+    pub fn original_nodes(&self) -> Vec<NodeId> {
+        self.reader.nodes()
+    }
+
+    /// Read a node as it was in the original file.
+    pub fn read_node(&mut self, node: NodeId) -> io::Result<NodeContents> {
+        self.reader.read_node(node)
+    }
+
+    pub fn ipm_subtree(&mut self) -> io::Result<NodeId> {
+        ipm_subtree(&mut self.reader, self.max_data_size)
+    }
+
+    /// Add a new node, which should have a [`NodeId`] from [`Self::new_node_id`].
+    pub fn add_node(&mut self, node: NodeContents) {
+        self.nodes.insert(u32::from(node.node), node);
+    }
+
+    /// Add or replace the PC of a folder.
+    pub fn set_folder(&mut self, properties: PropertyNode) {
+        self.folders.insert(u32::from(properties.node), properties);
+    }
+
+    /// Add or replace a TC.
+    pub fn set_table(&mut self, table: TableNode) {
+        self.tables.insert(u32::from(table.node), table);
+    }
+
+    /// Get the PC of `node` for editing, reading it from the original file the first time. This
+    /// is usually a folder, but messages which are moved between folders are edited here too.
+    pub fn folder(&mut self, node: NodeId) -> io::Result<&mut PropertyNode> {
+        let key = u32::from(node);
+        if !self.folders.contains_key(&key) {
+            let properties = PropertyNode::read(&self.reader.read_node(node)?, self.max_data_size)?;
+            self.folders.insert(key, properties);
+        }
+        Ok(self.folders.get_mut(&key).expect("inserted above"))
+    }
+
+    /// Get the TC in `node` for editing, reading it from the original file the first time.
+    pub fn table(&mut self, node: NodeId) -> io::Result<&mut TableNode> {
+        let key = u32::from(node);
+        if !self.tables.contains_key(&key) {
+            let table = TableNode::read(&self.reader.read_node(node)?, self.max_data_size)?;
+            self.tables.insert(key, table);
+        }
+        Ok(self.tables.get_mut(&key).expect("inserted above"))
+    }
+
+    /// Allocate the next unused [`NodeId`] of `id_type` from `rgnid`. Folders also need the
+    /// NIDs of their TCs, which share the same index, to be unused.
+    pub fn new_node_id(&mut self, id_type: NodeIdType) -> io::Result<NodeId> {
+        let related: &[NodeIdType] = match id_type {
+            NodeIdType::NormalFolder => &[
+                NodeIdType::NormalFolder,
+                NodeIdType::HierarchyTable,
+                NodeIdType::ContentsTable,
+                NodeIdType::AssociatedContentsTable,
+            ],
+            _ => &[id_type],
+        };
+
+        loop {
+            let next = &mut self.next_node_ids[id_type as usize];
+            let index = *next;
+            *next += 1;
+
+            let nodes = related
+                .iter()
+                .map(|id_type| NodeId::new(*id_type, index))
+                .collect::<Result<Vec<_>, _>>()?;
+            if nodes
+                .iter()
+                .any(|node| self.used.contains(&u32::from(*node)))
+            {
+                continue;
+            }
+
+            self.used.extend(nodes.into_iter().map(u32::from));
+            return Ok(NodeId::new(id_type, index)?);
+        }
+    }
+
+    /// Find the sub-folder of `folder` with a display name of `name`.
+    pub fn find_sub_folder(&mut self, folder: NodeId, name: &str) -> io::Result<Option<NodeId>> {
+        let hierarchy = table_id(NodeIdType::HierarchyTable, folder)?;
+        for sub_folder in self.table(hierarchy)?.row_ids() {
+            let display_name = match self.folders.get(&u32::from(sub_folder)) {
+                Some(properties) => properties.string(PR_DISPLAY_NAME),
+                None => {
+                    PropertyNode::read(&self.reader.read_node(sub_folder)?, self.max_data_size)?
+                        .string(PR_DISPLAY_NAME)
+                }
+            };
+            if display_name.as_deref() == Some(name) {
+                return Ok(Some(sub_folder));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remove `folder` from the hierarchy table of `parent`, and remove every node which belongs
+    /// to it or any of its sub-folders.
+    pub fn remove_folder(&mut self, parent: NodeId, folder: NodeId) -> io::Result<()> {
+        let hierarchy = table_id(NodeIdType::HierarchyTable, parent)?;
+        self.table(hierarchy)?.remove_row(folder);
+
+        let mut pending = vec![folder];
+        while let Some(folder) = pending.pop() {
+            for id_type in [
+                NodeIdType::HierarchyTable,
+                NodeIdType::ContentsTable,
+                NodeIdType::AssociatedContentsTable,
+            ] {
+                let table = table_id(id_type, folder)?;
+                if !self.used.contains(&u32::from(table)) {
+                    continue;
+                }
+                let rows = self.table(table)?.row_ids();
+                if matches!(id_type, NodeIdType::HierarchyTable) {
+                    pending.extend(rows);
+                } else {
+                    self.removed.extend(rows.into_iter().map(u32::from));
+                }
+                self.removed.insert(u32::from(table));
+            }
+            self.removed.insert(u32::from(folder));
+        }
+
+        Ok(())
+    }
+
+    pub fn is_removed(&self, node: NodeId) -> bool {
+        self.removed.contains(&u32::from(node))
+    }
+
+    /// Update or add the row for `folder` in the hierarchy table of its parent, with the values
+    /// from the folder's PC. The root folder is its own parent, and it does not have a row.
+    pub fn update_hierarchy_row(&mut self, folder: NodeId) -> io::Result<()> {
+        let Some(parent) = self
+            .folder(folder)?
+            .parent
+            .filter(|parent| *parent != folder)
+        else {
+            return Ok(());
+        };
+        let parent_hierarchy = table_id(NodeIdType::HierarchyTable, parent)?;
+        self.table(parent_hierarchy)?;
+        let properties = &self.folders[&u32::from(folder)];
+        let table = self
+            .tables
+            .get_mut(&u32::from(parent_hierarchy))
+            .expect("loaded above");
+        let row = table.row_from_properties(folder, properties)?;
+        table.set_row(row);
+        Ok(())
+    }
+
+    /// Update or add the row for `message` in the contents table of its parent folder, with the
+    /// values from the message's PC, which must already be loaded with [`Self::folder`] or
+    /// [`Self::set_folder`]. Folder associated information (FAI) messages go in the associated
+    /// contents table instead.
+    pub fn update_contents_row(&mut self, message: NodeId) -> io::Result<()> {
+        let parent = self
+            .folder(message)?
+            .parent
+            .ok_or(MessagingError::MessageParentFolderNotFound)?;
+        let id_type = match message.id_type()? {
+            NodeIdType::AssociatedMessage => NodeIdType::AssociatedContentsTable,
+            _ => NodeIdType::ContentsTable,
+        };
+        let contents = table_id(id_type, parent)?;
+        self.table(contents)?;
+        let properties = &self.folders[&u32::from(message)];
+        let table = self
+            .tables
+            .get_mut(&u32::from(contents))
+            .expect("loaded above");
+        let row = table.row_from_properties(message, properties)?;
+        table.set_row(row);
+        Ok(())
+    }
+
+    /// Add `messages` and `unread` (either of which may be negative) to `PR_CONTENT_COUNT` and
+    /// `PR_CONTENT_UNREAD` on `folder`, and update its row in the hierarchy table of its parent.
+    pub fn add_content_counts(
+        &mut self,
+        folder: NodeId,
+        messages: i32,
+        unread: i32,
+    ) -> io::Result<()> {
+        let properties = self.folder(folder)?;
+        for (prop_id, delta) in [(PR_CONTENT_COUNT, messages), (PR_CONTENT_UNREAD, unread)] {
+            let value = properties.integer(prop_id).unwrap_or_default() + delta;
+            properties.set(
+                prop_id,
+                PropertyType::Integer32,
+                PropertyData::Small(value.max(0) as u32),
+            );
+        }
+        self.update_hierarchy_row(folder)
+    }
+
+    /// Write the nodes which were added or changed in this edit, and drop any which were removed.
+    fn changed_nodes(&mut self) -> io::Result<BTreeMap<u32, NodeContents>> {
+        let ltp_index = NodeIdType::ListsTablesProperties as usize;
+        let mut next_ltp_index = self.next_node_ids[ltp_index];
+        let mut changed = std::mem::take(&mut self.nodes);
+        for (key, properties) in std::mem::take(&mut self.folders) {
+            changed.insert(
+                key,
+                properties.write(self.max_data_size, &mut next_ltp_index)?,
+            );
+        }
+        for (key, table) in std::mem::take(&mut self.tables) {
+            changed.insert(key, table.write(self.max_data_size, &mut next_ltp_index)?);
+        }
+        self.next_node_ids[ltp_index] = next_ltp_index;
+        changed.retain(|key, _| !self.removed.contains(key));
+        Ok(changed)
+    }
+
+    /// Finish the edit, and collect the changes to apply to the original file.
+    fn into_changes(mut self) -> io::Result<StoreChanges> {
+        let nodes = self.changed_nodes()?;
+        let original: BTreeSet<_> = self.reader.nodes().into_iter().map(u32::from).collect();
+        let removed = self
+            .removed
+            .intersection(&original)
+            .map(|key| NodeId::from(*key))
+            .collect();
+
+        Ok(StoreChanges {
+            nodes,
+            removed,
+            next_node_ids: self.next_node_ids,
+        })
+    }
+
+    /// Write every node in the store, with the changes in this edit, to `writer`.
+    fn write_to(&mut self, mut writer: NodeWriter<UnicodePstFile>) -> io::Result<()> {
+        let mut changed = self.changed_nodes()?;
+        for node in self.reader.nodes() {
+            let key = u32::from(node);
+            if self.removed.contains(&key) {
+                continue;
+            }
+            match changed.remove(&key) {
+                Some(node) => writer.write_node(&node)?,
+                None => writer.write_node(&self.reader.read_node(node)?)?,
+            }
+        }
+        for node in changed.into_values() {
+            writer.write_node(&node)?;
+        }
+        writer.finish(&self.next_node_ids)
+    }
+}
+
+/// The result of a [`StoreEditor`], which [`edit_store`] applies to the original file.
+struct StoreChanges {
+    /// New and changed nodes, which replace any node with the same [`NodeId`].
+    nodes: BTreeMap<u32, NodeContents>,
+    /// Nodes in the original file which were removed.
+    removed: Vec<NodeId>,
+    next_node_ids: Vec<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        messaging::{
+            attachment::AttachmentData,
+            read_write::StoreReadWrite,
+            store::{Store, UnicodeStore},
+        },
+        ndb::root::{AmapStatus, Root},
+        test_util::{TempFile, EMPTY_PST},
+        PstFileReader,
+    };
+    use std::sync::Arc;
+
+    /// Open `folder` in the file at `path`, and add `message` to it with
+    /// [`UnicodeFolder::add_message`].
+    fn add_message(path: &Path, folder: NodeId, message: &MessageBuilder) -> io::Result<NodeId> {
+        open_folder(path, folder)?.add_message(message)
+    }
+
+    /// Open the Unicode PST file at `path`, and edit it in place with [`edit_store`].
+    fn edit_file<T>(
+        path: &Path,
+        edit: impl FnOnce(&mut StoreEditor<'_>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        edit_store(&mut UnicodePstFile::open(path)?, edit)
+    }
+
+    /// Create a folder in the file at `path` with [`UnicodePstFile::create_folder`].
+    fn create_folder(path: &Path, parent: NodeId, display_name: &str) -> io::Result<NodeId> {
+        UnicodePstFile::open(path)?.create_folder(parent, display_name)
+    }
+
+    /// Create a sub-folder of `parent` in the file at `path` with
+    /// [`UnicodeFolder::create_sub_folder_with_class`].
+    fn create_folder_with_class(
+        path: &Path,
+        parent: NodeId,
+        display_name: &str,
+        container_class: &str,
+    ) -> io::Result<NodeId> {
+        open_folder(path, parent)?.create_sub_folder_with_class(display_name, container_class)
+    }
+
+    /// Delete `folder` from the file at `path` with [`UnicodeFolder::delete`].
+    fn delete_folder(path: &Path, folder: NodeId) -> io::Result<()> {
+        open_folder(path, folder)?.delete()
+    }
+
+    /// Open `folder` in a new store for the file at `path`, to edit it through the store.
+    fn open_folder(path: &Path, folder: NodeId) -> io::Result<UnicodeFolder> {
+        let store = UnicodeStore::read(Arc::new(UnicodePstFile::open(path)?))?;
+        read_folder(&store, folder)
+    }
+
+    /// Read `folder` from `store`, and take it out of its [`Arc`] so it can be edited.
+    fn read_folder(store: &Arc<UnicodeStore>, folder: NodeId) -> io::Result<UnicodeFolder> {
+        let entry_id = store.properties().make_entry_id(folder)?;
+        Ok(
+            Arc::into_inner(UnicodeFolder::read(store.clone(), &entry_id)?)
+                .expect("folder was just read"),
+        )
+    }
+
+    /// Open `message` in the file at `path`, to edit it through another handle to the same file.
+    fn open_message(path: &Path, message: NodeId) -> io::Result<Arc<UnicodeMessage>> {
+        let store = UnicodeStore::read(Arc::new(UnicodePstFile::open(path)?))?;
+        let entry_id = store.properties().make_entry_id(message)?;
+        UnicodeMessage::read(store, &entry_id, None)
+    }
+
+    /// Read `message` from `store`.
+    fn read_message(store: &Arc<UnicodeStore>, message: NodeId) -> io::Result<Arc<UnicodeMessage>> {
+        let entry_id = store.properties().make_entry_id(message)?;
+        UnicodeMessage::read(store.clone(), &entry_id, None)
+    }
+
+    /// Move all of `messages` into `folder` with [`UnicodeFolder::move_messages`].
+    fn move_messages(path: &Path, messages: &[NodeId], folder: NodeId) -> io::Result<()> {
+        let mut folder = open_folder(path, folder)?;
+        let messages = messages
+            .iter()
+            .map(|message| read_message(folder.pst_store(), *message))
+            .collect::<io::Result<Vec<_>>>()?;
+        let messages: Vec<_> = messages.iter().map(Arc::as_ref).collect();
+        folder.move_messages(&messages)
+    }
+
+    /// Copy each of `messages` into `folder` with [`UnicodeMessage::copy_to`], checking the
+    /// counts on the cached properties of `folder` after each one.
+    fn copy_messages(path: &Path, messages: &[NodeId], folder: NodeId) -> io::Result<Vec<NodeId>> {
+        let mut folder = open_folder(path, folder)?;
+        let mut count = folder.properties().content_count()?;
+        messages
+            .iter()
+            .map(|message| {
+                let copy = read_message(folder.pst_store(), *message)?.copy_to(&mut folder)?;
+                assert_eq!(copy.folder_id(), folder.properties().node_id());
+                count += 1;
+                assert_eq!(folder.properties().content_count()?, count);
+                Ok(copy.node_id())
+            })
+            .collect()
+    }
+
+    /// The display name of each sub-folder of the IPM subtree, from its row in the hierarchy
+    /// table and from the folder's PC.
+    fn ipm_sub_folders(path: &Path) -> io::Result<Vec<(NodeId, String, String)>> {
+        let pst = PstFileReader::open(path)?;
+        let root = pst.store_properties().ipm_sub_tree_entry_id()?;
+        let root = pst.folder(NodeId::from(&root))?;
+        let hierarchy = root
+            .hierarchy_table()
+            .ok_or(io::ErrorKind::NotFound)?
+            .clone();
+        let folders = hierarchy
+            .rows_matrix()?
+            .map(|row| {
+                let node = NodeId::from(u32::from(row.id()));
+                let row_name = match hierarchy.cell(row.id(), PR_DISPLAY_NAME)? {
+                    Some(PropertyValue::Unicode(value)) => value.to_string(),
+                    value => format!("{value:?}"),
+                };
+                let folder_name = pst.folder(node)?.properties().display_name()?;
+                Ok((node, row_name, folder_name))
+            })
+            .collect();
+        folders
+    }
+
+    /// Copy the nodes of `examples/Empty.pst` into a new file, rebuild the allocation map, and
+    /// trim the trailing zeros.
+    fn generate_empty_store_template() -> Vec<u8> {
+        let example = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let file = MemoryFile::default();
+        StoreEditor::new(&example)
+            .unwrap()
+            .write_to(NodeWriter::new(
+                Box::new(file.clone()),
+                NdbCryptMethod::None,
+            ))
+            .unwrap();
+        let mut pst = UnicodePstFile::open_in_memory(file.into_inner().unwrap()).unwrap();
+        pst.lock().unwrap().flush().unwrap();
+        let mut template = pst.to_vec().unwrap();
+        let len = template
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |end| end + 1);
+        template.truncate(len);
+        template
+    }
+
+    /// Set `UPDATE_EMPTY_STORE_TEMPLATE` to write a new `empty_store.pst` when this fails.
+    #[test]
+    fn test_empty_store_template() {
+        let generated = generate_empty_store_template();
+        if generated != EMPTY_STORE_TEMPLATE
+            && std::env::var_os("UPDATE_EMPTY_STORE_TEMPLATE").is_some()
+        {
+            fs::write(
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("src/messaging/empty_store.pst"),
+                &generated,
+            )
+            .unwrap();
+        }
+        assert!(
+            generated == EMPTY_STORE_TEMPLATE,
+            "empty_store.pst is out of date, set UPDATE_EMPTY_STORE_TEMPLATE to regenerate it"
+        );
+
+        let pst = UnicodePstFile::open_in_memory(empty_store_template()).unwrap();
+        assert_eq!(pst.header().crypt_method(), NdbCryptMethod::None);
+        assert_eq!(pst.header().root().amap_is_valid(), AmapStatus::Valid2);
+        let check = pst.check_allocation_map().unwrap();
+        assert!(check.is_consistent(), "{check:?}");
+
+        for node in [
+            NID_MESSAGE_STORE,
+            NID_NAME_TO_ID_MAP,
+            NID_ROOT_FOLDER,
+            NID_HIERARCHY_TABLE_TEMPLATE,
+            NID_CONTENTS_TABLE_TEMPLATE,
+            NID_ASSOC_CONTENTS_TABLE_TEMPLATE,
+            NID_ATTACHMENT_TABLE,
+            NID_RECIPIENT_TABLE,
+        ] {
+            assert!(pst.read_node(node).is_ok(), "{node:?}");
+        }
+
+        let store = UnicodeStore::read(Arc::new(pst)).unwrap();
+        let properties = store.properties();
+        for entry_id in [
+            properties.ipm_sub_tree_entry_id().unwrap(),
+            properties.ipm_wastebasket_entry_id().unwrap(),
+            properties.finder_entry_id().unwrap(),
+        ] {
+            UnicodeFolder::read(store.clone(), &entry_id).unwrap();
+        }
+
+        let example = PstFileReader::open(EMPTY_PST).unwrap();
+        assert_eq!(
+            properties.display_name().unwrap(),
+            example.store_properties().display_name().unwrap()
+        );
+        assert_eq!(
+            store.folders_dfs().count(),
+            example.folders().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_rename_folder() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_rename").unwrap();
+
+        let folders = ipm_sub_folders(&path).unwrap();
+        let (deleted_items, ..) = *folders
+            .iter()
+            .find(|(_, name, _)| name == "Deleted Items")
+            .unwrap();
+        let mut folder = open_folder(&path, deleted_items).unwrap();
+        folder.rename("Trash").unwrap();
+        assert_eq!(folder.properties().display_name().unwrap(), "Trash");
+
+        let renamed = ipm_sub_folders(&path).unwrap();
+        assert_eq!(renamed.len(), folders.len());
+        assert!(renamed.contains(&(deleted_items, "Trash".to_string(), "Trash".to_string())));
+
+        // The store reads the new BTree roots, so the parent folder has the new row.
+        let store = folder.pst_store().clone();
+        let ipm_subtree = NodeId::from(&store.properties().ipm_sub_tree_entry_id().unwrap());
+        let parent = read_folder(&store, ipm_subtree).unwrap();
+        let hierarchy = parent.hierarchy_table().unwrap();
+        match hierarchy
+            .cell(TableRowId::new(u32::from(deleted_items)), PR_DISPLAY_NAME)
+            .unwrap()
+        {
+            Some(PropertyValue::Unicode(value)) => assert_eq!(value.to_string(), "Trash"),
+            value => panic!("unexpected display name: {value:?}"),
+        }
+
+        // Invalid names fail without touching the file, and other nodes are not folders at all.
+        for name in ["", "Inbox/Archive", "Inbox\\Archive"] {
+            assert!(folder.rename(name).is_err());
+        }
+        assert!(open_folder(&path, NID_MESSAGE_STORE).is_err());
+        drop(folder);
+        assert_eq!(ipm_sub_folders(&path).unwrap(), renamed);
+    }
+
+    #[test]
+    fn test_rename_folder_in_memory() {
+        let pst = UnicodePstFile::open_in_memory(fs::read(EMPTY_PST).unwrap()).unwrap();
+        let store = UnicodeStore::read(Arc::new(pst)).unwrap();
+        let ipm_subtree = NodeId::from(&store.properties().ipm_sub_tree_entry_id().unwrap());
+        let mut folder = read_folder(&store, ipm_subtree).unwrap();
+        folder.rename("Personal Folders").unwrap();
+        assert_eq!(
+            folder.properties().display_name().unwrap(),
+            "Personal Folders"
+        );
+
+        let data = store.pst().to_vec().unwrap();
+        let store =
+            UnicodeStore::read(Arc::new(UnicodePstFile::open_in_memory(data).unwrap())).unwrap();
+        let folder = read_folder(&store, ipm_subtree).unwrap();
+        assert_eq!(
+            folder.properties().display_name().unwrap(),
+            "Personal Folders"
+        );
+    }
+
+    #[test]
+    fn test_edit_shared_file() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_shared_file").unwrap();
+
+        // The other handle would keep the old header, so the store refuses to edit the file.
+        let pst = Arc::new(UnicodePstFile::open(&path).unwrap());
+        let store = UnicodeStore::read(pst.clone()).unwrap();
+        let ipm_subtree = NodeId::from(&store.properties().ipm_sub_tree_entry_id().unwrap());
+        let mut folder = read_folder(&store, ipm_subtree).unwrap();
+        let err = folder.rename("Personal Folders").unwrap_err();
+        assert_eq!(err.to_string(), MessagingError::EditSharedFile.to_string());
+
+        drop(pst);
+        folder.rename("Personal Folders").unwrap();
+    }
+
+    #[test]
+    fn test_edit_wrong_file() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_wrong_file").unwrap();
+        let other = TempFile::copy_of(EMPTY_PST, "edit_wrong_file_other").unwrap();
+
+        // The copy has the same node IDs, so the edit would apply cleanly to the wrong file.
+        let folders = ipm_sub_folders(&path).unwrap();
+        let (deleted_items, ..) = *folders
+            .iter()
+            .find(|(_, name, _)| name == "Deleted Items")
+            .unwrap();
+        let mut folder = open_folder(&path, deleted_items).unwrap();
+
+        // A destination folder from another store is refused, even for the same file.
+        for path in [&other, &path] {
+            let mut other_folder = open_folder(path, deleted_items).unwrap();
+            let err = folder.move_to(&mut other_folder).unwrap_err();
+            assert_eq!(err.to_string(), MessagingError::EditWrongFile.to_string());
+        }
+        drop(folder);
+
+        assert_eq!(ipm_sub_folders(&path).unwrap(), folders);
+        assert_eq!(ipm_sub_folders(&other).unwrap(), folders);
+    }
+
+    /// The node IDs of the sub-folders of `folder`, from its hierarchy table.
+    fn sub_folder_ids(path: &Path, folder: NodeId) -> io::Result<Vec<NodeId>> {
+        let pst = PstFileReader::open(path)?;
+        let folder = pst.folder(folder)?;
+        Ok(folder
+            .hierarchy_table()
+            .map(|hierarchy| {
+                hierarchy
+                    .rows_matrix()
+                    .map(|rows| rows.map(|row| NodeId::from(u32::from(row.id()))).collect())
+            })
+            .transpose()?
+            .unwrap_or_default())
+    }
+
+    #[test]
+    fn test_create_folder() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_create_folder").unwrap();
+
+        let folder = create_folder(&path, NID_ROOT_FOLDER, "Archive").unwrap();
+        assert_eq!(folder.id_type().unwrap(), NodeIdType::NormalFolder);
+        assert!(sub_folder_ids(&path, NID_ROOT_FOLDER)
+            .unwrap()
+            .contains(&folder));
+        assert!(create_folder(&path, NID_ROOT_FOLDER, "Archive").is_err());
+        assert!(create_folder(&path, folder, "a/b").is_err());
+
+        let child = create_folder(&path, folder, "2024").unwrap();
+        assert_eq!(sub_folder_ids(&path, folder).unwrap(), vec![child]);
+
+        let pst = PstFileReader::open(&path).unwrap();
+        let archive = pst.folder(folder).unwrap();
+        let properties = archive.properties();
+        assert_eq!(properties.display_name().unwrap(), "Archive");
+        assert_eq!(properties.content_count().unwrap(), 0);
+        assert!(properties.has_sub_folders().unwrap());
+        assert!(archive.contents_table().is_some());
+        let child = pst.folder(child).unwrap();
+        assert_eq!(child.properties().display_name().unwrap(), "2024");
+        assert!(!child.properties().has_sub_folders().unwrap());
+        drop(pst);
+    }
+
+    #[test]
+    fn test_delete_folder() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_delete_folder").unwrap();
+
+        let a = create_folder(&path, NID_ROOT_FOLDER, "A").unwrap();
+        let b = create_folder_with_class(&path, a, "B", "IPF.Task").unwrap();
+        let mut b_folder = open_folder(&path, b).unwrap();
+        assert!(!b_folder.properties().has_sub_folders().unwrap());
+        let c = b_folder.create_sub_folder("C").unwrap();
+        assert!(b_folder.properties().has_sub_folders().unwrap());
+        drop(b_folder);
+
+        let pst = PstFileReader::open(&path).unwrap();
+        let folders = pst
+            .folders()
+            .unwrap()
+            .iter()
+            .map(|folder| {
+                let properties = folder.properties();
+                (
+                    properties.node_id(),
+                    properties.display_name().unwrap_or_default(),
+                    properties
+                        .get_typed::<String>(PR_CONTAINER_CLASS)
+                        .unwrap()
+                        .unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let nested = folders
+            .iter()
+            .skip_while(|(id, ..)| *id != a)
+            .take(3)
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nested,
+            vec![
+                (a, "A".to_string(), IPF_NOTE.to_string()),
+                (b, "B".to_string(), "IPF.Task".to_string()),
+                (c, "C".to_string(), IPF_NOTE.to_string()),
+            ]
+        );
+        drop(pst);
+
+        assert!(delete_folder(&path, NID_ROOT_FOLDER).is_err());
+        assert!(delete_folder(&path, a).is_err());
+        assert!(delete_folder(&path, b).is_err());
+
+        let inbox = create_folder(&path, NID_ROOT_FOLDER, "Inbox").unwrap();
+        add_message(&path, inbox, &MessageBuilder::default().subject("Keep")).unwrap();
+        assert!(delete_folder(&path, inbox).is_err());
+
+        delete_folder(&path, c).unwrap();
+        assert!(sub_folder_ids(&path, b).unwrap().is_empty());
+        let pst = PstFileReader::open(&path).unwrap();
+        assert!(pst.folder(c).is_err());
+        assert!(!pst
+            .folder(b)
+            .unwrap()
+            .properties()
+            .has_sub_folders()
+            .unwrap());
+        drop(pst);
+
+        delete_folder(&path, b).unwrap();
+        delete_folder(&path, a).unwrap();
+        assert!(!sub_folder_ids(&path, NID_ROOT_FOLDER).unwrap().contains(&a));
+        let pst = PstFileReader::open(&path).unwrap();
+        assert!(pst.folder(a).is_err());
+        drop(pst);
+    }
+
+    #[test]
+    fn test_create_and_delete_folder_in_memory() {
+        let pst = UnicodePstFile::create_in_memory(NdbCryptMethod::None).unwrap();
+        let store = UnicodeStore::read(Arc::new(pst)).unwrap();
+        let ipm_subtree = NodeId::from(&store.properties().ipm_sub_tree_entry_id().unwrap());
+        let sub_folders = |folder: &UnicodeFolder| {
+            folder
+                .hierarchy_table()
+                .unwrap()
+                .rows_matrix()
+                .unwrap()
+                .map(|row| NodeId::from(u32::from(row.id())))
+                .collect::<Vec<_>>()
+        };
+
+        let mut parent = read_folder(&store, ipm_subtree).unwrap();
+        let before = sub_folders(&parent);
+        let projects = parent.create_sub_folder("Projects").unwrap();
+        assert!(parent.properties().has_sub_folders().unwrap());
+        assert_eq!(
+            sub_folders(&parent),
+            [before.clone(), vec![projects]].concat()
+        );
+
+        read_folder(&store, projects).unwrap().delete().unwrap();
+        assert!(read_folder(&store, projects).is_err());
+        assert_eq!(
+            sub_folders(&read_folder(&store, ipm_subtree).unwrap()),
+            before
+        );
+    }
+
+    #[test]
+    fn test_move_folder() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_move").unwrap();
+
+        let folders = ipm_sub_folders(&path).unwrap();
+        let (deleted_items, ..) = *folders
+            .iter()
+            .find(|(_, name, _)| name == "Deleted Items")
+            .unwrap();
+        let ipm_subtree = {
+            let pst = PstFileReader::open(&path).unwrap();
+            NodeId::from(&pst.store_properties().ipm_sub_tree_entry_id().unwrap())
+        };
+        let projects = create_folder(&path, ipm_subtree, "Projects").unwrap();
+        assert_eq!(ipm_sub_folders(&path).unwrap().len(), folders.len() + 1);
+
+        let store = UnicodeStore::read(Arc::new(UnicodePstFile::open(&path).unwrap())).unwrap();
+        let mut projects_folder = read_folder(&store, projects).unwrap();
+        let mut deleted_items_folder = read_folder(&store, deleted_items).unwrap();
+        assert!(!projects_folder.properties().has_sub_folders().unwrap());
+        deleted_items_folder.move_to(&mut projects_folder).unwrap();
+        assert!(projects_folder.properties().has_sub_folders().unwrap());
+        assert_eq!(
+            projects_folder
+                .hierarchy_table()
+                .unwrap()
+                .rows_matrix()
+                .unwrap()
+                .map(|row| NodeId::from(u32::from(row.id())))
+                .collect::<Vec<_>>(),
+            vec![deleted_items]
+        );
+
+        let moved = ipm_sub_folders(&path).unwrap();
+        assert_eq!(
+            moved,
+            vec![(projects, "Projects".to_string(), "Projects".to_string())]
+        );
+        assert_eq!(
+            sub_folder_ids(&path, projects).unwrap(),
+            vec![deleted_items]
+        );
+
+        let pst = PstFileReader::open(&path).unwrap();
+        assert!(pst
+            .folder(projects)
+            .unwrap()
+            .properties()
+            .has_sub_folders()
+            .unwrap());
+        assert_eq!(
+            pst.folder(deleted_items)
+                .unwrap()
+                .properties()
+                .display_name()
+                .unwrap(),
+            "Deleted Items"
+        );
+        drop(pst);
+
+        // Moving a folder into itself or its own sub-folder fails without touching the file.
+        assert!(projects_folder.move_to(&mut deleted_items_folder).is_err());
+        let mut projects_again = read_folder(&store, projects).unwrap();
+        assert!(projects_folder.move_to(&mut projects_again).is_err());
+        drop((store, projects_folder, projects_again, deleted_items_folder));
+        assert_eq!(
+            sub_folder_ids(&path, projects).unwrap(),
+            vec![deleted_items]
+        );
+        assert_eq!(ipm_sub_folders(&path).unwrap(), moved);
+    }
+
+    /// Add `count` messages to `folder`, every third one unread, the same way they would be
+    /// added by [`UnicodeMessage::copy_to`].
+    fn add_messages(path: &Path, folder: NodeId, count: usize) -> io::Result<Vec<NodeId>> {
+        edit_file(path, |editor| {
+            let mut unread = 0;
+            let messages = (0..count)
+                .map(|index| {
+                    let node = editor.new_node_id(NodeIdType::NormalMessage)?;
+                    let properties = MessageBuilder::new()
+                        .subject(&format!("Message {index}"))
+                        .read(index % 3 != 0)
+                        .build(editor, node, folder)?;
+                    unread += i32::from(properties.is_unread());
+                    editor.set_folder(properties);
+                    editor.update_contents_row(node)?;
+                    Ok(node)
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            editor.add_content_counts(folder, count as i32, unread)?;
+            Ok(messages)
+        })
+    }
+
+    /// The message count and unread count on the folder PC, and the rows in its contents table.
+    fn folder_contents(path: &Path, folder: NodeId) -> io::Result<(i32, i32, Vec<NodeId>)> {
+        let pst = PstFileReader::open(path)?;
+        let folder = pst.folder(folder)?;
+        let properties = folder.properties();
+        let rows = folder
+            .contents_table()
+            .map(|contents| {
+                contents
+                    .rows_matrix()
+                    .map(|rows| rows.map(|row| NodeId::from(u32::from(row.id()))).collect())
+            })
+            .transpose()?
+            .unwrap_or_default();
+        Ok((
+            properties.content_count()?,
+            properties.unread_count()?,
+            rows,
+        ))
+    }
+
+    /// The parent of each message node in the NBT.
+    fn message_parents(path: &Path, messages: &[NodeId]) -> io::Result<Vec<Option<NodeId>>> {
+        let pst = UnicodePstFile::open(path)?;
+        let mut editor = StoreEditor::new(&pst)?;
+        messages
+            .iter()
+            .map(|message| Ok(editor.read_node(*message)?.parent))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_row_by_id() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_find_row_by_id").unwrap();
+
+        let ipm_subtree = {
+            let pst = PstFileReader::open(&path).unwrap();
+            NodeId::from(&pst.store_properties().ipm_sub_tree_entry_id().unwrap())
+        };
+        let inbox = create_folder(&path, ipm_subtree, "Inbox").unwrap();
+        let messages = add_messages(&path, inbox, 1000).unwrap();
+
+        let pst = PstFileReader::open(&path).unwrap();
+        let folder = pst.folder(inbox).unwrap();
+        let contents = folder.contents_table().unwrap();
+        let subject = |row: &TableRowData| {
+            contents
+                .row_values(row)
+                .unwrap()
+                .into_iter()
+                .find_map(|(prop_id, value)| match value {
+                    PropertyValue::Unicode(value) if prop_id == PR_SUBJECT => {
+                        Some(value.to_string())
+                    }
+                    _ => None,
+                })
+        };
+
+        for (index, message) in messages.iter().enumerate().step_by(97) {
+            let id = TableRowId::new(u32::from(*message));
+            let found = contents.find_row_by_id(id).unwrap().unwrap();
+            let scanned = contents
+                .rows_iter()
+                .map(Result::unwrap)
+                .find(|row| row.id() == id)
+                .unwrap();
+            assert_eq!(found.id(), id);
+            assert_eq!(subject(&found), Some(format!("Message {index}")));
+            assert_eq!(subject(&found), subject(&scanned));
+        }
+
+        let missing = TableRowId::new(u32::from(inbox));
+        assert!(contents.find_row_by_id(missing).unwrap().is_none());
+
+        drop(folder);
+        drop(pst);
+    }
+
+    #[test]
+    fn test_associated_messages() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_associated_messages").unwrap();
+
+        let ipm_subtree = {
+            let pst = PstFileReader::open(&path).unwrap();
+            NodeId::from(&pst.store_properties().ipm_sub_tree_entry_id().unwrap())
+        };
+        let inbox = create_folder(&path, ipm_subtree, "Inbox").unwrap();
+        let view = edit_file(&path, |editor| {
+            let node = editor.new_node_id(NodeIdType::AssociatedMessage)?;
+            let properties = MessageBuilder::new()
+                .message_class("IPM.Microsoft.FolderDesign.NamedView")
+                .build(editor, node, inbox)?;
+            editor.set_folder(properties);
+            editor.update_contents_row(node)?;
+            Ok(node)
+        })
+        .unwrap();
+
+        let pst = PstFileReader::open(&path).unwrap();
+        let folder = pst.folder(inbox).unwrap();
+        assert_eq!(folder.contents_table().unwrap().rows_iter().count(), 0);
+        let associated = folder.associated_table().unwrap();
+        let rows = associated
+            .rows_iter()
+            .map(|row| NodeId::from(u32::from(row.unwrap().id())))
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec![view]);
+        assert!(matches!(
+            associated.cell(TableRowId::new(u32::from(view)), PR_MESSAGE_CLASS),
+            Ok(Some(PropertyValue::Unicode(_)))
+        ));
+
+        let message = folder
+            .store()
+            .open_message(
+                &folder.store().properties().make_entry_id(view).unwrap(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            message.properties().message_class().unwrap(),
+            "IPM.Microsoft.FolderDesign.NamedView"
+        );
+
+        drop(message);
+        drop(folder);
+        drop(pst);
+    }
+
+    #[test]
+    fn test_row_count() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_row_count").unwrap();
+
+        let ipm_subtree = {
+            let pst = PstFileReader::open(&path).unwrap();
+            NodeId::from(&pst.store_properties().ipm_sub_tree_entry_id().unwrap())
+        };
+        let inbox = create_folder(&path, ipm_subtree, "Inbox").unwrap();
+        let archive = create_folder(&path, ipm_subtree, "Archive").unwrap();
+        let row_count = |folder: NodeId| {
+            let pst = PstFileReader::open(&path).unwrap();
+            let folder = pst.folder(folder).unwrap();
+            let contents = folder.contents_table().unwrap();
+            assert_eq!(contents.row_count(), contents.rows_iter().count());
+            contents.row_count()
+        };
+        assert_eq!(row_count(inbox), 0);
+
+        let message = add_message(&path, inbox, &MessageBuilder::new()).unwrap();
+        assert_eq!(row_count(inbox), 1);
+        add_message(&path, inbox, &MessageBuilder::new()).unwrap();
+        assert_eq!(row_count(inbox), 2);
+
+        move_messages(&path, &[message], archive).unwrap();
+        assert_eq!(row_count(inbox), 1);
+        assert_eq!(row_count(archive), 1);
+    }
+
+    #[test]
+    fn test_move_and_copy_messages() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_move_messages").unwrap();
+
+        let ipm_subtree = {
+            let pst = PstFileReader::open(&path).unwrap();
+            NodeId::from(&pst.store_properties().ipm_sub_tree_entry_id().unwrap())
+        };
+        let inbox = create_folder(&path, ipm_subtree, "Inbox").unwrap();
+        let archive = create_folder(&path, ipm_subtree, "Archive").unwrap();
+        let mut messages = add_messages(&path, inbox, 50).unwrap();
+        messages.sort_by_key(|message| u32::from(*message));
+        assert_eq!(
+            folder_contents(&path, inbox).unwrap(),
+            (50, 17, messages.clone())
+        );
+
+        move_messages(&path, &messages, archive).unwrap();
+        assert_eq!(folder_contents(&path, inbox).unwrap(), (0, 0, vec![]));
+        assert_eq!(
+            folder_contents(&path, archive).unwrap(),
+            (50, 17, messages.clone())
+        );
+        assert_eq!(
+            message_parents(&path, &messages).unwrap(),
+            vec![Some(archive); 50]
+        );
+
+        // Copies get new node IDs, and the originals stay where they are.
+        let copies = copy_messages(&path, &messages[..3], inbox).unwrap();
+        assert!(copies.iter().all(|copy| !messages.contains(copy)));
+        assert_eq!(
+            folder_contents(&path, inbox).unwrap(),
+            (3, 1, copies.clone())
+        );
+        assert_eq!(folder_contents(&path, archive).unwrap().0, 50);
+        assert_eq!(
+            message_parents(&path, &copies).unwrap(),
+            vec![Some(inbox); 3]
+        );
+
+        // Only normal messages can be moved, and only into folders.
+        assert!(move_messages(&path, &[archive], inbox).is_err());
+        assert!(move_messages(&path, &messages[..1], messages[1]).is_err());
+        assert_eq!(folder_contents(&path, inbox).unwrap().0, 3);
+
+        // A message read from another store is refused, even for the same file.
+        let mut inbox_folder = open_folder(&path, inbox).unwrap();
+        let other_store =
+            UnicodeStore::read(Arc::new(UnicodePstFile::open(&path).unwrap())).unwrap();
+        let message = read_message(&other_store, messages[0]).unwrap();
+        let err = message.move_to(&mut inbox_folder).unwrap_err();
+        assert_eq!(err.to_string(), MessagingError::EditWrongFile.to_string());
+        drop((message, other_store, inbox_folder));
+        assert_eq!(folder_contents(&path, inbox).unwrap().0, 3);
+    }
+
+    /// `PR_MESSAGE_FLAGS` from the PC of each message, and from its row in the contents table of
+    /// `folder`.
+    fn message_flags(
+        path: &Path,
+        folder: NodeId,
+        messages: &[NodeId],
+    ) -> io::Result<Vec<(i32, Option<i32>)>> {
+        let pst = UnicodePstFile::open(path)?;
+        let mut editor = StoreEditor::new(&pst)?;
+        let reader = PstFileReader::open(path)?;
+        let folder = reader.folder(folder)?;
+        let contents = folder.contents_table().ok_or(io::ErrorKind::NotFound)?;
+        messages
+            .iter()
+            .map(|message| {
+                let properties =
+                    PropertyNode::read(&editor.read_node(*message)?, editor.max_data_size())?;
+                let row =
+                    match contents.cell(TableRowId::new(u32::from(*message)), PR_MESSAGE_FLAGS)? {
+                        Some(PropertyValue::Integer32(flags)) => Some(flags),
+                        _ => None,
+                    };
+                Ok((
+                    properties.integer(PR_MESSAGE_FLAGS).unwrap_or_default(),
+                    row,
+                ))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_mark_as_read() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_mark_as_read").unwrap();
+
+        let (inbox, ..) = ipm_sub_folders(&path).unwrap()[0];
+        let messages = add_messages(&path, inbox, 6).unwrap();
+        let unread = |path: &Path| folder_contents(path, inbox).unwrap().1;
+        assert_eq!(unread(&path), 2);
+
+        // Message 0 is unread and message 1 is read, so only one of each is changed.
+        let store = UnicodeStore::read(Arc::new(UnicodePstFile::open(&path).unwrap())).unwrap();
+        let mut opened = messages
+            .iter()
+            .map(|message| Arc::into_inner(read_message(&store, *message).unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        let is_read = |opened: &[UnicodeMessage]| {
+            opened
+                .iter()
+                .map(|message| message.is_read().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(is_read(&opened), vec![false, true, true, false, true, true]);
+        for message in &mut opened[..2] {
+            message.mark_as_read().unwrap();
+        }
+        assert_eq!(unread(&path), 1);
+        for message in &mut opened[1..3] {
+            message.mark_as_unread().unwrap();
+        }
+        assert_eq!(unread(&path), 3);
+        assert_eq!(
+            is_read(&opened),
+            vec![true, false, false, false, true, true]
+        );
+        drop((opened, store));
+
+        let read = MSGFLAG_READ;
+        assert_eq!(
+            message_flags(&path, inbox, &messages[..4]).unwrap(),
+            vec![(read, Some(read)), (0, Some(0)), (0, Some(0)), (0, Some(0))]
+        );
+
+        assert!(open_message(&path, messages[0]).unwrap().is_read().unwrap());
+        assert!(open_message(&path, inbox).is_err());
+        assert_eq!(unread(&path), 3);
+    }
+
+    #[test]
+    fn test_add_message_in_memory() {
+        let pst = UnicodePstFile::create_in_memory(NdbCryptMethod::None).unwrap();
+        let store = UnicodeStore::read(Arc::new(pst)).unwrap();
+        let ipm_subtree = NodeId::from(&store.properties().ipm_sub_tree_entry_id().unwrap());
+        let mut folder = read_folder(&store, ipm_subtree).unwrap();
+        let message = folder
+            .add_message(&MessageBuilder::new().subject("In memory"))
+            .unwrap();
+
+        // The folder sees the new counts and contents table without being read again.
+        let properties = folder.properties();
+        assert_eq!(properties.content_count().unwrap(), 1);
+        assert_eq!(properties.unread_count().unwrap(), 1);
+        assert_eq!(folder.messages().count(), 1);
+
+        let message = read_message(&store, message).unwrap();
+        assert_eq!(
+            message
+                .properties()
+                .get_typed::<String>(PR_SUBJECT)
+                .unwrap()
+                .as_deref(),
+            Some("In memory")
+        );
+    }
+
+    #[test]
+    fn test_add_message() {
+        let path = TempFile::copy_of(EMPTY_PST, "edit_add_message").unwrap();
+
+        let (inbox, ..) = ipm_sub_folders(&path).unwrap()[0];
+        let body = "The quick brown fox jumps over the lazy dog. ".repeat(500);
+        assert!(body.len() > 20 * 1024);
+        let attachment: Vec<u8> = (0..5000).map(|index| (index % 251) as u8).collect();
+        let builder = MessageBuilder::new()
+            .subject("Quarterly report")
+            .body(&body)
+            .recipient(RecipientType::To, "Alice", "alice@example.com")
+            .recipient(RecipientType::Cc, "Bob", "bob@example.com")
+            .attachment("report.bin", attachment.clone());
+        let message = add_message(&path, inbox, &builder).unwrap();
+
+        let (count, unread, rows) = folder_contents(&path, inbox).unwrap();
+        assert_eq!((count, unread, rows), (1, 1, vec![message]));
+        assert_eq!(
+            UnicodePstFile::open(&path)
+                .unwrap()
+                .header()
+                .root()
+                .amap_is_valid(),
+            AmapStatus::Valid2
+        );
+
+        let pst = PstFileReader::open(&path).unwrap();
+        let message = pst.message(message).unwrap();
+        let properties = message.properties();
+        assert_eq!(
+            properties.get_string8(PR_SUBJECT).unwrap().as_deref(),
+            Some("Quarterly report")
+        );
+        assert_eq!(
+            properties.get_string8(PR_BODY).unwrap().as_deref(),
+            Some(body.as_str())
+        );
+        assert_eq!(
+            properties.get_string8(PR_DISPLAY_TO).unwrap().as_deref(),
+            Some("Alice")
+        );
+        assert!(!properties.is_read().unwrap());
+
+        let recipients = message
+            .recipients()
+            .iter()
+            .map(|recipient| {
+                let recipient = recipient?;
+                Ok((
+                    recipient.recipient_type(),
+                    recipient.display_name()?,
+                    recipient.email_address()?,
+                ))
+            })
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            recipients,
+            vec![
+                (
+                    RecipientType::To,
+                    Some("Alice".to_string()),
+                    Some("alice@example.com".to_string())
+                ),
+                (
+                    RecipientType::Cc,
+                    Some("Bob".to_string()),
+                    Some("bob@example.com".to_string())
+                ),
+            ]
+        );
+
+        let attachments = message.attachments(None).unwrap();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].properties().attachment_size().unwrap(), 5000);
+        let Some(AttachmentData::Binary(data)) = attachments[0].data() else {
+            panic!("attachment should have binary data");
+        };
+        assert_eq!(data.buffer(), attachment.as_slice());
+    }
+}
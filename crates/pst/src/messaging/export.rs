@@ -0,0 +1,427 @@
+//! Export data from a store: the folder hierarchy as JSON, without reading any message content,
+//! and attachment files into a directory tree which mirrors the folders.
+
+#[cfg(feature = "json")]
+use serde::{
+    ser::{Error, SerializeMap, SerializeSeq},
+    Serialize, Serializer,
+};
+#[cfg(feature = "json")]
+use std::io::Write;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use super::{attachment::*, folder::*, property_ids::*, search::*, store::*};
+use crate::{
+    ltp::prop_context::TypedProperties,
+    ndb::node_id::{NodeId, NID_ROOT_FOLDER},
+};
+
+/// Serialize the complete folder tree, starting at the root folder, as a JSON string. Each
+/// folder is an object with `name`, `message_count`, `unread` and `sub_folders` fields.
+#[cfg(feature = "json")]
+pub fn hierarchy_to_json(store: &dyn Store) -> io::Result<String> {
+    let mut buffer = vec![];
+    hierarchy_to_json_writer(store, &mut buffer)?;
+    String::from_utf8(buffer).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Stream the same JSON as [`hierarchy_to_json`] to `writer`. Folders are opened as they are
+/// written and released after all of their sub-folders, so only the folders on the path from the
+/// root to the current folder are held in memory.
+#[cfg(feature = "json")]
+pub fn hierarchy_to_json_writer<W: Write>(store: &dyn Store, writer: &mut W) -> io::Result<()> {
+    let root = open_folder(store, NID_ROOT_FOLDER)?;
+    serde_json::to_writer(
+        writer,
+        &FolderNode {
+            store,
+            folder: root,
+        },
+    )?;
+    Ok(())
+}
+
+#[cfg(feature = "json")]
+struct FolderNode<'a> {
+    store: &'a dyn Store,
+    folder: Arc<dyn Folder>,
+}
+
+#[cfg(feature = "json")]
+impl Serialize for FolderNode<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // The root folder does not have a PidTagDisplayName.
+        let name = self.folder.properties().display_name().unwrap_or_default();
+        let message_count = self.folder.message_count().map_err(S::Error::custom)?;
+        let unread = self.folder.unread_count().map_err(S::Error::custom)?;
+
+        let mut map = serializer.serialize_map(Some(4))?;
+        map.serialize_entry("name", &name)?;
+        map.serialize_entry("message_count", &message_count)?;
+        map.serialize_entry("unread", &unread)?;
+        map.serialize_entry(
+            "sub_folders",
+            &SubFolders {
+                store: self.store,
+                folder: self.folder.as_ref(),
+            },
+        )?;
+        map.end()
+    }
+}
+
+#[cfg(feature = "json")]
+struct SubFolders<'a> {
+    store: &'a dyn Store,
+    folder: &'a dyn Folder,
+}
+
+#[cfg(feature = "json")]
+impl Serialize for SubFolders<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(None)?;
+        if let Some(hierarchy_table) = self
+            .folder
+            .has_sub_folders()
+            .then(|| self.folder.hierarchy_table())
+            .flatten()
+        {
+            for row in hierarchy_table.rows_iter() {
+                let row = row.map_err(S::Error::custom)?;
+                let folder = open_folder(self.store, NodeId::from(u32::from(row.id())))
+                    .map_err(S::Error::custom)?;
+                seq.serialize_element(&FolderNode {
+                    store: self.store,
+                    folder,
+                })?;
+            }
+        }
+        seq.end()
+    }
+}
+
+/// Filter for [`extract_attachments`]. Every criterion which is set must match.
+#[derive(Clone, Default, Debug)]
+pub struct AttachmentFilter {
+    mime_types: Option<Vec<String>>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    delivered_after: Option<SystemTime>,
+}
+
+impl AttachmentFilter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only match attachments with one of these `PidTagAttachMimeTag` values. The comparison is
+    /// case-insensitive, and an entry like `image/*` matches every subtype.
+    pub fn mime_types(&mut self, types: &[&str]) -> &mut Self {
+        self.mime_types = Some(types.iter().map(|value| value.to_lowercase()).collect());
+        self
+    }
+
+    /// Only match attachments with at least `bytes` of data.
+    pub fn min_size(&mut self, bytes: u64) -> &mut Self {
+        self.min_size = Some(bytes);
+        self
+    }
+
+    /// Only match attachments with at most `bytes` of data.
+    pub fn max_size(&mut self, bytes: u64) -> &mut Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Only match attachments on messages with a `PidTagMessageDeliveryTime` at or after `t`.
+    pub fn delivered_after(&mut self, t: SystemTime) -> &mut Self {
+        self.delivered_after = Some(t);
+        self
+    }
+
+    fn matches(&self, mime_type: Option<&str>, size: u64) -> bool {
+        if self.min_size.is_some_and(|min_size| size < min_size)
+            || self.max_size.is_some_and(|max_size| size > max_size)
+        {
+            return false;
+        }
+
+        let Some(mime_types) = &self.mime_types else {
+            return true;
+        };
+        let Some(mime_type) = mime_type.map(str::to_lowercase) else {
+            return false;
+        };
+        mime_types
+            .iter()
+            .any(|filter| match filter.strip_suffix("/*") {
+                Some(prefix) => mime_type
+                    .split_once('/')
+                    .is_some_and(|(major, _)| major == prefix),
+                None => *filter == mime_type,
+            })
+    }
+}
+
+/// Save every file attachment which matches `filter` under `dir`, in a sub-directory for each
+/// folder named after its `PidTagDisplayName`. Attachments are named after their original file
+/// name, and a name which is already taken gets a `_2`, `_3`, etc. suffix. Embedded messages and
+/// attachments stored by reference are skipped. Returns the number of files written.
+pub fn extract_attachments(
+    store: &dyn Store,
+    dir: &Path,
+    filter: &AttachmentFilter,
+) -> io::Result<u64> {
+    extract_attachments_with_progress(store, dir, filter, |_, _| {})
+}
+
+/// Same as [`extract_attachments`], but call `progress` with the number of files extracted so far
+/// and an estimate of the total after each one is written. The estimate assumes one more file for
+/// each message which has not been read yet.
+pub fn extract_attachments_with_progress(
+    store: &dyn Store,
+    dir: &Path,
+    filter: &AttachmentFilter,
+    progress: impl Fn(u64, u64),
+) -> io::Result<u64> {
+    let folder_paths = folder_paths(store, dir)?;
+
+    let mut criteria = SearchCriteria::new();
+    criteria.has_attachments(true);
+    if let Some(delivered_after) = filter.delivered_after {
+        criteria.delivered_after(delivered_after);
+    }
+    let messages = store.search_messages(&criteria)?;
+
+    let properties = store.properties();
+    let mut extracted = 0;
+    for (index, message) in messages.iter().enumerate() {
+        let entry_id = properties.make_entry_id(message.node_id())?;
+        let folder_path = folder_paths
+            .get(&message.folder_id())
+            .map_or(dir, PathBuf::as_path);
+        let remaining = (messages.len() - index - 1) as u64;
+
+        for attachment in store.open_attachments(&entry_id, None)? {
+            let Some(AttachmentData::Binary(data)) = attachment.data() else {
+                continue;
+            };
+            let properties = attachment.properties();
+            let mime_type = properties.get_typed::<String>(PR_ATTACH_MIME_TAG)?;
+            if !filter.matches(mime_type.as_deref(), data.buffer().len() as u64) {
+                continue;
+            }
+
+            let file_name = [PR_ATTACH_LONG_FILENAME, PR_ATTACH_FILENAME, PR_DISPLAY_NAME]
+                .into_iter()
+                .find_map(|prop_id| properties.get_typed::<String>(prop_id).ok().flatten())
+                .filter(|file_name| !file_name.is_empty())
+                .unwrap_or_else(|| String::from("attachment"));
+
+            fs::create_dir_all(folder_path)?;
+            let path = unique_path(folder_path, &sanitize_file_name(&file_name));
+            fs::write(path, data.buffer())?;
+
+            extracted += 1;
+            progress(extracted, extracted + remaining);
+        }
+    }
+
+    Ok(extracted)
+}
+
+fn open_folder(store: &dyn Store, node_id: NodeId) -> io::Result<Arc<dyn Folder>> {
+    let entry_id = store.properties().make_entry_id(node_id)?;
+    store.open_folder(&entry_id)
+}
+
+/// Map each folder to the directory its attachments are saved in. The root folder does not have
+/// a `PidTagDisplayName`, so it maps to `dir` itself.
+fn folder_paths(store: &dyn Store, dir: &Path) -> io::Result<BTreeMap<NodeId, PathBuf>> {
+    let mut paths = BTreeMap::new();
+    let mut pending = vec![(NID_ROOT_FOLDER, None::<PathBuf>)];
+
+    while let Some((node_id, parent)) = pending.pop() {
+        let folder = open_folder(store, node_id)?;
+        let path = match parent {
+            Some(parent) => parent.join(sanitize_file_name(&folder.properties().display_name()?)),
+            None => dir.to_path_buf(),
+        };
+
+        if let Some(hierarchy_table) = folder
+            .has_sub_folders()
+            .then(|| folder.hierarchy_table())
+            .flatten()
+        {
+            for row in hierarchy_table.rows_iter() {
+                let node_id = NodeId::from(u32::from(row?.id()));
+                pending.push((node_id, Some(path.clone())));
+            }
+        }
+
+        paths.insert(node_id, path);
+    }
+
+    Ok(paths)
+}
+
+/// Replace the characters which are not allowed in a file name on Windows or Unix.
+fn sanitize_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim().trim_end_matches('.');
+    if name.is_empty() {
+        String::from("_")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Append `_2`, `_3`, etc. to the file stem until the name is not taken in `dir`.
+fn unique_path(dir: &Path, file_name: &str) -> PathBuf {
+    let path = dir.join(file_name);
+    if !path.exists() {
+        return path;
+    }
+
+    let file_name = Path::new(file_name);
+    let stem = file_name
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let extension = file_name
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (2..)
+        .map(|index| dir.join(format!("{stem}_{index}{extension}")))
+        .find(|path| !path.exists())
+        .expect("unbounded range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{TempFile, EMPTY_PST};
+    use crate::UnicodePstFile;
+
+    #[cfg(feature = "json")]
+    fn count_folders(folder: &serde_json::Value) -> usize {
+        1 + folder["sub_folders"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(count_folders)
+            .sum::<usize>()
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_hierarchy_to_json() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+
+        let json = hierarchy_to_json(store.as_ref()).unwrap();
+        let root: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(root["name"], "");
+        assert_eq!(root["message_count"], 0);
+        assert_eq!(root["unread"], 0);
+
+        let sub_folders = root["sub_folders"].as_array().unwrap();
+        assert!(sub_folders
+            .iter()
+            .any(|folder| folder["name"] == "Top of Outlook data file"));
+
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let reader = crate::PstFileReader::Unicode(UnicodeStore::read(pst).unwrap());
+        assert_eq!(count_folders(&root), reader.folders().unwrap().len());
+
+        let mut buffer = vec![];
+        hierarchy_to_json_writer(store.as_ref(), &mut buffer).unwrap();
+        assert_eq!(buffer, json.as_bytes());
+    }
+
+    #[test]
+    fn test_attachment_filter_matches() {
+        let mut filter = AttachmentFilter::new();
+        assert!(filter.matches(None, 0));
+
+        filter.mime_types(&["application/PDF", "image/*"]);
+        assert!(filter.matches(Some("application/pdf"), 10));
+        assert!(filter.matches(Some("Image/PNG"), 10));
+        assert!(!filter.matches(Some("text/plain"), 10));
+        assert!(!filter.matches(Some("imagery/png"), 10));
+        assert!(!filter.matches(None, 10));
+
+        filter.min_size(5).max_size(20);
+        assert!(filter.matches(Some("image/jpeg"), 5));
+        assert!(filter.matches(Some("image/jpeg"), 20));
+        assert!(!filter.matches(Some("image/jpeg"), 4));
+        assert!(!filter.matches(Some("image/jpeg"), 21));
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("report.pdf"), "report.pdf");
+        assert_eq!(sanitize_file_name("a/b\\c:d?.txt"), "a_b_c_d_.txt");
+        assert_eq!(sanitize_file_name(" name. "), "name");
+        assert_eq!(sanitize_file_name(".."), "_");
+        assert_eq!(sanitize_file_name(""), "_");
+    }
+
+    #[test]
+    fn test_unique_path() {
+        let dir = TempFile::dir("unique_path");
+        fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(unique_path(&dir, "report.pdf"), dir.join("report.pdf"));
+        fs::write(dir.join("report.pdf"), b"1").unwrap();
+        assert_eq!(unique_path(&dir, "report.pdf"), dir.join("report_2.pdf"));
+        fs::write(dir.join("report_2.pdf"), b"2").unwrap();
+        assert_eq!(unique_path(&dir, "report.pdf"), dir.join("report_3.pdf"));
+        fs::write(dir.join("README"), b"3").unwrap();
+        assert_eq!(unique_path(&dir, "README"), dir.join("README_2"));
+    }
+
+    #[test]
+    fn test_extract_attachments_empty() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+
+        let dir = TempFile::dir("extract_attachments");
+        let extracted = extract_attachments_with_progress(
+            store.as_ref(),
+            &dir,
+            &AttachmentFilter::new(),
+            |_, _| panic!("No attachments to extract"),
+        )
+        .unwrap();
+        assert_eq!(extracted, 0);
+        assert!(!dir.exists());
+
+        let paths = folder_paths(store.as_ref(), &dir).unwrap();
+        assert_eq!(paths[&NID_ROOT_FOLDER], *dir);
+        assert!(paths
+            .values()
+            .any(|path| path.ends_with("Top of Outlook data file/Deleted Items")));
+    }
+}
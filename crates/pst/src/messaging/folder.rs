@@ -1,15 +1,20 @@
 //! ## [Folders](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/dee5b9d0-5513-4c5e-94aa-8bd28a9350b2)
 
-use std::{cell::OnceCell, collections::BTreeMap, io, rc::Rc};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    iter::FusedIterator,
+    sync::{Arc, OnceLock},
+};
 
-use super::{read_write::*, store::*, *};
+use super::{message::MessageSummary, property_ids::*, read_write::*, store::*, *};
 use crate::{
     ltp::{
         heap::HeapNode,
-        prop_context::{BinaryValue, PropertyContext, PropertyValue},
-        prop_type::PropertyType,
+        prop_context::{BinaryValue, PropertyContext, PropertyValue, TypedProperties},
+        prop_type::{decode_string8, PropertyType, DEFAULT_CODEPAGE},
         read_write::*,
-        table_context::TableContext,
+        table_context::{TableContext, TableRowData},
     },
     ndb::{
         block_id::BlockId,
@@ -26,6 +31,33 @@ use crate::{
 pub struct FolderProperties {
     node_id: NodeId,
     properties: BTreeMap<u16, PropertyValue>,
+    store_codepage: Option<u32>,
+}
+
+/// Well known properties are serialized as named fields, like `name`, `content_count` and
+/// `unread_count`. Every property is also included in the `properties` map, keyed by its hex
+/// property ID.
+#[cfg(feature = "serde")]
+impl serde::Serialize for FolderProperties {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        const FIELDS: &[(&str, &[u16])] = &[
+            ("name", &[PR_DISPLAY_NAME]),
+            ("container_class", &[PR_CONTAINER_CLASS]),
+            ("content_count", &[PR_CONTENT_COUNT]),
+            ("unread_count", &[PR_CONTENT_UNREAD]),
+            ("has_sub_folders", &[PR_SUBFOLDERS]),
+        ];
+
+        let mut state = serializer.serialize_struct("FolderProperties", FIELDS.len() + 2)?;
+        state.serialize_field("node_id", &self.node_id)?;
+        super::serialize_named_properties(&mut state, FIELDS, &self.properties, self.codepage())?;
+        state.end()
+    }
 }
 
 impl FolderProperties {
@@ -44,11 +76,11 @@ impl FolderProperties {
     pub fn display_name(&self) -> io::Result<String> {
         let display_name = self
             .properties
-            .get(&0x3001)
+            .get(&PR_DISPLAY_NAME)
             .ok_or(MessagingError::FolderDisplayNameNotFound)?;
 
         match display_name {
-            PropertyValue::String8(value) => Ok(value.to_string()),
+            PropertyValue::String8(value) => Ok(decode_string8(value.buffer(), self.codepage())?),
             PropertyValue::Unicode(value) => Ok(value.to_string()),
             invalid => {
                 Err(MessagingError::InvalidFolderDisplayName(PropertyType::from(invalid)).into())
@@ -59,7 +91,7 @@ impl FolderProperties {
     pub fn content_count(&self) -> io::Result<i32> {
         let content_count = self
             .properties
-            .get(&0x3602)
+            .get(&PR_CONTENT_COUNT)
             .ok_or(MessagingError::FolderContentCountNotFound)?;
 
         match content_count {
@@ -73,7 +105,7 @@ impl FolderProperties {
     pub fn unread_count(&self) -> io::Result<i32> {
         let unread_count = self
             .properties
-            .get(&0x3603)
+            .get(&PR_CONTENT_UNREAD)
             .ok_or(MessagingError::FolderUnreadCountNotFound)?;
 
         match unread_count {
@@ -87,7 +119,7 @@ impl FolderProperties {
     pub fn has_sub_folders(&self) -> io::Result<bool> {
         let entry_id = self
             .properties
-            .get(&0x360A)
+            .get(&PR_SUBFOLDERS)
             .ok_or(MessagingError::FolderHasSubfoldersNotFound)?;
 
         match entry_id {
@@ -99,23 +131,284 @@ impl FolderProperties {
     }
 }
 
-pub trait Folder {
-    fn store(&self) -> Rc<dyn Store>;
+/// Folders encode `PtypString8` properties with the default code page of their store.
+impl TypedProperties for FolderProperties {
+    fn property_value(&self, id: u16) -> Option<&PropertyValue> {
+        self.get(id)
+    }
+
+    fn codepage(&self) -> u32 {
+        self.store_codepage.unwrap_or(DEFAULT_CODEPAGE)
+    }
+}
+
+pub trait Folder: Send + Sync {
+    fn store(&self) -> Arc<dyn Store>;
     fn properties(&self) -> &FolderProperties;
-    fn hierarchy_table(&self) -> Option<&Rc<dyn TableContext>>;
-    fn contents_table(&self) -> Option<&Rc<dyn TableContext>>;
-    fn associated_table(&self) -> Option<&Rc<dyn TableContext>>;
+    fn hierarchy_table(&self) -> Option<&Arc<dyn TableContext>>;
+    fn contents_table(&self) -> Option<&Arc<dyn TableContext>>;
+    /// The associated contents table, with a row for each folder associated information (FAI)
+    /// message in the folder, such as saved views, rules and forms.
+    fn associated_table(&self) -> Option<&Arc<dyn TableContext>>;
+
+    /// Whether the folder may have sub-folders. This is only `false` if `PidTagSubfolders` is
+    /// set to `false`, so a walk over the folder hierarchy can skip reading the hierarchy table
+    /// of leaf folders without missing the sub-folders of a folder which omits the property.
+    fn has_sub_folders(&self) -> bool {
+        !matches!(
+            self.properties().get(PR_SUBFOLDERS),
+            Some(PropertyValue::Boolean(false))
+        )
+    }
+
+    /// Lazily iterate over the messages in the contents table, see [`MessageIter`].
+    fn messages(&self) -> MessageIter {
+        MessageIter::new(self.store(), self.contents_table().cloned())
+    }
+
+    /// Get `PidTagContentCount`, or the number of rows in the contents table if the folder does
+    /// not have a valid count.
+    fn message_count(&self) -> io::Result<u32> {
+        let content_count = self.properties().get_typed::<i32>(PR_CONTENT_COUNT)?;
+        if let Some(Ok(count)) = content_count.map(u32::try_from) {
+            return Ok(count);
+        }
+
+        let count = self.contents_table().map_or(0, |table| table.row_count());
+        Ok(u32::try_from(count).unwrap_or(u32::MAX))
+    }
+
+    /// Get `PidTagContentUnreadCount`, or count the rows in the contents table without
+    /// `MSGFLAG_READ` if the folder does not have a valid count.
+    fn unread_count(&self) -> io::Result<u32> {
+        let unread_count = self.properties().get_typed::<i32>(PR_CONTENT_UNREAD)?;
+        if let Some(Ok(count)) = unread_count.map(u32::try_from) {
+            return Ok(count);
+        }
+
+        let mut count = 0;
+        for_each_contents_value(self, PR_MESSAGE_FLAGS, |flags| {
+            if !matches!(flags, Some(PropertyValue::Integer32(flags)) if flags & MSGFLAG_READ != 0)
+            {
+                count += 1;
+            }
+        })?;
+        Ok(count)
+    }
+
+    /// Sum `PidTagMessageSize` (or `PidTagMessageSizeExtended`) across the contents table.
+    fn total_size_bytes(&self) -> io::Result<u64> {
+        let mut total = 0_u64;
+        for_each_contents_value(self, PR_MESSAGE_SIZE, |size| {
+            total += match size {
+                Some(PropertyValue::Integer32(size)) => u64::from(size as u32),
+                Some(PropertyValue::Integer64(size)) => size as u64,
+                _ => 0,
+            };
+        })?;
+        Ok(total)
+    }
+}
+
+/// Call `f` with the `prop_id` column of every row in the folder's contents table, reading the
+/// rows one data block at a time.
+fn for_each_contents_value<F: Folder + ?Sized>(
+    folder: &F,
+    prop_id: u16,
+    mut f: impl FnMut(Option<PropertyValue>),
+) -> io::Result<()> {
+    let Some(contents_table) = folder.contents_table() else {
+        return Ok(());
+    };
+    let context = contents_table.context();
+    let column = context
+        .columns()
+        .iter()
+        .find(|column| column.prop_id() == prop_id);
+
+    for row in contents_table.rows_iter() {
+        let row = row?;
+        let value = match column {
+            Some(column) => row
+                .column(context, column)?
+                .map(|value| contents_table.read_column(&value, column.prop_type()))
+                .transpose()?,
+            None => None,
+        };
+        f(value);
+    }
+
+    Ok(())
+}
+
+/// Iterator over a folder and every folder below it in the hierarchy, see
+/// [`Store::folders_dfs`]. Only the IDs of the folders which have not been visited yet are kept,
+/// and each folder is opened when it is returned. A folder which fails to open, or whose
+/// hierarchy table cannot be read, is returned as an error and the walk continues without its
+/// sub-folders.
+pub struct FolderIter {
+    breadth_first: bool,
+    store: Option<Arc<dyn Store>>,
+    next: Option<io::Result<Arc<dyn Folder>>>,
+    pending: VecDeque<NodeId>,
+}
+
+impl FolderIter {
+    /// Visit `root` first, then each sub-folder followed by all of the folders below it, in the
+    /// order of the hierarchy tables.
+    pub fn depth_first(root: Arc<dyn Folder>) -> Self {
+        Self::new(Ok(root), false)
+    }
+
+    /// Visit `root` first, then all of its sub-folders, then all of their sub-folders, and so on.
+    pub fn breadth_first(root: Arc<dyn Folder>) -> Self {
+        Self::new(Ok(root), true)
+    }
+
+    pub(crate) fn new(root: io::Result<Arc<dyn Folder>>, breadth_first: bool) -> Self {
+        Self {
+            breadth_first,
+            store: None,
+            next: Some(root),
+            pending: Default::default(),
+        }
+    }
+
+    fn open_next(&mut self) -> Option<io::Result<Arc<dyn Folder>>> {
+        if let Some(folder) = self.next.take() {
+            return Some(folder);
+        }
+
+        let node_id = if self.breadth_first {
+            self.pending.pop_front()
+        } else {
+            self.pending.pop_back()
+        }?;
+        let store = self.store.as_ref()?;
+        Some(
+            store
+                .properties()
+                .make_entry_id(node_id)
+                .and_then(|entry_id| store.open_folder(&entry_id)),
+        )
+    }
+
+    fn push_sub_folders(&mut self, folder: &dyn Folder) -> io::Result<()> {
+        if !folder.has_sub_folders() {
+            return Ok(());
+        }
+        let Some(hierarchy_table) = folder.hierarchy_table() else {
+            return Ok(());
+        };
+
+        let sub_folders = hierarchy_table
+            .rows_iter()
+            .map(|row| Ok(NodeId::from(u32::from(row?.id()))))
+            .collect::<io::Result<Vec<_>>>()?;
+        if self.breadth_first {
+            self.pending.extend(sub_folders);
+        } else {
+            // The last folder pushed is the first one visited.
+            self.pending.extend(sub_folders.into_iter().rev());
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for FolderIter {
+    type Item = io::Result<Arc<dyn Folder>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let folder = match self.open_next()? {
+            Ok(folder) => folder,
+            Err(err) => return Some(Err(err)),
+        };
+        if self.store.is_none() {
+            self.store = Some(folder.store());
+        }
+        if let Err(err) = self.push_sub_folders(folder.as_ref()) {
+            return Some(Err(err));
+        }
+        Some(Ok(folder))
+    }
 }
 
+impl FusedIterator for FolderIter {}
+
+/// Iterator over the rows of a folder's contents table, see [`Folder::messages`]. The rows are
+/// read [`MessageIter::BATCH_SIZE`] at a time, and each [`MessageSummary`] only holds the
+/// columns of its row, so the messages themselves are not opened till [`MessageSummary::load`]
+/// is called. A batch of rows which cannot be read is returned as an error, and the iteration
+/// continues with the next batch.
+pub struct MessageIter {
+    store: Arc<dyn Store>,
+    contents_table: Option<Arc<dyn TableContext>>,
+    next_row: usize,
+    rows: VecDeque<TableRowData>,
+}
+
+impl MessageIter {
+    /// How many rows are read from the contents table at once.
+    pub const BATCH_SIZE: usize = 64;
+
+    fn new(store: Arc<dyn Store>, contents_table: Option<Arc<dyn TableContext>>) -> Self {
+        Self {
+            store,
+            contents_table,
+            next_row: 0,
+            rows: Default::default(),
+        }
+    }
+
+    fn message_ref(&self, row: TableRowData) -> io::Result<MessageSummary> {
+        let node_id = NodeId::from(u32::from(row.id()));
+        let entry_id = self.store.properties().make_entry_id(node_id)?;
+        let properties = match self.contents_table.as_ref() {
+            Some(contents_table) => contents_table.row_values(&row)?.into_iter().collect(),
+            None => BTreeMap::new(),
+        };
+        Ok(MessageSummary::new(
+            self.store.clone(),
+            entry_id,
+            properties.into(),
+        ))
+    }
+}
+
+impl Iterator for MessageIter {
+    type Item = io::Result<MessageSummary>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rows.is_empty() {
+            let contents_table = self.contents_table.as_ref()?;
+            let start = self.next_row;
+            self.next_row = start.saturating_add(Self::BATCH_SIZE);
+            match contents_table.rows_range(start, Self::BATCH_SIZE) {
+                Ok(rows) if rows.is_empty() => {
+                    self.contents_table = None;
+                    return None;
+                }
+                Ok(rows) => self.rows.extend(rows),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        let row = self.rows.pop_front()?;
+        Some(self.message_ref(row))
+    }
+}
+
+impl FusedIterator for MessageIter {}
+
 struct FolderInner<Pst>
 where
     Pst: PstFile,
 {
-    store: Rc<Pst::Store>,
+    store: Arc<Pst::Store>,
     properties: FolderProperties,
-    hierarchy_table: OnceCell<Option<Rc<dyn TableContext>>>,
-    contents_table: OnceCell<Option<Rc<dyn TableContext>>>,
-    associated_table: OnceCell<Option<Rc<dyn TableContext>>>,
+    hierarchy_table: OnceLock<Option<Arc<dyn TableContext>>>,
+    contents_table: OnceLock<Option<Arc<dyn TableContext>>>,
+    associated_table: OnceLock<Option<Arc<dyn TableContext>>>,
 }
 
 impl<Pst> FolderInner<Pst>
@@ -151,7 +444,7 @@ where
     <Pst as PstFile>::PropertyContext: PropertyContextReadWrite<Pst>,
     <Pst as PstFile>::Store: StoreReadWrite<Pst>,
 {
-    fn read(store: Rc<<Pst as PstFile>::Store>, entry_id: &EntryId) -> io::Result<Self> {
+    fn read(store: Arc<<Pst as PstFile>::Store>, entry_id: &EntryId) -> io::Result<Self> {
         let node_id = entry_id.node_id();
         let node_id_type = node_id.id_type()?;
         match node_id_type {
@@ -218,14 +511,18 @@ where
                         .map(|value| (prop_id, value))
                 })
                 .chain([
-                    Ok((0x0FFF, PropertyValue::Binary(BinaryValue::new(entry_id)))),
-                    Ok((0x3601, PropertyValue::Integer32(folder_type))),
+                    Ok((
+                        PR_ENTRYID,
+                        PropertyValue::Binary(BinaryValue::new(entry_id)),
+                    )),
+                    Ok((PR_FOLDER_TYPE, PropertyValue::Integer32(folder_type))),
                 ])
                 .collect::<io::Result<BTreeMap<_, _>>>()?;
 
             FolderProperties {
                 node_id,
                 properties,
+                store_codepage: Some(store.properties().codepage()),
             }
         };
 
@@ -238,7 +535,7 @@ where
         })
     }
 
-    fn read_table(&self, node_id_type: NodeIdType) -> io::Result<Option<Rc<dyn TableContext>>> {
+    fn read_table(&self, node_id_type: NodeIdType) -> io::Result<Option<Arc<dyn TableContext>>> {
         let pst = self.store.pst();
         let header = pst.header();
         let root = header.root();
@@ -273,19 +570,19 @@ where
         ))
     }
 
-    fn hierarchy_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn hierarchy_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.hierarchy_table
             .get_or_init(|| self.read_table(NodeIdType::HierarchyTable).ok()?)
             .as_ref()
     }
 
-    fn contents_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn contents_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.contents_table
             .get_or_init(|| self.read_table(NodeIdType::ContentsTable).ok()?)
             .as_ref()
     }
 
-    fn associated_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn associated_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.associated_table
             .get_or_init(|| self.read_table(NodeIdType::AssociatedContentsTable).ok()?)
             .as_ref()
@@ -297,13 +594,24 @@ pub struct UnicodeFolder {
 }
 
 impl UnicodeFolder {
-    pub fn read(store: Rc<UnicodeStore>, entry_id: &EntryId) -> io::Result<Rc<Self>> {
+    pub fn read(store: Arc<UnicodeStore>, entry_id: &EntryId) -> io::Result<Arc<Self>> {
         <Self as FolderReadWrite<UnicodePstFile>>::read(store, entry_id)
     }
+
+    /// Read the properties of this folder again from its store, and drop the cached tables, after
+    /// an edit changed them.
+    pub(crate) fn reload(&mut self) -> io::Result<()> {
+        let store = self.inner.store.clone();
+        let entry_id = store
+            .properties()
+            .make_entry_id(self.inner.properties.node_id)?;
+        self.inner = FolderInner::read(store, &entry_id)?;
+        Ok(())
+    }
 }
 
 impl Folder for UnicodeFolder {
-    fn store(&self) -> Rc<dyn Store> {
+    fn store(&self) -> Arc<dyn Store> {
         self.inner.store.clone()
     }
 
@@ -311,23 +619,27 @@ impl Folder for UnicodeFolder {
         &self.inner.properties
     }
 
-    fn hierarchy_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn hierarchy_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.inner.hierarchy_table()
     }
 
-    fn contents_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn contents_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.inner.contents_table()
     }
 
-    fn associated_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn associated_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.inner.associated_table()
     }
 }
 
 impl FolderReadWrite<UnicodePstFile> for UnicodeFolder {
-    fn read(store: Rc<UnicodeStore>, entry_id: &EntryId) -> io::Result<Rc<Self>> {
+    fn read(store: Arc<UnicodeStore>, entry_id: &EntryId) -> io::Result<Arc<Self>> {
         let inner = FolderInner::read(store, entry_id)?;
-        Ok(Rc::new(Self { inner }))
+        Ok(Arc::new(Self { inner }))
+    }
+
+    fn pst_store(&self) -> &Arc<UnicodeStore> {
+        &self.inner.store
     }
 }
 
@@ -336,13 +648,13 @@ pub struct AnsiFolder {
 }
 
 impl AnsiFolder {
-    pub fn read(store: Rc<AnsiStore>, entry_id: &EntryId) -> io::Result<Rc<Self>> {
+    pub fn read(store: Arc<AnsiStore>, entry_id: &EntryId) -> io::Result<Arc<Self>> {
         <Self as FolderReadWrite<AnsiPstFile>>::read(store, entry_id)
     }
 }
 
 impl Folder for AnsiFolder {
-    fn store(&self) -> Rc<dyn Store> {
+    fn store(&self) -> Arc<dyn Store> {
         self.inner.store.clone()
     }
 
@@ -350,22 +662,210 @@ impl Folder for AnsiFolder {
         &self.inner.properties
     }
 
-    fn hierarchy_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn hierarchy_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.inner.hierarchy_table()
     }
 
-    fn contents_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn contents_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.inner.contents_table()
     }
 
-    fn associated_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn associated_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.inner.associated_table()
     }
 }
 
 impl FolderReadWrite<AnsiPstFile> for AnsiFolder {
-    fn read(store: Rc<AnsiStore>, entry_id: &EntryId) -> io::Result<Rc<Self>> {
+    fn read(store: Arc<AnsiStore>, entry_id: &EntryId) -> io::Result<Arc<Self>> {
         let inner = FolderInner::read(store, entry_id)?;
-        Ok(Rc::new(Self { inner }))
+        Ok(Arc::new(Self { inner }))
+    }
+
+    fn pst_store(&self) -> &Arc<AnsiStore> {
+        &self.inner.store
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{TempFile, EMPTY_PST};
+
+    /// Wrap a folder without any of its properties, to test the contents table fallbacks.
+    struct WithoutProperties {
+        folder: Arc<dyn Folder>,
+        properties: FolderProperties,
+    }
+
+    impl Folder for WithoutProperties {
+        fn store(&self) -> Arc<dyn Store> {
+            self.folder.store()
+        }
+
+        fn properties(&self) -> &FolderProperties {
+            &self.properties
+        }
+
+        fn hierarchy_table(&self) -> Option<&Arc<dyn TableContext>> {
+            self.folder.hierarchy_table()
+        }
+
+        fn contents_table(&self) -> Option<&Arc<dyn TableContext>> {
+            self.folder.contents_table()
+        }
+
+        fn associated_table(&self) -> Option<&Arc<dyn TableContext>> {
+            self.folder.associated_table()
+        }
+    }
+
+    #[test]
+    fn test_folder_counts() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let entry_id = store.properties().ipm_sub_tree_entry_id().unwrap();
+        let folder = store.open_folder(&entry_id).unwrap();
+
+        let content_count = folder.properties().content_count().unwrap();
+        let unread_count = folder.properties().unread_count().unwrap();
+        assert_eq!(folder.message_count().unwrap() as i32, content_count);
+        assert_eq!(folder.unread_count().unwrap() as i32, unread_count);
+        assert_eq!(folder.total_size_bytes().unwrap(), 0);
+
+        let rows = folder.contents_table().unwrap().rows_iter().count();
+        let folder = WithoutProperties {
+            folder,
+            properties: Default::default(),
+        };
+        assert_eq!(folder.message_count().unwrap() as usize, rows);
+        assert!(folder.unread_count().unwrap() as usize <= rows);
+    }
+
+    #[test]
+    fn test_folder_iter() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        assert_eq!(store.folders_dfs().count(), 6);
+
+        let depth_first = store
+            .folders_dfs()
+            .map(|folder| folder.unwrap().properties().node_id())
+            .collect::<Vec<_>>();
+        let root = depth_first[0];
+        assert_eq!(root, NID_ROOT_FOLDER);
+
+        let root = store
+            .open_folder(&store.properties().make_entry_id(root).unwrap())
+            .unwrap();
+        let mut breadth_first = FolderIter::breadth_first(root)
+            .map(|folder| folder.unwrap().properties().node_id())
+            .collect::<Vec<_>>();
+        assert_eq!(breadth_first[0], NID_ROOT_FOLDER);
+        assert_ne!(breadth_first, depth_first);
+
+        let mut depth_first = depth_first;
+        depth_first.sort();
+        breadth_first.sort();
+        assert_eq!(breadth_first, depth_first);
+
+        let mut iter = store.folders_dfs();
+        iter.by_ref().for_each(drop);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_messages() {
+        use crate::messaging::edit::MessageBuilder;
+
+        let path = TempFile::copy_of(EMPTY_PST, "messages").unwrap();
+        let mut folder = {
+            let pst = Arc::new(UnicodePstFile::open(&path).unwrap());
+            let store = UnicodeStore::read(pst).unwrap();
+            let entry_id = store.properties().ipm_sub_tree_entry_id().unwrap();
+            let folder = UnicodeFolder::read(store, &entry_id).unwrap();
+            assert_eq!(folder.messages().count(), 0);
+            Arc::into_inner(folder).unwrap()
+        };
+
+        // More than one batch of rows, all added through the same folder.
+        let count = MessageIter::BATCH_SIZE + 2;
+        let mut messages = (0..count)
+            .map(|index| {
+                let subject = format!("Message {index}");
+                let node_id = folder
+                    .add_message(&MessageBuilder::new().subject(&subject))
+                    .unwrap();
+                (node_id, subject)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(folder.messages().count(), count);
+        let folder = folder.properties().node_id();
+        messages.sort_by_key(|(node_id, _)| u32::from(*node_id));
+
+        let pst = Arc::new(UnicodePstFile::open(&path).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let folder = store
+            .open_folder(&store.properties().make_entry_id(folder).unwrap())
+            .unwrap();
+        let mut iter = folder.messages();
+        let mut found = iter
+            .by_ref()
+            .map(|message| {
+                let message = message.unwrap();
+                let subject = message.properties().get_typed::<String>(PR_SUBJECT);
+                (message.node_id(), subject.unwrap().unwrap(), message)
+            })
+            .collect::<Vec<_>>();
+        assert!(iter.next().is_none());
+        found.sort_by_key(|(node_id, ..)| u32::from(*node_id));
+
+        assert_eq!(found.len(), count);
+        for ((node_id, subject), (found_id, found_subject, message)) in messages.iter().zip(&found)
+        {
+            assert_eq!(node_id, found_id);
+            assert_eq!(subject, found_subject);
+            let loaded = message.load(Some(&[PR_SUBJECT])).unwrap();
+            assert_eq!(
+                loaded.properties().get_typed::<String>(PR_SUBJECT).unwrap(),
+                Some(subject.clone())
+            );
+        }
+
+        drop(found);
+        drop(folder);
+        drop(store);
+    }
+
+    #[test]
+    fn test_has_sub_folders() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let entry_id = store.properties().ipm_sub_tree_entry_id().unwrap();
+        let folder = store.open_folder(&entry_id).unwrap();
+        assert!(folder.has_sub_folders());
+        assert!(folder.properties().has_sub_folders().unwrap());
+
+        // Every folder which says it is a leaf really has no sub-folders.
+        let folders = crate::PstFileReader::open(EMPTY_PST)
+            .unwrap()
+            .folders()
+            .unwrap();
+        let leaves: Vec<_> = folders
+            .iter()
+            .filter(|folder| !folder.has_sub_folders())
+            .collect();
+        assert!(!leaves.is_empty());
+        for leaf in leaves {
+            let rows = leaf.hierarchy_table().map_or(0, |table| table.row_count());
+            assert_eq!(rows, 0);
+        }
+
+        // Without PidTagSubfolders, the folder may have sub-folders.
+        let folder = WithoutProperties {
+            folder,
+            properties: Default::default(),
+        };
+        assert!(folder.has_sub_folders());
+        assert!(folder.hierarchy_table().is_some());
     }
 }
@@ -0,0 +1,965 @@
+//! ## Contact, Appointment and Task Items
+//!
+//! Typed wrappers for the item kinds which store most of their properties as named properties.
+//! Contacts are described in [MS-OXOCNTC](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxocntc),
+//! and tasks in [MS-OXOTASK](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxotask).
+
+use std::{
+    io::{self, Write},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use super::{
+    calendar::ICS_PRODUCT_ID, content_line::*, message::*, named_prop::*, property_ids::*,
+    store::*, time::system_time_to_filetime, *,
+};
+use crate::{
+    ltp::{
+        prop_context::{FromProperty, GuidValue, PropertyValue, TypedProperties},
+        LtpResult,
+    },
+    ndb::node_id::NodeId,
+};
+
+pub use super::calendar::{Appointment, AppointmentPropertyIds, IcsExport};
+
+/// `PidTagMessageClass` of an e-mail message
+pub const IPM_NOTE: &str = "IPM.Note";
+/// `PidTagMessageClass` of a sticky note
+pub const IPM_STICKY_NOTE: &str = "IPM.StickyNote";
+/// `PidTagMessageClass` of a contact item
+pub const IPM_CONTACT: &str = "IPM.Contact";
+/// `PidTagMessageClass` of a calendar item
+pub const IPM_APPOINTMENT: &str = "IPM.Appointment";
+/// `PidTagMessageClass` of a task item
+pub const IPM_TASK: &str = "IPM.Task";
+
+/// `PidTagContainerClass` of a folder which holds e-mail messages
+pub const IPF_NOTE: &str = "IPF.Note";
+
+/// Check if `message_class` is `expected` or one of its derived classes, e.g.
+/// `IPM.Contact.Custom` is an [`IPM_CONTACT`]. Message classes are case-insensitive.
+pub fn is_message_class(message_class: &str, expected: &str) -> bool {
+    let Some(prefix) = message_class.get(..expected.len()) else {
+        return false;
+    };
+    prefix.eq_ignore_ascii_case(expected)
+        && matches!(
+            message_class.as_bytes().get(expected.len()),
+            None | Some(b'.')
+        )
+}
+
+/// The kind of item a message is, from the common `IPM.*` prefixes of its `PidTagMessageClass`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageType {
+    /// [`IPM_NOTE`]
+    Email,
+    /// [`IPM_APPOINTMENT`]
+    Appointment,
+    /// [`IPM_CONTACT`]
+    Contact,
+    /// [`IPM_TASK`]
+    Task,
+    /// [`IPM_STICKY_NOTE`]
+    Note,
+    /// Any other message class, or a message without one.
+    Other,
+}
+
+impl MessageType {
+    pub fn from_message_class(message_class: &str) -> Self {
+        [
+            (IPM_NOTE, Self::Email),
+            (IPM_APPOINTMENT, Self::Appointment),
+            (IPM_CONTACT, Self::Contact),
+            (IPM_TASK, Self::Task),
+            (IPM_STICKY_NOTE, Self::Note),
+        ]
+        .into_iter()
+        .find(|(expected, _)| is_message_class(message_class, expected))
+        .map_or(Self::Other, |(_, message_type)| message_type)
+    }
+}
+
+/// Fail with [`MessagingError::UnexpectedMessageClass`] unless the message is an `expected`.
+pub(crate) fn check_message_class(
+    properties: &MessageProperties,
+    expected: &'static str,
+) -> io::Result<()> {
+    let actual = properties.message_class().unwrap_or_default();
+    if is_message_class(&actual, expected) {
+        Ok(())
+    } else {
+        Err(MessagingError::UnexpectedMessageClass { expected, actual }.into())
+    }
+}
+
+/// `PSETID_Address`
+pub const PSETID_ADDRESS: GuidValue = GuidValue::new(
+    0x00062004,
+    0x0000,
+    0x0000,
+    [0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+);
+
+/// `PidLidEmail1EmailAddress`
+pub const PID_LID_EMAIL1_EMAIL_ADDRESS: u32 = 0x8083;
+/// `PidLidEmail2EmailAddress`
+pub const PID_LID_EMAIL2_EMAIL_ADDRESS: u32 = 0x8093;
+/// `PidLidEmail3EmailAddress`
+pub const PID_LID_EMAIL3_EMAIL_ADDRESS: u32 = 0x80A3;
+
+/// The `PSETID_Address` named properties, resolved to the property IDs used in one store.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ContactPropertyIds {
+    email_addresses: [Option<u16>; 3],
+}
+
+impl ContactPropertyIds {
+    pub fn resolve(named_props: &dyn NamedPropertyMap) -> io::Result<Self> {
+        let find = |lid| named_props.find_numeric_id(&PSETID_ADDRESS, lid);
+        Ok(Self {
+            email_addresses: [
+                find(PID_LID_EMAIL1_EMAIL_ADDRESS)?,
+                find(PID_LID_EMAIL2_EMAIL_ADDRESS)?,
+                find(PID_LID_EMAIL3_EMAIL_ADDRESS)?,
+            ],
+        })
+    }
+
+    /// `PidLidEmail1EmailAddress`, `PidLidEmail2EmailAddress` and `PidLidEmail3EmailAddress`
+    pub fn email_addresses(&self) -> [Option<u16>; 3] {
+        self.email_addresses
+    }
+}
+
+/// A contact item, with the `PSETID_Address` named properties looked up through the store's
+/// [`NamedPropertyMap`].
+pub struct Contact {
+    message: Arc<dyn Message>,
+    display_name: Option<String>,
+    given_name: Option<String>,
+    surname: Option<String>,
+    company_name: Option<String>,
+    job_title: Option<String>,
+    business_phone: Option<String>,
+    home_phone: Option<String>,
+    mobile_phone: Option<String>,
+    email_addresses: Vec<String>,
+    post_office_box: Option<String>,
+    street_address: Option<String>,
+    locality: Option<String>,
+    state_or_province: Option<String>,
+    postal_code: Option<String>,
+    country: Option<String>,
+}
+
+impl Contact {
+    pub fn open(store: &dyn Store, node_id: NodeId) -> io::Result<Self> {
+        let prop_ids = ContactPropertyIds::resolve(&*store.named_property_map()?)?;
+        let entry_id = store.properties().make_entry_id(node_id)?;
+        let message = store.open_message(&entry_id, None)?;
+        Self::read(message, &prop_ids)
+    }
+
+    /// Read the contact properties from a message which is already open, using `prop_ids`
+    /// resolved from the same store. Fails with [`MessagingError::UnexpectedMessageClass`] if
+    /// the message is not an [`IPM_CONTACT`].
+    pub fn read(message: Arc<dyn Message>, prop_ids: &ContactPropertyIds) -> io::Result<Self> {
+        let properties = message.properties();
+        check_message_class(properties, IPM_CONTACT)?;
+
+        let email_addresses = prop_ids
+            .email_addresses
+            .into_iter()
+            .flatten()
+            .filter_map(|prop_id| properties.get_string8(prop_id).transpose())
+            .filter(|email| !matches!(email, Ok(email) if email.is_empty()))
+            .collect::<LtpResult<_>>()?;
+
+        Ok(Self {
+            display_name: properties.get_string8(PR_DISPLAY_NAME)?,
+            given_name: properties.get_string8(PR_GIVEN_NAME)?,
+            surname: properties.get_string8(PR_SURNAME)?,
+            company_name: properties.get_string8(PR_COMPANY_NAME)?,
+            job_title: properties.get_string8(PR_TITLE)?,
+            business_phone: properties.get_string8(PR_BUSINESS_TELEPHONE_NUMBER)?,
+            home_phone: properties.get_string8(PR_HOME_TELEPHONE_NUMBER)?,
+            mobile_phone: properties.get_string8(PR_MOBILE_TELEPHONE_NUMBER)?,
+            email_addresses,
+            post_office_box: properties.get_string8(PR_POST_OFFICE_BOX)?,
+            street_address: properties.get_string8(PR_STREET_ADDRESS)?,
+            locality: properties.get_string8(PR_LOCALITY)?,
+            state_or_province: properties.get_string8(PR_STATE_OR_PROVINCE)?,
+            postal_code: properties.get_string8(PR_POSTAL_CODE)?,
+            country: properties.get_string8(PR_COUNTRY)?,
+            message,
+        })
+    }
+
+    pub fn message(&self) -> &Arc<dyn Message> {
+        &self.message
+    }
+
+    /// `PidTagDisplayName`
+    pub fn display_name(&self) -> Option<&str> {
+        self.display_name.as_deref()
+    }
+
+    /// `PidTagGivenName`
+    pub fn given_name(&self) -> Option<&str> {
+        self.given_name.as_deref()
+    }
+
+    /// `PidTagSurname`
+    pub fn surname(&self) -> Option<&str> {
+        self.surname.as_deref()
+    }
+
+    /// `PidTagCompanyName`
+    pub fn company_name(&self) -> Option<&str> {
+        self.company_name.as_deref()
+    }
+
+    /// `PidTagTitle`
+    pub fn job_title(&self) -> Option<&str> {
+        self.job_title.as_deref()
+    }
+
+    /// `PidTagBusinessTelephoneNumber`
+    pub fn business_phone(&self) -> Option<&str> {
+        self.business_phone.as_deref()
+    }
+
+    /// `PidTagHomeTelephoneNumber`
+    pub fn home_phone(&self) -> Option<&str> {
+        self.home_phone.as_deref()
+    }
+
+    /// `PidTagMobileTelephoneNumber`
+    pub fn mobile_phone(&self) -> Option<&str> {
+        self.mobile_phone.as_deref()
+    }
+
+    /// The non-empty `PidLidEmail1EmailAddress`, `PidLidEmail2EmailAddress` and
+    /// `PidLidEmail3EmailAddress` values, in that order.
+    pub fn email_addresses(&self) -> &[String] {
+        &self.email_addresses
+    }
+
+    /// `PidTagPostOfficeBox`
+    pub fn post_office_box(&self) -> Option<&str> {
+        self.post_office_box.as_deref()
+    }
+
+    /// `PidTagStreetAddress`
+    pub fn street_address(&self) -> Option<&str> {
+        self.street_address.as_deref()
+    }
+
+    /// `PidTagLocality`
+    pub fn locality(&self) -> Option<&str> {
+        self.locality.as_deref()
+    }
+
+    /// `PidTagStateOrProvince`
+    pub fn state_or_province(&self) -> Option<&str> {
+        self.state_or_province.as_deref()
+    }
+
+    /// `PidTagPostalCode`
+    pub fn postal_code(&self) -> Option<&str> {
+        self.postal_code.as_deref()
+    }
+
+    /// `PidTagCountry`
+    pub fn country(&self) -> Option<&str> {
+        self.country.as_deref()
+    }
+
+    /// Write the contact as a [vCard 3.0](https://www.rfc-editor.org/rfc/rfc2426) object. The
+    /// address properties are the business address, so they are exported as `ADR;TYPE=WORK`.
+    pub fn to_vcard(&self, writer: &mut impl Write) -> io::Result<()> {
+        let field = |value: &Option<String>| escape_text(value.as_deref().unwrap_or_default());
+
+        write_line(writer, "BEGIN", "VCARD")?;
+        write_line(writer, "VERSION", "3.0")?;
+
+        let name = format!("{};{};;;", field(&self.surname), field(&self.given_name));
+        write_line(writer, "N", &name)?;
+
+        // FN is required, so fall back to the name parts if there is no display name.
+        let formatted_name = match self.display_name.as_deref() {
+            Some(display_name) if !display_name.is_empty() => display_name.to_string(),
+            _ => [self.given_name.as_deref(), self.surname.as_deref()]
+                .into_iter()
+                .flatten()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        write_text(writer, "FN", &formatted_name)?;
+
+        if let Some(company_name) = self.company_name.as_deref() {
+            write_text(writer, "ORG", company_name)?;
+        }
+        if let Some(job_title) = self.job_title.as_deref() {
+            write_text(writer, "TITLE", job_title)?;
+        }
+
+        for (name, phone) in [
+            ("TEL;TYPE=WORK,VOICE", &self.business_phone),
+            ("TEL;TYPE=HOME,VOICE", &self.home_phone),
+            ("TEL;TYPE=CELL", &self.mobile_phone),
+        ] {
+            if let Some(phone) = phone.as_deref() {
+                write_text(writer, name, phone)?;
+            }
+        }
+
+        for email in self.email_addresses.iter() {
+            write_text(writer, "EMAIL;TYPE=INTERNET", email)?;
+        }
+
+        let address = [
+            &self.post_office_box,
+            &self.street_address,
+            &self.locality,
+            &self.state_or_province,
+            &self.postal_code,
+            &self.country,
+        ];
+        if address.iter().any(|part| part.is_some()) {
+            let [post_office_box, street, locality, region, postal_code, country] =
+                address.map(field);
+            write_line(
+                writer,
+                "ADR;TYPE=WORK",
+                &format!("{post_office_box};;{street};{locality};{region};{postal_code};{country}"),
+            )?;
+        }
+
+        write_line(writer, "END", "VCARD")
+    }
+}
+
+/// `PSETID_Task`
+pub const PSETID_TASK: GuidValue = GuidValue::new(
+    0x00062003,
+    0x0000,
+    0x0000,
+    [0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+);
+
+/// `PidLidTaskStatus`
+pub const PID_LID_TASK_STATUS: u32 = 0x8101;
+/// `PidLidPercentComplete`
+pub const PID_LID_PERCENT_COMPLETE: u32 = 0x8102;
+/// `PidLidTaskStartDate`
+pub const PID_LID_TASK_START_DATE: u32 = 0x8104;
+/// `PidLidTaskDueDate`
+pub const PID_LID_TASK_DUE_DATE: u32 = 0x8105;
+/// `PidLidTaskComplete`
+pub const PID_LID_TASK_COMPLETE: u32 = 0x811C;
+/// `PidLidTaskOwner`
+pub const PID_LID_TASK_OWNER: u32 = 0x811F;
+/// `PidLidTaskFRecurring`
+pub const PID_LID_TASK_F_RECURRING: u32 = 0x8126;
+
+/// The `PSETID_Task` named properties, resolved to the property IDs used in one store.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TaskPropertyIds {
+    status: Option<u16>,
+    percent_complete: Option<u16>,
+    start_date: Option<u16>,
+    due_date: Option<u16>,
+    complete: Option<u16>,
+    owner: Option<u16>,
+    recurring: Option<u16>,
+}
+
+impl TaskPropertyIds {
+    pub fn resolve(named_props: &dyn NamedPropertyMap) -> io::Result<Self> {
+        let find = |lid| named_props.find_numeric_id(&PSETID_TASK, lid);
+        Ok(Self {
+            status: find(PID_LID_TASK_STATUS)?,
+            percent_complete: find(PID_LID_PERCENT_COMPLETE)?,
+            start_date: find(PID_LID_TASK_START_DATE)?,
+            due_date: find(PID_LID_TASK_DUE_DATE)?,
+            complete: find(PID_LID_TASK_COMPLETE)?,
+            owner: find(PID_LID_TASK_OWNER)?,
+            recurring: find(PID_LID_TASK_F_RECURRING)?,
+        })
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    pub fn percent_complete(&self) -> Option<u16> {
+        self.percent_complete
+    }
+
+    pub fn start_date(&self) -> Option<u16> {
+        self.start_date
+    }
+
+    pub fn due_date(&self) -> Option<u16> {
+        self.due_date
+    }
+
+    pub fn complete(&self) -> Option<u16> {
+        self.complete
+    }
+
+    pub fn owner(&self) -> Option<u16> {
+        self.owner
+    }
+
+    pub fn recurring(&self) -> Option<u16> {
+        self.recurring
+    }
+}
+
+/// `PidLidTaskStatus`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskStatus {
+    NotStarted,
+    InProgress,
+    Complete,
+    Waiting,
+    Deferred,
+    Unknown(i32),
+}
+
+impl From<i32> for TaskStatus {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Self::NotStarted,
+            1 => Self::InProgress,
+            2 => Self::Complete,
+            3 => Self::Waiting,
+            4 => Self::Deferred,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+/// A task item, with the `PSETID_Task` named properties looked up through the store's
+/// [`NamedPropertyMap`].
+pub struct Task {
+    message: Arc<dyn Message>,
+    subject: Option<String>,
+    status: Option<TaskStatus>,
+    percent_complete: Option<f64>,
+    start_date: Option<SystemTime>,
+    due_date: Option<SystemTime>,
+    complete: bool,
+    owner: Option<String>,
+    recurring: bool,
+}
+
+impl Task {
+    pub fn open(store: &dyn Store, node_id: NodeId) -> io::Result<Self> {
+        let prop_ids = TaskPropertyIds::resolve(&*store.named_property_map()?)?;
+        let entry_id = store.properties().make_entry_id(node_id)?;
+        let message = store.open_message(&entry_id, None)?;
+        Self::read(message, &prop_ids)
+    }
+
+    /// Read the task properties from a message which is already open, using `prop_ids`
+    /// resolved from the same store. Fails with [`MessagingError::UnexpectedMessageClass`] if
+    /// the message is not an [`IPM_TASK`].
+    pub fn read(message: Arc<dyn Message>, prop_ids: &TaskPropertyIds) -> io::Result<Self> {
+        let properties = message.properties();
+        check_message_class(properties, IPM_TASK)?;
+
+        let subject = properties.get_string8(PR_SUBJECT)?;
+        let status = get_named::<i32>(properties, prop_ids.status)?.map(TaskStatus::from);
+        let percent_complete = get_named(properties, prop_ids.percent_complete)?;
+        let start_date = get_named(properties, prop_ids.start_date)?;
+        let due_date = get_named(properties, prop_ids.due_date)?;
+        let complete = get_named(properties, prop_ids.complete)?.unwrap_or_default();
+        let owner = prop_ids
+            .owner
+            .map(|prop_id| properties.get_string8(prop_id))
+            .transpose()?
+            .flatten();
+        let recurring = get_named(properties, prop_ids.recurring)?.unwrap_or_default();
+
+        Ok(Self {
+            message,
+            subject,
+            status,
+            percent_complete,
+            start_date,
+            due_date,
+            complete,
+            owner,
+            recurring,
+        })
+    }
+
+    pub fn message(&self) -> &Arc<dyn Message> {
+        &self.message
+    }
+
+    /// `PidTagSubject`
+    pub fn subject(&self) -> Option<&str> {
+        self.subject.as_deref()
+    }
+
+    /// `PidLidTaskStatus`
+    pub fn status(&self) -> Option<TaskStatus> {
+        self.status
+    }
+
+    /// `PidLidPercentComplete`, between `0.0` and `1.0`.
+    pub fn percent_complete(&self) -> Option<f64> {
+        self.percent_complete
+    }
+
+    /// `PidLidTaskStartDate`
+    pub fn start_date(&self) -> Option<SystemTime> {
+        self.start_date
+    }
+
+    /// `PidLidTaskDueDate`
+    pub fn due_date(&self) -> Option<SystemTime> {
+        self.due_date
+    }
+
+    /// `PidLidTaskComplete`
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// `PidLidTaskOwner`
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    /// `PidLidTaskFRecurring`
+    pub fn is_recurring(&self) -> bool {
+        self.recurring
+    }
+
+    /// Write the task as an [iCalendar](https://www.rfc-editor.org/rfc/rfc5545) object with a
+    /// single `VTODO`. The start and due dates are the local date of the task, so they are
+    /// written as `DATE` values without a time. The recurrence pattern of a recurring task is
+    /// not exported.
+    pub fn to_ics(&self, writer: &mut impl Write) -> io::Result<()> {
+        let properties = self.message.properties();
+
+        write_line(writer, "BEGIN", "VCALENDAR")?;
+        write_line(writer, "VERSION", "2.0")?;
+        write_text(writer, "PRODID", ICS_PRODUCT_ID)?;
+        write_line(writer, "BEGIN", "VTODO")?;
+
+        if let Some(search_key) = properties.get_typed::<Vec<u8>>(PR_SEARCH_KEY)? {
+            let uid: String = search_key
+                .iter()
+                .map(|byte| format!("{byte:02X}"))
+                .collect();
+            write_line(writer, "UID", &uid)?;
+        }
+
+        let timestamp = [PR_LAST_MODIFICATION_TIME, PR_CREATION_TIME]
+            .into_iter()
+            .find_map(|prop_id| match properties.get(prop_id) {
+                Some(PropertyValue::Time(value)) => Some(*value),
+                _ => None,
+            });
+        if let Some(timestamp) = timestamp {
+            write_line(writer, "DTSTAMP", &format_date_time(timestamp))?;
+        }
+
+        for (name, value) in [("DTSTART", self.start_date), ("DUE", self.due_date)] {
+            if let Some(value) = value {
+                let filetime = system_time_to_filetime(value) as i64;
+                write_line(
+                    writer,
+                    &format!("{name};VALUE=DATE"),
+                    &format_date(filetime),
+                )?;
+            }
+        }
+
+        if let Some(subject) = self.subject.as_deref() {
+            write_text(writer, "SUMMARY", subject)?;
+        }
+        if let Some(body) = properties.get_string8(PR_BODY)? {
+            write_text(writer, "DESCRIPTION", &body)?;
+        }
+
+        let status = match self.status {
+            _ if self.complete => Some("COMPLETED"),
+            Some(TaskStatus::InProgress) => Some("IN-PROCESS"),
+            Some(TaskStatus::Complete) => Some("COMPLETED"),
+            Some(TaskStatus::NotStarted | TaskStatus::Waiting | TaskStatus::Deferred) => {
+                Some("NEEDS-ACTION")
+            }
+            Some(TaskStatus::Unknown(_)) | None => None,
+        };
+        if let Some(status) = status {
+            write_line(writer, "STATUS", status)?;
+        }
+        if let Some(percent_complete) = self.percent_complete {
+            let percent_complete = (percent_complete * 100.0).round().clamp(0.0, 100.0);
+            write_line(writer, "PERCENT-COMPLETE", &format!("{percent_complete}"))?;
+        }
+
+        write_line(writer, "END", "VTODO")?;
+        write_line(writer, "END", "VCALENDAR")
+    }
+}
+
+/// Get a named property converted to `T`, or `None` if the name is not in the store's
+/// [`NamedPropertyMap`] or the property is not set on this message.
+pub(crate) fn get_named<T: FromProperty>(
+    properties: &MessageProperties,
+    prop_id: Option<u16>,
+) -> LtpResult<Option<T>> {
+    match prop_id {
+        Some(prop_id) => properties.get_typed(prop_id),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        ltp::{
+            prop_context::PropertyValue, prop_type::PropertyType,
+            read_write::PropertyValueReadWrite, table_context::TableContext,
+        },
+        messaging::{attachment::Attachment, time::system_time_to_filetime},
+        ndb::node_id::NodeIdType,
+        test_util::EMPTY_PST,
+        UnicodePstFile,
+    };
+    use std::{
+        collections::BTreeMap,
+        io::Cursor,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    struct TestMessage {
+        store: Arc<dyn Store>,
+        properties: MessageProperties,
+    }
+
+    impl Message for TestMessage {
+        fn store(&self) -> Arc<dyn Store> {
+            self.store.clone()
+        }
+
+        fn properties(&self) -> &MessageProperties {
+            &self.properties
+        }
+
+        fn recipient_table(&self) -> Option<&Arc<dyn TableContext>> {
+            None
+        }
+
+        fn attachment_table(&self) -> Option<&Arc<dyn TableContext>> {
+            None
+        }
+
+        fn embedded_depth(&self) -> usize {
+            0
+        }
+
+        fn attachments_with_limit(
+            &self,
+            _prop_ids: Option<&[u16]>,
+            _max_depth: usize,
+        ) -> io::Result<Vec<Arc<dyn Attachment>>> {
+            Ok(vec![])
+        }
+    }
+
+    pub(crate) fn unicode(value: &str) -> PropertyValue {
+        let buffer: Vec<_> = value.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        PropertyValue::read(&mut Cursor::new(buffer), PropertyType::Unicode).unwrap()
+    }
+
+    pub(crate) fn time(seconds: u64) -> PropertyValue {
+        let filetime = system_time_to_filetime(UNIX_EPOCH + Duration::from_secs(seconds));
+        PropertyValue::Time(filetime as i64)
+    }
+
+    pub(crate) fn message(
+        message_class: &str,
+        values: impl IntoIterator<Item = (u16, PropertyValue)>,
+    ) -> Arc<dyn Message> {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let mut properties: BTreeMap<_, _> = values.into_iter().collect();
+        properties.insert(PR_MESSAGE_CLASS, unicode(message_class));
+        Arc::new(TestMessage {
+            store,
+            properties: MessageProperties::from(properties),
+        })
+    }
+
+    fn assert_unexpected_class(err: io::Error, expected_class: &str) {
+        let err = err
+            .into_inner()
+            .unwrap()
+            .downcast::<MessagingError>()
+            .unwrap();
+        assert!(matches!(
+            *err,
+            MessagingError::UnexpectedMessageClass { expected, .. } if expected == expected_class
+        ));
+    }
+
+    #[test]
+    fn test_is_message_class() {
+        assert!(is_message_class("IPM.Contact", IPM_CONTACT));
+        assert!(is_message_class("ipm.contact", IPM_CONTACT));
+        assert!(is_message_class("IPM.Contact.Custom", IPM_CONTACT));
+        assert!(!is_message_class("IPM.ContactGroup", IPM_CONTACT));
+        assert!(!is_message_class("IPM", IPM_CONTACT));
+        assert!(!is_message_class("IPM.Note", IPM_TASK));
+    }
+
+    #[test]
+    fn test_message_type() {
+        for (message_class, expected) in [
+            ("IPM.Note", MessageType::Email),
+            ("IPM.Note.SMIME.MultipartSigned", MessageType::Email),
+            ("IPM.Appointment", MessageType::Appointment),
+            ("ipm.contact", MessageType::Contact),
+            ("IPM.Task", MessageType::Task),
+            ("IPM.StickyNote", MessageType::Note),
+            ("IPM.DistList", MessageType::Other),
+            ("IPM", MessageType::Other),
+            ("", MessageType::Other),
+        ] {
+            let message = message(message_class, []);
+            assert_eq!(
+                message.properties().message_type(),
+                expected,
+                "{message_class}"
+            );
+        }
+
+        let missing = MessageProperties::default();
+        assert_eq!(missing.message_class(), None);
+        assert_eq!(missing.message_type(), MessageType::Other);
+    }
+
+    #[test]
+    fn test_resolve_property_ids() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let named_props = store.named_property_map().unwrap();
+
+        let contact_ids = ContactPropertyIds::resolve(&*named_props).unwrap();
+        let task_ids = TaskPropertyIds::resolve(&*named_props).unwrap();
+        for prop_id in contact_ids
+            .email_addresses()
+            .into_iter()
+            .chain([
+                task_ids.status(),
+                task_ids.percent_complete(),
+                task_ids.start_date(),
+                task_ids.due_date(),
+                task_ids.complete(),
+                task_ids.owner(),
+                task_ids.recurring(),
+            ])
+            .flatten()
+        {
+            assert!(prop_id >= 0x8000);
+        }
+
+        let missing = NodeId::new(NodeIdType::NormalMessage, 0x7FFF).unwrap();
+        assert!(Contact::open(store.as_ref(), missing).is_err());
+        assert!(Task::open(store.as_ref(), missing).is_err());
+    }
+
+    #[test]
+    fn test_read_contact() {
+        let prop_ids = ContactPropertyIds {
+            email_addresses: [Some(0x8001), None, Some(0x8003)],
+        };
+        let contact = message(
+            "IPM.Contact",
+            [
+                (PR_DISPLAY_NAME, unicode("Alice Example")),
+                (PR_GIVEN_NAME, unicode("Alice")),
+                (PR_SURNAME, unicode("Example")),
+                (PR_MOBILE_TELEPHONE_NUMBER, unicode("+1 555 0100")),
+                (0x8001, unicode("alice@example.com")),
+                (0x8002, unicode("ignored@example.com")),
+                (0x8003, unicode("alice@example.org")),
+            ],
+        );
+        let contact = Contact::read(contact, &prop_ids).unwrap();
+        assert_eq!(contact.display_name(), Some("Alice Example"));
+        assert_eq!(contact.given_name(), Some("Alice"));
+        assert_eq!(contact.surname(), Some("Example"));
+        assert_eq!(contact.company_name(), None);
+        assert_eq!(contact.mobile_phone(), Some("+1 555 0100"));
+        assert_eq!(
+            contact.email_addresses(),
+            ["alice@example.com", "alice@example.org"]
+        );
+
+        let note = message("IPM.Note", []);
+        let err = Contact::read(note, &prop_ids).err().unwrap();
+        assert_unexpected_class(err, IPM_CONTACT);
+    }
+
+    #[test]
+    fn test_contact_to_vcard() {
+        let prop_ids = ContactPropertyIds {
+            email_addresses: [Some(0x8001), None, None],
+        };
+        let contact = message(
+            "IPM.Contact",
+            [
+                (PR_GIVEN_NAME, unicode("John")),
+                (PR_SURNAME, unicode("Smith, Jr.")),
+                (PR_COMPANY_NAME, unicode("Example; Inc.")),
+                (PR_BUSINESS_TELEPHONE_NUMBER, unicode("+1 555 0100")),
+                (PR_MOBILE_TELEPHONE_NUMBER, unicode("+1 555 0101")),
+                (PR_STREET_ADDRESS, unicode("1 Main St\r\nSuite 2")),
+                (PR_LOCALITY, unicode("Springfield")),
+                (PR_POSTAL_CODE, unicode("12345")),
+                (0x8001, unicode("john@example.com")),
+            ],
+        );
+        let contact = Contact::read(contact, &prop_ids).unwrap();
+
+        let mut vcard = Vec::new();
+        contact.to_vcard(&mut vcard).unwrap();
+        assert_eq!(
+            String::from_utf8(vcard).unwrap(),
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:3.0\r\n",
+                "N:Smith\\, Jr.;John;;;\r\n",
+                "FN:John Smith\\, Jr.\r\n",
+                "ORG:Example\\; Inc.\r\n",
+                "TEL;TYPE=WORK,VOICE:+1 555 0100\r\n",
+                "TEL;TYPE=CELL:+1 555 0101\r\n",
+                "EMAIL;TYPE=INTERNET:john@example.com\r\n",
+                "ADR;TYPE=WORK:;;1 Main St\\nSuite 2;Springfield;;12345;\r\n",
+                "END:VCARD\r\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_read_appointment() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let named_props = store.named_property_map().unwrap();
+        let prop_ids = AppointmentPropertyIds::resolve(&*named_props).unwrap();
+
+        let appointment = message("IPM.Appointment", [(PR_SUBJECT, unicode("Standup"))]);
+        let appointment = Appointment::read(appointment, &prop_ids).unwrap();
+        assert_eq!(appointment.title(), Some("Standup"));
+        assert!(!appointment.is_all_day());
+
+        let task = message("IPM.Task", []);
+        let err = Appointment::read(task, &prop_ids).err().unwrap();
+        assert_unexpected_class(err, IPM_APPOINTMENT);
+    }
+
+    #[test]
+    fn test_read_task() {
+        let prop_ids = TaskPropertyIds {
+            status: Some(0x8001),
+            percent_complete: Some(0x8002),
+            start_date: None,
+            due_date: Some(0x8004),
+            complete: Some(0x8005),
+            owner: Some(0x8006),
+            recurring: Some(0x8007),
+        };
+        let task = message(
+            "IPM.Task",
+            [
+                (PR_SUBJECT, unicode("File report")),
+                (0x8001, PropertyValue::Integer32(1)),
+                (0x8002, PropertyValue::Floating64(0.25)),
+                (0x8004, time(1_700_000_000)),
+                (0x8005, PropertyValue::Boolean(false)),
+                (0x8006, unicode("Alice")),
+                (0x8007, PropertyValue::Boolean(true)),
+            ],
+        );
+        let task = Task::read(task, &prop_ids).unwrap();
+        assert_eq!(task.owner(), Some("Alice"));
+        assert!(task.is_recurring());
+        assert_eq!(task.subject(), Some("File report"));
+        assert_eq!(task.status(), Some(TaskStatus::InProgress));
+        assert_eq!(task.percent_complete(), Some(0.25));
+        assert_eq!(task.start_date(), None);
+        assert_eq!(
+            task.due_date(),
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert!(!task.is_complete());
+
+        let invalid = message("IPM.Task", [(0x8002, unicode("25%"))]);
+        assert!(Task::read(invalid, &prop_ids).is_err());
+
+        let contact = message("IPM.Contact", []);
+        let err = Task::read(contact, &prop_ids).err().unwrap();
+        assert_unexpected_class(err, IPM_TASK);
+    }
+
+    #[test]
+    fn test_task_to_ics() {
+        let prop_ids = TaskPropertyIds {
+            status: Some(0x8001),
+            percent_complete: Some(0x8002),
+            start_date: Some(0x8003),
+            due_date: Some(0x8004),
+            ..Default::default()
+        };
+        let task = message(
+            "IPM.Task",
+            [
+                (PR_SUBJECT, unicode("File report; Q1")),
+                (PR_BODY, unicode("Send to finance")),
+                (PR_LAST_MODIFICATION_TIME, time(1_704_067_200)),
+                (0x8001, PropertyValue::Integer32(1)),
+                (0x8002, PropertyValue::Floating64(0.5)),
+                (0x8003, time(1_704_067_200)),
+                (0x8004, time(1_704_931_200)),
+            ],
+        );
+        let task = Task::read(task, &prop_ids).unwrap();
+
+        let mut ics = Vec::new();
+        task.to_ics(&mut ics).unwrap();
+        assert_eq!(
+            String::from_utf8(ics).unwrap(),
+            concat!(
+                "BEGIN:VCALENDAR\r\n",
+                "VERSION:2.0\r\n",
+                "PRODID:-//outlook-pst-rs//outlook-pst//EN\r\n",
+                "BEGIN:VTODO\r\n",
+                "DTSTAMP:20240101T000000Z\r\n",
+                "DTSTART;VALUE=DATE:20240101\r\n",
+                "DUE;VALUE=DATE:20240111\r\n",
+                "SUMMARY:File report\\; Q1\r\n",
+                "DESCRIPTION:Send to finance\r\n",
+                "STATUS:IN-PROCESS\r\n",
+                "PERCENT-COMPLETE:50\r\n",
+                "END:VTODO\r\n",
+                "END:VCALENDAR\r\n",
+            )
+        );
+    }
+}
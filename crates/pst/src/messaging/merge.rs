@@ -0,0 +1,763 @@
+//! ## Merge Stores
+//!
+//! Copy the folders and messages from one Unicode PST file into another.
+//!
+//! The destination is edited in place, in a single [`WriteTransaction`](crate::WriteTransaction).
+//! Folders which are created, or which receive messages, get new property and table contexts.
+//! Messages are copied with a new [`NodeId`], and with their named properties mapped to the
+//! property IDs in the destination. Every other node in the destination is left alone, except for
+//! the named property map when the copies have named properties.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    io,
+    path::Path,
+};
+
+use super::{edit::*, named_prop::*, property_ids::*};
+use crate::{
+    ltp::prop_type::PropertyType,
+    ndb::node_id::*,
+    upgrade::{NodeContents, NodeReader, NodeSource},
+    UnicodePstFile,
+};
+
+/// What [`merge_stores`] does when the folder it is about to import already exists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// Leave the existing folder alone and do not import anything.
+    Skip,
+    /// Import the folder with a `" (2)"`, `" (3)"`, etc. suffix on its display name.
+    #[default]
+    Rename,
+    /// Delete the existing folder, with all of its messages and sub-folders, and import the new
+    /// folder in its place.
+    Overwrite,
+}
+
+/// Where [`merge_stores`] puts the folders from the source store.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeTarget {
+    /// Merge each folder under the source IPM subtree into the folder with the same display
+    /// name at the same path in the destination, creating any folders which are missing.
+    #[default]
+    MatchingFolders,
+    /// Import the source IPM subtree as a new top-level folder, named after the display name of
+    /// the source store. [`MergeOptions::conflict`] decides what happens if that folder already
+    /// exists.
+    Subtree,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MergeOptions {
+    /// What to do if the top-level folder for [`MergeTarget::Subtree`] already exists. This is not
+    /// used with [`MergeTarget::MatchingFolders`], which merges into folders that already exist.
+    pub conflict: ConflictResolution,
+    pub target: MergeTarget,
+    /// Skip any message with a `PidTagInternetMessageId` which is already in the destination,
+    /// including messages imported earlier in the same merge.
+    pub deduplicate_by_message_id: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    pub folders_created: usize,
+    pub messages_imported: usize,
+    /// Messages which were left out because of [`MergeOptions::deduplicate_by_message_id`].
+    pub messages_skipped: usize,
+    /// Named properties which were left off of the imported folders and messages, because their
+    /// property ID is not in the named property map of the source.
+    pub named_properties_dropped: usize,
+}
+
+/// Copy every folder and message under the IPM subtree of the PST file at `source` into the PST
+/// file at `dest`. Both files must be Unicode PST files, an ANSI file can be converted with
+/// [`upgrade_to_unicode`](crate::upgrade_to_unicode) first.
+///
+/// Folder-associated (FAI) messages are not copied. Named properties on the copied folders,
+/// messages and attachments are mapped to the property IDs for the same names in `dest`, and any
+/// names which `dest` does not have yet are added to its named property map. The `source` file is
+/// not modified.
+pub fn merge_stores(
+    dest: impl AsRef<Path>,
+    source: impl AsRef<Path>,
+    options: &MergeOptions,
+) -> io::Result<MergeReport> {
+    let source = UnicodePstFile::open(source)?;
+    edit_store(&mut UnicodePstFile::open(dest)?, |dest| {
+        let mut merger = StoreMerger::new(dest, &source)?;
+        merger.merge(options)?;
+        merger.update_folders()?;
+        Ok(merger.report)
+    })
+}
+
+/// Changes to the message counts of a folder, which are applied to its PC and its row in the
+/// parent hierarchy table after all of the messages have been imported.
+#[derive(Default)]
+struct FolderCounts {
+    messages: i32,
+    unread: i32,
+}
+
+/// The state of a merge into a [`StoreEditor`] for the destination.
+struct StoreMerger<'a, 'b> {
+    dest: &'a mut StoreEditor<'b>,
+    source: NodeReader<'a, UnicodePstFile>,
+    counts: BTreeMap<u32, FolderCounts>,
+    message_ids: Option<HashSet<String>>,
+    /// The named property map in the source.
+    source_names: NamedPropertyMapProperties,
+    /// The property ID in the destination for each named property ID in the source, or `None` if
+    /// it could not be resolved.
+    named_prop_ids: BTreeMap<u16, Option<u16>>,
+    report: MergeReport,
+}
+
+impl<'a, 'b> StoreMerger<'a, 'b> {
+    fn new(dest: &'a mut StoreEditor<'b>, source: &'a UnicodePstFile) -> io::Result<Self> {
+        let mut source = NodeReader::new(source)?;
+        let source_names =
+            PropertyNode::read(&source.read_node(NID_NAME_TO_ID_MAP)?, dest.max_data_size())?;
+        let source_names = NamedPropertyMapProperties::from_property_node(&source_names);
+
+        Ok(Self {
+            dest,
+            source,
+            counts: Default::default(),
+            message_ids: None,
+            source_names,
+            named_prop_ids: Default::default(),
+            report: Default::default(),
+        })
+    }
+
+    fn merge(&mut self, options: &MergeOptions) -> io::Result<()> {
+        let max_data_size = self.dest.max_data_size();
+        if options.deduplicate_by_message_id {
+            let mut message_ids = HashSet::new();
+            for node in self.dest.original_nodes() {
+                if !matches!(node.id_type(), Ok(NodeIdType::NormalMessage)) {
+                    continue;
+                }
+                let message = PropertyNode::read(&self.dest.read_node(node)?, max_data_size)?;
+                if let Some(message_id) = message.string(PR_INTERNET_MESSAGE_ID) {
+                    message_ids.insert(message_id);
+                }
+            }
+            self.message_ids = Some(message_ids);
+        }
+
+        let source_root = ipm_subtree(&mut self.source, max_data_size)?;
+        let dest_root = self.dest.ipm_subtree()?;
+
+        match options.target {
+            MergeTarget::MatchingFolders => self.merge_folder(source_root, dest_root),
+            MergeTarget::Subtree => {
+                let store =
+                    PropertyNode::read(&self.source.read_node(NID_MESSAGE_STORE)?, max_data_size)?;
+                let name = store
+                    .string(PR_DISPLAY_NAME)
+                    .unwrap_or_else(|| String::from("Imported"));
+
+                let name = match (
+                    self.dest.find_sub_folder(dest_root, &name)?,
+                    options.conflict,
+                ) {
+                    (None, _) => name,
+                    (Some(_), ConflictResolution::Skip) => return Ok(()),
+                    (Some(existing), ConflictResolution::Overwrite) => {
+                        self.dest.remove_folder(dest_root, existing)?;
+                        self.counts.entry(u32::from(dest_root)).or_default();
+                        name
+                    }
+                    (Some(_), ConflictResolution::Rename) => {
+                        let mut suffix = 2;
+                        loop {
+                            let renamed = format!("{name} ({suffix})");
+                            if self.dest.find_sub_folder(dest_root, &renamed)?.is_none() {
+                                break renamed;
+                            }
+                            suffix += 1;
+                        }
+                    }
+                };
+
+                let folder = self.create_folder(dest_root, source_root, &name)?;
+                self.merge_folder(source_root, folder)
+            }
+        }
+    }
+
+    /// Import the messages in `source`, then recursively merge each of its sub-folders into the
+    /// sub-folder of `dest` with the same display name.
+    fn merge_folder(&mut self, source: NodeId, dest: NodeId) -> io::Result<()> {
+        let max_data_size = self.dest.max_data_size();
+        let contents = table_id(NodeIdType::ContentsTable, source)?;
+        let contents = TableNode::read(&self.source.read_node(contents)?, max_data_size)?;
+        for message in contents.row_ids() {
+            let message = self.source.read_node(message)?;
+            self.add_message(dest, message)?;
+        }
+
+        let hierarchy = table_id(NodeIdType::HierarchyTable, source)?;
+        let hierarchy = TableNode::read(&self.source.read_node(hierarchy)?, max_data_size)?;
+        for sub_folder in hierarchy.row_ids() {
+            let properties =
+                PropertyNode::read(&self.source.read_node(sub_folder)?, max_data_size)?;
+            let name = properties.string(PR_DISPLAY_NAME).unwrap_or_default();
+            let target = match self.dest.find_sub_folder(dest, &name)? {
+                Some(target) => target,
+                None => self.create_folder(dest, sub_folder, &name)?,
+            };
+            self.merge_folder(sub_folder, target)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add a copy of `message` to the contents table of `folder`, unless it is a duplicate.
+    fn add_message(&mut self, folder: NodeId, message: NodeContents) -> io::Result<()> {
+        let max_data_size = self.dest.max_data_size();
+        let properties = PropertyNode::read(&message, max_data_size)?;
+        if let (Some(message_ids), Some(message_id)) = (
+            self.message_ids.as_mut(),
+            properties.string(PR_INTERNET_MESSAGE_ID),
+        ) {
+            if !message_ids.insert(message_id) {
+                self.report.messages_skipped += 1;
+                return Ok(());
+            }
+        }
+
+        let (mut message, properties) = match self.remap_node(&message)? {
+            Some(remapped) => {
+                let properties = PropertyNode::read(&remapped, max_data_size)?;
+                (remapped, properties)
+            }
+            None => (message, properties),
+        };
+
+        let node = self.dest.new_node_id(NodeIdType::NormalMessage)?;
+        message.node = node;
+        message.parent = Some(folder);
+
+        let contents = table_id(NodeIdType::ContentsTable, folder)?;
+        let contents = self.dest.table(contents)?;
+        let row = contents.row_from_properties(node, &properties)?;
+        contents.set_row(row);
+        self.dest.add_node(message);
+
+        let counts = self.counts.entry(u32::from(folder)).or_default();
+        counts.messages += 1;
+        if properties.is_unread() {
+            counts.unread += 1;
+        }
+        self.report.messages_imported += 1;
+        Ok(())
+    }
+
+    /// Map the named properties in the PC of `node` to the property IDs in the destination, along
+    /// with any attachments or embedded messages in its sub-nodes. Returns `None` if nothing
+    /// needed to change, so the node can be copied as-is.
+    fn remap_node(&mut self, node: &NodeContents) -> io::Result<Option<NodeContents>> {
+        let max_data_size = self.dest.max_data_size();
+        let mut properties = PropertyNode::read(node, max_data_size)?;
+        let mut changed = self.remap_properties(&mut properties)?;
+        for sub_node in properties.sub_nodes.iter_mut() {
+            if !matches!(
+                sub_node.node.id_type(),
+                Ok(NodeIdType::Attachment | NodeIdType::NormalMessage)
+            ) {
+                continue;
+            }
+            if let Some(remapped) = self.remap_node(sub_node)? {
+                *sub_node = remapped;
+                changed = true;
+            }
+        }
+        if !changed {
+            return Ok(None);
+        }
+
+        // Values which do not fit in the heap go in new sub-nodes, after the ones which are left.
+        let mut next_ltp_index = properties
+            .sub_nodes
+            .iter()
+            .map(|sub_node| sub_node.node.index() + 1)
+            .max()
+            .unwrap_or(1);
+        Ok(Some(properties.write(max_data_size, &mut next_ltp_index)?))
+    }
+
+    /// Map the named properties in `properties` from the property IDs in the source to the
+    /// property IDs in the destination, and drop any which the source cannot resolve. Returns
+    /// `true` if anything changed.
+    fn remap_properties(&mut self, properties: &mut PropertyNode) -> io::Result<bool> {
+        let named = properties.properties.split_off(&0x8000);
+        let mut changed = false;
+        for (prop_id, value) in named {
+            match self.dest_prop_id(prop_id)? {
+                Some(dest_id) => {
+                    changed |= dest_id != prop_id;
+                    properties.properties.insert(dest_id, value);
+                }
+                None => {
+                    self.report.named_properties_dropped += 1;
+                    changed = true;
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Find the property ID in the destination for the named property `prop_id` in the source,
+    /// and add it to the named property map in the destination if it is missing.
+    fn dest_prop_id(&mut self, prop_id: u16) -> io::Result<Option<u16>> {
+        if let Some(dest_id) = self.named_prop_ids.get(&prop_id) {
+            return Ok(*dest_id);
+        }
+
+        let dest_id = match self.source_names.resolve_id(prop_id)? {
+            Some(named_prop) => {
+                let dest_names = self.dest.folder(NID_NAME_TO_ID_MAP)?;
+                let existing = {
+                    let dest_names = NamedPropertyMapProperties::from_property_node(dest_names);
+                    match &named_prop {
+                        NamedProperty::Numeric { guid, id } => {
+                            dest_names.find_numeric_id(guid, *id)?
+                        }
+                        NamedProperty::String { guid, name } => dest_names.find_id(guid, name)?,
+                    }
+                };
+                match existing {
+                    Some(dest_id) => Some(dest_id),
+                    None => Some(add_named_property(dest_names, &named_prop)?),
+                }
+            }
+            None => None,
+        };
+        self.named_prop_ids.insert(prop_id, dest_id);
+        Ok(dest_id)
+    }
+
+    /// Create an empty folder under `parent`, with a copy of the PC of `template` in the source
+    /// store and empty TCs.
+    fn create_folder(
+        &mut self,
+        parent: NodeId,
+        template: NodeId,
+        name: &str,
+    ) -> io::Result<NodeId> {
+        let max_data_size = self.dest.max_data_size();
+        let folder = self.dest.new_node_id(NodeIdType::NormalFolder)?;
+
+        let mut properties = PropertyNode::read(&self.source.read_node(template)?, max_data_size)?;
+        self.remap_properties(&mut properties)?;
+        properties.node = folder;
+        properties.parent = Some(parent);
+        properties.set(
+            PR_DISPLAY_NAME,
+            PropertyType::Unicode,
+            PropertyData::Bytes(unicode_bytes(name)),
+        );
+        properties.set(
+            PR_CONTENT_COUNT,
+            PropertyType::Integer32,
+            PropertyData::Small(0),
+        );
+        properties.set(
+            PR_CONTENT_UNREAD,
+            PropertyType::Integer32,
+            PropertyData::Small(0),
+        );
+        properties.set(PR_SUBFOLDERS, PropertyType::Boolean, PropertyData::Small(0));
+        self.dest.set_folder(properties);
+
+        // The columns of the TCs in the source may have named property IDs from the source, so
+        // use the templates in the destination instead.
+        for (id_type, template) in [
+            (NodeIdType::HierarchyTable, NID_HIERARCHY_TABLE_TEMPLATE),
+            (NodeIdType::ContentsTable, NID_CONTENTS_TABLE_TEMPLATE),
+            (
+                NodeIdType::AssociatedContentsTable,
+                NID_ASSOC_CONTENTS_TABLE_TEMPLATE,
+            ),
+        ] {
+            let mut table = TableNode::read(&self.dest.read_node(template)?, max_data_size)?;
+            table.node = table_id(id_type, folder)?;
+            table.rows.clear();
+            self.dest.set_table(table);
+        }
+
+        // Adding the new folder to the parent hierarchy table happens with the counts.
+        self.counts.entry(u32::from(folder)).or_default();
+        self.counts.entry(u32::from(parent)).or_default();
+        self.report.folders_created += 1;
+        Ok(folder)
+    }
+
+    /// Apply the changes in [`Self::counts`] to each folder PC, and update or add the folder's
+    /// row in the hierarchy table of its parent.
+    fn update_folders(&mut self) -> io::Result<()> {
+        let counts = std::mem::take(&mut self.counts);
+        let folders: Vec<_> = counts
+            .into_iter()
+            .map(|(folder, counts)| (NodeId::from(folder), counts))
+            .filter(|(folder, _)| !self.dest.is_removed(*folder))
+            .collect();
+
+        for (folder, counts) in folders.iter() {
+            let folder = *folder;
+            let properties = self.dest.folder(folder)?;
+            let messages = properties.integer(PR_CONTENT_COUNT).unwrap_or_default();
+            let unread = properties.integer(PR_CONTENT_UNREAD).unwrap_or_default();
+            properties.set(
+                PR_CONTENT_COUNT,
+                PropertyType::Integer32,
+                PropertyData::Small((messages + counts.messages) as u32),
+            );
+            properties.set(
+                PR_CONTENT_UNREAD,
+                PropertyType::Integer32,
+                PropertyData::Small((unread + counts.unread) as u32),
+            );
+
+            self.dest.update_hierarchy_row(folder)?;
+        }
+
+        // A parent may be updated before the rows for its new sub-folders are added, so only set
+        // PR_SUBFOLDERS once all of the hierarchy tables are complete.
+        for (folder, _) in folders {
+            let hierarchy = table_id(NodeIdType::HierarchyTable, folder)?;
+            let has_sub_folders = !self.dest.table(hierarchy)?.rows.is_empty();
+            self.dest.folder(folder)?.set(
+                PR_SUBFOLDERS,
+                PropertyType::Boolean,
+                PropertyData::Small(u32::from(has_sub_folders)),
+            );
+            self.dest.update_hierarchy_row(folder)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ltp::prop_context::TypedProperties,
+        messaging::store::{Store, UnicodeStore},
+        ndb::{block::UnicodeDataTree, header::Header, root::*},
+        test_util::{TempFile, EMPTY_PST},
+        PstFile, PstFileReader,
+    };
+    use std::{fs, sync::Arc};
+
+    /// Subject, `PidTagInternetMessageId` and whether the message has been read.
+    type TestMessage<'a> = (&'a str, &'a str, bool);
+
+    /// Build a message PC with the properties the merge uses, and a `PidTagBody` which is too
+    /// big for the heap, since a message cannot be opened without a sub-node tree.
+    fn test_message(subject: &str, message_id: &str, read: bool) -> io::Result<NodeContents> {
+        let mut message = PropertyNode {
+            node: Default::default(),
+            parent: None,
+            properties: Default::default(),
+            sub_nodes: Default::default(),
+        };
+        let mut set_string = |prop_id, value: &str| {
+            message.set(
+                prop_id,
+                PropertyType::Unicode,
+                PropertyData::Bytes(unicode_bytes(value)),
+            )
+        };
+        set_string(PR_MESSAGE_CLASS, "IPM.Note");
+        set_string(PR_SUBJECT, subject);
+        set_string(PR_INTERNET_MESSAGE_ID, message_id);
+        set_string(PR_BODY, &format!("Body of {subject}. ").repeat(200));
+        message.set(
+            PR_MESSAGE_FLAGS,
+            PropertyType::Integer32,
+            PropertyData::Small(if read { MSGFLAG_READ as u32 } else { 0 }),
+        );
+
+        let mut next_ltp_index = 1;
+        message.write(UnicodeDataTree::max_data_size(), &mut next_ltp_index)
+    }
+
+    /// Make a copy of the sample file with new folders under the IPM subtree, using the IPM
+    /// subtree itself as the template for each folder.
+    fn build_fixture(path: &Path, folders: &[(&str, &[TestMessage])]) -> io::Result<()> {
+        fs::copy(EMPTY_PST, path)?;
+        let template = UnicodePstFile::open(EMPTY_PST)?;
+        edit_store(&mut UnicodePstFile::open(path)?, |dest| {
+            let mut merger = StoreMerger::new(dest, &template)?;
+            let root = merger.dest.ipm_subtree()?;
+            for (name, messages) in folders {
+                let folder = merger.create_folder(root, root, name)?;
+                for (subject, message_id, read) in messages.iter() {
+                    merger.add_message(folder, test_message(subject, message_id, *read)?)?;
+                }
+            }
+            merger.update_folders()
+        })
+    }
+
+    /// Display name, message count, unread count and message subjects of every folder.
+    type FolderSummary = (String, u32, u32, Vec<String>);
+
+    fn folder_summaries(path: &Path) -> io::Result<Vec<FolderSummary>> {
+        let pst = PstFileReader::open(path)?;
+        pst.folders()?
+            .into_iter()
+            .map(|folder| {
+                let subjects = match folder.contents_table() {
+                    Some(contents_table) => contents_table
+                        .rows_matrix()?
+                        .map(|row| {
+                            let message = pst.message(NodeId::from(u32::from(row.id())))?;
+                            Ok(message
+                                .properties()
+                                .get_string8(PR_SUBJECT)?
+                                .unwrap_or_default())
+                        })
+                        .collect::<io::Result<Vec<_>>>()?,
+                    None => Default::default(),
+                };
+                // Some of the special folders in the sample file have a `PtypNull` display name.
+                Ok((
+                    folder.properties().display_name().unwrap_or_default(),
+                    folder.message_count()?,
+                    folder.unread_count()?,
+                    subjects,
+                ))
+            })
+            .collect()
+    }
+
+    fn find_folder<'a>(folders: &'a [FolderSummary], name: &str) -> &'a FolderSummary {
+        folders
+            .iter()
+            .find(|(display_name, ..)| display_name == name)
+            .unwrap_or_else(|| panic!("missing folder: {name}"))
+    }
+
+    #[test]
+    fn test_merge_stores() {
+        let dest = TempFile::new("merge_dest");
+        let source = TempFile::new("merge_source");
+        build_fixture(
+            &dest,
+            &[(
+                "Inbox",
+                &[
+                    ("One", "<1@example.com>", false),
+                    ("Two", "<2@example.com>", true),
+                ],
+            )],
+        )
+        .unwrap();
+        build_fixture(
+            &source,
+            &[
+                (
+                    "Inbox",
+                    &[
+                        ("Two again", "<2@example.com>", false),
+                        ("Three", "<3@example.com>", false),
+                    ],
+                ),
+                ("Archive", &[("Four", "<4@example.com>", true)]),
+            ],
+        )
+        .unwrap();
+        let source_data = fs::read(&source).unwrap();
+
+        let options = MergeOptions {
+            deduplicate_by_message_id: true,
+            ..Default::default()
+        };
+        let report = merge_stores(&dest, &source, &options).unwrap();
+        assert_eq!(
+            report,
+            MergeReport {
+                folders_created: 1,
+                messages_imported: 2,
+                messages_skipped: 1,
+                named_properties_dropped: 0,
+            }
+        );
+        assert_eq!(fs::read(&source).unwrap(), source_data);
+
+        let pst = UnicodePstFile::open(&dest).unwrap();
+        assert_eq!(pst.header().root().amap_is_valid(), AmapStatus::Valid2);
+        drop(pst);
+
+        let folders = folder_summaries(&dest).unwrap();
+        let (_, count, unread, subjects) = find_folder(&folders, "Inbox");
+        assert_eq!((*count, *unread), (3, 2));
+        assert_eq!(subjects, &["One", "Two", "Three"]);
+        let (_, count, unread, subjects) = find_folder(&folders, "Archive");
+        assert_eq!((*count, *unread), (1, 0));
+        assert_eq!(subjects, &["Four"]);
+        let total: u32 = folders.iter().map(|(_, count, ..)| count).sum();
+        assert_eq!(total, 4);
+
+        // Importing the whole source store twice renames the second copy. Each copy has the
+        // "Deleted Items" folder from the sample file as well as "Inbox" and "Archive".
+        let store_name = PstFileReader::open(&source)
+            .unwrap()
+            .store()
+            .properties()
+            .display_name()
+            .unwrap();
+        let renamed = format!("{store_name} (2)");
+        let options = MergeOptions {
+            target: MergeTarget::Subtree,
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            let report = merge_stores(&dest, &source, &options).unwrap();
+            assert_eq!(
+                report,
+                MergeReport {
+                    folders_created: 4,
+                    messages_imported: 3,
+                    messages_skipped: 0,
+                    named_properties_dropped: 0,
+                }
+            );
+        }
+        let folders = folder_summaries(&dest).unwrap();
+        find_folder(&folders, &store_name);
+        find_folder(&folders, &renamed);
+        let total: u32 = folders.iter().map(|(_, count, ..)| count).sum();
+        assert_eq!(total, 10);
+
+        // Overwriting replaces the first copy, and skipping leaves everything alone.
+        let options = MergeOptions {
+            target: MergeTarget::Subtree,
+            conflict: ConflictResolution::Overwrite,
+            ..Default::default()
+        };
+        merge_stores(&dest, &source, &options).unwrap();
+        let options = MergeOptions {
+            target: MergeTarget::Subtree,
+            conflict: ConflictResolution::Skip,
+            ..Default::default()
+        };
+        assert_eq!(
+            merge_stores(&dest, &source, &options).unwrap(),
+            MergeReport::default()
+        );
+        let folders = folder_summaries(&dest).unwrap();
+        let names = |name: &str| {
+            folders
+                .iter()
+                .filter(|(display_name, ..)| display_name == name)
+                .count()
+        };
+        assert_eq!(names(&store_name), 1);
+        assert_eq!(names(&renamed), 1);
+        assert_eq!(names("Inbox"), 3);
+        let total: u32 = folders.iter().map(|(_, count, ..)| count).sum();
+        assert_eq!(total, 10);
+    }
+
+    #[test]
+    fn test_merge_named_properties() {
+        let dest = TempFile::copy_of(EMPTY_PST, "merge_named_dest").unwrap();
+        let source = TempFile::new("merge_named_source");
+        build_fixture(
+            &source,
+            &[("Named", &[("Named", "<n@example.com>", false)])],
+        )
+        .unwrap();
+
+        let first = NamedProperty::String {
+            guid: PS_PUBLIC_STRINGS,
+            name: String::from("MergeFirst"),
+        };
+        let second = NamedProperty::Numeric {
+            guid: PS_MAPI,
+            id: 0x7FFF_0001,
+        };
+
+        // The destination only has the second name, at the ID which the first name has in the
+        // source, so a copy which is not remapped would show the wrong value for the first name.
+        let (source_first, source_second) =
+            edit_store(&mut UnicodePstFile::open(&source).unwrap(), |editor| {
+                let names = editor.folder(NID_NAME_TO_ID_MAP)?;
+                let source_first = add_named_property(names, &first)?;
+                let source_second = add_named_property(names, &second)?;
+
+                let root = editor.ipm_subtree()?;
+                let folder = editor.find_sub_folder(root, "Named")?.unwrap();
+                let message = editor
+                    .table(table_id(NodeIdType::ContentsTable, folder)?)?
+                    .row_ids();
+                let message = editor.folder(message[0])?;
+                for (prop_id, value) in [(source_first, "one"), (source_second, "two")] {
+                    message.set(
+                        prop_id,
+                        PropertyType::Unicode,
+                        PropertyData::Bytes(unicode_bytes(value)),
+                    );
+                }
+                message.set(0xF000, PropertyType::Integer32, PropertyData::Small(7));
+                Ok((source_first, source_second))
+            })
+            .unwrap();
+        let dest_second = edit_store(&mut UnicodePstFile::open(&dest).unwrap(), |editor| {
+            add_named_property(editor.folder(NID_NAME_TO_ID_MAP)?, &second)
+        })
+        .unwrap();
+        assert_eq!(dest_second, source_first);
+
+        let report = merge_stores(&dest, &source, &Default::default()).unwrap();
+        assert_eq!(
+            report,
+            MergeReport {
+                folders_created: 1,
+                messages_imported: 1,
+                messages_skipped: 0,
+                named_properties_dropped: 1,
+            }
+        );
+
+        let store = UnicodeStore::read(Arc::new(UnicodePstFile::open(&dest).unwrap())).unwrap();
+        let names = store.named_property_map().unwrap();
+        let dest_first = names.find_id(&PS_PUBLIC_STRINGS, "MergeFirst").unwrap();
+        assert_eq!(dest_first, Some(source_second));
+        assert_eq!(names.resolve_id(source_second).unwrap(), Some(first));
+        assert_eq!(names.resolve_id(dest_second).unwrap(), Some(second));
+
+        let pst = PstFileReader::open(&dest).unwrap();
+        let folder = pst
+            .folders()
+            .unwrap()
+            .into_iter()
+            .find(|folder| folder.properties().display_name().ok().as_deref() == Some("Named"))
+            .unwrap();
+        let row = folder
+            .contents_table()
+            .unwrap()
+            .rows_matrix()
+            .unwrap()
+            .next()
+            .unwrap();
+        let message = pst.message(NodeId::from(u32::from(row.id()))).unwrap();
+        let properties = message.properties();
+        assert_eq!(
+            properties.get_string8(source_second).unwrap().as_deref(),
+            Some("one")
+        );
+        assert_eq!(
+            properties.get_string8(dest_second).unwrap().as_deref(),
+            Some("two")
+        );
+        assert!(properties.get(0xF000).is_none());
+    }
+}
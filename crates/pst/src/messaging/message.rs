@@ -1,15 +1,24 @@
 //! ## [Message Objects](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/1042af37-aaa4-4edc-bffd-90a1ede24188)
 
-use std::{collections::BTreeMap, io, rc::Rc};
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{Arc, Weak},
+    time::SystemTime,
+};
 
-use super::{read_write::*, store::*, *};
+use super::{
+    attachment::*, items::MessageType, property_ids::*, read_write::*, recipient::*, rtf::*,
+    sender::*, store::*, time::*, *,
+};
 use crate::{
     ltp::{
         heap::HeapNode,
-        prop_context::{PropertyContext, PropertyValue},
-        prop_type::PropertyType,
+        prop_context::{PropertyContext, PropertyValue, TypedProperties},
+        prop_type::{decode_string8, PropertyType, DEFAULT_CODEPAGE},
         read_write::*,
         table_context::TableContext,
+        LtpError, LtpResult,
     },
     ndb::{
         block::{IntermediateTreeBlock, LeafSubNodeTreeEntry, SubNodeTree},
@@ -26,6 +35,53 @@ use crate::{
 #[derive(Default, Debug)]
 pub struct MessageProperties {
     properties: BTreeMap<u16, PropertyValue>,
+    store_codepage: Option<u32>,
+}
+
+/// Well known properties are serialized as named fields, like `subject`, `sender_name` and
+/// `delivery_time`. Every property is also included in the `properties` map, keyed by its hex
+/// property ID.
+#[cfg(feature = "serde")]
+impl serde::Serialize for MessageProperties {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        const FIELDS: &[(&str, &[u16])] = &[
+            ("subject", &[PR_SUBJECT]),
+            ("message_class", &[PR_MESSAGE_CLASS]),
+            ("sender_name", &[PR_SENDER_NAME, PR_SENT_REPRESENTING_NAME]),
+            (
+                "sender_email",
+                &[PR_SENDER_SMTP_ADDRESS, PR_SENDER_EMAIL_ADDRESS],
+            ),
+            ("to", &[PR_DISPLAY_TO]),
+            ("cc", &[PR_DISPLAY_CC]),
+            ("submit_time", &[PR_CLIENT_SUBMIT_TIME]),
+            ("delivery_time", &[PR_MESSAGE_DELIVERY_TIME]),
+            ("creation_time", &[PR_CREATION_TIME]),
+            ("last_modification_time", &[PR_LAST_MODIFICATION_TIME]),
+            ("message_size", &[PR_MESSAGE_SIZE]),
+            ("message_flags", &[PR_MESSAGE_FLAGS]),
+            ("importance", &[PR_IMPORTANCE]),
+            ("internet_message_id", &[PR_INTERNET_MESSAGE_ID]),
+        ];
+
+        let mut state = serializer.serialize_struct("MessageProperties", FIELDS.len() + 1)?;
+        super::serialize_named_properties(&mut state, FIELDS, &self.properties, self.codepage())?;
+        state.end()
+    }
+}
+
+impl From<BTreeMap<u16, PropertyValue>> for MessageProperties {
+    fn from(properties: BTreeMap<u16, PropertyValue>) -> Self {
+        Self {
+            properties,
+            store_codepage: None,
+        }
+    }
 }
 
 impl MessageProperties {
@@ -37,23 +93,26 @@ impl MessageProperties {
         self.properties.iter()
     }
 
-    pub fn message_class(&self) -> io::Result<String> {
-        let message_class = self
-            .properties
-            .get(&0x001A)
-            .ok_or(MessagingError::MessageClassNotFound)?;
+    /// Get the `PidTagMessageClass`, or `None` if the message does not have one. A
+    /// `PtypString8` value is decoded with [`TypedProperties::codepage`], and a value which is not
+    /// a string or cannot be decoded is treated as missing.
+    pub fn message_class(&self) -> Option<String> {
+        self.get_string8(PR_MESSAGE_CLASS).ok().flatten()
+    }
 
-        match message_class {
-            PropertyValue::String8(value) => Ok(value.to_string()),
-            PropertyValue::Unicode(value) => Ok(value.to_string()),
-            invalid => Err(MessagingError::InvalidMessageClass(PropertyType::from(invalid)).into()),
-        }
+    /// Classify the message by its [`Self::message_class`]. A message without a valid
+    /// `PidTagMessageClass` is [`MessageType::Other`].
+    pub fn message_type(&self) -> MessageType {
+        self.message_class()
+            .map_or(MessageType::Other, |message_class| {
+                MessageType::from_message_class(&message_class)
+            })
     }
 
     pub fn message_flags(&self) -> io::Result<i32> {
         let message_flags = self
             .properties
-            .get(&0x0E07)
+            .get(&PR_MESSAGE_FLAGS)
             .ok_or(MessagingError::MessageFlagsNotFound)?;
 
         match message_flags {
@@ -62,10 +121,18 @@ impl MessageProperties {
         }
     }
 
+    /// Check for `MSGFLAG_READ` in `PR_MESSAGE_FLAGS`, which must be set.
+    pub fn is_read(&self) -> LtpResult<bool> {
+        let message_flags = self
+            .get_typed::<i32>(PR_MESSAGE_FLAGS)?
+            .ok_or(LtpError::PropertyNotFound(PR_MESSAGE_FLAGS))?;
+        Ok(message_flags & MSGFLAG_READ != 0)
+    }
+
     pub fn message_size(&self) -> io::Result<i32> {
         let message_size = self
             .properties
-            .get(&0x0E08)
+            .get(&PR_MESSAGE_SIZE)
             .ok_or(MessagingError::MessageSizeNotFound)?;
 
         match message_size {
@@ -77,7 +144,7 @@ impl MessageProperties {
     pub fn message_status(&self) -> io::Result<i32> {
         let message_status = self
             .properties
-            .get(&0x0E17)
+            .get(&PR_MESSAGE_STATUS)
             .ok_or(MessagingError::MessageStatusNotFound)?;
 
         match message_status {
@@ -91,7 +158,7 @@ impl MessageProperties {
     pub fn creation_time(&self) -> io::Result<i64> {
         let creation_time = self
             .properties
-            .get(&0x3007)
+            .get(&PR_CREATION_TIME)
             .ok_or(MessagingError::MessageCreationTimeNotFound)?;
 
         match creation_time {
@@ -105,7 +172,7 @@ impl MessageProperties {
     pub fn last_modification_time(&self) -> io::Result<i64> {
         let last_modification_time = self
             .properties
-            .get(&0x3008)
+            .get(&PR_LAST_MODIFICATION_TIME)
             .ok_or(MessagingError::MessageLastModificationTimeNotFound)?;
 
         match last_modification_time {
@@ -117,10 +184,105 @@ impl MessageProperties {
         }
     }
 
+    /// Get `PidTagMessageDeliveryTime`, or `None` if the message has not been delivered.
+    pub fn delivery_time(&self) -> LtpResult<Option<SystemTime>> {
+        let Some(delivery_time) = self.properties.get(&PR_MESSAGE_DELIVERY_TIME) else {
+            return Ok(None);
+        };
+
+        match delivery_time {
+            PropertyValue::Time(value) => {
+                Ok(u64::try_from(*value).ok().and_then(filetime_to_system_time))
+            }
+            invalid => Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Time,
+                actual: PropertyType::from(invalid),
+            }),
+        }
+    }
+
+    /// Get the decompressed `PidTagRtfCompressed` body, or `None` if the message does not have
+    /// an RTF body. See [`decompress`].
+    pub fn body_rtf(&self) -> LtpResult<Option<Vec<u8>>> {
+        let Some(rtf_compressed) = self.properties.get(&PR_RTF_COMPRESSED) else {
+            return Ok(None);
+        };
+
+        match rtf_compressed {
+            PropertyValue::Binary(value) => Ok(Some(decompress_lzfu(value.buffer())?)),
+            invalid => Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Binary,
+                actual: PropertyType::from(invalid),
+            }),
+        }
+    }
+
+    /// Get the body in the format the message was authored in, with a fallback to any other
+    /// body which is present. If `PidTagNativeBody` names a body which the message has, that one
+    /// is returned, otherwise the precedence is `PidTagHtml`, then `PidTagRtfCompressed`, and
+    /// finally `PidTagBody`. Returns `None` if the message has no body at all.
+    ///
+    /// A binary `PidTagHtml` is decoded with `PidTagInternetCodepage` if it is set, or
+    /// [`Self::codepage`] otherwise. Empty bodies and unsupported code pages are reported as
+    /// errors.
+    pub fn best_body(&self) -> io::Result<Option<Body>> {
+        let native_body = match self.get_typed::<i32>(PR_NATIVE_BODY)? {
+            Some(NATIVE_BODY_PLAIN) => Some(PR_BODY),
+            Some(NATIVE_BODY_RTF) => Some(PR_RTF_COMPRESSED),
+            Some(NATIVE_BODY_HTML) => Some(PR_HTML_BODY),
+            _ => None,
+        };
+
+        let Some(prop_id) = native_body
+            .into_iter()
+            .chain([PR_HTML_BODY, PR_RTF_COMPRESSED, PR_BODY])
+            .find(|prop_id| self.properties.contains_key(prop_id))
+        else {
+            return Ok(None);
+        };
+
+        let body = match prop_id {
+            PR_HTML_BODY => Body::Html(self.body_html()?.unwrap_or_default()),
+            PR_RTF_COMPRESSED => Body::Rtf(self.body_rtf()?.unwrap_or_default()),
+            _ => Body::Plain(self.get_string8(PR_BODY)?.unwrap_or_default()),
+        };
+
+        let is_empty = match &body {
+            Body::Html(body) | Body::Plain(body) => body.is_empty(),
+            Body::Rtf(body) => body.is_empty(),
+        };
+        if is_empty {
+            return Err(MessagingError::EmptyMessageBody(prop_id).into());
+        }
+
+        Ok(Some(body))
+    }
+
+    /// Get `PidTagHtml` as a string. The property is usually binary, in which case it is decoded
+    /// with `PidTagInternetCodepage` if it is set, or [`Self::codepage`] otherwise. An unsupported
+    /// code page fails with [`LtpError::UnsupportedCodePage`], like [`Self::get_string8`].
+    pub fn body_html(&self) -> io::Result<Option<String>> {
+        let Some(value) = self.properties.get(&PR_HTML_BODY) else {
+            return Ok(None);
+        };
+
+        match value {
+            PropertyValue::Binary(value) => {
+                let codepage = match self.get_typed::<i32>(PR_INTERNET_CPID)? {
+                    Some(codepage) => u32::try_from(codepage)
+                        .map_err(|_| LtpError::UnsupportedCodePage(codepage as u32))?,
+                    None => self.codepage(),
+                };
+                Ok(Some(decode_string8(value.buffer(), codepage)?))
+            }
+            _ => Ok(self.get_string8(PR_HTML_BODY)?),
+        }
+    }
+
     pub fn search_key(&self) -> io::Result<&[u8]> {
         let search_key = self
             .properties
-            .get(&0x300B)
+            .get(&PR_SEARCH_KEY)
             .ok_or(MessagingError::MessageSearchKeyNotFound)?;
 
         match search_key {
@@ -132,22 +294,142 @@ impl MessageProperties {
     }
 }
 
-pub trait Message {
-    fn store(&self) -> Rc<dyn Store>;
+/// `PtypString8` properties on a message are encoded with `PidTagMessageCodepage` if it is
+/// present, then `PidTagInternetCodepage`, and finally the default code page of the store the
+/// message was read from.
+impl TypedProperties for MessageProperties {
+    fn property_value(&self, id: u16) -> Option<&PropertyValue> {
+        self.get(id)
+    }
+
+    fn codepage(&self) -> u32 {
+        super::codepage_property(self)
+            .or(self.store_codepage)
+            .unwrap_or(DEFAULT_CODEPAGE)
+    }
+}
+
+/// `PidTagNativeBody` value for a plain text body.
+pub const NATIVE_BODY_PLAIN: i32 = 0x00000001;
+/// `PidTagNativeBody` value for an RTF body.
+pub const NATIVE_BODY_RTF: i32 = 0x00000002;
+/// `PidTagNativeBody` value for an HTML body.
+pub const NATIVE_BODY_HTML: i32 = 0x00000003;
+
+/// The best available body of a message, see [`MessageProperties::best_body`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Body {
+    /// `PidTagHtml`
+    Html(String),
+    /// Decompressed `PidTagRtfCompressed`
+    Rtf(Vec<u8>),
+    /// `PidTagBody`
+    Plain(String),
+}
+
+pub trait Message: Send + Sync {
+    fn store(&self) -> Arc<dyn Store>;
     fn properties(&self) -> &MessageProperties;
-    fn recipient_table(&self) -> Option<&Rc<dyn TableContext>>;
-    fn attachment_table(&self) -> Option<&Rc<dyn TableContext>>;
+    fn recipient_table(&self) -> Option<&Arc<dyn TableContext>>;
+    fn attachment_table(&self) -> Option<&Arc<dyn TableContext>>;
+
+    /// How many embedded message attachments this message is nested in, `0` for a message which
+    /// is stored in a folder.
+    fn embedded_depth(&self) -> usize;
+
+    /// Read each of the attachments in the attachment table. Reading an embedded message which
+    /// would be nested deeper than `max_depth` fails with
+    /// [`LtpError::EmbeddedMessageDepthExceeded`].
+    fn attachments_with_limit(
+        &self,
+        prop_ids: Option<&[u16]>,
+        max_depth: usize,
+    ) -> io::Result<Vec<Arc<dyn Attachment>>>;
+
+    /// Read each of the attachments in the attachment table, limiting embedded messages to
+    /// [`DEFAULT_MAX_EMBEDDED_DEPTH`] levels of nesting.
+    fn attachments(&self, prop_ids: Option<&[u16]>) -> io::Result<Vec<Arc<dyn Attachment>>> {
+        self.attachments_with_limit(prop_ids, DEFAULT_MAX_EMBEDDED_DEPTH)
+    }
+
+    /// Get the To, Cc and Bcc recipients from the recipient table. `PtypString8` columns are
+    /// decoded with [`MessageProperties::codepage`].
+    fn recipients(&self) -> RecipientTable {
+        RecipientTable::new(
+            self.recipient_table().cloned(),
+            self.properties().codepage(),
+        )
+    }
+
+    /// See [`MessageProperties::sender`].
+    fn sender(&self) -> io::Result<Sender> {
+        self.properties().sender()
+    }
+
+    /// See [`MessageProperties::best_body`].
+    fn best_body(&self) -> io::Result<Option<Body>> {
+        self.properties().best_body()
+    }
+}
+
+/// A message in the contents table of a folder, see [`Folder::messages`]. The properties are
+/// the columns of its row in the contents table, e.g. the subject, delivery time, flags and size,
+/// which are read without opening the message itself. Use [`MessageSummary::load`] to read the
+/// message PC, recipients and attachments.
+///
+/// [`Folder::messages`]: super::folder::Folder::messages
+pub struct MessageSummary {
+    store: Arc<dyn Store>,
+    entry_id: EntryId,
+    properties: MessageProperties,
+}
+
+impl MessageSummary {
+    pub(crate) fn new(
+        store: Arc<dyn Store>,
+        entry_id: EntryId,
+        properties: MessageProperties,
+    ) -> Self {
+        Self {
+            store,
+            entry_id,
+            properties,
+        }
+    }
+
+    pub fn node_id(&self) -> NodeId {
+        self.entry_id.node_id()
+    }
+
+    pub fn entry_id(&self) -> &EntryId {
+        &self.entry_id
+    }
+
+    /// The columns of the message's row in the contents table. These are copies of the
+    /// properties in the message PC, which may be out of date if the file was not updated
+    /// consistently.
+    pub fn properties(&self) -> &MessageProperties {
+        &self.properties
+    }
+
+    /// Open the message, see [`Store::open_message`].
+    pub fn load(&self, prop_ids: Option<&[u16]>) -> io::Result<Arc<dyn Message>> {
+        self.store.open_message(&self.entry_id, prop_ids)
+    }
 }
 
 struct MessageInner<Pst>
 where
     Pst: PstFile,
 {
-    store: Rc<Pst::Store>,
+    store: Arc<Pst::Store>,
+    message: Weak<Pst::Message>,
+    node_id: NodeId,
+    depth: usize,
     properties: MessageProperties,
     sub_nodes: MessageSubNodes<Pst>,
-    recipient_table: Option<Rc<dyn TableContext>>,
-    attachment_table: Option<Rc<dyn TableContext>>,
+    recipient_table: Option<Arc<dyn TableContext>>,
+    attachment_table: Option<Arc<dyn TableContext>>,
 }
 
 impl<Pst> MessageInner<Pst>
@@ -191,7 +473,7 @@ where
     <Pst as PstFile>::Store: StoreReadWrite<Pst>,
 {
     fn read(
-        store: Rc<<Pst as PstFile>::Store>,
+        store: Arc<<Pst as PstFile>::Store>,
         entry_id: &EntryId,
         prop_ids: Option<&[u16]>,
     ) -> io::Result<Self> {
@@ -228,14 +510,16 @@ where
             node_btree.find_entry(file, node_key, &mut page_cache)?
         };
 
-        Self::read_embedded(store, node, prop_ids)
+        Self::read_embedded(store, node, prop_ids, 0)
     }
 
     fn read_embedded(
-        store: Rc<<Pst as PstFile>::Store>,
+        store: Arc<<Pst as PstFile>::Store>,
         node: <Pst as PstFile>::NodeBTreeEntry,
         prop_ids: Option<&[u16]>,
+        depth: usize,
     ) -> io::Result<Self> {
+        let node_id = node.node();
         let pst = store.pst();
         let header = pst.header();
         let root = header.root();
@@ -280,7 +564,10 @@ where
                         .map(|value| (prop_id, value))
                 })
                 .collect::<io::Result<BTreeMap<_, _>>>()?;
-            let properties = MessageProperties { properties };
+            let properties = MessageProperties {
+                properties,
+                store_codepage: Some(store.properties().codepage()),
+            };
 
             let block = block_btree.find_entry(file, sub_node.search_key(), &mut page_cache)?;
             let sub_nodes = SubNodeTree::<Pst>::read(file, &block)?;
@@ -347,12 +634,42 @@ where
 
         Ok(Self {
             store,
+            message: Weak::new(),
+            node_id,
+            depth,
             properties,
             sub_nodes,
             recipient_table,
             attachment_table,
         })
     }
+
+    fn attachments<A>(
+        &self,
+        prop_ids: Option<&[u16]>,
+        max_depth: usize,
+    ) -> io::Result<Vec<Arc<dyn Attachment>>>
+    where
+        A: Attachment + AttachmentReadWrite<Pst> + 'static,
+    {
+        let Some(attachment_table) = self.attachment_table.as_ref() else {
+            return Ok(vec![]);
+        };
+        let message = self
+            .message
+            .upgrade()
+            .ok_or(MessagingError::MessageDropped)?;
+
+        attachment_table
+            .rows_iter()
+            .map(|row| {
+                let sub_node = NodeId::from(u32::from(row?.id()));
+                let attachment: Arc<dyn Attachment> =
+                    A::read(message.clone(), sub_node, prop_ids, max_depth)?;
+                Ok(attachment)
+            })
+            .collect()
+    }
 }
 
 pub type MessageSubNodes<Pst> = BTreeMap<NodeId, LeafSubNodeTreeEntry<<Pst as PstFile>::BlockId>>;
@@ -365,16 +682,44 @@ pub struct UnicodeMessage {
 
 impl UnicodeMessage {
     pub fn read(
-        store: Rc<UnicodeStore>,
+        store: Arc<UnicodeStore>,
         entry_id: &EntryId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>> {
+    ) -> io::Result<Arc<Self>> {
         <Self as MessageReadWrite<UnicodePstFile>>::read(store, entry_id, prop_ids)
     }
+
+    /// The [`NodeId`] of the message PC, or of its sub-node if it is embedded in an attachment.
+    pub fn node_id(&self) -> NodeId {
+        self.inner.node_id
+    }
+
+    /// Check for `MSGFLAG_READ` in `PR_MESSAGE_FLAGS`, as it was when the message was read, or
+    /// last marked as read or unread through this handle.
+    pub fn is_read(&self) -> LtpResult<bool> {
+        self.properties().is_read()
+    }
+
+    /// Replace the cached `PR_MESSAGE_FLAGS`, after an edit changed them in the store.
+    pub(crate) fn set_message_flags(&mut self, message_flags: i32) {
+        self.inner
+            .properties
+            .properties
+            .insert(PR_MESSAGE_FLAGS, PropertyValue::Integer32(message_flags));
+    }
+
+    fn new_cyclic(inner: MessageInner<UnicodePstFile>, message: &Weak<Self>) -> Self {
+        Self {
+            inner: MessageInner {
+                message: message.clone(),
+                ..inner
+            },
+        }
+    }
 }
 
 impl Message for UnicodeMessage {
-    fn store(&self) -> Rc<dyn Store> {
+    fn store(&self) -> Arc<dyn Store> {
         self.inner.store.clone()
     }
 
@@ -382,35 +727,64 @@ impl Message for UnicodeMessage {
         &self.inner.properties
     }
 
-    fn recipient_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn recipient_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.inner.recipient_table.as_ref()
     }
 
-    fn attachment_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn attachment_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.inner.attachment_table.as_ref()
     }
+
+    fn embedded_depth(&self) -> usize {
+        self.inner.depth
+    }
+
+    fn attachments_with_limit(
+        &self,
+        prop_ids: Option<&[u16]>,
+        max_depth: usize,
+    ) -> io::Result<Vec<Arc<dyn Attachment>>> {
+        self.inner
+            .attachments::<UnicodeAttachment>(prop_ids, max_depth)
+    }
+
+    /// Read each of the attachments in the attachment table, limiting embedded messages to the
+    /// [`Limits::max_embedded_message_depth`](crate::ndb::options::Limits::max_embedded_message_depth)
+    /// the file was opened with.
+    fn attachments(&self, prop_ids: Option<&[u16]>) -> io::Result<Vec<Arc<dyn Attachment>>> {
+        let max_depth = self
+            .inner
+            .store
+            .pst()
+            .reader()
+            .parse_options()
+            .limits
+            .max_embedded_message_depth;
+        self.attachments_with_limit(prop_ids, max_depth)
+    }
 }
 
 impl MessageReadWrite<UnicodePstFile> for UnicodeMessage {
     fn read(
-        store: Rc<UnicodeStore>,
+        store: Arc<UnicodeStore>,
         entry_id: &EntryId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>> {
+    ) -> io::Result<Arc<Self>> {
         let inner = MessageInner::read(store, entry_id, prop_ids)?;
-        Ok(Rc::new(Self { inner }))
+        Ok(Arc::new_cyclic(|message| Self::new_cyclic(inner, message)))
     }
 
     fn read_embedded(
-        store: Rc<UnicodeStore>,
+        store: Arc<UnicodeStore>,
         node: UnicodeNodeBTreeEntry,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>> {
-        let inner = MessageInner::read_embedded(store, node, prop_ids)?;
-        Ok(Rc::new(Self { inner }))
+        depth: usize,
+    ) -> io::Result<Arc<Self>> {
+        let inner = MessageInner::read_embedded(store, node, prop_ids, depth)?;
+        Ok(Arc::new_cyclic(|message| Self::new_cyclic(inner, message)))
     }
 
-    fn pst_store(&self) -> &Rc<UnicodeStore> {
+    fn pst_store(&self) -> &Arc<UnicodeStore> {
         &self.inner.store
     }
 
@@ -425,16 +799,30 @@ pub struct AnsiMessage {
 
 impl AnsiMessage {
     pub fn read(
-        store: Rc<AnsiStore>,
+        store: Arc<AnsiStore>,
         entry_id: &EntryId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>> {
+    ) -> io::Result<Arc<Self>> {
         <Self as MessageReadWrite<AnsiPstFile>>::read(store, entry_id, prop_ids)
     }
+
+    /// The [`NodeId`] of the message PC, or of its sub-node if it is embedded in an attachment.
+    pub fn node_id(&self) -> NodeId {
+        self.inner.node_id
+    }
+
+    fn new_cyclic(inner: MessageInner<AnsiPstFile>, message: &Weak<Self>) -> Self {
+        Self {
+            inner: MessageInner {
+                message: message.clone(),
+                ..inner
+            },
+        }
+    }
 }
 
 impl Message for AnsiMessage {
-    fn store(&self) -> Rc<dyn Store> {
+    fn store(&self) -> Arc<dyn Store> {
         self.inner.store.clone()
     }
 
@@ -442,35 +830,64 @@ impl Message for AnsiMessage {
         &self.inner.properties
     }
 
-    fn recipient_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn recipient_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.inner.recipient_table.as_ref()
     }
 
-    fn attachment_table(&self) -> Option<&Rc<dyn TableContext>> {
+    fn attachment_table(&self) -> Option<&Arc<dyn TableContext>> {
         self.inner.attachment_table.as_ref()
     }
+
+    fn embedded_depth(&self) -> usize {
+        self.inner.depth
+    }
+
+    fn attachments_with_limit(
+        &self,
+        prop_ids: Option<&[u16]>,
+        max_depth: usize,
+    ) -> io::Result<Vec<Arc<dyn Attachment>>> {
+        self.inner
+            .attachments::<AnsiAttachment>(prop_ids, max_depth)
+    }
+
+    /// Read each of the attachments in the attachment table, limiting embedded messages to the
+    /// [`Limits::max_embedded_message_depth`](crate::ndb::options::Limits::max_embedded_message_depth)
+    /// the file was opened with.
+    fn attachments(&self, prop_ids: Option<&[u16]>) -> io::Result<Vec<Arc<dyn Attachment>>> {
+        let max_depth = self
+            .inner
+            .store
+            .pst()
+            .reader()
+            .parse_options()
+            .limits
+            .max_embedded_message_depth;
+        self.attachments_with_limit(prop_ids, max_depth)
+    }
 }
 
 impl MessageReadWrite<AnsiPstFile> for AnsiMessage {
     fn read(
-        store: Rc<AnsiStore>,
+        store: Arc<AnsiStore>,
         entry_id: &EntryId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>> {
+    ) -> io::Result<Arc<Self>> {
         let inner = MessageInner::read(store, entry_id, prop_ids)?;
-        Ok(Rc::new(Self { inner }))
+        Ok(Arc::new_cyclic(|message| Self::new_cyclic(inner, message)))
     }
 
     fn read_embedded(
-        store: Rc<AnsiStore>,
+        store: Arc<AnsiStore>,
         node: AnsiNodeBTreeEntry,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>> {
-        let inner = MessageInner::read_embedded(store, node, prop_ids)?;
-        Ok(Rc::new(Self { inner }))
+        depth: usize,
+    ) -> io::Result<Arc<Self>> {
+        let inner = MessageInner::read_embedded(store, node, prop_ids, depth)?;
+        Ok(Arc::new_cyclic(|message| Self::new_cyclic(inner, message)))
     }
 
-    fn pst_store(&self) -> &Rc<AnsiStore> {
+    fn pst_store(&self) -> &Arc<AnsiStore> {
         &self.inner.store
     }
 
@@ -478,3 +895,234 @@ impl MessageReadWrite<AnsiPstFile> for AnsiMessage {
         &self.inner.sub_nodes
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ltp::{
+        prop_context::{BinaryValue, String8Value},
+        read_write::PropertyValueReadWrite,
+    };
+    use std::io::Cursor;
+
+    fn unicode(value: &str) -> PropertyValue {
+        let buffer: Vec<_> = value.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        PropertyValue::read(&mut Cursor::new(buffer), PropertyType::Unicode).unwrap()
+    }
+
+    fn binary(value: &[u8]) -> PropertyValue {
+        PropertyValue::Binary(BinaryValue::new(value.to_vec()))
+    }
+
+    /// [Example 1: Simple Compressed RTF](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxrtfcp/029bff74-8c00-402e-ac2b-0210a5f57371)
+    const COMPRESSED_SIMPLE_RTF: &[u8] = &[
+        0x2d, 0x00, 0x00, 0x00, 0x2b, 0x00, 0x00, 0x00, 0x4c, 0x5a, 0x46, 0x75, 0xf1, 0xc5, 0xc7,
+        0xa7, 0x03, 0x00, 0x0a, 0x00, 0x72, 0x63, 0x70, 0x67, 0x31, 0x32, 0x35, 0x42, 0x32, 0x0a,
+        0xf3, 0x20, 0x68, 0x65, 0x6c, 0x09, 0x00, 0x20, 0x62, 0x77, 0x05, 0xb0, 0x6c, 0x64, 0x7d,
+        0x0a, 0x80, 0x0f, 0xa0,
+    ];
+
+    fn best_body(
+        values: impl IntoIterator<Item = (u16, PropertyValue)>,
+    ) -> io::Result<Option<Body>> {
+        MessageProperties::from(values.into_iter().collect::<BTreeMap<_, _>>()).best_body()
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_named_fields() {
+        let properties = MessageProperties::from(BTreeMap::from([
+            (PR_SUBJECT, unicode("Hello")),
+            (PR_SENT_REPRESENTING_NAME, unicode("Sender")),
+            (PR_MESSAGE_FLAGS, PropertyValue::Integer32(MSGFLAG_READ)),
+            (
+                PR_MESSAGE_DELIVERY_TIME,
+                PropertyValue::Time(116_444_736_000_000_000),
+            ),
+        ]));
+        let json = serde_json::to_value(&properties).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "subject": "Hello",
+                "sender_name": "Sender",
+                "delivery_time": "1970-01-01T00:00:00Z",
+                "message_flags": MSGFLAG_READ,
+                "properties": {
+                    "0x0037": { "Unicode": "Hello" },
+                    "0x0042": { "Unicode": "Sender" },
+                    "0x0E06": { "Time": "1970-01-01T00:00:00Z" },
+                    "0x0E07": { "Integer32": MSGFLAG_READ },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_delivery_time() {
+        let delivery_time = |value| {
+            MessageProperties::from(BTreeMap::from([(PR_MESSAGE_DELIVERY_TIME, value)]))
+                .delivery_time()
+        };
+        assert_eq!(
+            delivery_time(PropertyValue::Time(116_444_736_000_000_000)).unwrap(),
+            Some(SystemTime::UNIX_EPOCH)
+        );
+        assert_eq!(delivery_time(PropertyValue::Time(-1)).unwrap(), None);
+        assert!(matches!(
+            delivery_time(PropertyValue::Integer32(0)),
+            Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Time,
+                actual: PropertyType::Integer32,
+            })
+        ));
+        assert_eq!(
+            MessageProperties::from(BTreeMap::new())
+                .delivery_time()
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_body_rtf() {
+        let body_rtf = |value| {
+            MessageProperties::from(BTreeMap::from([(PR_RTF_COMPRESSED, value)])).body_rtf()
+        };
+        assert_eq!(
+            body_rtf(binary(COMPRESSED_SIMPLE_RTF)).unwrap(),
+            Some(b"{\\rtf1\\ansi\\ansicpg1252\\pard hello world}\r\n".to_vec())
+        );
+        assert!(matches!(
+            body_rtf(PropertyValue::Integer32(0)),
+            Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Binary,
+                actual: PropertyType::Integer32,
+            })
+        ));
+
+        let mut corrupt = COMPRESSED_SIMPLE_RTF.to_vec();
+        corrupt[20] ^= 0xFF;
+        assert!(matches!(body_rtf(binary(&corrupt)), Err(LtpError::Io(_))));
+        assert_eq!(
+            MessageProperties::from(BTreeMap::new()).body_rtf().unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_get_string8_codepage() {
+        let string8 = |value: &[u8]| PropertyValue::String8(String8Value::new(value.to_vec()));
+        let properties = |values: Vec<(u16, PropertyValue)>, store_codepage| MessageProperties {
+            properties: values.into_iter().collect(),
+            store_codepage,
+        };
+
+        // PidTagMessageCodepage takes precedence over the code page of the store.
+        #[cfg(feature = "codepage")]
+        {
+            let french = properties(
+                vec![
+                    (PR_SUBJECT, string8(b"R\xE9sum\xE9")),
+                    (PR_MESSAGE_CODEPAGE, PropertyValue::Integer32(1252)),
+                ],
+                Some(932),
+            );
+            assert_eq!(
+                french.get_string8(PR_SUBJECT).unwrap().as_deref(),
+                Some("Résumé")
+            );
+
+            let japanese = properties(vec![(PR_SUBJECT, string8(b"\x93\xFA\x96\x7B"))], Some(932));
+            assert_eq!(
+                japanese.get_string8(PR_SUBJECT).unwrap().as_deref(),
+                Some("日本")
+            );
+        }
+
+        let unsupported = properties(vec![(PR_SUBJECT, string8(b"R\xE9sum\xE9"))], Some(12345));
+        assert!(matches!(
+            unsupported.get_string8(PR_SUBJECT),
+            Err(LtpError::UnsupportedCodePage(12345))
+        ));
+        assert_eq!(unsupported.get_string8(PR_BODY).unwrap(), None);
+    }
+
+    #[test]
+    fn test_best_body_precedence() {
+        let plain = (PR_BODY, unicode("hello world"));
+        let rtf = (PR_RTF_COMPRESSED, binary(COMPRESSED_SIMPLE_RTF));
+        let html = (PR_HTML_BODY, binary(b"<p>hello world</p>"));
+
+        assert_eq!(best_body([]).unwrap(), None);
+        assert_eq!(
+            best_body([plain.clone()]).unwrap(),
+            Some(Body::Plain("hello world".to_string()))
+        );
+        assert_eq!(
+            best_body([plain.clone(), rtf.clone()]).unwrap(),
+            Some(Body::Rtf(
+                b"{\\rtf1\\ansi\\ansicpg1252\\pard hello world}\r\n".to_vec()
+            ))
+        );
+        assert_eq!(
+            best_body([plain.clone(), rtf.clone(), html.clone()]).unwrap(),
+            Some(Body::Html("<p>hello world</p>".to_string()))
+        );
+
+        // PidTagNativeBody wins if the message has that body.
+        assert_eq!(
+            best_body([
+                plain.clone(),
+                html.clone(),
+                (PR_NATIVE_BODY, PropertyValue::Integer32(NATIVE_BODY_PLAIN)),
+            ])
+            .unwrap(),
+            Some(Body::Plain("hello world".to_string()))
+        );
+        assert!(matches!(
+            best_body([
+                html,
+                (PR_NATIVE_BODY, PropertyValue::Integer32(NATIVE_BODY_RTF)),
+            ])
+            .unwrap(),
+            Some(Body::Html(_))
+        ));
+    }
+
+    #[test]
+    fn test_best_body_html_codepage() {
+        let html = (PR_HTML_BODY, binary(b"<p>\xC1\xE3\xDC\xF0\xE7</p>"));
+        #[cfg(feature = "codepage")]
+        assert_eq!(
+            best_body([
+                html.clone(),
+                (PR_INTERNET_CPID, PropertyValue::Integer32(1253)),
+            ])
+            .unwrap(),
+            Some(Body::Html("<p>Αγάπη</p>".to_string()))
+        );
+
+        let err =
+            best_body([html, (PR_INTERNET_CPID, PropertyValue::Integer32(12345))]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            LtpError::UnsupportedCodePage(12345).to_string()
+        );
+    }
+
+    #[test]
+    fn test_best_body_empty() {
+        let err = best_body([(PR_BODY, unicode(""))]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            MessagingError::EmptyMessageBody(PR_BODY).to_string()
+        );
+
+        let err = best_body([(PR_HTML_BODY, binary(b""))]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            MessagingError::EmptyMessageBody(PR_HTML_BODY).to_string()
+        );
+    }
+}
@@ -4,12 +4,25 @@ use std::io;
 use thiserror::Error;
 
 pub mod attachment;
+pub mod calendar;
+pub mod edit;
+pub mod export;
 pub mod folder;
+pub mod items;
+pub mod merge;
 pub mod message;
 pub mod named_prop;
+pub mod note;
+pub mod property_ids;
+pub mod recipient;
+pub mod recurrence;
+pub mod rtf;
 pub mod search;
+pub mod sender;
 pub mod store;
+pub mod time;
 
+pub(crate) mod content_line;
 pub(crate) mod read_write;
 
 #[derive(Error, Debug)]
@@ -40,6 +53,8 @@ pub enum MessagingError {
     StoreDisplayNameNotFound,
     #[error("Invalid PidTagDisplayName on store: {0:?}")]
     InvalidStoreDisplayName(crate::ltp::prop_type::PropertyType),
+    #[error("Invalid PidTagPstPassword on store: {0:?}")]
+    InvalidStorePassword(crate::ltp::prop_type::PropertyType),
     #[error("Missing PidTagIpmSubTreeEntryId on store")]
     StoreIpmSubTreeEntryIdNotFound,
     #[error("Invalid PidTagIpmSubTreeEntryId on store: {0:?}")]
@@ -54,10 +69,28 @@ pub enum MessagingError {
     InvalidStoreFinderEntryId(crate::ltp::prop_type::PropertyType),
     #[error("EntryID in wrong store")]
     EntryIdWrongStore,
+    #[error("EntryID does not refer to a folder or message: 0x{0:08X}")]
+    UnresolvableEntryId(u32),
     #[error("Missing PidTagDisplayName on folder")]
     FolderDisplayNameNotFound,
     #[error("Invalid PidTagDisplayName on folder: {0:?}")]
     InvalidFolderDisplayName(crate::ltp::prop_type::PropertyType),
+    #[error("Invalid folder name: {0:?}")]
+    InvalidFolderName(String),
+    #[error("Folder already has a sub-folder named {0:?}")]
+    DuplicateFolderName(String),
+    #[error("Folder 0x{0:08X} still has messages or sub-folders")]
+    FolderNotEmpty(u32),
+    #[error("Cannot delete the root folder")]
+    DeleteRootFolder,
+    #[error("Cannot move folder 0x{0:08X} into itself or one of its sub-folders")]
+    FolderMoveCycle(u32),
+    #[error("Cannot edit a message which is embedded in an attachment")]
+    EditEmbeddedMessage,
+    #[error("Cannot edit a folder or message through a handle to a different store")]
+    EditWrongFile,
+    #[error("Cannot edit a PST file which is shared outside of its store")]
+    EditSharedFile,
     #[error("Missing PidTagContentCount on folder")]
     FolderContentCountNotFound,
     #[error("Invalid PidTagContentCount on folder: {0:?}")]
@@ -96,18 +129,34 @@ pub enum MessagingError {
     MessageLastModificationTimeNotFound,
     #[error("Invalid PidTagMessageLastModificationTime on message: {0:?}")]
     InvalidMessageLastModificationTime(crate::ltp::prop_type::PropertyType),
+    #[error("Empty body property 0x{0:04X} on message")]
+    EmptyMessageBody(u16),
+    #[error("Failed to decompress RTF: {0}")]
+    RtfDecompressionFailed(String),
     #[error("Missing PidTagMessageSearchKey on message")]
     MessageSearchKeyNotFound,
     #[error("Invalid PidTagMessageSearchKey on message: {0:?}")]
     InvalidMessageSearchKey(crate::ltp::prop_type::PropertyType),
     #[error("Invalid message EntryID NID_TYPE: {0:?}")]
     InvalidMessageEntryIdType(crate::ndb::node_id::NodeIdType),
+    #[error("Missing parent folder on message")]
+    MessageParentFolderNotFound,
     #[error("Missing Sub-Node Tree on message")]
     MessageSubNodeTreeNotFound,
     #[error("Multiple NID_TYPE_RECIPIENT_TABLE sub-nodes on message")]
     MultipleMessageRecipientTables,
     #[error("Multiple NID_TYPE_ATTACHMENT_TABLE sub-nodes on message")]
     MultipleMessageAttachmentTables,
+    #[error("Message has been dropped")]
+    MessageDropped,
+    #[error("Missing PidTagRecipientType on recipient")]
+    RecipientTypeNotFound,
+    #[error("Invalid PidTagRecipientType on recipient: {0:?}")]
+    InvalidRecipientType(crate::ltp::prop_type::PropertyType),
+    #[error("Unrecognized PidTagRecipientType on recipient: 0x{0:08X}")]
+    UnknownRecipientType(i32),
+    #[error("Invalid string property 0x{0:04X} on recipient: {1:?}")]
+    InvalidRecipientStringProperty(u16, crate::ltp::prop_type::PropertyType),
     #[error("Missing PidTagAttachSize on message")]
     AttachmentSizeNotFound,
     #[error("Invalid PidTagAttachSize on message: {0:?}")]
@@ -170,6 +219,15 @@ pub enum MessagingError {
     NamedPropertyMapBucketNotFound(u16),
     #[error("Invalid PidTagNameidBucketBase + hash on Named Property Lookup Map: {0:?}")]
     InvalidNamedPropertyMapBucket(crate::ltp::prop_type::PropertyType),
+    #[error("Invalid appointment property LID 0x{0:04X}: {1:?}")]
+    InvalidAppointmentProperty(u32, crate::ltp::prop_type::PropertyType),
+    #[error("Invalid PidLidAppointmentRecur: {0}")]
+    InvalidRecurrencePattern(#[from] recurrence::RecurrenceError),
+    #[error("Message class {actual:?} is not {expected}")]
+    UnexpectedMessageClass {
+        expected: &'static str,
+        actual: String,
+    },
     #[error("Invalid SUD wSUDType: 0x{0:04X}")]
     InvalidSearchUpdateType(u16),
     #[error("Invalid SUD queue offset: 0x{0:08X}")]
@@ -185,3 +243,112 @@ impl From<MessagingError> for io::Error {
 }
 
 pub type MessagingResult<T> = Result<T, MessagingError>;
+
+/// The code page named by `PidTagMessageCodepage`, or `PidTagInternetCodepage` if that is not
+/// set. Stores and messages both use these to say how their `PtypString8` values are encoded.
+///
+/// This reads the raw values, because [`TypedProperties::get_typed`] needs the code page.
+///
+/// [`TypedProperties::get_typed`]: crate::ltp::prop_context::TypedProperties::get_typed
+pub(crate) fn codepage_property(
+    properties: &impl crate::ltp::prop_context::TypedProperties,
+) -> Option<u32> {
+    use crate::ltp::prop_context::PropertyValue;
+
+    [
+        property_ids::PR_MESSAGE_CODEPAGE,
+        property_ids::PR_INTERNET_CPID,
+    ]
+    .into_iter()
+    .find_map(|prop_id| match properties.property_value(prop_id)? {
+        PropertyValue::Integer32(value) => u32::try_from(*value).ok(),
+        _ => None,
+    })
+}
+
+/// Serialize a property map with the property IDs formatted as hex strings, e.g. `"0x0037"`.
+#[cfg(feature = "serde")]
+pub(crate) fn serialize_properties<S>(
+    properties: &std::collections::BTreeMap<u16, crate::ltp::prop_context::PropertyValue>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_map(
+        properties
+            .iter()
+            .map(|(prop_id, value)| (format!("0x{prop_id:04X}"), value)),
+    )
+}
+
+/// Serialize a property object as a struct with a named field for each of the well known
+/// `fields`, followed by every property in a `properties` map from [`serialize_properties`]. Each
+/// field uses the first of its property IDs which is set, and fields with none of them are left
+/// out. `PtypString8` fields are decoded with `codepage`.
+#[cfg(feature = "serde")]
+pub(crate) fn serialize_named_properties<S: serde::ser::SerializeStruct>(
+    state: &mut S,
+    fields: &[(&'static str, &[u16])],
+    properties: &std::collections::BTreeMap<u16, crate::ltp::prop_context::PropertyValue>,
+    codepage: u32,
+) -> Result<(), S::Error> {
+    struct Properties<'a>(
+        &'a std::collections::BTreeMap<u16, crate::ltp::prop_context::PropertyValue>,
+    );
+
+    impl serde::Serialize for Properties<'_> {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            serialize_properties(self.0, serializer)
+        }
+    }
+
+    for (name, prop_ids) in fields {
+        match prop_ids.iter().find_map(|prop_id| properties.get(prop_id)) {
+            Some(value) => state.serialize_field(name, &PlainValue(value, codepage))?,
+            None => state.skip_field(name)?,
+        }
+    }
+    state.serialize_field("properties", &Properties(properties))
+}
+
+/// Serialize strings, numbers, booleans and times without the [`PropertyValue`] variant tag,
+/// so the named fields of [`serialize_named_properties`] read like plain JSON values.
+/// `PtypString8` values are decoded with the code page, or escaped like their
+/// [`Display`](std::fmt::Display) form if it is not supported.
+///
+/// [`PropertyValue`]: crate::ltp::prop_context::PropertyValue
+#[cfg(feature = "serde")]
+struct PlainValue<'a>(&'a crate::ltp::prop_context::PropertyValue, u32);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for PlainValue<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use crate::ltp::{
+            prop_context::{format_time, PropertyValue},
+            prop_type::decode_string8,
+        };
+
+        match self.0 {
+            PropertyValue::Integer16(value) => serializer.serialize_i16(*value),
+            PropertyValue::Integer32(value) => serializer.serialize_i32(*value),
+            PropertyValue::Integer64(value) => serializer.serialize_i64(*value),
+            PropertyValue::Boolean(value) => serializer.serialize_bool(*value),
+            PropertyValue::String8(value) => match decode_string8(value.buffer(), self.1) {
+                Ok(decoded) => serializer.serialize_str(&decoded),
+                Err(_) => serializer.collect_str(value),
+            },
+            PropertyValue::Unicode(value) => serializer.collect_str(value),
+            PropertyValue::Time(value) => serializer.serialize_str(&format_time(*value)),
+            PropertyValue::Guid(value) => serializer.collect_str(value),
+            PropertyValue::Binary(value) => serde::Serialize::serialize(value, serializer),
+            value => serde::Serialize::serialize(value, serializer),
+        }
+    }
+}
@@ -5,15 +5,20 @@ use std::{
     collections::BTreeMap,
     fmt::Display,
     io::{self, Cursor, Read, Seek, SeekFrom, Write},
-    rc::Rc,
+    sync::Arc,
 };
 
-use super::{read_write::*, store::*, *};
+use super::{
+    edit::{PropertyData, PropertyNode},
+    read_write::*,
+    store::*,
+    *,
+};
 use crate::{
     crc::compute_crc,
     ltp::{
         heap::HeapNode,
-        prop_context::{GuidValue, PropertyContext, PropertyValue},
+        prop_context::{BinaryValue, GuidValue, PropertyContext, PropertyValue, TypedProperties},
         prop_type::PropertyType,
         read_write::*,
     },
@@ -249,12 +254,49 @@ impl NamedPropReadWrite for NameIdEntry {
     }
 }
 
+/// A named property resolved from the [Named Property Lookup Map](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/e17e195d-0454-4b9b-b398-c9127a26a678).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NamedProperty {
+    Numeric { guid: GuidValue, id: u32 },
+    String { guid: GuidValue, name: String },
+}
+
+impl NamedProperty {
+    pub fn guid(&self) -> &GuidValue {
+        match self {
+            Self::Numeric { guid, .. } | Self::String { guid, .. } => guid,
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct NamedPropertyMapProperties {
     properties: BTreeMap<u16, PropertyValue>,
 }
 
 impl NamedPropertyMapProperties {
+    /// Decode the Named Property Lookup Map in a PC which is being edited, so the lookups see any
+    /// entries added by [`add_named_property`].
+    pub(crate) fn from_property_node(map: &PropertyNode) -> Self {
+        let properties = map
+            .properties
+            .iter()
+            .filter_map(|(prop_id, (prop_type, data))| {
+                let value = match (prop_type, data) {
+                    (PropertyType::Integer32, PropertyData::Small(value)) => {
+                        PropertyValue::Integer32(*value as i32)
+                    }
+                    (PropertyType::Binary, PropertyData::Bytes(data)) => {
+                        PropertyValue::Binary(BinaryValue::new(data.clone()))
+                    }
+                    _ => return None,
+                };
+                Some((*prop_id, value))
+            })
+            .collect();
+        Self { properties }
+    }
+
     pub fn get(&self, id: u16) -> Option<&PropertyValue> {
         self.properties.get(&id)
     }
@@ -426,6 +468,82 @@ impl NamedPropertyMapProperties {
         }
     }
 
+    fn resolve_guid(
+        guid: NamedPropertyGuid,
+        stream_guid: &[GuidValue],
+    ) -> MessagingResult<GuidValue> {
+        match guid {
+            NamedPropertyGuid::None => Ok(GuidValue::default()),
+            NamedPropertyGuid::Mapi => Ok(PS_MAPI),
+            NamedPropertyGuid::PublicStrings => Ok(PS_PUBLIC_STRINGS),
+            NamedPropertyGuid::GuidIndex(index) => {
+                stream_guid.get(usize::from(index)).copied().ok_or(
+                    MessagingError::NamedPropertyMapGuidIndexOutOfBounds(u16::from(guid)),
+                )
+            }
+        }
+    }
+
+    /// Resolve a named property ID (`0x8000` and above) to its property set GUID and name.
+    pub fn resolve_id(&self, prop_id: u16) -> io::Result<Option<NamedProperty>> {
+        if prop_id < 0x8000 {
+            return Ok(None);
+        }
+
+        let Some(entry) = self
+            .stream_entry()?
+            .into_iter()
+            .find(|entry| entry.prop_id() == prop_id)
+        else {
+            return Ok(None);
+        };
+
+        let guid = Self::resolve_guid(entry.guid(), &self.stream_guid()?)?;
+        Ok(Some(match entry.id() {
+            NamedPropertyId::Number(id) => NamedProperty::Numeric { guid, id },
+            NamedPropertyId::StringOffset(offset) => NamedProperty::String {
+                guid,
+                name: self.lookup_string(offset)?.to_string(),
+            },
+        }))
+    }
+
+    /// Find the property ID mapped to a numeric named property (a `LID`) in the `guid` property
+    /// set.
+    pub fn find_numeric_id(&self, guid: &GuidValue, id: u32) -> io::Result<Option<u16>> {
+        let stream_guid = self.stream_guid()?;
+
+        for entry in self.stream_entry()? {
+            if entry.id() != NamedPropertyId::Number(id) {
+                continue;
+            }
+            if Self::resolve_guid(entry.guid(), &stream_guid)? == *guid {
+                return Ok(Some(entry.prop_id()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Find the property ID mapped to a string named property in the `guid` property set.
+    pub fn find_id(&self, guid: &GuidValue, name: &str) -> io::Result<Option<u16>> {
+        let stream_guid = self.stream_guid()?;
+
+        for entry in self.stream_entry()? {
+            let NamedPropertyId::StringOffset(offset) = entry.id() else {
+                continue;
+            };
+            if Self::resolve_guid(entry.guid(), &stream_guid)? != *guid {
+                continue;
+            }
+            if self.lookup_string(offset)?.to_string() == name {
+                return Ok(Some(entry.prop_id()));
+            }
+        }
+
+        Ok(None)
+    }
+
     pub fn stream_string(&self) -> io::Result<Vec<StringEntry>> {
         let stream_string = self
             .properties
@@ -454,16 +572,134 @@ impl NamedPropertyMapProperties {
     }
 }
 
-pub trait NamedPropertyMap {
-    fn store(&self) -> Rc<dyn Store>;
+impl TypedProperties for NamedPropertyMapProperties {
+    fn property_value(&self, id: u16) -> Option<&PropertyValue> {
+        self.get(id)
+    }
+}
+
+/// Add `named_prop` to the Named Property Lookup Map in `map`, which must not have it already,
+/// and return the property ID it is mapped to. The GUID and name are appended to the GUID and
+/// string streams if they are not there yet, and the new entry is appended to the entry stream
+/// and to its hash bucket.
+pub(crate) fn add_named_property(
+    map: &mut PropertyNode,
+    named_prop: &NamedProperty,
+) -> io::Result<u16> {
+    let properties = NamedPropertyMapProperties::from_property_node(map);
+    let prop_index = properties
+        .stream_entry()?
+        .iter()
+        .map(|entry| u16::from(entry.prop_index) + 1)
+        .max()
+        .unwrap_or_default();
+    let prop_index = NamedPropertyIndex::try_from(prop_index)?;
+
+    let guid = match *named_prop.guid() {
+        PS_MAPI => NamedPropertyGuid::Mapi,
+        PS_PUBLIC_STRINGS => NamedPropertyGuid::PublicStrings,
+        guid => {
+            let stream_guid = properties.stream_guid()?;
+            let index = match stream_guid.iter().position(|existing| *existing == guid) {
+                Some(index) => index,
+                None => {
+                    let mut data = Vec::with_capacity(16);
+                    PropertyValue::Guid(guid).write(&mut data)?;
+                    append_stream(map, 0x0002, &data)?;
+                    stream_guid.len()
+                }
+            };
+            // wGuid holds the GUID index shifted left by one bit, after the 3 reserved values.
+            let index = u16::try_from(index).unwrap_or(u16::MAX);
+            if index >= 0x7FFD {
+                return Err(MessagingError::NamedPropertyMapGuidIndexOutOfBounds(index).into());
+            }
+            NamedPropertyGuid::GuidIndex(index)
+        }
+    };
+
+    let (id, hash_id) = match named_prop {
+        NamedProperty::Numeric { id, .. } => {
+            (NamedPropertyId::Number(*id), NamedPropertyId::Number(*id))
+        }
+        NamedProperty::String { name, .. } => {
+            let buffer: Vec<_> = name.encode_utf16().flat_map(u16::to_le_bytes).collect();
+            let hash_id = NamedPropertyId::StringOffset(compute_crc(0, &buffer));
+            let entry = StringEntry::new(
+                u32::try_from(buffer.len())
+                    .map_err(|_| MessagingError::NamedPropertyMapStringEntryOutOfBounds)?,
+                buffer,
+            )?;
+
+            // Each string starts on a 4-byte boundary.
+            let mut data = Vec::new();
+            entry.write(&mut data)?;
+            data.resize(data.len().next_multiple_of(4), 0);
+            let offset = append_stream(map, 0x0004, &data)?;
+            let offset = u32::try_from(offset)
+                .map_err(|_| MessagingError::NamedPropertyMapStringEntryOutOfBounds)?;
+            (NamedPropertyId::StringOffset(offset), hash_id)
+        }
+    };
+
+    let mut data = Vec::with_capacity(8);
+    NameIdEntry::new(id, guid, prop_index).write(&mut data)?;
+    append_stream(map, 0x0003, &data)?;
+
+    // The hash buckets have the CRC of the name in place of the string offset.
+    let hash_entry = NameIdEntry::new(hash_id, guid, prop_index);
+    let bucket_count = properties.bucket_count()?;
+    let bucket_offset = hash_entry.hash_value() % u32::from(bucket_count);
+    let mut data = Vec::with_capacity(8);
+    hash_entry.write(&mut data)?;
+    append_stream(map, 0x1000 + bucket_offset as u16, &data)?;
+
+    Ok(prop_index.prop_id())
+}
+
+/// Append `data` to the binary stream `prop_id` in `map`, and return the offset where it starts.
+fn append_stream(map: &mut PropertyNode, prop_id: u16, data: &[u8]) -> io::Result<usize> {
+    let stream = map.properties.entry(prop_id).or_insert((
+        PropertyType::Binary,
+        PropertyData::Bytes(Default::default()),
+    ));
+    match stream {
+        (PropertyType::Binary, PropertyData::Bytes(stream)) => {
+            let offset = stream.len();
+            stream.extend_from_slice(data);
+            Ok(offset)
+        }
+        (prop_type, _) => {
+            Err(MessagingError::InvalidNamedPropertyMapStreamEntry(*prop_type).into())
+        }
+    }
+}
+
+pub trait NamedPropertyMap: Send + Sync {
+    fn store(&self) -> Arc<dyn Store>;
     fn properties(&self) -> &NamedPropertyMapProperties;
+
+    /// See [`NamedPropertyMapProperties::resolve_id`].
+    fn resolve_id(&self, prop_id: u16) -> io::Result<Option<NamedProperty>> {
+        self.properties().resolve_id(prop_id)
+    }
+
+    /// See [`NamedPropertyMapProperties::find_numeric_id`].
+    fn find_numeric_id(&self, guid: &GuidValue, id: u32) -> io::Result<Option<u16>> {
+        self.properties().find_numeric_id(guid, id)
+    }
+
+    /// See [`NamedPropertyMapProperties::find_id`].
+    fn find_id(&self, guid: &GuidValue, name: &str) -> io::Result<Option<u16>> {
+        self.properties().find_id(guid, name)
+    }
 }
 
 struct NamedPropertyMapInner<Pst>
 where
     Pst: PstFile,
 {
-    store: Rc<Pst::Store>,
+    store: Arc<Pst::Store>,
     properties: NamedPropertyMapProperties,
 }
 
@@ -499,7 +735,7 @@ where
     <Pst as PstFile>::PropertyContext: PropertyContextReadWrite<Pst>,
     <Pst as PstFile>::Store: StoreReadWrite<Pst>,
 {
-    fn read(store: Rc<<Pst as PstFile>::Store>) -> io::Result<Self> {
+    fn read(store: Arc<<Pst as PstFile>::Store>) -> io::Result<Self> {
         let pst = store.pst();
         let header = pst.header();
         let root = header.root();
@@ -561,7 +797,7 @@ pub struct UnicodeNamedPropertyMap {
 }
 
 impl NamedPropertyMap for UnicodeNamedPropertyMap {
-    fn store(&self) -> Rc<dyn Store> {
+    fn store(&self) -> Arc<dyn Store> {
         self.inner.store.clone()
     }
 
@@ -571,9 +807,9 @@ impl NamedPropertyMap for UnicodeNamedPropertyMap {
 }
 
 impl NamedPropertyMapReadWrite<UnicodePstFile> for UnicodeNamedPropertyMap {
-    fn read(store: Rc<UnicodeStore>) -> io::Result<Rc<Self>> {
+    fn read(store: Arc<UnicodeStore>) -> io::Result<Arc<Self>> {
         let inner = NamedPropertyMapInner::read(store)?;
-        Ok(Rc::new(Self { inner }))
+        Ok(Arc::new(Self { inner }))
     }
 }
 
@@ -582,7 +818,7 @@ pub struct AnsiNamedPropertyMap {
 }
 
 impl NamedPropertyMap for AnsiNamedPropertyMap {
-    fn store(&self) -> Rc<dyn Store> {
+    fn store(&self) -> Arc<dyn Store> {
         self.inner.store.clone()
     }
 
@@ -592,8 +828,100 @@ impl NamedPropertyMap for AnsiNamedPropertyMap {
 }
 
 impl NamedPropertyMapReadWrite<AnsiPstFile> for AnsiNamedPropertyMap {
-    fn read(store: Rc<AnsiStore>) -> io::Result<Rc<Self>> {
+    fn read(store: Arc<AnsiStore>) -> io::Result<Arc<Self>> {
         let inner = NamedPropertyMapInner::read(store)?;
-        Ok(Rc::new(Self { inner }))
+        Ok(Arc::new(Self { inner }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ndb::block::UnicodeDataTree,
+        test_util::EMPTY_PST,
+        upgrade::{NodeReader, NodeSource},
+    };
+
+    #[test]
+    fn test_resolve_named_properties() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let named_props = store.named_property_map().unwrap();
+
+        assert_eq!(
+            named_props.resolve_id(0x800A).unwrap(),
+            Some(NamedProperty::String {
+                guid: PS_PUBLIC_STRINGS,
+                name: String::from("DRMLicense"),
+            })
+        );
+        assert_eq!(
+            named_props
+                .find_id(&PS_PUBLIC_STRINGS, "DRMLicense")
+                .unwrap(),
+            Some(0x800A)
+        );
+
+        let Some(NamedProperty::Numeric { guid, id }) = named_props.resolve_id(0x8010).unwrap()
+        else {
+            panic!("expected a numeric named property");
+        };
+        assert_eq!(guid.data1(), 0x00062008);
+        assert_eq!(id, 34064);
+
+        assert_eq!(
+            named_props.find_numeric_id(&guid, 34064).unwrap(),
+            Some(0x8010)
+        );
+        assert_eq!(named_props.find_numeric_id(&PS_MAPI, 34064).unwrap(), None);
+
+        assert_eq!(named_props.resolve_id(0x0037).unwrap(), None);
+        assert_eq!(named_props.resolve_id(0xFFFF).unwrap(), None);
+        assert_eq!(named_props.find_id(&PS_MAPI, "DRMLicense").unwrap(), None);
+    }
+
+    #[test]
+    fn test_add_named_property() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let node = NodeReader::new(&pst)
+            .unwrap()
+            .read_node(NID_NAME_TO_ID_MAP)
+            .unwrap();
+        let mut map = PropertyNode::read(&node, UnicodeDataTree::max_data_size()).unwrap();
+        let entries = NamedPropertyMapProperties::from_property_node(&map)
+            .stream_entry()
+            .unwrap()
+            .len();
+
+        let guid = GuidValue::new(0x12345678, 0x1234, 0x5678, [1, 2, 3, 4, 5, 6, 7, 8]);
+        let numeric = NamedProperty::Numeric { guid, id: 0x8001 };
+        let string = NamedProperty::String {
+            guid,
+            name: String::from("Added"),
+        };
+        let numeric_id = add_named_property(&mut map, &numeric).unwrap();
+        let string_id = add_named_property(&mut map, &string).unwrap();
+        assert_eq!(string_id, numeric_id + 1);
+
+        let properties = NamedPropertyMapProperties::from_property_node(&map);
+        assert_eq!(properties.stream_entry().unwrap().len(), entries + 2);
+        assert_eq!(properties.resolve_id(numeric_id).unwrap(), Some(numeric));
+        assert_eq!(properties.resolve_id(string_id).unwrap(), Some(string));
+        assert_eq!(
+            properties.find_numeric_id(&guid, 0x8001).unwrap(),
+            Some(numeric_id)
+        );
+        assert_eq!(properties.find_id(&guid, "Added").unwrap(), Some(string_id));
+
+        // Both entries use the same new GUID, and the numeric one is in its hash bucket.
+        assert_eq!(properties.stream_guid().unwrap().last(), Some(&guid));
+        let entry = properties
+            .stream_entry()
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.prop_id() == numeric_id)
+            .unwrap();
+        assert!(properties.hash_bucket(&entry).unwrap().contains(&entry));
     }
 }
@@ -0,0 +1,291 @@
+//! ## Note Objects
+//!
+//! Sticky notes are described in [MS-OXONOTE](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxonote).
+
+use std::{
+    io::{self, Write},
+    sync::Arc,
+};
+
+use super::{items::*, message::*, named_prop::*, property_ids::*, store::*};
+use crate::{
+    ltp::prop_context::{GuidValue, TypedProperties},
+    ndb::node_id::NodeId,
+};
+
+/// `PSETID_Note`
+pub const PSETID_NOTE: GuidValue = GuidValue::new(
+    0x0006200E,
+    0x0000,
+    0x0000,
+    [0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+);
+
+/// `PidLidNoteColor`
+pub const PID_LID_NOTE_COLOR: u32 = 0x8B00;
+/// `PidLidNoteWidth`
+pub const PID_LID_NOTE_WIDTH: u32 = 0x8B02;
+/// `PidLidNoteHeight`
+pub const PID_LID_NOTE_HEIGHT: u32 = 0x8B03;
+/// `PidLidNoteX`
+pub const PID_LID_NOTE_X: u32 = 0x8B04;
+/// `PidLidNoteY`
+pub const PID_LID_NOTE_Y: u32 = 0x8B05;
+
+/// The `PSETID_Note` named properties, resolved to the property IDs used in one store.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct NotePropertyIds {
+    color: Option<u16>,
+    width: Option<u16>,
+    height: Option<u16>,
+    left: Option<u16>,
+    top: Option<u16>,
+}
+
+impl NotePropertyIds {
+    pub fn resolve(named_props: &dyn NamedPropertyMap) -> io::Result<Self> {
+        let find = |lid| named_props.find_numeric_id(&PSETID_NOTE, lid);
+        Ok(Self {
+            color: find(PID_LID_NOTE_COLOR)?,
+            width: find(PID_LID_NOTE_WIDTH)?,
+            height: find(PID_LID_NOTE_HEIGHT)?,
+            left: find(PID_LID_NOTE_X)?,
+            top: find(PID_LID_NOTE_Y)?,
+        })
+    }
+
+    pub fn color(&self) -> Option<u16> {
+        self.color
+    }
+
+    pub fn width(&self) -> Option<u16> {
+        self.width
+    }
+
+    pub fn height(&self) -> Option<u16> {
+        self.height
+    }
+
+    pub fn left(&self) -> Option<u16> {
+        self.left
+    }
+
+    pub fn top(&self) -> Option<u16> {
+        self.top
+    }
+}
+
+/// `PidLidNoteColor`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteColor {
+    Blue,
+    Green,
+    Pink,
+    Yellow,
+    White,
+    Unknown(i32),
+}
+
+impl From<i32> for NoteColor {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Self::Blue,
+            1 => Self::Green,
+            2 => Self::Pink,
+            3 => Self::Yellow,
+            4 => Self::White,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+/// A sticky note, with the `PSETID_Note` named properties looked up through the store's
+/// [`NamedPropertyMap`].
+pub struct NoteObject {
+    message: Arc<dyn Message>,
+    subject: Option<String>,
+    body: Option<String>,
+    color: Option<NoteColor>,
+    width: Option<i32>,
+    height: Option<i32>,
+    left: Option<i32>,
+    top: Option<i32>,
+}
+
+impl NoteObject {
+    pub fn open(store: &dyn Store, node_id: NodeId) -> io::Result<Self> {
+        let prop_ids = NotePropertyIds::resolve(&*store.named_property_map()?)?;
+        let entry_id = store.properties().make_entry_id(node_id)?;
+        let message = store.open_message(&entry_id, None)?;
+        Self::read(message, &prop_ids)
+    }
+
+    /// Read the note properties from a message which is already open, using `prop_ids`
+    /// resolved from the same store. Fails with [`MessagingError::UnexpectedMessageClass`] if
+    /// the message is not an [`IPM_STICKY_NOTE`].
+    ///
+    /// [`MessagingError::UnexpectedMessageClass`]: super::MessagingError::UnexpectedMessageClass
+    pub fn read(message: Arc<dyn Message>, prop_ids: &NotePropertyIds) -> io::Result<Self> {
+        let properties = message.properties();
+        check_message_class(properties, IPM_STICKY_NOTE)?;
+
+        let subject = properties.get_string8(PR_SUBJECT)?;
+        let body = properties.get_string8(PR_BODY)?;
+        let color = get_named::<i32>(properties, prop_ids.color)?.map(NoteColor::from);
+        let width = get_named(properties, prop_ids.width)?;
+        let height = get_named(properties, prop_ids.height)?;
+        let left = get_named(properties, prop_ids.left)?;
+        let top = get_named(properties, prop_ids.top)?;
+
+        Ok(Self {
+            message,
+            subject,
+            body,
+            color,
+            width,
+            height,
+            left,
+            top,
+        })
+    }
+
+    pub fn message(&self) -> &Arc<dyn Message> {
+        &self.message
+    }
+
+    /// `PidTagSubject`, which Outlook sets to the first line of the body.
+    pub fn subject(&self) -> Option<&str> {
+        self.subject.as_deref()
+    }
+
+    /// `PidTagBody`
+    pub fn body(&self) -> Option<&str> {
+        self.body.as_deref()
+    }
+
+    /// `PidLidNoteColor`
+    pub fn color(&self) -> Option<NoteColor> {
+        self.color
+    }
+
+    /// `PidLidNoteWidth`, in pixels.
+    pub fn width(&self) -> Option<i32> {
+        self.width
+    }
+
+    /// `PidLidNoteHeight`, in pixels.
+    pub fn height(&self) -> Option<i32> {
+        self.height
+    }
+
+    /// `PidLidNoteX`, the distance in pixels from the left edge of the screen to the note window.
+    pub fn left(&self) -> Option<i32> {
+        self.left
+    }
+
+    /// `PidLidNoteY`, the distance in pixels from the top edge of the screen to the note window.
+    pub fn top(&self) -> Option<i32> {
+        self.top
+    }
+
+    /// Write the subject and body as `Subject: ...` and `Body: ...` lines. Missing values are
+    /// written as empty strings.
+    pub fn to_plain_text(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "Subject: {}", self.subject().unwrap_or_default())?;
+        writeln!(writer, "Body: {}", self.body().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ltp::prop_context::PropertyValue,
+        messaging::{
+            items::tests::{message, unicode},
+            MessagingError,
+        },
+        ndb::node_id::NodeIdType,
+        test_util::EMPTY_PST,
+        UnicodePstFile,
+    };
+
+    #[test]
+    fn test_open_note() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+
+        let prop_ids = NotePropertyIds::resolve(&*store.named_property_map().unwrap()).unwrap();
+        for prop_id in [
+            prop_ids.color(),
+            prop_ids.width(),
+            prop_ids.height(),
+            prop_ids.left(),
+            prop_ids.top(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            assert!(prop_id >= 0x8000);
+        }
+
+        let missing = NodeId::new(NodeIdType::NormalMessage, 0x7FFF).unwrap();
+        assert!(NoteObject::open(store.as_ref(), missing).is_err());
+    }
+
+    #[test]
+    fn test_read_note() {
+        let prop_ids = NotePropertyIds {
+            color: Some(0x8001),
+            width: Some(0x8002),
+            height: Some(0x8003),
+            left: Some(0x8004),
+            top: None,
+        };
+        let note = message(
+            "IPM.StickyNote",
+            [
+                (PR_SUBJECT, unicode("Groceries")),
+                (PR_BODY, unicode("Groceries\r\nMilk")),
+                (0x8001, PropertyValue::Integer32(3)),
+                (0x8002, PropertyValue::Integer32(200)),
+                (0x8003, PropertyValue::Integer32(166)),
+                (0x8004, PropertyValue::Integer32(40)),
+            ],
+        );
+        let note = NoteObject::read(note, &prop_ids).unwrap();
+        assert_eq!(note.subject(), Some("Groceries"));
+        assert_eq!(note.body(), Some("Groceries\r\nMilk"));
+        assert_eq!(note.color(), Some(NoteColor::Yellow));
+        assert_eq!(note.width(), Some(200));
+        assert_eq!(note.height(), Some(166));
+        assert_eq!(note.left(), Some(40));
+        assert_eq!(note.top(), None);
+
+        let mut text = Vec::new();
+        note.to_plain_text(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "Subject: Groceries\nBody: Groceries\r\nMilk\n"
+        );
+
+        let unknown = message("IPM.StickyNote", [(0x8001, PropertyValue::Integer32(9))]);
+        let unknown = NoteObject::read(unknown, &prop_ids).unwrap();
+        assert_eq!(unknown.color(), Some(NoteColor::Unknown(9)));
+
+        let invalid = message("IPM.StickyNote", [(0x8002, unicode("200px"))]);
+        assert!(NoteObject::read(invalid, &prop_ids).is_err());
+
+        let email = message("IPM.Note", []);
+        let err = NoteObject::read(email, &prop_ids).err().unwrap();
+        let err = err
+            .into_inner()
+            .unwrap()
+            .downcast::<MessagingError>()
+            .unwrap();
+        assert!(matches!(
+            *err,
+            MessagingError::UnexpectedMessageClass { expected, .. } if expected == IPM_STICKY_NOTE
+        ));
+    }
+}
@@ -0,0 +1,521 @@
+//! Well-known [MAPI property identifiers](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxprops/f6ab1613-aefe-447d-a49c-18217230b148)
+//! which show up in PST files.
+
+use std::fmt::{Debug, Display};
+
+/// `PidTagExpiryTime`
+pub const PR_EXPIRY_TIME: u16 = 0x0015;
+/// `PidTagImportance`
+pub const PR_IMPORTANCE: u16 = 0x0017;
+/// `PidTagMessageClass`
+pub const PR_MESSAGE_CLASS: u16 = 0x001A;
+/// `PidTagOriginatorDeliveryReportRequested`
+pub const PR_ORIGINATOR_DELIVERY_REPORT_REQUESTED: u16 = 0x0023;
+/// `PidTagPriority`
+pub const PR_PRIORITY: u16 = 0x0026;
+/// `PidTagReadReceiptRequested`
+pub const PR_READ_RECEIPT_REQUESTED: u16 = 0x0029;
+/// `PidTagSensitivity`
+pub const PR_SENSITIVITY: u16 = 0x0036;
+/// `PidTagSubject`
+pub const PR_SUBJECT: u16 = 0x0037;
+/// `PidTagClientSubmitTime`
+pub const PR_CLIENT_SUBMIT_TIME: u16 = 0x0039;
+/// `PidTagSentRepresentingSearchKey`
+pub const PR_SENT_REPRESENTING_SEARCH_KEY: u16 = 0x003B;
+/// `PidTagSubjectPrefix`
+pub const PR_SUBJECT_PREFIX: u16 = 0x003D;
+/// `PidTagReceivedByEntryId`
+pub const PR_RECEIVED_BY_ENTRYID: u16 = 0x003F;
+/// `PidTagReceivedByName`
+pub const PR_RECEIVED_BY_NAME: u16 = 0x0040;
+/// `PidTagSentRepresentingEntryId`
+pub const PR_SENT_REPRESENTING_ENTRYID: u16 = 0x0041;
+/// `PidTagSentRepresentingName`
+pub const PR_SENT_REPRESENTING_NAME: u16 = 0x0042;
+/// `PidTagReceivedRepresentingEntryId`
+pub const PR_RCVD_REPRESENTING_ENTRYID: u16 = 0x0043;
+/// `PidTagReceivedRepresentingName`
+pub const PR_RCVD_REPRESENTING_NAME: u16 = 0x0044;
+/// `PidTagOriginalSubject`
+pub const PR_ORIGINAL_SUBJECT: u16 = 0x0049;
+/// `PidTagReplyRecipientEntries`
+pub const PR_REPLY_RECIPIENT_ENTRIES: u16 = 0x004F;
+/// `PidTagReplyRecipientNames`
+pub const PR_REPLY_RECIPIENT_NAMES: u16 = 0x0050;
+/// `PidTagReceivedBySearchKey`
+pub const PR_RECEIVED_BY_SEARCH_KEY: u16 = 0x0051;
+/// `PidTagSentRepresentingAddressType`
+pub const PR_SENT_REPRESENTING_ADDRTYPE: u16 = 0x0064;
+/// `PidTagSentRepresentingEmailAddress`
+pub const PR_SENT_REPRESENTING_EMAIL_ADDRESS: u16 = 0x0065;
+/// `PidTagConversationTopic`
+pub const PR_CONVERSATION_TOPIC: u16 = 0x0070;
+/// `PidTagConversationIndex`
+pub const PR_CONVERSATION_INDEX: u16 = 0x0071;
+/// `PidTagReceivedByAddressType`
+pub const PR_RECEIVED_BY_ADDRTYPE: u16 = 0x0075;
+/// `PidTagReceivedByEmailAddress`
+pub const PR_RECEIVED_BY_EMAIL_ADDRESS: u16 = 0x0076;
+/// `PidTagTransportMessageHeaders`
+pub const PR_TRANSPORT_MESSAGE_HEADERS: u16 = 0x007D;
+/// `PidTagRecipientType`
+pub const PR_RECIPIENT_TYPE: u16 = 0x0C15;
+/// `PidTagSenderEntryId`
+pub const PR_SENDER_ENTRYID: u16 = 0x0C19;
+/// `PidTagSenderName`
+pub const PR_SENDER_NAME: u16 = 0x0C1A;
+/// `PidTagSenderSearchKey`
+pub const PR_SENDER_SEARCH_KEY: u16 = 0x0C1D;
+/// `PidTagSenderAddressType`
+pub const PR_SENDER_ADDRTYPE: u16 = 0x0C1E;
+/// `PidTagSenderEmailAddress`
+pub const PR_SENDER_EMAIL_ADDRESS: u16 = 0x0C1F;
+/// `PidTagDisplayBcc`
+pub const PR_DISPLAY_BCC: u16 = 0x0E02;
+/// `PidTagDisplayCc`
+pub const PR_DISPLAY_CC: u16 = 0x0E03;
+/// `PidTagDisplayTo`
+pub const PR_DISPLAY_TO: u16 = 0x0E04;
+/// `PidTagMessageDeliveryTime`
+pub const PR_MESSAGE_DELIVERY_TIME: u16 = 0x0E06;
+/// `PidTagMessageFlags`
+pub const PR_MESSAGE_FLAGS: u16 = 0x0E07;
+/// `PidTagMessageSize`
+pub const PR_MESSAGE_SIZE: u16 = 0x0E08;
+/// `PidTagParentEntryId`
+pub const PR_PARENT_ENTRYID: u16 = 0x0E09;
+/// `PidTagResponsibility`
+pub const PR_RESPONSIBILITY: u16 = 0x0E0F;
+/// `PidTagMessageRecipients`
+pub const PR_MESSAGE_RECIPIENTS: u16 = 0x0E12;
+/// `PidTagMessageAttachments`
+pub const PR_MESSAGE_ATTACHMENTS: u16 = 0x0E13;
+/// `PidTagMessageStatus`
+pub const PR_MESSAGE_STATUS: u16 = 0x0E17;
+/// `PidTagHasAttachments`
+pub const PR_HASATTACH: u16 = 0x0E1B;
+/// `PidTagNormalizedSubject`
+pub const PR_NORMALIZED_SUBJECT: u16 = 0x0E1D;
+/// `PidTagRtfInSync`
+pub const PR_RTF_IN_SYNC: u16 = 0x0E1F;
+/// `PidTagAttachSize`
+pub const PR_ATTACH_SIZE: u16 = 0x0E20;
+/// `PidTagAttachNumber`
+pub const PR_ATTACH_NUM: u16 = 0x0E21;
+/// `PidTagAccess`
+pub const PR_ACCESS: u16 = 0x0FF4;
+/// `PidTagInstanceKey`
+pub const PR_INSTANCE_KEY: u16 = 0x0FF6;
+/// `PidTagAccessLevel`
+pub const PR_ACCESS_LEVEL: u16 = 0x0FF7;
+/// `PidTagRecordKey`
+pub const PR_RECORD_KEY: u16 = 0x0FF9;
+/// `PidTagStoreRecordKey`
+pub const PR_STORE_RECORD_KEY: u16 = 0x0FFA;
+/// `PidTagObjectType`
+pub const PR_OBJECT_TYPE: u16 = 0x0FFE;
+/// `PidTagEntryId`
+pub const PR_ENTRYID: u16 = 0x0FFF;
+/// `PidTagBody`
+pub const PR_BODY: u16 = 0x1000;
+/// `PidTagRtfSyncBodyCrc`
+pub const PR_RTF_SYNC_BODY_CRC: u16 = 0x1006;
+/// `PidTagRtfSyncBodyCount`
+pub const PR_RTF_SYNC_BODY_COUNT: u16 = 0x1007;
+/// `PidTagRtfSyncBodyTag`
+pub const PR_RTF_SYNC_BODY_TAG: u16 = 0x1008;
+/// `PidTagRtfCompressed`
+pub const PR_RTF_COMPRESSED: u16 = 0x1009;
+/// `PidTagHtml`
+pub const PR_HTML_BODY: u16 = 0x1013;
+/// `PidTagNativeBody`
+pub const PR_NATIVE_BODY: u16 = 0x1016;
+/// `PidTagInternetMessageId`
+pub const PR_INTERNET_MESSAGE_ID: u16 = 0x1035;
+/// `PidTagInternetReferences`
+pub const PR_INTERNET_REFERENCES: u16 = 0x1039;
+/// `PidTagInReplyToId`
+pub const PR_IN_REPLY_TO_ID: u16 = 0x1042;
+/// `PidTagIconIndex`
+pub const PR_ICON_INDEX: u16 = 0x1080;
+/// `PidTagAttributeHidden`
+pub const PR_ATTR_HIDDEN: u16 = 0x10F4;
+/// `PidTagRowid`
+pub const PR_ROWID: u16 = 0x3000;
+/// `PidTagDisplayName`
+pub const PR_DISPLAY_NAME: u16 = 0x3001;
+/// `PidTagAddressType`
+pub const PR_ADDRTYPE: u16 = 0x3002;
+/// `PidTagEmailAddress`
+pub const PR_EMAIL_ADDRESS: u16 = 0x3003;
+/// `PidTagComment`
+pub const PR_COMMENT: u16 = 0x3004;
+/// `PidTagCreationTime`
+pub const PR_CREATION_TIME: u16 = 0x3007;
+/// `PidTagLastModificationTime`
+pub const PR_LAST_MODIFICATION_TIME: u16 = 0x3008;
+/// `PidTagSearchKey`
+pub const PR_SEARCH_KEY: u16 = 0x300B;
+/// `PidTagValidFolderMask`
+pub const PR_VALID_FOLDER_MASK: u16 = 0x35DF;
+/// `PidTagIpmSubTreeEntryId`
+pub const PR_IPM_SUBTREE_ENTRYID: u16 = 0x35E0;
+/// `PidTagIpmWastebasketEntryId`
+pub const PR_IPM_WASTEBASKET_ENTRYID: u16 = 0x35E3;
+/// `PidTagFinderEntryId`
+pub const PR_FINDER_ENTRYID: u16 = 0x35E7;
+/// `PidTagFolderType`
+pub const PR_FOLDER_TYPE: u16 = 0x3601;
+/// `PidTagContentCount`
+pub const PR_CONTENT_COUNT: u16 = 0x3602;
+/// `PidTagContentUnreadCount`
+pub const PR_CONTENT_UNREAD: u16 = 0x3603;
+/// `PidTagSubfolders`
+pub const PR_SUBFOLDERS: u16 = 0x360A;
+/// `PidTagContainerClass`
+pub const PR_CONTAINER_CLASS: u16 = 0x3613;
+/// `PidTagAttachDataBinary`
+pub const PR_ATTACH_DATA_BIN: u16 = 0x3701;
+/// `PidTagAttachEncoding`
+pub const PR_ATTACH_ENCODING: u16 = 0x3702;
+/// `PidTagAttachExtension`
+pub const PR_ATTACH_EXTENSION: u16 = 0x3703;
+/// `PidTagAttachFilename`
+pub const PR_ATTACH_FILENAME: u16 = 0x3704;
+/// `PidTagAttachMethod`
+pub const PR_ATTACH_METHOD: u16 = 0x3705;
+/// `PidTagAttachLongFilename`
+pub const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+/// `PidTagAttachPathname`
+pub const PR_ATTACH_PATHNAME: u16 = 0x3708;
+/// `PidTagAttachRendering`
+pub const PR_ATTACH_RENDERING: u16 = 0x3709;
+/// `PidTagAttachTag`
+pub const PR_ATTACH_TAG: u16 = 0x370A;
+/// `PidTagRenderingPosition`
+pub const PR_RENDERING_POSITION: u16 = 0x370B;
+/// `PidTagAttachLongPathname`
+pub const PR_ATTACH_LONG_PATHNAME: u16 = 0x370D;
+/// `PidTagAttachMimeTag`
+pub const PR_ATTACH_MIME_TAG: u16 = 0x370E;
+/// `PidTagAttachContentId`
+pub const PR_ATTACH_CONTENT_ID: u16 = 0x3712;
+/// `PidTagAttachContentLocation`
+pub const PR_ATTACH_CONTENT_LOCATION: u16 = 0x3713;
+/// `PidTagAttachFlags`
+pub const PR_ATTACH_FLAGS: u16 = 0x3714;
+/// `PidTagDisplayType`
+pub const PR_DISPLAY_TYPE: u16 = 0x3900;
+/// `PidTagSmtpAddress`
+pub const PR_SMTP_ADDRESS: u16 = 0x39FE;
+/// `PidTagAccount`
+pub const PR_ACCOUNT: u16 = 0x3A00;
+/// `PidTagGivenName`
+pub const PR_GIVEN_NAME: u16 = 0x3A06;
+/// `PidTagBusinessTelephoneNumber`
+pub const PR_BUSINESS_TELEPHONE_NUMBER: u16 = 0x3A08;
+/// `PidTagHomeTelephoneNumber`
+pub const PR_HOME_TELEPHONE_NUMBER: u16 = 0x3A09;
+/// `PidTagSurname`
+pub const PR_SURNAME: u16 = 0x3A11;
+/// `PidTagCompanyName`
+pub const PR_COMPANY_NAME: u16 = 0x3A16;
+/// `PidTagTitle`
+pub const PR_TITLE: u16 = 0x3A17;
+/// `PidTagMobileTelephoneNumber`
+pub const PR_MOBILE_TELEPHONE_NUMBER: u16 = 0x3A1C;
+/// `PidTagCountry`
+pub const PR_COUNTRY: u16 = 0x3A26;
+/// `PidTagLocality`
+pub const PR_LOCALITY: u16 = 0x3A27;
+/// `PidTagStateOrProvince`
+pub const PR_STATE_OR_PROVINCE: u16 = 0x3A28;
+/// `PidTagStreetAddress`
+pub const PR_STREET_ADDRESS: u16 = 0x3A29;
+/// `PidTagPostalCode`
+pub const PR_POSTAL_CODE: u16 = 0x3A2A;
+/// `PidTagPostOfficeBox`
+pub const PR_POST_OFFICE_BOX: u16 = 0x3A2B;
+/// `PidTagInternetCodepage`
+pub const PR_INTERNET_CPID: u16 = 0x3FDE;
+/// `PidTagCreatorName`
+pub const PR_CREATOR_NAME: u16 = 0x3FF8;
+/// `PidTagLastModifierName`
+pub const PR_LAST_MODIFIER_NAME: u16 = 0x3FFA;
+/// `PidTagMessageCodepage`
+pub const PR_MESSAGE_CODEPAGE: u16 = 0x3FFD;
+/// `PidTagSenderSmtpAddress`
+pub const PR_SENDER_SMTP_ADDRESS: u16 = 0x5D01;
+/// `PidTagSentRepresentingSmtpAddress`
+pub const PR_SENT_REPRESENTING_SMTP_ADDRESS: u16 = 0x5D02;
+/// `PidTagRecipientDisplayName`
+pub const PR_RECIPIENT_DISPLAY_NAME: u16 = 0x5FF6;
+/// `PidTagRecipientEntryId`
+pub const PR_RECIPIENT_ENTRYID: u16 = 0x5FF7;
+/// `PidTagRecipientFlags`
+pub const PR_RECIPIENT_FLAGS: u16 = 0x5FFD;
+/// `PidTagRecipientTrackStatus`
+pub const PR_RECIPIENT_TRACK_STATUS: u16 = 0x5FFF;
+/// `PidTagPstHiddenCount`
+pub const PR_PST_HIDDEN_COUNT: u16 = 0x6635;
+/// `PidTagPstHiddenUnread`
+pub const PR_PST_HIDDEN_UNREAD: u16 = 0x6636;
+/// `PidTagLtpRowId`
+pub const PR_LTP_ROW_ID: u16 = 0x67F2;
+/// `PidTagLtpRowVer`
+pub const PR_LTP_ROW_VER: u16 = 0x67F3;
+/// `PidTagPstPassword`
+pub const PR_PST_PASSWORD: u16 = 0x67FF;
+
+/// `MSGFLAG_READ` in `PidTagMessageFlags`
+pub const MSGFLAG_READ: i32 = 0x0000_0001;
+/// `MSGFLAG_HASATTACH` in `PidTagMessageFlags`
+pub const MSGFLAG_HASATTACH: i32 = 0x0000_0010;
+
+/// `MAPI_MAILUSER` in `PidTagObjectType`
+pub const MAPI_MAILUSER: i32 = 0x0000_0006;
+/// `MAPI_ATTACH` in `PidTagObjectType`
+pub const MAPI_ATTACH: i32 = 0x0000_0007;
+
+/// `DT_MAILUSER` in `PidTagDisplayType`
+pub const DT_MAILUSER: i32 = 0x0000_0000;
+
+/// Wrapper for a property ID which displays the symbolic name of well-known properties.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PropertyTag(u16);
+
+impl PropertyTag {
+    pub const fn new(prop_id: u16) -> Self {
+        Self(prop_id)
+    }
+
+    pub const fn prop_id(&self) -> u16 {
+        self.0
+    }
+
+    /// Get the symbolic name of the property, e.g. `PR_SUBJECT`, if it is one of the constants
+    /// defined in this module.
+    pub fn name(&self) -> Option<&'static str> {
+        let name = match self.0 {
+            PR_EXPIRY_TIME => "PR_EXPIRY_TIME",
+            PR_IMPORTANCE => "PR_IMPORTANCE",
+            PR_MESSAGE_CLASS => "PR_MESSAGE_CLASS",
+            PR_ORIGINATOR_DELIVERY_REPORT_REQUESTED => "PR_ORIGINATOR_DELIVERY_REPORT_REQUESTED",
+            PR_PRIORITY => "PR_PRIORITY",
+            PR_READ_RECEIPT_REQUESTED => "PR_READ_RECEIPT_REQUESTED",
+            PR_SENSITIVITY => "PR_SENSITIVITY",
+            PR_SUBJECT => "PR_SUBJECT",
+            PR_CLIENT_SUBMIT_TIME => "PR_CLIENT_SUBMIT_TIME",
+            PR_SENT_REPRESENTING_SEARCH_KEY => "PR_SENT_REPRESENTING_SEARCH_KEY",
+            PR_SUBJECT_PREFIX => "PR_SUBJECT_PREFIX",
+            PR_RECEIVED_BY_ENTRYID => "PR_RECEIVED_BY_ENTRYID",
+            PR_RECEIVED_BY_NAME => "PR_RECEIVED_BY_NAME",
+            PR_SENT_REPRESENTING_ENTRYID => "PR_SENT_REPRESENTING_ENTRYID",
+            PR_SENT_REPRESENTING_NAME => "PR_SENT_REPRESENTING_NAME",
+            PR_RCVD_REPRESENTING_ENTRYID => "PR_RCVD_REPRESENTING_ENTRYID",
+            PR_RCVD_REPRESENTING_NAME => "PR_RCVD_REPRESENTING_NAME",
+            PR_ORIGINAL_SUBJECT => "PR_ORIGINAL_SUBJECT",
+            PR_REPLY_RECIPIENT_ENTRIES => "PR_REPLY_RECIPIENT_ENTRIES",
+            PR_REPLY_RECIPIENT_NAMES => "PR_REPLY_RECIPIENT_NAMES",
+            PR_RECEIVED_BY_SEARCH_KEY => "PR_RECEIVED_BY_SEARCH_KEY",
+            PR_SENT_REPRESENTING_ADDRTYPE => "PR_SENT_REPRESENTING_ADDRTYPE",
+            PR_SENT_REPRESENTING_EMAIL_ADDRESS => "PR_SENT_REPRESENTING_EMAIL_ADDRESS",
+            PR_CONVERSATION_TOPIC => "PR_CONVERSATION_TOPIC",
+            PR_CONVERSATION_INDEX => "PR_CONVERSATION_INDEX",
+            PR_RECEIVED_BY_ADDRTYPE => "PR_RECEIVED_BY_ADDRTYPE",
+            PR_RECEIVED_BY_EMAIL_ADDRESS => "PR_RECEIVED_BY_EMAIL_ADDRESS",
+            PR_TRANSPORT_MESSAGE_HEADERS => "PR_TRANSPORT_MESSAGE_HEADERS",
+            PR_RECIPIENT_TYPE => "PR_RECIPIENT_TYPE",
+            PR_SENDER_ENTRYID => "PR_SENDER_ENTRYID",
+            PR_SENDER_NAME => "PR_SENDER_NAME",
+            PR_SENDER_SEARCH_KEY => "PR_SENDER_SEARCH_KEY",
+            PR_SENDER_ADDRTYPE => "PR_SENDER_ADDRTYPE",
+            PR_SENDER_EMAIL_ADDRESS => "PR_SENDER_EMAIL_ADDRESS",
+            PR_DISPLAY_BCC => "PR_DISPLAY_BCC",
+            PR_DISPLAY_CC => "PR_DISPLAY_CC",
+            PR_DISPLAY_TO => "PR_DISPLAY_TO",
+            PR_MESSAGE_DELIVERY_TIME => "PR_MESSAGE_DELIVERY_TIME",
+            PR_MESSAGE_FLAGS => "PR_MESSAGE_FLAGS",
+            PR_MESSAGE_SIZE => "PR_MESSAGE_SIZE",
+            PR_PARENT_ENTRYID => "PR_PARENT_ENTRYID",
+            PR_RESPONSIBILITY => "PR_RESPONSIBILITY",
+            PR_MESSAGE_RECIPIENTS => "PR_MESSAGE_RECIPIENTS",
+            PR_MESSAGE_ATTACHMENTS => "PR_MESSAGE_ATTACHMENTS",
+            PR_MESSAGE_STATUS => "PR_MESSAGE_STATUS",
+            PR_HASATTACH => "PR_HASATTACH",
+            PR_NORMALIZED_SUBJECT => "PR_NORMALIZED_SUBJECT",
+            PR_RTF_IN_SYNC => "PR_RTF_IN_SYNC",
+            PR_ATTACH_SIZE => "PR_ATTACH_SIZE",
+            PR_ATTACH_NUM => "PR_ATTACH_NUM",
+            PR_ACCESS => "PR_ACCESS",
+            PR_INSTANCE_KEY => "PR_INSTANCE_KEY",
+            PR_ACCESS_LEVEL => "PR_ACCESS_LEVEL",
+            PR_RECORD_KEY => "PR_RECORD_KEY",
+            PR_STORE_RECORD_KEY => "PR_STORE_RECORD_KEY",
+            PR_OBJECT_TYPE => "PR_OBJECT_TYPE",
+            PR_ENTRYID => "PR_ENTRYID",
+            PR_BODY => "PR_BODY",
+            PR_RTF_SYNC_BODY_CRC => "PR_RTF_SYNC_BODY_CRC",
+            PR_RTF_SYNC_BODY_COUNT => "PR_RTF_SYNC_BODY_COUNT",
+            PR_RTF_SYNC_BODY_TAG => "PR_RTF_SYNC_BODY_TAG",
+            PR_RTF_COMPRESSED => "PR_RTF_COMPRESSED",
+            PR_HTML_BODY => "PR_HTML_BODY",
+            PR_NATIVE_BODY => "PR_NATIVE_BODY",
+            PR_INTERNET_MESSAGE_ID => "PR_INTERNET_MESSAGE_ID",
+            PR_INTERNET_REFERENCES => "PR_INTERNET_REFERENCES",
+            PR_IN_REPLY_TO_ID => "PR_IN_REPLY_TO_ID",
+            PR_ICON_INDEX => "PR_ICON_INDEX",
+            PR_ATTR_HIDDEN => "PR_ATTR_HIDDEN",
+            PR_ROWID => "PR_ROWID",
+            PR_DISPLAY_NAME => "PR_DISPLAY_NAME",
+            PR_ADDRTYPE => "PR_ADDRTYPE",
+            PR_EMAIL_ADDRESS => "PR_EMAIL_ADDRESS",
+            PR_COMMENT => "PR_COMMENT",
+            PR_CREATION_TIME => "PR_CREATION_TIME",
+            PR_LAST_MODIFICATION_TIME => "PR_LAST_MODIFICATION_TIME",
+            PR_SEARCH_KEY => "PR_SEARCH_KEY",
+            PR_VALID_FOLDER_MASK => "PR_VALID_FOLDER_MASK",
+            PR_IPM_SUBTREE_ENTRYID => "PR_IPM_SUBTREE_ENTRYID",
+            PR_IPM_WASTEBASKET_ENTRYID => "PR_IPM_WASTEBASKET_ENTRYID",
+            PR_FINDER_ENTRYID => "PR_FINDER_ENTRYID",
+            PR_FOLDER_TYPE => "PR_FOLDER_TYPE",
+            PR_CONTENT_COUNT => "PR_CONTENT_COUNT",
+            PR_CONTENT_UNREAD => "PR_CONTENT_UNREAD",
+            PR_SUBFOLDERS => "PR_SUBFOLDERS",
+            PR_CONTAINER_CLASS => "PR_CONTAINER_CLASS",
+            PR_ATTACH_DATA_BIN => "PR_ATTACH_DATA_BIN",
+            PR_ATTACH_ENCODING => "PR_ATTACH_ENCODING",
+            PR_ATTACH_EXTENSION => "PR_ATTACH_EXTENSION",
+            PR_ATTACH_FILENAME => "PR_ATTACH_FILENAME",
+            PR_ATTACH_METHOD => "PR_ATTACH_METHOD",
+            PR_ATTACH_LONG_FILENAME => "PR_ATTACH_LONG_FILENAME",
+            PR_ATTACH_PATHNAME => "PR_ATTACH_PATHNAME",
+            PR_ATTACH_RENDERING => "PR_ATTACH_RENDERING",
+            PR_ATTACH_TAG => "PR_ATTACH_TAG",
+            PR_RENDERING_POSITION => "PR_RENDERING_POSITION",
+            PR_ATTACH_LONG_PATHNAME => "PR_ATTACH_LONG_PATHNAME",
+            PR_ATTACH_MIME_TAG => "PR_ATTACH_MIME_TAG",
+            PR_ATTACH_CONTENT_ID => "PR_ATTACH_CONTENT_ID",
+            PR_ATTACH_CONTENT_LOCATION => "PR_ATTACH_CONTENT_LOCATION",
+            PR_ATTACH_FLAGS => "PR_ATTACH_FLAGS",
+            PR_DISPLAY_TYPE => "PR_DISPLAY_TYPE",
+            PR_SMTP_ADDRESS => "PR_SMTP_ADDRESS",
+            PR_ACCOUNT => "PR_ACCOUNT",
+            PR_GIVEN_NAME => "PR_GIVEN_NAME",
+            PR_BUSINESS_TELEPHONE_NUMBER => "PR_BUSINESS_TELEPHONE_NUMBER",
+            PR_HOME_TELEPHONE_NUMBER => "PR_HOME_TELEPHONE_NUMBER",
+            PR_SURNAME => "PR_SURNAME",
+            PR_COMPANY_NAME => "PR_COMPANY_NAME",
+            PR_TITLE => "PR_TITLE",
+            PR_MOBILE_TELEPHONE_NUMBER => "PR_MOBILE_TELEPHONE_NUMBER",
+            PR_COUNTRY => "PR_COUNTRY",
+            PR_LOCALITY => "PR_LOCALITY",
+            PR_STATE_OR_PROVINCE => "PR_STATE_OR_PROVINCE",
+            PR_STREET_ADDRESS => "PR_STREET_ADDRESS",
+            PR_POSTAL_CODE => "PR_POSTAL_CODE",
+            PR_POST_OFFICE_BOX => "PR_POST_OFFICE_BOX",
+            PR_INTERNET_CPID => "PR_INTERNET_CPID",
+            PR_CREATOR_NAME => "PR_CREATOR_NAME",
+            PR_LAST_MODIFIER_NAME => "PR_LAST_MODIFIER_NAME",
+            PR_MESSAGE_CODEPAGE => "PR_MESSAGE_CODEPAGE",
+            PR_SENDER_SMTP_ADDRESS => "PR_SENDER_SMTP_ADDRESS",
+            PR_SENT_REPRESENTING_SMTP_ADDRESS => "PR_SENT_REPRESENTING_SMTP_ADDRESS",
+            PR_RECIPIENT_DISPLAY_NAME => "PR_RECIPIENT_DISPLAY_NAME",
+            PR_RECIPIENT_ENTRYID => "PR_RECIPIENT_ENTRYID",
+            PR_RECIPIENT_FLAGS => "PR_RECIPIENT_FLAGS",
+            PR_RECIPIENT_TRACK_STATUS => "PR_RECIPIENT_TRACK_STATUS",
+            PR_PST_HIDDEN_COUNT => "PR_PST_HIDDEN_COUNT",
+            PR_PST_HIDDEN_UNREAD => "PR_PST_HIDDEN_UNREAD",
+            PR_LTP_ROW_ID => "PR_LTP_ROW_ID",
+            PR_LTP_ROW_VER => "PR_LTP_ROW_VER",
+            PR_PST_PASSWORD => "PR_PST_PASSWORD",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+impl From<u16> for PropertyTag {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+impl From<PropertyTag> for u16 {
+    fn from(value: PropertyTag) -> Self {
+        value.0
+    }
+}
+
+impl Display for PropertyTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "0x{:04X}", self.0),
+        }
+    }
+}
+
+impl Debug for PropertyTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "PropertyTag {{ {name}: 0x{:04X} }}", self.0),
+            None => write!(f, "PropertyTag {{ 0x{:04X} }}", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_property_ids() {
+        // Values from [MS-OXPROPS] section 2.
+        assert_eq!(PR_MESSAGE_CLASS, 0x001A);
+        assert_eq!(PR_SUBJECT, 0x0037);
+        assert_eq!(PR_CLIENT_SUBMIT_TIME, 0x0039);
+        assert_eq!(PR_SENDER_NAME, 0x0C1A);
+        assert_eq!(PR_SENDER_EMAIL_ADDRESS, 0x0C1F);
+        assert_eq!(PR_DISPLAY_TO, 0x0E04);
+        assert_eq!(PR_MESSAGE_DELIVERY_TIME, 0x0E06);
+        assert_eq!(PR_MESSAGE_FLAGS, 0x0E07);
+        assert_eq!(PR_MESSAGE_SIZE, 0x0E08);
+        assert_eq!(PR_HASATTACH, 0x0E1B);
+        assert_eq!(PR_ATTACH_SIZE, 0x0E20);
+        assert_eq!(PR_RECORD_KEY, 0x0FF9);
+        assert_eq!(PR_ENTRYID, 0x0FFF);
+        assert_eq!(PR_BODY, 0x1000);
+        assert_eq!(PR_RTF_COMPRESSED, 0x1009);
+        assert_eq!(PR_HTML_BODY, 0x1013);
+        assert_eq!(PR_DISPLAY_NAME, 0x3001);
+        assert_eq!(PR_CREATION_TIME, 0x3007);
+        assert_eq!(PR_LAST_MODIFICATION_TIME, 0x3008);
+        assert_eq!(PR_SEARCH_KEY, 0x300B);
+        assert_eq!(PR_IPM_SUBTREE_ENTRYID, 0x35E0);
+        assert_eq!(PR_CONTENT_COUNT, 0x3602);
+        assert_eq!(PR_CONTENT_UNREAD, 0x3603);
+        assert_eq!(PR_SUBFOLDERS, 0x360A);
+        assert_eq!(PR_ATTACH_DATA_BIN, 0x3701);
+        assert_eq!(PR_ATTACH_METHOD, 0x3705);
+        assert_eq!(PR_ATTACH_LONG_FILENAME, 0x3707);
+        assert_eq!(PR_STREET_ADDRESS, 0x3A29);
+        assert_eq!(PR_POSTAL_CODE, 0x3A2A);
+        assert_eq!(PR_SENDER_SMTP_ADDRESS, 0x5D01);
+        assert_eq!(PR_SENT_REPRESENTING_SMTP_ADDRESS, 0x5D02);
+        assert_eq!(PR_ATTACH_MIME_TAG, 0x370E);
+        assert_eq!(PR_MESSAGE_CODEPAGE, 0x3FFD);
+        assert_eq!(PR_LTP_ROW_ID, 0x67F2);
+        assert_eq!(PR_PST_PASSWORD, 0x67FF);
+    }
+
+    #[test]
+    fn test_property_tag_display() {
+        assert_eq!(PropertyTag::new(PR_SUBJECT).to_string(), "PR_SUBJECT");
+        assert_eq!(PropertyTag::from(0x0E1B).to_string(), "PR_HASATTACH");
+        assert_eq!(PropertyTag::from(0x7FFF).to_string(), "0x7FFF");
+        assert_eq!(PropertyTag::from(0x0037).name(), Some("PR_SUBJECT"));
+        assert_eq!(PropertyTag::from(0x8000).name(), None);
+    }
+}
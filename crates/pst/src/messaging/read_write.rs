@@ -12,16 +12,17 @@ pub trait StoreReadWrite<Pst>: Store + Sized
 where
     Pst: PstFile,
 {
-    fn pst(&self) -> &Pst;
-    fn node_btree(&self) -> &PstFileReadWriteNodeBTree<Pst>;
-    fn block_btree(&self) -> &PstFileReadWriteBlockBTree<Pst>;
+    fn pst(&self) -> Arc<Pst>;
+    fn node_btree(&self) -> Arc<PstFileReadWriteNodeBTree<Pst>>;
+    fn block_btree(&self) -> Arc<PstFileReadWriteBlockBTree<Pst>>;
 }
 
 pub trait FolderReadWrite<Pst>: Folder + Sized
 where
     Pst: PstFile,
 {
-    fn read(store: Rc<Pst::Store>, entry_id: &EntryId) -> io::Result<Rc<Self>>;
+    fn read(store: Arc<Pst::Store>, entry_id: &EntryId) -> io::Result<Arc<Self>>;
+    fn pst_store(&self) -> &Arc<Pst::Store>;
 }
 
 pub trait MessageReadWrite<Pst>: Message + Sized
@@ -29,16 +30,17 @@ where
     Pst: PstFile,
 {
     fn read(
-        store: Rc<Pst::Store>,
+        store: Arc<Pst::Store>,
         entry_id: &EntryId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>>;
+    ) -> io::Result<Arc<Self>>;
     fn read_embedded(
-        store: Rc<Pst::Store>,
+        store: Arc<Pst::Store>,
         node: Pst::NodeBTreeEntry,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>>;
-    fn pst_store(&self) -> &Rc<Pst::Store>;
+        depth: usize,
+    ) -> io::Result<Arc<Self>>;
+    fn pst_store(&self) -> &Arc<Pst::Store>;
     fn sub_nodes(&self) -> &MessageSubNodes<Pst>;
 }
 
@@ -47,10 +49,11 @@ where
     Pst: PstFile,
 {
     fn read(
-        message: Rc<Pst::Message>,
+        message: Arc<Pst::Message>,
         sub_node: NodeId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<Self>>;
+        max_depth: usize,
+    ) -> io::Result<Arc<Self>>;
 }
 
 pub trait NamedPropReadWrite: Sized {
@@ -62,7 +65,7 @@ pub trait NamedPropertyMapReadWrite<Pst>: NamedPropertyMap + Sized
 where
     Pst: PstFile,
 {
-    fn read(store: Rc<Pst::Store>) -> io::Result<Rc<Self>>;
+    fn read(store: Arc<Pst::Store>) -> io::Result<Arc<Self>>;
 }
 
 pub trait SearchReadWrite: Sized {
@@ -74,5 +77,5 @@ pub trait SearchUpdateQueueReadWrite<Pst>: SearchUpdateQueue + Sized
 where
     Pst: PstFile,
 {
-    fn read(store: Rc<Pst::Store>) -> io::Result<Rc<Self>>;
+    fn read(store: Arc<Pst::Store>) -> io::Result<Arc<Self>>;
 }
@@ -0,0 +1,309 @@
+//! ## Recipient Table
+//!
+//! The recipients of a message are stored in the `NID_TYPE_RECIPIENT_TABLE` sub-node, which is a
+//! [`TableContext`] with one row per recipient.
+
+use std::{collections::BTreeMap, io, sync::Arc};
+
+use super::{property_ids::*, *};
+use crate::ltp::{
+    prop_context::PropertyValue,
+    prop_type::{decode_string8_or_latin1, PropertyType},
+    table_context::{TableContext, TableRowId},
+};
+
+/// `MAPI_P1`: The recipient did not successfully receive the message on the previous attempt.
+pub const MAPI_P1: i32 = 0x1000_0000;
+/// `MAPI_SUBMITTED`: The recipient has already received the message successfully.
+pub const MAPI_SUBMITTED: i32 = 0x8000_0000_u32 as i32;
+
+/// `PidTagRecipientType`
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecipientType {
+    /// `MAPI_ORIG`: The recipient is the message originator.
+    Originator = 0x00000000,
+    /// `MAPI_TO`: The recipient is a primary (To) recipient.
+    To = 0x00000001,
+    /// `MAPI_CC`: The recipient is a carbon copy (Cc) recipient.
+    Cc = 0x00000002,
+    /// `MAPI_BCC`: The recipient is a blind carbon copy (Bcc) recipient.
+    Bcc = 0x00000003,
+}
+
+impl TryFrom<i32> for RecipientType {
+    type Error = MessagingError;
+
+    /// The [`MAPI_P1`] and [`MAPI_SUBMITTED`] flags are ignored.
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value & !(MAPI_P1 | MAPI_SUBMITTED) {
+            0x00000000 => Ok(Self::Originator),
+            0x00000001 => Ok(Self::To),
+            0x00000002 => Ok(Self::Cc),
+            0x00000003 => Ok(Self::Bcc),
+            _ => Err(MessagingError::UnknownRecipientType(value)),
+        }
+    }
+}
+
+/// `PidTagAddressType`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum AddressType {
+    /// `SMTP`: An Internet email address.
+    Smtp,
+    /// `EX`: An Exchange (X.500) distinguished name.
+    Exchange,
+    /// `X400`: An X.400 address.
+    X400,
+    Other(String),
+}
+
+impl From<String> for AddressType {
+    fn from(value: String) -> Self {
+        match value.to_ascii_uppercase().as_str() {
+            "SMTP" => Self::Smtp,
+            "EX" => Self::Exchange,
+            "X400" => Self::X400,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+/// A single row in the recipient table.
+#[derive(Clone, Debug)]
+pub struct RecipientEntry {
+    row_id: TableRowId,
+    recipient_type: RecipientType,
+    properties: BTreeMap<u16, PropertyValue>,
+    codepage: u32,
+}
+
+impl RecipientEntry {
+    /// Build an entry from the values read from one row of the recipient table, decoding
+    /// `PtypString8` values with `codepage`.
+    pub fn new(
+        row_id: TableRowId,
+        values: impl IntoIterator<Item = (u16, PropertyValue)>,
+        codepage: u32,
+    ) -> io::Result<Self> {
+        let properties: BTreeMap<_, _> = values.into_iter().collect();
+        let recipient_type = properties
+            .get(&PR_RECIPIENT_TYPE)
+            .ok_or(MessagingError::RecipientTypeNotFound)?;
+        let recipient_type = match recipient_type {
+            PropertyValue::Integer32(value) => RecipientType::try_from(*value)?,
+            invalid => {
+                return Err(
+                    MessagingError::InvalidRecipientType(PropertyType::from(invalid)).into(),
+                )
+            }
+        };
+
+        Ok(Self {
+            row_id,
+            recipient_type,
+            properties,
+            codepage,
+        })
+    }
+
+    pub fn row_id(&self) -> TableRowId {
+        self.row_id
+    }
+
+    pub fn recipient_type(&self) -> RecipientType {
+        self.recipient_type
+    }
+
+    /// `PidTagDisplayName`
+    pub fn display_name(&self) -> io::Result<Option<String>> {
+        self.get_string(PR_DISPLAY_NAME)
+    }
+
+    /// `PidTagEmailAddress`, which is a distinguished name rather than an SMTP address if
+    /// [`Self::address_type`] is [`AddressType::Exchange`].
+    pub fn email_address(&self) -> io::Result<Option<String>> {
+        self.get_string(PR_EMAIL_ADDRESS)
+    }
+
+    /// `PidTagAddressType`
+    pub fn address_type(&self) -> io::Result<Option<AddressType>> {
+        Ok(self.get_string(PR_ADDRTYPE)?.map(AddressType::from))
+    }
+
+    pub fn get(&self, prop_id: u16) -> Option<&PropertyValue> {
+        self.properties.get(&prop_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&u16, &PropertyValue)> {
+        self.properties.iter()
+    }
+
+    /// Get a string column, decoding `PtypString8` values with the message code page, or Latin-1
+    /// if that is not supported. Returns `None` if the column is not set in this row.
+    pub fn get_string(&self, prop_id: u16) -> io::Result<Option<String>> {
+        match self.properties.get(&prop_id) {
+            None => Ok(None),
+            Some(PropertyValue::String8(value)) => Ok(Some(decode_string8_or_latin1(
+                value.buffer(),
+                self.codepage,
+            ))),
+            Some(PropertyValue::Unicode(value)) => Ok(Some(value.to_string())),
+            Some(invalid) => Err(MessagingError::InvalidRecipientStringProperty(
+                prop_id,
+                PropertyType::from(invalid),
+            )
+            .into()),
+        }
+    }
+}
+
+/// The recipients of a message, see [`Message::recipients`](super::message::Message::recipients).
+pub struct RecipientTable {
+    table: Option<Arc<dyn TableContext>>,
+    codepage: u32,
+}
+
+impl RecipientTable {
+    pub fn new(table: Option<Arc<dyn TableContext>>, codepage: u32) -> Self {
+        Self { table, codepage }
+    }
+
+    pub fn table(&self) -> Option<&Arc<dyn TableContext>> {
+        self.table.as_ref()
+    }
+
+    /// Read the recipients one row at a time. A message without a recipient table has no
+    /// recipients.
+    pub fn iter(&self) -> impl Iterator<Item = io::Result<RecipientEntry>> + '_ {
+        self.table.iter().flat_map(move |table| {
+            table.rows_iter().map(move |row| {
+                let row = row?;
+                let values = table.row_values(&row)?;
+                RecipientEntry::new(row.id(), values, self.codepage)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ltp::read_write::PropertyValueReadWrite;
+    use std::io::Cursor;
+
+    fn unicode(value: &str) -> PropertyValue {
+        let buffer: Vec<_> = value.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        PropertyValue::read(&mut Cursor::new(buffer), PropertyType::Unicode).unwrap()
+    }
+
+    fn string8(value: &[u8]) -> PropertyValue {
+        PropertyValue::read(&mut Cursor::new(value.to_vec()), PropertyType::String8).unwrap()
+    }
+
+    fn recipient(
+        id: u32,
+        recipient_type: i32,
+        name: PropertyValue,
+        address_type: &str,
+        address: &str,
+    ) -> io::Result<RecipientEntry> {
+        RecipientEntry::new(
+            TableRowId::new(id),
+            [
+                (PR_RECIPIENT_TYPE, PropertyValue::Integer32(recipient_type)),
+                (PR_DISPLAY_NAME, name),
+                (PR_ADDRTYPE, unicode(address_type)),
+                (PR_EMAIL_ADDRESS, unicode(address)),
+            ],
+            1252,
+        )
+    }
+
+    #[test]
+    fn test_recipient_entries() {
+        let entries = [
+            recipient(1, 1, unicode("Alice"), "SMTP", "alice@example.com"),
+            recipient(2, 1, string8(b"Bj\xF6rn"), "smtp", "bjorn@example.com"),
+            recipient(3, 2, unicode("Carol"), "EX", "/o=Example/cn=Carol"),
+            recipient(
+                4,
+                2 | MAPI_SUBMITTED,
+                unicode("Dan"),
+                "X400",
+                "c=US;a= ;p=Example",
+            ),
+            recipient(5, 3, unicode("Eve"), "FAX", "+1 555 0100"),
+        ]
+        .into_iter()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.recipient_type(),
+                    entry.display_name().unwrap().unwrap(),
+                    entry.address_type().unwrap().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (RecipientType::To, "Alice".to_string(), AddressType::Smtp),
+                (RecipientType::To, "Björn".to_string(), AddressType::Smtp),
+                (
+                    RecipientType::Cc,
+                    "Carol".to_string(),
+                    AddressType::Exchange
+                ),
+                (RecipientType::Cc, "Dan".to_string(), AddressType::X400),
+                (
+                    RecipientType::Bcc,
+                    "Eve".to_string(),
+                    AddressType::Other("FAX".to_string())
+                ),
+            ]
+        );
+        assert_eq!(
+            entries[0].email_address().unwrap().as_deref(),
+            Some("alice@example.com")
+        );
+        assert_eq!(entries[2].row_id(), TableRowId::new(3));
+    }
+
+    #[test]
+    fn test_invalid_recipient_type() {
+        let err = recipient(1, 4, unicode("Frank"), "SMTP", "frank@example.com").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            MessagingError::UnknownRecipientType(4).to_string()
+        );
+
+        let err = RecipientEntry::new(TableRowId::new(1), [], 1252).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            MessagingError::RecipientTypeNotFound.to_string()
+        );
+
+        let err = RecipientEntry::new(
+            TableRowId::new(1),
+            [(PR_RECIPIENT_TYPE, unicode("To"))],
+            1252,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            MessagingError::InvalidRecipientType(PropertyType::Unicode).to_string()
+        );
+    }
+
+    #[test]
+    fn test_empty_recipient_table() {
+        let recipients = RecipientTable::new(None, 1252);
+        assert!(recipients.table().is_none());
+        assert_eq!(recipients.iter().count(), 0);
+    }
+}
@@ -0,0 +1,1202 @@
+//! ## Appointment Recurrence Patterns
+//!
+//! `PidLidAppointmentRecur` holds an `AppointmentRecurrencePattern`, described in
+//! [MS-OXOCAL](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxocal/cf7153b4-f8b5-4cb6-bbb7-dc2bae2ef4b8).
+//! It wraps a `RecurrencePattern` with the time of day of each instance and the list of modified
+//! instances. All of the dates and times in the blob are in the appointment's local time zone,
+//! stored as minutes since January 1, 1601.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::{
+    io::Cursor,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+use super::time::*;
+
+#[derive(Error, Debug)]
+pub enum RecurrenceError {
+    #[error("Recurrence pattern is truncated at offset {0}")]
+    Truncated(u64),
+    #[error("Invalid RecurrencePattern ReaderVersion: 0x{0:04X}")]
+    InvalidReaderVersion(u16),
+    #[error("Invalid RecurrencePattern WriterVersion: 0x{0:04X}")]
+    InvalidWriterVersion(u16),
+    #[error("Invalid AppointmentRecurrencePattern ReaderVersion2: 0x{0:08X}")]
+    InvalidReaderVersion2(u32),
+    #[error("Invalid AppointmentRecurrencePattern WriterVersion2: 0x{0:08X}")]
+    InvalidWriterVersion2(u32),
+    #[error("Invalid RecurFrequency: 0x{0:04X}")]
+    InvalidRecurFrequency(u16),
+    #[error("Invalid PatternType: 0x{0:04X}")]
+    InvalidPatternType(u16),
+    #[error("Hijri calendar PatternType is not supported: 0x{0:04X}")]
+    UnsupportedPatternType(u16),
+    #[error("Invalid Period for {0:?}: {1}")]
+    InvalidPeriod(RecurFrequency, u32),
+    #[error("Invalid days of the week mask: 0x{0:08X}")]
+    InvalidDaysOfWeek(u32),
+    #[error("Invalid day of the month: {0}")]
+    InvalidDayOfMonth(u32),
+    #[error("Invalid week of the month: {0}")]
+    InvalidWeekOfMonth(u32),
+    #[error("Invalid EndType: 0x{0:08X}")]
+    InvalidEndType(u32),
+    #[error("Invalid FirstDOW: {0}")]
+    InvalidFirstDayOfWeek(u32),
+    #[error("ExceptionCount {exceptions} does not match ModifiedInstanceCount {modified}")]
+    ExceptionCountMismatch { modified: usize, exceptions: usize },
+}
+
+pub type RecurrenceResult<T> = Result<T, RecurrenceError>;
+
+/// `RecurrencePattern` `ReaderVersion` and `WriterVersion`
+const RECURRENCE_PATTERN_VERSION: u16 = 0x3004;
+/// `AppointmentRecurrencePattern` `ReaderVersion2`
+const APPOINTMENT_RECURRENCE_READER_VERSION: u32 = 0x3006;
+
+const MINUTES_PER_DAY: i64 = 1_440;
+
+/// `RecurFrequency`
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecurFrequency {
+    Daily = 0x200A,
+    Weekly = 0x200B,
+    Monthly = 0x200C,
+    Yearly = 0x200D,
+}
+
+impl TryFrom<u16> for RecurFrequency {
+    type Error = RecurrenceError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0x200A => Ok(Self::Daily),
+            0x200B => Ok(Self::Weekly),
+            0x200C => Ok(Self::Monthly),
+            0x200D => Ok(Self::Yearly),
+            _ => Err(RecurrenceError::InvalidRecurFrequency(value)),
+        }
+    }
+}
+
+/// Bit mask of the days of the week, with `0x01` for Sunday through `0x40` for Saturday.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DaysOfWeek(u8);
+
+impl DaysOfWeek {
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Check for a day of the week, where `0` is Sunday.
+    pub fn contains(&self, day_of_week: u8) -> bool {
+        day_of_week < 7 && self.0 & (1 << day_of_week) != 0
+    }
+}
+
+impl TryFrom<u32> for DaysOfWeek {
+    type Error = RecurrenceError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match u8::try_from(value) {
+            Ok(bits @ 0x01..=0x7F) => Ok(Self(bits)),
+            _ => Err(RecurrenceError::InvalidDaysOfWeek(value)),
+        }
+    }
+}
+
+/// `PatternType` with the `PatternTypeSpecific` data for that type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecurrencePatternType {
+    /// Every `Period` minutes, which is always a whole number of days.
+    Day,
+    /// Every `Period` weeks, on each of the days.
+    Week(DaysOfWeek),
+    /// Every `Period` months on this day of the month, or the last day of shorter months.
+    Month(u8),
+    /// Every `Period` months on the Nth matching day, where the week of the month is 1 to 4, or
+    /// 5 for the last one.
+    MonthNth(DaysOfWeek, u8),
+    /// Every `Period` months on the last day of the month.
+    MonthEnd,
+}
+
+/// `EndType`, with the `OccurrenceCount` for [`RecurrenceEnd::Count`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecurrenceEnd {
+    /// The last instance is on or before [`RecurrencePattern::end_date`].
+    Date,
+    /// The recurrence ends after this many instances, including any which were deleted.
+    Count(u32),
+    /// The recurrence never ends.
+    Never,
+}
+
+/// `ExceptionInfo` for a modified instance. Only the new start and end times are kept, the
+/// other overridden properties are skipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecurrenceException {
+    start: u32,
+    end: u32,
+    original_start: u32,
+    override_flags: u16,
+}
+
+impl RecurrenceException {
+    /// `StartDateTime`, in local time.
+    pub fn start(&self) -> SystemTime {
+        minutes_to_system_time(i64::from(self.start))
+    }
+
+    /// `EndDateTime`, in local time.
+    pub fn end(&self) -> SystemTime {
+        minutes_to_system_time(i64::from(self.end))
+    }
+
+    /// `OriginalStartDate`, the start of the instance this replaces, in local time.
+    pub fn original_start(&self) -> SystemTime {
+        minutes_to_system_time(i64::from(self.original_start))
+    }
+
+    /// `OverrideFlags`, the `ARO_*` bits for the properties which differ from the series.
+    pub fn override_flags(&self) -> u16 {
+        self.override_flags
+    }
+}
+
+/// A concrete instance of a recurring appointment, in local time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Occurrence {
+    start: SystemTime,
+    end: SystemTime,
+    modified: bool,
+}
+
+impl Occurrence {
+    pub fn start(&self) -> SystemTime {
+        self.start
+    }
+
+    pub fn end(&self) -> SystemTime {
+        self.end
+    }
+
+    /// `true` if this instance comes from a [`RecurrenceException`].
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+}
+
+/// A parsed `PidLidAppointmentRecur` blob.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecurrencePattern {
+    frequency: RecurFrequency,
+    pattern_type: RecurrencePatternType,
+    calendar_type: u16,
+    period: u32,
+    end: RecurrenceEnd,
+    first_day_of_week: u8,
+    deleted_instance_dates: Vec<u32>,
+    modified_instance_dates: Vec<u32>,
+    start_date: u32,
+    end_date: u32,
+    start_time_offset: u32,
+    end_time_offset: u32,
+    exceptions: Vec<RecurrenceException>,
+}
+
+impl RecurrencePattern {
+    pub fn parse(data: &[u8]) -> RecurrenceResult<Self> {
+        let mut cursor = Cursor::new(data);
+
+        let reader_version = read_u16(&mut cursor)?;
+        if reader_version != RECURRENCE_PATTERN_VERSION {
+            return Err(RecurrenceError::InvalidReaderVersion(reader_version));
+        }
+        let writer_version = read_u16(&mut cursor)?;
+        if writer_version != RECURRENCE_PATTERN_VERSION {
+            return Err(RecurrenceError::InvalidWriterVersion(writer_version));
+        }
+
+        let frequency = RecurFrequency::try_from(read_u16(&mut cursor)?)?;
+        let pattern_type = read_u16(&mut cursor)?;
+        let calendar_type = read_u16(&mut cursor)?;
+        let _first_date_time = read_u32(&mut cursor)?;
+        let period = read_u32(&mut cursor)?;
+        let _sliding_flag = read_u32(&mut cursor)?;
+
+        let pattern_type = match pattern_type {
+            0x0000 => RecurrencePatternType::Day,
+            0x0001 => RecurrencePatternType::Week(DaysOfWeek::try_from(read_u32(&mut cursor)?)?),
+            0x0002 => {
+                let day = read_u32(&mut cursor)?;
+                match u8::try_from(day) {
+                    Ok(day @ 1..=31) => RecurrencePatternType::Month(day),
+                    _ => return Err(RecurrenceError::InvalidDayOfMonth(day)),
+                }
+            }
+            0x0003 => {
+                let days = DaysOfWeek::try_from(read_u32(&mut cursor)?)?;
+                let week = read_u32(&mut cursor)?;
+                match u8::try_from(week) {
+                    Ok(week @ 1..=5) => RecurrencePatternType::MonthNth(days, week),
+                    _ => return Err(RecurrenceError::InvalidWeekOfMonth(week)),
+                }
+            }
+            0x0004 => {
+                let _day = read_u32(&mut cursor)?;
+                RecurrencePatternType::MonthEnd
+            }
+            0x000A..=0x000C => return Err(RecurrenceError::UnsupportedPatternType(pattern_type)),
+            _ => return Err(RecurrenceError::InvalidPatternType(pattern_type)),
+        };
+
+        let valid_period = match (frequency, pattern_type) {
+            (_, RecurrencePatternType::Day) => {
+                period > 0 && i64::from(period) % MINUTES_PER_DAY == 0
+            }
+            (RecurFrequency::Yearly, _) => period > 0 && period % 12 == 0,
+            _ => period > 0,
+        };
+        if !valid_period {
+            return Err(RecurrenceError::InvalidPeriod(frequency, period));
+        }
+
+        let end_type = read_u32(&mut cursor)?;
+        let occurrence_count = read_u32(&mut cursor)?;
+        let end = match end_type {
+            0x2021 => RecurrenceEnd::Date,
+            0x2022 => RecurrenceEnd::Count(occurrence_count),
+            0x2023 | 0xFFFFFFFF => RecurrenceEnd::Never,
+            _ => return Err(RecurrenceError::InvalidEndType(end_type)),
+        };
+
+        let first_day_of_week = read_u32(&mut cursor)?;
+        let first_day_of_week = match u8::try_from(first_day_of_week) {
+            Ok(day @ 0..=6) => day,
+            _ => return Err(RecurrenceError::InvalidFirstDayOfWeek(first_day_of_week)),
+        };
+
+        let deleted_instance_dates = read_dates(&mut cursor)?;
+        let modified_instance_dates = read_dates(&mut cursor)?;
+        let start_date = read_u32(&mut cursor)?;
+        let end_date = read_u32(&mut cursor)?;
+
+        let reader_version2 = read_u32(&mut cursor)?;
+        if reader_version2 != APPOINTMENT_RECURRENCE_READER_VERSION {
+            return Err(RecurrenceError::InvalidReaderVersion2(reader_version2));
+        }
+        let writer_version2 = read_u32(&mut cursor)?;
+        if writer_version2 < APPOINTMENT_RECURRENCE_READER_VERSION {
+            return Err(RecurrenceError::InvalidWriterVersion2(writer_version2));
+        }
+
+        let start_time_offset = read_u32(&mut cursor)?;
+        let end_time_offset = read_u32(&mut cursor)?;
+
+        let exception_count = usize::from(read_u16(&mut cursor)?);
+        if exception_count != modified_instance_dates.len() {
+            return Err(RecurrenceError::ExceptionCountMismatch {
+                modified: modified_instance_dates.len(),
+                exceptions: exception_count,
+            });
+        }
+        let exceptions = (0..exception_count)
+            .map(|_| read_exception(&mut cursor))
+            .collect::<RecurrenceResult<_>>()?;
+
+        // The reserved blocks and ExtendedException array which follow only add Unicode
+        // versions of the overridden subject and location.
+
+        Ok(Self {
+            frequency,
+            pattern_type,
+            calendar_type,
+            period,
+            end,
+            first_day_of_week,
+            deleted_instance_dates,
+            modified_instance_dates,
+            start_date,
+            end_date,
+            start_time_offset,
+            end_time_offset,
+            exceptions,
+        })
+    }
+
+    pub fn frequency(&self) -> RecurFrequency {
+        self.frequency
+    }
+
+    pub fn pattern_type(&self) -> RecurrencePatternType {
+        self.pattern_type
+    }
+
+    /// `CalendarType`, which is `0` for the default Gregorian calendar.
+    pub fn calendar_type(&self) -> u16 {
+        self.calendar_type
+    }
+
+    /// `Period`, in minutes for [`RecurrencePatternType::Day`], weeks for
+    /// [`RecurrencePatternType::Week`], and months for the others.
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+
+    pub fn end(&self) -> RecurrenceEnd {
+        self.end
+    }
+
+    /// `FirstDOW`, where `0` is Sunday.
+    pub fn first_day_of_week(&self) -> u8 {
+        self.first_day_of_week
+    }
+
+    /// `DeletedInstanceDates`, the original dates of instances which were deleted or modified.
+    pub fn deleted_instance_dates(&self) -> impl Iterator<Item = SystemTime> + '_ {
+        self.deleted_instance_dates
+            .iter()
+            .map(|&minutes| minutes_to_system_time(i64::from(minutes)))
+    }
+
+    /// `ModifiedInstanceDates`, the new dates of instances which were modified.
+    pub fn modified_instance_dates(&self) -> impl Iterator<Item = SystemTime> + '_ {
+        self.modified_instance_dates
+            .iter()
+            .map(|&minutes| minutes_to_system_time(i64::from(minutes)))
+    }
+
+    /// `StartDate`, midnight on the date of the first instance.
+    pub fn start_date(&self) -> SystemTime {
+        minutes_to_system_time(i64::from(self.start_date))
+    }
+
+    /// `EndDate`, midnight on the date of the last instance.
+    pub fn end_date(&self) -> SystemTime {
+        minutes_to_system_time(i64::from(self.end_date))
+    }
+
+    /// `StartTimeOffset`, the time of day each instance starts.
+    pub fn start_time_offset(&self) -> Duration {
+        Duration::from_secs(u64::from(self.start_time_offset) * 60)
+    }
+
+    /// `EndTimeOffset`, the time each instance ends relative to midnight on its start date.
+    pub fn end_time_offset(&self) -> Duration {
+        Duration::from_secs(u64::from(self.end_time_offset) * 60)
+    }
+
+    pub fn exceptions(&self) -> &[RecurrenceException] {
+        &self.exceptions
+    }
+
+    /// List the instances which start in the range `[start, end)`, in order. Deleted instances
+    /// are skipped and modified instances are moved to their new times. Both `start` and `end`
+    /// are compared to the local times in the pattern, without any time zone conversion.
+    pub fn occurrences_between(&self, start: SystemTime, end: SystemTime) -> Vec<Occurrence> {
+        let start = system_time_to_minutes(start);
+        let end = system_time_to_minutes(end);
+        let start_time_offset = i64::from(self.start_time_offset);
+        let end_time_offset = i64::from(self.end_time_offset);
+        let in_range = |minutes: i64| (start..end).contains(&minutes);
+
+        let mut occurrences: Vec<_> = self
+            .instance_days(end.div_euclid(MINUTES_PER_DAY))
+            .into_iter()
+            .map(|day| day * MINUTES_PER_DAY)
+            .filter(|&date| {
+                !self
+                    .deleted_instance_dates
+                    .iter()
+                    .any(|&deleted| i64::from(deleted) == date)
+            })
+            .filter(|&date| in_range(date + start_time_offset))
+            .map(|date| Occurrence {
+                start: minutes_to_system_time(date + start_time_offset),
+                end: minutes_to_system_time(date + end_time_offset),
+                modified: false,
+            })
+            .chain(
+                self.exceptions
+                    .iter()
+                    .filter(|exception| in_range(i64::from(exception.start)))
+                    .map(|exception| Occurrence {
+                        start: exception.start(),
+                        end: exception.end(),
+                        modified: true,
+                    }),
+            )
+            .collect();
+        occurrences.sort_by_key(Occurrence::start);
+        occurrences
+    }
+
+    /// Format the pattern as an iCalendar `RRULE` value. An end date is converted to a `COUNT`,
+    /// so the rule does not depend on the time zone of `UNTIL`. The rule is evaluated in the time
+    /// zone of `DTSTART`, so `BYDAY` only lines up with the local dates if the instances do not
+    /// cross midnight when they are converted to that time zone.
+    pub fn to_rrule(&self) -> String {
+        let mut rule = match (self.frequency, self.pattern_type) {
+            (_, RecurrencePatternType::Day) => format!(
+                "FREQ=DAILY;INTERVAL={}",
+                i64::from(self.period) / MINUTES_PER_DAY
+            ),
+            (_, RecurrencePatternType::Week(days)) => format!(
+                "FREQ=WEEKLY;INTERVAL={};BYDAY={};WKST={}",
+                self.period,
+                format_days(days),
+                DAY_NAMES[usize::from(self.first_day_of_week)]
+            ),
+            (RecurFrequency::Yearly, _) => {
+                let (_, month, _) = civil_from_days(i64::from(self.start_date) / MINUTES_PER_DAY);
+                format!("FREQ=YEARLY;INTERVAL={};BYMONTH={month}", self.period / 12)
+            }
+            _ => format!("FREQ=MONTHLY;INTERVAL={}", self.period),
+        };
+
+        match self.pattern_type {
+            RecurrencePatternType::Month(day) if day > 28 => {
+                // Shorter months fall back to their last day, which is the last of the days
+                // from the 28th through `day` which exists in that month.
+                let days: Vec<_> = (28..=day).map(|day| day.to_string()).collect();
+                rule.push_str(&format!(";BYMONTHDAY={};BYSETPOS=-1", days.join(",")));
+            }
+            RecurrencePatternType::Month(day) => rule.push_str(&format!(";BYMONTHDAY={day}")),
+            RecurrencePatternType::MonthNth(days, week) => {
+                let position = if week == 5 { -1 } else { i32::from(week) };
+                rule.push_str(&format!(";BYDAY={};BYSETPOS={position}", format_days(days)));
+            }
+            RecurrencePatternType::MonthEnd => rule.push_str(";BYMONTHDAY=-1"),
+            _ => {}
+        }
+
+        match self.end {
+            RecurrenceEnd::Count(count) => rule.push_str(&format!(";COUNT={count}")),
+            RecurrenceEnd::Date => {
+                let last_day = i64::from(self.end_date) / MINUTES_PER_DAY;
+                let count = self.instance_days(last_day).len();
+                rule.push_str(&format!(";COUNT={count}"));
+            }
+            RecurrenceEnd::Never => {}
+        }
+
+        rule
+    }
+
+    /// List the dates of every instance in the series up to and including `last_day`, as days
+    /// since January 1, 1601. This includes deleted and modified instances.
+    fn instance_days(&self, last_day: i64) -> Vec<i64> {
+        let first_day = i64::from(self.start_date) / MINUTES_PER_DAY;
+        let last_day = last_day.min(i64::from(self.end_date) / MINUTES_PER_DAY);
+        let limit = match self.end {
+            RecurrenceEnd::Count(count) => count as usize,
+            _ => usize::MAX,
+        };
+
+        let period = i64::from(self.period);
+        let first_month = {
+            let (year, month, _) = civil_from_days(first_day);
+            year * 12 + i64::from(month) - 1
+        };
+        let first_week =
+            first_day - (day_of_week(first_day) - i64::from(self.first_day_of_week)).rem_euclid(7);
+
+        let mut days = Vec::new();
+        for block in 0.. {
+            let candidates = match self.pattern_type {
+                RecurrencePatternType::Day => {
+                    vec![first_day + block * period / MINUTES_PER_DAY]
+                }
+                RecurrencePatternType::Week(mask) => {
+                    let week = first_week + block * period * 7;
+                    (week..week + 7)
+                        .filter(|&day| mask.contains(day_of_week(day) as u8))
+                        .collect()
+                }
+                pattern_type => {
+                    let month = first_month + block * period;
+                    let (year, month) = (month.div_euclid(12), (month.rem_euclid(12) + 1) as u8);
+                    vec![day_in_month(year, month, pattern_type)]
+                }
+            };
+
+            if candidates.first().is_none_or(|&day| day > last_day) {
+                break;
+            }
+
+            for day in candidates {
+                if day < first_day {
+                    continue;
+                }
+                if day > last_day || days.len() >= limit {
+                    return days;
+                }
+                days.push(day);
+            }
+        }
+        days
+    }
+}
+
+const DAY_NAMES: [&str; 7] = ["SU", "MO", "TU", "WE", "TH", "FR", "SA"];
+
+fn format_days(days: DaysOfWeek) -> String {
+    (0..7)
+        .filter(|&day| days.contains(day))
+        .map(|day| DAY_NAMES[usize::from(day)])
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Day of the week for a count of days since January 1, 1601, which was a Monday. `0` is
+/// Sunday.
+fn day_of_week(day: i64) -> i64 {
+    (day + 1).rem_euclid(7)
+}
+
+fn days_in_month(year: i64, month: u8) -> u8 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    (days_from_civil(next_year, next_month, 1) - days_from_civil(year, month, 1)) as u8
+}
+
+/// Find the instance for a monthly or yearly pattern in a single month.
+fn day_in_month(year: i64, month: u8, pattern_type: RecurrencePatternType) -> i64 {
+    let first = days_from_civil(year, month, 1);
+    let length = days_in_month(year, month);
+    match pattern_type {
+        RecurrencePatternType::Month(day) => first + i64::from(day.min(length)) - 1,
+        RecurrencePatternType::MonthNth(mask, week) => {
+            let matches: Vec<_> = (first..first + i64::from(length))
+                .filter(|&day| mask.contains(day_of_week(day) as u8))
+                .collect();
+            // Every day of the week occurs at least 4 times in a month.
+            if week == 5 {
+                matches[matches.len() - 1]
+            } else {
+                matches[usize::from(week - 1)]
+            }
+        }
+        _ => first + i64::from(length) - 1,
+    }
+}
+
+const MINUTES_FROM_1601_TO_1970: i64 = 11_644_473_600 / 60;
+
+fn minutes_to_system_time(minutes: i64) -> SystemTime {
+    let seconds = (minutes - MINUTES_FROM_1601_TO_1970) * 60;
+    if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    }
+}
+
+fn system_time_to_minutes(time: SystemTime) -> i64 {
+    (system_time_to_filetime(time) / 600_000_000) as i64
+}
+
+fn truncated(cursor: &Cursor<&[u8]>) -> RecurrenceError {
+    RecurrenceError::Truncated(cursor.position())
+}
+
+fn read_u16(cursor: &mut Cursor<&[u8]>) -> RecurrenceResult<u16> {
+    cursor
+        .read_u16::<LittleEndian>()
+        .map_err(|_| truncated(cursor))
+}
+
+fn read_u32(cursor: &mut Cursor<&[u8]>) -> RecurrenceResult<u32> {
+    cursor
+        .read_u32::<LittleEndian>()
+        .map_err(|_| truncated(cursor))
+}
+
+fn read_dates(cursor: &mut Cursor<&[u8]>) -> RecurrenceResult<Vec<u32>> {
+    let count = read_u32(cursor)?;
+    (0..count).map(|_| read_u32(cursor)).collect()
+}
+
+/// `OverrideFlags` bits for the optional fields in `ExceptionInfo`, with the size of each one.
+/// The string fields have a variable size, indicated with `None`.
+const EXCEPTION_FIELDS: [(u16, Option<u64>); 9] = [
+    // ARO_SUBJECT
+    (0x0001, None),
+    // ARO_MEETINGTYPE
+    (0x0002, Some(4)),
+    // ARO_REMINDERDELTA
+    (0x0004, Some(4)),
+    // ARO_REMINDER
+    (0x0008, Some(4)),
+    // ARO_LOCATION
+    (0x0010, None),
+    // ARO_BUSYSTATUS
+    (0x0020, Some(4)),
+    // ARO_ATTACHMENT
+    (0x0040, Some(4)),
+    // ARO_SUBTYPE
+    (0x0080, Some(4)),
+    // ARO_APPTCOLOR
+    (0x0100, Some(4)),
+];
+
+fn read_exception(cursor: &mut Cursor<&[u8]>) -> RecurrenceResult<RecurrenceException> {
+    let start = read_u32(cursor)?;
+    let end = read_u32(cursor)?;
+    let original_start = read_u32(cursor)?;
+    let override_flags = read_u16(cursor)?;
+
+    for (flag, size) in EXCEPTION_FIELDS {
+        if override_flags & flag == 0 {
+            continue;
+        }
+        let size = match size {
+            Some(size) => size,
+            None => {
+                // The first length includes a terminator which is not stored, the second one is
+                // the number of bytes in the string.
+                let _length = read_u16(cursor)?;
+                u64::from(read_u16(cursor)?)
+            }
+        };
+        let position = cursor.position() + size;
+        if position > cursor.get_ref().len() as u64 {
+            return Err(truncated(cursor));
+        }
+        cursor.set_position(position);
+    }
+
+    Ok(RecurrenceException {
+        start,
+        end,
+        original_start,
+        override_flags,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build an `AppointmentRecurrencePattern` field by field, following the layout in
+    /// MS-OXOCAL section 2.2.1.44.
+    struct TestPattern {
+        frequency: u16,
+        pattern_type: u16,
+        pattern_specific: Vec<u32>,
+        period: u32,
+        end_type: u32,
+        occurrence_count: u32,
+        first_day_of_week: u32,
+        deleted: Vec<u32>,
+        modified: Vec<u32>,
+        start_date: u32,
+        end_date: u32,
+        start_time_offset: u32,
+        end_time_offset: u32,
+        exceptions: Vec<Vec<u8>>,
+    }
+
+    impl Default for TestPattern {
+        fn default() -> Self {
+            Self {
+                frequency: RecurFrequency::Daily as u16,
+                pattern_type: 0x0000,
+                pattern_specific: vec![],
+                period: 1_440,
+                end_type: 0x2023,
+                occurrence_count: 10,
+                first_day_of_week: 0,
+                deleted: vec![],
+                modified: vec![],
+                start_date: date(2024, 1, 1),
+                end_date: NEVER_END_DATE,
+                // 9:00 to 9:30
+                start_time_offset: 540,
+                end_time_offset: 570,
+                exceptions: vec![],
+            }
+        }
+    }
+
+    impl TestPattern {
+        fn to_bytes(&self) -> Vec<u8> {
+            let mut data = Vec::new();
+            let u16 = |data: &mut Vec<u8>, value: u16| data.extend_from_slice(&value.to_le_bytes());
+            let u32 = |data: &mut Vec<u8>, value: u32| data.extend_from_slice(&value.to_le_bytes());
+
+            u16(&mut data, 0x3004);
+            u16(&mut data, 0x3004);
+            u16(&mut data, self.frequency);
+            u16(&mut data, self.pattern_type);
+            u16(&mut data, 0);
+            u32(&mut data, 0);
+            u32(&mut data, self.period);
+            u32(&mut data, 0);
+            for &value in &self.pattern_specific {
+                u32(&mut data, value);
+            }
+            u32(&mut data, self.end_type);
+            u32(&mut data, self.occurrence_count);
+            u32(&mut data, self.first_day_of_week);
+            for dates in [&self.deleted, &self.modified] {
+                u32(&mut data, dates.len() as u32);
+                for &date in dates {
+                    u32(&mut data, date);
+                }
+            }
+            u32(&mut data, self.start_date);
+            u32(&mut data, self.end_date);
+            u32(&mut data, 0x3006);
+            u32(&mut data, 0x3009);
+            u32(&mut data, self.start_time_offset);
+            u32(&mut data, self.end_time_offset);
+            u16(&mut data, self.exceptions.len() as u16);
+            for exception in &self.exceptions {
+                data.extend_from_slice(exception);
+            }
+            // ReservedBlock1Size and ReservedBlock2Size, with no ExtendedException entries.
+            u32(&mut data, 0);
+            u32(&mut data, 0);
+            data
+        }
+
+        fn parse(&self) -> RecurrencePattern {
+            RecurrencePattern::parse(&self.to_bytes()).unwrap()
+        }
+    }
+
+    /// `EndDate` for a recurrence which never ends, 12/31/4500 at midnight.
+    const NEVER_END_DATE: u32 = 0x5AE980DF;
+
+    const SUNDAY: u32 = 0x01;
+    const MONDAY: u32 = 0x02;
+    const TUESDAY: u32 = 0x04;
+    const WEDNESDAY: u32 = 0x08;
+    const THURSDAY: u32 = 0x10;
+    const FRIDAY: u32 = 0x20;
+
+    fn date(year: i64, month: u8, day: u8) -> u32 {
+        (days_from_civil(year, month, day) * MINUTES_PER_DAY) as u32
+    }
+
+    fn at(year: i64, month: u8, day: u8, hour: u32, minute: u32) -> SystemTime {
+        minutes_to_system_time(i64::from(date(year, month, day) + hour * 60 + minute))
+    }
+
+    fn starts(pattern: &RecurrencePattern) -> Vec<(i64, u8, u8)> {
+        pattern
+            .occurrences_between(at(2000, 1, 1, 0, 0), at(2100, 1, 1, 0, 0))
+            .into_iter()
+            .map(|occurrence| {
+                let minutes = system_time_to_minutes(occurrence.start());
+                civil_from_days(minutes.div_euclid(MINUTES_PER_DAY))
+            })
+            .collect()
+    }
+
+    fn exception(start: u32, end: u32, original_start: u32, subject: Option<&str>) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&start.to_le_bytes());
+        data.extend_from_slice(&end.to_le_bytes());
+        data.extend_from_slice(&original_start.to_le_bytes());
+        match subject {
+            Some(subject) => {
+                // ARO_SUBJECT | ARO_BUSYSTATUS
+                data.extend_from_slice(&0x0021_u16.to_le_bytes());
+                data.extend_from_slice(&(subject.len() as u16 + 1).to_le_bytes());
+                data.extend_from_slice(&(subject.len() as u16).to_le_bytes());
+                data.extend_from_slice(subject.as_bytes());
+                data.extend_from_slice(&2_u32.to_le_bytes());
+            }
+            None => data.extend_from_slice(&0_u16.to_le_bytes()),
+        }
+        data
+    }
+
+    #[test]
+    fn test_daily() {
+        // Every 2 days, 5 times.
+        let pattern = TestPattern {
+            period: 2 * 1_440,
+            end_type: 0x2022,
+            occurrence_count: 5,
+            end_date: date(2024, 1, 9),
+            ..Default::default()
+        }
+        .parse();
+        assert_eq!(pattern.frequency(), RecurFrequency::Daily);
+        assert_eq!(pattern.pattern_type(), RecurrencePatternType::Day);
+        assert_eq!(pattern.end(), RecurrenceEnd::Count(5));
+        assert_eq!(pattern.start_time_offset(), Duration::from_secs(9 * 3_600));
+        assert_eq!(
+            starts(&pattern),
+            [
+                (2024, 1, 1),
+                (2024, 1, 3),
+                (2024, 1, 5),
+                (2024, 1, 7),
+                (2024, 1, 9)
+            ]
+        );
+
+        let occurrences = pattern.occurrences_between(at(2024, 1, 3, 9, 0), at(2024, 1, 5, 9, 0));
+        assert_eq!(
+            occurrences,
+            [Occurrence {
+                start: at(2024, 1, 3, 9, 0),
+                end: at(2024, 1, 3, 9, 30),
+                modified: false,
+            }]
+        );
+        assert_eq!(pattern.to_rrule(), "FREQ=DAILY;INTERVAL=2;COUNT=5");
+    }
+
+    #[test]
+    fn test_weekly() {
+        // Every Monday, Wednesday and Friday until Friday, January 12, 2024.
+        let pattern = TestPattern {
+            frequency: RecurFrequency::Weekly as u16,
+            pattern_type: 0x0001,
+            pattern_specific: vec![MONDAY | WEDNESDAY | FRIDAY],
+            period: 1,
+            end_type: 0x2021,
+            end_date: date(2024, 1, 12),
+            ..Default::default()
+        }
+        .parse();
+        assert_eq!(
+            starts(&pattern),
+            [
+                (2024, 1, 1),
+                (2024, 1, 3),
+                (2024, 1, 5),
+                (2024, 1, 8),
+                (2024, 1, 10),
+                (2024, 1, 12)
+            ]
+        );
+        assert_eq!(
+            pattern.to_rrule(),
+            "FREQ=WEEKLY;INTERVAL=1;BYDAY=MO,WE,FR;WKST=SU;COUNT=6"
+        );
+
+        // Every other Tuesday and Sunday with weeks starting on Monday, 4 times. The first
+        // Sunday is in the same week as the first Tuesday.
+        let pattern = TestPattern {
+            frequency: RecurFrequency::Weekly as u16,
+            pattern_type: 0x0001,
+            pattern_specific: vec![TUESDAY | SUNDAY],
+            period: 2,
+            end_type: 0x2022,
+            occurrence_count: 4,
+            first_day_of_week: 1,
+            start_date: date(2024, 1, 2),
+            end_date: date(2024, 1, 21),
+            ..Default::default()
+        }
+        .parse();
+        assert_eq!(
+            starts(&pattern),
+            [(2024, 1, 2), (2024, 1, 7), (2024, 1, 16), (2024, 1, 21)]
+        );
+    }
+
+    #[test]
+    fn test_monthly() {
+        // The 31st of every month, or the last day of shorter months.
+        let pattern = TestPattern {
+            frequency: RecurFrequency::Monthly as u16,
+            pattern_type: 0x0002,
+            pattern_specific: vec![31],
+            period: 1,
+            end_type: 0x2022,
+            occurrence_count: 4,
+            start_date: date(2024, 1, 31),
+            end_date: date(2024, 4, 30),
+            ..Default::default()
+        }
+        .parse();
+        assert_eq!(pattern.pattern_type(), RecurrencePatternType::Month(31));
+        assert_eq!(
+            starts(&pattern),
+            [(2024, 1, 31), (2024, 2, 29), (2024, 3, 31), (2024, 4, 30)]
+        );
+        assert_eq!(
+            pattern.to_rrule(),
+            "FREQ=MONTHLY;INTERVAL=1;BYMONTHDAY=28,29,30,31;BYSETPOS=-1;COUNT=4"
+        );
+
+        // The last day of every other month, forever.
+        let pattern = TestPattern {
+            frequency: RecurFrequency::Monthly as u16,
+            pattern_type: 0x0004,
+            pattern_specific: vec![31],
+            period: 2,
+            start_date: date(2023, 12, 31),
+            ..Default::default()
+        }
+        .parse();
+        assert_eq!(
+            pattern
+                .occurrences_between(at(2024, 1, 1, 0, 0), at(2024, 7, 1, 0, 0))
+                .into_iter()
+                .map(|occurrence| occurrence.start())
+                .collect::<Vec<_>>(),
+            [
+                at(2024, 2, 29, 9, 0),
+                at(2024, 4, 30, 9, 0),
+                at(2024, 6, 30, 9, 0)
+            ]
+        );
+        assert_eq!(pattern.to_rrule(), "FREQ=MONTHLY;INTERVAL=2;BYMONTHDAY=-1");
+    }
+
+    #[test]
+    fn test_monthly_nth() {
+        // The last Friday of every month.
+        let pattern = TestPattern {
+            frequency: RecurFrequency::Monthly as u16,
+            pattern_type: 0x0003,
+            pattern_specific: vec![FRIDAY, 5],
+            period: 1,
+            end_type: 0x2022,
+            occurrence_count: 3,
+            start_date: date(2024, 1, 26),
+            end_date: date(2024, 3, 29),
+            ..Default::default()
+        }
+        .parse();
+        assert_eq!(
+            pattern.pattern_type(),
+            RecurrencePatternType::MonthNth(DaysOfWeek(FRIDAY as u8), 5)
+        );
+        assert_eq!(
+            starts(&pattern),
+            [(2024, 1, 26), (2024, 2, 23), (2024, 3, 29)]
+        );
+        assert_eq!(
+            pattern.to_rrule(),
+            "FREQ=MONTHLY;INTERVAL=1;BYDAY=FR;BYSETPOS=-1;COUNT=3"
+        );
+
+        // The first weekday of every month.
+        let pattern = TestPattern {
+            frequency: RecurFrequency::Monthly as u16,
+            pattern_type: 0x0003,
+            pattern_specific: vec![0x3E, 1],
+            period: 1,
+            end_type: 0x2022,
+            occurrence_count: 3,
+            start_date: date(2024, 6, 3),
+            end_date: date(2024, 8, 1),
+            ..Default::default()
+        }
+        .parse();
+        assert_eq!(starts(&pattern), [(2024, 6, 3), (2024, 7, 1), (2024, 8, 1)]);
+    }
+
+    #[test]
+    fn test_yearly() {
+        // February 29th, which falls back to the 28th in other years.
+        let pattern = TestPattern {
+            frequency: RecurFrequency::Yearly as u16,
+            pattern_type: 0x0002,
+            pattern_specific: vec![29],
+            period: 12,
+            end_type: 0x2022,
+            occurrence_count: 3,
+            start_date: date(2024, 2, 29),
+            end_date: date(2026, 2, 28),
+            ..Default::default()
+        }
+        .parse();
+        assert_eq!(
+            starts(&pattern),
+            [(2024, 2, 29), (2025, 2, 28), (2026, 2, 28)]
+        );
+        assert_eq!(
+            pattern.to_rrule(),
+            "FREQ=YEARLY;INTERVAL=1;BYMONTH=2;BYMONTHDAY=28,29;BYSETPOS=-1;COUNT=3"
+        );
+
+        // The fourth Thursday of November.
+        let pattern = TestPattern {
+            frequency: RecurFrequency::Yearly as u16,
+            pattern_type: 0x0003,
+            pattern_specific: vec![THURSDAY, 4],
+            period: 12,
+            end_type: 0x2021,
+            start_date: date(2024, 11, 28),
+            end_date: date(2026, 11, 26),
+            ..Default::default()
+        }
+        .parse();
+        assert_eq!(
+            starts(&pattern),
+            [(2024, 11, 28), (2025, 11, 27), (2026, 11, 26)]
+        );
+        assert_eq!(
+            pattern.to_rrule(),
+            "FREQ=YEARLY;INTERVAL=1;BYMONTH=11;BYDAY=TH;BYSETPOS=4;COUNT=3"
+        );
+    }
+
+    #[test]
+    fn test_exceptions() {
+        // Every Monday, with January 8th deleted and January 15th moved to Tuesday at 10:00.
+        let pattern = TestPattern {
+            frequency: RecurFrequency::Weekly as u16,
+            pattern_type: 0x0001,
+            pattern_specific: vec![MONDAY],
+            period: 1,
+            deleted: vec![date(2024, 1, 8), date(2024, 1, 15)],
+            modified: vec![date(2024, 1, 16)],
+            exceptions: vec![exception(
+                date(2024, 1, 16) + 600,
+                date(2024, 1, 16) + 660,
+                date(2024, 1, 15) + 540,
+                Some("Moved"),
+            )],
+            ..Default::default()
+        }
+        .parse();
+
+        assert_eq!(pattern.end(), RecurrenceEnd::Never);
+        assert_eq!(pattern.deleted_instance_dates().count(), 2);
+        assert_eq!(
+            pattern.modified_instance_dates().collect::<Vec<_>>(),
+            [at(2024, 1, 16, 0, 0)]
+        );
+        let [exception] = pattern.exceptions() else {
+            panic!("expected one exception");
+        };
+        assert_eq!(exception.original_start(), at(2024, 1, 15, 9, 0));
+        assert_eq!(exception.override_flags(), 0x0021);
+
+        let occurrences = pattern.occurrences_between(at(2024, 1, 1, 0, 0), at(2024, 1, 29, 0, 0));
+        assert_eq!(
+            occurrences,
+            [
+                Occurrence {
+                    start: at(2024, 1, 1, 9, 0),
+                    end: at(2024, 1, 1, 9, 30),
+                    modified: false,
+                },
+                Occurrence {
+                    start: at(2024, 1, 16, 10, 0),
+                    end: at(2024, 1, 16, 11, 0),
+                    modified: true,
+                },
+                Occurrence {
+                    start: at(2024, 1, 22, 9, 0),
+                    end: at(2024, 1, 22, 9, 30),
+                    modified: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        let data = TestPattern::default().to_bytes();
+        for len in [0, 3, 20, data.len() - 12] {
+            assert!(matches!(
+                RecurrencePattern::parse(&data[..len]),
+                Err(RecurrenceError::Truncated(_))
+            ));
+        }
+
+        let mut invalid = data.clone();
+        invalid[0] = 0x05;
+        assert!(matches!(
+            RecurrencePattern::parse(&invalid),
+            Err(RecurrenceError::InvalidReaderVersion(0x3005))
+        ));
+
+        let invalid = TestPattern {
+            frequency: 0x2000,
+            ..Default::default()
+        };
+        assert!(matches!(
+            RecurrencePattern::parse(&invalid.to_bytes()),
+            Err(RecurrenceError::InvalidRecurFrequency(0x2000))
+        ));
+
+        let invalid = TestPattern {
+            period: 90,
+            ..Default::default()
+        };
+        assert!(matches!(
+            RecurrencePattern::parse(&invalid.to_bytes()),
+            Err(RecurrenceError::InvalidPeriod(RecurFrequency::Daily, 90))
+        ));
+
+        let invalid = TestPattern {
+            frequency: RecurFrequency::Monthly as u16,
+            pattern_type: 0x000A,
+            pattern_specific: vec![1],
+            ..Default::default()
+        };
+        assert!(matches!(
+            RecurrencePattern::parse(&invalid.to_bytes()),
+            Err(RecurrenceError::UnsupportedPatternType(0x000A))
+        ));
+
+        let invalid = TestPattern {
+            frequency: RecurFrequency::Monthly as u16,
+            pattern_type: 0x0003,
+            pattern_specific: vec![MONDAY, 6],
+            period: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            RecurrencePattern::parse(&invalid.to_bytes()),
+            Err(RecurrenceError::InvalidWeekOfMonth(6))
+        ));
+
+        let invalid = TestPattern {
+            end_type: 0x2024,
+            ..Default::default()
+        };
+        assert!(matches!(
+            RecurrencePattern::parse(&invalid.to_bytes()),
+            Err(RecurrenceError::InvalidEndType(0x2024))
+        ));
+
+        let invalid = TestPattern {
+            modified: vec![date(2024, 1, 2)],
+            ..Default::default()
+        };
+        assert!(matches!(
+            RecurrencePattern::parse(&invalid.to_bytes()),
+            Err(RecurrenceError::ExceptionCountMismatch {
+                modified: 1,
+                exceptions: 0
+            })
+        ));
+
+        // The subject in the exception claims more bytes than are left.
+        let mut truncated = exception(0, 0, 0, Some("Moved"));
+        truncated.truncate(truncated.len() - 6);
+        let invalid = TestPattern {
+            deleted: vec![date(2024, 1, 2)],
+            modified: vec![date(2024, 1, 2)],
+            exceptions: vec![truncated],
+            ..Default::default()
+        };
+        let mut data = invalid.to_bytes();
+        data.truncate(data.len() - 8);
+        assert!(matches!(
+            RecurrencePattern::parse(&data),
+            Err(RecurrenceError::Truncated(_))
+        ));
+    }
+}
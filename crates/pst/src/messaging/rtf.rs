@@ -0,0 +1,89 @@
+//! [MS-OXRTFCP](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxrtfcp/65dfe2df-1b69-43fc-8ebd-21819a7463fb):
+//! Rich Text Format (RTF) Compression Algorithm, used for `PidTagRtfCompressed`.
+
+use std::io;
+
+use super::*;
+
+pub use compressed_rtf::Error as RtfError;
+
+/// Decompress a `PidTagRtfCompressed` value with the LZFu algorithm, or unwrap it if it was stored
+/// uncompressed. The header is validated first: `COMPSIZE` must match the size of `data`,
+/// `COMPTYPE` must be `LZFu` or `MELA`, and the `CRC` must match the compressed data or be 0 for
+/// uncompressed data. Compressed data must end with the terminating dictionary reference.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, RtfError> {
+    compressed_rtf::decompress_rtf_bytes_strict(data)
+}
+
+/// [`decompress`] with the [`RtfError`] converted to an [`io::Error`]. The result is the raw RTF,
+/// which is usually ASCII with escaped code page text.
+pub fn decompress_lzfu(data: &[u8]) -> io::Result<Vec<u8>> {
+    decompress(data).map_err(|err| match err {
+        RtfError::IoError(err) => err,
+        err => MessagingError::RtfDecompressionFailed(err.to_string()).into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// [Example 1: Simple Compressed RTF](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxrtfcp/029bff74-8c00-402e-ac2b-0210a5f57371)
+    const COMPRESSED_SIMPLE_RTF: &[u8] = &[
+        0x2d, 0x00, 0x00, 0x00, 0x2b, 0x00, 0x00, 0x00, 0x4c, 0x5a, 0x46, 0x75, 0xf1, 0xc5, 0xc7,
+        0xa7, 0x03, 0x00, 0x0a, 0x00, 0x72, 0x63, 0x70, 0x67, 0x31, 0x32, 0x35, 0x42, 0x32, 0x0a,
+        0xf3, 0x20, 0x68, 0x65, 0x6c, 0x09, 0x00, 0x20, 0x62, 0x77, 0x05, 0xb0, 0x6c, 0x64, 0x7d,
+        0x0a, 0x80, 0x0f, 0xa0,
+    ];
+
+    /// [Example 2: Reading a Token from the Dictionary that Crosses WritePosition](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxrtfcp/421a2da5-7752-4985-8981-0f19f1e5b687)
+    const COMPRESSED_CROSSING_WRITE_RTF: &[u8] = &[
+        0x1a, 0x00, 0x00, 0x00, 0x1c, 0x00, 0x00, 0x00, 0x4c, 0x5a, 0x46, 0x75, 0xe2, 0xd4, 0x4b,
+        0x51, 0x41, 0x00, 0x04, 0x20, 0x57, 0x58, 0x59, 0x5a, 0x0d, 0x6e, 0x7d, 0x01, 0x0e, 0xb0,
+    ];
+
+    #[test]
+    fn test_decompress_lzfu() {
+        let rtf = decompress_lzfu(COMPRESSED_SIMPLE_RTF).unwrap();
+        assert_eq!(rtf, b"{\\rtf1\\ansi\\ansicpg1252\\pard hello world}\r\n");
+
+        let rtf = decompress_lzfu(COMPRESSED_CROSSING_WRITE_RTF).unwrap();
+        assert_eq!(rtf, b"{\\rtf1 WXYZWXYZWXYZWXYZWXYZ}");
+    }
+
+    #[test]
+    fn test_decompress() {
+        let uncompressed = b"{\\rtf1\\ansi\\ansicpg1252\\pard hello world}\r\n";
+        let mut data = Vec::from((uncompressed.len() as u32 + 12).to_le_bytes());
+        data.extend_from_slice(&(uncompressed.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"MELA");
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(uncompressed);
+        assert_eq!(decompress(&data).unwrap(), uncompressed);
+        assert_eq!(
+            decompress(&data).unwrap(),
+            decompress(COMPRESSED_SIMPLE_RTF).unwrap()
+        );
+
+        data[8..12].copy_from_slice(b"ABCD");
+        assert!(matches!(
+            decompress(&data),
+            Err(RtfError::InvalidCompressionType(0x44434241))
+        ));
+
+        let mut data = COMPRESSED_SIMPLE_RTF.to_vec();
+        data.truncate(data.len() - 1);
+        assert!(matches!(
+            decompress(&data),
+            Err(RtfError::CompressedSizeMismatch(0x2D))
+        ));
+    }
+
+    #[test]
+    fn test_decompress_lzfu_bad_crc() {
+        let mut data = COMPRESSED_SIMPLE_RTF.to_vec();
+        data[20] ^= 0xFF;
+        let err = decompress_lzfu(&data).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
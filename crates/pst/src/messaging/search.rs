@@ -4,17 +4,18 @@ use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::{
     io::{self, Cursor, Read, Write},
     marker::PhantomData,
-    rc::Rc,
+    sync::Arc,
+    time::SystemTime,
 };
 
-use super::{read_write::*, *};
+use super::{property_ids::*, read_write::*, store::*, *};
 use crate::{
-    messaging::store::{AnsiStore, UnicodeStore},
+    ltp::prop_context::{FromProperty, PropertyValue, TypedProperties},
     ndb::{
         block::{DataTree, IntermediateTreeBlock},
         block_id::BlockId,
         header::Header,
-        node_id::{NodeId, NID_SEARCH_MANAGEMENT_QUEUE},
+        node_id::{NodeId, NID_ROOT_FOLDER, NID_SEARCH_MANAGEMENT_QUEUE},
         page::{BTreePage, NodeBTreeEntry, RootBTree},
         read_write::*,
         root::Root,
@@ -440,7 +441,7 @@ impl SearchReadWrite for SearchUpdate {
 
 const SEARCH_UPDATE_SIZE: u32 = 20;
 
-pub trait SearchUpdateQueue {
+pub trait SearchUpdateQueue: Send + Sync {
     fn updates(&self) -> &[SearchUpdate];
 }
 
@@ -487,7 +488,7 @@ where
     <Pst as PstFile>::DataBlock: BlockReadWrite + Clone,
     <Pst as PstFile>::Store: StoreReadWrite<Pst>,
 {
-    fn read(store: Rc<Pst::Store>) -> io::Result<Self> {
+    fn read(store: Arc<Pst::Store>) -> io::Result<Self> {
         let pst = store.pst();
         let header = pst.header();
         let encoding = header.crypt_method();
@@ -516,8 +517,7 @@ where
         let start = usize::try_from(start)
             .map_err(|_| MessagingError::InvalidSearchUpdateQueueOffset(start))?;
 
-        let key = node.data().search_key();
-        if u64::from(key) == 0 {
+        if u64::from(node.data().search_key()) == 0 {
             return Ok(Self {
                 updates: Default::default(),
                 _phantom: PhantomData,
@@ -525,8 +525,13 @@ where
         }
 
         let mut page_cache = Default::default();
-        let block = block_btree.find_entry(file, key, &mut page_cache)?;
-        let tree = DataTree::<Pst>::read(file, encoding, &block)?;
+        let tree = DataTree::<Pst>::read_block(
+            file,
+            encoding,
+            &block_btree,
+            &mut page_cache,
+            node.data(),
+        )?;
         let mut data = Vec::new();
         let mut block_cache = Default::default();
         let size = tree
@@ -572,9 +577,9 @@ impl SearchUpdateQueue for UnicodeSearchUpdateQueue {
 }
 
 impl SearchUpdateQueueReadWrite<UnicodePstFile> for UnicodeSearchUpdateQueue {
-    fn read(store: Rc<UnicodeStore>) -> io::Result<Rc<Self>> {
+    fn read(store: Arc<UnicodeStore>) -> io::Result<Arc<Self>> {
         let inner = SearchUpdateQueueInner::read(store)?;
-        Ok(Rc::new(Self { inner }))
+        Ok(Arc::new(Self { inner }))
     }
 }
 
@@ -589,8 +594,283 @@ impl SearchUpdateQueue for AnsiSearchUpdateQueue {
 }
 
 impl SearchUpdateQueueReadWrite<AnsiPstFile> for AnsiSearchUpdateQueue {
-    fn read(store: Rc<AnsiStore>) -> io::Result<Rc<Self>> {
+    fn read(store: Arc<AnsiStore>) -> io::Result<Arc<Self>> {
         let inner = SearchUpdateQueueInner::read(store)?;
-        Ok(Rc::new(Self { inner }))
+        Ok(Arc::new(Self { inner }))
+    }
+}
+
+/// Criteria for [`Store::search_messages`]. Every criterion which is set must match, and the
+/// string comparisons are case-insensitive substring matches.
+#[derive(Clone, Default, Debug)]
+pub struct SearchCriteria {
+    sender: Option<String>,
+    subject: Option<String>,
+    delivered_after: Option<SystemTime>,
+    delivered_before: Option<SystemTime>,
+    has_attachments: Option<bool>,
+    folder: Option<EntryId>,
+}
+
+impl SearchCriteria {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Match `PidTagSenderName`, `PidTagSentRepresentingName`, or `PidTagSenderEmailAddress`.
+    pub fn sender_contains(&mut self, s: &str) -> &mut Self {
+        self.sender = Some(s.to_lowercase());
+        self
+    }
+
+    pub fn subject_contains(&mut self, s: &str) -> &mut Self {
+        self.subject = Some(s.to_lowercase());
+        self
+    }
+
+    /// Only match messages with a `PidTagMessageDeliveryTime` at or after `t`.
+    pub fn delivered_after(&mut self, t: SystemTime) -> &mut Self {
+        self.delivered_after = Some(t);
+        self
+    }
+
+    /// Only match messages with a `PidTagMessageDeliveryTime` before `t`.
+    pub fn delivered_before(&mut self, t: SystemTime) -> &mut Self {
+        self.delivered_before = Some(t);
+        self
+    }
+
+    /// Match the `MSGFLAG_HASATTACH` bit in `PidTagMessageFlags`.
+    pub fn has_attachments(&mut self, b: bool) -> &mut Self {
+        self.has_attachments = Some(b);
+        self
+    }
+
+    /// Only search the contents table of a single folder, instead of every folder in the store.
+    pub fn in_folder(&mut self, entry_id: &EntryId) -> &mut Self {
+        self.folder = Some(*entry_id);
+        self
+    }
+
+    /// Check the properties returned by `get_property`, decoding `PtypString8` values with
+    /// `codepage`.
+    fn matches(
+        &self,
+        codepage: u32,
+        mut get_property: impl FnMut(u16) -> io::Result<Option<PropertyValue>>,
+    ) -> io::Result<bool> {
+        let mut get_string = |prop_id| -> io::Result<Option<String>> {
+            Ok(get_property(prop_id)?
+                .map(|value| String::from_value_in_codepage(value, codepage))
+                .transpose()?
+                .map(|value| value.to_lowercase()))
+        };
+
+        if let Some(subject) = &self.subject {
+            if !get_string(PR_SUBJECT)?.is_some_and(|value| value.contains(subject.as_str())) {
+                return Ok(false);
+            }
+        }
+
+        if let Some(sender) = &self.sender {
+            let mut found = false;
+            for prop_id in [
+                PR_SENDER_NAME,
+                PR_SENT_REPRESENTING_NAME,
+                PR_SENDER_EMAIL_ADDRESS,
+            ] {
+                if get_string(prop_id)?.is_some_and(|value| value.contains(sender.as_str())) {
+                    found = true;
+                    break;
+                }
+            }
+            if !found {
+                return Ok(false);
+            }
+        }
+
+        if self.delivered_after.is_some() || self.delivered_before.is_some() {
+            let Some(delivered) = get_property(PR_MESSAGE_DELIVERY_TIME)?
+                .map(SystemTime::from_value)
+                .transpose()?
+            else {
+                return Ok(false);
+            };
+            if self.delivered_after.is_some_and(|after| delivered < after)
+                || self
+                    .delivered_before
+                    .is_some_and(|before| delivered >= before)
+            {
+                return Ok(false);
+            }
+        }
+
+        if let Some(has_attachments) = self.has_attachments {
+            let flags = get_property(PR_MESSAGE_FLAGS)?
+                .map(i32::from_value)
+                .transpose()?
+                .unwrap_or_default();
+            if (flags & MSGFLAG_HASATTACH != 0) != has_attachments {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// A message found by [`Store::search_messages`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MessageRef {
+    pub(crate) node_id: NodeId,
+    pub(crate) folder_id: NodeId,
+}
+
+impl MessageRef {
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    pub fn folder_id(&self) -> NodeId {
+        self.folder_id
+    }
+}
+
+pub(crate) fn search_messages<S: Store + ?Sized>(
+    store: &S,
+    criteria: &SearchCriteria,
+) -> io::Result<Vec<MessageRef>> {
+    let properties = store.properties();
+    let mut pending = vec![match &criteria.folder {
+        Some(entry_id) => *entry_id,
+        None => properties.make_entry_id(NID_ROOT_FOLDER)?,
+    }];
+    let mut results = vec![];
+
+    while let Some(entry_id) = pending.pop() {
+        let folder = store.open_folder(&entry_id)?;
+        let folder_id = folder.properties().node_id();
+
+        if let Some(contents_table) = folder.contents_table() {
+            let columns = contents_table.context().columns();
+            for row in contents_table.rows_iter() {
+                let row_id = row?.id();
+                let node_id = NodeId::from(u32::from(row_id));
+                let mut message = None;
+                let matches = criteria.matches(properties.codepage(), |prop_id| {
+                    if columns.iter().any(|column| column.prop_id() == prop_id) {
+                        return contents_table.cell(row_id, prop_id);
+                    }
+
+                    // Fall back to the message itself for properties which are not in the
+                    // contents table.
+                    let message = match &message {
+                        Some(message) => message,
+                        None => message
+                            .insert(store.open_message(&properties.make_entry_id(node_id)?, None)?),
+                    };
+                    Ok(message.properties().get(prop_id).cloned())
+                })?;
+
+                if matches {
+                    results.push(MessageRef { node_id, folder_id });
+                }
+            }
+        }
+
+        if criteria.folder.is_none() && folder.has_sub_folders() {
+            if let Some(hierarchy_table) = folder.hierarchy_table() {
+                for row in hierarchy_table.rows_iter() {
+                    let node_id = NodeId::from(u32::from(row?.id()));
+                    pending.push(properties.make_entry_id(node_id)?);
+                }
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ltp::{
+            prop_type::{PropertyType, DEFAULT_CODEPAGE},
+            read_write::PropertyValueReadWrite,
+        },
+        messaging::time::system_time_to_filetime,
+        test_util::EMPTY_PST,
+        UnicodePstFile,
+    };
+    use std::{collections::BTreeMap, time::Duration};
+
+    fn unicode(value: &str) -> PropertyValue {
+        let buffer: Vec<_> = value.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        PropertyValue::read(&mut Cursor::new(buffer), PropertyType::Unicode).unwrap()
+    }
+
+    #[test]
+    fn test_search_criteria_matches() {
+        let delivered = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let properties = BTreeMap::from([
+            (PR_SUBJECT, unicode("Quarterly Report")),
+            (PR_SENDER_NAME, unicode("Alice Example")),
+            (
+                PR_MESSAGE_DELIVERY_TIME,
+                PropertyValue::Time(system_time_to_filetime(delivered) as i64),
+            ),
+            (
+                PR_MESSAGE_FLAGS,
+                PropertyValue::Integer32(MSGFLAG_HASATTACH),
+            ),
+        ]);
+        let matches = |criteria: &SearchCriteria| {
+            criteria
+                .matches(DEFAULT_CODEPAGE, |prop_id| {
+                    Ok(properties.get(&prop_id).cloned())
+                })
+                .unwrap()
+        };
+
+        assert!(matches(&SearchCriteria::new()));
+        assert!(matches(
+            SearchCriteria::new()
+                .subject_contains("REPORT")
+                .sender_contains("alice")
+                .has_attachments(true)
+        ));
+        assert!(!matches(SearchCriteria::new().subject_contains("invoice")));
+        assert!(!matches(SearchCriteria::new().sender_contains("bob")));
+        assert!(!matches(SearchCriteria::new().has_attachments(false)));
+
+        let second = Duration::from_secs(1);
+        assert!(matches(
+            SearchCriteria::new()
+                .delivered_after(delivered)
+                .delivered_before(delivered + second)
+        ));
+        assert!(!matches(
+            SearchCriteria::new().delivered_after(delivered + second)
+        ));
+        assert!(!matches(SearchCriteria::new().delivered_before(delivered)));
+    }
+
+    #[test]
+    fn test_search_messages_empty_store() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+
+        assert!(store
+            .search_messages(&SearchCriteria::new())
+            .unwrap()
+            .is_empty());
+
+        let ipm_sub_tree = store.properties().ipm_sub_tree_entry_id().unwrap();
+        assert!(store
+            .search_messages(SearchCriteria::new().in_folder(&ipm_sub_tree))
+            .unwrap()
+            .is_empty());
     }
 }
@@ -0,0 +1,200 @@
+//! Typed access to the sender of a message, and the mailbox it was sent on behalf of.
+
+use std::io;
+
+use super::{message::*, property_ids::*, recipient::AddressType};
+use crate::ltp::prop_context::TypedProperties;
+
+/// The property IDs which describe one sender.
+struct SenderPropertyIds {
+    name: u16,
+    address_type: u16,
+    email_address: u16,
+    smtp_address: u16,
+}
+
+const SENDER: SenderPropertyIds = SenderPropertyIds {
+    name: PR_SENDER_NAME,
+    address_type: PR_SENDER_ADDRTYPE,
+    email_address: PR_SENDER_EMAIL_ADDRESS,
+    smtp_address: PR_SENDER_SMTP_ADDRESS,
+};
+
+const SENT_REPRESENTING: SenderPropertyIds = SenderPropertyIds {
+    name: PR_SENT_REPRESENTING_NAME,
+    address_type: PR_SENT_REPRESENTING_ADDRTYPE,
+    email_address: PR_SENT_REPRESENTING_EMAIL_ADDRESS,
+    smtp_address: PR_SENT_REPRESENTING_SMTP_ADDRESS,
+};
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Sender {
+    name: Option<String>,
+    address_type: Option<AddressType>,
+    email: Option<String>,
+    smtp_address: Option<String>,
+}
+
+impl Sender {
+    fn read(properties: &MessageProperties, ids: &SenderPropertyIds) -> io::Result<Option<Self>> {
+        let sender = Self {
+            name: properties.get_string8(ids.name)?,
+            address_type: properties
+                .get_string8(ids.address_type)?
+                .map(AddressType::from),
+            email: properties.get_string8(ids.email_address)?,
+            smtp_address: properties.get_string8(ids.smtp_address)?,
+        };
+
+        Ok(if sender == Self::default() {
+            None
+        } else {
+            Some(sender)
+        })
+    }
+
+    /// `PidTagSenderName` or `PidTagSentRepresentingName`
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// `PidTagSenderAddressType` or `PidTagSentRepresentingAddressType`
+    pub fn address_type(&self) -> Option<&AddressType> {
+        self.address_type.as_ref()
+    }
+
+    /// `PidTagSenderEmailAddress` or `PidTagSentRepresentingEmailAddress`, which is an X.500
+    /// distinguished name if [`Self::address_type`] is [`AddressType::Exchange`].
+    pub fn email(&self) -> Option<&str> {
+        self.email.as_deref()
+    }
+
+    /// Best-effort SMTP address: `PidTagSenderSmtpAddress` (or
+    /// `PidTagSentRepresentingSmtpAddress`) if it is set, otherwise [`Self::email`] unchanged,
+    /// even if that is an Exchange distinguished name.
+    pub fn smtp(&self) -> Option<&str> {
+        self.smtp_address.as_deref().or(self.email())
+    }
+
+    /// Compare the addresses of 2 senders, ignoring case, or their names if neither one has an
+    /// address.
+    fn is_same_mailbox(&self, other: &Self) -> bool {
+        match (self.smtp(), other.smtp()) {
+            (Some(this), Some(other)) => this.eq_ignore_ascii_case(other),
+            (None, None) => self.name == other.name,
+            _ => false,
+        }
+    }
+}
+
+impl MessageProperties {
+    /// Get the sender of the message. If none of the `PidTagSender*` properties are set, this
+    /// falls back to the `PidTagSentRepresenting*` properties, and then to an empty [`Sender`].
+    pub fn sender(&self) -> io::Result<Sender> {
+        Ok(match Sender::read(self, &SENDER)? {
+            Some(sender) => sender,
+            None => Sender::read(self, &SENT_REPRESENTING)?.unwrap_or_default(),
+        })
+    }
+
+    /// Get the mailbox the message was sent as, from the `PidTagSentRepresenting*` properties.
+    pub fn sent_representing(&self) -> io::Result<Option<Sender>> {
+        Sender::read(self, &SENT_REPRESENTING)
+    }
+
+    /// Get the mailbox the message was sent on behalf of, if that is different from
+    /// [`Self::sender`].
+    pub fn sent_on_behalf_of(&self) -> io::Result<Option<Sender>> {
+        let Some(sender) = Sender::read(self, &SENDER)? else {
+            return Ok(None);
+        };
+        Ok(self
+            .sent_representing()?
+            .filter(|representing| !representing.is_same_mailbox(&sender)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ltp::{
+        prop_context::PropertyValue, prop_type::PropertyType, read_write::PropertyValueReadWrite,
+    };
+    use std::{collections::BTreeMap, io::Cursor};
+
+    fn unicode(value: &str) -> PropertyValue {
+        let buffer: Vec<_> = value.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        PropertyValue::read(&mut Cursor::new(buffer), PropertyType::Unicode).unwrap()
+    }
+
+    fn properties(values: &[(u16, &str)]) -> MessageProperties {
+        MessageProperties::from(
+            values
+                .iter()
+                .map(|(prop_id, value)| (*prop_id, unicode(value)))
+                .collect::<BTreeMap<_, _>>(),
+        )
+    }
+
+    const EX_DN: &str = "/O=EXAMPLE/OU=EXCHANGE ADMINISTRATIVE GROUP/CN=RECIPIENTS/CN=ALICE";
+
+    #[test]
+    fn test_exchange_sender() {
+        let mut values = vec![
+            (PR_SENDER_NAME, "Alice"),
+            (PR_SENDER_ADDRTYPE, "EX"),
+            (PR_SENDER_EMAIL_ADDRESS, EX_DN),
+        ];
+
+        let sender = properties(&values).sender().unwrap();
+        assert_eq!(sender.name(), Some("Alice"));
+        assert_eq!(sender.address_type(), Some(&AddressType::Exchange));
+        assert_eq!(sender.email(), Some(EX_DN));
+        assert_eq!(sender.smtp(), Some(EX_DN));
+
+        values.push((PR_SENDER_SMTP_ADDRESS, "alice@example.com"));
+        let sender = properties(&values).sender().unwrap();
+        assert_eq!(sender.email(), Some(EX_DN));
+        assert_eq!(sender.smtp(), Some("alice@example.com"));
+    }
+
+    #[test]
+    fn test_sent_representing_fallback() {
+        let message = properties(&[
+            (PR_SENT_REPRESENTING_NAME, "Bob"),
+            (PR_SENT_REPRESENTING_ADDRTYPE, "SMTP"),
+            (PR_SENT_REPRESENTING_EMAIL_ADDRESS, "bob@example.com"),
+        ]);
+        let sender = message.sender().unwrap();
+        assert_eq!(sender.name(), Some("Bob"));
+        assert_eq!(sender.smtp(), Some("bob@example.com"));
+        assert_eq!(message.sent_on_behalf_of().unwrap(), None);
+
+        assert_eq!(properties(&[]).sender().unwrap(), Sender::default());
+    }
+
+    #[test]
+    fn test_sent_on_behalf_of() {
+        let message = properties(&[
+            (PR_SENDER_NAME, "Alice"),
+            (PR_SENDER_ADDRTYPE, "EX"),
+            (PR_SENDER_EMAIL_ADDRESS, EX_DN),
+            (PR_SENDER_SMTP_ADDRESS, "alice@example.com"),
+            (PR_SENT_REPRESENTING_NAME, "Sales"),
+            (PR_SENT_REPRESENTING_ADDRTYPE, "SMTP"),
+            (PR_SENT_REPRESENTING_EMAIL_ADDRESS, "sales@example.com"),
+        ]);
+        let on_behalf_of = message.sent_on_behalf_of().unwrap().unwrap();
+        assert_eq!(on_behalf_of.name(), Some("Sales"));
+        assert_eq!(on_behalf_of.smtp(), Some("sales@example.com"));
+
+        // Outlook sets both sets of properties to the same mailbox on ordinary messages.
+        let message = properties(&[
+            (PR_SENDER_NAME, "Alice"),
+            (PR_SENDER_SMTP_ADDRESS, "alice@example.com"),
+            (PR_SENT_REPRESENTING_NAME, "Alice"),
+            (PR_SENT_REPRESENTING_SMTP_ADDRESS, "Alice@Example.com"),
+        ]);
+        assert_eq!(message.sent_on_behalf_of().unwrap(), None);
+    }
+}
@@ -2,21 +2,26 @@
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::{
-    cell::OnceCell,
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt::Debug,
     io::{self, Read, Write},
-    rc::{Rc, Weak},
+    sync::{Arc, Mutex, RwLock, RwLockReadGuard, Weak},
+    time::SystemTime,
 };
+use thiserror::Error;
 
-use super::{folder::*, message::*, read_write::*, *};
+use super::{attachment::*, folder::*, message::*, property_ids::*, read_write::*, *};
 use crate::{
     ltp::{
         heap::HeapNode,
-        prop_context::{PropertyContext, PropertyValue},
-        prop_type::PropertyType,
+        prop_context::{PropertyContext, PropertyValue, TypedProperties},
+        prop_type::{
+            decode_string8, encode_latin1, encode_string8, PropertyType, DEFAULT_CODEPAGE,
+        },
         read_write::*,
         table_context::TableContext,
+        tree::heap_tree_record_range,
+        LtpError,
     },
     ndb::{
         block_id::BlockId,
@@ -103,6 +108,39 @@ impl EntryId {
     pub fn node_id(&self) -> NodeId {
         self.node_id
     }
+
+    /// Parse a `PR_ENTRYID` or any other property which holds an entry ID.
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        Self::try_from(bytes)
+    }
+
+    /// Get the kind of object this entry ID refers to, from the type of its [`NodeId`].
+    pub fn record_type(&self) -> EntryIdType {
+        if self.node_id == NID_MESSAGE_STORE {
+            return EntryIdType::Store;
+        }
+
+        match self.node_id.id_type() {
+            Ok(NodeIdType::NormalFolder | NodeIdType::SearchFolder) => EntryIdType::Folder,
+            Ok(NodeIdType::NormalMessage | NodeIdType::AssociatedMessage) => EntryIdType::Message,
+            _ => EntryIdType::Unknown,
+        }
+    }
+}
+
+/// The kind of object an [`EntryId`] refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryIdType {
+    Message,
+    Folder,
+    Store,
+    Unknown,
+}
+
+/// A folder or message opened with [`Store::resolve_entry_id`].
+pub enum ResolvedEntry {
+    Folder(Arc<dyn Folder>),
+    Message(Arc<dyn Message>),
 }
 
 impl StoreKeyReadWrite for EntryId {
@@ -164,6 +202,27 @@ pub struct StoreProperties {
     properties: BTreeMap<u16, PropertyValue>,
 }
 
+/// Well known properties are serialized as named fields, like `display_name`. Every property is
+/// also included in the `properties` map, keyed by its hex property ID.
+#[cfg(feature = "serde")]
+impl serde::Serialize for StoreProperties {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        const FIELDS: &[(&str, &[u16])] = &[
+            ("display_name", &[PR_DISPLAY_NAME]),
+            ("record_key", &[PR_RECORD_KEY]),
+        ];
+
+        let mut state = serializer.serialize_struct("StoreProperties", FIELDS.len() + 1)?;
+        super::serialize_named_properties(&mut state, FIELDS, &self.properties, self.codepage())?;
+        state.end()
+    }
+}
+
 impl StoreProperties {
     pub fn get(&self, id: u16) -> Option<&PropertyValue> {
         self.properties.get(&id)
@@ -176,7 +235,7 @@ impl StoreProperties {
     pub fn record_key(&self) -> io::Result<StoreRecordKey> {
         let record_key = self
             .properties
-            .get(&0xFF9)
+            .get(&PR_RECORD_KEY)
             .ok_or(MessagingError::StoreRecordKeyNotFound)?;
 
         match record_key {
@@ -200,11 +259,11 @@ impl StoreProperties {
     pub fn display_name(&self) -> io::Result<String> {
         let display_name = self
             .properties
-            .get(&0x3001)
+            .get(&PR_DISPLAY_NAME)
             .ok_or(MessagingError::StoreDisplayNameNotFound)?;
 
         match display_name {
-            PropertyValue::String8(value) => Ok(value.to_string()),
+            PropertyValue::String8(value) => Ok(decode_string8(value.buffer(), self.codepage())?),
             PropertyValue::Unicode(value) => Ok(value.to_string()),
             invalid => {
                 Err(MessagingError::InvalidStoreDisplayName(PropertyType::from(invalid)).into())
@@ -212,10 +271,21 @@ impl StoreProperties {
         }
     }
 
+    /// The CRC of the password in `PidTagPstPassword`, or 0 if the store does not have a password.
+    pub fn password_crc(&self) -> io::Result<u32> {
+        match self.properties.get(&PR_PST_PASSWORD) {
+            None => Ok(0),
+            Some(PropertyValue::Integer32(value)) => Ok(*value as u32),
+            Some(invalid) => {
+                Err(MessagingError::InvalidStorePassword(PropertyType::from(invalid)).into())
+            }
+        }
+    }
+
     pub fn ipm_sub_tree_entry_id(&self) -> io::Result<EntryId> {
         let entry_id = self
             .properties
-            .get(&0x35E0)
+            .get(&PR_IPM_SUBTREE_ENTRYID)
             .ok_or(MessagingError::StoreIpmSubTreeEntryIdNotFound)?;
 
         match entry_id {
@@ -229,7 +299,7 @@ impl StoreProperties {
     pub fn ipm_wastebasket_entry_id(&self) -> io::Result<EntryId> {
         let entry_id = self
             .properties
-            .get(&0x35E3)
+            .get(&PR_IPM_WASTEBASKET_ENTRYID)
             .ok_or(MessagingError::StoreIpmWastebasketEntryIdNotFound)?;
 
         match entry_id {
@@ -244,7 +314,7 @@ impl StoreProperties {
     pub fn finder_entry_id(&self) -> io::Result<EntryId> {
         let entry_id = self
             .properties
-            .get(&0x35E7)
+            .get(&PR_FINDER_ENTRYID)
             .ok_or(MessagingError::StoreFinderEntryIdNotFound)?;
 
         match entry_id {
@@ -256,30 +326,217 @@ impl StoreProperties {
     }
 }
 
-pub trait Store {
+/// The store object names the default code page for the whole store the same way a message does,
+/// with `PidTagMessageCodepage` or `PidTagInternetCodepage`.
+impl TypedProperties for StoreProperties {
+    fn property_value(&self, id: u16) -> Option<&PropertyValue> {
+        self.get(id)
+    }
+
+    fn codepage(&self) -> u32 {
+        super::codepage_property(self).unwrap_or(DEFAULT_CODEPAGE)
+    }
+}
+
+pub trait Store: Send + Sync {
     fn properties(&self) -> &StoreProperties;
-    fn root_hierarchy_table(&self) -> io::Result<Rc<dyn TableContext>>;
+    fn root_hierarchy_table(&self) -> io::Result<Arc<dyn TableContext>>;
     fn unique_value(&self) -> u32;
-    fn open_folder(&self, entry_id: &EntryId) -> io::Result<Rc<dyn Folder>>;
+    fn open_folder(&self, entry_id: &EntryId) -> io::Result<Arc<dyn Folder>>;
     fn open_message(
         &self,
         entry_id: &EntryId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<dyn Message>>;
-    fn named_property_map(&self) -> io::Result<Rc<dyn NamedPropertyMap>>;
-    fn search_update_queue(&self) -> io::Result<Rc<dyn SearchUpdateQueue>>;
+    ) -> io::Result<Arc<dyn Message>>;
+    fn named_property_map(&self) -> io::Result<Arc<dyn NamedPropertyMap>>;
+    fn search_update_queue(&self) -> io::Result<Arc<dyn SearchUpdateQueue>>;
+
+    /// `PidTagDisplayName`, or `None` if the store does not have one. See
+    /// [`StoreProperties::display_name`] for the reason it is missing or invalid.
+    fn display_name(&self) -> Option<String> {
+        self.properties().get_typed(PR_DISPLAY_NAME).ok().flatten()
+    }
+
+    /// `PidTagRecordKey`, or `None` if the store does not have one.
+    fn record_key(&self) -> Option<Vec<u8>> {
+        self.properties().get_typed(PR_RECORD_KEY).ok().flatten()
+    }
+
+    /// `PidTagIpmSubTreeEntryId`, which refers to the "Top of Personal Folders" folder that
+    /// Outlook shows as the root of the folder tree, or `None` if it is missing or invalid.
+    fn root_folder_entry_id(&self) -> Option<EntryId> {
+        self.properties().ipm_sub_tree_entry_id().ok()
+    }
+
+    /// `PidTagCreationTime`, or `None` if it is missing or invalid.
+    fn creation_time(&self) -> Option<SystemTime> {
+        self.properties().get_typed(PR_CREATION_TIME).ok().flatten()
+    }
+
+    /// `PidTagLastModificationTime`, or `None` if it is missing or invalid.
+    fn last_modification_time(&self) -> Option<SystemTime> {
+        self.properties()
+            .get_typed(PR_LAST_MODIFICATION_TIME)
+            .ok()
+            .flatten()
+    }
+
+    /// Walk the whole folder tree depth first, starting with the root folder, see
+    /// [`FolderIter::depth_first`]. Use [`FolderIter::breadth_first`] for the other order.
+    fn folders_dfs(&self) -> FolderIter {
+        let root = self
+            .properties()
+            .make_entry_id(NID_ROOT_FOLDER)
+            .and_then(|entry_id| self.open_folder(&entry_id));
+        FolderIter::new(root, false)
+    }
+
+    /// The CRC of the store's password, or 0 if it does not have one, see
+    /// [`StoreProperties::password_crc`].
+    fn password_crc(&self) -> io::Result<u32> {
+        self.properties().password_crc()
+    }
+
+    /// Check `password` against the CRC in [`Store::password_crc`]. A store without a password
+    /// only matches an empty `password`.
+    fn verify_password(&self, password: &str) -> bool {
+        self.password_crc()
+            .is_ok_and(|crc| crc == compute_password_crc(password))
+    }
+
+    /// Open the message at `entry_id` and read each of the attachments in its attachment table.
+    /// The message itself is available from [`Attachment::message`].
+    fn open_attachments(
+        &self,
+        entry_id: &EntryId,
+        prop_ids: Option<&[u16]>,
+    ) -> io::Result<Vec<Arc<dyn Attachment>>> {
+        self.open_message(entry_id, None)?.attachments(prop_ids)
+    }
+
+    /// Find the messages matching `criteria`, walking every folder's contents table depth first
+    /// from the root folder unless [`SearchCriteria::in_folder`] limits it to a single folder.
+    fn search_messages(&self, criteria: &SearchCriteria) -> io::Result<Vec<MessageRef>> {
+        search::search_messages(self, criteria)
+    }
+
+    /// Open the folder or message which `entry_id` refers to, e.g. from `PR_PARENT_ENTRYID`.
+    /// Entry IDs for another store, or for anything other than a folder or message, are errors.
+    fn resolve_entry_id(&self, entry_id: &EntryId) -> io::Result<ResolvedEntry> {
+        match entry_id.record_type() {
+            EntryIdType::Folder => Ok(ResolvedEntry::Folder(self.open_folder(entry_id)?)),
+            EntryIdType::Message => Ok(ResolvedEntry::Message(self.open_message(entry_id, None)?)),
+            EntryIdType::Store | EntryIdType::Unknown => {
+                Err(MessagingError::UnresolvableEntryId(u32::from(entry_id.node_id())).into())
+            }
+        }
+    }
+}
+
+/// Compute the value Outlook stores in `PidTagPstPassword` for `password`, which is the same CRC
+/// used for blocks in the file, over the password encoded with the Windows-1252 code page. The
+/// CRC of an empty password is 0, which means the store does not have a password. Without the
+/// `codepage` feature the password is encoded as Latin-1, which only differs for the characters
+/// Windows-1252 puts in `0x80..=0x9F`, like `€`.
+pub fn compute_password_crc(password: &str) -> u32 {
+    let bytes =
+        encode_string8(password, 1252).unwrap_or_else(|_| encode_latin1(password, '\u{FF}'));
+    crc::compute_crc(0, &bytes)
+}
+
+/// An error reading part of the store which does not prevent reading the rest of it.
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("Failed to read Node BTree page at 0x{0:X}: {1}")]
+    NodeBTreePage(u64, #[source] io::Error),
+    #[error("Failed to read message {0:?}: {1}")]
+    Message(NodeId, #[source] io::Error),
+}
+
+impl StoreError {
+    /// The [`NodeId`] of the message which could not be read, if any.
+    pub fn node(&self) -> Option<NodeId> {
+        match self {
+            Self::NodeBTreePage(..) => None,
+            Self::Message(node, _) => Some(*node),
+        }
+    }
+}
+
+impl From<StoreError> for io::Error {
+    fn from(err: StoreError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Iterator returned by [`UnicodeStore::iter_messages`] and [`AnsiStore::iter_messages`].
+pub struct StoreMessages<S> {
+    store: Arc<S>,
+    nodes: std::vec::IntoIter<Result<NodeId, StoreError>>,
+}
+
+impl Iterator for StoreMessages<UnicodeStore> {
+    type Item = Result<Arc<UnicodeMessage>, StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = match self.nodes.next()? {
+            Ok(node) => node,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(
+            self.store
+                .properties()
+                .make_entry_id(node)
+                .and_then(|entry_id| UnicodeMessage::read(self.store.clone(), &entry_id, None))
+                .map_err(|err| StoreError::Message(node, err)),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
+    }
+}
+
+impl Iterator for StoreMessages<AnsiStore> {
+    type Item = Result<Arc<AnsiMessage>, StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = match self.nodes.next()? {
+            Ok(node) => node,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(
+            self.store
+                .properties()
+                .make_entry_id(node)
+                .and_then(|entry_id| AnsiMessage::read(self.store.clone(), &entry_id, None))
+                .map_err(|err| StoreError::Message(node, err)),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
+    }
+}
+
+/// The file a store was read from, with the roots of its BTrees as of the last edit.
+struct StoreFile<Pst>
+where
+    Pst: PstFile,
+{
+    pst: Arc<Pst>,
+    node_btree: Arc<PstFileReadWriteNodeBTree<Pst>>,
+    block_btree: Arc<PstFileReadWriteBlockBTree<Pst>>,
 }
 
 struct StoreInner<Pst>
 where
     Pst: PstFile + PstFileLock<Pst> + 'static,
 {
-    pst: Rc<Pst>,
-    node_btree: PstFileReadWriteNodeBTree<Pst>,
-    block_btree: PstFileReadWriteBlockBTree<Pst>,
+    file: RwLock<StoreFile<Pst>>,
     properties: StoreProperties,
     store: Weak<Pst::Store>,
-    root_hierarchy_table: OnceCell<io::Result<Rc<dyn TableContext>>>,
+    root_hierarchy_table: Mutex<Option<Arc<dyn TableContext>>>,
 }
 
 impl<Pst> StoreInner<Pst>
@@ -318,11 +575,37 @@ where
     <Pst as PstFile>::NamedPropertyMap: NamedPropertyMapReadWrite<Pst>,
     <Pst as PstFile>::SearchUpdateQueue: SearchUpdateQueueReadWrite<Pst>,
 {
-    fn read(pst: Rc<Pst>) -> io::Result<Self> {
+    /// Clear `PidTagPstPassword` on the message store in `pst`, see
+    /// [`UnicodeStore::reset_password`].
+    fn reset_password(pst: &mut Pst) -> io::Result<bool> {
+        let mut transaction = pst.begin_transaction()?;
+        let changed = transaction.update_node_data(NID_MESSAGE_STORE, |blocks| {
+            let Some((block_index, range)) = heap_tree_record_range(blocks, PR_PST_PASSWORD)?
+            else {
+                return Ok(false);
+            };
+
+            // The record holds wPropType followed by dwValueHnid, which is the CRC itself.
+            let record = &mut blocks[block_index][range];
+            let prop_type = u16::from_le_bytes([record[0], record[1]]);
+            if PropertyType::from_raw(prop_type) != PropertyType::Integer32 {
+                return Err(LtpError::InvalidPropertyType(prop_type).into());
+            }
+            if record[2..6] == [0; 4] {
+                return Ok(false);
+            }
+            record[2..6].fill(0);
+            Ok(true)
+        })?;
+        transaction.commit()?;
+        Ok(changed)
+    }
+
+    fn read(pst: Arc<Pst>) -> io::Result<Self> {
         let header = pst.header();
-        let root = header.root();
+        let (node_btree, block_btree) = Self::read_btrees(&pst)?;
 
-        let (node_btree, block_btree, properties) = {
+        let properties = {
             let mut file = pst
                 .reader()
                 .lock()
@@ -330,14 +613,6 @@ where
             let file = &mut *file;
 
             let encoding = header.crypt_method();
-            let node_btree = <<Pst as PstFile>::NodeBTree as RootBTreeReadWrite>::read(
-                file,
-                *root.node_btree(),
-            )?;
-            let block_btree = <<Pst as PstFile>::BlockBTree as RootBTreeReadWrite>::read(
-                file,
-                *root.block_btree(),
-            )?;
 
             let mut page_cache = pst.node_cache();
             let node_key: <Pst as PstFile>::BTreeKey = u32::from(NID_MESSAGE_STORE).into();
@@ -365,58 +640,127 @@ where
                         .map(|value| (prop_id, value))
                 })
                 .collect::<io::Result<BTreeMap<_, _>>>()?;
-            let properties = StoreProperties { properties };
-
-            (node_btree, block_btree, properties)
+            StoreProperties { properties }
         };
 
         Ok(Self {
-            pst,
-            node_btree,
-            block_btree,
+            file: RwLock::new(StoreFile {
+                pst,
+                node_btree: Arc::new(node_btree),
+                block_btree: Arc::new(block_btree),
+            }),
             properties,
             store: Default::default(),
             root_hierarchy_table: Default::default(),
         })
     }
 
-    fn root_hierarchy_table(&self) -> io::Result<Rc<dyn TableContext>> {
-        let hierarchy_table =
-            self.root_hierarchy_table
-                .get_or_init(|| {
-                    let store = self.store.upgrade().ok_or(
-                        MessagingError::StoreRootHierarchyTableFailed(
-                            "Store has been dropped".to_string(),
-                        ),
-                    )?;
-                    let mut file = self
-                        .pst
-                        .reader()
-                        .lock()
-                        .map_err(|_| MessagingError::FailedToLockFile)?;
-
-                    let file = &mut *file;
-                    let node_id = NodeId::new(NodeIdType::HierarchyTable, NID_ROOT_FOLDER.index())?;
-                    let mut page_cache = self.pst.node_cache();
-                    let node_key: <Pst as PstFile>::BTreeKey = u32::from(node_id).into();
-                    let node = self
-                        .node_btree
-                        .find_entry(file, node_key, &mut page_cache)?;
-
-                    <<Pst as PstFile>::TableContext as TableContextReadWrite<Pst>>::read(
-                        store.clone(),
-                        node,
-                    )
-                })
-                .as_ref()
-                .map_err(|err| format!("{err:?}"))
-                .cloned()
-                .map_err(MessagingError::StoreRootHierarchyTableFailed)?;
+    fn read_btrees(
+        pst: &Pst,
+    ) -> io::Result<(
+        PstFileReadWriteNodeBTree<Pst>,
+        PstFileReadWriteBlockBTree<Pst>,
+    )> {
+        let root = pst.header().root();
+        let mut file = pst
+            .reader()
+            .lock()
+            .map_err(|_| MessagingError::FailedToLockFile)?;
+        let file = &mut *file;
+
+        let node_btree =
+            <<Pst as PstFile>::NodeBTree as RootBTreeReadWrite>::read(file, *root.node_btree())?;
+        let block_btree =
+            <<Pst as PstFile>::BlockBTree as RootBTreeReadWrite>::read(file, *root.block_btree())?;
+        Ok((node_btree, block_btree))
+    }
 
-        Ok(hierarchy_table)
+    fn file(&self) -> RwLockReadGuard<'_, StoreFile<Pst>> {
+        // Edits never leave the file half updated when they panic, so a poisoned lock is safe.
+        self.file.read().unwrap_or_else(|err| err.into_inner())
     }
 
-    fn open_folder(&self, entry_id: &EntryId) -> io::Result<Rc<dyn Folder>> {
+    fn pst(&self) -> Arc<Pst> {
+        self.file().pst.clone()
+    }
+
+    fn node_btree(&self) -> Arc<PstFileReadWriteNodeBTree<Pst>> {
+        self.file().node_btree.clone()
+    }
+
+    fn block_btree(&self) -> Arc<PstFileReadWriteBlockBTree<Pst>> {
+        self.file().block_btree.clone()
+    }
+
+    /// Run `edit` on the file this store was read from, see [`UnicodeStore::edit_file`]. The
+    /// BTree roots are read again from the header afterwards, whether or not `edit` succeeded,
+    /// and the cached root hierarchy table is dropped.
+    fn edit_file<T>(&self, edit: impl FnOnce(&mut Pst) -> io::Result<T>) -> io::Result<T> {
+        let result = {
+            let mut file = self
+                .file
+                .write()
+                .map_err(|_| MessagingError::FailedToLockFile)?;
+            let pst = Arc::get_mut(&mut file.pst).ok_or(MessagingError::EditSharedFile)?;
+            let result = edit(pst);
+
+            let (node_btree, block_btree) = Self::read_btrees(&file.pst)?;
+            file.node_btree = Arc::new(node_btree);
+            file.block_btree = Arc::new(block_btree);
+            result
+        };
+
+        // Release the file before locking the root hierarchy table, which is held while the
+        // table is read from the file.
+        *self
+            .root_hierarchy_table
+            .lock()
+            .map_err(|_| MessagingError::FailedToLockFile)? = None;
+        result
+    }
+
+    fn root_hierarchy_table(&self) -> io::Result<Arc<dyn TableContext>> {
+        let mut hierarchy_table = self
+            .root_hierarchy_table
+            .lock()
+            .map_err(|_| MessagingError::FailedToLockFile)?;
+        if let Some(hierarchy_table) = hierarchy_table.as_ref() {
+            return Ok(hierarchy_table.clone());
+        }
+
+        let read_table = || {
+            let store =
+                self.store
+                    .upgrade()
+                    .ok_or(MessagingError::StoreRootHierarchyTableFailed(
+                        "Store has been dropped".to_string(),
+                    ))?;
+            let node = {
+                let file = self.file();
+                let mut reader = file
+                    .pst
+                    .reader()
+                    .lock()
+                    .map_err(|_| MessagingError::FailedToLockFile)?;
+
+                let node_id = NodeId::new(NodeIdType::HierarchyTable, NID_ROOT_FOLDER.index())?;
+                let mut page_cache = file.pst.node_cache();
+                let node_key: <Pst as PstFile>::BTreeKey = u32::from(node_id).into();
+                file.node_btree
+                    .find_entry(&mut *reader, node_key, &mut page_cache)?
+            };
+
+            // The reader must be unlocked before reading the table context, which locks
+            // it again.
+            <<Pst as PstFile>::TableContext as TableContextReadWrite<Pst>>::read(store, node)
+        };
+        let table = read_table()
+            .map_err(|err| MessagingError::StoreRootHierarchyTableFailed(format!("{err:?}")))?;
+        *hierarchy_table = Some(table.clone());
+        Ok(table)
+    }
+
+    fn open_folder(&self, entry_id: &EntryId) -> io::Result<Arc<dyn Folder>> {
         let store = self.store.upgrade().ok_or(MessagingError::StoreOpenFolder(
             "Store has been dropped".to_string(),
         ))?;
@@ -429,7 +773,7 @@ where
         &self,
         entry_id: &EntryId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<dyn Message>> {
+    ) -> io::Result<Arc<dyn Message>> {
         let store = self.store.upgrade().ok_or(MessagingError::StoreOpenFolder(
             "Store has been dropped".to_string(),
         ))?;
@@ -438,7 +782,7 @@ where
         )?)
     }
 
-    fn named_property_map(&self) -> io::Result<Rc<dyn NamedPropertyMap>> {
+    fn named_property_map(&self) -> io::Result<Arc<dyn NamedPropertyMap>> {
         let store = self
             .store
             .upgrade()
@@ -448,7 +792,7 @@ where
         Ok(<<Pst as PstFile>::NamedPropertyMap as NamedPropertyMapReadWrite<Pst>>::read(store)?)
     }
 
-    fn search_update_queue(&self) -> io::Result<Rc<dyn SearchUpdateQueue>> {
+    fn search_update_queue(&self) -> io::Result<Arc<dyn SearchUpdateQueue>> {
         let store = self
             .store
             .upgrade()
@@ -459,7 +803,71 @@ where
     }
 
     fn unique_value(&self) -> u32 {
-        self.pst.header().unique_value()
+        self.pst().header().unique_value()
+    }
+
+    /// Collect every [`NodeIdType::NormalMessage`] node in the NBT, in key order. A page which
+    /// cannot be read is recorded as an error in place of the nodes it would have contained,
+    /// and the walk continues with its siblings.
+    fn message_nodes(&self) -> Vec<Result<NodeId, StoreError>> {
+        let mut nodes = Vec::new();
+        let file = self.file();
+        match file.pst.reader().lock() {
+            Ok(mut reader) => {
+                let root = file.pst.header().root().node_btree().block();
+                let mut visited = BTreeSet::from([root]);
+                Self::collect_message_nodes(
+                    &mut *reader,
+                    &file.node_btree,
+                    &mut visited,
+                    &mut nodes,
+                )
+            }
+            Err(_) => {
+                let page = file.pst.header().root().node_btree().index().index().into();
+                nodes.push(Err(StoreError::NodeBTreePage(
+                    page,
+                    MessagingError::FailedToLockFile.into(),
+                )));
+            }
+        }
+        nodes
+    }
+
+    fn collect_message_nodes<R: PstReader>(
+        file: &mut R,
+        node_btree: &PstFileReadWriteNodeBTree<Pst>,
+        visited: &mut BTreeSet<<Pst as PstFile>::PageId>,
+        nodes: &mut Vec<Result<NodeId, StoreError>>,
+    ) {
+        match node_btree {
+            RootBTreePage::Intermediate(page, ..) => {
+                for entry in page.entries() {
+                    let block = entry.block();
+                    if !visited.insert(block.block()) {
+                        nodes.push(Err(StoreError::NodeBTreePage(
+                            block.index().index().into(),
+                            NdbError::CyclicReference(block.block().into_u64()).into(),
+                        )));
+                        continue;
+                    }
+                    match <Pst::NodeBTree as RootBTreeReadWrite>::read(file, block) {
+                        Ok(child) => Self::collect_message_nodes(file, &child, visited, nodes),
+                        Err(err) => nodes.push(Err(StoreError::NodeBTreePage(
+                            block.index().index().into(),
+                            err,
+                        ))),
+                    }
+                }
+            }
+            RootBTreePage::Leaf(page) => nodes.extend(
+                page.entries()
+                    .iter()
+                    .map(|entry| entry.node())
+                    .filter(|node| matches!(node.id_type(), Ok(NodeIdType::NormalMessage)))
+                    .map(Ok),
+            ),
+        }
     }
 }
 
@@ -468,9 +876,39 @@ pub struct UnicodeStore {
 }
 
 impl UnicodeStore {
-    pub fn read(pst: Rc<UnicodePstFile>) -> io::Result<Rc<Self>> {
+    pub fn read(pst: Arc<UnicodePstFile>) -> io::Result<Arc<Self>> {
         let inner = StoreInner::read(pst)?;
-        Ok(Rc::new_cyclic(|store| Self::new_cyclic(inner, store)))
+        Ok(Arc::new_cyclic(|store| Self::new_cyclic(inner, store)))
+    }
+
+    /// Clear the [`PidTagPstPassword`](https://learn.microsoft.com/en-us/openspecs/exchange_server_protocols/ms-oxprops/1de2c70d-b6ba-4ab8-b7cc-b8f1bfc1e1e1)
+    /// property on the message store in `pst`, and remove the password from the file. The heap
+    /// block holding the property is written to a new block in a [`WriteTransaction`], and the
+    /// old one is released. Returns `false` if there was no password to clear. Any store which
+    /// was already read from the file still sees the old value.
+    pub fn reset_password(pst: &mut UnicodePstFile) -> io::Result<bool> {
+        StoreInner::reset_password(pst)
+    }
+
+    /// Iterate over every message in the store, regardless of which folder it is in. A message
+    /// which cannot be read yields an [`Err`] with its [`NodeId`], and iteration continues with
+    /// the next one.
+    pub fn iter_messages(self: &Arc<Self>) -> StoreMessages<Self> {
+        StoreMessages {
+            store: self.clone(),
+            nodes: self.inner.message_nodes().into_iter(),
+        }
+    }
+
+    /// Run `edit` on the file this store was read from. The store must hold the only reference
+    /// to the file, or this fails with [`MessagingError::EditSharedFile`], since nothing else
+    /// would see the new header after the edit. The store reads its BTree roots again when `edit`
+    /// returns, so folders and messages which are read from it afterwards see the changes.
+    pub(crate) fn edit_file<T>(
+        &self,
+        edit: impl FnOnce(&mut UnicodePstFile) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.inner.edit_file(edit)
     }
 
     fn new_cyclic(inner: StoreInner<UnicodePstFile>, store: &Weak<Self>) -> Self {
@@ -488,7 +926,7 @@ impl Store for UnicodeStore {
         &self.inner.properties
     }
 
-    fn root_hierarchy_table(&self) -> io::Result<Rc<dyn TableContext>> {
+    fn root_hierarchy_table(&self) -> io::Result<Arc<dyn TableContext>> {
         self.inner.root_hierarchy_table()
     }
 
@@ -496,7 +934,7 @@ impl Store for UnicodeStore {
         self.inner.unique_value()
     }
 
-    fn open_folder(&self, entry_id: &EntryId) -> io::Result<Rc<dyn Folder>> {
+    fn open_folder(&self, entry_id: &EntryId) -> io::Result<Arc<dyn Folder>> {
         self.inner.open_folder(entry_id)
     }
 
@@ -504,30 +942,30 @@ impl Store for UnicodeStore {
         &self,
         entry_id: &EntryId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<dyn Message>> {
+    ) -> io::Result<Arc<dyn Message>> {
         self.inner.open_message(entry_id, prop_ids)
     }
 
-    fn named_property_map(&self) -> io::Result<Rc<dyn NamedPropertyMap>> {
+    fn named_property_map(&self) -> io::Result<Arc<dyn NamedPropertyMap>> {
         self.inner.named_property_map()
     }
 
-    fn search_update_queue(&self) -> io::Result<Rc<dyn SearchUpdateQueue>> {
+    fn search_update_queue(&self) -> io::Result<Arc<dyn SearchUpdateQueue>> {
         self.inner.search_update_queue()
     }
 }
 
 impl StoreReadWrite<UnicodePstFile> for UnicodeStore {
-    fn pst(&self) -> &UnicodePstFile {
-        self.inner.pst.as_ref()
+    fn pst(&self) -> Arc<UnicodePstFile> {
+        self.inner.pst()
     }
 
-    fn node_btree(&self) -> &PstFileReadWriteNodeBTree<UnicodePstFile> {
-        &self.inner.node_btree
+    fn node_btree(&self) -> Arc<PstFileReadWriteNodeBTree<UnicodePstFile>> {
+        self.inner.node_btree()
     }
 
-    fn block_btree(&self) -> &PstFileReadWriteBlockBTree<UnicodePstFile> {
-        &self.inner.block_btree
+    fn block_btree(&self) -> Arc<PstFileReadWriteBlockBTree<UnicodePstFile>> {
+        self.inner.block_btree()
     }
 }
 
@@ -536,9 +974,24 @@ pub struct AnsiStore {
 }
 
 impl AnsiStore {
-    pub fn read(pst: Rc<AnsiPstFile>) -> io::Result<Rc<Self>> {
+    pub fn read(pst: Arc<AnsiPstFile>) -> io::Result<Arc<Self>> {
         let inner = StoreInner::read(pst)?;
-        Ok(Rc::new_cyclic(|store| Self::new_cyclic(inner, store)))
+        Ok(Arc::new_cyclic(|store| Self::new_cyclic(inner, store)))
+    }
+
+    /// See [`UnicodeStore::reset_password`].
+    pub fn reset_password(pst: &mut AnsiPstFile) -> io::Result<bool> {
+        StoreInner::reset_password(pst)
+    }
+
+    /// Iterate over every message in the store, regardless of which folder it is in. A message
+    /// which cannot be read yields an [`Err`] with its [`NodeId`], and iteration continues with
+    /// the next one.
+    pub fn iter_messages(self: &Arc<Self>) -> StoreMessages<Self> {
+        StoreMessages {
+            store: self.clone(),
+            nodes: self.inner.message_nodes().into_iter(),
+        }
     }
 
     fn new_cyclic(inner: StoreInner<AnsiPstFile>, store: &Weak<Self>) -> Self {
@@ -556,7 +1009,7 @@ impl Store for AnsiStore {
         &self.inner.properties
     }
 
-    fn root_hierarchy_table(&self) -> io::Result<Rc<dyn TableContext>> {
+    fn root_hierarchy_table(&self) -> io::Result<Arc<dyn TableContext>> {
         self.inner.root_hierarchy_table()
     }
 
@@ -564,7 +1017,7 @@ impl Store for AnsiStore {
         self.inner.unique_value()
     }
 
-    fn open_folder(&self, entry_id: &EntryId) -> io::Result<Rc<dyn Folder>> {
+    fn open_folder(&self, entry_id: &EntryId) -> io::Result<Arc<dyn Folder>> {
         self.inner.open_folder(entry_id)
     }
 
@@ -572,29 +1025,379 @@ impl Store for AnsiStore {
         &self,
         entry_id: &EntryId,
         prop_ids: Option<&[u16]>,
-    ) -> io::Result<Rc<dyn Message>> {
+    ) -> io::Result<Arc<dyn Message>> {
         self.inner.open_message(entry_id, prop_ids)
     }
 
-    fn named_property_map(&self) -> io::Result<Rc<dyn NamedPropertyMap>> {
+    fn named_property_map(&self) -> io::Result<Arc<dyn NamedPropertyMap>> {
         self.inner.named_property_map()
     }
 
-    fn search_update_queue(&self) -> io::Result<Rc<dyn SearchUpdateQueue>> {
+    fn search_update_queue(&self) -> io::Result<Arc<dyn SearchUpdateQueue>> {
         self.inner.search_update_queue()
     }
 }
 
 impl StoreReadWrite<AnsiPstFile> for AnsiStore {
-    fn pst(&self) -> &AnsiPstFile {
-        self.inner.pst.as_ref()
+    fn pst(&self) -> Arc<AnsiPstFile> {
+        self.inner.pst()
     }
 
-    fn node_btree(&self) -> &PstFileReadWriteNodeBTree<AnsiPstFile> {
-        &self.inner.node_btree
+    fn node_btree(&self) -> Arc<PstFileReadWriteNodeBTree<AnsiPstFile>> {
+        self.inner.node_btree()
     }
 
-    fn block_btree(&self) -> &PstFileReadWriteBlockBTree<AnsiPstFile> {
-        &self.inner.block_btree
+    fn block_btree(&self) -> Arc<PstFileReadWriteBlockBTree<AnsiPstFile>> {
+        self.inner.block_btree()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ltp::{prop_type::PropertyType, LtpError};
+    use crate::test_util::{message_node, TempFile, EMPTY_PST, FIRST_MESSAGE_INDEX};
+    use std::thread;
+
+    #[test]
+    fn test_read_folders_from_threads() {
+        let pst = Arc::new(UnicodePstFile::open_with_readers(EMPTY_PST, 2).unwrap());
+
+        let read_folder = |get_entry_id: fn(&StoreProperties) -> io::Result<EntryId>| {
+            let pst = pst.clone();
+            thread::spawn(move || {
+                let store = UnicodeStore::read(pst)?;
+                let entry_id = get_entry_id(store.properties())?;
+                let folder = store.open_folder(&entry_id)?;
+                folder.properties().display_name()
+            })
+        };
+
+        let ipm_sub_tree = read_folder(StoreProperties::ipm_sub_tree_entry_id);
+        let wastebasket = read_folder(StoreProperties::ipm_wastebasket_entry_id);
+
+        let ipm_sub_tree = ipm_sub_tree.join().unwrap().unwrap();
+        let wastebasket = wastebasket.join().unwrap().unwrap();
+        assert_ne!(ipm_sub_tree, wastebasket);
+    }
+
+    #[test]
+    fn test_root_hierarchy_table_single_reader() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let hierarchy_table = store.root_hierarchy_table().unwrap();
+        assert!(hierarchy_table.rows_matrix().unwrap().next().is_some());
+    }
+
+    #[test]
+    fn test_resolve_entry_id() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let properties = store.properties();
+
+        let ipm_sub_tree = properties.ipm_sub_tree_entry_id().unwrap();
+        let bytes = Vec::<u8>::try_from(&ipm_sub_tree).unwrap();
+        let parsed = EntryId::parse(&bytes).unwrap();
+        assert_eq!(parsed.node_id(), ipm_sub_tree.node_id());
+        assert_eq!(parsed.record_type(), EntryIdType::Folder);
+        let ResolvedEntry::Folder(folder) = store.resolve_entry_id(&parsed).unwrap() else {
+            panic!("IPM subtree should resolve to a folder");
+        };
+        assert_eq!(
+            folder.properties().display_name().unwrap(),
+            store
+                .open_folder(&ipm_sub_tree)
+                .unwrap()
+                .properties()
+                .display_name()
+                .unwrap()
+        );
+
+        let record_key = StoreRecordKey::new(*ipm_sub_tree.record_key());
+        let message = EntryId::new(
+            record_key,
+            NodeId::new(NodeIdType::NormalMessage, 0x10000).unwrap(),
+        );
+        assert_eq!(message.record_type(), EntryIdType::Message);
+        assert!(store.resolve_entry_id(&message).is_err());
+
+        let store_entry_id = EntryId::new(record_key, NID_MESSAGE_STORE);
+        assert_eq!(store_entry_id.record_type(), EntryIdType::Store);
+        let Err(err) = store.resolve_entry_id(&store_entry_id) else {
+            panic!("store entry ID should not resolve");
+        };
+        assert!(matches!(
+            err.into_inner().map(|err| err.downcast::<MessagingError>()),
+            Some(Ok(err)) if matches!(*err, MessagingError::UnresolvableEntryId(0x21))
+        ));
+
+        let wrong_store = EntryId::new(StoreRecordKey::new([0; 16]), ipm_sub_tree.node_id());
+        assert!(store.resolve_entry_id(&wrong_store).is_err());
+        assert!(EntryId::parse(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn test_get_typed() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let properties = store.properties();
+
+        let display_name = properties.get_typed::<String>(PR_DISPLAY_NAME).unwrap();
+        assert_eq!(display_name, Some(properties.display_name().unwrap()));
+
+        assert!(matches!(
+            properties.get_typed::<i32>(PR_DISPLAY_NAME),
+            Err(LtpError::PropertyTypeMismatch {
+                expected: PropertyType::Integer32,
+                ..
+            })
+        ));
+
+        assert_eq!(properties.get_typed_or(0x7FFF, 42_i32).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_store_identity() {
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let properties = store.properties();
+
+        assert_eq!(
+            store.display_name(),
+            Some(properties.display_name().unwrap())
+        );
+        assert_eq!(
+            store.record_key().as_deref(),
+            Some(&properties.record_key().unwrap().record_key()[..])
+        );
+
+        let root_folder = store.root_folder_entry_id().unwrap();
+        assert_eq!(
+            root_folder.node_id(),
+            properties.ipm_sub_tree_entry_id().unwrap().node_id()
+        );
+        assert!(store.open_folder(&root_folder).is_ok());
+
+        assert_eq!(
+            store.creation_time(),
+            properties.get_typed(PR_CREATION_TIME).unwrap()
+        );
+        assert_eq!(
+            store.last_modification_time(),
+            properties.get_typed(PR_LAST_MODIFICATION_TIME).unwrap()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_snapshot() {
+        const SNAPSHOT: &str = include_str!("../../examples/Empty.store.json");
+
+        let pst = Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let hierarchy_table = store.root_hierarchy_table().unwrap();
+        let rows: Vec<_> = hierarchy_table
+            .rows_matrix()
+            .unwrap()
+            .map(|row| {
+                hierarchy_table
+                    .row_values(row)
+                    .map(|values| (row.id(), values))
+            })
+            .collect::<io::Result<_>>()
+            .unwrap();
+
+        let actual = serde_json::json!({
+            "store": store.properties(),
+            "root_hierarchy_table": rows,
+        });
+        let expected: serde_json::Value = serde_json::from_str(SNAPSHOT).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_iter_messages_skips_corrupt_message() {
+        use crate::upgrade::*;
+
+        let path = TempFile::new("iter_messages");
+        let message_id =
+            |index| NodeId::new(NodeIdType::NormalMessage, FIRST_MESSAGE_INDEX + index).unwrap();
+        {
+            let empty = UnicodePstFile::open(EMPTY_PST).unwrap();
+            let mut reader = NodeReader::new(&empty).unwrap();
+            let mut writer =
+                NodeWriter::<UnicodePstFile>::create(&path, empty.header().crypt_method()).unwrap();
+            for node in reader.nodes() {
+                writer.write_node(&reader.read_node(node).unwrap()).unwrap();
+            }
+
+            // The third message has garbage in place of its heap.
+            for index in 0..5 {
+                let message = if index == 2 {
+                    NodeContents {
+                        node: message_id(index),
+                        parent: None,
+                        data: vec![vec![0xAB; 64]],
+                        sub_nodes: Default::default(),
+                    }
+                } else {
+                    message_node(message_id(index), &format!("Message {index}"), "Body").unwrap()
+                };
+                writer.write_node(&message).unwrap();
+            }
+
+            let mut next_node_ids = reader.next_node_ids();
+            next_node_ids[NodeIdType::NormalMessage as usize] = FIRST_MESSAGE_INDEX + 5;
+            writer.finish(&next_node_ids).unwrap();
+        }
+
+        let pst = Arc::new(UnicodePstFile::open(&path).unwrap());
+        let store = UnicodeStore::read(pst).unwrap();
+        let results: Vec<_> = store.iter_messages().collect();
+        assert_eq!(results.len(), 5);
+
+        let mut subjects = Vec::new();
+        for (index, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(message) => subjects.push(
+                    message
+                        .properties()
+                        .get_string8(PR_SUBJECT)
+                        .unwrap()
+                        .unwrap(),
+                ),
+                Err(err) => {
+                    assert_eq!(index, 2);
+                    assert_eq!(err.node(), Some(message_id(2)));
+                }
+            }
+        }
+        assert_eq!(
+            subjects,
+            ["Message 0", "Message 1", "Message 3", "Message 4"]
+        );
+
+        drop(store);
+    }
+
+    #[test]
+    fn test_compute_password_crc() {
+        assert_eq!(compute_password_crc(""), 0);
+        assert_eq!(
+            compute_password_crc("secret"),
+            crc::compute_crc(0, b"secret")
+        );
+
+        // Non-ASCII characters use their Windows-1252 encoding rather than UTF-8.
+        let crc = compute_password_crc("Pässwörd");
+        assert_eq!(crc, crc::compute_crc(0, b"P\xE4ssw\xF6rd"));
+        assert_ne!(crc, crc::compute_crc(0, "Pässwörd".as_bytes()));
+        assert_ne!(crc, compute_password_crc("pässwörd"));
+    }
+
+    #[test]
+    fn test_verify_password_without_password() {
+        let store = UnicodeStore::read(Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap())).unwrap();
+        assert_eq!(store.password_crc().unwrap(), 0);
+        assert!(store.verify_password(""));
+        assert!(!store.verify_password("secret"));
+    }
+
+    #[test]
+    fn test_reset_password_without_password() {
+        // The sample file was created by Outlook without a password, so there is nothing to reset.
+        let path = TempFile::copy_of(EMPTY_PST, "reset_without_password").unwrap();
+        let mut pst = UnicodePstFile::open(&path).unwrap();
+        let old_data = pst.read_node(NID_MESSAGE_STORE).unwrap().data();
+        assert!(!UnicodeStore::reset_password(&mut pst).unwrap());
+        assert_eq!(pst.read_node(NID_MESSAGE_STORE).unwrap().data(), old_data);
+        drop(pst);
+
+        let store = UnicodeStore::read(Arc::new(UnicodePstFile::open(&path).unwrap())).unwrap();
+        assert_eq!(store.password_crc().unwrap(), 0);
+        assert!(store.verify_password(""));
+    }
+
+    /// Copy the sample file with `PidTagPstPassword` on the message store set to `password_crc`.
+    fn write_with_password(path: &std::path::Path, password_crc: u32) -> io::Result<()> {
+        use crate::{
+            ltp::{
+                heap::HeapNodeEditor,
+                prop_context::{PropertyTreeRecordValue, PropertyValueRecord},
+                tree::{allocate_heap_tree, heap_tree_records},
+            },
+            ndb::block::UnicodeDataTree,
+            upgrade::*,
+        };
+
+        let empty = UnicodePstFile::open(EMPTY_PST)?;
+        let mut reader = NodeReader::new(&empty)?;
+        let mut writer = NodeWriter::<UnicodePstFile>::create(path, empty.header().crypt_method())?;
+        for node in reader.nodes() {
+            let mut contents = reader.read_node(node)?;
+            if node == NID_MESSAGE_STORE {
+                let mut heap =
+                    HeapNodeEditor::read(&contents.data, UnicodeDataTree::max_data_size())?;
+                let (_, mut records) = heap_tree_records(&heap, heap.user_root())?;
+                let mut record = Vec::with_capacity(8);
+                PR_PST_PASSWORD.write(&mut record)?;
+                PropertyTreeRecordValue::new(
+                    PropertyType::Integer32,
+                    PropertyValueRecord::Small(password_crc),
+                )
+                .write(&mut record)?;
+                records.retain(|record| record[..2] != PR_PST_PASSWORD.to_le_bytes());
+                records.push(record);
+                records.sort_by_key(|record| u16::from_le_bytes([record[0], record[1]]));
+                let user_root = allocate_heap_tree(&mut heap, 2, 6, &records)?;
+                heap.set_user_root(user_root);
+                contents.data = heap.write()?;
+            }
+            writer.write_node(&contents)?;
+        }
+        writer.finish(&reader.next_node_ids())?;
+        drop(writer);
+
+        UnicodePstFile::open(path)?.lock()?.flush()
+    }
+
+    #[test]
+    fn test_reset_password() {
+        let path = TempFile::new("reset_password");
+        write_with_password(&path, compute_password_crc("Pässwörd")).unwrap();
+
+        {
+            let store = UnicodeStore::read(Arc::new(UnicodePstFile::open(&path).unwrap())).unwrap();
+            assert_eq!(
+                store.password_crc().unwrap(),
+                compute_password_crc("Pässwörd")
+            );
+            assert!(store.verify_password("Pässwörd"));
+            assert!(!store.verify_password(""));
+            assert!(!store.verify_password("pässwörd"));
+        }
+
+        let mut pst = UnicodePstFile::open(&path).unwrap();
+        let old_data = pst.read_node(NID_MESSAGE_STORE).unwrap().data();
+        assert!(UnicodeStore::reset_password(&mut pst).unwrap());
+        assert!(!UnicodeStore::reset_password(&mut pst).unwrap());
+        drop(pst);
+
+        // The store PC is copied to a new block, and reading it checks the CRC of that block.
+        let pst = Arc::new(UnicodePstFile::open(&path).unwrap());
+        assert_ne!(pst.read_node(NID_MESSAGE_STORE).unwrap().data(), old_data);
+        assert_eq!(pst.header().root().amap_is_valid(), AmapStatus::Valid2);
+        let store = UnicodeStore::read(pst).unwrap();
+        assert_eq!(store.password_crc().unwrap(), 0);
+        assert!(store.verify_password(""));
+        assert!(!store.verify_password("Pässwörd"));
+
+        let empty = UnicodeStore::read(Arc::new(UnicodePstFile::open(EMPTY_PST).unwrap())).unwrap();
+        assert_eq!(
+            store.properties().display_name().unwrap(),
+            empty.properties().display_name().unwrap()
+        );
+
+        drop(store);
     }
 }
@@ -0,0 +1,185 @@
+//! Conversions between `PtypTime` values and [`SystemTime`].
+//!
+//! A `PtypTime` value is a Windows `FILETIME`, the number of 100-nanosecond intervals since
+//! January 1, 1601 (UTC).
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of 100-nanosecond intervals between January 1, 1601 and January 1, 1970.
+const FILETIME_UNIX_EPOCH: u64 = 116_444_736_000_000_000;
+
+const FILETIME_TICKS_PER_SECOND: u64 = 10_000_000;
+
+const NANOS_PER_FILETIME_TICK: u64 = 100;
+
+fn ticks_to_duration(ticks: u64) -> Duration {
+    let seconds = ticks / FILETIME_TICKS_PER_SECOND;
+    let nanos = (ticks % FILETIME_TICKS_PER_SECOND) * NANOS_PER_FILETIME_TICK;
+    Duration::new(seconds, nanos as u32)
+}
+
+fn duration_to_ticks(duration: Duration) -> u64 {
+    duration
+        .as_secs()
+        .saturating_mul(FILETIME_TICKS_PER_SECOND)
+        .saturating_add(u64::from(duration.subsec_nanos()) / NANOS_PER_FILETIME_TICK)
+}
+
+/// Convert a `FILETIME` to a [`SystemTime`]. A value of `0` means the time is not set, so this
+/// returns `None`, as it does if the time cannot be represented on this platform.
+pub fn filetime_to_system_time(ft: u64) -> Option<SystemTime> {
+    if ft == 0 {
+        return None;
+    }
+
+    if ft >= FILETIME_UNIX_EPOCH {
+        UNIX_EPOCH.checked_add(ticks_to_duration(ft - FILETIME_UNIX_EPOCH))
+    } else {
+        UNIX_EPOCH.checked_sub(ticks_to_duration(FILETIME_UNIX_EPOCH - ft))
+    }
+}
+
+/// Convert a [`SystemTime`] to a `FILETIME`, saturating at the limits of the `FILETIME` range.
+pub fn system_time_to_filetime(t: SystemTime) -> u64 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(after) => FILETIME_UNIX_EPOCH.saturating_add(duration_to_ticks(after)),
+        Err(before) => FILETIME_UNIX_EPOCH.saturating_sub(duration_to_ticks(before.duration())),
+    }
+}
+
+/// A `FILETIME` broken down into the Gregorian calendar date and time of day in UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UtcDateTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// The remaining 100-nanosecond intervals after `second`.
+    pub ticks: u32,
+}
+
+impl UtcDateTime {
+    pub fn from_filetime(ft: i64) -> Self {
+        const TICKS_PER_SECOND: i64 = FILETIME_TICKS_PER_SECOND as i64;
+        const SECONDS_PER_DAY: i64 = 86_400;
+
+        let seconds = ft.div_euclid(TICKS_PER_SECOND);
+        let ticks = ft.rem_euclid(TICKS_PER_SECOND) as u32;
+        let days = seconds.div_euclid(SECONDS_PER_DAY);
+        let seconds = seconds.rem_euclid(SECONDS_PER_DAY);
+
+        let (year, month, day) = civil_from_days(days);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (seconds / 3_600) as u8,
+            minute: ((seconds / 60) % 60) as u8,
+            second: (seconds % 60) as u8,
+            ticks,
+        }
+    }
+}
+
+/// Number of days between 1601-01-01, the `FILETIME` epoch, and 1970-01-01.
+const DAYS_FROM_1601_TO_1970: i64 = 134_774;
+
+/// Convert a count of days since 1601-01-01 to a Gregorian `(year, month, day)`.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days - DAYS_FROM_1601_TO_1970 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month as u8, day as u8)
+}
+
+/// Convert a Gregorian `(year, month, day)` to a count of days since 1601-01-01.
+pub(crate) fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let (month, day) = (i64::from(month), i64::from(day));
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468 + DAYS_FROM_1601_TO_1970
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-01 00:00:00 UTC
+    const JANUARY_1_2024_FILETIME: u64 = 133_485_408_000_000_000;
+    const JANUARY_1_2024_UNIX_SECONDS: u64 = 1_704_067_200;
+
+    #[test]
+    fn test_filetime_to_system_time() {
+        let time = filetime_to_system_time(JANUARY_1_2024_FILETIME).unwrap();
+        assert_eq!(
+            time.duration_since(UNIX_EPOCH).unwrap(),
+            Duration::from_secs(JANUARY_1_2024_UNIX_SECONDS)
+        );
+
+        let time = filetime_to_system_time(FILETIME_UNIX_EPOCH).unwrap();
+        assert_eq!(time, UNIX_EPOCH);
+
+        assert_eq!(filetime_to_system_time(0), None);
+    }
+
+    #[test]
+    fn test_system_time_to_filetime() {
+        let time = UNIX_EPOCH + Duration::from_secs(JANUARY_1_2024_UNIX_SECONDS);
+        assert_eq!(system_time_to_filetime(time), JANUARY_1_2024_FILETIME);
+        assert_eq!(system_time_to_filetime(UNIX_EPOCH), FILETIME_UNIX_EPOCH);
+    }
+
+    #[test]
+    fn test_utc_date_time() {
+        let time = UtcDateTime::from_filetime(JANUARY_1_2024_FILETIME as i64 + 36_610_000_123);
+        assert_eq!(
+            time,
+            UtcDateTime {
+                year: 2024,
+                month: 1,
+                day: 1,
+                hour: 1,
+                minute: 1,
+                second: 1,
+                ticks: 123,
+            }
+        );
+
+        let time = UtcDateTime::from_filetime(0);
+        assert_eq!((time.year, time.month, time.day), (1601, 1, 1));
+    }
+
+    #[test]
+    fn test_civil_days() {
+        assert_eq!(civil_from_days(0), (1601, 1, 1));
+        assert_eq!(days_from_civil(1601, 1, 1), 0);
+        assert_eq!(days_from_civil(1970, 1, 1), DAYS_FROM_1601_TO_1970);
+        for days in [0, 59, 60, 365, 146_096, 146_097, 157_000] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ft = JANUARY_1_2024_FILETIME + 1_234_567;
+        let time = filetime_to_system_time(ft).unwrap();
+        assert_eq!(system_time_to_filetime(time), ft);
+    }
+}
@@ -2,14 +2,28 @@
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::{
-    collections::{btree_map, BTreeMap, VecDeque},
+    borrow::Cow,
+    collections::{btree_map, BTreeMap, BTreeSet, VecDeque},
+    fmt::Debug,
     io::{self, Cursor, Read, Seek, SeekFrom, Write},
     iter,
 };
 use tracing::error;
 
-use super::{block_id::*, block_ref::*, byte_index::*, node_id::*, page::*, read_write::*, *};
-use crate::{AnsiPstFile, PstFile, PstFileReadWriteBlockBTree, PstReader, UnicodePstFile};
+use super::{
+    block_id::*,
+    block_ref::*,
+    byte_index::*,
+    node_id::*,
+    options::{enforce, Check, Limits},
+    page::*,
+    read_write::*,
+    *,
+};
+use crate::{
+    block_sig::compute_sig, AnsiPstFile, PstFile, PstFileReadWriteBlockBTree, PstReader,
+    UnicodePstFile,
+};
 
 pub const MAX_BLOCK_SIZE: u16 = 8192;
 
@@ -19,6 +33,28 @@ pub const fn block_size(size: u16) -> u16 {
     size.div_ceil(64) * 64
 }
 
+/// Decode the data from a block with the file's [`NdbCryptMethod`]. Data which is not encoded is
+/// returned as-is, so a borrowed buffer is only copied if it needs to be decoded.
+pub fn decode_block_data(
+    data: Cow<'_, [u8]>,
+    encoding: NdbCryptMethod,
+    cyclic_key: u32,
+) -> Cow<'_, [u8]> {
+    match encoding {
+        NdbCryptMethod::Cyclic => {
+            let mut data = data.into_owned();
+            crate::encode::cyclic::encode_decode_block(&mut data, cyclic_key);
+            Cow::Owned(data)
+        }
+        NdbCryptMethod::Permute => {
+            let mut data = data.into_owned();
+            crate::encode::permute::decode_block(&mut data);
+            Cow::Owned(data)
+        }
+        _ => data,
+    }
+}
+
 /// [BLOCKTRAILER](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/a14943ef-70c2-403f-898c-5bc3747117e1)
 pub trait BlockTrailer {
     type BlockId: BlockId;
@@ -31,7 +67,7 @@ pub trait BlockTrailer {
     fn verify_block_id(&self, is_internal: bool) -> NdbResult<()>;
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct UnicodeBlockTrailer {
     size: u16,
     signature: u16,
@@ -120,7 +156,7 @@ impl BlockTrailerReadWrite for UnicodeBlockTrailer {
     }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct AnsiBlockTrailer {
     size: u16,
     signature: u16,
@@ -218,7 +254,7 @@ pub trait Block {
     fn trailer(&self) -> &Self::Trailer;
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, PartialEq, Eq)]
 pub struct UnicodeDataBlock {
     encoding: NdbCryptMethod,
     data: Vec<u8>,
@@ -265,13 +301,25 @@ impl BlockReadWrite for UnicodeDataBlock {
     }
 }
 
+impl Debug for UnicodeDataBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "UnicodeDataBlock {{ {:?}, size: 0x{:X}, {:?} }}",
+            self.encoding,
+            self.data.len(),
+            self.trailer
+        )
+    }
+}
+
 impl From<UnicodeDataBlock> for Vec<u8> {
     fn from(value: UnicodeDataBlock) -> Self {
         value.data
     }
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, PartialEq, Eq)]
 pub struct AnsiDataBlock {
     encoding: NdbCryptMethod,
     data: Vec<u8>,
@@ -314,6 +362,18 @@ impl BlockReadWrite for AnsiDataBlock {
     }
 }
 
+impl Debug for AnsiDataBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "AnsiDataBlock {{ {:?}, size: 0x{:X}, {:?} }}",
+            self.encoding,
+            self.data.len(),
+            self.trailer
+        )
+    }
+}
+
 impl From<AnsiDataBlock> for Vec<u8> {
     fn from(value: AnsiDataBlock) -> Self {
         value.data
@@ -337,7 +397,7 @@ pub trait IntermediateTreeBlock {
     fn trailer(&self) -> &Self::Trailer;
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct DataTreeBlockHeader {
     level: u8,
     entry_count: u16,
@@ -404,7 +464,7 @@ where
     fn block(&self) -> <Pst as PstFile>::BlockId;
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct UnicodeDataTreeEntry(UnicodeBlockId);
 
 impl IntermediateTreeEntry for UnicodeDataTreeEntry {}
@@ -443,7 +503,7 @@ impl From<UnicodeDataTreeEntry> for UnicodeBlockId {
     }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct AnsiDataTreeEntry(AnsiBlockId);
 
 impl IntermediateTreeEntry for AnsiDataTreeEntry {}
@@ -484,6 +544,7 @@ impl From<AnsiDataTreeEntry> for AnsiBlockId {
 
 /// [XBLOCK](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/5b7a6935-e83d-4917-9f62-6ce3707f09e0)
 /// / [XXBLOCK](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/061b6ac4-d1da-468c-b75d-0303a0a8f468)
+#[derive(Clone, PartialEq, Eq, Debug)]
 struct DataTreeBlockInner<Entry, Trailer>
 where
     Entry: IntermediateTreeEntry,
@@ -514,6 +575,7 @@ where
     }
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct UnicodeDataTreeBlock {
     inner: DataTreeBlockInner<UnicodeDataTreeEntry, UnicodeBlockTrailer>,
 }
@@ -548,6 +610,7 @@ impl IntermediateTreeBlockReadWrite for UnicodeDataTreeBlock {
     }
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct AnsiDataTreeBlock {
     inner: DataTreeBlockInner<AnsiDataTreeEntry, AnsiBlockTrailer>,
 }
@@ -584,6 +647,72 @@ impl IntermediateTreeBlockReadWrite for AnsiDataTreeBlock {
 
 pub type DataBlockCache<Pst> = BTreeMap<<Pst as PstFile>::BlockId, DataTree<Pst>>;
 
+/// Reserves space for new blocks in a file and writes them, see [`DataTree::build`].
+pub trait BlockAllocator<Pst>
+where
+    Pst: PstFile,
+{
+    /// The [`NdbCryptMethod`] used to encode new data blocks.
+    fn encoding(&self) -> NdbCryptMethod;
+
+    /// Allocate a new BID and file offset for a block with `size` bytes of data.
+    fn allocate_block(
+        &mut self,
+        is_internal: bool,
+        size: u16,
+    ) -> io::Result<<Pst as PstFile>::BlockBTreeEntry>;
+
+    /// Write a block at the location returned by [`Self::allocate_block`].
+    fn write_block(
+        &mut self,
+        block: &DataTree<Pst>,
+        entry: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<()>;
+}
+
+/// State for one walk over the intermediate blocks of a data tree or sub-node tree. It enforces
+/// the [`Limits`] on depth, and remembers which intermediate blocks it has already visited so that
+/// a block which is reachable from itself fails with [`NdbError::CyclicReference`].
+struct TreeWalk {
+    limits: Limits,
+    depth: usize,
+    visited: BTreeSet<u64>,
+}
+
+impl TreeWalk {
+    fn new() -> Self {
+        Self {
+            limits: Limits::current(),
+            depth: 0,
+            visited: Default::default(),
+        }
+    }
+
+    fn enter_data_tree(&mut self, block_id: impl BlockId) -> NdbResult<()> {
+        self.depth += 1;
+        self.limits.check_data_tree_depth(self.depth)?;
+        self.visit(block_id)
+    }
+
+    fn enter_sub_node_tree(&mut self, block_id: impl BlockId) -> NdbResult<()> {
+        self.depth += 1;
+        self.limits.check_sub_node_depth(self.depth)?;
+        self.visit(block_id)
+    }
+
+    fn visit(&mut self, block_id: impl BlockId) -> NdbResult<()> {
+        let block_id = block_id.into_u64();
+        if !self.visited.insert(block_id) {
+            return Err(NdbError::CyclicReference(block_id));
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+}
+
 pub enum DataTree<Pst>
 where
     Pst: PstFile,
@@ -592,6 +721,20 @@ where
     Leaf(Box<<Pst as PstFile>::DataBlock>),
 }
 
+impl<Pst> Debug for DataTree<Pst>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::DataTreeBlock: Debug,
+    <Pst as PstFile>::DataBlock: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Intermediate(block) => f.debug_tuple("Intermediate").field(block).finish(),
+            Self::Leaf(block) => f.debug_tuple("Leaf").field(block).finish(),
+        }
+    }
+}
+
 impl<Pst> DataTree<Pst>
 where
     Pst: PstFile,
@@ -609,16 +752,104 @@ where
     where
         R: PstReader,
     {
-        f.seek(SeekFrom::Start(block.block().index().index().into()))?;
+        let offset = block.block().index().index().into();
+        f.seek(SeekFrom::Start(offset))?;
 
-        let block_size = block_size(
-            block.size() + <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE,
-        );
-        let mut data = vec![0; block_size as usize];
+        let mut data = vec![0; Self::padded_size(block) as usize];
         f.read_exact(&mut data)?;
-        let mut cursor = Cursor::new(data);
+        Self::read_from_slice(&data, encoding, block).map_err(error_at(offset))
+    }
+
+    /// Look up `block_id` in the [`BlockBTree`] and read the block it refers to, see
+    /// [`Self::read`]. Leaf blocks are decoded with `encoding`, and XBLOCKs and XXBLOCKs are
+    /// returned as [`DataTree::Intermediate`] without reading any of their child blocks.
+    pub fn read_block<R>(
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &PstFileReadWriteBlockBTree<Pst>,
+        page_cache: &mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+        block_id: <Pst as PstFile>::BlockId,
+    ) -> io::Result<Self>
+    where
+        R: PstReader,
+        <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey> + BlockIdReadWrite,
+        <Pst as PstFile>::ByteIndex: ByteIndexReadWrite,
+        <Pst as PstFile>::BlockRef: BlockRefReadWrite,
+        <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+        <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+        <Pst as PstFile>::BlockBTree: RootBTreeReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::Entry: BTreeEntryReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+            RootBTreeIntermediatePageReadWrite<
+                Pst,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::Entry,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+            >,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage:
+            RootBTreeLeafPageReadWrite<Pst> + BTreePageReadWrite,
+    {
+        let entry = block_btree.find_entry(f, block_id.search_key(), page_cache)?;
+        Self::read(f, encoding, &entry)
+    }
+
+    /// Read the block with [`PstFile::read_at`], which borrows the block straight from the file
+    /// mapping if it was opened with `open_mapped`. Leaf blocks are only copied to decode them.
+    pub fn read_at(
+        pst: &Pst,
+        encoding: NdbCryptMethod,
+        block: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<Self> {
+        let data = pst.read_at(
+            block.block().index().index().into(),
+            Self::padded_size(block) as usize,
+        )?;
+        let _scope = pst.reader().parse_scope();
+        Self::read_from_slice(&data, encoding, block)
+    }
+
+    fn padded_size(block: &<Pst as PstFile>::BlockBTreeEntry) -> u16 {
+        block_size(block.size() + <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE)
+    }
+
+    /// Decode a block which was read from the file at the offset in its [`BlockBTree`] entry. The
+    /// CRC is checked while reading the block, and then the `bid` and `wSig` in the trailer are
+    /// checked against the entry, so a stale or misplaced block is not mistaken for this one.
+    pub(crate) fn read_from_slice(
+        data: &[u8],
+        encoding: NdbCryptMethod,
+        entry: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<Self> {
+        let block = Self::decode_from_slice(data, encoding, entry)?;
+
+        let block_id = entry.block().block();
+        let trailer_block_id = block.block_id();
+        if trailer_block_id.into_u64() != block_id.into_u64() {
+            return Err(NdbError::MismatchBlockTrailerId(trailer_block_id.into_u64()).into());
+        }
+
+        let signature = match &block {
+            Self::Intermediate(block) => block.trailer().signature(),
+            Self::Leaf(block) => block.trailer().signature(),
+        };
+        let offset: u64 = entry.block().index().index().into();
+        let expected = compute_sig(
+            (offset & u64::from(u32::MAX)) as u32,
+            (block_id.into_u64() & u64::from(u32::MAX)) as u32,
+        );
+        if signature != expected {
+            enforce(Check::Signature, NdbError::InvalidBlockSignature(signature))?;
+        }
+
+        Ok(block)
+    }
 
+    fn decode_from_slice(
+        data: &[u8],
+        encoding: NdbCryptMethod,
+        block: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<Self> {
         let block = if block.block().block().is_internal() {
+            let mut cursor = Cursor::new(data);
             let header = DataTreeBlockHeader::read(&mut cursor)?;
             cursor.seek(SeekFrom::Start(0))?;
             let block = <<Pst as PstFile>::DataTreeBlock as IntermediateTreeBlockReadWrite>::read(
@@ -628,8 +859,8 @@ where
             )?;
             Self::Intermediate(Box::new(block))
         } else {
-            let block = <<Pst as PstFile>::DataBlock as BlockReadWrite>::read(
-                &mut cursor,
+            let block = <<Pst as PstFile>::DataBlock as BlockReadWrite>::read_from_slice(
+                data,
                 block.size(),
                 encoding,
             )?;
@@ -639,6 +870,134 @@ where
         Ok(block)
     }
 
+    /// The BID of the root block, which is stored in the node or sub-node.
+    pub fn block_id(&self) -> <Pst as PstFile>::BlockId {
+        match self {
+            Self::Intermediate(block) => block.trailer().block_id(),
+            Self::Leaf(block) => block.trailer().block_id(),
+        }
+    }
+
+    /// The largest amount of data which fits in a single data block.
+    pub fn max_data_size() -> usize {
+        usize::from(
+            MAX_BLOCK_SIZE - <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE,
+        )
+    }
+
+    /// Split `data` into data blocks of up to [`Self::max_data_size`] bytes, then allocate and
+    /// write each of them with `allocator`. If there is more than one data block, this also
+    /// writes an XBLOCK, or an XXBLOCK with multiple XBLOCKs if the data blocks do not fit in a
+    /// single XBLOCK. Empty data does not need any blocks, the node should use a BID of 0.
+    pub fn build(data: &[u8], allocator: &mut dyn BlockAllocator<Pst>) -> io::Result<Self> {
+        if data.is_empty() {
+            return Err(NdbError::InvalidBlockSize(0).into());
+        }
+        if u32::try_from(data.len()).is_err() {
+            return Err(NdbError::DataTreeTooLarge(data.len() as u64).into());
+        }
+
+        Self::build_from_blocks(data.chunks(Self::max_data_size()), allocator)
+    }
+
+    /// Build a data tree from data which has already been split into blocks, e.g. the rows of a
+    /// Table Context, which must not span more than one block.
+    pub fn build_from_blocks<'a>(
+        blocks: impl IntoIterator<Item = &'a [u8]>,
+        allocator: &mut dyn BlockAllocator<Pst>,
+    ) -> io::Result<Self> {
+        let encoding = allocator.encoding();
+        let mut root = None;
+        let mut level = Vec::new();
+        for data in blocks {
+            let size = u16::try_from(data.len())
+                .ok()
+                .filter(|&size| usize::from(size) <= Self::max_data_size())
+                .ok_or(NdbError::InvalidBlockSize(u16::MAX))?;
+            let entry = allocator.allocate_block(false, size)?;
+            let trailer = Self::block_trailer(&entry)?;
+            let block = <<Pst as PstFile>::DataBlock as BlockReadWrite>::new(
+                encoding,
+                data.to_vec(),
+                trailer,
+            )?;
+            let block = Self::Leaf(Box::new(block));
+            allocator.write_block(&block, &entry)?;
+            level.push((trailer.block_id(), u64::from(size)));
+            root = Some(block);
+        }
+
+        let max_entries = usize::from(
+            (MAX_BLOCK_SIZE
+                - <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE
+                - <DataTreeBlockHeader as IntermediateTreeHeaderReadWrite>::HEADER_SIZE)
+                / <<Pst as PstFile>::DataTreeEntry as IntermediateTreeEntryReadWrite>::ENTRY_SIZE,
+        );
+
+        for depth in 1..=2 {
+            if level.len() <= 1 {
+                break;
+            }
+
+            let mut next_level = Vec::with_capacity(level.len().div_ceil(max_entries));
+            for chunk in level.chunks(max_entries) {
+                let total_size: u64 = chunk.iter().map(|(_, size)| size).sum();
+                let header = DataTreeBlockHeader::new(
+                    depth,
+                    chunk.len() as u16,
+                    u32::try_from(total_size)
+                        .map_err(|_| NdbError::DataTreeTooLarge(total_size))?,
+                );
+                let entries = chunk
+                    .iter()
+                    .map(|(block_id, _)| {
+                        <<Pst as PstFile>::DataTreeEntry as IntermediateDataTreeEntry<Pst>>::new(
+                            *block_id,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let size = <DataTreeBlockHeader as IntermediateTreeHeaderReadWrite>::HEADER_SIZE
+                    + entries.len() as u16
+                        * <<Pst as PstFile>::DataTreeEntry as IntermediateTreeEntryReadWrite>::ENTRY_SIZE;
+                let entry = allocator.allocate_block(true, size)?;
+                let trailer = Self::block_trailer(&entry)?;
+                let block =
+                    <<Pst as PstFile>::DataTreeBlock as IntermediateTreeBlockReadWrite>::new(
+                        header, entries, trailer,
+                    )?;
+                let block = Self::Intermediate(Box::new(block));
+                allocator.write_block(&block, &entry)?;
+                next_level.push((trailer.block_id(), total_size));
+                root = Some(block);
+            }
+            level = next_level;
+        }
+
+        match (root, level.as_slice()) {
+            (Some(root), [_]) => Ok(root),
+            (_, []) => Err(NdbError::InvalidInternalBlockEntryCount(0).into()),
+            _ => Err(NdbError::DataTreeTooLarge(level.iter().map(|(_, size)| size).sum()).into()),
+        }
+    }
+
+    fn block_trailer(
+        entry: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> NdbResult<<Pst as PstFile>::BlockTrailer> {
+        let block = entry.block();
+        let offset: u64 = block.index().index().into();
+        let block_id = block.block();
+        let signature = compute_sig(
+            (offset & u64::from(u32::MAX)) as u32,
+            (block_id.into_u64() & u64::from(u32::MAX)) as u32,
+        );
+        <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::new(
+            entry.size(),
+            signature,
+            0,
+            block_id,
+        )
+    }
+
     pub fn write<W: Write + Seek>(
         &self,
         f: &mut W,
@@ -680,30 +1039,97 @@ where
             RootBTreeLeafPageReadWrite<Pst> + BTreePageReadWrite,
     {
         match self {
-            Self::Intermediate(block, ..) => {
-                let mut blocks = Vec::with_capacity(block.entries().len());
+            Self::Intermediate(..) => {
+                let mut blocks = Vec::new();
+                let mut total_size = 0;
+                self.collect_blocks(
+                    f,
+                    encoding,
+                    block_btree,
+                    page_cache,
+                    block_cache,
+                    &mut TreeWalk::new(),
+                    &mut blocks,
+                    &mut total_size,
+                )?;
+                Ok(Box::new(blocks.into_iter()))
+            }
+            Self::Leaf(block) => Ok(Box::new(Some(block.as_ref()).cloned().into_iter())),
+        }
+    }
+
+    /// Append the data blocks under this tree to `blocks`, checking the [`Limits`] and looking for
+    /// cycles as we go, so that a hostile data tree fails instead of recursing without bound.
+    #[allow(clippy::too_many_arguments)]
+    fn collect_blocks<R>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &PstFileReadWriteBlockBTree<Pst>,
+        page_cache: &mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+        block_cache: &mut DataBlockCache<Pst>,
+        walk: &mut TreeWalk,
+        blocks: &mut Vec<<Pst as PstFile>::DataBlock>,
+        total_size: &mut u64,
+    ) -> io::Result<()>
+    where
+        R: PstReader,
+        <Pst as PstFile>::DataBlock: Clone,
+        <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey> + BlockIdReadWrite,
+        <Pst as PstFile>::ByteIndex: ByteIndexReadWrite,
+        <Pst as PstFile>::BlockRef: BlockRefReadWrite,
+        <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+        <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+        <Pst as PstFile>::BlockBTree: RootBTreeReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::Entry: BTreeEntryReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+            RootBTreeIntermediatePageReadWrite<
+                Pst,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::Entry,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+            >,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage:
+            RootBTreeLeafPageReadWrite<Pst> + BTreePageReadWrite,
+    {
+        match self {
+            Self::Intermediate(block) => {
+                walk.enter_data_tree(block.trailer().block_id())?;
+                walk.limits
+                    .check_value_size(u64::from(block.header().total_size()))?;
                 for entry in block.entries() {
                     let data_tree = match block_cache.remove(&entry.block()) {
                         Some(entry) => entry,
-                        None => {
-                            let data_block = block_btree.find_entry(
-                                f,
-                                entry.block().search_key(),
-                                page_cache,
-                            )?;
-                            Self::read(&mut *f, encoding, &data_block)?
-                        }
+                        None => Self::read_block(
+                            &mut *f,
+                            encoding,
+                            block_btree,
+                            page_cache,
+                            entry.block(),
+                        )?,
                     };
-                    let entries = data_tree
-                        .blocks(f, encoding, block_btree, page_cache, block_cache)
-                        .map(|blocks| blocks.collect::<Vec<_>>());
+                    let result = data_tree.collect_blocks(
+                        f,
+                        encoding,
+                        block_btree,
+                        page_cache,
+                        block_cache,
+                        walk,
+                        blocks,
+                        total_size,
+                    );
                     block_cache.insert(entry.block(), data_tree);
-                    blocks.push(entries?);
+                    result?;
                 }
-                Ok(Box::new(blocks.into_iter().flatten()))
+                walk.leave();
+            }
+            Self::Leaf(block) => {
+                *total_size += block.data().len() as u64;
+                walk.limits.check_value_size(*total_size)?;
+                blocks.push(block.as_ref().clone());
+                walk.limits.check_block_count(blocks.len())?;
             }
-            Self::Leaf(block) => Ok(Box::new(Some(block.as_ref()).cloned().into_iter())),
         }
+        Ok(())
     }
 
     pub fn nth<R>(
@@ -715,6 +1141,46 @@ where
         page_cache: &mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
         block_cache: &mut DataBlockCache<Pst>,
     ) -> io::Result<Option<Vec<u8>>>
+    where
+        R: PstReader,
+        <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey> + BlockIdReadWrite,
+        <Pst as PstFile>::ByteIndex: ByteIndexReadWrite,
+        <Pst as PstFile>::BlockRef: BlockRefReadWrite,
+        <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+        <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+        <Pst as PstFile>::BlockBTree: RootBTreeReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::Entry: BTreeEntryReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+            RootBTreeIntermediatePageReadWrite<
+                Pst,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::Entry,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+            >,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage:
+            RootBTreeLeafPageReadWrite<Pst> + BTreePageReadWrite,
+    {
+        self.nth_in_walk(
+            n,
+            f,
+            encoding,
+            block_btree,
+            page_cache,
+            block_cache,
+            &mut TreeWalk::new(),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn nth_in_walk<R>(
+        &self,
+        n: usize,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &PstFileReadWriteBlockBTree<Pst>,
+        page_cache: &mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+        block_cache: &mut DataBlockCache<Pst>,
+        walk: &mut TreeWalk,
+    ) -> io::Result<Option<Vec<u8>>>
     where
         R: PstReader,
         <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey> + BlockIdReadWrite,
@@ -734,18 +1200,64 @@ where
             RootBTreeLeafPageReadWrite<Pst> + BTreePageReadWrite,
     {
         Ok(Some(match self {
-            Self::Intermediate(_) => {
-                let Some(data_block) = self
-                    .sub_entries(f, encoding, block_btree, page_cache, block_cache)?
-                    .nth(n)
-                else {
+            Self::Intermediate(block) => {
+                walk.enter_data_tree(block.trailer().block_id())?;
+
+                // Only descend into the child block which contains the nth leaf, so we don't have
+                // to look up every leaf block in the data tree.
+                let mut n = n;
+                let mut data_block = None;
+                for entry in block.entries() {
+                    if entry.block().is_internal() {
+                        let data_tree = match block_cache.remove(&entry.block()) {
+                            Some(data_tree) => data_tree,
+                            None => Self::read_block(
+                                &mut *f,
+                                encoding,
+                                block_btree,
+                                page_cache,
+                                entry.block(),
+                            )?,
+                        };
+                        let count = match &data_tree {
+                            Self::Intermediate(block) => block.entries().len(),
+                            Self::Leaf(_) => 1,
+                        };
+                        if n < count {
+                            let result = data_tree.nth_in_walk(
+                                n,
+                                f,
+                                encoding,
+                                block_btree,
+                                page_cache,
+                                block_cache,
+                                walk,
+                            );
+                            block_cache.insert(entry.block(), data_tree);
+                            return result;
+                        }
+                        block_cache.insert(entry.block(), data_tree);
+                        n -= count;
+                    } else if n == 0 {
+                        data_block = Some(entry.block());
+                        break;
+                    } else {
+                        n -= 1;
+                    }
+                }
+
+                let Some(data_block) = data_block else {
                     return Ok(None);
                 };
 
-                let data_tree = match block_cache.entry(data_block.block().block()) {
-                    btree_map::Entry::Vacant(entry) => {
-                        entry.insert(Self::read(&mut *f, encoding, &data_block)?)
-                    }
+                let data_tree = match block_cache.entry(data_block) {
+                    btree_map::Entry::Vacant(entry) => entry.insert(Self::read_block(
+                        &mut *f,
+                        encoding,
+                        block_btree,
+                        page_cache,
+                        data_block,
+                    )?),
                     btree_map::Entry::Occupied(entry) => entry.into_mut(),
                 };
 
@@ -828,37 +1340,96 @@ where
             RootBTreeLeafPageReadWrite<Pst> + BTreePageReadWrite,
     {
         match self {
-            Self::Intermediate(block, ..) => {
-                let mut blocks = Vec::with_capacity(block.entries().len());
-                for entry in block.entries() {
-                    if entry.block().is_internal() {
-                        let data_tree = match block_cache.remove(&entry.block()) {
-                            Some(entry) => entry,
-                            None => {
-                                let data_block = block_btree.find_entry(
-                                    f,
-                                    entry.block().search_key(),
-                                    page_cache,
-                                )?;
-                                Self::read(&mut *f, encoding, &data_block)?
-                            }
-                        };
-                        let entries = data_tree
-                            .sub_entries(f, encoding, block_btree, page_cache, block_cache)
-                            .map(|entries| entries.collect::<Vec<_>>());
-                        block_cache.insert(entry.block(), data_tree);
-                        blocks.push(entries?);
-                    } else {
-                        let data_block =
-                            block_btree.find_entry(f, entry.block().search_key(), page_cache)?;
-                        blocks.push(vec![data_block]);
-                    }
-                }
-                Ok(Box::new(blocks.into_iter().flatten()))
+            Self::Intermediate(..) => {
+                let mut blocks = Vec::new();
+                let mut total_size = 0;
+                self.collect_sub_entries(
+                    f,
+                    encoding,
+                    block_btree,
+                    page_cache,
+                    block_cache,
+                    &mut TreeWalk::new(),
+                    &mut blocks,
+                    &mut total_size,
+                )?;
+                Ok(Box::new(blocks.into_iter()))
             }
             Self::Leaf(_) => Ok(Box::new(iter::empty())),
         }
     }
+
+    /// Append the [`BlockBTree`] entries of the data blocks under this tree to `blocks`, with the
+    /// same checks as [`Self::collect_blocks`].
+    #[allow(clippy::too_many_arguments)]
+    fn collect_sub_entries<R>(
+        &self,
+        f: &mut R,
+        encoding: NdbCryptMethod,
+        block_btree: &PstFileReadWriteBlockBTree<Pst>,
+        page_cache: &mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+        block_cache: &mut DataBlockCache<Pst>,
+        walk: &mut TreeWalk,
+        blocks: &mut Vec<<Pst as PstFile>::BlockBTreeEntry>,
+        total_size: &mut u64,
+    ) -> io::Result<()>
+    where
+        R: PstReader,
+        <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey> + BlockIdReadWrite,
+        <Pst as PstFile>::ByteIndex: ByteIndexReadWrite,
+        <Pst as PstFile>::BlockRef: BlockRefReadWrite,
+        <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+        <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+        <Pst as PstFile>::BlockBTree: RootBTreeReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::Entry: BTreeEntryReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+            RootBTreeIntermediatePageReadWrite<
+                Pst,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::Entry,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+            >,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage:
+            RootBTreeLeafPageReadWrite<Pst> + BTreePageReadWrite,
+    {
+        let Self::Intermediate(block) = self else {
+            return Ok(());
+        };
+
+        walk.enter_data_tree(block.trailer().block_id())?;
+        walk.limits
+            .check_value_size(u64::from(block.header().total_size()))?;
+        for entry in block.entries() {
+            if entry.block().is_internal() {
+                let data_tree = match block_cache.remove(&entry.block()) {
+                    Some(entry) => entry,
+                    None => {
+                        Self::read_block(&mut *f, encoding, block_btree, page_cache, entry.block())?
+                    }
+                };
+                let result = data_tree.collect_sub_entries(
+                    f,
+                    encoding,
+                    block_btree,
+                    page_cache,
+                    block_cache,
+                    walk,
+                    blocks,
+                    total_size,
+                );
+                block_cache.insert(entry.block(), data_tree);
+                result?;
+            } else {
+                let data_block =
+                    block_btree.find_entry(f, entry.block().search_key(), page_cache)?;
+                *total_size += u64::from(data_block.size());
+                walk.limits.check_value_size(*total_size)?;
+                blocks.push(data_block);
+                walk.limits.check_block_count(blocks.len())?;
+            }
+        }
+        walk.leave();
+        Ok(())
+    }
 }
 
 struct DataTreeCursor<Pst>
@@ -994,13 +1565,13 @@ where
 pub type UnicodeDataTree = DataTree<UnicodePstFile>;
 pub type AnsiDataTree = DataTree<AnsiPstFile>;
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 struct SubNodeTreeBlockHeader {
     level: u8,
     entry_count: u16,
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct UnicodeSubNodeTreeBlockHeader(SubNodeTreeBlockHeader);
 
 impl UnicodeSubNodeTreeBlockHeader {
@@ -1033,7 +1604,10 @@ impl IntermediateTreeHeaderReadWrite for UnicodeSubNodeTreeBlockHeader {
 
         let padding = f.read_u32::<LittleEndian>()?;
         if padding != 0 {
-            return Err(NdbError::InvalidSubNodeBlockPadding(padding).into());
+            enforce(
+                Check::Padding,
+                NdbError::InvalidSubNodeBlockPadding(padding),
+            )?;
         }
 
         Ok(Self::new(level, entry_count))
@@ -1053,7 +1627,7 @@ impl SubNodeTreeBlockHeaderReadWrite for UnicodeSubNodeTreeBlockHeader {
     }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct AnsiSubNodeTreeBlockHeader(SubNodeTreeBlockHeader);
 
 impl AnsiSubNodeTreeBlockHeader {
@@ -1101,7 +1675,7 @@ impl SubNodeTreeBlockHeaderReadWrite for AnsiSubNodeTreeBlockHeader {
 }
 
 /// [SLENTRY (Leaf Block Entry)](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/85c4d943-0779-43c5-bd98-61dc9bb5dfd6)
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct LeafSubNodeTreeEntry<Block>
 where
     Block: BlockId,
@@ -1184,7 +1758,7 @@ impl IntermediateTreeEntryReadWrite for AnsiLeafSubNodeTreeEntry {
 }
 
 /// [SIENTRY (Intermediate Block Entry)](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/9e79c673-d2f4-49fb-a00b-51b08fd2d1e4)
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct IntermediateSubNodeTreeEntry<Block>
 where
     Block: BlockId,
@@ -1249,6 +1823,7 @@ impl IntermediateTreeEntryReadWrite for AnsiIntermediateSubNodeTreeEntry {
 
 /// [SLBLOCK](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/5182eb24-4b0b-4816-aa3f-719cc6e6b018)
 /// / [SIBLOCK](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/729fb9bd-060a-4bbc-9b3b-8f014b487dad)
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct SubNodeTreeBlock<Header, Entry, Trailer>
 where
     Header: IntermediateTreeHeader,
@@ -1339,6 +1914,20 @@ where
     Leaf(Box<<Pst as PstFile>::SubNodeBlock>),
 }
 
+impl<Pst> Debug for SubNodeTree<Pst>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::SubNodeTreeBlock: Debug,
+    <Pst as PstFile>::SubNodeBlock: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Intermediate(block) => f.debug_tuple("Intermediate").field(block).finish(),
+            Self::Leaf(block) => f.debug_tuple("Leaf").field(block).finish(),
+        }
+    }
+}
+
 impl<Pst> SubNodeTree<Pst>
 where
     Pst: PstFile,
@@ -1355,8 +1944,15 @@ where
         f: &mut R,
         block: &<Pst as PstFile>::BlockBTreeEntry,
     ) -> io::Result<Self> {
-        f.seek(SeekFrom::Start(block.block().index().index().into()))?;
+        let offset = block.block().index().index().into();
+        f.seek(SeekFrom::Start(offset))?;
+        Self::read_at(f, block).map_err(error_at(offset))
+    }
 
+    fn read_at<R: PstReader>(
+        f: &mut R,
+        block: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<Self> {
         let block_size = block_size(
             block.size() + <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE,
         );
@@ -1407,6 +2003,35 @@ where
         node: NodeId,
         page_cache: &mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
     ) -> io::Result<<Pst as PstFile>::BlockId>
+    where
+        R: PstReader,
+        <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey> + BlockIdReadWrite,
+        <Pst as PstFile>::ByteIndex: ByteIndexReadWrite,
+        <Pst as PstFile>::BlockRef: BlockRefReadWrite,
+        <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+        <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+        <Pst as PstFile>::BlockBTree: RootBTreeReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::Entry: BTreeEntryReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+            RootBTreeIntermediatePageReadWrite<
+                Pst,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::Entry,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+            >,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage:
+            RootBTreeLeafPageReadWrite<Pst> + BTreePageReadWrite,
+    {
+        self.find_entry_in_walk(f, block_btree, node, page_cache, &mut TreeWalk::new())
+    }
+
+    fn find_entry_in_walk<R>(
+        &self,
+        f: &mut R,
+        block_btree: &PstFileReadWriteBlockBTree<Pst>,
+        node: NodeId,
+        page_cache: &mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+        walk: &mut TreeWalk,
+    ) -> io::Result<<Pst as PstFile>::BlockId>
     where
         R: PstReader,
         <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey> + BlockIdReadWrite,
@@ -1427,6 +2052,7 @@ where
     {
         match self {
             Self::Intermediate(block) => {
+                walk.enter_sub_node_tree(block.trailer().block_id())?;
                 let entries = block.entries();
                 let index =
                     entries.partition_point(|entry| u32::from(entry.node()) <= u32::from(node));
@@ -1436,7 +2062,7 @@ where
                     .ok_or(NdbError::SubNodeNotFound(node))?;
                 let block = block_btree.find_entry(f, entry.block().search_key(), page_cache)?;
                 let page = Self::read(f, &block)?;
-                page.find_entry(f, block_btree, node, page_cache)
+                page.find_entry_in_walk(f, block_btree, node, page_cache, walk)
             }
             Self::Leaf(block) => {
                 let entry = block
@@ -1474,28 +2100,390 @@ where
             >,
         <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage:
             RootBTreeLeafPageReadWrite<Pst> + BTreePageReadWrite,
+    {
+        let mut entries = Vec::new();
+        self.collect_entries(
+            f,
+            block_btree,
+            page_cache,
+            &mut TreeWalk::new(),
+            &mut entries,
+        )?;
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    /// Append the leaf entries under this tree to `entries`, failing once the SIBLOCKs are nested
+    /// deeper than the [`Limits`] allow or one of them is reachable from itself.
+    fn collect_entries<R>(
+        &self,
+        f: &mut R,
+        block_btree: &PstFileReadWriteBlockBTree<Pst>,
+        page_cache: &mut RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+        walk: &mut TreeWalk,
+        entries: &mut Vec<LeafSubNodeTreeEntry<<Pst as PstFile>::BlockId>>,
+    ) -> io::Result<()>
+    where
+        R: PstReader,
+        <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey> + BlockIdReadWrite,
+        <Pst as PstFile>::ByteIndex: ByteIndexReadWrite,
+        <Pst as PstFile>::BlockRef: BlockRefReadWrite,
+        <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+        <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+        <Pst as PstFile>::BlockBTree: RootBTreeReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::Entry: BTreeEntryReadWrite,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+            RootBTreeIntermediatePageReadWrite<
+                Pst,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::Entry,
+                <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+            >,
+        <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage:
+            RootBTreeLeafPageReadWrite<Pst> + BTreePageReadWrite,
     {
         match self {
             Self::Intermediate(block) => {
-                let entries = block
-                    .entries()
-                    .iter()
-                    .map(|entry| {
-                        let block =
-                            block_btree.find_entry(f, entry.block().search_key(), page_cache)?;
-                        let sub_nodes = Self::read(f, &block)?;
-                        sub_nodes.entries(f, block_btree, page_cache)
-                    })
-                    .collect::<io::Result<Vec<_>>>()?;
-                Ok(Box::new(entries.into_iter().flatten()))
-            }
-            Self::Leaf(block) => {
-                let entries = block.entries().to_vec();
-                Ok(Box::new(entries.into_iter()))
+                walk.enter_sub_node_tree(block.trailer().block_id())?;
+                for entry in block.entries() {
+                    let block =
+                        block_btree.find_entry(f, entry.block().search_key(), page_cache)?;
+                    let sub_nodes = Self::read(f, &block)?;
+                    sub_nodes.collect_entries(f, block_btree, page_cache, walk, entries)?;
+                }
+                walk.leave();
             }
+            Self::Leaf(block) => entries.extend(block.entries().iter().copied()),
         }
+        Ok(())
     }
 }
 
 pub type UnicodeSubNodeTree = SubNodeTree<UnicodePstFile>;
 pub type AnsiSubNodeTree = SubNodeTree<AnsiPstFile>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ndb::options::{ParseOptions, ParseScope};
+
+    #[derive(Default)]
+    struct TestAllocator {
+        writer: Cursor<Vec<u8>>,
+        next_offset: u64,
+        next_block: u64,
+        blocks: BTreeMap<UnicodeBlockId, UnicodeBlockBTreeEntry>,
+    }
+
+    impl BlockAllocator<UnicodePstFile> for TestAllocator {
+        fn encoding(&self) -> NdbCryptMethod {
+            NdbCryptMethod::Permute
+        }
+
+        fn allocate_block(
+            &mut self,
+            is_internal: bool,
+            size: u16,
+        ) -> io::Result<UnicodeBlockBTreeEntry> {
+            self.next_block += 1;
+            let block_id = UnicodeBlockId::new(is_internal, self.next_block)?;
+            let offset = self.next_offset;
+            self.next_offset += u64::from(block_size(size + UnicodeBlockTrailer::SIZE));
+
+            let entry = UnicodeBlockBTreeEntry::new(
+                UnicodeBlockRef::new(block_id, UnicodeByteIndex::new(offset)),
+                size,
+            );
+            self.blocks.insert(block_id, entry);
+            Ok(entry)
+        }
+
+        fn write_block(
+            &mut self,
+            block: &UnicodeDataTree,
+            entry: &UnicodeBlockBTreeEntry,
+        ) -> io::Result<()> {
+            block.write(&mut self.writer, entry)
+        }
+    }
+
+    impl TestAllocator {
+        fn read(&self, block_id: UnicodeBlockId) -> UnicodeDataTree {
+            let mut reader = Cursor::new(self.writer.get_ref().as_slice());
+            UnicodeDataTree::read(&mut reader, self.encoding(), &self.blocks[&block_id]).unwrap()
+        }
+
+        fn read_data(&self, block_id: UnicodeBlockId) -> Vec<u8> {
+            match self.read(block_id) {
+                DataTree::Intermediate(block) => {
+                    let data: Vec<_> = block
+                        .entries()
+                        .iter()
+                        .flat_map(|entry| self.read_data(entry.block()))
+                        .collect();
+                    assert_eq!(data.len(), block.header().total_size() as usize);
+                    data
+                }
+                DataTree::Leaf(block) => block.data().to_vec(),
+            }
+        }
+
+        fn block_btree(&self) -> PstFileReadWriteBlockBTree<UnicodePstFile> {
+            let entries: Vec<_> = self.blocks.values().copied().collect();
+            let trailer =
+                UnicodePageTrailer::new(PageType::BlockBTree, 0, UnicodePageId::from(1), 0);
+            let page = UnicodeBlockBTreePage::new(0, 20, 24, &entries, trailer).unwrap();
+            RootBTreePage::Leaf(Box::new(page))
+        }
+
+        /// Write a chain of `length` XBLOCKs where the last one points back at the first, as found
+        /// in fuzzed files, and return the BID of the first one.
+        fn write_cyclic_xblocks(&mut self, length: u64) -> UnicodeBlockId {
+            let size = DataTreeBlockHeader::HEADER_SIZE + UnicodeDataTreeEntry::ENTRY_SIZE;
+            let entries: Vec<_> = (0..length)
+                .map(|_| self.allocate_block(true, size).unwrap())
+                .collect();
+            for (index, entry) in entries.iter().enumerate() {
+                let next = entries[(index + 1) % entries.len()].block().block();
+                let block = UnicodeDataTreeBlock::new(
+                    DataTreeBlockHeader::new(1, 1, 100),
+                    vec![UnicodeDataTreeEntry::new(next)],
+                    UnicodeDataTree::block_trailer(entry).unwrap(),
+                )
+                .unwrap();
+                self.write_block(&DataTree::Intermediate(Box::new(block)), entry)
+                    .unwrap();
+            }
+            entries[0].block().block()
+        }
+    }
+
+    fn ndb_error(err: io::Error) -> NdbError {
+        *err.into_inner().unwrap().downcast::<NdbError>().unwrap()
+    }
+
+    fn test_data(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_build_data_block() {
+        let max_data_size = UnicodeDataTree::max_data_size();
+        assert_eq!(max_data_size, 8176);
+
+        let mut allocator = TestAllocator::default();
+        assert!(UnicodeDataTree::build(&[], &mut allocator).is_err());
+        assert!(allocator.blocks.is_empty());
+
+        for size in [1, max_data_size] {
+            let mut allocator = TestAllocator::default();
+            let data = test_data(size);
+            let tree = UnicodeDataTree::build(&data, &mut allocator).unwrap();
+            assert!(matches!(tree, DataTree::Leaf(_)));
+            assert_eq!(allocator.blocks.len(), 1);
+            assert_eq!(allocator.read_data(tree.block_id()), data);
+        }
+    }
+
+    #[test]
+    fn test_build_xblock() {
+        let mut allocator = TestAllocator::default();
+        let data = test_data(UnicodeDataTree::max_data_size() * 2 + 100);
+        let tree = UnicodeDataTree::build(&data, &mut allocator).unwrap();
+
+        let DataTree::Intermediate(block) = &tree else {
+            panic!("expected an XBLOCK");
+        };
+        assert!(tree.block_id().is_internal());
+        assert_eq!(block.header().level(), 1);
+        assert_eq!(block.header().entry_count(), 3);
+        assert_eq!(block.header().total_size() as usize, data.len());
+        assert_eq!(allocator.blocks.len(), 4);
+        assert_eq!(allocator.read_data(tree.block_id()), data);
+    }
+
+    #[test]
+    fn test_build_xxblock() {
+        // 1021 entries fit in a Unicode XBLOCK, so this needs 2 XBLOCKs under an XXBLOCK.
+        let mut allocator = TestAllocator::default();
+        let data = test_data(UnicodeDataTree::max_data_size() * 1021 + 1);
+        let tree = UnicodeDataTree::build(&data, &mut allocator).unwrap();
+
+        let DataTree::Intermediate(block) = &tree else {
+            panic!("expected an XXBLOCK");
+        };
+        assert_eq!(block.header().level(), 2);
+        assert_eq!(block.header().entry_count(), 2);
+        assert_eq!(block.header().total_size() as usize, data.len());
+        assert_eq!(allocator.blocks.len(), 1022 + 3);
+        assert_eq!(allocator.read_data(tree.block_id()), data);
+    }
+
+    #[test]
+    fn test_cyclic_data_tree() {
+        for length in [1, 3] {
+            let mut allocator = TestAllocator::default();
+            let block_id = allocator.write_cyclic_xblocks(length);
+            let block_btree = allocator.block_btree();
+            let tree = allocator.read(block_id);
+            let mut reader = Cursor::new(allocator.writer.get_ref().as_slice());
+            let mut page_cache = Default::default();
+            let mut block_cache = Default::default();
+            let is_cycle = |err| matches!(ndb_error(err), NdbError::CyclicReference(id) if id == block_id.into_u64());
+
+            let err = tree
+                .blocks(
+                    &mut reader,
+                    NdbCryptMethod::Permute,
+                    &block_btree,
+                    &mut page_cache,
+                    &mut block_cache,
+                )
+                .err()
+                .unwrap();
+            assert!(is_cycle(err));
+
+            let err = tree
+                .nth(
+                    0,
+                    &mut reader,
+                    NdbCryptMethod::Permute,
+                    &block_btree,
+                    &mut page_cache,
+                    &mut block_cache,
+                )
+                .unwrap_err();
+            assert!(is_cycle(err));
+
+            let err = tree
+                .reader(
+                    &mut reader,
+                    NdbCryptMethod::Permute,
+                    &block_btree,
+                    &mut page_cache,
+                    &mut block_cache,
+                )
+                .err()
+                .unwrap();
+            assert!(is_cycle(err));
+        }
+    }
+
+    #[test]
+    fn test_data_tree_limits() {
+        let mut allocator = TestAllocator::default();
+        let data = test_data(UnicodeDataTree::max_data_size() * 2 + 100);
+        let tree = UnicodeDataTree::build(&data, &mut allocator).unwrap();
+        let block_btree = allocator.block_btree();
+        let mut reader = Cursor::new(allocator.writer.get_ref().as_slice());
+        let mut page_cache = Default::default();
+        let warnings = Default::default();
+
+        let mut read_blocks = |limits| {
+            let _scope = ParseScope::new(
+                ParseOptions {
+                    limits,
+                    ..Default::default()
+                },
+                &warnings,
+            );
+            let mut block_cache = Default::default();
+            tree.blocks(
+                &mut reader,
+                NdbCryptMethod::Permute,
+                &block_btree,
+                &mut page_cache,
+                &mut block_cache,
+            )
+            .map(|blocks| blocks.count())
+        };
+
+        assert_eq!(read_blocks(Limits::DEFAULT).unwrap(), 3);
+
+        let err = read_blocks(Limits {
+            max_blocks_per_value: 2,
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(matches!(
+            ndb_error(err),
+            NdbError::BlockCountLimitExceeded(2)
+        ));
+
+        let err = read_blocks(Limits {
+            max_value_size: data.len() as u64 - 1,
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(matches!(
+            ndb_error(err),
+            NdbError::ValueSizeLimitExceeded(_)
+        ));
+
+        let err = read_blocks(Limits {
+            max_data_tree_depth: 0,
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(matches!(
+            ndb_error(err),
+            NdbError::DataTreeDepthLimitExceeded(0)
+        ));
+    }
+
+    #[test]
+    fn test_cyclic_sub_node_tree() {
+        let mut allocator = TestAllocator::default();
+        let size = UnicodeSubNodeTreeBlockHeader::HEADER_SIZE
+            + UnicodeIntermediateSubNodeTreeEntry::ENTRY_SIZE;
+        let entry = allocator.allocate_block(true, size).unwrap();
+        let node = NodeId::new(NodeIdType::Attachment, 1).unwrap();
+        let block = UnicodeIntermediateSubNodeTreeBlock::new(
+            UnicodeSubNodeTreeBlockHeader::new(1, 1),
+            vec![IntermediateSubNodeTreeEntry::new(
+                node,
+                entry.block().block(),
+            )],
+            UnicodeDataTree::block_trailer(&entry).unwrap(),
+        )
+        .unwrap();
+        UnicodeSubNodeTree::Intermediate(Box::new(block))
+            .write(&mut allocator.writer, &entry)
+            .unwrap();
+
+        let block_btree = allocator.block_btree();
+        let mut reader = Cursor::new(allocator.writer.get_ref().as_slice());
+        let mut page_cache = Default::default();
+        let tree = UnicodeSubNodeTree::read(&mut reader, &entry).unwrap();
+        let block_id = entry.block().block().into_u64();
+
+        let err = tree
+            .entries(&mut reader, &block_btree, &mut page_cache)
+            .err()
+            .unwrap();
+        assert!(matches!(ndb_error(err), NdbError::CyclicReference(id) if id == block_id));
+
+        let err = tree
+            .find_entry(&mut reader, &block_btree, node, &mut page_cache)
+            .unwrap_err();
+        assert!(matches!(ndb_error(err), NdbError::CyclicReference(id) if id == block_id));
+
+        let warnings = Default::default();
+        let _scope = ParseScope::new(
+            ParseOptions {
+                limits: Limits {
+                    max_sub_node_depth: 0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            &warnings,
+        );
+        let err = tree
+            .entries(&mut reader, &block_btree, &mut page_cache)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            ndb_error(err),
+            NdbError::SubNodeDepthLimitExceeded(0)
+        ));
+    }
+}
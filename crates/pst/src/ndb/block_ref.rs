@@ -10,7 +10,7 @@ pub trait BlockRef: Copy + Default + Sized {
     fn index(&self) -> Self::Index;
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct UnicodeBlockRef {
     block: UnicodeBlockId,
     index: UnicodeByteIndex,
@@ -41,7 +41,7 @@ impl BlockRefReadWrite for UnicodeBlockRef {
     }
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct UnicodePageRef {
     page: UnicodePageId,
     index: UnicodeByteIndex,
@@ -72,7 +72,7 @@ impl BlockRefReadWrite for UnicodePageRef {
     }
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct AnsiBlockRef {
     block: AnsiBlockId,
     index: AnsiByteIndex,
@@ -103,7 +103,7 @@ impl BlockRefReadWrite for AnsiBlockRef {
     }
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub struct AnsiPageRef {
     page: AnsiPageId,
     index: AnsiByteIndex,
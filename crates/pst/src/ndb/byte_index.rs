@@ -14,7 +14,7 @@ pub trait ByteIndex: Copy + Default + Sized {
     fn index(&self) -> Self::Index;
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
 pub struct UnicodeByteIndex(u64);
 
 impl UnicodeByteIndex {
@@ -64,7 +64,7 @@ impl From<UnicodeByteIndex> for u64 {
     }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
 pub struct AnsiByteIndex(u32);
 
 impl AnsiByteIndex {
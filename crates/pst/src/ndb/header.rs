@@ -14,6 +14,34 @@ const HEADER_MAGIC: u32 = u32::from_be_bytes(*b"NDB!");
 
 const HEADER_MAGIC_CLIENT: u16 = u16::from_be_bytes(*b"MS");
 
+const HEADER_MAGIC_CLIENT_OST: u16 = u16::from_be_bytes(*b"OS");
+
+/// `wMagicClient`
+///
+/// ### See also
+/// [Header]
+#[repr(u16)]
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum NdbClient {
+    /// `SM`: Personal Storage Table (PST) file
+    #[default]
+    Pst = HEADER_MAGIC_CLIENT,
+    /// `SO`: Offline Storage Table (OST) file, which is always Unicode
+    Ost = HEADER_MAGIC_CLIENT_OST,
+}
+
+impl TryFrom<u16> for NdbClient {
+    type Error = NdbError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            HEADER_MAGIC_CLIENT => Ok(NdbClient::Pst),
+            HEADER_MAGIC_CLIENT_OST => Ok(NdbClient::Ost),
+            _ => Err(NdbError::InvalidNdbHeaderMagicClientValue(value)),
+        }
+    }
+}
+
 /// `wVer`
 ///
 /// ### See also
@@ -38,6 +66,23 @@ impl TryFrom<u16> for NdbVersion {
     }
 }
 
+impl NdbVersion {
+    /// Read `dwMagic` and `wVer` from the start of a [Header] without parsing the rest of it, to
+    /// find out which format the file uses.
+    pub fn read(f: &mut dyn Read) -> io::Result<Self> {
+        let magic = f.read_u32::<LittleEndian>()?;
+        if magic != HEADER_MAGIC {
+            return Err(NdbError::InvalidNdbHeaderMagicValue(magic).into());
+        }
+
+        // dwCRCPartial, wMagicClient
+        let mut skipped = [0_u8; 6];
+        f.read_exact(&mut skipped)?;
+
+        Ok(Self::try_from(f.read_u16::<LittleEndian>()?)?)
+    }
+}
+
 const NDB_CLIENT_VERSION: u16 = 19;
 const NDB_PLATFORM_CREATE: u8 = 0x01;
 const NDB_PLATFORM_ACCESS: u8 = 0x01;
@@ -82,6 +127,7 @@ where
     Pst: PstFile,
 {
     fn version(&self) -> NdbVersion;
+    fn client(&self) -> NdbClient;
     fn crypt_method(&self) -> NdbCryptMethod;
     fn next_block(&self) -> <Pst as PstFile>::BlockId;
     fn next_page(&self) -> <Pst as PstFile>::PageId;
@@ -92,6 +138,7 @@ where
 
 #[derive(Clone, Debug)]
 pub struct UnicodeHeader {
+    client: NdbClient,
     next_page: UnicodePageId,
     unique: u32,
     nids: [u32; 32],
@@ -111,6 +158,7 @@ pub struct UnicodeHeader {
 impl UnicodeHeader {
     pub fn new(root: UnicodeRoot, crypt_method: NdbCryptMethod) -> Self {
         Self {
+            client: NdbClient::Pst,
             next_page: UnicodePageId::from(1),
             unique: 0,
             nids: NDB_DEFAULT_NIDS,
@@ -133,6 +181,10 @@ impl Header<UnicodePstFile> for UnicodeHeader {
         NdbVersion::Unicode
     }
 
+    fn client(&self) -> NdbClient {
+        self.client
+    }
+
     fn crypt_method(&self) -> NdbCryptMethod {
         self.crypt_method
     }
@@ -159,6 +211,10 @@ impl Header<UnicodePstFile> for UnicodeHeader {
 }
 
 impl HeaderReadWrite<UnicodePstFile> for UnicodeHeader {
+    fn new(root: UnicodeRoot, crypt_method: NdbCryptMethod) -> Self {
+        Self::new(root, crypt_method)
+    }
+
     fn read(f: &mut dyn Read) -> io::Result<Self> {
         // dwMagic
         let magic = f.read_u32::<LittleEndian>()?;
@@ -178,10 +234,7 @@ impl HeaderReadWrite<UnicodePstFile> for UnicodeHeader {
         let mut cursor = Cursor::new(crc_data);
 
         // wMagicClient
-        let magic = cursor.read_u16::<LittleEndian>()?;
-        if magic != HEADER_MAGIC_CLIENT {
-            return Err(NdbError::InvalidNdbHeaderMagicClientValue(magic).into());
-        }
+        let client = NdbClient::try_from(cursor.read_u16::<LittleEndian>()?)?;
 
         // wVer
         let version = NdbVersion::try_from(cursor.read_u16::<LittleEndian>()?)?;
@@ -283,6 +336,7 @@ impl HeaderReadWrite<UnicodePstFile> for UnicodeHeader {
         f.read_exact(&mut reserved3)?;
 
         Ok(Self {
+            client,
             next_page,
             unique,
             nids,
@@ -302,7 +356,7 @@ impl HeaderReadWrite<UnicodePstFile> for UnicodeHeader {
     fn write(&self, f: &mut dyn Write) -> io::Result<()> {
         let mut cursor = Cursor::new([0_u8; 516]);
         // wMagicClient
-        cursor.write_u16::<LittleEndian>(HEADER_MAGIC_CLIENT)?;
+        cursor.write_u16::<LittleEndian>(self.client as u16)?;
         // wVer
         cursor.write_u16::<LittleEndian>(NdbVersion::Unicode as u16)?;
         // wVerClient
@@ -373,6 +427,18 @@ impl HeaderReadWrite<UnicodePstFile> for UnicodeHeader {
     fn first_free_page_map(&mut self) -> &mut [u8] {
         &mut self.free_page_map
     }
+
+    fn set_next_block(&mut self, next_block: UnicodeBlockId) {
+        self.next_block = next_block;
+    }
+
+    fn set_next_page(&mut self, next_page: UnicodePageId) {
+        self.next_page = next_page;
+    }
+
+    fn next_node_ids(&mut self) -> &mut [u32] {
+        &mut self.nids
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -414,6 +480,10 @@ impl Header<AnsiPstFile> for AnsiHeader {
         NdbVersion::Ansi
     }
 
+    fn client(&self) -> NdbClient {
+        NdbClient::Pst
+    }
+
     fn crypt_method(&self) -> NdbCryptMethod {
         self.crypt_method
     }
@@ -440,6 +510,10 @@ impl Header<AnsiPstFile> for AnsiHeader {
 }
 
 impl HeaderReadWrite<AnsiPstFile> for AnsiHeader {
+    fn new(root: AnsiRoot, crypt_method: NdbCryptMethod) -> Self {
+        Self::new(root, crypt_method)
+    }
+
     fn read(f: &mut dyn Read) -> io::Result<Self> {
         // dwMagic
         let magic = f.read_u32::<LittleEndian>()?;
@@ -626,6 +700,18 @@ impl HeaderReadWrite<AnsiPstFile> for AnsiHeader {
     fn first_free_page_map(&mut self) -> &mut [u8] {
         &mut self.free_page_map
     }
+
+    fn set_next_block(&mut self, next_block: AnsiBlockId) {
+        self.next_block = next_block;
+    }
+
+    fn set_next_page(&mut self, next_page: AnsiPageId) {
+        self.next_page = next_page;
+    }
+
+    fn next_node_ids(&mut self) -> &mut [u32] {
+        &mut self.nids
+    }
 }
 
 #[cfg(test)]
@@ -636,5 +722,6 @@ mod tests {
     fn test_magic_values() {
         assert_eq!(HEADER_MAGIC, 0x4E444221);
         assert_eq!(HEADER_MAGIC_CLIENT, 0x4D53);
+        assert_eq!(HEADER_MAGIC_CLIENT_OST, 0x4F53);
     }
 }
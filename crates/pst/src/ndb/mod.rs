@@ -10,6 +10,7 @@ pub mod block_ref;
 pub mod byte_index;
 pub mod header;
 pub mod node_id;
+pub mod options;
 pub mod page;
 pub mod root;
 
@@ -20,6 +21,7 @@ use node_id::NodeId;
 use page::PageType;
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum NdbError {
     #[error("Invalid nidType: 0x{0:02X}")]
     InvalidNodeIdType(u8),
@@ -95,6 +97,10 @@ pub enum NdbError {
     InvalidBlockSize(u16),
     #[error("Invalid BLOCKTRAILER dwCRC: 0x{0:08X}")]
     InvalidBlockCrc(u32),
+    #[error("Invalid BLOCKTRAILER wSig: 0x{0:04X}")]
+    InvalidBlockSignature(u16),
+    #[error("BLOCKTRAILER bid does not match BBTENTRY: 0x{0:X}")]
+    MismatchBlockTrailerId(u64),
     #[error("Invalid BLOCKTRAILER bid: 0x{0:X}")]
     InvalidUnicodeBlockTrailerId(u64),
     #[error("Invalid BLOCKTRAILER bid: 0x{0:X}")]
@@ -109,10 +115,76 @@ pub enum NdbError {
     InvalidInternalBlockLevel(u8),
     #[error("Invalid internal block cEnt: 0x{0:X}")]
     InvalidInternalBlockEntryCount(u16),
+    #[error("Data tree is too large: 0x{0:X}")]
+    DataTreeTooLarge(u64),
     #[error("Invalid sub-node tree block dwPadding: 0x{0:08X}")]
     InvalidSubNodeBlockPadding(u32),
     #[error("Sub-node not found: {0:?}")]
     SubNodeNotFound(NodeId),
+    #[error("Missing BBTENTRY for referenced block: 0x{0:X}")]
+    MissingBlockReference(u64),
+    #[error("Block or page 0x{0:X} is referenced from within itself")]
+    CyclicReference(u64),
+    #[error("Data tree is nested deeper than the limit of {0} levels")]
+    DataTreeDepthLimitExceeded(usize),
+    #[error("Sub-node tree is nested deeper than the limit of {0} levels")]
+    SubNodeDepthLimitExceeded(usize),
+    #[error("Data tree has more than the limit of {0} blocks")]
+    BlockCountLimitExceeded(usize),
+    #[error("Data tree is larger than the limit of {0} bytes")]
+    ValueSizeLimitExceeded(u64),
+    #[error("Invalid page offset: 0x{0:X}")]
+    InvalidPageOffset(u64),
+    #[error("Read past end of file: offset: 0x{0:X}, size: 0x{1:X}")]
+    ReadPastEndOfFile(u64, usize),
+    #[error("{source} (block or page at offset: 0x{offset:X})")]
+    At { offset: u64, source: Box<NdbError> },
+}
+
+impl NdbError {
+    /// Record the file `offset` of the block or page which failed to parse. An error which
+    /// already has an offset keeps the innermost one.
+    pub fn at(self, offset: u64) -> Self {
+        match self {
+            Self::At { .. } => self,
+            err => Self::At {
+                offset,
+                source: Box::new(err),
+            },
+        }
+    }
+
+    /// The file offset recorded with [`NdbError::at`], if any.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Self::At { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// The underlying error, without the offset recorded with [`NdbError::at`].
+    pub fn without_offset(&self) -> &Self {
+        match self {
+            Self::At { source, .. } => source.without_offset(),
+            err => err,
+        }
+    }
+}
+
+/// Record the file `offset` in an [`NdbError`] wrapped in an [`io::Error`], see [`NdbError::at`].
+/// Any other error is returned unchanged.
+pub(crate) fn error_at(offset: u64) -> impl FnOnce(io::Error) -> io::Error {
+    move |err| {
+        if !err.get_ref().is_some_and(|err| err.is::<NdbError>()) {
+            return err;
+        }
+        let kind = err.kind();
+        match err.into_inner().map(|err| err.downcast::<NdbError>()) {
+            Some(Ok(err)) => io::Error::new(kind, err.at(offset)),
+            Some(Err(err)) => io::Error::new(kind, err),
+            None => io::Error::from(kind),
+        }
+    }
 }
 
 impl From<NdbError> for io::Error {
@@ -122,3 +194,37 @@ impl From<NdbError> for io::Error {
 }
 
 pub type NdbResult<T> = Result<T, NdbError>;
+
+/// Reason that the [DLISTPAGE](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/5d426b2d-ec10-4614-b768-46813652d5e3)
+/// could not be used. The density list is optional, so a file which never had one is not corrupt.
+#[derive(Error, Debug)]
+pub enum DensityListError {
+    #[error("Density list not present")]
+    NotPresent,
+    #[error("Invalid density list dwCRC: 0x{0:08X}")]
+    InvalidCrc(u32),
+    #[error("Invalid density list: {0}")]
+    Invalid(io::Error),
+}
+
+impl From<io::Error> for DensityListError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            return Self::NotPresent;
+        }
+
+        match err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<NdbError>())
+            .map(NdbError::without_offset)
+        {
+            Some(
+                NdbError::UnexpectedPageType(_)
+                | NdbError::InvalidPageType(_)
+                | NdbError::MismatchPageTypeRepeat(..),
+            ) => Self::NotPresent,
+            Some(NdbError::InvalidPageCrc(crc)) => Self::InvalidCrc(*crc),
+            _ => Self::Invalid(err),
+        }
+    }
+}
@@ -103,9 +103,12 @@ impl TryFrom<u8> for NodeIdType {
 pub const MAX_NODE_INDEX: u32 = 1_u32.rotate_right(5) - 1;
 
 #[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct NodeId(u32);
 
 impl NodeId {
+    /// Build a [`NodeId`] from its parts. This fails if `index` does not fit in the 27 bits
+    /// above `nidType`, so it is safe to use for nodes which are going to be written to a file.
     pub fn new(id_type: NodeIdType, index: u32) -> NdbResult<Self> {
         let id_type = id_type as u8;
         if id_type >> 5 != 0 {
@@ -120,6 +123,15 @@ impl NodeId {
         Ok(Self(shifted_index | (u32::from(id_type))))
     }
 
+    /// Check that the `nidType` bits of a raw `u32` value are one of the [`NodeIdType`] values
+    /// defined by the spec. [`NodeId::from`] accepts any value, because Outlook writes some nodes
+    /// with reserved types which still need to be copied as-is.
+    pub fn from_raw(value: u32) -> NdbResult<Self> {
+        let node_id = Self(value);
+        node_id.id_type()?;
+        Ok(node_id)
+    }
+
     pub fn id_type(&self) -> NdbResult<NodeIdType> {
         let nid_type = self.0 & 0x1F;
         NodeIdType::try_from(nid_type as u8)
@@ -223,6 +235,28 @@ pub const NID_RESERVED3: NodeId = NodeId(0x301);
 /// Search Gatherer Folder Queue (section [2.4.8.5.3](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/5dd87c45-5f2d-4945-b7e3-2612bd1a94d3)).
 pub const NID_SEARCH_GATHERER_FOLDER_QUEUE: NodeId = NodeId(0x321);
 
+/// [`NID_HIERARCHY_TABLE_TEMPLATE`](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/0510ece4-6853-4bef-8cc8-8df3468e3ff1):
+/// Template for the hierarchy table of a Folder object.
+pub const NID_HIERARCHY_TABLE_TEMPLATE: NodeId = NodeId(0x60D);
+
+/// [`NID_CONTENTS_TABLE_TEMPLATE`](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/0510ece4-6853-4bef-8cc8-8df3468e3ff1):
+/// Template for the contents table of a Folder object.
+pub const NID_CONTENTS_TABLE_TEMPLATE: NodeId = NodeId(0x60E);
+
+/// [`NID_ASSOC_CONTENTS_TABLE_TEMPLATE`](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/0510ece4-6853-4bef-8cc8-8df3468e3ff1):
+/// Template for the FAI contents table of a Folder object.
+pub const NID_ASSOC_CONTENTS_TABLE_TEMPLATE: NodeId = NodeId(0x60F);
+
+/// [`NID_ATTACHMENT_TABLE`](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/0510ece4-6853-4bef-8cc8-8df3468e3ff1):
+/// Template for the attachment table of a Message object. Messages use the same NID for their
+/// own attachment table sub-node.
+pub const NID_ATTACHMENT_TABLE: NodeId = NodeId(0x671);
+
+/// [`NID_RECIPIENT_TABLE`](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/0510ece4-6853-4bef-8cc8-8df3468e3ff1):
+/// Template for the recipient table of a Message object. Messages use the same NID for their own
+/// recipient table sub-node.
+pub const NID_RECIPIENT_TABLE: NodeId = NodeId(0x692);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,4 +270,21 @@ mod tests {
         };
         assert_eq!(value, MAX_NODE_INDEX + 1);
     }
+
+    #[test]
+    fn test_nid_from_raw() {
+        let node_id = NodeId::from_raw(0x122).unwrap();
+        assert_eq!(node_id, NID_ROOT_FOLDER);
+        assert_eq!(node_id.id_type().unwrap(), NodeIdType::NormalFolder);
+        assert_eq!(node_id.index(), 0x9);
+
+        let Err(NdbError::InvalidNodeIdType(value)) = NodeId::from_raw(0x6B6) else {
+            panic!("NodeId type should be reserved");
+        };
+        assert_eq!(value, 0x16);
+
+        let node_id = NodeId::new(NodeIdType::NormalMessage, MAX_NODE_INDEX).unwrap();
+        assert_eq!(node_id.index(), MAX_NODE_INDEX);
+        assert_eq!(NodeId::from_raw(u32::from(node_id)).unwrap(), node_id);
+    }
 }
@@ -0,0 +1,289 @@
+//! Options which relax some of the checks made while reading the file, for files which violate
+//! the spec in ways that Outlook tolerates, and limits which protect against hostile files.
+
+use std::{
+    cell::{Cell, RefCell},
+    marker::PhantomData,
+    mem,
+    sync::{Mutex, PoisonError},
+};
+
+use super::{NdbError, NdbResult};
+use crate::messaging::attachment::DEFAULT_MAX_EMBEDDED_DEPTH;
+
+/// Which of the checks on padding, CRCs and signatures fail a read. The [`Default`] is
+/// [`ParseOptions::strict`]. Checks which are relaxed are recorded as warnings instead, see
+/// [`PstFile::take_parse_warnings`](crate::PstFile::take_parse_warnings).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Fail on non-zero padding in BTree pages and sub-node tree blocks.
+    pub strict_padding: bool,
+    /// Fail on a `dwCRC` mismatch in BTree pages and blocks.
+    pub verify_crc: bool,
+    /// Fail on a `wSig` mismatch in block trailers.
+    pub verify_signature: bool,
+    /// Limits on the recursive structures and sizes read from the file. These are always
+    /// enforced.
+    pub limits: Limits,
+}
+
+impl ParseOptions {
+    /// Fail on any violation.
+    pub const fn strict() -> Self {
+        Self {
+            strict_padding: true,
+            verify_crc: true,
+            verify_signature: true,
+            limits: Limits::DEFAULT,
+        }
+    }
+
+    /// Tolerate padding which is not zero, but still verify CRCs and signatures.
+    pub const fn lenient() -> Self {
+        Self {
+            strict_padding: false,
+            ..Self::strict()
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self::strict()
+    }
+}
+
+/// Bounds on the structures read from a file, so that a hostile file cannot drive the reader into
+/// unbounded recursion or memory use with cycles or huge entry counts. The defaults are well past
+/// anything Outlook writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Levels of XBLOCKs and XXBLOCKs in a data tree. Outlook never writes more than 2.
+    pub max_data_tree_depth: usize,
+    /// Levels of SIBLOCKs in a sub-node tree.
+    pub max_sub_node_depth: usize,
+    /// Data blocks in a single data tree.
+    pub max_blocks_per_value: usize,
+    /// Total size in bytes of the data in a single data tree.
+    pub max_value_size: u64,
+    /// Levels of embedded messages in attachments, see
+    /// [`Message::attachments`](crate::messaging::message::Message::attachments).
+    pub max_embedded_message_depth: usize,
+}
+
+impl Limits {
+    pub const DEFAULT: Self = Self {
+        max_data_tree_depth: 8,
+        max_sub_node_depth: 8,
+        max_blocks_per_value: 1 << 20,
+        max_value_size: 1 << 32,
+        max_embedded_message_depth: DEFAULT_MAX_EMBEDDED_DEPTH,
+    };
+
+    /// The limits of the innermost [`ParseScope`] on this thread, or [`Limits::DEFAULT`] if
+    /// there is none.
+    pub(crate) fn current() -> Self {
+        CONTEXT.with_borrow(|contexts| {
+            contexts
+                .last()
+                .map_or(Self::DEFAULT, |context| context.options.limits)
+        })
+    }
+
+    /// Check an intermediate block which is `depth` levels deep in a data tree, counting from 1
+    /// for the root block.
+    pub(crate) fn check_data_tree_depth(&self, depth: usize) -> NdbResult<()> {
+        if depth > self.max_data_tree_depth {
+            return Err(NdbError::DataTreeDepthLimitExceeded(
+                self.max_data_tree_depth,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check an intermediate block which is `depth` levels deep in a sub-node tree, counting from
+    /// 1 for the root block.
+    pub(crate) fn check_sub_node_depth(&self, depth: usize) -> NdbResult<()> {
+        if depth > self.max_sub_node_depth {
+            return Err(NdbError::SubNodeDepthLimitExceeded(self.max_sub_node_depth));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_block_count(&self, count: usize) -> NdbResult<()> {
+        if count > self.max_blocks_per_value {
+            return Err(NdbError::BlockCountLimitExceeded(self.max_blocks_per_value));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_value_size(&self, size: u64) -> NdbResult<()> {
+        if size > self.max_value_size {
+            return Err(NdbError::ValueSizeLimitExceeded(self.max_value_size));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The kind of check which found a violation, see [`enforce`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Check {
+    Padding,
+    Crc,
+    Signature,
+}
+
+struct ParseContext {
+    scope_id: u64,
+    options: ParseOptions,
+    warnings: Vec<NdbError>,
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Vec<ParseContext>> = const { RefCell::new(Vec::new()) };
+    static NEXT_SCOPE_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Return `err` if the [`ParseOptions`] of the innermost [`ParseScope`] on this thread enforce
+/// `check`, or record it as a warning in that scope otherwise. Without a scope, every check is
+/// enforced.
+pub(crate) fn enforce(check: Check, err: NdbError) -> NdbResult<()> {
+    CONTEXT.with_borrow_mut(|contexts| {
+        let Some(context) = contexts.last_mut() else {
+            return Err(err);
+        };
+        let enforced = match check {
+            Check::Padding => context.options.strict_padding,
+            Check::Crc => context.options.verify_crc,
+            Check::Signature => context.options.verify_signature,
+        };
+        if enforced {
+            return Err(err);
+        }
+        context.warnings.push(err);
+        Ok(())
+    })
+}
+
+/// Applies [`ParseOptions`] to the reads on this thread while it is alive, and adds any
+/// warnings to the file's list when it is dropped. Scopes can be nested, and the innermost one
+/// wins. Each scope removes its own entry when it is dropped, even if a scope which was created
+/// after it is still alive, so it must stay on the thread which created it.
+pub(crate) struct ParseScope<'a> {
+    id: u64,
+    warnings: &'a Mutex<Vec<NdbError>>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<'a> ParseScope<'a> {
+    pub(crate) fn new(options: ParseOptions, warnings: &'a Mutex<Vec<NdbError>>) -> Self {
+        let id = NEXT_SCOPE_ID.replace(NEXT_SCOPE_ID.get().wrapping_add(1));
+        CONTEXT.with_borrow_mut(|contexts| {
+            contexts.push(ParseContext {
+                scope_id: id,
+                options,
+                warnings: Default::default(),
+            })
+        });
+        Self {
+            id,
+            warnings,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for ParseScope<'_> {
+    fn drop(&mut self) {
+        let warnings = CONTEXT
+            .with_borrow_mut(|contexts| {
+                let index = contexts
+                    .iter()
+                    .rposition(|context| context.scope_id == self.id)?;
+                Some(contexts.remove(index))
+            })
+            .map(|context| context.warnings)
+            .unwrap_or_default();
+        if !warnings.is_empty() {
+            self.warnings
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .extend(warnings);
+        }
+    }
+}
+
+/// Remove and return the warnings collected in `warnings`.
+pub(crate) fn take_warnings(warnings: &Mutex<Vec<NdbError>>) -> Vec<NdbError> {
+    mem::take(&mut *warnings.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_scopes() {
+        let warnings = Mutex::new(Vec::new());
+        assert!(enforce(Check::Padding, NdbError::InvalidBTreePagePadding(1)).is_err());
+
+        {
+            let _lenient = ParseScope::new(ParseOptions::lenient(), &warnings);
+            enforce(Check::Padding, NdbError::InvalidBTreePagePadding(1)).unwrap();
+            assert!(enforce(Check::Crc, NdbError::InvalidBlockCrc(2)).is_err());
+
+            {
+                let _strict = ParseScope::new(ParseOptions::strict(), &warnings);
+                assert!(enforce(Check::Padding, NdbError::InvalidBTreePagePadding(3)).is_err());
+            }
+
+            enforce(Check::Padding, NdbError::InvalidSubNodeBlockPadding(4)).unwrap();
+        }
+
+        assert!(enforce(Check::Padding, NdbError::InvalidBTreePagePadding(5)).is_err());
+        let warnings = take_warnings(&warnings);
+        assert!(matches!(
+            warnings.as_slice(),
+            [
+                NdbError::InvalidBTreePagePadding(1),
+                NdbError::InvalidSubNodeBlockPadding(4)
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_out_of_order_scopes() {
+        let outer_warnings = Mutex::new(Vec::new());
+        let inner_warnings = Mutex::new(Vec::new());
+
+        let outer = ParseScope::new(ParseOptions::lenient(), &outer_warnings);
+        let inner = ParseScope::new(ParseOptions::strict(), &inner_warnings);
+        assert!(enforce(Check::Padding, NdbError::InvalidBTreePagePadding(1)).is_err());
+
+        // Dropping the outer scope first must not remove the inner one.
+        drop(outer);
+        assert!(enforce(Check::Padding, NdbError::InvalidBTreePagePadding(2)).is_err());
+
+        let lenient = ParseScope::new(ParseOptions::lenient(), &outer_warnings);
+        enforce(Check::Padding, NdbError::InvalidBTreePagePadding(3)).unwrap();
+        drop(inner);
+        enforce(Check::Padding, NdbError::InvalidBTreePagePadding(4)).unwrap();
+        drop(lenient);
+
+        assert!(enforce(Check::Padding, NdbError::InvalidBTreePagePadding(5)).is_err());
+        assert!(take_warnings(&inner_warnings).is_empty());
+        assert!(matches!(
+            take_warnings(&outer_warnings).as_slice(),
+            [
+                NdbError::InvalidBTreePagePadding(3),
+                NdbError::InvalidBTreePagePadding(4)
+            ]
+        ));
+    }
+}
@@ -80,7 +80,7 @@ pub trait PageTrailer {
     fn block_id(&self) -> Self::BlockId;
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
 pub struct UnicodePageTrailer {
     page_type: PageType,
     signature: u16,
@@ -109,6 +109,8 @@ impl PageTrailer for UnicodePageTrailer {
 }
 
 impl PageTrailerReadWrite for UnicodePageTrailer {
+    const SIZE: usize = 16;
+
     fn new(page_type: PageType, signature: u16, block_id: UnicodePageId, crc: u32) -> Self {
         Self {
             page_type,
@@ -145,7 +147,7 @@ impl PageTrailerReadWrite for UnicodePageTrailer {
     }
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
 pub struct AnsiPageTrailer {
     page_type: PageType,
     signature: u16,
@@ -174,6 +176,8 @@ impl PageTrailer for AnsiPageTrailer {
 }
 
 impl PageTrailerReadWrite for AnsiPageTrailer {
+    const SIZE: usize = 12;
+
     fn new(page_type: PageType, signature: u16, block_id: AnsiPageId, crc: u32) -> Self {
         Self {
             page_type,
@@ -366,6 +370,7 @@ where
 {
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct UnicodeMapPage<const P: u8> {
     map_bits: MapBits,
     trailer: UnicodePageTrailer,
@@ -424,6 +429,7 @@ impl<const PAGE_TYPE: u8> MapPageReadWrite<UnicodePstFile, PAGE_TYPE>
     }
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct AnsiMapPage<const P: u8> {
     map_bits: MapBits,
     trailer: AnsiPageTrailer,
@@ -695,7 +701,7 @@ impl MapPageReadWrite<AnsiPstFile, { PageType::FreePageMap as u8 }>
 const DENSITY_LIST_ENTRY_PAGE_NUMBER_MASK: u32 = 0x000F_FFFF;
 
 /// [DLISTPAGEENT](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/9d3c45b9-a415-446c-954f-b1b473dbb415)
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct DensityListPageEntry(u32);
 
 impl DensityListPageEntry {
@@ -727,6 +733,37 @@ impl DensityListPageEntry {
     }
 }
 
+/// Shared implementation of [`DensityListPageReadWrite::update_entry`] for the fixed size entry
+/// arrays in [`UnicodeDensityListPage`] and [`AnsiDensityListPage`].
+fn update_density_list_entry(
+    entries: &mut [DensityListPageEntry],
+    entry_count: &mut u8,
+    page: u32,
+    free_slots: u16,
+) -> NdbResult<()> {
+    let entry = DensityListPageEntry::new(page, free_slots)?;
+    let mut count = *entry_count as usize;
+
+    if let Some(existing) = entries[..count]
+        .iter()
+        .position(|entry| entry.page() == page)
+    {
+        entries.copy_within(existing + 1..count, existing);
+        count -= 1;
+    } else if count == entries.len() {
+        return Err(NdbError::InvalidDensityListEntryCount(count + 1));
+    }
+
+    let index = entries[..count]
+        .iter()
+        .position(|existing| existing.free_slots() < free_slots)
+        .unwrap_or(count);
+    entries.copy_within(index..count, index + 1);
+    entries[index] = entry;
+    *entry_count = (count + 1) as u8;
+    Ok(())
+}
+
 pub const DENSITY_LIST_FILE_OFFSET: u64 = 0x4200;
 
 /// [DLISTPAGE](https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-pst/5d426b2d-ec10-4614-b768-46813652d5e3)
@@ -738,8 +775,23 @@ where
     fn current_page(&self) -> u32;
     fn entries(&self) -> &[DensityListPageEntry];
     fn trailer(&self) -> &<Pst as PstFile>::PageTrailer;
+
+    /// Iterate over the AMap pages with at least `min_free_slots` free slots. The density list is
+    /// sorted from the emptiest page to the fullest, so an allocator can take the first match
+    /// instead of scanning every AMap page.
+    fn free_pages<'a>(
+        &'a self,
+        min_free_slots: u16,
+    ) -> Box<dyn 'a + Iterator<Item = &'a DensityListPageEntry>> {
+        Box::new(
+            self.entries()
+                .iter()
+                .filter(move |entry| entry.free_slots() >= min_free_slots),
+        )
+    }
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct UnicodeDensityListPage {
     backfill_complete: bool,
     current_page: u32,
@@ -799,11 +851,21 @@ impl DensityListPageReadWrite<UnicodePstFile> for UnicodeDensityListPage {
         })
     }
 
-    fn read<R: PstReader>(f: &mut R) -> io::Result<Self> {
-        f.seek(SeekFrom::Start(DENSITY_LIST_FILE_OFFSET))?;
-
+    fn read_page(f: &mut dyn Read) -> io::Result<Self> {
         let mut buffer = [0_u8; 496];
         f.read_exact(&mut buffer)?;
+
+        // pageTrailer
+        let trailer = UnicodePageTrailer::read(f)?;
+        if trailer.page_type() != PageType::DensityList {
+            return Err(NdbError::UnexpectedPageType(trailer.page_type()).into());
+        }
+
+        let crc = compute_crc(0, &buffer);
+        if crc != trailer.crc() {
+            return Err(NdbError::InvalidPageCrc(crc).into());
+        }
+
         let mut cursor = Cursor::new(buffer);
 
         // bFlags
@@ -840,19 +902,6 @@ impl DensityListPageReadWrite<UnicodePstFile> for UnicodeDensityListPage {
             return Err(NdbError::InvalidDensityListPadding.into());
         }
 
-        let buffer = cursor.into_inner();
-
-        // pageTrailer
-        let trailer = UnicodePageTrailer::read(f)?;
-        if trailer.page_type() != PageType::DensityList {
-            return Err(NdbError::UnexpectedPageType(trailer.page_type()).into());
-        }
-
-        let crc = compute_crc(0, &buffer);
-        if crc != trailer.crc() {
-            return Err(NdbError::InvalidPageCrc(crc).into());
-        }
-
         Ok(Self {
             backfill_complete,
             current_page,
@@ -898,8 +947,13 @@ impl DensityListPageReadWrite<UnicodePstFile> for UnicodeDensityListPage {
         };
         trailer.write(f)
     }
+
+    fn update_entry(&mut self, page: u32, free_slots: u16) -> NdbResult<()> {
+        update_density_list_entry(&mut self.entries, &mut self.entry_count, page, free_slots)
+    }
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct AnsiDensityListPage {
     backfill_complete: bool,
     current_page: u32,
@@ -958,11 +1012,21 @@ impl DensityListPageReadWrite<AnsiPstFile> for AnsiDensityListPage {
         })
     }
 
-    fn read<R: PstReader>(f: &mut R) -> io::Result<Self> {
-        f.seek(SeekFrom::Start(DENSITY_LIST_FILE_OFFSET))?;
-
+    fn read_page(f: &mut dyn Read) -> io::Result<Self> {
         let mut buffer = [0_u8; 500];
         f.read_exact(&mut buffer)?;
+
+        // pageTrailer
+        let trailer = AnsiPageTrailer::read(f)?;
+        if trailer.page_type() != PageType::DensityList {
+            return Err(NdbError::UnexpectedPageType(trailer.page_type()).into());
+        }
+
+        let crc = compute_crc(0, &buffer);
+        if crc != trailer.crc() {
+            return Err(NdbError::InvalidPageCrc(crc).into());
+        }
+
         let mut cursor = Cursor::new(buffer);
 
         // bFlags
@@ -999,19 +1063,6 @@ impl DensityListPageReadWrite<AnsiPstFile> for AnsiDensityListPage {
             return Err(NdbError::InvalidDensityListPadding.into());
         }
 
-        let buffer = cursor.into_inner();
-
-        // pageTrailer
-        let trailer = AnsiPageTrailer::read(f)?;
-        if trailer.page_type() != PageType::DensityList {
-            return Err(NdbError::UnexpectedPageType(trailer.page_type()).into());
-        }
-
-        let crc = compute_crc(0, &buffer);
-        if crc != trailer.crc() {
-            return Err(NdbError::InvalidPageCrc(crc).into());
-        }
-
         Ok(Self {
             backfill_complete,
             current_page,
@@ -1057,6 +1108,10 @@ impl DensityListPageReadWrite<AnsiPstFile> for AnsiDensityListPage {
         };
         trailer.write(f)
     }
+
+    fn update_entry(&mut self, page: u32, free_slots: u16) -> NdbResult<()> {
+        update_density_list_entry(&mut self.entries, &mut self.entry_count, page, free_slots)
+    }
 }
 
 pub trait BTreeEntryKey: Copy + Sized + Into<u64> + From<u32> {}
@@ -1080,6 +1135,7 @@ pub trait BTreePage {
     fn trailer(&self) -> &Self::Trailer;
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct UnicodeBTreeEntryPage {
     level: u8,
     max_entries: u8,
@@ -1160,6 +1216,7 @@ impl BTreePageReadWrite for UnicodeBTreeEntryPage {
 
 impl UnicodeBTreePageReadWrite<UnicodeBTreePageEntry> for UnicodeBTreeEntryPage {}
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct AnsiBTreeEntryPage {
     level: u8,
     max_entries: u8,
@@ -1266,7 +1323,7 @@ impl BTreePageKeyReadWrite for u64 {
     }
 }
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
 pub struct UnicodeBTreePageEntry {
     key: u64,
     block: UnicodePageRef,
@@ -1306,7 +1363,7 @@ impl BTreePageKeyReadWrite for u32 {
     }
 }
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
 pub struct AnsiBTreePageEntry {
     key: u32,
     block: AnsiPageRef,
@@ -1345,7 +1402,7 @@ pub trait BlockBTreeEntry: BTreeEntry {
     fn ref_count(&self) -> u16;
 }
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
 pub struct UnicodeBlockBTreeEntry {
     block: UnicodeBlockRef,
     size: u16,
@@ -1417,8 +1474,13 @@ impl BlockBTreeEntryReadWrite for UnicodeBlockBTreeEntry {
     fn new(block: UnicodeBlockRef, size: u16) -> Self {
         Self::new(block, size)
     }
+
+    fn with_ref_count(self, ref_count: u16) -> Self {
+        Self { ref_count, ..self }
+    }
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct UnicodeBlockBTreePage {
     max_entries: u8,
     entry_size: u8,
@@ -1496,7 +1558,7 @@ impl BTreePageReadWrite for UnicodeBlockBTreePage {
 
 impl UnicodeBTreePageReadWrite<UnicodeBlockBTreeEntry> for UnicodeBlockBTreePage {}
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
 pub struct AnsiBlockBTreeEntry {
     block: AnsiBlockRef,
     size: u16,
@@ -1559,8 +1621,13 @@ impl BlockBTreeEntryReadWrite for AnsiBlockBTreeEntry {
     fn new(block: AnsiBlockRef, size: u16) -> Self {
         Self::new(block, size)
     }
+
+    fn with_ref_count(self, ref_count: u16) -> Self {
+        Self { ref_count, ..self }
+    }
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct AnsiBlockBTreePage {
     max_entries: u8,
     entry_size: u8,
@@ -1648,7 +1715,7 @@ pub trait NodeBTreeEntry: BTreeEntry {
     fn parent(&self) -> Option<NodeId>;
 }
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
 pub struct UnicodeNodeBTreeEntry {
     node: NodeId,
     data: UnicodeBlockId,
@@ -1773,6 +1840,7 @@ impl NodeBTreeEntryReadWrite for UnicodeNodeBTreeEntry {
     }
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct UnicodeNodeBTreePage {
     max_entries: u8,
     entry_size: u8,
@@ -1850,7 +1918,7 @@ impl BTreePageReadWrite for UnicodeNodeBTreePage {
 
 impl UnicodeBTreePageReadWrite<UnicodeNodeBTreeEntry> for UnicodeNodeBTreePage {}
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
 pub struct AnsiNodeBTreeEntry {
     node: NodeId,
     data: AnsiBlockId,
@@ -1962,6 +2030,7 @@ impl NodeBTreeEntryReadWrite for AnsiNodeBTreeEntry {
     }
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct AnsiNodeBTreePage {
     max_entries: u8,
     entry_size: u8,
@@ -2233,7 +2302,8 @@ where
     <Self as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
 {
     fn read<R: PstReader>(f: &mut R, block: <Pst as PstFile>::PageRef) -> io::Result<Self> {
-        f.seek(SeekFrom::Start(block.index().index().into()))?;
+        let offset = block.index().index().into();
+        f.seek(SeekFrom::Start(offset))?;
 
         let mut buffer = [0_u8; PAGE_SIZE];
         f.read_exact(&mut buffer)?;
@@ -2243,11 +2313,13 @@ where
         let level = cursor.read_u8()?;
 
         cursor.seek(SeekFrom::Start(0))?;
-        Ok(if level == 0 {
-            Self::Leaf(Box::new(LeafPage::read(&mut cursor)?))
+        let page = if level == 0 {
+            LeafPage::read(&mut cursor).map(|page| Self::Leaf(Box::new(page)))
         } else {
-            Self::Intermediate(Box::new(IntermediatePage::read(&mut cursor)?), PhantomData)
-        })
+            IntermediatePage::read(&mut cursor)
+                .map(|page| Self::Intermediate(Box::new(page), PhantomData))
+        };
+        page.map_err(error_at(offset))
     }
 
     fn write<W: Write + Seek>(
@@ -2269,32 +2341,7 @@ where
         key: <Pst as PstFile>::BTreeKey,
         page_cache: &mut RootBTreePageCache<Self>,
     ) -> io::Result<Entry> {
-        let search_key: u64 = key.into();
-        match self {
-            Self::Intermediate(page, ..) => {
-                let entries = <Self::IntermediatePage as BTreePage>::entries(page);
-                let index = entries.partition_point(|entry| entry.key().into() <= search_key);
-                let entry = index
-                    .checked_sub(1)
-                    .and_then(|index| entries.get(index))
-                    .ok_or(NdbError::BTreePageNotFound(search_key))?;
-                let block = entry.block();
-                let page = match page_cache.remove(&block.block()) {
-                    Some(page) => page,
-                    None => <Self as RootBTreeReadWrite>::read(f, block)?,
-                };
-                let entry = page.find_entry(f, key, page_cache);
-                page_cache.insert(block.block(), page);
-                entry
-            }
-            Self::Leaf(page) => {
-                let entry = <Self::LeafPage as BTreePage>::entries(page)
-                    .iter()
-                    .find(|entry| entry.key().into() == search_key)
-                    .ok_or(NdbError::BTreePageNotFound(search_key))?;
-                Ok(*entry)
-            }
-        }
+        self.find_entry_on_path(f, key, page_cache, &mut Vec::new())
     }
 }
 
@@ -2334,6 +2381,49 @@ where
     ) -> io::Result<Entry> {
         <Self as RootBTreeReadWrite>::find_entry(self, f, key, page_cache)
     }
+
+    /// Descend to the leaf page for `key`, with the pages already visited on the way down in
+    /// `path`, so that a page which refers back to one of its parents fails instead of recursing
+    /// without bound.
+    fn find_entry_on_path<R: PstReader>(
+        &self,
+        f: &mut R,
+        key: <Pst as PstFile>::BTreeKey,
+        page_cache: &mut RootBTreePageCache<Self>,
+        path: &mut Vec<<Pst as PstFile>::PageId>,
+    ) -> io::Result<Entry> {
+        let search_key: u64 = key.into();
+        match self {
+            Self::Intermediate(page, ..) => {
+                let entries = <IntermediatePage as BTreePage>::entries(page);
+                let index =
+                    entries.partition_point(|entry| Into::<u64>::into(entry.key()) <= search_key);
+                let entry = index
+                    .checked_sub(1)
+                    .and_then(|index| entries.get(index))
+                    .ok_or(NdbError::BTreePageNotFound(search_key))?;
+                let block = entry.block();
+                if path.contains(&block.block()) {
+                    return Err(NdbError::CyclicReference(block.block().into_u64()).into());
+                }
+                path.push(block.block());
+                let page = match page_cache.remove(&block.block()) {
+                    Some(page) => page,
+                    None => <Self as RootBTreeReadWrite>::read(f, block)?,
+                };
+                let entry = page.find_entry_on_path(f, key, page_cache, path);
+                page_cache.insert(block.block(), page);
+                entry
+            }
+            Self::Leaf(page) => {
+                let entry = <LeafPage as BTreePage>::entries(page)
+                    .iter()
+                    .find(|entry| Into::<u64>::into(entry.key()) == search_key)
+                    .ok_or(NdbError::BTreePageNotFound(search_key))?;
+                Ok(*entry)
+            }
+        }
+    }
 }
 
 pub type UnicodeBTree<Entry, LeafPage> =
@@ -2379,3 +2469,345 @@ impl NodeBTreeReadWrite<UnicodePstFile, UnicodeNodeBTreeEntry> for UnicodeNodeBT
 pub type AnsiNodeBTree = AnsiBTree<AnsiNodeBTreeEntry, AnsiNodeBTreePage>;
 impl NodeBTree<AnsiPstFile, AnsiNodeBTreeEntry> for AnsiNodeBTree {}
 impl NodeBTreeReadWrite<AnsiPstFile, AnsiNodeBTreeEntry> for AnsiNodeBTree {}
+
+type AnyBTreePage<Pst, BTree> = RootBTreePage<
+    Pst,
+    <BTree as RootBTree>::Entry,
+    <BTree as RootBTree>::IntermediatePage,
+    <BTree as RootBTree>::LeafPage,
+>;
+
+/// Any page read from an arbitrary offset with [`read_any_page`], identified by the `ptype` in
+/// its [`PageTrailer`].
+pub enum AnyPage<Pst>
+where
+    Pst: PstFile,
+{
+    AllocationMap {
+        offset: u64,
+        page: Box<<Pst as PstFile>::AllocationMapPage>,
+    },
+    AllocationPageMap {
+        offset: u64,
+        page: Box<<Pst as PstFile>::AllocationPageMapPage>,
+    },
+    FreeMap {
+        offset: u64,
+        page: Box<<Pst as PstFile>::FreeMapPage>,
+    },
+    FreePageMap {
+        offset: u64,
+        page: Box<<Pst as PstFile>::FreePageMapPage>,
+    },
+    DensityList {
+        offset: u64,
+        page: Box<<Pst as PstFile>::DensityListPage>,
+    },
+    BlockBTree {
+        offset: u64,
+        page: AnyBTreePage<Pst, <Pst as PstFile>::BlockBTree>,
+    },
+    NodeBTree {
+        offset: u64,
+        page: AnyBTreePage<Pst, <Pst as PstFile>::NodeBTree>,
+    },
+}
+
+impl<Pst> AnyPage<Pst>
+where
+    Pst: PstFile,
+{
+    /// Byte offset of the page in the file.
+    pub fn offset(&self) -> u64 {
+        match self {
+            Self::AllocationMap { offset, .. }
+            | Self::AllocationPageMap { offset, .. }
+            | Self::FreeMap { offset, .. }
+            | Self::FreePageMap { offset, .. }
+            | Self::DensityList { offset, .. }
+            | Self::BlockBTree { offset, .. }
+            | Self::NodeBTree { offset, .. } => *offset,
+        }
+    }
+
+    pub fn page_type(&self) -> PageType {
+        match self {
+            Self::AllocationMap { .. } => PageType::AllocationMap,
+            Self::AllocationPageMap { .. } => PageType::AllocationPageMap,
+            Self::FreeMap { .. } => PageType::FreeMap,
+            Self::FreePageMap { .. } => PageType::FreePageMap,
+            Self::DensityList { .. } => PageType::DensityList,
+            Self::BlockBTree { .. } => PageType::BlockBTree,
+            Self::NodeBTree { .. } => PageType::NodeBTree,
+        }
+    }
+}
+
+/// Read the page at `offset`, which must be aligned on a [`PAGE_SIZE`] boundary, and parse it
+/// according to the `ptype` in its [`PageTrailer`]. The page CRC is validated along with the rest
+/// of the page structure, so this fails if the declared type does not match the page contents.
+pub fn read_any_page<Pst, R>(f: &mut R, offset: u64) -> io::Result<AnyPage<Pst>>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::ByteIndex: ByteIndex<Index: TryFrom<u64>>,
+    <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+    <Pst as PstFile>::AllocationMapPage: AllocationMapPageReadWrite<Pst>,
+    <Pst as PstFile>::AllocationPageMapPage: AllocationPageMapPageReadWrite<Pst>,
+    <Pst as PstFile>::FreeMapPage: FreeMapPageReadWrite<Pst>,
+    <Pst as PstFile>::FreePageMapPage: FreePageMapPageReadWrite<Pst>,
+    <Pst as PstFile>::DensityListPage: DensityListPageReadWrite<Pst>,
+    <Pst as PstFile>::NodeBTreeEntry: NodeBTreeEntryReadWrite,
+    <Pst as PstFile>::NodeBTree: NodeBTreeReadWrite<Pst, <Pst as PstFile>::NodeBTreeEntry>,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::NodeBTreeEntry,
+            <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockBTree: BlockBTreeReadWrite<Pst, <Pst as PstFile>::BlockBTreeEntry>,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::BlockBTreeEntry,
+            <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    R: PstReader,
+{
+    if offset % PAGE_SIZE as u64 != 0 {
+        return Err(NdbError::InvalidPageOffset(offset).into());
+    }
+
+    f.seek(SeekFrom::Start(offset))?;
+    let mut buffer = [0_u8; PAGE_SIZE];
+    f.read_exact(&mut buffer)?;
+
+    // Anything which fails to parse is reported along with the offset of the page.
+    let page = (|| -> io::Result<AnyPage<Pst>> {
+        let trailer_offset = PAGE_SIZE - <Pst::PageTrailer as PageTrailerReadWrite>::SIZE;
+        let trailer =
+            <Pst::PageTrailer as PageTrailerReadWrite>::read(&mut &buffer[trailer_offset..])?;
+        let mut cursor = buffer.as_slice();
+
+        Ok(match trailer.page_type() {
+            PageType::AllocationMap => AnyPage::AllocationMap {
+                offset,
+                page: Box::new(AllocationMapPageReadWrite::read(&mut cursor)?),
+            },
+            PageType::AllocationPageMap => AnyPage::AllocationPageMap {
+                offset,
+                page: Box::new(AllocationPageMapPageReadWrite::read(&mut cursor)?),
+            },
+            PageType::FreeMap => AnyPage::FreeMap {
+                offset,
+                page: Box::new(FreeMapPageReadWrite::read(&mut cursor)?),
+            },
+            PageType::FreePageMap => AnyPage::FreePageMap {
+                offset,
+                page: Box::new(FreePageMapPageReadWrite::read(&mut cursor)?),
+            },
+            PageType::DensityList => AnyPage::DensityList {
+                offset,
+                page: Box::new(DensityListPageReadWrite::read_page(&mut cursor)?),
+            },
+            page_type @ (PageType::BlockBTree | PageType::NodeBTree) => {
+                let index = <<Pst::ByteIndex as ByteIndex>::Index>::try_from(offset)
+                    .map_err(|_| NdbError::InvalidPageOffset(offset))?;
+                let page = <Pst::PageRef as BlockRefReadWrite>::new(
+                    trailer.block_id(),
+                    <Pst::ByteIndex as ByteIndexReadWrite>::new(index),
+                );
+
+                if page_type == PageType::BlockBTree {
+                    AnyPage::BlockBTree {
+                        offset,
+                        page: <Pst::BlockBTree as RootBTreeReadWrite>::read(f, page)?,
+                    }
+                } else {
+                    AnyPage::NodeBTree {
+                        offset,
+                        page: <Pst::NodeBTree as RootBTreeReadWrite>::read(f, page)?,
+                    }
+                }
+            }
+            PageType::None => return Err(NdbError::UnexpectedPageType(PageType::None).into()),
+        })
+    })();
+    page.map_err(error_at(offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ndb::{header::Header, root::Root, DensityListError};
+    use crate::test_util::EMPTY_PST;
+    use std::fs::{self, File};
+
+    /// The first AMap page immediately follows the DList page.
+    const FIRST_AMAP_FILE_OFFSET: u64 = DENSITY_LIST_FILE_OFFSET + PAGE_SIZE as u64;
+
+    #[test]
+    fn test_read_any_page_maps() {
+        let mut file = File::open(EMPTY_PST).unwrap();
+
+        let page = read_any_page::<UnicodePstFile, _>(&mut file, DENSITY_LIST_FILE_OFFSET).unwrap();
+        assert!(matches!(page, AnyPage::DensityList { .. }));
+        assert_eq!(page.offset(), DENSITY_LIST_FILE_OFFSET);
+
+        let page = read_any_page::<UnicodePstFile, _>(&mut file, FIRST_AMAP_FILE_OFFSET).unwrap();
+        assert_eq!(page.page_type(), PageType::AllocationMap);
+        assert_eq!(page.offset(), FIRST_AMAP_FILE_OFFSET);
+    }
+
+    #[test]
+    fn test_read_any_page_btrees() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let root = pst.header().root();
+        let mut file = File::open(EMPTY_PST).unwrap();
+
+        let offset = root.node_btree().index().index();
+        let page = read_any_page::<UnicodePstFile, _>(&mut file, offset).unwrap();
+        assert!(matches!(page, AnyPage::NodeBTree { .. }));
+        assert_eq!(page.offset(), offset);
+
+        let offset = root.block_btree().index().index();
+        let page = read_any_page::<UnicodePstFile, _>(&mut file, offset).unwrap();
+        assert!(matches!(page, AnyPage::BlockBTree { .. }));
+        assert_eq!(page.offset(), offset);
+    }
+
+    #[test]
+    fn test_read_any_page_unaligned() {
+        let mut file = File::open(EMPTY_PST).unwrap();
+        let Err(err) = read_any_page::<UnicodePstFile, _>(&mut file, FIRST_AMAP_FILE_OFFSET + 1)
+        else {
+            panic!("Unaligned offset should fail");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_read_any_page_bad_crc() {
+        let mut data = fs::read(EMPTY_PST).unwrap();
+        data[FIRST_AMAP_FILE_OFFSET as usize] ^= 0xFF;
+
+        let mut cursor = Cursor::new(data);
+        let Err(err) = read_any_page::<UnicodePstFile, _>(&mut cursor, FIRST_AMAP_FILE_OFFSET)
+        else {
+            panic!("Corrupt page should fail");
+        };
+        let err = err.into_inner().unwrap().downcast::<NdbError>().unwrap();
+        assert!(matches!(err.without_offset(), NdbError::InvalidPageCrc(_)));
+        assert_eq!(err.offset(), Some(FIRST_AMAP_FILE_OFFSET));
+        assert!(err
+            .to_string()
+            .ends_with(&format!("offset: 0x{FIRST_AMAP_FILE_OFFSET:X})")));
+    }
+
+    /// Copy `Empty.pst` with its density list replaced by one with `entries`.
+    fn with_density_list(entries: &[DensityListPageEntry]) -> Vec<u8> {
+        let data = fs::read(EMPTY_PST).unwrap();
+        let page = UnicodeDensityListPage::read(&mut Cursor::new(&data)).unwrap();
+        let page = UnicodeDensityListPage::new(true, page.current_page(), entries, *page.trailer())
+            .unwrap();
+        let mut cursor = Cursor::new(data);
+        page.write(&mut cursor).unwrap();
+        cursor.into_inner()
+    }
+
+    #[test]
+    fn test_cyclic_btree() {
+        // An intermediate BBT page whose only entry points back at itself.
+        let page_ref = UnicodePageRef::new(UnicodePageId::from(1), UnicodeByteIndex::new(0));
+        let trailer = UnicodePageTrailer::new(PageType::BlockBTree, 0, page_ref.block(), 0);
+        let entry = UnicodeBTreePageEntry::new(0, page_ref);
+        let page = UnicodeBTreeEntryPage::new(1, 20, 24, &[entry], trailer).unwrap();
+        let btree = UnicodeBlockBTree::Intermediate(Box::new(page), PhantomData);
+        let mut file = Cursor::new(vec![0; PAGE_SIZE]);
+        btree.write(&mut file, page_ref).unwrap();
+
+        let btree = UnicodeBlockBTree::read(&mut file, page_ref).unwrap();
+        let err = btree
+            .find_entry(&mut file, 4, &mut Default::default())
+            .unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<NdbError>().unwrap();
+        assert!(matches!(*err, NdbError::CyclicReference(1)));
+    }
+
+    #[test]
+    fn test_density_list_free_pages() {
+        let entries = [
+            DensityListPageEntry::new(2, 300).unwrap(),
+            DensityListPageEntry::new(0, 120).unwrap(),
+            DensityListPageEntry::new(1, 8).unwrap(),
+        ];
+        let data = with_density_list(&entries);
+        let pst = UnicodePstFile::read_from(Box::new(Cursor::new(data))).unwrap();
+        let density_list = pst.density_list().unwrap();
+        assert!(density_list.backfill_complete());
+
+        let pages: Vec<_> = density_list
+            .free_pages(100)
+            .map(|entry| entry.page())
+            .collect();
+        assert_eq!(pages, [2, 0]);
+        assert_eq!(density_list.free_pages(0).count(), entries.len());
+    }
+
+    #[test]
+    fn test_density_list_update_entry() {
+        let data = with_density_list(&[
+            DensityListPageEntry::new(2, 300).unwrap(),
+            DensityListPageEntry::new(0, 120).unwrap(),
+        ]);
+        let mut page = UnicodeDensityListPage::read(&mut Cursor::new(data)).unwrap();
+
+        page.update_entry(1, 200).unwrap();
+        page.update_entry(2, 8).unwrap();
+        let entries: Vec<_> = page
+            .entries()
+            .iter()
+            .map(|entry| (entry.page(), entry.free_slots()))
+            .collect();
+        assert_eq!(entries, [(1, 200), (0, 120), (2, 8)]);
+
+        assert!(matches!(
+            page.update_entry(0, 0x1000),
+            Err(NdbError::InvalidDensityListEntryFreeSlots(0x1000))
+        ));
+
+        let mut cursor = Cursor::new(vec![0; DENSITY_LIST_FILE_OFFSET as usize + PAGE_SIZE]);
+        page.write(&mut cursor).unwrap();
+        cursor.set_position(DENSITY_LIST_FILE_OFFSET);
+        let page = UnicodeDensityListPage::read_page(&mut cursor).unwrap();
+        assert_eq!(page.entries().len(), 3);
+        assert_eq!(page.entries()[0].page(), 1);
+    }
+
+    #[test]
+    fn test_density_list_errors() {
+        let offset = DENSITY_LIST_FILE_OFFSET as usize;
+
+        let mut data = fs::read(EMPTY_PST).unwrap();
+        data[offset + 4] ^= 0xFF;
+        let pst = UnicodePstFile::read_from(Box::new(Cursor::new(data))).unwrap();
+        assert!(matches!(
+            pst.density_list().err(),
+            Some(DensityListError::InvalidCrc(_))
+        ));
+
+        let mut data = fs::read(EMPTY_PST).unwrap();
+        data[offset..offset + PAGE_SIZE].fill(0);
+        let pst = UnicodePstFile::read_from(Box::new(Cursor::new(data))).unwrap();
+        assert!(matches!(
+            pst.density_list().err(),
+            Some(DensityListError::NotPresent)
+        ));
+    }
+}
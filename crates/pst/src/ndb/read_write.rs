@@ -1,15 +1,24 @@
 #![allow(dead_code)]
 
 use std::{
-    cell::RefCell,
+    borrow::Cow,
     cmp::Ordering,
     collections::BTreeMap,
     io::{self, Cursor, Read, Seek, SeekFrom, Write},
-    rc::Rc,
+    sync::{Arc, Mutex},
 };
 
 use super::{
-    block::*, block_id::*, block_ref::*, byte_index::*, header::*, node_id::*, page::*, root::*, *,
+    block::*,
+    block_id::*,
+    block_ref::*,
+    byte_index::*,
+    header::*,
+    node_id::*,
+    options::{enforce, Check},
+    page::*,
+    root::*,
+    *,
 };
 use crate::{
     crc::compute_crc,
@@ -114,6 +123,15 @@ where
 
     fn set_amap_status(&mut self, status: AmapStatus);
     fn reset_free_size(&mut self, free_bytes: <Pst as PstFile>::ByteIndex) -> NdbResult<()>;
+    fn set_free_sizes(
+        &mut self,
+        amap_free_bytes: <Pst as PstFile>::ByteIndex,
+        pmap_free_bytes: <Pst as PstFile>::ByteIndex,
+    );
+    fn set_file_eof_index(&mut self, file_eof_index: <Pst as PstFile>::ByteIndex);
+    fn set_amap_last_index(&mut self, amap_last_index: <Pst as PstFile>::ByteIndex);
+    fn set_node_btree(&mut self, node_btree: <Pst as PstFile>::PageRef);
+    fn set_block_btree(&mut self, block_btree: <Pst as PstFile>::PageRef);
 }
 
 pub trait HeaderReadWrite<Pst>: Header<Pst> + Sized
@@ -121,14 +139,22 @@ where
     Pst: PstFile,
     <Pst as PstFile>::Root: Root<Pst> + RootReadWrite<Pst>,
 {
+    fn new(root: <Pst as PstFile>::Root, crypt_method: NdbCryptMethod) -> Self;
     fn read(f: &mut dyn Read) -> io::Result<Self>;
     fn write(&self, f: &mut dyn Write) -> io::Result<()>;
     fn update_unique(&mut self);
     fn first_free_map(&mut self) -> &mut [u8];
     fn first_free_page_map(&mut self) -> &mut [u8];
+    fn set_next_block(&mut self, next_block: <Pst as PstFile>::BlockId);
+    fn set_next_page(&mut self, next_page: <Pst as PstFile>::PageId);
+
+    /// The `rgnid` array of the next available [`NodeId`] index for each [`NodeIdType`].
+    fn next_node_ids(&mut self) -> &mut [u32];
 }
 
 pub trait PageTrailerReadWrite: PageTrailer + Copy + Sized {
+    const SIZE: usize;
+
     fn new(page_type: PageType, signature: u16, block_id: Self::BlockId, crc: u32) -> Self;
     fn read(f: &mut dyn Read) -> io::Result<Self>;
     fn write(&self, f: &mut dyn Write) -> io::Result<()>;
@@ -256,8 +282,17 @@ where
         entries: &[DensityListPageEntry],
         trailer: <Pst as PstFile>::PageTrailer,
     ) -> NdbResult<Self>;
-    fn read<R: PstReader>(f: &mut R) -> io::Result<Self>;
+    fn read_page(f: &mut dyn Read) -> io::Result<Self>;
     fn write<W: Write + Seek>(&self, f: &mut W) -> io::Result<()>;
+
+    /// Replace the entry for the AMap page at index `page` with `free_slots`, or add one if the
+    /// page is not in the list yet. The entries stay sorted from the emptiest page to the fullest.
+    fn update_entry(&mut self, page: u32, free_slots: u16) -> NdbResult<()>;
+
+    fn read<R: PstReader>(f: &mut R) -> io::Result<Self> {
+        f.seek(SeekFrom::Start(DENSITY_LIST_FILE_OFFSET))?;
+        Self::read_page(f)
+    }
 }
 
 pub trait BTreePageKeyReadWrite: BTreeEntryKey + TryFrom<u64> {
@@ -274,6 +309,9 @@ pub trait BTreeEntryReadWrite: BTreeEntry + Copy + Sized + Default {
 
 pub trait BlockBTreeEntryReadWrite: BlockBTreeEntry + BTreeEntryReadWrite {
     fn new(block: Self::Block, size: u16) -> Self;
+
+    /// Copy this entry with a different `cRef` reference count.
+    fn with_ref_count(self, ref_count: u16) -> Self;
 }
 
 pub trait BTreePageEntryReadWrite: BTreePageEntry
@@ -352,7 +390,7 @@ where
         // dwPadding
         let padding = cursor.read_u32::<LittleEndian>()?;
         if padding != 0 {
-            return Err(NdbError::InvalidBTreePagePadding(padding).into());
+            enforce(Check::Padding, NdbError::InvalidBTreePagePadding(padding))?;
         }
 
         // pageTrailer
@@ -364,7 +402,7 @@ where
 
         let crc = compute_crc(0, buffer);
         if crc != trailer.crc() {
-            return Err(NdbError::InvalidPageCrc(crc).into());
+            enforce(Check::Crc, NdbError::InvalidPageCrc(crc))?;
         }
 
         // rgentries
@@ -480,7 +518,7 @@ where
 
         let crc = compute_crc(0, buffer);
         if crc != trailer.crc() {
-            return Err(NdbError::InvalidPageCrc(crc).into());
+            enforce(Check::Crc, NdbError::InvalidPageCrc(crc))?;
         }
 
         // rgentries
@@ -568,37 +606,35 @@ where
     fn new(encoding: NdbCryptMethod, data: Vec<u8>, trailer: Self::Trailer) -> NdbResult<Self>;
 
     fn read<R: PstReader>(f: &mut R, size: u16, encoding: NdbCryptMethod) -> io::Result<Self> {
-        let mut data = vec![0; size as usize];
+        let mut data = vec![0; block_size(size + Self::Trailer::SIZE) as usize];
         f.read_exact(&mut data)?;
+        Self::read_from_slice(&data, size, encoding)
+    }
 
-        let offset = size + Self::Trailer::SIZE;
-        let offset = i64::from(block_size(offset) - offset);
-        if offset > 0 {
-            f.seek(SeekFrom::Current(offset))?;
-        }
-
-        let trailer = Self::Trailer::read(f)?;
+    /// Read a block from a buffer holding the whole block, including the padding and the
+    /// trailer. The trailer and the CRC are checked in place, so the only copy is the owned
+    /// buffer which holds the decoded data.
+    fn read_from_slice(data: &[u8], size: u16, encoding: NdbCryptMethod) -> io::Result<Self> {
+        let trailer_offset = block_size(size + Self::Trailer::SIZE) - Self::Trailer::SIZE;
+        let (Some(data), Some(mut trailer)) = (
+            data.get(..usize::from(size)),
+            data.get(usize::from(trailer_offset)..),
+        ) else {
+            return Err(NdbError::InvalidBlockSize(size).into());
+        };
+
+        let trailer = Self::Trailer::read(&mut trailer)?;
         if trailer.size() != size {
             return Err(NdbError::InvalidBlockSize(trailer.size()).into());
         }
         trailer.verify_block_id(false)?;
-        let crc = compute_crc(0, &data);
+        let crc = compute_crc(0, data);
         if crc != trailer.crc() {
-            return Err(NdbError::InvalidBlockCrc(crc).into());
-        }
-
-        match encoding {
-            NdbCryptMethod::Cyclic => {
-                let key = trailer.cyclic_key();
-                cyclic::encode_decode_block(&mut data, key);
-            }
-            NdbCryptMethod::Permute => {
-                permute::decode_block(&mut data);
-            }
-            _ => {}
+            enforce(Check::Crc, NdbError::InvalidBlockCrc(crc))?;
         }
 
-        Ok(Self::new(encoding, data, trailer)?)
+        let data = decode_block_data(Cow::Borrowed(data), encoding, trailer.cyclic_key());
+        Ok(Self::new(encoding, data.into_owned(), trailer)?)
     }
 
     fn write<W: Write + Seek>(&self, f: &mut W) -> io::Result<()> {
@@ -698,7 +734,7 @@ where
 
         let crc = compute_crc(0, &data);
         if crc != trailer.crc() {
-            return Err(NdbError::InvalidBlockCrc(crc).into());
+            enforce(Check::Crc, NdbError::InvalidBlockCrc(crc))?;
         }
 
         Ok(Self::new(header, entries, trailer)?)
@@ -746,8 +782,8 @@ type RootBTreePageReadWrite<BTree> = RootBTreePage<
 pub type RootBTreePageCache<BTree> =
     BTreeMap<<<BTree as RootBTree>::Pst as PstFile>::PageId, RootBTreePageReadWrite<BTree>>;
 
-pub type BlockBTreePageCache<Pst> = Rc<RefCell<RootBTreePageCache<<Pst as PstFile>::BlockBTree>>>;
-pub type NodeBTreePageCache<Pst> = Rc<RefCell<RootBTreePageCache<<Pst as PstFile>::NodeBTree>>>;
+pub type BlockBTreePageCache<Pst> = Arc<Mutex<RootBTreePageCache<<Pst as PstFile>::BlockBTree>>>;
+pub type NodeBTreePageCache<Pst> = Arc<Mutex<RootBTreePageCache<<Pst as PstFile>::NodeBTree>>>;
 
 pub trait RootBTreeReadWrite: RootBTree + Sized
 where
@@ -849,3 +885,677 @@ where
         + RootBTreeLeafPageReadWrite<Pst>,
 {
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnsiPstFile, UnicodePstFile};
+    use proptest::{collection::vec, option, prelude::*};
+    use std::fmt::Debug;
+
+    /// Write `value`, check the size of the output, and read it back.
+    fn round_trip<T: PartialEq + Debug>(
+        value: &T,
+        size: usize,
+        write: impl FnOnce(&T, &mut dyn Write) -> io::Result<()>,
+        read: impl FnOnce(&mut dyn Read) -> io::Result<T>,
+    ) {
+        let mut data = Vec::new();
+        write(value, &mut data).unwrap();
+        assert_eq!(data.len(), size);
+        let mut cursor = data.as_slice();
+        assert_eq!(&read(&mut cursor).unwrap(), value);
+        assert!(cursor.is_empty());
+    }
+
+    fn ndb_error(err: io::Error) -> NdbError {
+        *err.into_inner().unwrap().downcast::<NdbError>().unwrap()
+    }
+
+    fn unicode_block_id() -> impl Strategy<Value = UnicodeBlockId> {
+        (any::<bool>(), 0..=MAX_UNICODE_BLOCK_INDEX)
+            .prop_map(|(is_internal, index)| UnicodeBlockId::new(is_internal, index).unwrap())
+    }
+
+    fn ansi_block_id() -> impl Strategy<Value = AnsiBlockId> {
+        (any::<bool>(), 0..=MAX_ANSI_BLOCK_INDEX)
+            .prop_map(|(is_internal, index)| AnsiBlockId::new(is_internal, index).unwrap())
+    }
+
+    /// A `bidSub` which is not read back as `None`.
+    fn unicode_sub_node() -> impl Strategy<Value = Option<UnicodeBlockId>> {
+        option::of(
+            (any::<bool>(), 1..=MAX_UNICODE_BLOCK_INDEX)
+                .prop_map(|(is_internal, index)| UnicodeBlockId::new(is_internal, index).unwrap()),
+        )
+    }
+
+    /// A `bidSub` which is not read back as `None`.
+    fn ansi_sub_node() -> impl Strategy<Value = Option<AnsiBlockId>> {
+        option::of(
+            (any::<bool>(), 1..=MAX_ANSI_BLOCK_INDEX)
+                .prop_map(|(is_internal, index)| AnsiBlockId::new(is_internal, index).unwrap()),
+        )
+    }
+
+    fn unicode_page_ref() -> impl Strategy<Value = UnicodePageRef> {
+        (any::<u64>(), any::<u64>()).prop_map(|(block, index)| {
+            UnicodePageRef::new(UnicodePageId::from(block), UnicodeByteIndex::new(index))
+        })
+    }
+
+    fn ansi_page_ref() -> impl Strategy<Value = AnsiPageRef> {
+        (any::<u32>(), any::<u32>()).prop_map(|(block, index)| {
+            AnsiPageRef::new(AnsiPageId::from(block), AnsiByteIndex::new(index))
+        })
+    }
+
+    fn btree_page_type() -> impl Strategy<Value = PageType> {
+        prop_oneof![Just(PageType::BlockBTree), Just(PageType::NodeBTree)]
+    }
+
+    fn page_type() -> impl Strategy<Value = PageType> {
+        prop_oneof![
+            btree_page_type(),
+            Just(PageType::FreeMap),
+            Just(PageType::AllocationPageMap),
+            Just(PageType::AllocationMap),
+            Just(PageType::FreePageMap),
+            Just(PageType::DensityList),
+        ]
+    }
+
+    fn amap_status() -> impl Strategy<Value = AmapStatus> {
+        prop_oneof![
+            Just(AmapStatus::Invalid),
+            Just(AmapStatus::Valid1),
+            Just(AmapStatus::Valid2),
+        ]
+    }
+
+    fn unicode_page_trailer(page_type: PageType) -> impl Strategy<Value = UnicodePageTrailer> {
+        (any::<u16>(), any::<u64>(), any::<u32>()).prop_map(move |(signature, block_id, crc)| {
+            UnicodePageTrailer::new(page_type, signature, UnicodePageId::from(block_id), crc)
+        })
+    }
+
+    fn ansi_page_trailer(page_type: PageType) -> impl Strategy<Value = AnsiPageTrailer> {
+        (any::<u16>(), any::<u32>(), any::<u32>()).prop_map(move |(signature, block_id, crc)| {
+            AnsiPageTrailer::new(page_type, signature, AnsiPageId::from(block_id), crc)
+        })
+    }
+
+    fn node_id() -> impl Strategy<Value = NodeId> {
+        any::<u32>().prop_map(NodeId::from)
+    }
+
+    /// A parent `nidParent` which is not read back as `None`.
+    fn parent_node_id() -> impl Strategy<Value = Option<NodeId>> {
+        option::of((1..=u32::MAX).prop_map(NodeId::from))
+    }
+
+    /// Write a BTree page, read it back and check that it matches. The CRC in the trailer is
+    /// recomputed when the page is written, so the trailers are compared without it.
+    fn btree_page_round_trip<Page>(
+        page: &Page,
+        write: impl FnOnce(&Page, &mut dyn Write) -> io::Result<()>,
+        read: impl FnOnce(&mut dyn Read) -> io::Result<Page>,
+    ) where
+        Page: BTreePageReadWrite<Entry: PartialEq + Debug, Trailer: PageTrailer<BlockId: Debug>>,
+    {
+        let mut data = Vec::new();
+        write(page, &mut data).unwrap();
+        assert_eq!(data.len(), PAGE_SIZE);
+        let read = read(&mut data.as_slice()).unwrap();
+        assert_eq!(read.level(), page.level());
+        assert_eq!(read.max_entries(), page.max_entries());
+        assert_eq!(read.entry_size(), page.entry_size());
+        assert_eq!(read.entries(), page.entries());
+        let (expected, actual) = (page.trailer(), read.trailer());
+        assert_eq!(actual.page_type(), expected.page_type());
+        assert_eq!(actual.signature(), expected.signature());
+        assert_eq!(actual.block_id(), expected.block_id());
+    }
+
+    proptest! {
+        #[test]
+        fn test_block_id_round_trip(unicode in unicode_block_id(), ansi in ansi_block_id()) {
+            round_trip(&unicode, 8, UnicodeBlockId::write, UnicodeBlockId::read);
+            round_trip(&ansi, 4, AnsiBlockId::write, AnsiBlockId::read);
+        }
+
+        #[test]
+        fn test_block_id_out_of_range(
+            unicode in (MAX_UNICODE_BLOCK_INDEX + 1)..,
+            ansi in (MAX_ANSI_BLOCK_INDEX + 1)..,
+        ) {
+            prop_assert!(matches!(
+                UnicodeBlockId::new(false, unicode),
+                Err(NdbError::InvalidUnicodeBlockIndex(value)) if value == unicode
+            ));
+            prop_assert!(matches!(
+                AnsiBlockId::new(true, ansi),
+                Err(NdbError::InvalidAnsiBlockIndex(value)) if value == ansi
+            ));
+        }
+
+        #[test]
+        fn test_byte_index_round_trip(unicode in any::<u64>(), ansi in any::<u32>()) {
+            let unicode = UnicodeByteIndex::new(unicode);
+            round_trip(&unicode, 8, UnicodeByteIndex::write, UnicodeByteIndex::read);
+            let ansi = AnsiByteIndex::new(ansi);
+            round_trip(&ansi, 4, AnsiByteIndex::write, AnsiByteIndex::read);
+        }
+
+        #[test]
+        fn test_block_ref_round_trip(
+            unicode in (unicode_block_id(), any::<u64>()),
+            ansi in (ansi_block_id(), any::<u32>()),
+        ) {
+            let unicode = UnicodeBlockRef::new(unicode.0, UnicodeByteIndex::new(unicode.1));
+            round_trip(&unicode, 16, UnicodeBlockRef::write, UnicodeBlockRef::read);
+            let ansi = AnsiBlockRef::new(ansi.0, AnsiByteIndex::new(ansi.1));
+            round_trip(&ansi, 8, AnsiBlockRef::write, AnsiBlockRef::read);
+        }
+
+        #[test]
+        fn test_page_trailer_round_trip(
+            unicode in page_type().prop_flat_map(unicode_page_trailer),
+            ansi in page_type().prop_flat_map(ansi_page_trailer),
+        ) {
+            round_trip(
+                &unicode,
+                UnicodePageTrailer::SIZE,
+                UnicodePageTrailer::write,
+                UnicodePageTrailer::read,
+            );
+            round_trip(
+                &ansi,
+                AnsiPageTrailer::SIZE,
+                AnsiPageTrailer::write,
+                AnsiPageTrailer::read,
+            );
+        }
+
+        #[test]
+        fn test_page_trailer_invalid_type(page_type in any::<u8>(), repeat in any::<u8>()) {
+            prop_assume!(page_type != repeat || PageType::try_from(page_type).is_err());
+            let data = [&[page_type, repeat][..], &[0; 14]].concat();
+            let err = ndb_error(UnicodePageTrailer::read(&mut data.as_slice()).unwrap_err());
+            if page_type != repeat {
+                prop_assert!(matches!(
+                    err,
+                    NdbError::MismatchPageTypeRepeat(first, second)
+                        if first == page_type && second == repeat
+                ));
+            } else {
+                prop_assert!(
+                    matches!(err, NdbError::InvalidPageType(value) if value == page_type)
+                );
+            }
+        }
+
+        #[test]
+        fn test_block_trailer_round_trip(
+            size in 1..=(MAX_BLOCK_SIZE - UnicodeBlockTrailer::SIZE),
+            signature in any::<u16>(),
+            crc in any::<u32>(),
+            unicode in unicode_block_id(),
+            ansi in ansi_block_id(),
+        ) {
+            let unicode = UnicodeBlockTrailer::new(size, signature, crc, unicode).unwrap();
+            round_trip(
+                &unicode,
+                usize::from(UnicodeBlockTrailer::SIZE),
+                UnicodeBlockTrailer::write,
+                UnicodeBlockTrailer::read,
+            );
+            let ansi = AnsiBlockTrailer::new(size, signature, crc, ansi).unwrap();
+            round_trip(
+                &ansi,
+                usize::from(AnsiBlockTrailer::SIZE),
+                AnsiBlockTrailer::write,
+                AnsiBlockTrailer::read,
+            );
+        }
+
+        #[test]
+        fn test_block_trailer_invalid_size(
+            size in prop_oneof![Just(0), (MAX_BLOCK_SIZE - AnsiBlockTrailer::SIZE + 1)..],
+        ) {
+            prop_assert!(matches!(
+                AnsiBlockTrailer::new(size, 0, 0, AnsiBlockId::default()),
+                Err(NdbError::InvalidBlockSize(value)) if value == size
+            ));
+            let data = [&size.to_le_bytes()[..], &[0; 10]].concat();
+            let err = ndb_error(AnsiBlockTrailer::read(&mut data.as_slice()).unwrap_err());
+            prop_assert!(matches!(err, NdbError::InvalidBlockSize(value) if value == size));
+        }
+
+        #[test]
+        fn test_root_round_trip(
+            indices in any::<[u64; 4]>(),
+            btrees in (unicode_page_ref(), unicode_page_ref()),
+            amap_is_valid in amap_status(),
+            reserved in any::<(u32, u8, u16)>(),
+        ) {
+            let mut root = <UnicodeRoot as RootReadWrite<UnicodePstFile>>::new(
+                UnicodeByteIndex::new(indices[0]),
+                UnicodeByteIndex::new(indices[1]),
+                UnicodeByteIndex::new(indices[2]),
+                UnicodeByteIndex::new(indices[3]),
+                btrees.0,
+                btrees.1,
+                amap_is_valid,
+            );
+            root.load_reserved(reserved.0, reserved.1, reserved.2);
+            round_trip(
+                &root,
+                72,
+                <UnicodeRoot as RootReadWrite<UnicodePstFile>>::write,
+                <UnicodeRoot as RootReadWrite<UnicodePstFile>>::read,
+            );
+        }
+
+        #[test]
+        fn test_ansi_root_round_trip(
+            indices in any::<[u32; 4]>(),
+            btrees in (ansi_page_ref(), ansi_page_ref()),
+            amap_is_valid in amap_status(),
+            reserved in any::<(u32, u8, u16)>(),
+        ) {
+            let mut root = <AnsiRoot as RootReadWrite<AnsiPstFile>>::new(
+                AnsiByteIndex::new(indices[0]),
+                AnsiByteIndex::new(indices[1]),
+                AnsiByteIndex::new(indices[2]),
+                AnsiByteIndex::new(indices[3]),
+                btrees.0,
+                btrees.1,
+                amap_is_valid,
+            );
+            root.load_reserved(reserved.0, reserved.1, reserved.2);
+            round_trip(
+                &root,
+                40,
+                <AnsiRoot as RootReadWrite<AnsiPstFile>>::write,
+                <AnsiRoot as RootReadWrite<AnsiPstFile>>::read,
+            );
+        }
+
+        #[test]
+        fn test_data_tree_header_round_trip(
+            level in any::<u8>(),
+            entry_count in any::<u16>(),
+            total_size in any::<u32>(),
+        ) {
+            let header = DataTreeBlockHeader::new(level, entry_count, total_size);
+            round_trip(
+                &header,
+                usize::from(DataTreeBlockHeader::HEADER_SIZE),
+                DataTreeBlockHeader::write,
+                DataTreeBlockHeader::read,
+            );
+        }
+
+        #[test]
+        fn test_intermediate_tree_header_invalid_type(block_type in any::<u8>()) {
+            let data = [block_type, 0, 0, 0, 0, 0, 0, 0];
+            match block_type {
+                0x01 => {
+                    prop_assert!(DataTreeBlockHeader::read(&mut data.as_slice()).is_ok());
+                }
+                _ => {
+                    let err = DataTreeBlockHeader::read(&mut data.as_slice()).unwrap_err();
+                    prop_assert!(matches!(
+                        ndb_error(err),
+                        NdbError::InvalidInternalBlockType(value) if value == block_type
+                    ));
+                }
+            }
+            match block_type {
+                0x02 => {
+                    let mut cursor = data.as_slice();
+                    prop_assert!(UnicodeSubNodeTreeBlockHeader::read(&mut cursor).is_ok());
+                }
+                _ => {
+                    let mut cursor = data.as_slice();
+                    let err = UnicodeSubNodeTreeBlockHeader::read(&mut cursor).unwrap_err();
+                    prop_assert!(matches!(
+                        ndb_error(err),
+                        NdbError::InvalidInternalBlockType(value) if value == block_type
+                    ));
+                }
+            }
+        }
+
+        #[test]
+        fn test_data_tree_entry_round_trip(unicode in unicode_block_id(), ansi in ansi_block_id()) {
+            round_trip(
+                &UnicodeDataTreeEntry::from(unicode),
+                usize::from(UnicodeDataTreeEntry::ENTRY_SIZE),
+                UnicodeDataTreeEntry::write,
+                UnicodeDataTreeEntry::read,
+            );
+            round_trip(
+                &AnsiDataTreeEntry::from(ansi),
+                usize::from(AnsiDataTreeEntry::ENTRY_SIZE),
+                AnsiDataTreeEntry::write,
+                AnsiDataTreeEntry::read,
+            );
+        }
+
+        #[test]
+        fn test_sub_node_tree_header_round_trip(level in any::<u8>(), entry_count in any::<u16>()) {
+            round_trip(
+                &UnicodeSubNodeTreeBlockHeader::new(level, entry_count),
+                usize::from(UnicodeSubNodeTreeBlockHeader::HEADER_SIZE),
+                UnicodeSubNodeTreeBlockHeader::write,
+                UnicodeSubNodeTreeBlockHeader::read,
+            );
+            round_trip(
+                &AnsiSubNodeTreeBlockHeader::new(level, entry_count),
+                usize::from(AnsiSubNodeTreeBlockHeader::HEADER_SIZE),
+                AnsiSubNodeTreeBlockHeader::write,
+                AnsiSubNodeTreeBlockHeader::read,
+            );
+        }
+
+        #[test]
+        fn test_sub_node_entry_round_trip(
+            node in node_id(),
+            unicode in (unicode_block_id(), unicode_sub_node()),
+            ansi in (ansi_block_id(), ansi_sub_node()),
+        ) {
+            round_trip(
+                &UnicodeLeafSubNodeTreeEntry::new(node, unicode.0, unicode.1),
+                usize::from(UnicodeLeafSubNodeTreeEntry::ENTRY_SIZE),
+                UnicodeLeafSubNodeTreeEntry::write,
+                UnicodeLeafSubNodeTreeEntry::read,
+            );
+            round_trip(
+                &UnicodeIntermediateSubNodeTreeEntry::new(node, unicode.0),
+                usize::from(UnicodeIntermediateSubNodeTreeEntry::ENTRY_SIZE),
+                UnicodeIntermediateSubNodeTreeEntry::write,
+                UnicodeIntermediateSubNodeTreeEntry::read,
+            );
+            round_trip(
+                &AnsiLeafSubNodeTreeEntry::new(node, ansi.0, ansi.1),
+                usize::from(AnsiLeafSubNodeTreeEntry::ENTRY_SIZE),
+                AnsiLeafSubNodeTreeEntry::write,
+                AnsiLeafSubNodeTreeEntry::read,
+            );
+            round_trip(
+                &AnsiIntermediateSubNodeTreeEntry::new(node, ansi.0),
+                usize::from(AnsiIntermediateSubNodeTreeEntry::ENTRY_SIZE),
+                AnsiIntermediateSubNodeTreeEntry::write,
+                AnsiIntermediateSubNodeTreeEntry::read,
+            );
+        }
+
+        #[test]
+        fn test_btree_entry_round_trip(
+            node in (node_id(), parent_node_id()),
+            unicode in (unicode_block_id(), unicode_sub_node(), any::<u64>()),
+            ansi in (ansi_block_id(), ansi_sub_node(), any::<u32>()),
+            size in any::<u16>(),
+            ref_count in any::<u16>(),
+        ) {
+            let page = UnicodePageRef::new(UnicodePageId::from(unicode.2), Default::default());
+            round_trip(
+                &UnicodeBTreePageEntry::new(unicode.2, page),
+                <UnicodeBTreePageEntry as BTreeEntryReadWrite>::ENTRY_SIZE,
+                <UnicodeBTreePageEntry as BTreeEntryReadWrite>::write,
+                <UnicodeBTreePageEntry as BTreeEntryReadWrite>::read,
+            );
+            let block = UnicodeBlockRef::new(unicode.0, UnicodeByteIndex::new(unicode.2));
+            round_trip(
+                &UnicodeBlockBTreeEntry::new(block, size).with_ref_count(ref_count),
+                UnicodeBlockBTreeEntry::ENTRY_SIZE,
+                UnicodeBlockBTreeEntry::write,
+                UnicodeBlockBTreeEntry::read,
+            );
+            round_trip(
+                &UnicodeNodeBTreeEntry::new(node.0, unicode.0, unicode.1, node.1),
+                UnicodeNodeBTreeEntry::ENTRY_SIZE,
+                UnicodeNodeBTreeEntry::write,
+                UnicodeNodeBTreeEntry::read,
+            );
+            let page = AnsiPageRef::new(AnsiPageId::from(ansi.2), Default::default());
+            round_trip(
+                &AnsiBTreePageEntry::new(ansi.2, page),
+                <AnsiBTreePageEntry as BTreeEntryReadWrite>::ENTRY_SIZE,
+                <AnsiBTreePageEntry as BTreeEntryReadWrite>::write,
+                <AnsiBTreePageEntry as BTreeEntryReadWrite>::read,
+            );
+            let block = AnsiBlockRef::new(ansi.0, AnsiByteIndex::new(ansi.2));
+            round_trip(
+                &AnsiBlockBTreeEntry::new(block, size).with_ref_count(ref_count),
+                AnsiBlockBTreeEntry::ENTRY_SIZE,
+                AnsiBlockBTreeEntry::write,
+                AnsiBlockBTreeEntry::read,
+            );
+            round_trip(
+                &AnsiNodeBTreeEntry::new(node.0, ansi.0, ansi.1, node.1),
+                AnsiNodeBTreeEntry::ENTRY_SIZE,
+                AnsiNodeBTreeEntry::write,
+                AnsiNodeBTreeEntry::read,
+            );
+        }
+
+        #[test]
+        fn test_node_btree_entry_invalid_node_id(node in (u64::from(u32::MAX) + 1)..) {
+            let data = [&node.to_le_bytes()[..], &[0; 24]].concat();
+            let err = UnicodeNodeBTreeEntry::read(&mut data.as_slice()).unwrap_err();
+            prop_assert!(matches!(
+                ndb_error(err),
+                NdbError::InvalidNodeBTreeEntryNodeId(value) if value == node
+            ));
+        }
+
+        #[test]
+        fn test_unicode_btree_page_round_trip(
+            level in 1_u8..=8,
+            entries in vec(
+                (any::<u64>(), unicode_page_ref())
+                    .prop_map(|(key, block)| UnicodeBTreePageEntry::new(key, block)),
+                0..=(UNICODE_BTREE_ENTRIES_SIZE / <UnicodeBTreePageEntry as BTreeEntryReadWrite>::ENTRY_SIZE),
+            ),
+            trailer in btree_page_type().prop_flat_map(unicode_page_trailer),
+        ) {
+            let page = UnicodeBTreeEntryPage::new(
+                level,
+                (UNICODE_BTREE_ENTRIES_SIZE / <UnicodeBTreePageEntry as BTreeEntryReadWrite>::ENTRY_SIZE) as u8,
+                <UnicodeBTreePageEntry as BTreeEntryReadWrite>::ENTRY_SIZE as u8,
+                &entries,
+                trailer,
+            )
+            .unwrap();
+            btree_page_round_trip(
+                &page,
+                <UnicodeBTreeEntryPage as UnicodeBTreePageReadWrite<_>>::write,
+                <UnicodeBTreeEntryPage as UnicodeBTreePageReadWrite<_>>::read,
+            );
+        }
+
+        #[test]
+        fn test_unicode_leaf_btree_page_round_trip(
+            blocks in vec(
+                (unicode_block_id(), any::<u64>(), any::<u16>()).prop_map(|(block, index, size)| {
+                    UnicodeBlockBTreeEntry::new(
+                        UnicodeBlockRef::new(block, UnicodeByteIndex::new(index)),
+                        size,
+                    )
+                }),
+                0..=(UNICODE_BTREE_ENTRIES_SIZE / UnicodeBlockBTreeEntry::ENTRY_SIZE),
+            ),
+            nodes in vec(
+                (node_id(), unicode_block_id(), unicode_sub_node(), parent_node_id()).prop_map(
+                    |(node, data, sub_node, parent)| {
+                        UnicodeNodeBTreeEntry::new(node, data, sub_node, parent)
+                    },
+                ),
+                0..=(UNICODE_BTREE_ENTRIES_SIZE / UnicodeNodeBTreeEntry::ENTRY_SIZE),
+            ),
+            block_trailer in unicode_page_trailer(PageType::BlockBTree),
+            node_trailer in unicode_page_trailer(PageType::NodeBTree),
+        ) {
+            let page = UnicodeBlockBTreePage::new(
+                0,
+                (UNICODE_BTREE_ENTRIES_SIZE / UnicodeBlockBTreeEntry::ENTRY_SIZE) as u8,
+                UnicodeBlockBTreeEntry::ENTRY_SIZE as u8,
+                &blocks,
+                block_trailer,
+            )
+            .unwrap();
+            btree_page_round_trip(
+                &page,
+                <UnicodeBlockBTreePage as UnicodeBTreePageReadWrite<_>>::write,
+                <UnicodeBlockBTreePage as UnicodeBTreePageReadWrite<_>>::read,
+            );
+
+            let page = UnicodeNodeBTreePage::new(
+                0,
+                (UNICODE_BTREE_ENTRIES_SIZE / UnicodeNodeBTreeEntry::ENTRY_SIZE) as u8,
+                UnicodeNodeBTreeEntry::ENTRY_SIZE as u8,
+                &nodes,
+                node_trailer,
+            )
+            .unwrap();
+            btree_page_round_trip(
+                &page,
+                <UnicodeNodeBTreePage as UnicodeBTreePageReadWrite<_>>::write,
+                <UnicodeNodeBTreePage as UnicodeBTreePageReadWrite<_>>::read,
+            );
+        }
+
+        #[test]
+        fn test_ansi_btree_page_round_trip(
+            level in 1_u8..=8,
+            entries in vec(
+                (any::<u32>(), ansi_page_ref())
+                    .prop_map(|(key, block)| AnsiBTreePageEntry::new(key, block)),
+                0..=(ANSI_BTREE_ENTRIES_SIZE / <AnsiBTreePageEntry as BTreeEntryReadWrite>::ENTRY_SIZE),
+            ),
+            blocks in vec(
+                (ansi_block_id(), any::<u32>(), any::<u16>()).prop_map(|(block, index, size)| {
+                    AnsiBlockBTreeEntry::new(AnsiBlockRef::new(block, AnsiByteIndex::new(index)), size)
+                }),
+                0..=(ANSI_BTREE_ENTRIES_SIZE / AnsiBlockBTreeEntry::ENTRY_SIZE),
+            ),
+            nodes in vec(
+                (node_id(), ansi_block_id(), ansi_sub_node(), parent_node_id()).prop_map(
+                    |(node, data, sub_node, parent)| {
+                        AnsiNodeBTreeEntry::new(node, data, sub_node, parent)
+                    },
+                ),
+                0..=(ANSI_BTREE_ENTRIES_SIZE / AnsiNodeBTreeEntry::ENTRY_SIZE),
+            ),
+            trailer in btree_page_type().prop_flat_map(ansi_page_trailer),
+            block_trailer in ansi_page_trailer(PageType::BlockBTree),
+            node_trailer in ansi_page_trailer(PageType::NodeBTree),
+        ) {
+            let page = <AnsiBTreeEntryPage as BTreePageReadWrite>::new(
+                level,
+                (ANSI_BTREE_ENTRIES_SIZE / <AnsiBTreePageEntry as BTreeEntryReadWrite>::ENTRY_SIZE) as u8,
+                <AnsiBTreePageEntry as BTreeEntryReadWrite>::ENTRY_SIZE as u8,
+                &entries,
+                trailer,
+            )
+            .unwrap();
+            btree_page_round_trip(
+                &page,
+                <AnsiBTreeEntryPage as AnsiBTreePageReadWrite<_>>::write,
+                <AnsiBTreeEntryPage as AnsiBTreePageReadWrite<_>>::read,
+            );
+
+            let page = AnsiBlockBTreePage::new(
+                0,
+                (ANSI_BTREE_ENTRIES_SIZE / AnsiBlockBTreeEntry::ENTRY_SIZE) as u8,
+                AnsiBlockBTreeEntry::ENTRY_SIZE as u8,
+                &blocks,
+                block_trailer,
+            )
+            .unwrap();
+            btree_page_round_trip(
+                &page,
+                <AnsiBlockBTreePage as AnsiBTreePageReadWrite<_>>::write,
+                <AnsiBlockBTreePage as AnsiBTreePageReadWrite<_>>::read,
+            );
+
+            let page = AnsiNodeBTreePage::new(
+                0,
+                (ANSI_BTREE_ENTRIES_SIZE / AnsiNodeBTreeEntry::ENTRY_SIZE) as u8,
+                AnsiNodeBTreeEntry::ENTRY_SIZE as u8,
+                &nodes,
+                node_trailer,
+            )
+            .unwrap();
+            btree_page_round_trip(
+                &page,
+                <AnsiNodeBTreePage as AnsiBTreePageReadWrite<_>>::write,
+                <AnsiNodeBTreePage as AnsiBTreePageReadWrite<_>>::read,
+            );
+        }
+
+        #[test]
+        fn test_btree_page_invalid_level(level in 0_u8..=255, trailer in unicode_page_trailer(PageType::BlockBTree)) {
+            let result = UnicodeBTreeEntryPage::new(level, 20, 24, &[], trailer);
+            if (1..=8).contains(&level) {
+                prop_assert!(result.is_ok());
+            } else {
+                prop_assert!(
+                    matches!(result, Err(NdbError::InvalidBTreePageLevel(value)) if value == level)
+                );
+            }
+
+            let result = UnicodeBlockBTreePage::new(level, 20, 24, &[], trailer);
+            if level == 0 {
+                let page = result.unwrap();
+                let mut data = Vec::new();
+                UnicodeBTreePageReadWrite::write(&page, &mut data).unwrap();
+
+                // cLevel is the last byte before dwPadding.
+                data[UNICODE_BTREE_ENTRIES_SIZE + 3] = 9;
+                let err = <UnicodeBlockBTreePage as UnicodeBTreePageReadWrite<_>>::read(
+                    &mut data.as_slice(),
+                )
+                .unwrap_err();
+                prop_assert!(matches!(ndb_error(err), NdbError::InvalidBTreePageLevel(9)));
+            } else {
+                prop_assert!(
+                    matches!(result, Err(NdbError::InvalidBTreePageLevel(value)) if value == level)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_ansi_data_block_round_trip() {
+        // ANSI blocks have a 12 byte trailer, so 52 bytes of data fill a 64 byte block exactly,
+        // where a Unicode block with the same data would need 128 bytes.
+        for size in [1, 52, 53, 500, MAX_BLOCK_SIZE - AnsiBlockTrailer::SIZE] {
+            let data: Vec<_> = (0..size).map(|index| index as u8).collect();
+            let block_id = AnsiBlockId::new(false, 1).unwrap();
+            let trailer = AnsiBlockTrailer::new(size, 0, 0, block_id).unwrap();
+            let block = AnsiDataBlock::new(NdbCryptMethod::Permute, data.clone(), trailer).unwrap();
+
+            let mut cursor = Cursor::new(Vec::new());
+            block.write(&mut cursor).unwrap();
+            let buffer = cursor.into_inner();
+            assert_eq!(
+                buffer.len(),
+                usize::from(block_size(size + AnsiBlockTrailer::SIZE))
+            );
+
+            let mut cursor = Cursor::new(buffer.as_slice());
+            let block = AnsiDataBlock::read(&mut cursor, size, NdbCryptMethod::Permute).unwrap();
+            assert_eq!(block.data(), data.as_slice());
+            assert_eq!(block.trailer().size(), size);
+            assert_eq!(block.trailer().block_id(), block_id);
+            assert_eq!(
+                block.trailer().crc(),
+                compute_crc(0, &buffer[..usize::from(size)])
+            );
+        }
+    }
+}
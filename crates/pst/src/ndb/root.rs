@@ -53,7 +53,7 @@ where
     fn amap_is_valid(&self) -> AmapStatus;
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct UnicodeRoot {
     reserved1: u32,
     file_eof_index: UnicodeByteIndex,
@@ -170,9 +170,34 @@ impl RootReadWrite<UnicodePstFile> for UnicodeRoot {
         self.pmap_free_size = 0.into();
         Ok(())
     }
+
+    fn set_free_sizes(
+        &mut self,
+        amap_free_bytes: UnicodeByteIndex,
+        pmap_free_bytes: UnicodeByteIndex,
+    ) {
+        self.amap_free_size = amap_free_bytes;
+        self.pmap_free_size = pmap_free_bytes;
+    }
+
+    fn set_file_eof_index(&mut self, file_eof_index: UnicodeByteIndex) {
+        self.file_eof_index = file_eof_index;
+    }
+
+    fn set_amap_last_index(&mut self, amap_last_index: UnicodeByteIndex) {
+        self.amap_last_index = amap_last_index;
+    }
+
+    fn set_node_btree(&mut self, node_btree: UnicodePageRef) {
+        self.node_btree = node_btree;
+    }
+
+    fn set_block_btree(&mut self, block_btree: UnicodePageRef) {
+        self.block_btree = block_btree;
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct AnsiRoot {
     file_eof_index: AnsiByteIndex,
     amap_last_index: AnsiByteIndex,
@@ -289,4 +314,25 @@ impl RootReadWrite<AnsiPstFile> for AnsiRoot {
         self.pmap_free_size = 0.into();
         Ok(())
     }
+
+    fn set_free_sizes(&mut self, amap_free_bytes: AnsiByteIndex, pmap_free_bytes: AnsiByteIndex) {
+        self.amap_free_size = amap_free_bytes;
+        self.pmap_free_size = pmap_free_bytes;
+    }
+
+    fn set_file_eof_index(&mut self, file_eof_index: AnsiByteIndex) {
+        self.file_eof_index = file_eof_index;
+    }
+
+    fn set_amap_last_index(&mut self, amap_last_index: AnsiByteIndex) {
+        self.amap_last_index = amap_last_index;
+    }
+
+    fn set_node_btree(&mut self, node_btree: AnsiPageRef) {
+        self.node_btree = node_btree;
+    }
+
+    fn set_block_btree(&mut self, block_btree: AnsiPageRef) {
+        self.block_btree = block_btree;
+    }
 }
@@ -0,0 +1,430 @@
+//! Rebuild the BTrees of a PST file whose BTree pages are damaged, by scanning the file for
+//! blocks and NBT leaf pages which are still intact.
+//!
+//! Every block has a [`BlockTrailer`] with its size, a signature computed from its BID and file
+//! offset, and a CRC of its data, so valid blocks can be found by checking each 64-byte boundary
+//! of the file without any help from the BBT. Nodes do not have anything like that in their
+//! blocks, so the NBT entries come from any NBT leaf pages which pass their own checks. When a
+//! page was rewritten, the old copy may still be in the file, so the page with the highest page
+//! ID wins for every NID in its key range.
+
+use std::{collections::BTreeMap, io::Cursor};
+
+use super::*;
+use crate::{block_sig::compute_sig, crc::compute_crc, upgrade::NodeWriter};
+
+/// The result of [`PstFile::rebuild_btrees_from_scan`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Blocks with a valid trailer and CRC which were added to the new BBT.
+    pub blocks_recovered: usize,
+    /// Blocks with a valid trailer and CRC which were left out, because they overlapped a newer
+    /// block or repeated the BID of another block.
+    pub blocks_skipped: usize,
+    /// Nodes from the NBT leaf pages which were added to the new NBT.
+    pub nodes_recovered: usize,
+    /// Nodes from the NBT leaf pages which were left out, because their data or sub-node block
+    /// was not recovered.
+    pub nodes_skipped: usize,
+}
+
+impl<Pst> PstFileInner<Pst>
+where
+    Pst: PstFile + PstFileLock<Pst>,
+    <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey>
+        + From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index>
+        + Debug,
+    <Pst as PstFile>::PageId: From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index> + Debug,
+    <Pst as PstFile>::ByteIndex: ByteIndex<Index: TryFrom<u64>> + Debug,
+    <Pst as PstFile>::BlockRef: Debug,
+    <Pst as PstFile>::PageRef: Debug,
+    <Pst as PstFile>::Root: RootReadWrite<Pst>,
+    <Pst as PstFile>::Header: HeaderReadWrite<Pst>,
+    <Pst as PstFile>::DensityListPage: DensityListPageReadWrite<Pst>,
+    <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+    <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+    <Pst as PstFile>::NodeBTreeEntry: NodeBTreeEntryReadWrite,
+    <Pst as PstFile>::NodeBTree: NodeBTreeReadWrite<Pst, <Pst as PstFile>::NodeBTreeEntry>,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::NodeBTreeEntry,
+            <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockBTree: BlockBTreeReadWrite<Pst, <Pst as PstFile>::BlockBTreeEntry>,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::BlockBTreeEntry,
+            <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::AllocationMapPage: AllocationMapPageReadWrite<Pst>,
+    <Pst as PstFile>::AllocationPageMapPage: AllocationPageMapPageReadWrite<Pst>,
+    <Pst as PstFile>::FreeMapPage: FreeMapPageReadWrite<Pst>,
+    <Pst as PstFile>::FreePageMapPage: FreePageMapPageReadWrite<Pst>,
+    <Pst as PstFile>::DensityListPage: DensityListPageReadWrite<Pst>,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry:
+        IntermediateTreeEntryReadWrite + From<<Pst as PstFile>::BlockId>,
+    <Pst as PstFile>::DataBlock: BlockReadWrite + Clone,
+    <Pst as PstFile>::SubNodeTreeBlockHeader: SubNodeTreeBlockHeaderReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::SubNodeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+{
+    /// See [`PstFile::rebuild_btrees_from_scan`].
+    pub(crate) fn rebuild_btrees_from_scan(&mut self) -> io::Result<RepairReport> {
+        if self.transaction.is_some() {
+            return Err(PstError::TransactionInProgress.into());
+        }
+
+        let file_eof: u64 = self.header.root().file_eof_index().index().into();
+        let mut report = RepairReport::default();
+        let mut blocks = Vec::new();
+        let mut node_pages = Vec::new();
+
+        for region in 0..amap_page_count(file_eof) {
+            let region_start = AMAP_FIRST_OFFSET + region * AMAP_DATA_SIZE;
+            let len = AMAP_DATA_SIZE.min(file_eof.saturating_sub(region_start));
+            let reserved = amap_reserved_pages(region) * PAGE_SIZE as u64;
+            if len <= reserved {
+                continue;
+            }
+
+            let data = self.reader.read_at(
+                region_start,
+                usize::try_from(len).map_err(|_| PstError::IntegerConversion)?,
+            )?;
+            let data =
+                &data[usize::try_from(reserved).map_err(|_| PstError::IntegerConversion)?..];
+            let offset = region_start + reserved;
+            blocks.extend(Self::scan_blocks(data, offset)?);
+            node_pages.extend(Self::scan_node_pages(data, offset));
+        }
+
+        // Stale copies of a block can overlap the space which was later reused for a newer one,
+        // and the newer block has the higher BID.
+        blocks.sort_by_key(|(offset, _)| *offset);
+        let mut live_blocks: Vec<(u64, <Pst as PstFile>::BlockBTreeEntry)> = Vec::new();
+        for (offset, entry) in blocks {
+            if let Some((last_offset, last)) = live_blocks.last_mut() {
+                let last_end = *last_offset
+                    + u64::from(block_size(
+                        last.size()
+                            + <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE,
+                    ));
+                if offset < last_end {
+                    report.blocks_skipped += 1;
+                    if entry.key().into() > last.key().into() {
+                        *last_offset = offset;
+                        *last = entry;
+                    }
+                    continue;
+                }
+            }
+            live_blocks.push((offset, entry));
+        }
+
+        let mut block_btree = BTreeMap::new();
+        for (_, entry) in live_blocks {
+            if block_btree.insert(entry.key().into(), entry).is_some() {
+                report.blocks_skipped += 1;
+            }
+        }
+        report.blocks_recovered = block_btree.len();
+
+        // Walk the NBT leaf pages from newest to oldest, an older page only fills in NIDs which
+        // are outside the key range of every newer page.
+        node_pages.sort_by_key(|(page_id, _)| std::cmp::Reverse(*page_id));
+        let mut covered: Vec<(u32, u32)> = Vec::new();
+        let mut node_btree = BTreeMap::new();
+        for (_, entries) in node_pages {
+            let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+                continue;
+            };
+            let range = (u32::from(first.node()), u32::from(last.node()));
+            for entry in entries {
+                let key = u32::from(entry.node());
+                if covered
+                    .iter()
+                    .any(|(first, last)| (*first..=*last).contains(&key))
+                {
+                    continue;
+                }
+                node_btree.entry(key).or_insert(entry);
+            }
+            covered.push(range);
+        }
+
+        let is_recovered = |block: <Pst as PstFile>::BlockId| {
+            let key: u64 = block.search_key().into();
+            key == 0 || block_btree.contains_key(&key)
+        };
+        let nodes: Vec<_> = node_btree
+            .into_values()
+            .filter(|entry| {
+                let recovered =
+                    is_recovered(entry.data()) && entry.sub_node().is_none_or(is_recovered);
+                if !recovered {
+                    report.nodes_skipped += 1;
+                }
+                recovered
+            })
+            .collect();
+        report.nodes_recovered = nodes.len();
+
+        let file = {
+            let mut writer = self
+                .writer
+                .as_ref()?
+                .lock()
+                .map_err(|_| PstError::LockError)?;
+            writer.flush()?;
+            writer.get_ref().try_clone()?
+        };
+        let mut writer = NodeWriter::<Pst>::append(
+            file,
+            self.header.crypt_method(),
+            file_eof,
+            self.header.next_page().into_u64(),
+        );
+        let (node_btree, block_btree, file_eof) =
+            writer.write_btrees(nodes, block_btree.into_values().collect())?;
+        let next_page =
+            <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(
+                writer.next_page(),
+            )
+            .map_err(|_| PstError::IntegerConversion)?;
+        drop(writer);
+
+        // The allocation map is rebuilt from the new BTrees, which leaves the damaged pages free.
+        let header = {
+            let old_root = self.header.root();
+            let mut root = <<Pst as PstFile>::Root as RootReadWrite<Pst>>::new(
+                Self::byte_index(file_eof)?,
+                Self::byte_index(file_eof - AMAP_DATA_SIZE)?,
+                Self::byte_index(0)?,
+                Self::byte_index(0)?,
+                node_btree,
+                block_btree,
+                AmapStatus::Invalid,
+            );
+            root.load_reserved(
+                old_root.reserved1(),
+                old_root.reserved2(),
+                old_root.reserved3(),
+            );
+            *self.header.root_mut() = root;
+            self.header
+                .set_next_page(<Pst as PstFile>::PageId::from(next_page));
+            self.header.update_unique();
+            self.header.clone()
+        };
+
+        {
+            let mut writer = self
+                .writer
+                .as_ref()?
+                .lock()
+                .map_err(|_| PstError::LockError)?;
+            let writer = &mut *writer;
+            writer.seek(SeekFrom::Start(0))?;
+            header.write(writer)?;
+            writer.flush()?;
+        }
+
+        self.node_cache
+            .lock()
+            .map_err(|_| PstError::LockError)?
+            .clear();
+        self.block_cache
+            .lock()
+            .map_err(|_| PstError::LockError)?
+            .clear();
+
+        self.rebuild_allocation_map()?;
+        Ok(report)
+    }
+
+    /// Find every block in `data`, which starts at `offset` in the file, with a valid trailer,
+    /// signature and CRC. Blocks are aligned on 64 bytes, and their trailer is at the end, so the
+    /// trailer is checked at every 64-byte boundary.
+    fn scan_blocks(
+        data: &[u8],
+        offset: u64,
+    ) -> io::Result<Vec<(u64, <Pst as PstFile>::BlockBTreeEntry)>> {
+        let trailer_size = <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE;
+        let mut blocks = Vec::new();
+
+        for end in (64..=data.len()).step_by(64) {
+            let Ok(trailer) = <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::read(
+                &mut &data[end - usize::from(trailer_size)..end],
+            ) else {
+                continue;
+            };
+
+            let size = trailer.size();
+            let start = match end.checked_sub(usize::from(block_size(size + trailer_size))) {
+                Some(start) => start,
+                None => continue,
+            };
+            let block_id = trailer.block_id();
+            if block_id.search_key().into() == 0 {
+                continue;
+            }
+
+            let block_offset = offset + start as u64;
+            let signature = compute_sig(
+                (block_offset & u64::from(u32::MAX)) as u32,
+                (block_id.into_u64() & u64::from(u32::MAX)) as u32,
+            );
+            if signature != trailer.signature()
+                || compute_crc(0, &data[start..start + usize::from(size)]) != trailer.crc()
+            {
+                continue;
+            }
+
+            let block = <<Pst as PstFile>::BlockRef as BlockRefReadWrite>::new(
+                block_id,
+                Self::byte_index(block_offset)?,
+            );
+            blocks.push((
+                block_offset,
+                <<Pst as PstFile>::BlockBTreeEntry as BlockBTreeEntryReadWrite>::new(block, size),
+            ));
+        }
+
+        Ok(blocks)
+    }
+
+    /// Find every NBT leaf page in `data`, which starts at `offset` in the file, with a valid
+    /// trailer, signature and CRC, and return the entries of each one with its page ID.
+    fn scan_node_pages(
+        data: &[u8],
+        offset: u64,
+    ) -> Vec<(u64, Vec<<Pst as PstFile>::NodeBTreeEntry>)> {
+        data.chunks_exact(PAGE_SIZE)
+            .enumerate()
+            .filter_map(|(index, page)| {
+                let page = <<<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage as RootBTreeLeafPageReadWrite<Pst>>::read(
+                    &mut Cursor::new(page),
+                )
+                .ok()?;
+                let trailer = page.trailer();
+                let page_id = trailer.block_id().into_u64();
+                let page_offset = offset + (index * PAGE_SIZE) as u64;
+                (page.level() == 0
+                    && trailer.page_type() == PageType::NodeBTree
+                    && trailer.signature() == PageType::NodeBTree.signature(page_offset, page_id))
+                .then(|| (page_id, page.entries().to_vec()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        messaging::property_ids::{PR_BODY, PR_SUBJECT},
+        test_util::{message_node, TempFile, EMPTY_PST, FIRST_MESSAGE_INDEX},
+        upgrade::{NodeReader, NodeSink, NodeSource},
+    };
+    use std::fs;
+
+    #[test]
+    fn test_rebuild_btrees_from_scan() {
+        let path = TempFile::new("repair");
+
+        let body = "Lorem ipsum dolor sit amet. ".repeat(140);
+        let message_id =
+            |index| NodeId::new(NodeIdType::NormalMessage, FIRST_MESSAGE_INDEX + index).unwrap();
+        let node_count = {
+            let empty = UnicodePstFile::open(EMPTY_PST).unwrap();
+            let mut reader = NodeReader::new(&empty).unwrap();
+            let mut writer =
+                NodeWriter::<UnicodePstFile>::create(&path, empty.header().crypt_method()).unwrap();
+            let nodes = reader.nodes();
+            for node in nodes.iter() {
+                writer
+                    .write_node(&reader.read_node(*node).unwrap())
+                    .unwrap();
+            }
+            for index in 0..100 {
+                let subject = format!("Message {index}");
+                let message = message_node(message_id(index), &subject, &body).unwrap();
+                writer.write_node(&message).unwrap();
+            }
+
+            let mut next_node_ids = reader.next_node_ids();
+            next_node_ids[NodeIdType::NormalMessage as usize] = FIRST_MESSAGE_INDEX + 100;
+            writer.finish(&next_node_ids).unwrap();
+            nodes.len() + 100
+        };
+        UnicodePstFile::open(&path)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .flush()
+            .unwrap();
+
+        // Wipe out the root pages of both BTrees, the NBT has more than one level by now so its
+        // leaf pages are still intact.
+        {
+            let pst = UnicodePstFile::open(&path).unwrap();
+            let root = pst.header().root();
+            let node_btree: u64 = root.node_btree().index().index();
+            let block_btree: u64 = root.block_btree().index().index();
+            drop(pst);
+
+            let mut data = fs::read(&path).unwrap();
+            for offset in [node_btree, block_btree] {
+                let offset = offset as usize;
+                data[offset..offset + PAGE_SIZE].fill(0);
+            }
+            fs::write(&path, data).unwrap();
+        }
+        assert!(PstFileReader::open(&path).is_err());
+
+        let mut pst = UnicodePstFile::open(&path).unwrap();
+        let report = pst.rebuild_btrees_from_scan().unwrap();
+        assert_eq!(report.nodes_recovered, node_count);
+        assert_eq!(report.nodes_skipped, 0);
+        assert_eq!(report.blocks_skipped, 0);
+        assert!(report.blocks_recovered >= node_count);
+        assert_eq!(pst.header().root().amap_is_valid(), AmapStatus::Valid2);
+        drop(pst);
+
+        let reader = PstFileReader::open(&path).unwrap();
+        for index in (0..100).step_by(7) {
+            let message = reader.message(message_id(index)).unwrap();
+            let properties = message.properties();
+            assert_eq!(
+                properties.get_string8(PR_SUBJECT).unwrap(),
+                Some(format!("Message {index}"))
+            );
+            assert_eq!(
+                properties.get_string8(PR_BODY).unwrap().as_ref(),
+                Some(&body)
+            );
+        }
+        assert!(reader.folders().unwrap().len() > 1);
+        drop(reader);
+
+        // The pages which were replaced are still in the file, but the new ones win.
+        let mut pst = UnicodePstFile::open(&path).unwrap();
+        assert_eq!(pst.rebuild_btrees_from_scan().unwrap(), report);
+        drop(pst);
+    }
+}
@@ -0,0 +1,131 @@
+//! Scaffolding shared by the unit tests in every module.
+
+use std::{
+    fs, io,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ltp::{heap::*, prop_context::*, prop_type::PropertyType, read_write::*, tree::*},
+    messaging::property_ids::{PR_BODY, PR_MESSAGE_CLASS, PR_MESSAGE_FLAGS, PR_SUBJECT},
+    ndb::{block::UnicodeDataTree, node_id::*},
+    upgrade::{split_data, NodeContents},
+};
+
+/// The sample file from Outlook, with nothing but the mandatory folders in it.
+pub(crate) const EMPTY_PST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/Empty.pst");
+
+/// A file or directory in the temp directory, which is deleted when this is dropped, even if the
+/// test fails. The name includes the process ID, so concurrent test runs do not collide.
+pub(crate) struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// A PST file named after `name`, which does not exist yet.
+    pub(crate) fn new(name: &str) -> Self {
+        Self {
+            path: std::env::temp_dir().join(format!("{name}_{}.pst", std::process::id())),
+        }
+    }
+
+    /// A copy of the PST file at `source`, usually [`EMPTY_PST`].
+    pub(crate) fn copy_of(source: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        let file = Self::new(name);
+        fs::copy(source, &file.path)?;
+        Ok(file)
+    }
+
+    /// A directory named after `name`, which does not exist yet.
+    pub(crate) fn dir(name: &str) -> Self {
+        Self {
+            path: std::env::temp_dir().join(format!("{name}_{}", std::process::id())),
+        }
+    }
+}
+
+impl Deref for TempFile {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempFile {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = if self.path.is_dir() {
+            fs::remove_dir_all(&self.path)
+        } else {
+            fs::remove_file(&self.path)
+        };
+    }
+}
+
+pub(crate) const FIRST_MESSAGE_INDEX: u32 = 0x10000;
+
+/// Build a message with `PidTagMessageClass` and `PidTagSubject` in the PC, and a
+/// `PidTagBody` which is too big for the heap in an LTP sub-node.
+pub(crate) fn message_node(node: NodeId, subject: &str, body: &str) -> io::Result<NodeContents> {
+    let max_data_size = UnicodeDataTree::max_data_size();
+    let unicode =
+        |value: &str| -> Vec<u8> { value.encode_utf16().flat_map(u16::to_le_bytes).collect() };
+
+    let mut heap = HeapNodeEditor::new(HeapNodeType::Properties, max_data_size);
+    let body_node = NodeId::new(NodeIdType::ListsTablesProperties, 1)?;
+    let properties = [
+        (
+            PR_MESSAGE_CLASS,
+            PropertyType::Unicode,
+            PropertyValueRecord::Heap(heap.allocate(unicode("IPM.Note"))?),
+        ),
+        (
+            PR_SUBJECT,
+            PropertyType::Unicode,
+            PropertyValueRecord::Heap(heap.allocate(unicode(subject))?),
+        ),
+        (
+            PR_MESSAGE_FLAGS,
+            PropertyType::Integer32,
+            PropertyValueRecord::Small(0),
+        ),
+        (
+            PR_BODY,
+            PropertyType::Unicode,
+            PropertyValueRecord::Node(body_node),
+        ),
+    ];
+    let mut records = Vec::with_capacity(properties.len());
+    for (prop_id, prop_type, value) in properties {
+        let mut record = Vec::with_capacity(8);
+        prop_id.write(&mut record)?;
+        PropertyTreeRecordValue::new(prop_type, value).write(&mut record)?;
+        records.push(record);
+    }
+    let user_root = allocate_heap_tree(
+        &mut heap,
+        <PropertyTreeRecordKey as HeapTreeEntryKey>::SIZE,
+        <PropertyTreeRecordValue as HeapTreeEntryValue>::SIZE,
+        &records,
+    )?;
+    heap.set_user_root(user_root);
+
+    Ok(NodeContents {
+        node,
+        parent: None,
+        data: heap.write()?,
+        sub_nodes: vec![NodeContents {
+            node: body_node,
+            parent: None,
+            data: split_data(&unicode(body), max_data_size),
+            sub_nodes: Default::default(),
+        }],
+    })
+}
@@ -0,0 +1,2033 @@
+//! Copy every node in a PST file into a new PST file, either to compact it or to upgrade an ANSI
+//! file to Unicode.
+//!
+//! The NDB layer is rebuilt from scratch in the destination file: every block gets a new BID,
+//! the BTrees are written bottom-up, and the allocation map is rebuilt by the first write
+//! transaction on the new file. When upgrading, property and table contexts are rebuilt with
+//! their `PtypString8` values re-encoded as `PtypString`.
+
+use std::io::Cursor;
+
+use super::*;
+use crate::{
+    block_sig::compute_sig,
+    crc::{compute_crc, CrcWriter},
+    ltp::{
+        prop_type::*,
+        read_write::{HeapNodePageReadWrite, PropertyValueReadWrite, TableContextInfoReadWrite},
+        LtpError, LtpResult,
+    },
+    messaging::property_ids::{PR_INTERNET_CPID, PR_MESSAGE_CODEPAGE},
+};
+
+/// Copy an ANSI PST file to a new Unicode PST file at `dest`.
+///
+/// `PtypString8` and `PtypMultipleString8` properties and table columns are decoded with the
+/// code page of the message store, or of the message or attachment which overrides it with
+/// `PidTagMessageCodepage` or `PidTagInternetCodepage`, and stored as `PtypString` and
+/// `PtypMultipleString`. Everything else is copied as-is, keeping the same [`NodeId`] values.
+pub fn upgrade_to_unicode(
+    ansi: &AnsiPstFile,
+    dest: impl AsRef<Path>,
+) -> io::Result<UnicodePstFile> {
+    let dest = dest.as_ref();
+    {
+        let mut reader = NodeReader::new(ansi)?;
+        let mut writer = NodeWriter::<UnicodePstFile>::create(dest, ansi.header().crypt_method())?;
+        copy_nodes(&mut reader, &mut writer, StringConversion::ToUnicode)?;
+    }
+
+    let mut pst = UnicodePstFile::open(dest)?;
+    pst.lock()?.flush()?;
+    Ok(pst)
+}
+
+/// The result of [`PstFile::compact`] or [`PstFile::compact_with_encoding`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    /// The number of nodes copied to the new file.
+    pub nodes: usize,
+    /// `ibFileEof` in the source file.
+    pub source_size: u64,
+    /// `ibFileEof` in the new file.
+    pub dest_size: u64,
+}
+
+impl CompactionStats {
+    /// How many bytes smaller the new file is than the source file.
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.source_size.saturating_sub(self.dest_size)
+    }
+}
+
+/// Copy every node in `pst` to a new file at `dest`, with its blocks encoded using `encoding`.
+/// Blocks which are not referenced by any node, and pages which are not part of the BTrees, are
+/// left behind, so the new file only grows as much as the live data needs. Returns the number of
+/// nodes which were copied.
+pub(crate) fn compact<Pst>(pst: &Pst, dest: &Path, encoding: NdbCryptMethod) -> io::Result<usize>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey>
+        + From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index>
+        + Debug,
+    <Pst as PstFile>::PageId: From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index> + Debug,
+    <Pst as PstFile>::ByteIndex: ByteIndex<Index: TryFrom<u64>> + Debug,
+    <Pst as PstFile>::BlockRef: Debug,
+    <Pst as PstFile>::PageRef: Debug,
+    <Pst as PstFile>::Root: RootReadWrite<Pst>,
+    <Pst as PstFile>::Header: HeaderReadWrite<Pst>,
+    <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+    <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+    <Pst as PstFile>::NodeBTreeEntry: NodeBTreeEntryReadWrite,
+    <Pst as PstFile>::NodeBTree: NodeBTreeReadWrite<Pst, <Pst as PstFile>::NodeBTreeEntry>,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::NodeBTreeEntry,
+            <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockBTree: BlockBTreeReadWrite<Pst, <Pst as PstFile>::BlockBTreeEntry>,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::BlockBTreeEntry,
+            <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry:
+        IntermediateTreeEntryReadWrite + From<<Pst as PstFile>::BlockId>,
+    <Pst as PstFile>::DataBlock: BlockReadWrite + Clone,
+    <Pst as PstFile>::SubNodeTreeBlockHeader: SubNodeTreeBlockHeaderReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::SubNodeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+{
+    let mut reader = NodeReader::new(pst)?;
+    let mut writer = NodeWriter::<Pst>::create(dest, encoding)?;
+    let nodes = reader.nodes();
+    for node in nodes.iter() {
+        let node = reader.read_node(*node)?;
+        writer.write_node(&node)?;
+    }
+    writer.finish(&reader.next_node_ids())?;
+    Ok(nodes.len())
+}
+
+/// Convert and copy every node from `source` to `dest`, then write the BTrees and header.
+fn copy_nodes(
+    source: &mut dyn NodeSource,
+    dest: &mut dyn NodeSink,
+    strings: StringConversion,
+) -> io::Result<()> {
+    let mut next_node_ids = source.next_node_ids();
+    let ltp_index = usize::from(NodeIdType::ListsTablesProperties as u8);
+    let mut converter = NodeConverter {
+        strings,
+        codepage: DEFAULT_CODEPAGE,
+        max_data_size: dest.max_data_size(),
+        row_index_size: dest.row_index_size(),
+        next_ltp_index: next_node_ids[ltp_index],
+    };
+
+    let nodes = source.nodes();
+    if nodes.contains(&NID_MESSAGE_STORE) {
+        let store = source.read_node(NID_MESSAGE_STORE)?;
+        if let Some(codepage) = converter.node_codepage(&store)? {
+            converter.codepage = codepage;
+        }
+    }
+
+    for node in nodes {
+        let mut node = source.read_node(node)?;
+        let codepage = converter.codepage;
+        converter.convert(&mut node, codepage)?;
+        dest.write_node(&node)?;
+    }
+
+    next_node_ids[ltp_index] = converter.next_ltp_index;
+    dest.finish(&next_node_ids)
+}
+
+/// The data blocks and sub-nodes of a single node, without any of the BIDs from the file it was
+/// read from.
+#[derive(Clone)]
+pub(crate) struct NodeContents {
+    pub node: NodeId,
+    pub parent: Option<NodeId>,
+    pub data: Vec<Vec<u8>>,
+    pub sub_nodes: Vec<NodeContents>,
+}
+
+pub(crate) trait NodeSource {
+    /// The `rgnid` array from the header.
+    fn next_node_ids(&self) -> Vec<u32>;
+
+    /// Every [`NodeId`] in the NBT, in key order.
+    fn nodes(&self) -> Vec<NodeId>;
+
+    fn read_node(&mut self, node: NodeId) -> io::Result<NodeContents>;
+}
+
+pub(crate) trait NodeSink {
+    /// The largest data block which can be written.
+    fn max_data_size(&self) -> usize;
+
+    /// The size of a `dwRowIndex` value in the TC row index.
+    fn row_index_size(&self) -> u8;
+
+    fn write_node(&mut self, node: &NodeContents) -> io::Result<()>;
+
+    /// Write the BTrees and the header, with `next_node_ids` as the `rgnid` array.
+    fn finish(&mut self, next_node_ids: &[u32]) -> io::Result<()>;
+}
+
+pub(crate) struct NodeReader<'a, Pst>
+where
+    Pst: PstFile,
+{
+    header: &'a <Pst as PstFile>::Header,
+    reader: PstReaderGuard<'a>,
+    block_btree: PstFileReadWriteBlockBTree<Pst>,
+    page_cache: RootBTreePageCache<<Pst as PstFile>::BlockBTree>,
+    nodes: Vec<<Pst as PstFile>::NodeBTreeEntry>,
+}
+
+impl<'a, Pst> NodeReader<'a, Pst>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey>
+        + From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index>
+        + Debug,
+    <Pst as PstFile>::PageId: From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index> + Debug,
+    <Pst as PstFile>::ByteIndex: ByteIndex<Index: TryFrom<u64>> + Debug,
+    <Pst as PstFile>::BlockRef: Debug,
+    <Pst as PstFile>::PageRef: Debug,
+    <Pst as PstFile>::Root: RootReadWrite<Pst>,
+    <Pst as PstFile>::Header: HeaderReadWrite<Pst>,
+    <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+    <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+    <Pst as PstFile>::NodeBTreeEntry: NodeBTreeEntryReadWrite,
+    <Pst as PstFile>::NodeBTree: NodeBTreeReadWrite<Pst, <Pst as PstFile>::NodeBTreeEntry>,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::NodeBTreeEntry,
+            <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockBTree: BlockBTreeReadWrite<Pst, <Pst as PstFile>::BlockBTreeEntry>,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::BlockBTreeEntry,
+            <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry:
+        IntermediateTreeEntryReadWrite + From<<Pst as PstFile>::BlockId>,
+    <Pst as PstFile>::DataBlock: BlockReadWrite + Clone,
+    <Pst as PstFile>::SubNodeTreeBlockHeader: SubNodeTreeBlockHeaderReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::SubNodeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+{
+    pub fn new(pst: &'a Pst) -> io::Result<Self> {
+        let header = pst.header();
+        let root = header.root();
+        let mut reader = pst.reader().lock().map_err(|_| PstError::LockError)?;
+
+        let node_btree =
+            <Pst::NodeBTree as RootBTreeReadWrite>::read(&mut *reader, *root.node_btree())?;
+        let block_btree =
+            <Pst::BlockBTree as RootBTreeReadWrite>::read(&mut *reader, *root.block_btree())?;
+
+        let mut nodes = Vec::new();
+        Self::node_entries(&mut *reader, &node_btree, &mut nodes)?;
+
+        Ok(Self {
+            header,
+            reader,
+            block_btree,
+            page_cache: Default::default(),
+            nodes,
+        })
+    }
+
+    /// Recursively collect the leaf entries in the NBT.
+    fn node_entries<R: PstReader>(
+        reader: &mut R,
+        node_btree: &PstFileReadWriteNodeBTree<Pst>,
+        nodes: &mut Vec<<Pst as PstFile>::NodeBTreeEntry>,
+    ) -> io::Result<()> {
+        match node_btree {
+            RootBTreePage::Intermediate(page, ..) => {
+                let level = page.level();
+                for entry in page.entries() {
+                    let block = entry.block();
+                    let node_btree = <Pst::NodeBTree as RootBTreeReadWrite>::read(reader, block)?;
+                    let child_level = match &node_btree {
+                        RootBTreePage::Intermediate(page, ..) => page.level(),
+                        RootBTreePage::Leaf(_) => 0,
+                    };
+                    if child_level + 1 != level {
+                        return Err(PstError::InvalidBTreePage(block.index().index().into()).into());
+                    }
+                    Self::node_entries(reader, &node_btree, nodes)?;
+                }
+            }
+            RootBTreePage::Leaf(page) => nodes.extend_from_slice(page.entries()),
+        }
+
+        Ok(())
+    }
+
+    /// Read the contents of each block in a data tree. Nodes which do not have any data use a
+    /// BID of 0, and they do not have any blocks.
+    fn read_data(&mut self, block: <Pst as PstFile>::BlockId) -> io::Result<Vec<Vec<u8>>> {
+        if block.search_key().into() == 0 {
+            return Ok(Default::default());
+        }
+
+        let encoding = self.header.crypt_method();
+        let reader = &mut *self.reader;
+        let data_tree = DataTree::<Pst>::read_block(
+            reader,
+            encoding,
+            &self.block_btree,
+            &mut self.page_cache,
+            block,
+        )?;
+        let mut block_cache = Default::default();
+        let blocks = data_tree
+            .blocks(
+                reader,
+                encoding,
+                &self.block_btree,
+                &mut self.page_cache,
+                &mut block_cache,
+            )?
+            .map(|block| block.data().to_vec())
+            .collect();
+        Ok(blocks)
+    }
+
+    fn find_entry(&self, node: NodeId) -> io::Result<<Pst as PstFile>::NodeBTreeEntry> {
+        Ok(self
+            .nodes
+            .binary_search_by_key(&u32::from(node), |entry| u32::from(entry.node()))
+            .map(|index| self.nodes[index])
+            .map_err(|_| NdbError::InvalidNodeBTreeEntryNodeId(u64::from(u32::from(node))))?)
+    }
+
+    /// Compute a CRC of the parent, data and sub-nodes of `node`. The data is streamed through
+    /// [`DataTree::reader`], so a large node is never held in memory all at once.
+    pub fn hash_node(&mut self, node: NodeId) -> io::Result<u32> {
+        let entry = self.find_entry(node)?;
+        let parent = entry.parent().map(u32::from).unwrap_or_default();
+        let crc = compute_crc(0, &parent.to_le_bytes());
+        let crc = self.hash_data(crc, entry.data())?;
+        match entry.sub_node() {
+            Some(sub_node) => self.hash_sub_nodes(crc, sub_node),
+            None => Ok(crc),
+        }
+    }
+
+    fn hash_data(&mut self, crc: u32, block: <Pst as PstFile>::BlockId) -> io::Result<u32> {
+        if block.search_key().into() == 0 {
+            return Ok(crc);
+        }
+
+        let encoding = self.header.crypt_method();
+        let reader = &mut *self.reader;
+        let data_tree = DataTree::<Pst>::read_block(
+            reader,
+            encoding,
+            &self.block_btree,
+            &mut self.page_cache,
+            block,
+        )?;
+        let mut block_cache = Default::default();
+        let mut data = data_tree.reader(
+            reader,
+            encoding,
+            &self.block_btree,
+            &mut self.page_cache,
+            &mut block_cache,
+        )?;
+        let mut crc = CrcWriter(crc);
+        io::copy(&mut data, &mut crc)?;
+        Ok(crc.0)
+    }
+
+    fn hash_sub_nodes(&mut self, crc: u32, block: <Pst as PstFile>::BlockId) -> io::Result<u32> {
+        let reader = &mut *self.reader;
+        let block =
+            self.block_btree
+                .find_entry(reader, block.search_key(), &mut self.page_cache)?;
+        let sub_nodes: Vec<_> = SubNodeTree::<Pst>::read(reader, &block)?
+            .entries(reader, &self.block_btree, &mut self.page_cache)?
+            .collect();
+
+        let mut crc = crc;
+        for entry in sub_nodes {
+            crc = compute_crc(crc, &u32::from(entry.node()).to_le_bytes());
+            crc = self.hash_data(crc, entry.block())?;
+            if let Some(sub_node) = entry.sub_node() {
+                crc = self.hash_sub_nodes(crc, sub_node)?;
+            }
+        }
+        Ok(crc)
+    }
+
+    /// Check that every block referenced by every node has an entry in the BBT, following the
+    /// XBLOCKs and XXBLOCKs in each data tree and the SIBLOCKs and SLBLOCKs in each sub-node
+    /// tree.
+    pub fn validate(&mut self) -> io::Result<ValidationReport> {
+        let mut report = ValidationReport::default();
+        for entry in self.nodes.clone() {
+            let node = entry.node();
+            self.validate_data(node, entry.data(), &mut report)?;
+            if let Some(sub_node) = entry.sub_node() {
+                self.validate_sub_nodes(node, sub_node, &mut report)?;
+            }
+            report.nodes_checked += 1;
+        }
+        Ok(report)
+    }
+
+    /// Look up `block` in the BBT, and add an [`NdbError::MissingBlockReference`] to `report` if
+    /// it is not there.
+    fn validate_block(
+        &mut self,
+        node: NodeId,
+        block: <Pst as PstFile>::BlockId,
+        report: &mut ValidationReport,
+    ) -> io::Result<Option<<Pst as PstFile>::BlockBTreeEntry>> {
+        let reader = &mut *self.reader;
+        match self
+            .block_btree
+            .find_entry(reader, block.search_key(), &mut self.page_cache)
+        {
+            Ok(entry) => {
+                report.blocks_checked += 1;
+                Ok(Some(entry))
+            }
+            Err(err)
+                if matches!(
+                    err.get_ref()
+                        .and_then(|err| err.downcast_ref::<NdbError>())
+                        .map(NdbError::without_offset),
+                    Some(NdbError::BTreePageNotFound(_))
+                ) =>
+            {
+                let block = block.search_key().into();
+                report
+                    .errors
+                    .push((node, NdbError::MissingBlockReference(block)));
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn validate_data(
+        &mut self,
+        node: NodeId,
+        block: <Pst as PstFile>::BlockId,
+        report: &mut ValidationReport,
+    ) -> io::Result<()> {
+        if block.search_key().into() == 0 {
+            return Ok(());
+        }
+        let Some(entry) = self.validate_block(node, block, report)? else {
+            return Ok(());
+        };
+        if !block.is_internal() {
+            return Ok(());
+        }
+
+        let encoding = self.header.crypt_method();
+        let children: Vec<_> = match DataTree::<Pst>::read(&mut *self.reader, encoding, &entry)? {
+            DataTree::Intermediate(block, ..) => {
+                block.entries().iter().map(|entry| entry.block()).collect()
+            }
+            DataTree::Leaf(_) => Default::default(),
+        };
+        for child in children {
+            self.validate_data(node, child, report)?;
+        }
+        Ok(())
+    }
+
+    fn validate_sub_nodes(
+        &mut self,
+        node: NodeId,
+        block: <Pst as PstFile>::BlockId,
+        report: &mut ValidationReport,
+    ) -> io::Result<()> {
+        let Some(entry) = self.validate_block(node, block, report)? else {
+            return Ok(());
+        };
+
+        match SubNodeTree::<Pst>::read(&mut *self.reader, &entry)? {
+            SubNodeTree::Intermediate(block) => {
+                let children: Vec<_> = block.entries().iter().map(|entry| entry.block()).collect();
+                for child in children {
+                    self.validate_sub_nodes(node, child, report)?;
+                }
+            }
+            SubNodeTree::Leaf(block) => {
+                for entry in block.entries().to_vec() {
+                    self.validate_data(node, entry.block(), report)?;
+                    if let Some(sub_node) = entry.sub_node() {
+                        self.validate_sub_nodes(node, sub_node, report)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_sub_nodes(
+        &mut self,
+        block: <Pst as PstFile>::BlockId,
+    ) -> io::Result<Vec<NodeContents>> {
+        let reader = &mut *self.reader;
+        let block =
+            self.block_btree
+                .find_entry(reader, block.search_key(), &mut self.page_cache)?;
+        let sub_nodes: Vec<_> = SubNodeTree::<Pst>::read(reader, &block)?
+            .entries(reader, &self.block_btree, &mut self.page_cache)?
+            .collect();
+
+        sub_nodes
+            .into_iter()
+            .map(|entry| {
+                Ok(NodeContents {
+                    node: entry.node(),
+                    parent: None,
+                    data: self.read_data(entry.block())?,
+                    sub_nodes: match entry.sub_node() {
+                        Some(sub_node) => self.read_sub_nodes(sub_node)?,
+                        None => Default::default(),
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+impl<Pst> NodeSource for NodeReader<'_, Pst>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey>
+        + From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index>
+        + Debug,
+    <Pst as PstFile>::PageId: From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index> + Debug,
+    <Pst as PstFile>::ByteIndex: ByteIndex<Index: TryFrom<u64>> + Debug,
+    <Pst as PstFile>::BlockRef: Debug,
+    <Pst as PstFile>::PageRef: Debug,
+    <Pst as PstFile>::Root: RootReadWrite<Pst>,
+    <Pst as PstFile>::Header: HeaderReadWrite<Pst>,
+    <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+    <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+    <Pst as PstFile>::NodeBTreeEntry: NodeBTreeEntryReadWrite,
+    <Pst as PstFile>::NodeBTree: NodeBTreeReadWrite<Pst, <Pst as PstFile>::NodeBTreeEntry>,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::NodeBTreeEntry,
+            <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockBTree: BlockBTreeReadWrite<Pst, <Pst as PstFile>::BlockBTreeEntry>,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::BlockBTreeEntry,
+            <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry:
+        IntermediateTreeEntryReadWrite + From<<Pst as PstFile>::BlockId>,
+    <Pst as PstFile>::DataBlock: BlockReadWrite + Clone,
+    <Pst as PstFile>::SubNodeTreeBlockHeader: SubNodeTreeBlockHeaderReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::SubNodeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+{
+    fn next_node_ids(&self) -> Vec<u32> {
+        let mut header = self.header.clone();
+        header.next_node_ids().to_vec()
+    }
+
+    fn nodes(&self) -> Vec<NodeId> {
+        self.nodes.iter().map(|entry| entry.node()).collect()
+    }
+
+    fn read_node(&mut self, node: NodeId) -> io::Result<NodeContents> {
+        let entry = self.find_entry(node)?;
+
+        Ok(NodeContents {
+            node,
+            parent: entry.parent(),
+            data: self.read_data(entry.data())?,
+            sub_nodes: match entry.sub_node() {
+                Some(sub_node) => self.read_sub_nodes(sub_node)?,
+                None => Default::default(),
+            },
+        })
+    }
+}
+
+/// Which direction to re-encode string properties while copying.
+#[derive(Clone, Copy)]
+enum StringConversion {
+    /// Re-encode `PtypString8` values as `PtypString`.
+    ToUnicode,
+    /// Re-encode `PtypString` values as `PtypString8`, which is only used to build ANSI files for
+    /// the tests.
+    #[cfg(test)]
+    ToString8,
+}
+
+impl StringConversion {
+    /// Get the [`PropertyType`] which should replace `prop_type`, if it needs to be converted.
+    fn convert_type(self, prop_type: PropertyType) -> Option<PropertyType> {
+        match (self, prop_type) {
+            (Self::ToUnicode, PropertyType::String8) => Some(PropertyType::Unicode),
+            (Self::ToUnicode, PropertyType::MultipleString8) => Some(PropertyType::MultipleUnicode),
+            #[cfg(test)]
+            (Self::ToString8, PropertyType::Unicode) => Some(PropertyType::String8),
+            #[cfg(test)]
+            (Self::ToString8, PropertyType::MultipleUnicode) => Some(PropertyType::MultipleString8),
+            _ => None,
+        }
+    }
+
+    fn convert_value(
+        self,
+        prop_type: PropertyType,
+        data: &[u8],
+        codepage: u32,
+    ) -> io::Result<Vec<u8>> {
+        let to_unicode = |value: &String8Value| -> LtpResult<UnicodeValue> {
+            let value = decode_string8_or_latin1(value.buffer(), codepage);
+            Ok(UnicodeValue::new(value.encode_utf16().collect()))
+        };
+        #[cfg(test)]
+        let to_string8 = |value: &UnicodeValue| -> LtpResult<String8Value> {
+            let value = value.to_string();
+            Ok(String8Value::new(
+                encode_string8(&value, codepage)
+                    .unwrap_or_else(|_| encode_latin1(&value, '\u{FF}')),
+            ))
+        };
+
+        let value = match PropertyValue::read(&mut Cursor::new(data), prop_type)? {
+            PropertyValue::String8(value) => PropertyValue::Unicode(to_unicode(&value)?),
+            PropertyValue::MultipleString8(values) => PropertyValue::MultipleUnicode(
+                values.iter().map(to_unicode).collect::<LtpResult<_>>()?,
+            ),
+            #[cfg(test)]
+            PropertyValue::Unicode(value) => PropertyValue::String8(to_string8(&value)?),
+            #[cfg(test)]
+            PropertyValue::MultipleUnicode(values) => PropertyValue::MultipleString8(
+                values.iter().map(to_string8).collect::<LtpResult<_>>()?,
+            ),
+            value => value,
+        };
+
+        let mut data = Vec::new();
+        value.write(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Rebuilds the heap of every PC and TC for the destination file format, re-encoding string
+/// values along the way.
+struct NodeConverter {
+    strings: StringConversion,
+    codepage: u32,
+    max_data_size: usize,
+    row_index_size: u8,
+    next_ltp_index: u32,
+}
+
+impl NodeConverter {
+    /// Convert `node` and all of its sub-nodes. String values in `node` are decoded with
+    /// `codepage` unless it is a PC which sets its own code page.
+    fn convert(&mut self, node: &mut NodeContents, codepage: u32) -> io::Result<()> {
+        let codepage = match Self::client_signature(node) {
+            Some(HeapNodeType::Properties) => {
+                let codepage = self.node_codepage(node)?.unwrap_or(codepage);
+                self.convert_property_context(node, codepage)?;
+                codepage
+            }
+            Some(HeapNodeType::Table) => {
+                self.convert_table_context(node, codepage)?;
+                codepage
+            }
+            Some(_) => codepage,
+            None => {
+                if node
+                    .data
+                    .iter()
+                    .any(|block| block.len() > self.max_data_size)
+                {
+                    node.data = split_data(&node.data.concat(), self.max_data_size);
+                }
+                codepage
+            }
+        };
+
+        for sub_node in node.sub_nodes.iter_mut() {
+            self.convert(sub_node, codepage)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the [`HeapNodeType`] of a node which holds an HN. Sub-nodes with
+    /// [`NodeIdType::ListsTablesProperties`] hold raw values, even if they happen to start with
+    /// the HN signature.
+    fn client_signature(node: &NodeContents) -> Option<HeapNodeType> {
+        if matches!(node.node.id_type(), Ok(NodeIdType::ListsTablesProperties)) {
+            return None;
+        }
+
+        let mut cursor = node.data.first()?.as_slice();
+        let header = HeapNodeHeader::read(&mut cursor).ok()?;
+        Some(header.client_signature())
+    }
+
+    /// Get `PidTagMessageCodepage` or `PidTagInternetCodepage` from a PC.
+    fn node_codepage(&self, node: &NodeContents) -> io::Result<Option<u32>> {
+        if Self::client_signature(node) != Some(HeapNodeType::Properties) {
+            return Ok(None);
+        }
+
+        let heap = HeapNodeEditor::read(&node.data, self.max_data_size)?;
+        let properties = Self::property_records(&heap)?;
+        Ok([PR_MESSAGE_CODEPAGE, PR_INTERNET_CPID]
+            .into_iter()
+            .find_map(|prop_id| {
+                properties
+                    .iter()
+                    .find(|(key, value)| {
+                        *key == prop_id && value.prop_type() == PropertyType::Integer32
+                    })
+                    .map(|(_, value)| u32::from(value.value()))
+            }))
+    }
+
+    fn property_records(
+        heap: &HeapNodeEditor,
+    ) -> io::Result<Vec<(PropertyTreeRecordKey, PropertyTreeRecordValue)>> {
+        let (_, records) = heap_tree_records(heap, heap.user_root())?;
+        records
+            .into_iter()
+            .map(|record| {
+                let mut cursor = record.as_slice();
+                let key = PropertyTreeRecordKey::read(&mut cursor)?;
+                let value = PropertyTreeRecordValue::read(&mut cursor)?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+
+    fn convert_property_context(
+        &mut self,
+        node: &mut NodeContents,
+        codepage: u32,
+    ) -> io::Result<()> {
+        let heap = HeapNodeEditor::read(&node.data, self.max_data_size)?;
+        let properties = Self::property_records(&heap)?;
+
+        let mut new_heap = HeapNodeEditor::new(HeapNodeType::Properties, self.max_data_size);
+        let mut records = Vec::with_capacity(properties.len());
+        for (prop_id, value) in properties {
+            let prop_type = value.prop_type();
+            let value = self.copy_value(
+                &heap,
+                &mut new_heap,
+                node,
+                prop_type,
+                value.value(),
+                codepage,
+            )?;
+            let prop_type = self.strings.convert_type(prop_type).unwrap_or(prop_type);
+
+            let mut record = Vec::with_capacity(8);
+            prop_id.write(&mut record)?;
+            PropertyTreeRecordValue::new(prop_type, value).write(&mut record)?;
+            records.push(record);
+        }
+
+        let user_root = allocate_heap_tree(
+            &mut new_heap,
+            <PropertyTreeRecordKey as HeapTreeEntryKey>::SIZE,
+            <PropertyTreeRecordValue as HeapTreeEntryValue>::SIZE,
+            &records,
+        )?;
+        new_heap.set_user_root(user_root);
+        node.data = new_heap.write()?;
+        Ok(())
+    }
+
+    fn convert_table_context(&mut self, node: &mut NodeContents, codepage: u32) -> io::Result<()> {
+        let heap = HeapNodeEditor::read(&node.data, self.max_data_size)?;
+        let info = TableContextInfo::read(&mut heap.get(heap.user_root())?)?;
+        let (_, row_index) = heap_tree_records(&heap, info.row_index())?;
+        let row_size = usize::from(info.end_existence_bitmap());
+
+        let mut rows = match info.rows() {
+            None => Default::default(),
+            Some(rows) if matches!(rows.id_type(), Ok(NodeIdType::HeapNode)) => {
+                heap.get(HeapId::from(u32::from(rows)))?.to_vec()
+            }
+            Some(rows) => {
+                let sub_node = node
+                    .sub_nodes
+                    .iter()
+                    .find(|sub_node| sub_node.node == rows)
+                    .ok_or(LtpError::PropertySubNodeValueNotFound(u32::from(rows)))?;
+                // Rows never span blocks, so skip any padding at the end of each block.
+                sub_node
+                    .data
+                    .iter()
+                    .flat_map(|block| &block[..block.len() - block.len() % row_size.max(1)])
+                    .copied()
+                    .collect()
+            }
+        };
+
+        let mut new_heap = HeapNodeEditor::new(HeapNodeType::Table, self.max_data_size);
+        let existence_bitmap = usize::from(info.end_1byte_values())..row_size;
+        for row in rows.chunks_exact_mut(row_size.max(1)) {
+            for column in info.columns() {
+                let prop_type = column.prop_type();
+                if !is_hnid_column(prop_type)
+                    || !check_existence_bitmap(
+                        usize::from(column.existence_bitmap_index()),
+                        &row[existence_bitmap.clone()],
+                    )?
+                {
+                    continue;
+                }
+
+                let offset = usize::from(column.offset());
+                let cell = row
+                    .get(offset..offset + 4)
+                    .ok_or(LtpError::InvalidTableColumnOffset(column.offset()))?;
+                let cell = u32::from_le_bytes([cell[0], cell[1], cell[2], cell[3]]);
+                let value = if matches!(NodeId::from(cell).id_type(), Ok(NodeIdType::HeapNode)) {
+                    PropertyValueRecord::Heap(HeapId::from(cell))
+                } else {
+                    PropertyValueRecord::Node(NodeId::from(cell))
+                };
+
+                let value =
+                    self.copy_value(&heap, &mut new_heap, node, prop_type, value, codepage)?;
+                row[offset..offset + 4].copy_from_slice(&u32::from(value).to_le_bytes());
+            }
+        }
+
+        // The row index is the only structure in a TC which changes size between formats.
+        let row_index_size = usize::from(self.row_index_size);
+        let key_size = usize::from(<TableRowId as HeapTreeEntryKey>::SIZE);
+        let row_index: Vec<_> = row_index
+            .into_iter()
+            .map(|record| {
+                let mut value = [0; 4];
+                let index = &record[key_size..];
+                value[..index.len()].copy_from_slice(index);
+                let mut record = record[..key_size].to_vec();
+                record.extend_from_slice(&value[..row_index_size]);
+                record
+            })
+            .collect();
+        let row_index = allocate_heap_tree(
+            &mut new_heap,
+            <TableRowId as HeapTreeEntryKey>::SIZE,
+            self.row_index_size,
+            &row_index,
+        )?;
+
+        let rows = match info.rows() {
+            None => None,
+            Some(rows_id) if matches!(rows_id.id_type(), Ok(NodeIdType::HeapNode)) => {
+                Some(NodeId::from(u32::from(new_heap.allocate(rows)?)))
+            }
+            Some(rows_id) => {
+                let rows_per_block = (self.max_data_size / row_size.max(1)).max(1);
+                let data = split_data(&rows, rows_per_block * row_size.max(1));
+                if let Some(sub_node) = node
+                    .sub_nodes
+                    .iter_mut()
+                    .find(|sub_node| sub_node.node == rows_id)
+                {
+                    sub_node.data = data;
+                }
+                Some(rows_id)
+            }
+        };
+
+        let columns = info
+            .columns()
+            .iter()
+            .map(|column| {
+                let prop_type = column.prop_type();
+                TableColumnDescriptor::new(
+                    self.strings.convert_type(prop_type).unwrap_or(prop_type),
+                    column.prop_id(),
+                    column.offset(),
+                    column.size(),
+                    column.existence_bitmap_index(),
+                )
+            })
+            .collect();
+        let info = TableContextInfo::new(
+            info.end_4byte_values(),
+            info.end_2byte_values(),
+            info.end_1byte_values(),
+            info.end_existence_bitmap(),
+            row_index,
+            rows,
+            columns,
+        )?;
+
+        let mut data = Vec::new();
+        info.write(&mut data)?;
+        let user_root = new_heap.allocate(data)?;
+        new_heap.set_user_root(user_root);
+        node.data = new_heap.write()?;
+        Ok(())
+    }
+
+    /// Copy the value referenced by an HNID from `heap` to `new_heap`, converting it if it is a
+    /// string. Values which are too large for the heap are moved to a new sub-node of `node`, and
+    /// values which are already in a sub-node are converted in place.
+    fn copy_value(
+        &mut self,
+        heap: &HeapNodeEditor,
+        new_heap: &mut HeapNodeEditor,
+        node: &mut NodeContents,
+        prop_type: PropertyType,
+        value: PropertyValueRecord,
+        codepage: u32,
+    ) -> io::Result<PropertyValueRecord> {
+        let convert = self.strings.convert_type(prop_type).is_some();
+
+        match value {
+            PropertyValueRecord::Heap(heap_id) if u32::from(heap_id) != 0 => {
+                let data = heap.get(heap_id)?;
+                let data = if convert {
+                    self.strings.convert_value(prop_type, data, codepage)?
+                } else {
+                    data.to_vec()
+                };
+
+                if data.len() <= MAX_HEAP_ALLOCATION_SIZE {
+                    return Ok(PropertyValueRecord::Heap(new_heap.allocate(data)?));
+                }
+
+                let sub_node = NodeId::new(NodeIdType::ListsTablesProperties, self.next_ltp_index)?;
+                self.next_ltp_index += 1;
+                let index = node
+                    .sub_nodes
+                    .partition_point(|existing| u32::from(existing.node) < u32::from(sub_node));
+                node.sub_nodes.insert(
+                    index,
+                    NodeContents {
+                        node: sub_node,
+                        parent: None,
+                        data: split_data(&data, self.max_data_size),
+                        sub_nodes: Default::default(),
+                    },
+                );
+                Ok(PropertyValueRecord::Node(sub_node))
+            }
+            PropertyValueRecord::Node(sub_node) if convert => {
+                let max_data_size = self.max_data_size;
+                let strings = self.strings;
+                let existing = node
+                    .sub_nodes
+                    .iter_mut()
+                    .find(|existing| existing.node == sub_node)
+                    .ok_or(LtpError::PropertySubNodeValueNotFound(u32::from(sub_node)))?;
+                let data = strings.convert_value(prop_type, &existing.data.concat(), codepage)?;
+                existing.data = split_data(&data, max_data_size);
+                Ok(value)
+            }
+            value => Ok(value),
+        }
+    }
+}
+
+/// Columns which store an HNID in the row instead of the value.
+pub(crate) fn is_hnid_column(prop_type: PropertyType) -> bool {
+    matches!(
+        prop_type,
+        PropertyType::String8
+            | PropertyType::Unicode
+            | PropertyType::Guid
+            | PropertyType::Binary
+            | PropertyType::Object
+            | PropertyType::MultipleInteger16
+            | PropertyType::MultipleInteger32
+            | PropertyType::MultipleFloating32
+            | PropertyType::MultipleFloating64
+            | PropertyType::MultipleCurrency
+            | PropertyType::MultipleFloatingTime
+            | PropertyType::MultipleInteger64
+            | PropertyType::MultipleString8
+            | PropertyType::MultipleUnicode
+            | PropertyType::MultipleTime
+            | PropertyType::MultipleGuid
+            | PropertyType::MultipleBinary
+    )
+}
+
+/// Split `data` into blocks of at most `block_size` bytes. Empty data still needs one empty
+/// block, since a node or sub-node without any data block has no BID to look up.
+pub(crate) fn split_data(data: &[u8], block_size: usize) -> Vec<Vec<u8>> {
+    if data.is_empty() {
+        return vec![Default::default()];
+    }
+    data.chunks(block_size).map(<[u8]>::to_vec).collect()
+}
+
+/// Writes nodes to a new file, allocating space for each block and page sequentially.
+pub(crate) struct NodeWriter<Pst>
+where
+    Pst: PstFile,
+{
+    writer: BufWriter<Box<dyn PstWriter>>,
+    encoding: NdbCryptMethod,
+    next_offset: u64,
+    next_block: u64,
+    next_page: u64,
+    nodes: Vec<<Pst as PstFile>::NodeBTreeEntry>,
+    blocks: Vec<<Pst as PstFile>::BlockBTreeEntry>,
+}
+
+impl<Pst> NodeWriter<Pst>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey>
+        + From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index>
+        + Debug,
+    <Pst as PstFile>::PageId: From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index> + Debug,
+    <Pst as PstFile>::ByteIndex: ByteIndex<Index: TryFrom<u64>> + Debug,
+    <Pst as PstFile>::BlockRef: Debug,
+    <Pst as PstFile>::PageRef: Debug,
+    <Pst as PstFile>::Root: RootReadWrite<Pst>,
+    <Pst as PstFile>::Header: HeaderReadWrite<Pst>,
+    <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+    <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+    <Pst as PstFile>::NodeBTreeEntry: NodeBTreeEntryReadWrite,
+    <Pst as PstFile>::NodeBTree: NodeBTreeReadWrite<Pst, <Pst as PstFile>::NodeBTreeEntry>,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::NodeBTreeEntry,
+            <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockBTree: BlockBTreeReadWrite<Pst, <Pst as PstFile>::BlockBTreeEntry>,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::BlockBTreeEntry,
+            <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry:
+        IntermediateTreeEntryReadWrite + From<<Pst as PstFile>::BlockId>,
+    <Pst as PstFile>::DataBlock: BlockReadWrite + Clone,
+    <Pst as PstFile>::SubNodeTreeBlockHeader: SubNodeTreeBlockHeaderReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::SubNodeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+{
+    pub fn create(path: &Path, encoding: NdbCryptMethod) -> io::Result<Self> {
+        Ok(Self::new(Box::new(File::create(path)?), encoding))
+    }
+
+    /// Write a new file to `writer`, which should be empty.
+    pub fn new(writer: Box<dyn PstWriter>, encoding: NdbCryptMethod) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+            encoding,
+            next_offset: AMAP_FIRST_OFFSET,
+            next_block: 1,
+            next_page: 1,
+            nodes: Default::default(),
+            blocks: Default::default(),
+        }
+    }
+
+    /// Append pages to an existing `file`, starting at `file_eof` and numbering them from
+    /// `next_page`. Only [`Self::write_btrees`] is meant to be used on the result, nothing else
+    /// in the file is touched.
+    pub fn append(
+        file: Box<dyn PstWriter>,
+        encoding: NdbCryptMethod,
+        file_eof: u64,
+        next_page: u64,
+    ) -> Self {
+        Self {
+            writer: BufWriter::new(file),
+            encoding,
+            next_offset: file_eof.max(AMAP_FIRST_OFFSET),
+            next_block: 1,
+            next_page,
+            nodes: Default::default(),
+            blocks: Default::default(),
+        }
+    }
+
+    /// The ID which the next page will get, for `bidNextP` in the header.
+    pub fn next_page(&self) -> u64 {
+        self.next_page
+    }
+
+    fn byte_index(offset: u64) -> PstResult<<Pst as PstFile>::ByteIndex> {
+        let index =
+            <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(offset)
+                .map_err(|_| PstError::IntegerConversion)?;
+        Ok(<<Pst as PstFile>::ByteIndex as ByteIndexReadWrite>::new(
+            index,
+        ))
+    }
+
+    fn block_id(&mut self, is_internal: bool) -> PstResult<<Pst as PstFile>::BlockId> {
+        let block_id = (self.next_block << 2) | if is_internal { 2 } else { 0 };
+        self.next_block += 1;
+        let index =
+            <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(block_id)
+                .map_err(|_| PstError::IntegerConversion)?;
+        Ok(<Pst as PstFile>::BlockId::from(index))
+    }
+
+    /// Reserve `size` bytes aligned to `alignment`, skipping the AMap, PMap, FMap and FPMap pages
+    /// at the start of each allocation map region. Nothing is allowed to cross into the next
+    /// region.
+    fn allocate(&mut self, size: u64, alignment: u64) -> u64 {
+        loop {
+            let region = (self.next_offset - AMAP_FIRST_OFFSET) / AMAP_DATA_SIZE;
+            let region_start = AMAP_FIRST_OFFSET + region * AMAP_DATA_SIZE;
+            let reserved_end = region_start + amap_reserved_pages(region) * PAGE_SIZE as u64;
+
+            let offset = self
+                .next_offset
+                .max(reserved_end)
+                .next_multiple_of(alignment);
+            if offset + size <= region_start + AMAP_DATA_SIZE {
+                self.next_offset = offset + size;
+                return offset;
+            }
+
+            self.next_offset = region_start + AMAP_DATA_SIZE;
+        }
+    }
+
+    fn add_block(
+        &mut self,
+        block_id: <Pst as PstFile>::BlockId,
+        offset: u64,
+        size: u16,
+    ) -> PstResult<<Pst as PstFile>::BlockBTreeEntry> {
+        let block = <<Pst as PstFile>::BlockRef as BlockRefReadWrite>::new(
+            block_id,
+            Self::byte_index(offset)?,
+        );
+        let entry =
+            <<Pst as PstFile>::BlockBTreeEntry as BlockBTreeEntryReadWrite>::new(block, size);
+        self.blocks.push(entry);
+        Ok(entry)
+    }
+
+    fn allocate_page(
+        &mut self,
+        page_type: PageType,
+    ) -> PstResult<(
+        u64,
+        <Pst as PstFile>::PageRef,
+        <Pst as PstFile>::PageTrailer,
+    )> {
+        let offset = self.allocate(PAGE_SIZE as u64, PAGE_SIZE as u64);
+        let page_id =
+            <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(
+                self.next_page,
+            )
+            .map_err(|_| PstError::IntegerConversion)?;
+        let page_id = <Pst as PstFile>::PageId::from(page_id);
+        self.next_page += 1;
+
+        let signature = page_type.signature(offset, page_id.into_u64());
+        let trailer = <<Pst as PstFile>::PageTrailer as PageTrailerReadWrite>::new(
+            page_type, signature, page_id, 0,
+        );
+        let page_ref = <<Pst as PstFile>::PageRef as BlockRefReadWrite>::new(
+            page_id,
+            Self::byte_index(offset)?,
+        );
+        Ok((offset, page_ref, trailer))
+    }
+
+    /// Write the leaf pages of a BTree holding `entries`, followed by as many levels of
+    /// intermediate pages as it takes to reach a single root page.
+    fn write_btree<BTree>(
+        &mut self,
+        page_type: PageType,
+        entries: &[<BTree as RootBTree>::Entry],
+    ) -> io::Result<<Pst as PstFile>::PageRef>
+    where
+        BTree: RootBTree<Pst = Pst>,
+        <BTree as RootBTree>::Entry: BTreeEntryReadWrite,
+        <BTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+        <BTree as RootBTree>::IntermediatePage: RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <BTree as RootBTree>::Entry,
+            <BTree as RootBTree>::LeafPage,
+        >,
+        <<BTree as RootBTree>::IntermediatePage as BTreePage>::Entry: BTreePageEntryReadWrite,
+    {
+        let entries_size =
+            <<BTree as RootBTree>::LeafPage as RootBTreeLeafPageReadWrite<Pst>>::BTREE_ENTRIES_SIZE;
+        let entry_size = <<BTree as RootBTree>::Entry as BTreeEntryReadWrite>::ENTRY_SIZE;
+        let max_entries = entries_size / entry_size;
+
+        let chunks: Vec<_> = if entries.is_empty() {
+            vec![entries]
+        } else {
+            entries.chunks(max_entries).collect()
+        };
+
+        let mut level = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            let (offset, page_ref, trailer) = self.allocate_page(page_type)?;
+            let page = <<BTree as RootBTree>::LeafPage as BTreePageReadWrite>::new(
+                0,
+                max_entries as u8,
+                entry_size as u8,
+                chunk,
+                trailer,
+            )?;
+            self.writer.seek(SeekFrom::Start(offset))?;
+            <<BTree as RootBTree>::LeafPage as RootBTreeLeafPageReadWrite<Pst>>::write(
+                &page,
+                &mut self.writer,
+            )?;
+
+            let key = chunk
+                .first()
+                .map(BTreeEntry::key)
+                .unwrap_or_else(|| 0_u32.into());
+            level.push((key, page_ref));
+        }
+
+        let entry_size =
+            <<<BTree as RootBTree>::IntermediatePage as BTreePage>::Entry as BTreePageEntryReadWrite>::ENTRY_SIZE;
+        let max_entries = entries_size / entry_size;
+        let mut depth = 0;
+        while level.len() > 1 {
+            depth += 1;
+            level = level
+                .chunks(max_entries)
+                .map(|chunk| {
+                    let entries: Vec<_> = chunk
+                        .iter()
+                        .map(|(key, page_ref)| {
+                            <<<BTree as RootBTree>::IntermediatePage as BTreePage>::Entry as BTreePageEntryReadWrite>::new(
+                                *key, *page_ref,
+                            )
+                        })
+                        .collect();
+                    let (offset, page_ref, trailer) = self.allocate_page(page_type)?;
+                    let page = <<BTree as RootBTree>::IntermediatePage as BTreePageReadWrite>::new(
+                        depth,
+                        max_entries as u8,
+                        entry_size as u8,
+                        &entries,
+                        trailer,
+                    )?;
+                    self.writer.seek(SeekFrom::Start(offset))?;
+                    <<BTree as RootBTree>::IntermediatePage as RootBTreeIntermediatePageReadWrite<
+                        Pst,
+                        <BTree as RootBTree>::Entry,
+                        <BTree as RootBTree>::LeafPage,
+                    >>::write(&page, &mut self.writer)?;
+                    Ok((chunk[0].0, page_ref))
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+        }
+
+        Ok(level[0].1)
+    }
+
+    /// Sort `nodes` and `blocks` and write them to a new NBT and BBT. Returns the root pages and
+    /// the `ibFileEof` after the last page, rounded up to the end of its allocation map region.
+    pub fn write_btrees(
+        &mut self,
+        mut nodes: Vec<<Pst as PstFile>::NodeBTreeEntry>,
+        mut blocks: Vec<<Pst as PstFile>::BlockBTreeEntry>,
+    ) -> io::Result<(<Pst as PstFile>::PageRef, <Pst as PstFile>::PageRef, u64)> {
+        nodes.sort_by_key(|entry| entry.key().into());
+        blocks.sort_by_key(|entry| entry.key().into());
+
+        let node_btree =
+            self.write_btree::<<Pst as PstFile>::NodeBTree>(PageType::NodeBTree, &nodes)?;
+        let block_btree =
+            self.write_btree::<<Pst as PstFile>::BlockBTree>(PageType::BlockBTree, &blocks)?;
+
+        // Round the file up to the end of the last allocation map region, the AMap pages are
+        // filled in by the first write transaction because the root says they are invalid.
+        let regions = (self.next_offset - AMAP_FIRST_OFFSET)
+            .div_ceil(AMAP_DATA_SIZE)
+            .max(1);
+        let file_eof = AMAP_FIRST_OFFSET + regions * AMAP_DATA_SIZE;
+        self.writer.flush()?;
+        self.writer.get_mut().set_len(file_eof)?;
+
+        Ok((node_btree, block_btree, file_eof))
+    }
+}
+
+impl<Pst> BlockAllocator<Pst> for NodeWriter<Pst>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey>
+        + From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index>
+        + Debug,
+    <Pst as PstFile>::PageId: From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index> + Debug,
+    <Pst as PstFile>::ByteIndex: ByteIndex<Index: TryFrom<u64>> + Debug,
+    <Pst as PstFile>::BlockRef: Debug,
+    <Pst as PstFile>::PageRef: Debug,
+    <Pst as PstFile>::Root: RootReadWrite<Pst>,
+    <Pst as PstFile>::Header: HeaderReadWrite<Pst>,
+    <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+    <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+    <Pst as PstFile>::NodeBTreeEntry: NodeBTreeEntryReadWrite,
+    <Pst as PstFile>::NodeBTree: NodeBTreeReadWrite<Pst, <Pst as PstFile>::NodeBTreeEntry>,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::NodeBTreeEntry,
+            <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockBTree: BlockBTreeReadWrite<Pst, <Pst as PstFile>::BlockBTreeEntry>,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::BlockBTreeEntry,
+            <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry:
+        IntermediateTreeEntryReadWrite + From<<Pst as PstFile>::BlockId>,
+    <Pst as PstFile>::DataBlock: BlockReadWrite + Clone,
+    <Pst as PstFile>::SubNodeTreeBlockHeader: SubNodeTreeBlockHeaderReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::SubNodeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+{
+    fn encoding(&self) -> NdbCryptMethod {
+        self.encoding
+    }
+
+    fn allocate_block(
+        &mut self,
+        is_internal: bool,
+        size: u16,
+    ) -> io::Result<<Pst as PstFile>::BlockBTreeEntry> {
+        let block_id = self.block_id(is_internal)?;
+        let offset = self.allocate(
+            u64::from(block_size(
+                size + <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE,
+            )),
+            64,
+        );
+        Ok(self.add_block(block_id, offset, size)?)
+    }
+
+    fn write_block(
+        &mut self,
+        block: &DataTree<Pst>,
+        entry: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<()> {
+        block.write(&mut self.writer, entry)
+    }
+}
+
+impl<Pst> SubNodeBlockWriter<Pst> for NodeWriter<Pst>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey>
+        + From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index>
+        + Debug,
+    <Pst as PstFile>::PageId: From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index> + Debug,
+    <Pst as PstFile>::ByteIndex: ByteIndex<Index: TryFrom<u64>> + Debug,
+    <Pst as PstFile>::BlockRef: Debug,
+    <Pst as PstFile>::PageRef: Debug,
+    <Pst as PstFile>::Root: RootReadWrite<Pst>,
+    <Pst as PstFile>::Header: HeaderReadWrite<Pst>,
+    <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+    <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+    <Pst as PstFile>::NodeBTreeEntry: NodeBTreeEntryReadWrite,
+    <Pst as PstFile>::NodeBTree: NodeBTreeReadWrite<Pst, <Pst as PstFile>::NodeBTreeEntry>,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::NodeBTreeEntry,
+            <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockBTree: BlockBTreeReadWrite<Pst, <Pst as PstFile>::BlockBTreeEntry>,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::BlockBTreeEntry,
+            <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry:
+        IntermediateTreeEntryReadWrite + From<<Pst as PstFile>::BlockId>,
+    <Pst as PstFile>::DataBlock: BlockReadWrite + Clone,
+    <Pst as PstFile>::SubNodeTreeBlockHeader: SubNodeTreeBlockHeaderReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::SubNodeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+{
+    fn write_sub_node_block(
+        &mut self,
+        block: &SubNodeTree<Pst>,
+        entry: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<()> {
+        block.write(&mut self.writer, entry)
+    }
+}
+
+/// A [`BlockAllocator`] which can also write the SLBLOCKs and SIBLOCKs of a sub-node tree, so
+/// [`write_sub_node_tree`] can write every block of a [`NodeContents`].
+pub(crate) trait SubNodeBlockWriter<Pst>: BlockAllocator<Pst>
+where
+    Pst: PstFile,
+{
+    /// Write an SLBLOCK or SIBLOCK at the location returned by
+    /// [`BlockAllocator::allocate_block`].
+    fn write_sub_node_block(
+        &mut self,
+        block: &SubNodeTree<Pst>,
+        entry: &<Pst as PstFile>::BlockBTreeEntry,
+    ) -> io::Result<()>;
+}
+
+/// Write a single data block, or an XBLOCK or XXBLOCK tree if there is more than one block.
+/// Nodes without any data blocks get a BID of 0, the same as the file they were read from.
+pub(crate) fn write_data_tree<Pst>(
+    allocator: &mut dyn BlockAllocator<Pst>,
+    blocks: &[Vec<u8>],
+) -> io::Result<<Pst as PstFile>::BlockId>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry: IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::DataBlock: BlockReadWrite,
+{
+    if blocks.is_empty() {
+        return Ok(Default::default());
+    }
+
+    let data_tree =
+        DataTree::<Pst>::build_from_blocks(blocks.iter().map(Vec::as_slice), allocator)?;
+    Ok(data_tree.block_id())
+}
+
+/// Write an SLBLOCK, or an SIBLOCK with multiple SLBLOCKs if there are too many sub-nodes, along
+/// with the data tree and sub-node tree of each sub-node.
+pub(crate) fn write_sub_node_tree<Pst, Writer>(
+    writer: &mut Writer,
+    sub_nodes: &[NodeContents],
+) -> io::Result<Option<<Pst as PstFile>::BlockId>>
+where
+    Pst: PstFile,
+    Writer: SubNodeBlockWriter<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry: IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::DataBlock: BlockReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlockHeader: SubNodeTreeBlockHeaderReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::SubNodeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+{
+    if sub_nodes.is_empty() {
+        return Ok(None);
+    }
+
+    let entries = sub_nodes
+        .iter()
+        .map(|sub_node| {
+            Ok(LeafSubNodeTreeEntry::new(
+                sub_node.node,
+                write_data_tree(writer, &sub_node.data)?,
+                write_sub_node_tree(writer, &sub_node.sub_nodes)?,
+            ))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let header_size =
+        <<Pst as PstFile>::SubNodeTreeBlockHeader as IntermediateTreeHeaderReadWrite>::HEADER_SIZE;
+    let max_entries = max_sub_node_entries::<Pst>(
+        header_size,
+        <<<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry as IntermediateTreeEntryReadWrite>::ENTRY_SIZE,
+    );
+    let leaves = entries
+        .chunks(max_entries)
+        .map(|chunk| {
+            let header =
+                <<Pst as PstFile>::SubNodeTreeBlockHeader as SubNodeTreeBlockHeaderReadWrite>::new(
+                    0,
+                    chunk.len() as u16,
+                );
+            let size = header_size
+                + chunk.len() as u16
+                    * <<<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry as IntermediateTreeEntryReadWrite>::ENTRY_SIZE;
+            let (entry, trailer) = allocate_sub_node_block(writer, size)?;
+            let block = <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlockReadWrite>::new(
+                header,
+                chunk.to_vec(),
+                trailer,
+            )?;
+            writer.write_sub_node_block(&SubNodeTree::Leaf(Box::new(block)), &entry)?;
+            Ok(IntermediateSubNodeTreeEntry::new(
+                chunk[0].node(),
+                trailer.block_id(),
+            ))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    if let [leaf] = leaves.as_slice() {
+        return Ok(Some(leaf.block()));
+    }
+
+    let entry_size =
+        <<<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry as IntermediateTreeEntryReadWrite>::ENTRY_SIZE;
+    if leaves.len() > max_sub_node_entries::<Pst>(header_size, entry_size) {
+        return Err(NdbError::InvalidInternalBlockEntryCount(
+            u16::try_from(leaves.len()).unwrap_or(u16::MAX),
+        )
+        .into());
+    }
+
+    let header = <<Pst as PstFile>::SubNodeTreeBlockHeader as SubNodeTreeBlockHeaderReadWrite>::new(
+        1,
+        leaves.len() as u16,
+    );
+    let size = header_size + leaves.len() as u16 * entry_size;
+    let (entry, trailer) = allocate_sub_node_block(writer, size)?;
+    let block = <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlockReadWrite>::new(
+        header, leaves, trailer,
+    )?;
+    writer.write_sub_node_block(&SubNodeTree::Intermediate(Box::new(block)), &entry)?;
+    Ok(Some(trailer.block_id()))
+}
+
+/// The number of entries which fit in an SLBLOCK or SIBLOCK.
+fn max_sub_node_entries<Pst>(header_size: u16, entry_size: u16) -> usize
+where
+    Pst: PstFile,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+{
+    usize::from(
+        (MAX_BLOCK_SIZE
+            - <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE
+            - header_size)
+            / entry_size,
+    )
+}
+
+/// Allocate an internal block with `size` bytes, and build the trailer which goes with it.
+fn allocate_sub_node_block<Pst>(
+    allocator: &mut dyn BlockAllocator<Pst>,
+    size: u16,
+) -> io::Result<(
+    <Pst as PstFile>::BlockBTreeEntry,
+    <Pst as PstFile>::BlockTrailer,
+)>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+{
+    let entry = allocator.allocate_block(true, size)?;
+    let offset: u64 = entry.block().index().index().into();
+    let block_id = entry.block().block();
+    let signature = compute_sig(
+        (offset & u64::from(u32::MAX)) as u32,
+        (block_id.into_u64() & u64::from(u32::MAX)) as u32,
+    );
+    let trailer = <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::new(
+        size, signature, 0, block_id,
+    )?;
+    Ok((entry, trailer))
+}
+
+impl<Pst> NodeSink for NodeWriter<Pst>
+where
+    Pst: PstFile,
+    <Pst as PstFile>::BlockId: BlockId<Index = <Pst as PstFile>::BTreeKey>
+        + From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index>
+        + Debug,
+    <Pst as PstFile>::PageId: From<<<Pst as PstFile>::ByteIndex as ByteIndex>::Index> + Debug,
+    <Pst as PstFile>::ByteIndex: ByteIndex<Index: TryFrom<u64>> + Debug,
+    <Pst as PstFile>::BlockRef: Debug,
+    <Pst as PstFile>::PageRef: Debug,
+    <Pst as PstFile>::Root: RootReadWrite<Pst>,
+    <Pst as PstFile>::Header: HeaderReadWrite<Pst>,
+    <Pst as PstFile>::PageTrailer: PageTrailerReadWrite,
+    <Pst as PstFile>::BTreeKey: BTreePageKeyReadWrite,
+    <Pst as PstFile>::NodeBTreeEntry: NodeBTreeEntryReadWrite,
+    <Pst as PstFile>::NodeBTree: NodeBTreeReadWrite<Pst, <Pst as PstFile>::NodeBTreeEntry>,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::NodeBTreeEntry,
+            <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::NodeBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::NodeBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockBTreeEntry: BlockBTreeEntryReadWrite,
+    <Pst as PstFile>::BlockBTree: BlockBTreeReadWrite<Pst, <Pst as PstFile>::BlockBTreeEntry>,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage:
+        RootBTreeIntermediatePageReadWrite<
+            Pst,
+            <Pst as PstFile>::BlockBTreeEntry,
+            <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage,
+        >,
+    <<<Pst as PstFile>::BlockBTree as RootBTree>::IntermediatePage as BTreePage>::Entry:
+        BTreePageEntryReadWrite,
+    <<Pst as PstFile>::BlockBTree as RootBTree>::LeafPage: RootBTreeLeafPageReadWrite<Pst>,
+    <Pst as PstFile>::BlockTrailer: BlockTrailerReadWrite,
+    <Pst as PstFile>::DataTreeBlock: IntermediateTreeBlockReadWrite,
+    <Pst as PstFile>::DataTreeEntry:
+        IntermediateTreeEntryReadWrite + From<<Pst as PstFile>::BlockId>,
+    <Pst as PstFile>::DataBlock: BlockReadWrite + Clone,
+    <Pst as PstFile>::SubNodeTreeBlockHeader: SubNodeTreeBlockHeaderReadWrite,
+    <Pst as PstFile>::SubNodeTreeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeTreeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+    <Pst as PstFile>::SubNodeBlock: IntermediateTreeBlockReadWrite,
+    <<Pst as PstFile>::SubNodeBlock as IntermediateTreeBlock>::Entry:
+        IntermediateTreeEntryReadWrite,
+{
+    fn max_data_size(&self) -> usize {
+        usize::from(
+            MAX_BLOCK_SIZE - <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE,
+        )
+    }
+
+    fn row_index_size(&self) -> u8 {
+        // `dwRowIndex` is a 16-bit value in ANSI files and a 32-bit value in Unicode files.
+        match <<Pst as PstFile>::BlockTrailer as BlockTrailerReadWrite>::SIZE {
+            16 => <UnicodeTableRowIndex as HeapTreeEntryValue>::SIZE,
+            _ => <AnsiTableRowIndex as HeapTreeEntryValue>::SIZE,
+        }
+    }
+
+    fn write_node(&mut self, node: &NodeContents) -> io::Result<()> {
+        let data = write_data_tree(self, &node.data)?;
+        let sub_node = write_sub_node_tree(self, &node.sub_nodes)?;
+        self.nodes.push(
+            <<Pst as PstFile>::NodeBTreeEntry as NodeBTreeEntryReadWrite>::new(
+                node.node,
+                data,
+                sub_node,
+                node.parent,
+            ),
+        );
+        Ok(())
+    }
+
+    fn finish(&mut self, next_node_ids: &[u32]) -> io::Result<()> {
+        let nodes = mem::take(&mut self.nodes);
+        let blocks = mem::take(&mut self.blocks);
+        let (node_btree, block_btree, file_eof) = self.write_btrees(nodes, blocks)?;
+        let amap_last = file_eof - AMAP_DATA_SIZE;
+
+        let root = <<Pst as PstFile>::Root as RootReadWrite<Pst>>::new(
+            Self::byte_index(file_eof)?,
+            Self::byte_index(amap_last)?,
+            Self::byte_index(0)?,
+            Self::byte_index(0)?,
+            node_btree,
+            block_btree,
+            AmapStatus::Invalid,
+        );
+        let mut header =
+            <<Pst as PstFile>::Header as HeaderReadWrite<Pst>>::new(root, self.encoding);
+
+        let next_block = self.block_id(false)?;
+        header.set_next_block(next_block);
+        let next_page =
+            <<<Pst as PstFile>::ByteIndex as ByteIndex>::Index as TryFrom<u64>>::try_from(
+                self.next_page,
+            )
+            .map_err(|_| PstError::IntegerConversion)?;
+        header.set_next_page(<Pst as PstFile>::PageId::from(next_page));
+        for (next, source) in header.next_node_ids().iter_mut().zip(next_node_ids) {
+            *next = *source;
+        }
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        header.write(&mut self.writer)?;
+        self.writer.flush()?;
+        self.writer.get_mut().set_len(file_eof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messaging::property_ids::{PR_BODY, PR_DISPLAY_NAME, PR_SUBJECT};
+    use crate::test_util::{message_node, TempFile, EMPTY_PST, FIRST_MESSAGE_INDEX};
+    use std::fs;
+
+    /// There is no ANSI sample file, so make one by copying a Unicode file with its strings
+    /// converted to `PtypString8`.
+    fn downgrade_to_ansi(unicode: &UnicodePstFile, dest: &Path) -> io::Result<()> {
+        let mut reader = NodeReader::new(unicode)?;
+        let mut writer = NodeWriter::<AnsiPstFile>::create(dest, unicode.header().crypt_method())?;
+        copy_nodes(&mut reader, &mut writer, StringConversion::ToString8)?;
+        drop(writer);
+
+        let mut pst = AnsiPstFile::open(dest)?;
+        pst.lock()?.flush()?;
+        Ok(())
+    }
+
+    /// Display name of a folder, with the subject of every message in the folder.
+    type FolderContents = (Option<String>, Vec<Option<String>>);
+
+    /// Get the display name of every folder which has one, and the subject of every message in each folder.
+    fn folder_contents(path: &Path) -> io::Result<Vec<FolderContents>> {
+        let pst = PstFileReader::open(path)?;
+        pst.folders()?
+            .into_iter()
+            .map(|folder| {
+                let display_name = folder.properties().display_name().ok();
+                let subjects = match folder.contents_table() {
+                    Some(contents_table) => contents_table
+                        .rows_matrix()?
+                        .map(|row| {
+                            let message = pst.message(NodeId::from(u32::from(row.id())))?;
+                            Ok(message.properties().get_string8(PR_SUBJECT)?)
+                        })
+                        .collect::<io::Result<Vec<_>>>()?,
+                    None => Default::default(),
+                };
+                Ok((display_name, subjects))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_upgrade_to_unicode() {
+        let ansi_path = TempFile::new("upgrade_ansi");
+        let unicode_path = TempFile::new("upgrade_unicode");
+
+        let empty = UnicodePstFile::open(EMPTY_PST).unwrap();
+        downgrade_to_ansi(&empty, &ansi_path).unwrap();
+
+        let ansi = AnsiPstFile::open(&ansi_path).unwrap();
+        let unicode = upgrade_to_unicode(&ansi, &unicode_path).unwrap();
+        assert_eq!(unicode.header().root().amap_is_valid(), AmapStatus::Valid2);
+        assert!(unicode.density_list().is_ok());
+
+        // Make sure the strings really were converted in both directions.
+        let display_names = |path: &Path| {
+            let pst = PstFileReader::open(path).unwrap();
+            pst.folders()
+                .unwrap()
+                .iter()
+                .filter_map(|folder| folder.properties().get(PR_DISPLAY_NAME).cloned())
+                .collect::<Vec<_>>()
+        };
+        let ansi_names = display_names(&ansi_path);
+        assert!(ansi_names
+            .iter()
+            .any(|name| matches!(name, PropertyValue::String8(_))));
+        assert!(!ansi_names
+            .iter()
+            .any(|name| matches!(name, PropertyValue::Unicode(_))));
+        assert!(!display_names(&unicode_path)
+            .iter()
+            .any(|name| matches!(name, PropertyValue::String8(_))));
+
+        let expected = folder_contents(Path::new(EMPTY_PST)).unwrap();
+        assert!(!expected.is_empty());
+        assert_eq!(folder_contents(&ansi_path).unwrap(), expected);
+        assert_eq!(folder_contents(&unicode_path).unwrap(), expected);
+
+        let store = PstFileReader::open(&unicode_path).unwrap();
+        assert_eq!(store.version(), NdbVersion::Unicode);
+
+        drop((ansi, unicode, store));
+    }
+
+    #[test]
+    fn test_allocation_stats() {
+        let source_path = TempFile::new("allocation_stats");
+        let dest_path = TempFile::new("allocation_stats_compact");
+
+        // Write 1000 messages and drop every other one from the BTrees, which leaves a hole in
+        // the file for each of them.
+        let body = "Lorem ipsum dolor sit amet. ".repeat(140);
+        {
+            let empty = UnicodePstFile::open(EMPTY_PST).unwrap();
+            let mut reader = NodeReader::new(&empty).unwrap();
+            let mut writer =
+                NodeWriter::<UnicodePstFile>::create(&source_path, empty.header().crypt_method())
+                    .unwrap();
+            for node in reader.nodes() {
+                writer.write_node(&reader.read_node(node).unwrap()).unwrap();
+            }
+            for index in 0..1000 {
+                let (nodes, blocks) = (writer.nodes.len(), writer.blocks.len());
+                let node =
+                    NodeId::new(NodeIdType::NormalMessage, FIRST_MESSAGE_INDEX + index).unwrap();
+                let message = message_node(node, &format!("Message {index}"), &body).unwrap();
+                writer.write_node(&message).unwrap();
+                if index % 2 != 0 {
+                    writer.nodes.truncate(nodes);
+                    writer.blocks.truncate(blocks);
+                }
+            }
+            let mut next_node_ids = reader.next_node_ids();
+            next_node_ids[NodeIdType::NormalMessage as usize] = FIRST_MESSAGE_INDEX + 1000;
+            writer.finish(&next_node_ids).unwrap();
+        }
+        let mut pst = UnicodePstFile::open(&source_path).unwrap();
+        pst.lock().unwrap().flush().unwrap();
+        pst.reclaim_orphaned_blocks().unwrap();
+
+        let stats = pst.allocation_stats().unwrap();
+        let file_eof = pst.header().root().file_eof_index().index();
+        assert_eq!(stats.amap_page_count as u64, amap_page_count(file_eof));
+        assert_eq!(stats.total_bytes, file_eof - AMAP_FIRST_OFFSET);
+        assert_eq!(stats.used_bytes + stats.free_bytes, stats.total_bytes);
+        assert_eq!(stats.free_bytes, pst.recompute_free_sizes().unwrap().0);
+        assert!(stats.largest_contiguous_free < stats.free_bytes);
+        assert!(stats.fragmentation_ratio > 0.5);
+
+        pst.compact(&dest_path).unwrap();
+        let compacted = UnicodePstFile::open(&dest_path)
+            .unwrap()
+            .allocation_stats()
+            .unwrap();
+        assert!(compacted.used_bytes < stats.used_bytes);
+        assert!(compacted.fragmentation_ratio < stats.fragmentation_ratio);
+        drop(pst);
+    }
+
+    #[test]
+    fn test_compact() {
+        let source_path = TempFile::new("compact_source");
+        let dest_path = TempFile::new("compact_dest");
+
+        // Add 1000 messages to a copy of the sample file, then delete 900 of them by dropping
+        // their NBT and BBT entries, which leaves their blocks behind as free space.
+        let body = "Lorem ipsum dolor sit amet. ".repeat(140);
+        let message_id =
+            |index| NodeId::new(NodeIdType::NormalMessage, FIRST_MESSAGE_INDEX + index).unwrap();
+        {
+            let empty = UnicodePstFile::open(EMPTY_PST).unwrap();
+            let mut reader = NodeReader::new(&empty).unwrap();
+            let mut writer =
+                NodeWriter::<UnicodePstFile>::create(&source_path, empty.header().crypt_method())
+                    .unwrap();
+            for node in reader.nodes() {
+                writer.write_node(&reader.read_node(node).unwrap()).unwrap();
+            }
+
+            for index in 0..1000 {
+                let (nodes, blocks) = (writer.nodes.len(), writer.blocks.len());
+                let subject = format!("Message {index}");
+                let message = message_node(message_id(index), &subject, &body).unwrap();
+                writer.write_node(&message).unwrap();
+                if index % 10 != 0 {
+                    writer.nodes.truncate(nodes);
+                    writer.blocks.truncate(blocks);
+                }
+            }
+
+            let mut next_node_ids = reader.next_node_ids();
+            next_node_ids[NodeIdType::NormalMessage as usize] = FIRST_MESSAGE_INDEX + 1000;
+            writer.finish(&next_node_ids).unwrap();
+        }
+        UnicodePstFile::open(&source_path)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .flush()
+            .unwrap();
+
+        let source_data = fs::read(&source_path).unwrap();
+        let source = UnicodePstFile::open(&source_path).unwrap();
+        let stats = source.compact(&dest_path).unwrap();
+        drop(source);
+        assert_eq!(fs::read(&source_path).unwrap(), source_data);
+
+        let dest_size = fs::metadata(&dest_path).unwrap().len();
+        assert!(dest_size * 4 < source_data.len() as u64);
+        assert_eq!(
+            stats.nodes,
+            NodeReader::new(&UnicodePstFile::open(EMPTY_PST).unwrap())
+                .unwrap()
+                .nodes()
+                .len()
+                + 100
+        );
+        assert_eq!(stats.source_size, source_data.len() as u64);
+        assert_eq!(stats.dest_size, dest_size);
+        assert_eq!(stats.bytes_reclaimed(), stats.source_size - dest_size);
+
+        let dest = UnicodePstFile::open(&dest_path).unwrap();
+        assert_eq!(dest.header().root().amap_is_valid(), AmapStatus::Valid2);
+        assert!(dest.density_list().is_ok());
+        drop(dest);
+
+        let dest = PstFileReader::open(&dest_path).unwrap();
+        for index in (0..1000).step_by(10) {
+            let message = dest.message(message_id(index)).unwrap();
+            let properties = message.properties();
+            assert_eq!(
+                properties.get_string8(PR_SUBJECT).unwrap(),
+                Some(format!("Message {index}"))
+            );
+            assert_eq!(
+                properties.get_string8(PR_BODY).unwrap().as_ref(),
+                Some(&body)
+            );
+        }
+        assert!(dest.message(message_id(1)).is_err());
+
+        // Every live node keeps its NID, parent, data and sub-nodes, and every block they refer
+        // to is in the new BBT.
+        let source = PstFileReader::open(&source_path).unwrap();
+        assert!(crate::diff::diff(&source, &dest).unwrap().is_empty());
+        drop((source, dest));
+        assert!(UnicodePstFile::open(&dest_path)
+            .unwrap()
+            .validate()
+            .unwrap()
+            .is_valid());
+
+        // Switch to a different encoding, every folder and message should read the same.
+        let source = UnicodePstFile::open(&source_path).unwrap();
+        let encoding = match source.header().crypt_method() {
+            NdbCryptMethod::Cyclic => NdbCryptMethod::Permute,
+            _ => NdbCryptMethod::Cyclic,
+        };
+        source.compact_with_encoding(&dest_path, encoding).unwrap();
+        drop(source);
+        let dest = UnicodePstFile::open(&dest_path).unwrap();
+        assert_eq!(dest.header().crypt_method(), encoding);
+        drop(dest);
+
+        let source = PstFileReader::open(&source_path).unwrap();
+        let dest = PstFileReader::open(&dest_path).unwrap();
+        let folder_properties = |pst: &PstFileReader| {
+            pst.folders()
+                .unwrap()
+                .iter()
+                .map(|folder| format!("{:?}", folder.properties().iter().collect::<Vec<_>>()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(folder_properties(&source), folder_properties(&dest));
+        assert!(crate::diff::diff(&source, &dest).unwrap().is_empty());
+        for index in (0..1000).step_by(10) {
+            let message_properties = |pst: &PstFileReader| {
+                let message = pst.message(message_id(index)).unwrap();
+                format!("{:?}", message.properties().iter().collect::<Vec<_>>())
+            };
+            assert_eq!(message_properties(&source), message_properties(&dest));
+        }
+        drop(source);
+
+        // Compacting a file without any free space should not make it any bigger.
+        let empty = UnicodePstFile::open(EMPTY_PST).unwrap();
+        empty.compact(&dest_path).unwrap();
+        assert!(fs::metadata(&dest_path).unwrap().len() <= fs::metadata(EMPTY_PST).unwrap().len());
+    }
+}
@@ -0,0 +1,166 @@
+//! Consistency checks which read the whole file without changing it.
+
+use super::*;
+
+/// The result of [`PstFile::validate`].
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    /// Nodes in the [`NodeBTree`] which were checked.
+    pub nodes_checked: usize,
+    /// References from those nodes which were found in the [`BlockBTree`].
+    pub blocks_checked: usize,
+    /// Every problem which was found, with the NID of the node where it was found.
+    pub errors: Vec<(NodeId, NdbError)>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// The result of [`PstFile::check_allocation_map`]. Each run of space is reported as a file
+/// offset and a size in bytes, in multiples of the 64 bytes covered by each bit in an AMap page.
+#[derive(Debug, Default)]
+pub struct AmapCheckReport {
+    /// AMap pages which were compared with the BTrees.
+    pub amap_pages_checked: usize,
+    /// Space which is marked as allocated in the AMap, but is not used by any BTree page or any
+    /// block in the [`BlockBTree`].
+    pub leaked: Vec<(u64, u64)>,
+    /// Space which is used by a BTree page or a block in the [`BlockBTree`], but is marked as
+    /// free in the AMap. Anything written there would overwrite it. Space past the end of the
+    /// file is left out, even though a rebuilt AMap marks it as allocated.
+    pub dangling: Vec<(u64, u64)>,
+    /// `ibAMapFree` from the [`Root`] in the header.
+    pub amap_free_size: u64,
+    /// The free space which `ibAMapFree` should report if the AMap matched the BTrees.
+    pub expected_free_size: u64,
+}
+
+impl AmapCheckReport {
+    /// Check that `ibAMapFree` in the [`Root`] matches the free space in the BTrees.
+    pub fn free_size_matches(&self) -> bool {
+        self.amap_free_size == self.expected_free_size
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.leaked.is_empty() && self.dangling.is_empty() && self.free_size_matches()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crc::compute_crc;
+    use crate::test_util::{TempFile, EMPTY_PST};
+    use std::fs;
+
+    #[test]
+    fn test_missing_block_reference() {
+        let path = TempFile::copy_of(EMPTY_PST, "validate").unwrap();
+
+        let (block, page) = {
+            let pst = UnicodePstFile::open(&path).unwrap();
+            let report = pst.validate().unwrap();
+            assert!(report.is_valid());
+            assert!(report.nodes_checked > 0);
+            assert!(report.blocks_checked > 0);
+
+            let block: u64 = pst
+                .read_node(NID_MESSAGE_STORE)
+                .unwrap()
+                .data()
+                .search_key();
+            let page: u64 = pst.header().root().block_btree().index().index();
+            (block, page as usize)
+        };
+
+        // Change the key of the BBTENTRY for the message store data block. The entries in a
+        // Unicode BTPAGE are followed by cEnt, cEntMax, cbEnt and cLevel, then the PAGETRAILER
+        // with dwCRC at offset 500. Each BTENTRY in an intermediate page has the key and then
+        // the BREF of the child page, with the file offset last.
+        let mut data = fs::read(&path).unwrap();
+        let read_u64 = |data: &[u8], offset: usize| {
+            u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+        };
+        let mut page = page;
+        while data[page + 491] > 0 {
+            let (count, entry_size) =
+                (usize::from(data[page + 488]), usize::from(data[page + 490]));
+            let child = (0..count)
+                .map(|index| page + index * entry_size)
+                .take_while(|offset| read_u64(&data, *offset) <= block)
+                .last()
+                .unwrap();
+            page = read_u64(&data, child + 16) as usize;
+        }
+        let page = &mut data[page..page + PAGE_SIZE];
+        let (count, entry_size) = (usize::from(page[488]), usize::from(page[490]));
+        let entry = (0..count)
+            .map(|index| index * entry_size)
+            .find(|offset| read_u64(page, *offset) == block)
+            .unwrap();
+        page[entry..entry + 8].copy_from_slice(&(u64::MAX - 3).to_le_bytes());
+        let crc = compute_crc(0, &page[..496]);
+        page[500..504].copy_from_slice(&crc.to_le_bytes());
+        fs::write(&path, data).unwrap();
+
+        let pst = UnicodePstFile::open(&path).unwrap();
+        let report = pst.validate().unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.errors.len(), 1);
+        let (node, err) = &report.errors[0];
+        assert_eq!(*node, NID_MESSAGE_STORE);
+        assert!(matches!(err, NdbError::MissingBlockReference(missing) if *missing == block));
+
+        drop(pst);
+    }
+
+    #[test]
+    fn test_check_allocation_map() {
+        let pst = UnicodePstFile::open(EMPTY_PST).unwrap();
+        let report = pst.check_allocation_map().unwrap();
+        assert_eq!(report.amap_pages_checked, 1);
+        assert!(report.is_consistent(), "{report:?}");
+        let block = {
+            let block_id = pst.read_node(NID_MESSAGE_STORE).unwrap().data();
+            let mut reader = pst.reader().lock().unwrap();
+            let block_btree =
+                UnicodeBlockBTree::read(&mut *reader, *pst.header().root().block_btree()).unwrap();
+            block_btree
+                .find_entry(&mut *reader, block_id.search_key(), &mut Default::default())
+                .unwrap()
+                .block()
+                .index()
+                .index()
+        };
+        drop(pst);
+
+        // Mark the last 64 bytes covered by the AMap page as allocated, and the first 64 bytes of
+        // the message store data block as free. The rgbAMapBits are followed by the PAGETRAILER
+        // with dwCRC at offset 500.
+        let path = TempFile::new("check_amap");
+        let mut data = fs::read(EMPTY_PST).unwrap();
+        let amap = AMAP_FIRST_OFFSET as usize;
+        let page = &mut data[amap..amap + PAGE_SIZE];
+        page[495] |= 0x01;
+        let bit = (block - AMAP_FIRST_OFFSET) / 64;
+        page[(bit / 8) as usize] &= !(0x80 >> (bit % 8));
+        let crc = compute_crc(0, &page[..496]);
+        page[500..504].copy_from_slice(&crc.to_le_bytes());
+        fs::write(&path, data).unwrap();
+
+        let pst = UnicodePstFile::open(&path).unwrap();
+        let report = pst.check_allocation_map().unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(
+            report.leaked,
+            vec![(AMAP_FIRST_OFFSET + AMAP_DATA_SIZE - 64, 64)]
+        );
+        assert_eq!(report.dangling, vec![(block, 64)]);
+        assert!(report.free_size_matches());
+
+        drop(pst);
+    }
+}